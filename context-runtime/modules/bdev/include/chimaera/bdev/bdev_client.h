@@ -90,6 +90,31 @@ class Client : public chi::ContainerClient {
     return ipc_manager->Send(task);
   }
 
+  /**
+   * Create bdev container from complete parameters - asynchronous
+   * Needed for kObject, whose bucket and credentials are in params.object_store_
+   * @param custom_pool_id Explicit pool ID for the pool being created
+   * @param params Creation parameters, passed to the runtime as they are
+   */
+  chi::Future<chimaera::bdev::CreateTask> AsyncCreate(
+      const chi::PoolQuery& pool_query,
+      const std::string& pool_name, const chi::PoolId& custom_pool_id,
+      const CreateParams& params) {
+    auto* ipc_manager = CHI_IPC;
+
+    auto task = ipc_manager->NewTask<chimaera::bdev::CreateTask>(
+        chi::CreateTaskId(),
+        chi::kAdminPoolId,  // Send to admin pool for GetOrCreatePool processing
+        pool_query,
+        CreateParams::chimod_lib_name,
+        pool_name,
+        custom_pool_id,
+        this,
+        params);  // Copy-constructs the task's CreateParams
+
+    return ipc_manager->Send(task);
+  }
+
   /**
    * Allocate data blocks - asynchronous
    */
@@ -43,6 +43,11 @@
 #include <list>
 #include <atomic>
 #include <chrono>
+#include <memory>
+
+#if WRP_CORE_ENABLE_S3
+#include "object_store.h"
+#endif
 
 /**
  * Runtime container for bdev ChiMod
@@ -370,6 +375,11 @@ class Runtime : public chi::Container {
   char* ram_buffer_;                              // RAM storage buffer
   chi::u64 ram_size_;                            // Total RAM buffer size
 
+#if WRP_CORE_ENABLE_S3
+  // Object-store storage (kObject)
+  std::unique_ptr<ObjectStore> object_store_;    // Bucket pages behind the device
+#endif
+
   // New allocator components
   GlobalBlockMap global_block_map_;              // Global block cache with per-worker locking
   Heap heap_;                                     // Heap allocator for new blocks
@@ -450,6 +460,17 @@ class Runtime : public chi::Container {
   void WriteToRam(hipc::FullPtr<WriteTask> task);
   void ReadFromRam(hipc::FullPtr<ReadTask> task);
 
+#if WRP_CORE_ENABLE_S3
+  /**
+   * Backend-specific object-store operations (coroutines that yield while a
+   * helper thread makes the blocking HTTP requests)
+   */
+  chi::TaskResume CreateObjectStore(ObjectStoreParams params,
+                                    hipc::FullPtr<CreateTask> task);
+  chi::TaskResume WriteToObjectStore(hipc::FullPtr<WriteTask> task);
+  chi::TaskResume ReadFromObjectStore(hipc::FullPtr<ReadTask> task);
+#endif
+
   /**
    * Update performance metrics
    */
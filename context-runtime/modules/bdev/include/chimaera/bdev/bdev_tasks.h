@@ -56,8 +56,9 @@ using MonitorTask = chimaera::admin::MonitorTask;
  * Block device type enumeration
  */
 enum class BdevType : chi::u32 {
-  kFile = 0,  // File-based block device (default)
-  kRam = 1    // RAM-based block device
+  kFile = 0,   // File-based block device (default)
+  kRam = 1,    // RAM-based block device
+  kObject = 2  // S3-compatible object store (requires WRP_CORE_ENABLE_S3)
 };
 
 /**
@@ -105,6 +106,31 @@ struct PerfMetrics {
   }
 };
 
+/**
+ * Object-store parameters for kObject block devices
+ *
+ * The device's offsets are split into pages of page_size_ bytes, each stored
+ * as the object "<prefix_>/<page number in hex>" of bucket_. Empty
+ * credentials and region fall back to the runtime's AWS_* environment.
+ */
+struct ObjectStoreParams {
+  std::string endpoint_;           // e.g. "http://minio:9000"; "" for AWS
+  std::string region_;             // Signing region ("" = AWS_REGION)
+  std::string bucket_;             // Bucket holding the pages
+  std::string prefix_;             // Key prefix of the pages
+  std::string access_key_id_;      // "" = AWS_ACCESS_KEY_ID
+  std::string secret_access_key_;  // "" = AWS_SECRET_ACCESS_KEY
+  std::string session_token_;      // Optional, for temporary credentials
+  chi::u64 page_size_ = 1024 * 1024;  // Bytes per object
+
+  // Cereal serialization
+  template <class Archive>
+  void serialize(Archive &ar) {
+    ar(endpoint_, region_, bucket_, prefix_, access_key_id_,
+       secret_access_key_, session_token_, page_size_);
+  }
+};
+
 /**
  * Persistence level for block devices
  */
@@ -122,7 +148,7 @@ struct CreateParams {
   // bdev-specific parameters
   BdevType bdev_type_;   // Block device type (file or RAM)
   chi::u64 total_size_;  // Total size for allocation (0 = file size for kFile,
-                         // required for kRam)
+                         // required for kRam, capacity hint for kObject)
   chi::u32 io_depth_;    // libaio queue depth (ignored for kRam)
   chi::u32 alignment_;   // I/O alignment (default 4096)
 
@@ -132,6 +158,9 @@ struct CreateParams {
   // Persistence level for this block device
   PersistenceLevel persistence_level_ = PersistenceLevel::kVolatile;
 
+  // Bucket, prefix and credentials (kObject only)
+  ObjectStoreParams object_store_;
+
   // Required: chimod library name for module manager
   static constexpr const char *chimod_lib_name = "chimaera_bdev";
 
@@ -203,10 +232,25 @@ struct CreateParams {
     }
   }
 
+  /**
+   * Default performance of an object store: tens of milliseconds per request
+   * and about 10 MB/s per stream, which keeps kObject targets low-scored
+   */
+  static PerfMetrics ObjectStorePerfMetrics() {
+    PerfMetrics metrics;
+    metrics.read_bandwidth_mbps_ = 10.0;
+    metrics.write_bandwidth_mbps_ = 8.0;
+    metrics.read_latency_us_ = 20000.0;   // 20ms
+    metrics.write_latency_us_ = 30000.0;  // 30ms
+    metrics.iops_ = 50.0;
+    return metrics;
+  }
+
   // Serialization support for cereal
   template <class Archive>
   void serialize(Archive &ar) {
-    ar(bdev_type_, total_size_, io_depth_, alignment_, perf_metrics_, persistence_level_);
+    ar(bdev_type_, total_size_, io_depth_, alignment_, perf_metrics_, persistence_level_,
+       object_store_);
   }
 
   /**
@@ -224,6 +268,9 @@ struct CreateParams {
         bdev_type_ = BdevType::kFile;
       } else if (type_str == "ram") {
         bdev_type_ = BdevType::kRam;
+      } else if (type_str == "object") {
+        bdev_type_ = BdevType::kObject;
+        perf_metrics_ = ObjectStorePerfMetrics();  // perf_metrics overrides
       }
     }
 
@@ -265,6 +312,28 @@ struct CreateParams {
       }
     }
 
+    // Load object-store location (optional, kObject only). Credentials are
+    // left to the runtime's AWS_* environment rather than the config file.
+    if (config["object_store"]) {
+      auto store = config["object_store"];
+      if (store["endpoint"]) {
+        object_store_.endpoint_ = store["endpoint"].as<std::string>();
+      }
+      if (store["region"]) {
+        object_store_.region_ = store["region"].as<std::string>();
+      }
+      if (store["bucket"]) {
+        object_store_.bucket_ = store["bucket"].as<std::string>();
+      }
+      if (store["prefix"]) {
+        object_store_.prefix_ = store["prefix"].as<std::string>();
+      }
+      if (store["page_size"]) {
+        object_store_.page_size_ = hshm::ConfigParse::ParseSize(
+            store["page_size"].as<std::string>());
+      }
+    }
+
     if (config["persistence_level"]) {
       std::string pl_str = config["persistence_level"].as<std::string>();
       if (pl_str == "volatile") {
@@ -0,0 +1,205 @@
+/*
+ * Copyright (c) 2024, Gnosis Research Center, Illinois Institute of Technology
+ * All rights reserved.
+ *
+ * This file is part of IOWarp Core.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice,
+ *    this list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+ * ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+ * LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+ * CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+ * SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+ * INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+ * CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+ * ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+ * POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#ifndef BDEV_OBJECT_STORE_H_
+#define BDEV_OBJECT_STORE_H_
+
+#include <cstddef>
+#include <mutex>
+#include <string>
+#include <vector>
+
+#include "bdev_tasks.h"
+
+/**
+ * S3-compatible object store backing kObject block devices
+ *
+ * Requests are plain HTTP(S) through libcurl and signed with AWS Signature
+ * Version 4. Calls block, so the runtime issues them off the worker threads.
+ */
+
+namespace chimaera::bdev {
+
+/**
+ * The pages of one kObject device
+ *
+ * Device offset o is byte (o % page_size) of page (o / page_size). A page is
+ * an object of at most page_size bytes; missing pages and bytes past the end
+ * of an object read as zeros.
+ */
+class ObjectStore {
+ public:
+  /**
+   * Resolve the endpoint and credentials; no request is made
+   * @param params Bucket, prefix and credentials; empty credentials and
+   *        region are taken from AWS_* environment variables
+   */
+  explicit ObjectStore(const ObjectStoreParams &params);
+
+  ~ObjectStore();
+
+  ObjectStore(const ObjectStore &) = delete;
+  ObjectStore &operator=(const ObjectStore &) = delete;
+
+  /**
+   * Check that the bucket exists and the credentials can reach it
+   * @param error Set to the reason on failure
+   * @return true if a HEAD of the bucket succeeded
+   */
+  bool Probe(std::string *error);
+
+  /**
+   * Write bytes at a device offset, rewriting the pages it touches
+   * @param data Bytes to write
+   * @param size Number of bytes
+   * @param offset Device offset of the first byte
+   * @param error Set to the reason on failure
+   * @return true if every page was stored
+   */
+  bool Write(const char *data, size_t size, chi::u64 offset,
+             std::string *error);
+
+  /**
+   * Read bytes at a device offset
+   * @param data Buffer of at least size bytes
+   * @param size Number of bytes
+   * @param offset Device offset of the first byte
+   * @param error Set to the reason on failure
+   * @return true if every page was read (missing pages are zeros)
+   */
+  bool Read(char *data, size_t size, chi::u64 offset, std::string *error);
+
+  /**
+   * Hex SHA-256 of a byte string
+   * @param data Bytes to hash
+   * @param size Number of bytes
+   * @return Lowercase hex digest
+   */
+  static std::string Sha256Hex(const char *data, size_t size);
+
+  /**
+   * Signature Version 4 signature of a canonical request
+   * @param secret Secret access key
+   * @param amz_date Request time as YYYYMMDDTHHMMSSZ
+   * @param region Signing region
+   * @param service Signing service ("s3")
+   * @param canonical_request Canonical request as the SigV4 spec builds it
+   * @return Lowercase hex signature
+   */
+  static std::string SignV4(const std::string &secret,
+                            const std::string &amz_date,
+                            const std::string &region,
+                            const std::string &service,
+                            const std::string &canonical_request);
+
+ private:
+  /** Result of one HTTP request */
+  struct Response {
+    long status_ = 0;     /**< HTTP status, 0 if no response arrived */
+    std::string body_;    /**< Response body */
+    std::string error_;   /**< Transport error, if any */
+  };
+
+  std::string endpoint_;           /**< Scheme and authority, no trailing / */
+  std::string host_;               /**< Authority, as signed */
+  std::string region_;
+  std::string bucket_;
+  std::string prefix_;             /**< Without a trailing / */
+  std::string access_key_id_;
+  std::string secret_access_key_;
+  std::string session_token_;
+  chi::u64 page_size_;
+
+  std::mutex handles_mutex_;       /**< Guards handles_ */
+  std::vector<void *> handles_;    /**< Idle CURL handles, kept for reuse */
+
+  static constexpr size_t kPageLocks = 64;
+  std::mutex page_locks_[kPageLocks];  /**< Serialize rewrites of a page */
+
+  /**
+   * Object key of a page
+   * @param page Page number
+   * @return "<prefix>/<page as 16 hex digits>"
+   */
+  std::string PageKey(chi::u64 page) const;
+
+  /**
+   * Send one signed request
+   * @param method HTTP method
+   * @param key Object key, or "" for the bucket itself
+   * @param body Request body (PUT only)
+   * @param range Value of the Range header, or "" for none
+   * @return Status and body
+   */
+  Response Send(const char *method, const std::string &key,
+                const std::string &body, const std::string &range);
+
+  /**
+   * Signed headers for a request
+   * @param method HTTP method
+   * @param path URI-encoded request path
+   * @param payload_hash Hex SHA-256 of the body
+   * @return Header lines, Authorization included
+   */
+  std::vector<std::string> SignedHeaders(const char *method,
+                                         const std::string &path,
+                                         const std::string &payload_hash);
+
+  /**
+   * Read part of a page
+   * @param page Page number
+   * @param begin Offset within the page
+   * @param end End offset within the page
+   * @param data Buffer of end - begin bytes
+   * @param error Set to the reason on failure
+   * @return true unless the store failed
+   */
+  bool ReadPage(chi::u64 page, size_t begin, size_t end, char *data,
+                std::string *error);
+
+  /**
+   * Write part of a page, merging with what the page holds
+   * @param page Page number
+   * @param begin Offset within the page
+   * @param end End offset within the page
+   * @param data end - begin bytes to write
+   * @param error Set to the reason on failure
+   * @return true if the page was stored
+   */
+  bool WritePage(chi::u64 page, size_t begin, size_t end, const char *data,
+                 std::string *error);
+};
+
+}  // namespace chimaera::bdev
+
+#endif  // BDEV_OBJECT_STORE_H_
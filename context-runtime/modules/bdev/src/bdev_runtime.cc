@@ -41,6 +41,7 @@
 #include <cmath>
 #include <cstdio>
 #include <cstring>
+#include <future>
 #include <thread>
 
 #include "hermes_shm/util/timer.h"
@@ -347,9 +348,15 @@ chi::TaskStat Runtime::GetTaskStats(chi::u32 method_id) const {
     case Method::kRead: {
       chi::TaskStat stat;
       stat.io_size_ = 1024 * 1024;
-      // wall_time = aligned pages / 500 MB/s
+      // wall_time = aligned pages / 500 MB/s, or / the configured write
+      // bandwidth for object stores so their targets score low
       size_t aligned = ((stat.io_size_ + 4095) / 4096) * 4096;
-      stat.wall_time_ = static_cast<float>(aligned) / 500.0f;
+      float bandwidth = 500.0f;
+      if (bdev_type_ == BdevType::kObject &&
+          perf_metrics_.write_bandwidth_mbps_ > 0.0) {
+        bandwidth = static_cast<float>(perf_metrics_.write_bandwidth_mbps_);
+      }
+      stat.wall_time_ = static_cast<float>(aligned) / bandwidth;
       return stat;
     }
     default: return chi::TaskStat();
@@ -455,11 +462,32 @@ chi::TaskResume Runtime::Create(hipc::FullPtr<CreateTask> task,
     }
     memset(ram_buffer_, 0, ram_size_);
     file_size_ = ram_size_;  // Use file_size_ for common allocation logic
+  } else if (bdev_type_ == BdevType::kObject) {
+#if WRP_CORE_ENABLE_S3
+    co_await CreateObjectStore(params.object_store_, task);
+    if (task->return_code_ != 0) {
+      co_return;
+    }
+    // The capacity is only a hint for placement: a bucket has no fixed size
+    file_size_ = (params.total_size_ > 0) ? params.total_size_
+                                          : (1ULL << 40);  // 1TB default
+#else
+    HLOG(kError,
+         "Object-store bdev '{}' needs a runtime built with "
+         "WRP_CORE_ENABLE_S3",
+         pool_name);
+    task->return_code_ = 6;
+    co_return;
+#endif
   }
 
   // Initialize common parameters
   alignment_ = params.alignment_;
   io_depth_ = params.io_depth_;
+  if (bdev_type_ == BdevType::kObject) {
+    // Start every block on a page, so a block of up to a page is one object
+    alignment_ = static_cast<chi::u32>(params.object_store_.page_size_);
+  }
 
   // Initialize the data allocator
   InitializeAllocator();
@@ -625,6 +653,11 @@ chi::TaskResume Runtime::Write(hipc::FullPtr<WriteTask> task,
     case BdevType::kRam:
       WriteToRam(task);
       break;
+#if WRP_CORE_ENABLE_S3
+    case BdevType::kObject:
+      co_await WriteToObjectStore(task);
+      break;
+#endif
     default:
       task->return_code_ = 1;
       task->bytes_written_ = 0;
@@ -642,6 +675,11 @@ chi::TaskResume Runtime::Read(hipc::FullPtr<ReadTask> task,
     case BdevType::kRam:
       ReadFromRam(task);
       break;
+#if WRP_CORE_ENABLE_S3
+    case BdevType::kObject:
+      co_await ReadFromObjectStore(task);
+      break;
+#endif
     default:
       task->return_code_ = 1;
       task->bytes_read_ = 0;
@@ -776,6 +814,119 @@ chi::TaskResume Runtime::ReadFromFile(hipc::FullPtr<ReadTask> task,
   co_return;
 }
 
+#if WRP_CORE_ENABLE_S3
+chi::TaskResume Runtime::CreateObjectStore(ObjectStoreParams params,
+                                           hipc::FullPtr<CreateTask> task) {
+  if (params.page_size_ == 0 || params.page_size_ > (1ULL << 31)) {
+    HLOG(kError, "Object-store page size {} is outside (0, 2GB]",
+         params.page_size_);
+    task->return_code_ = 7;
+    co_return;
+  }
+  object_store_ = std::make_unique<ObjectStore>(params);
+
+  // Fail now, not at the first write, on a bad bucket, endpoint or key
+  std::string error;
+  auto probe = std::async(std::launch::async,
+                          [&]() { return object_store_->Probe(&error); });
+  while (probe.wait_for(std::chrono::seconds(0)) !=
+         std::future_status::ready) {
+    co_await chi::yield(1000.0);
+  }
+  if (!probe.get()) {
+    HLOG(kError, "Object-store bdev s3://{}/{} is unreachable: {}",
+         params.bucket_, params.prefix_, error);
+    object_store_.reset();
+    task->return_code_ = 7;
+    co_return;
+  }
+  task->return_code_ = 0;
+  co_return;
+}
+
+chi::TaskResume Runtime::WriteToObjectStore(hipc::FullPtr<WriteTask> task) {
+  auto *ipc_mgr = CHI_IPC;
+  hipc::FullPtr<char> data_ptr = ipc_mgr->ToFullPtr(task->data_).Cast<char>();
+  std::vector<Block> blocks;
+  for (size_t i = 0; i < task->blocks_.size(); ++i) {
+    blocks.push_back(task->blocks_[i]);
+  }
+  chi::u64 length = task->length_;
+
+  // The HTTP requests block, so they run on a helper thread while the task
+  // yields; requests take milliseconds, so poll at that scale
+  std::string error;
+  auto io = std::async(std::launch::async, [&]() {
+    chi::u64 written = 0;
+    for (const Block &block : blocks) {
+      if (written == length) break;
+      chi::u64 size = std::min(length - written, block.size_);
+      if (!object_store_->Write(data_ptr.ptr_ + written,
+                                static_cast<size_t>(size), block.offset_,
+                                &error)) {
+        break;
+      }
+      written += size;
+    }
+    return written;
+  });
+  while (io.wait_for(std::chrono::seconds(0)) != std::future_status::ready) {
+    co_await chi::yield(1000.0);
+  }
+  task->bytes_written_ = io.get();
+  if (!error.empty()) {
+    HLOG(kError, "Object-store write failed: {}", error);
+    task->return_code_ = 4;
+    co_return;
+  }
+
+  task->return_code_ = 0;
+  total_writes_.fetch_add(1);
+  total_bytes_written_.fetch_add(task->bytes_written_);
+  co_return;
+}
+
+chi::TaskResume Runtime::ReadFromObjectStore(hipc::FullPtr<ReadTask> task) {
+  auto *ipc_mgr = CHI_IPC;
+  hipc::FullPtr<char> data_ptr = ipc_mgr->ToFullPtr(task->data_).Cast<char>();
+  std::vector<Block> blocks;
+  for (size_t i = 0; i < task->blocks_.size(); ++i) {
+    blocks.push_back(task->blocks_[i]);
+  }
+  chi::u64 length = task->length_;
+
+  // As for writes, the requests run on a helper thread
+  std::string error;
+  auto io = std::async(std::launch::async, [&]() {
+    chi::u64 read = 0;
+    for (const Block &block : blocks) {
+      if (read == length) break;
+      chi::u64 size = std::min(length - read, block.size_);
+      if (!object_store_->Read(data_ptr.ptr_ + read, static_cast<size_t>(size),
+                               block.offset_, &error)) {
+        break;
+      }
+      read += size;
+    }
+    return read;
+  });
+  while (io.wait_for(std::chrono::seconds(0)) != std::future_status::ready) {
+    co_await chi::yield(1000.0);
+  }
+  task->bytes_read_ = io.get();
+  if (!error.empty()) {
+    HLOG(kError, "Object-store read failed: {}", error);
+    task->return_code_ = 4;
+    co_return;
+  }
+
+  task->return_code_ = 0;
+  total_reads_.fetch_add(1);
+  total_bytes_read_.fetch_add(task->bytes_read_);
+  co_return;
+}
+#endif
+
 chi::TaskResume Runtime::GetStats(hipc::FullPtr<GetStatsTask> task,
                                   chi::RunContext &ctx) {
   // Predict wall time from learned model
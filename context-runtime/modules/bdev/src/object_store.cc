@@ -0,0 +1,435 @@
+/*
+ * Copyright (c) 2024, Gnosis Research Center, Illinois Institute of Technology
+ * All rights reserved.
+ *
+ * This file is part of IOWarp Core.
+ *
+ * Redistribution and use in source and binary forms, with or without
+ * modification, are permitted provided that the following conditions are met:
+ *
+ * 1. Redistributions of source code must retain the above copyright notice,
+ *    this list of conditions and the following disclaimer.
+ *
+ * 2. Redistributions in binary form must reproduce the above copyright notice,
+ *    this list of conditions and the following disclaimer in the documentation
+ *    and/or other materials provided with the distribution.
+ *
+ * 3. Neither the name of the copyright holder nor the names of its
+ *    contributors may be used to endorse or promote products derived from
+ *    this software without specific prior written permission.
+ *
+ * THIS SOFTWARE IS PROVIDED BY THE COPYRIGHT HOLDERS AND CONTRIBUTORS "AS IS"
+ * AND ANY EXPRESS OR IMPLIED WARRANTIES, INCLUDING, BUT NOT LIMITED TO, THE
+ * IMPLIED WARRANTIES OF MERCHANTABILITY AND FITNESS FOR A PARTICULAR PURPOSE
+ * ARE DISCLAIMED. IN NO EVENT SHALL THE COPYRIGHT HOLDER OR CONTRIBUTORS BE
+ * LIABLE FOR ANY DIRECT, INDIRECT, INCIDENTAL, SPECIAL, EXEMPLARY, OR
+ * CONSEQUENTIAL DAMAGES (INCLUDING, BUT NOT LIMITED TO, PROCUREMENT OF
+ * SUBSTITUTE GOODS OR SERVICES; LOSS OF USE, DATA, OR PROFITS; OR BUSINESS
+ * INTERRUPTION) HOWEVER CAUSED AND ON ANY THEORY OF LIABILITY, WHETHER IN
+ * CONTRACT, STRICT LIABILITY, OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE)
+ * ARISING IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE
+ * POSSIBILITY OF SUCH DAMAGE.
+ */
+
+#include <chimaera/bdev/object_store.h>
+#include <curl/curl.h>
+#include <openssl/evp.h>
+#include <openssl/hmac.h>
+
+#include <algorithm>
+#include <cstdio>
+#include <cstdlib>
+#include <cstring>
+#include <ctime>
+
+namespace chimaera::bdev {
+
+namespace {
+
+constexpr long kConnectTimeoutSec = 10;
+constexpr long kRequestTimeoutSec = 120;
+
+/**
+ * A parameter, or the environment variable standing in for it
+ * @param given Value from the create parameters
+ * @param name Environment variable used when given is empty
+ * @return given, the variable's value, or ""
+ */
+std::string ParamOrEnv(const std::string &given, const char *name) {
+  if (!given.empty()) return given;
+  const char *value = std::getenv(name);
+  return value != nullptr ? value : "";
+}
+
+/**
+ * Lowercase hex of a byte string
+ * @param data Bytes to format
+ * @param size Number of bytes
+ * @return Two hex digits per byte
+ */
+std::string Hex(const unsigned char *data, size_t size) {
+  static const char kDigits[] = "0123456789abcdef";
+  std::string out;
+  out.reserve(size * 2);
+  for (size_t i = 0; i < size; ++i) {
+    out.push_back(kDigits[data[i] >> 4]);
+    out.push_back(kDigits[data[i] & 0xf]);
+  }
+  return out;
+}
+
+/**
+ * HMAC-SHA256 of a message
+ * @param key Raw key bytes
+ * @param message Message to authenticate
+ * @return Raw 32-byte digest
+ */
+std::string Hmac(const std::string &key, const std::string &message) {
+  unsigned char digest[EVP_MAX_MD_SIZE];
+  unsigned int size = 0;
+  HMAC(EVP_sha256(), key.data(), static_cast<int>(key.size()),
+       reinterpret_cast<const unsigned char *>(message.data()), message.size(),
+       digest, &size);
+  return std::string(reinterpret_cast<char *>(digest), size);
+}
+
+/**
+ * URI-encode a path component as SigV4 canonicalizes it
+ * @param text Raw component
+ * @param keep_slash Whether '/' separates segments and stays as is
+ * @return Every byte outside A-Z a-z 0-9 - . _ ~ as %XX
+ */
+std::string UriEncode(const std::string &text, bool keep_slash) {
+  std::string out;
+  char escaped[4];
+  for (unsigned char c : text) {
+    bool unreserved = (c >= 'A' && c <= 'Z') || (c >= 'a' && c <= 'z') ||
+                      (c >= '0' && c <= '9') || c == '-' || c == '.' ||
+                      c == '_' || c == '~';
+    if (unreserved || (keep_slash && c == '/')) {
+      out.push_back(static_cast<char>(c));
+    } else {
+      std::snprintf(escaped, sizeof(escaped), "%%%02X", c);
+      out += escaped;
+    }
+  }
+  return out;
+}
+
+/**
+ * Current time in the x-amz-date format
+ * @return YYYYMMDDTHHMMSSZ in UTC
+ */
+std::string AmzDate() {
+  std::time_t now = std::time(nullptr);
+  std::tm utc;
+  gmtime_r(&now, &utc);
+  char out[17];
+  std::strftime(out, sizeof(out), "%Y%m%dT%H%M%SZ", &utc);
+  return out;
+}
+
+/**
+ * libcurl write callback appending to a std::string
+ */
+size_t AppendBody(char *data, size_t size, size_t count, void *body) {
+  static_cast<std::string *>(body)->append(data, size * count);
+  return size * count;
+}
+
+/**
+ * The <Code> of an S3 error document
+ * @param body Response body
+ * @return The code, or "" if the body has none
+ */
+std::string ErrorCode(const std::string &body) {
+  size_t begin = body.find("<Code>");
+  if (begin == std::string::npos) return "";
+  begin += 6;
+  size_t end = body.find("</Code>", begin);
+  if (end == std::string::npos) return "";
+  return body.substr(begin, end - begin);
+}
+
+/**
+ * Why a request failed, for the runtime's log
+ * @param method HTTP method
+ * @param key Object key
+ * @param status HTTP status, 0 if none arrived
+ * @param transport_error libcurl's error, if any
+ * @param body Response body, scanned for an S3 error code
+ * @return e.g. "PUT cte/0000000000000003: HTTP 403 AccessDenied"
+ */
+std::string Describe(const char *method, const std::string &key,
+                            long status, const std::string &transport_error,
+                            const std::string &body) {
+  std::string out = std::string(method) + " " + key + ": ";
+  if (!transport_error.empty()) return out + transport_error;
+  std::string code = ErrorCode(body);
+  return out + "HTTP " + std::to_string(status) +
+         (code.empty() ? "" : " " + code);
+}
+
+std::once_flag curl_init_once;
+
+}  // namespace
+
+ObjectStore::ObjectStore(const ObjectStoreParams &params)
+    : bucket_(params.bucket_),
+      prefix_(params.prefix_),
+      page_size_(params.page_size_ > 0 ? params.page_size_ : 1024 * 1024) {
+  std::call_once(curl_init_once, [] { curl_global_init(CURL_GLOBAL_ALL); });
+  // A key pair comes from one place: a token from the environment must not
+  // be paired with a key given in the parameters.
+  bool from_env = params.access_key_id_.empty();
+  access_key_id_ = ParamOrEnv(params.access_key_id_, "AWS_ACCESS_KEY_ID");
+  secret_access_key_ =
+      from_env ? ParamOrEnv("", "AWS_SECRET_ACCESS_KEY")
+               : params.secret_access_key_;
+  session_token_ = from_env ? ParamOrEnv("", "AWS_SESSION_TOKEN")
+                            : params.session_token_;
+  region_ = ParamOrEnv(params.region_, "AWS_REGION");
+  if (region_.empty()) region_ = "us-east-1";
+
+  while (!prefix_.empty() && prefix_.back() == '/') prefix_.pop_back();
+  endpoint_ = params.endpoint_.empty()
+                  ? "https://s3." + region_ + ".amazonaws.com"
+                  : params.endpoint_;
+  while (!endpoint_.empty() && endpoint_.back() == '/') endpoint_.pop_back();
+  size_t scheme = endpoint_.find("://");
+  if (scheme == std::string::npos) {
+    host_ = endpoint_;
+    endpoint_ = "https://" + endpoint_;
+  } else {
+    host_ = endpoint_.substr(scheme + 3);
+  }
+}
+
+ObjectStore::~ObjectStore() {
+  for (void *handle : handles_) {
+    curl_easy_cleanup(static_cast<CURL *>(handle));
+  }
+}
+
+bool ObjectStore::Probe(std::string *error) {
+  if (access_key_id_.empty() || secret_access_key_.empty()) {
+    *error = "no credentials: none given and AWS_ACCESS_KEY_ID or "
+             "AWS_SECRET_ACCESS_KEY is not set";
+    return false;
+  }
+  if (bucket_.empty()) {
+    *error = "no bucket given";
+    return false;
+  }
+  Response head = Send("HEAD", "", "", "");
+  if (head.status_ == 200) return true;
+  *error = Describe("HEAD", endpoint_ + "/" + bucket_, head.status_,
+                    head.error_, head.body_);
+  return false;
+}
+
+bool ObjectStore::Write(const char *data, size_t size, chi::u64 offset,
+                        std::string *error) {
+  size_t done = 0;
+  while (done < size) {
+    chi::u64 at = offset + done;
+    size_t begin = static_cast<size_t>(at % page_size_);
+    size_t end = static_cast<size_t>(
+        std::min<chi::u64>(page_size_, begin + (size - done)));
+    if (!WritePage(at / page_size_, begin, end, data + done, error)) {
+      return false;
+    }
+    done += end - begin;
+  }
+  return true;
+}
+
+bool ObjectStore::Read(char *data, size_t size, chi::u64 offset,
+                       std::string *error) {
+  size_t done = 0;
+  while (done < size) {
+    chi::u64 at = offset + done;
+    size_t begin = static_cast<size_t>(at % page_size_);
+    size_t end = static_cast<size_t>(
+        std::min<chi::u64>(page_size_, begin + (size - done)));
+    if (!ReadPage(at / page_size_, begin, end, data + done, error)) {
+      return false;
+    }
+    done += end - begin;
+  }
+  return true;
+}
+
+std::string ObjectStore::Sha256Hex(const char *data, size_t size) {
+  unsigned char digest[EVP_MAX_MD_SIZE];
+  unsigned int digest_size = 0;
+  EVP_Digest(data, size, digest, &digest_size, EVP_sha256(), nullptr);
+  return Hex(digest, digest_size);
+}
+
+std::string ObjectStore::SignV4(const std::string &secret,
+                                const std::string &amz_date,
+                                const std::string &region,
+                                const std::string &service,
+                                const std::string &canonical_request) {
+  std::string day = amz_date.substr(0, 8);
+  std::string scope = day + "/" + region + "/" + service + "/aws4_request";
+  std::string to_sign =
+      "AWS4-HMAC-SHA256\n" + amz_date + "\n" + scope + "\n" +
+      Sha256Hex(canonical_request.data(), canonical_request.size());
+  std::string key = Hmac("AWS4" + secret, day);
+  key = Hmac(key, region);
+  key = Hmac(key, service);
+  key = Hmac(key, "aws4_request");
+  std::string signature = Hmac(key, to_sign);
+  return Hex(reinterpret_cast<const unsigned char *>(signature.data()),
+             signature.size());
+}
+
+std::string ObjectStore::PageKey(chi::u64 page) const {
+  char hex[17];
+  std::snprintf(hex, sizeof(hex), "%016llx",
+                static_cast<unsigned long long>(page));
+  return prefix_.empty() ? hex : prefix_ + "/" + hex;
+}
+
+std::vector<std::string> ObjectStore::SignedHeaders(
+    const char *method, const std::string &path,
+    const std::string &payload_hash) {
+  std::string amz_date = AmzDate();
+  std::string canonical_headers = "host:" + host_ +
+                                  "\nx-amz-content-sha256:" + payload_hash +
+                                  "\nx-amz-date:" + amz_date + "\n";
+  std::string signed_names = "host;x-amz-content-sha256;x-amz-date";
+  if (!session_token_.empty()) {
+    canonical_headers += "x-amz-security-token:" + session_token_ + "\n";
+    signed_names += ";x-amz-security-token";
+  }
+  // No query string, hence the empty line after the path
+  std::string canonical_request = std::string(method) + "\n" + path +
+                                  "\n\n" + canonical_headers + "\n" +
+                                  signed_names + "\n" + payload_hash;
+  std::string scope =
+      amz_date.substr(0, 8) + "/" + region_ + "/s3/aws4_request";
+  std::string signature =
+      SignV4(secret_access_key_, amz_date, region_, "s3", canonical_request);
+
+  std::vector<std::string> lines = {"Host: " + host_,
+                                    "x-amz-content-sha256: " + payload_hash,
+                                    "x-amz-date: " + amz_date};
+  if (!session_token_.empty()) {
+    lines.push_back("x-amz-security-token: " + session_token_);
+  }
+  lines.push_back("Authorization: AWS4-HMAC-SHA256 Credential=" +
+                  access_key_id_ + "/" + scope +
+                  ", SignedHeaders=" + signed_names +
+                  ", Signature=" + signature);
+  return lines;
+}
+
+ObjectStore::Response ObjectStore::Send(const char *method,
+                                        const std::string &key,
+                                        const std::string &body,
+                                        const std::string &range) {
+  std::string path = "/" + UriEncode(bucket_, false);
+  if (!key.empty()) path += "/" + UriEncode(key, true);
+  std::vector<std::string> lines =
+      SignedHeaders(method, path, Sha256Hex(body.data(), body.size()));
+  if (!range.empty()) lines.push_back("Range: " + range);
+  lines.push_back("Expect:");  // No 100-continue round trip on PUT
+  curl_slist *headers = nullptr;
+  for (const auto &line : lines) headers = curl_slist_append(headers, line.c_str());
+
+  CURL *curl = nullptr;
+  {
+    std::lock_guard<std::mutex> lock(handles_mutex_);
+    if (!handles_.empty()) {
+      curl = static_cast<CURL *>(handles_.back());
+      handles_.pop_back();
+    }
+  }
+  if (curl == nullptr) curl = curl_easy_init();
+
+  Response response;
+  std::string url = endpoint_ + path;
+  curl_easy_setopt(curl, CURLOPT_URL, url.c_str());
+  curl_easy_setopt(curl, CURLOPT_HTTPHEADER, headers);
+  curl_easy_setopt(curl, CURLOPT_NOSIGNAL, 1L);
+  curl_easy_setopt(curl, CURLOPT_CONNECTTIMEOUT, kConnectTimeoutSec);
+  curl_easy_setopt(curl, CURLOPT_TIMEOUT, kRequestTimeoutSec);
+  curl_easy_setopt(curl, CURLOPT_WRITEFUNCTION, AppendBody);
+  curl_easy_setopt(curl, CURLOPT_WRITEDATA, &response.body_);
+  if (std::strcmp(method, "HEAD") == 0) {
+    curl_easy_setopt(curl, CURLOPT_NOBODY, 1L);
+  } else if (std::strcmp(method, "PUT") == 0) {
+    curl_easy_setopt(curl, CURLOPT_CUSTOMREQUEST, "PUT");
+    curl_easy_setopt(curl, CURLOPT_POSTFIELDS, body.data());
+    curl_easy_setopt(curl, CURLOPT_POSTFIELDSIZE_LARGE,
+                     static_cast<curl_off_t>(body.size()));
+  }
+  CURLcode rc = curl_easy_perform(curl);
+  if (rc == CURLE_OK) {
+    curl_easy_getinfo(curl, CURLINFO_RESPONSE_CODE, &response.status_);
+  } else {
+    response.error_ = curl_easy_strerror(rc);
+  }
+  curl_slist_free_all(headers);
+
+  curl_easy_reset(curl);  // Keeps the connection for the next request
+  std::lock_guard<std::mutex> lock(handles_mutex_);
+  handles_.push_back(curl);
+  return response;
+}
+
+bool ObjectStore::ReadPage(chi::u64 page, size_t begin, size_t end, char *data,
+                           std::string *error) {
+  std::string key = PageKey(page);
+  std::string range =
+      "bytes=" + std::to_string(begin) + "-" + std::to_string(end - 1);
+  Response got = Send("GET", key, "", range);
+  size_t length = end - begin;
+  size_t copied = 0;
+  if (got.status_ == 206) {
+    copied = std::min(got.body_.size(), length);
+    std::memcpy(data, got.body_.data(), copied);
+  } else if (got.status_ == 200) {
+    // The store ignored the range and sent the whole object
+    if (got.body_.size() > begin) {
+      copied = std::min(got.body_.size() - begin, length);
+      std::memcpy(data, got.body_.data() + begin, copied);
+    }
+  } else if (got.status_ != 404 && got.status_ != 416) {
+    // 404: page never written; 416: range past the end of a short page
+    *error = Describe("GET", key, got.status_, got.error_, got.body_);
+    return false;
+  }
+  std::memset(data + copied, 0, length - copied);
+  return true;
+}
+
+bool ObjectStore::WritePage(chi::u64 page, size_t begin, size_t end,
+                            const char *data, std::string *error) {
+  std::lock_guard<std::mutex> lock(page_locks_[page % kPageLocks]);
+  std::string key = PageKey(page);
+  std::string object;
+  if (begin != 0 || end != page_size_) {
+    // Partial page: keep the bytes around the written range
+    Response got = Send("GET", key, "", "");
+    if (got.status_ == 200) {
+      object = std::move(got.body_);
+    } else if (got.status_ != 404) {
+      *error = Describe("GET", key, got.status_, got.error_, got.body_);
+      return false;
+    }
+  }
+  if (object.size() < end) object.resize(end, '\0');
+  object.replace(begin, end - begin, data, end - begin);
+  Response put = Send("PUT", key, object, "");
+  if (put.status_ != 200) {
+    *error = Describe("PUT", key, put.status_, put.error_, put.body_);
+    return false;
+  }
+  return true;
+}
+
+}  // namespace chimaera::bdev
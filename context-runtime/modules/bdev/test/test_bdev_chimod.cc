@@ -66,6 +66,9 @@ using namespace std::chrono_literals;
 // Include bdev client and tasks
 #include <chimaera/bdev/bdev_client.h>
 #include <chimaera/bdev/bdev_tasks.h>
+#if WRP_CORE_ENABLE_S3
+#include <chimaera/bdev/object_store.h>
+#endif
 
 // Include admin client for pool management
 #include <chimaera/admin/admin_client.h>
@@ -1809,6 +1812,121 @@ TEST_CASE("bdev_force_net_flag", "[bdev][network][force_net]") {
   HLOG(kInfo, "[bdev_force_net_flag] TEST COMPLETE");
 }
 
+//==============================================================================
+// OBJECT-STORE BACKEND TESTS
+//==============================================================================
+
+TEST_CASE("bdev_object_sigv4_signature", "[bdev][object][sigv4]") {
+#if WRP_CORE_ENABLE_S3
+  // The example GET of the AWS Signature Version 4 documentation
+  std::string canonical_request =
+      "GET\n/\nAction=ListUsers&Version=2010-05-08\n"
+      "content-type:application/x-www-form-urlencoded; charset=utf-8\n"
+      "host:iam.amazonaws.com\nx-amz-date:20150830T123600Z\n\n"
+      "content-type;host;x-amz-date\n"
+      "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+  REQUIRE(chimaera::bdev::ObjectStore::Sha256Hex(canonical_request.data(),
+                                                 canonical_request.size()) ==
+          "f536975d06c0309214f805bb90ccff089219ecd68b2577efef23edd43b7e1a59");
+  std::string signature = chimaera::bdev::ObjectStore::SignV4(
+      "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "20150830T123600Z",
+      "us-east-1", "iam", canonical_request);
+  REQUIRE(signature ==
+          "5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7");
+#else
+  INFO("Skipping: built without WRP_CORE_ENABLE_S3");
+#endif
+}
+
+TEST_CASE("bdev_object_unreachable_store", "[bdev][object][error]") {
+  BdevChimodFixture fixture;
+  REQUIRE(g_initialized);
+
+  // Nothing listens on the discard port, so the create-time probe fails
+  // (code 7); without WRP_CORE_ENABLE_S3 kObject is refused outright (6)
+  chi::PoolId custom_pool_id(8011, 0);
+  chimaera::bdev::Client bdev_client(custom_pool_id);
+  chimaera::bdev::CreateParams params(chimaera::bdev::BdevType::kObject,
+                                      64 * k1MB);
+  params.object_store_.endpoint_ = "http://127.0.0.1:9";
+  params.object_store_.bucket_ = "cte-test";
+  params.object_store_.access_key_id_ = "AKIDEXAMPLE";
+  params.object_store_.secret_access_key_ = "secret";
+  auto create_task = bdev_client.AsyncCreate(
+      chi::PoolQuery::Dynamic(), "object_unreachable_test", custom_pool_id,
+      params);
+  create_task.Wait();
+  REQUIRE(create_task->GetReturnCode() != 0);
+}
+
+TEST_CASE("bdev_object_allocation_and_io", "[bdev][object][io]") {
+  // Needs a live store: CTE_TEST_S3_ENDPOINT (e.g. a local MinIO) and
+  // CTE_TEST_S3_BUCKET, with the runtime's AWS_* credentials
+  const char* endpoint = std::getenv("CTE_TEST_S3_ENDPOINT");
+  const char* bucket = std::getenv("CTE_TEST_S3_BUCKET");
+#if WRP_CORE_ENABLE_S3
+  if (endpoint == nullptr || bucket == nullptr) {
+    INFO("Skipping: CTE_TEST_S3_ENDPOINT or CTE_TEST_S3_BUCKET is not set");
+    return;
+  }
+#else
+  (void)endpoint;
+  (void)bucket;
+  INFO("Skipping: built without WRP_CORE_ENABLE_S3");
+  return;
+#endif
+  BdevChimodFixture fixture;
+  REQUIRE(g_initialized);
+
+  chi::PoolId custom_pool_id(8012, 0);
+  chimaera::bdev::Client bdev_client(custom_pool_id);
+  chimaera::bdev::CreateParams params(chimaera::bdev::BdevType::kObject,
+                                      64 * k1MB);
+  params.object_store_.endpoint_ = endpoint;
+  params.object_store_.bucket_ = bucket;
+  params.object_store_.prefix_ = "bdev_test_" + std::to_string(getpid());
+  auto create_task = bdev_client.AsyncCreate(
+      chi::PoolQuery::Dynamic(), "object_io_test", custom_pool_id, params);
+  create_task.Wait();
+  REQUIRE(create_task->GetReturnCode() == 0);
+  bdev_client.pool_id_ = create_task->new_pool_id_;
+
+  // A small block (one partial page) and a full 1MB block (one whole page)
+  for (chi::u64 size : {k4KB, k1MB}) {
+    auto pool_query = chi::PoolQuery::Local();
+    auto alloc_task = bdev_client.AsyncAllocateBlocks(pool_query, size);
+    alloc_task.Wait();
+    REQUIRE(alloc_task->return_code_ == 0);
+    REQUIRE(alloc_task->blocks_.size() == 1);
+    chimaera::bdev::Block block = alloc_task->blocks_[0];
+
+    auto write_buffer = CHI_IPC->AllocateBuffer(size);
+    REQUIRE_FALSE(write_buffer.IsNull());
+    for (size_t j = 0; j < size; ++j) {
+      write_buffer.ptr_[j] = static_cast<char>((j * 31 + size) % 251);
+    }
+    auto write_task = bdev_client.AsyncWrite(
+        pool_query, WrapBlock(block),
+        write_buffer.shm_.template Cast<void>().template Cast<void>(), size);
+    write_task.Wait();
+    REQUIRE(write_task->return_code_ == 0);
+    REQUIRE(write_task->bytes_written_ == size);
+
+    auto read_buffer = CHI_IPC->AllocateBuffer(size);
+    REQUIRE_FALSE(read_buffer.IsNull());
+    auto read_task = bdev_client.AsyncRead(
+        pool_query, WrapBlock(block),
+        read_buffer.shm_.template Cast<void>().template Cast<void>(), size);
+    read_task.Wait();
+    REQUIRE(read_task->return_code_ == 0);
+    REQUIRE(read_task->bytes_read_ == size);
+    REQUIRE(memcmp(write_buffer.ptr_, read_buffer.ptr_, size) == 0);
+
+    CHI_IPC->FreeBuffer(write_buffer);
+    CHI_IPC->FreeBuffer(read_buffer);
+  }
+}
+
 //==============================================================================
 // MAIN TEST RUNNER
 //==============================================================================
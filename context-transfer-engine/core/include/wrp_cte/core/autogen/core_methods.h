@@ -39,8 +39,10 @@ GLOBAL_CONST chi::u32 kBlobQuery = 31;
 GLOBAL_CONST chi::u32 kGetTargetInfo = 32;
 GLOBAL_CONST chi::u32 kFlushMetadata = 33;
 GLOBAL_CONST chi::u32 kFlushData = 34;
+GLOBAL_CONST chi::u32 kCompareAndSwapBlob = 35;
+GLOBAL_CONST chi::u32 kListBlobs = 36;
 
-GLOBAL_CONST chi::u32 kMaxMethodId = 35;
+GLOBAL_CONST chi::u32 kMaxMethodId = 37;
 
 inline const std::vector<std::string>& GetMethodNames() {
   static const std::vector<std::string> names = [] {
@@ -69,6 +71,8 @@ inline const std::vector<std::string>& GetMethodNames() {
     v[32] = "GetTargetInfo";
     v[33] = "FlushMetadata";
     v[34] = "FlushData";
+    v[35] = "CompareAndSwapBlob";
+    v[36] = "ListBlobs";
     return v;
   }();
   return names;
@@ -412,6 +412,31 @@ class Client : public chi::ContainerClient {
     return ipc_manager->Send(task);
   }
 
+  /**
+   * Asynchronous compare-and-swap of a small blob - returns immediately
+   * Overwrites the blob with desired only if it holds exactly expected
+   * @param tag_id Tag ID for blob lookup
+   * @param blob_name Name of the blob
+   * @param expected Current contents of the blob (empty: must not exist)
+   * @param desired Contents to write instead
+   * @param score Score to write desired at (0.0-1.0)
+   * @param pool_query Pool query for task routing (default: Dynamic)
+   * @return Future whose swapped_ says whether desired was written
+   */
+  chi::Future<CompareAndSwapBlobTask> AsyncCompareAndSwapBlob(
+      const TagId &tag_id, const std::string &blob_name,
+      const std::string &expected, const std::string &desired,
+      float score = 1.0f,
+      const chi::PoolQuery &pool_query = chi::PoolQuery::Dynamic()) {
+    auto *ipc_manager = CHI_IPC;
+
+    auto task = ipc_manager->NewTask<CompareAndSwapBlobTask>(
+        chi::CreateTaskId(), pool_id_, pool_query, tag_id, blob_name, expected,
+        desired, score);
+
+    return ipc_manager->Send(task);
+  }
+
   /**
    * Asynchronous get contained blobs - returns immediately
    * @param tag_id Tag ID
@@ -428,6 +453,26 @@ class Client : public chi::ContainerClient {
     return ipc_manager->Send(task);
   }
 
+  /**
+   * Asynchronous hierarchical blob listing - returns immediately
+   * @param tag_id Tag ID
+   * @param prefix Only list blobs whose names start with this
+   * @param delimiter Roll names up to the first delimiter after the prefix
+   *                  into common prefixes (empty = no roll-up)
+   * @param pool_query Pool query for task routing (default: Dynamic)
+   */
+  chi::Future<ListBlobsTask> AsyncListBlobs(
+      const TagId &tag_id, const std::string &prefix,
+      const std::string &delimiter,
+      const chi::PoolQuery &pool_query = chi::PoolQuery::Dynamic()) {
+    auto *ipc_manager = CHI_IPC;
+
+    auto task = ipc_manager->NewTask<ListBlobsTask>(
+        chi::CreateTaskId(), pool_id_, pool_query, tag_id, prefix, delimiter);
+
+    return ipc_manager->Send(task);
+  }
+
   /**
    * Asynchronous tag query - returns immediately
    * @param tag_regex Tag regex pattern to match
@@ -525,6 +570,13 @@ bool WRP_CTE_CLIENT_INIT(
     const std::string &config_path = "",
     const chi::PoolQuery &pool_query = chi::PoolQuery::Dynamic());
 
+/**
+ * Version of this client library ("major.minor.patch")
+ * Unmangled so bindings can look it up with dlsym and detect libraries that
+ * predate it.
+ */
+extern "C" const char *WrpCteCoreClientVersion();
+
 /**
  * Tag wrapper class - provides convenient API for tag operations
  */
@@ -46,6 +46,9 @@
 #include <wrp_cte/core/core_tasks.h>
 #include <wrp_cte/core/transaction_log.h>
 
+#include <string>
+#include <unordered_set>
+
 // Forward declarations to avoid circular dependency
 namespace wrp_cte::core {
 class Config;
@@ -224,6 +227,9 @@ private:
   chi::CoRwLock target_lock_;  // For registered_targets_ + target_name_to_id_
   chi::CoRwLock tag_map_lock_;  // For tag_name_to_id_ + tag_id_to_info_
   chi::CoRwLock blob_map_lock_;  // For tag_blob_name_to_info_
+  chi::CoMutex swap_lock_;  // For swaps_in_flight_
+  std::unordered_set<std::string>
+      swaps_in_flight_;  // Blobs with a CompareAndSwapBlob in progress
   // Use a set of locks based on maximum number of lanes for better concurrency
   static const size_t kMaxLocks =
       64; // Maximum number of locks (matches max lanes)
@@ -492,6 +498,25 @@ private:
    */
   chi::TaskResume FlushData(hipc::FullPtr<FlushDataTask> task, chi::RunContext &ctx);
 
+  /**
+   * Replace a blob only if it holds the expected bytes
+   * (Method::kCompareAndSwapBlob)
+   * @param task CompareAndSwapBlob task with the expected and desired contents
+   * @param ctx Runtime context for task execution
+   */
+  chi::TaskResume CompareAndSwapBlob(hipc::FullPtr<CompareAndSwapBlobTask> task,
+                                     chi::RunContext &ctx);
+
+  /**
+   * List one level of a tag's blob names by prefix and delimiter
+   * (Method::kListBlobs)
+   * @param task ListBlobs task containing the tag, prefix, delimiter and
+   *             results
+   * @param ctx Runtime context for task execution
+   */
+  chi::TaskResume ListBlobs(hipc::FullPtr<ListBlobsTask> task,
+                            chi::RunContext &ctx);
+
 private:
   /**
    * Helper function to compute hash-based pool query for blob operations
@@ -45,6 +45,7 @@
 #include <chimaera/bdev/bdev_client.h>
 #include <yaml-cpp/yaml.h>
 
+#include <algorithm>
 #include <chrono>
 // Include cereal for serialization
 #include <cereal/archives/binary.hpp>
@@ -1677,6 +1678,189 @@ struct GetContainedBlobsTask : public chi::Task {
   }
 };
 
+/**
+ * CompareAndSwapBlob task - Replace a small blob only if it still holds the
+ * expected bytes
+ * Behavior:
+ * - expected_ is the blob's whole current contents; empty means the blob must
+ *   not exist (or be empty).
+ * - If the blob matches, it is overwritten at offset 0 with desired_ at
+ *   score_ and swapped_ is set; otherwise nothing is written.
+ * - Swaps of the same blob are serialized by the container holding it, so of
+ *   two swaps from the same expected contents at most one succeeds. Plain
+ *   PutBlob writes to the blob are not ordered against swaps.
+ * - A swap that finds another swap of the blob in flight fails as a mismatch,
+ *   and the caller re-reads and retries.
+ */
+struct CompareAndSwapBlobTask : public chi::Task {
+  IN TagId tag_id_;                 // Tag ID for blob lookup
+  IN chi::priv::string blob_name_;  // Blob name (required)
+  IN chi::priv::string expected_;   // Contents the blob must hold
+  IN chi::priv::string desired_;    // Contents to replace them with
+  IN float score_;                  // Score to write desired_ at
+  OUT bool swapped_;                // Whether desired_ was written
+
+  // SHM constructor
+  CompareAndSwapBlobTask()
+      : chi::Task(),
+        tag_id_(TagId::GetNull()),
+        blob_name_(HSHM_MALLOC),
+        expected_(HSHM_MALLOC),
+        desired_(HSHM_MALLOC),
+        score_(1.0f),
+        swapped_(false) {}
+
+  // Emplace constructor
+  explicit CompareAndSwapBlobTask(const chi::TaskId &task_id,
+                                  const chi::PoolId &pool_id,
+                                  const chi::PoolQuery &pool_query,
+                                  const TagId &tag_id,
+                                  const std::string &blob_name,
+                                  const std::string &expected,
+                                  const std::string &desired, float score)
+      : chi::Task(task_id, pool_id, pool_query, Method::kCompareAndSwapBlob),
+        tag_id_(tag_id),
+        blob_name_(HSHM_MALLOC, blob_name),
+        expected_(HSHM_MALLOC, expected),
+        desired_(HSHM_MALLOC, desired),
+        score_(score),
+        swapped_(false) {
+    task_id_ = task_id;
+    pool_id_ = pool_id;
+    method_ = Method::kCompareAndSwapBlob;
+    task_flags_.Clear();
+    pool_query_ = pool_query;
+  }
+
+  /**
+   * Serialize IN and INOUT parameters
+   */
+  template <typename Archive>
+  void SerializeIn(Archive &ar) {
+    Task::SerializeIn(ar);
+    ar(tag_id_, blob_name_, expected_, desired_, score_);
+  }
+
+  /**
+   * Serialize OUT and INOUT parameters
+   */
+  template <typename Archive>
+  void SerializeOut(Archive &ar) {
+    Task::SerializeOut(ar);
+    ar(swapped_);
+  }
+
+  /**
+   * Copy from another CompareAndSwapBlobTask
+   */
+  void Copy(const hipc::FullPtr<CompareAndSwapBlobTask> &other) {
+    Task::Copy(other.template Cast<Task>());
+    tag_id_ = other->tag_id_;
+    blob_name_ = other->blob_name_;
+    expected_ = other->expected_;
+    desired_ = other->desired_;
+    score_ = other->score_;
+    swapped_ = other->swapped_;
+  }
+
+  /**
+   * Aggregate replica results into this task
+   */
+  void Aggregate(const hipc::FullPtr<chi::Task> &other_base) {
+    Task::Aggregate(other_base);
+    Copy(other_base.template Cast<CompareAndSwapBlobTask>());
+  }
+};
+
+/**
+ * ListBlobs task - List one level of the blob names in a tag
+ * Listing behavior:
+ * - Only blobs whose names start with prefix_ are returned (empty: all).
+ * - If delimiter_ is non-empty, a name with delimiter_ after the prefix is
+ *   rolled up into common_prefixes_ as the name through the first such
+ *   delimiter, instead of being returned in blob_names_, like a directory.
+ * - common_prefixes_ holds each rolled-up prefix once, in no particular order.
+ */
+struct ListBlobsTask : public chi::Task {
+  IN TagId tag_id_;                               // Tag ID to list
+  IN chi::priv::string prefix_;                   // Only names with this prefix
+  IN chi::priv::string delimiter_;                // Roll up names at this string
+  OUT std::vector<std::string> blob_names_;       // Names at this level
+  OUT std::vector<std::string> common_prefixes_;  // Rolled-up name prefixes
+
+  // SHM constructor
+  ListBlobsTask()
+      : chi::Task(),
+        tag_id_(TagId::GetNull()),
+        prefix_(HSHM_MALLOC),
+        delimiter_(HSHM_MALLOC) {}
+
+  // Emplace constructor
+  explicit ListBlobsTask(const chi::TaskId &task_id, const chi::PoolId &pool_id,
+                         const chi::PoolQuery &pool_query, const TagId &tag_id,
+                         const std::string &prefix,
+                         const std::string &delimiter)
+      : chi::Task(task_id, pool_id, pool_query, Method::kListBlobs),
+        tag_id_(tag_id),
+        prefix_(HSHM_MALLOC, prefix),
+        delimiter_(HSHM_MALLOC, delimiter) {
+    task_id_ = task_id;
+    pool_id_ = pool_id;
+    method_ = Method::kListBlobs;
+    task_flags_.Clear();
+    pool_query_ = pool_query;
+  }
+
+  /**
+   * Serialize IN and INOUT parameters
+   */
+  template <typename Archive>
+  void SerializeIn(Archive &ar) {
+    Task::SerializeIn(ar);
+    ar(tag_id_, prefix_, delimiter_);
+  }
+
+  /**
+   * Serialize OUT and INOUT parameters
+   */
+  template <typename Archive>
+  void SerializeOut(Archive &ar) {
+    Task::SerializeOut(ar);
+    ar(blob_names_, common_prefixes_);
+  }
+
+  /**
+   * Copy from another ListBlobsTask
+   */
+  void Copy(const hipc::FullPtr<ListBlobsTask> &other) {
+    Task::Copy(other.template Cast<Task>());
+    tag_id_ = other->tag_id_;
+    prefix_ = other->prefix_;
+    delimiter_ = other->delimiter_;
+    blob_names_ = other->blob_names_;
+    common_prefixes_ = other->common_prefixes_;
+  }
+
+  /**
+   * Aggregate results from a replica task
+   * Merges the blob_names_ vectors from multiple nodes, and the
+   * common_prefixes_ without repeating a prefix several nodes rolled up
+   */
+  void Aggregate(const hipc::FullPtr<chi::Task> &other_base) {
+    Task::Aggregate(other_base);
+    auto replica = other_base.template Cast<ListBlobsTask>();
+    for (const auto &name : replica->blob_names_) {
+      blob_names_.push_back(name);
+    }
+    for (const auto &prefix : replica->common_prefixes_) {
+      if (std::find(common_prefixes_.begin(), common_prefixes_.end(),
+                    prefix) == common_prefixes_.end()) {
+        common_prefixes_.push_back(prefix);
+      }
+    }
+  }
+};
+
 /**
  * TagQuery task - Query tags by regex pattern
  * New behavior:
@@ -183,6 +183,18 @@ chi::TaskResume Runtime::Run(chi::u32 method, hipc::FullPtr<chi::Task> task_ptr,
       co_await FlushData(typed_task, rctx);
       break;
     }
+    case Method::kCompareAndSwapBlob: {
+      // Cast task FullPtr to specific type
+      hipc::FullPtr<CompareAndSwapBlobTask> typed_task = task_ptr.template Cast<CompareAndSwapBlobTask>();
+      co_await CompareAndSwapBlob(typed_task, rctx);
+      break;
+    }
+    case Method::kListBlobs: {
+      // Cast task FullPtr to specific type
+      hipc::FullPtr<ListBlobsTask> typed_task = task_ptr.template Cast<ListBlobsTask>();
+      co_await ListBlobs(typed_task, rctx);
+      break;
+    }
     default: {
       // Unknown method - do nothing
       break;
@@ -315,6 +327,16 @@ void Runtime::SaveTask(chi::u32 method, chi::SaveTaskArchive& archive,
       archive << *typed_task.ptr_;
       break;
     }
+    case Method::kCompareAndSwapBlob: {
+      auto typed_task = task_ptr.template Cast<CompareAndSwapBlobTask>();
+      archive << *typed_task.ptr_;
+      break;
+    }
+    case Method::kListBlobs: {
+      auto typed_task = task_ptr.template Cast<ListBlobsTask>();
+      archive << *typed_task.ptr_;
+      break;
+    }
     default: {
       // Unknown method - do nothing
       break;
@@ -445,6 +467,16 @@ void Runtime::LoadTask(chi::u32 method, chi::LoadTaskArchive& archive,
       archive >> *typed_task.ptr_;
       break;
     }
+    case Method::kCompareAndSwapBlob: {
+      auto typed_task = task_ptr.template Cast<CompareAndSwapBlobTask>();
+      archive >> *typed_task.ptr_;
+      break;
+    }
+    case Method::kListBlobs: {
+      auto typed_task = task_ptr.template Cast<ListBlobsTask>();
+      archive >> *typed_task.ptr_;
+      break;
+    }
     default: {
       // Unknown method - do nothing
       break;
@@ -607,6 +639,18 @@ void Runtime::LocalLoadTask(chi::u32 method, chi::LocalLoadTaskArchive& archive,
       archive >> *typed_task.ptr_;
       break;
     }
+    case Method::kCompareAndSwapBlob: {
+      auto typed_task = task_ptr.template Cast<CompareAndSwapBlobTask>();
+      // Use archive operator which respects msg_type
+      archive >> *typed_task.ptr_;
+      break;
+    }
+    case Method::kListBlobs: {
+      auto typed_task = task_ptr.template Cast<ListBlobsTask>();
+      // Use archive operator which respects msg_type
+      archive >> *typed_task.ptr_;
+      break;
+    }
     default: {
       // Unknown method - do nothing
       break;
@@ -769,6 +813,18 @@ void Runtime::LocalSaveTask(chi::u32 method, chi::LocalSaveTaskArchive& archive,
       archive << *typed_task.ptr_;
       break;
     }
+    case Method::kCompareAndSwapBlob: {
+      auto typed_task = task_ptr.template Cast<CompareAndSwapBlobTask>();
+      // Use archive operator which respects msg_type
+      archive << *typed_task.ptr_;
+      break;
+    }
+    case Method::kListBlobs: {
+      auto typed_task = task_ptr.template Cast<ListBlobsTask>();
+      // Use archive operator which respects msg_type
+      archive << *typed_task.ptr_;
+      break;
+    }
     default: {
       // Unknown method - do nothing
       break;
@@ -1047,6 +1103,28 @@ hipc::FullPtr<chi::Task> Runtime::NewCopyTask(chi::u32 method, hipc::FullPtr<chi
       }
       break;
     }
+    case Method::kCompareAndSwapBlob: {
+      // Allocate new task
+      auto new_task_ptr = ipc_manager->NewTask<CompareAndSwapBlobTask>();
+      if (!new_task_ptr.IsNull()) {
+        // Copy task fields (includes base Task fields)
+        auto task_typed = orig_task_ptr.template Cast<CompareAndSwapBlobTask>();
+        new_task_ptr->Copy(task_typed);
+        return new_task_ptr.template Cast<chi::Task>();
+      }
+      break;
+    }
+    case Method::kListBlobs: {
+      // Allocate new task
+      auto new_task_ptr = ipc_manager->NewTask<ListBlobsTask>();
+      if (!new_task_ptr.IsNull()) {
+        // Copy task fields (includes base Task fields)
+        auto task_typed = orig_task_ptr.template Cast<ListBlobsTask>();
+        new_task_ptr->Copy(task_typed);
+        return new_task_ptr.template Cast<chi::Task>();
+      }
+      break;
+    }
     default: {
       // For unknown methods, create base Task copy
       auto new_task_ptr = ipc_manager->NewTask<chi::Task>();
@@ -1165,6 +1243,14 @@ hipc::FullPtr<chi::Task> Runtime::NewTask(chi::u32 method) {
       auto new_task_ptr = ipc_manager->NewTask<FlushDataTask>();
       return new_task_ptr.template Cast<chi::Task>();
     }
+    case Method::kCompareAndSwapBlob: {
+      auto new_task_ptr = ipc_manager->NewTask<CompareAndSwapBlobTask>();
+      return new_task_ptr.template Cast<chi::Task>();
+    }
+    case Method::kListBlobs: {
+      auto new_task_ptr = ipc_manager->NewTask<ListBlobsTask>();
+      return new_task_ptr.template Cast<chi::Task>();
+    }
     default: {
       // For unknown methods, return null pointer
       return hipc::FullPtr<chi::Task>();
@@ -1295,6 +1381,16 @@ void Runtime::Aggregate(chi::u32 method, hipc::FullPtr<chi::Task> orig_task,
       typed_task->Aggregate(replica_task);
       break;
     }
+    case Method::kCompareAndSwapBlob: {
+      auto typed_task = orig_task.template Cast<CompareAndSwapBlobTask>();
+      typed_task->Aggregate(replica_task);
+      break;
+    }
+    case Method::kListBlobs: {
+      auto typed_task = orig_task.template Cast<ListBlobsTask>();
+      typed_task->Aggregate(replica_task);
+      break;
+    }
     default: {
       orig_task->Aggregate(replica_task);
       break;
@@ -1402,6 +1498,14 @@ void Runtime::DelTask(chi::u32 method, hipc::FullPtr<chi::Task> task_ptr) {
       ipc_manager->DelTask(task_ptr.template Cast<FlushDataTask>());
       break;
     }
+    case Method::kCompareAndSwapBlob: {
+      ipc_manager->DelTask(task_ptr.template Cast<CompareAndSwapBlobTask>());
+      break;
+    }
+    case Method::kListBlobs: {
+      ipc_manager->DelTask(task_ptr.template Cast<ListBlobsTask>());
+      break;
+    }
     default: {
       ipc_manager->DelTask(task_ptr);
       break;
@@ -36,6 +36,10 @@
 #include <wrp_cte/core/core_client.h>
 #include <wrp_cte/core/core_config.h>
 
+#ifndef WRP_CTE_VERSION
+#define WRP_CTE_VERSION "unknown"
+#endif
+
 namespace wrp_cte::core {
 
 // Define global pointer variable for CTE client in source file
@@ -67,4 +71,6 @@ bool WRP_CTE_CLIENT_INIT(const std::string &config_path,
   return result;
 }
 
-} // namespace wrp_cte::core
\ No newline at end of file
+} // namespace wrp_cte::core
+
+extern "C" const char *WrpCteCoreClientVersion() { return WRP_CTE_VERSION; }
@@ -52,12 +52,17 @@
 #include <string>
 #include <tuple>
 #include <unordered_map>
+#include <unordered_set>
 #include <vector>
 
 #include "chimaera/worker.h"
 #include "hermes_shm/util/logging.h"
 #include "hermes_shm/util/timer.h"
 
+#ifndef WRP_CTE_VERSION
+#define WRP_CTE_VERSION "unknown"
+#endif
+
 namespace wrp_cte::core {
 
 // Bring chi namespace items into scope for CHI_CUR_WORKER macro
@@ -410,10 +415,15 @@ chi::PoolQuery Runtime::ScheduleTask(const hipc::FullPtr<chi::Task> &task) {
       auto typed = task.template Cast<GetBlobInfoTask>();
       return HashBlobToContainer(typed->tag_id_, typed->blob_name_.str());
     }
+    case Method::kCompareAndSwapBlob: {
+      auto typed = task.template Cast<CompareAndSwapBlobTask>();
+      return HashBlobToContainer(typed->tag_id_, typed->blob_name_.str());
+    }
 
     // Broadcast operations
     case Method::kGetTagSize:
     case Method::kGetContainedBlobs:
+    case Method::kListBlobs:
     case Method::kTagQuery:
     case Method::kBlobQuery:
       return chi::PoolQuery::Broadcast();
@@ -2913,6 +2923,86 @@ chi::TaskResume Runtime::GetBlobInfo(hipc::FullPtr<GetBlobInfoTask> task,
   co_return;
 }
 
+chi::TaskResume Runtime::CompareAndSwapBlob(
+    hipc::FullPtr<CompareAndSwapBlobTask> task, chi::RunContext &ctx) {
+  (void)ctx;
+  TagId tag_id = task->tag_id_;
+  std::string blob_name = task->blob_name_.str();
+  std::string expected = task->expected_.str();
+  std::string desired = task->desired_.str();
+  float score = task->score_;
+  task->swapped_ = false;
+
+  // Validate inputs
+  if (blob_name.empty() || desired.empty() || score < 0.0f || score > 1.0f) {
+    task->return_code_ = 1;
+    co_return;
+  }
+
+  // Step 1: Claim the blob; a swap already in flight makes this one a mismatch
+  std::string key = std::to_string(tag_id.major_) + "." +
+                    std::to_string(tag_id.minor_) + "." + blob_name;
+  {
+    chi::ScopedCoMutex lock(swap_lock_);
+    if (!swaps_in_flight_.insert(key).second) {
+      task->return_code_ = 0;
+      co_return;
+    }
+  }
+
+  auto *ipc_manager = CHI_IPC;
+  hipc::FullPtr<char> buffer;
+  chi::u32 result = 0;
+  try {
+    // Step 2: Read the current contents
+    BlobInfo *blob_info_ptr = CheckBlobExists(blob_name, tag_id);
+    chi::u64 size = blob_info_ptr ? blob_info_ptr->GetTotalSize() : 0;
+    chi::u64 buffer_size = std::max<chi::u64>(size, desired.size());
+    buffer = ipc_manager->AllocateBuffer(buffer_size);
+    // The blob is in this container, whichever one its name hashes to
+    chi::PoolQuery here = chi::PoolQuery::DirectId(container_id_);
+    if (buffer.IsNull()) {
+      HLOG(kError, "Failed to allocate buffer for CompareAndSwapBlob");
+      result = 5;
+    } else if (size != 0) {
+      auto get_task =
+          client_.AsyncGetBlob(tag_id, blob_name, 0, size, 0,
+                               buffer.shm_.template Cast<void>(), here);
+      co_await get_task;
+      if (get_task->return_code_ != 0u) result = 6;
+    }
+
+    // Step 3: Replace them if they are the expected ones
+    if (result == 0 && expected.size() == size &&
+        std::memcmp(buffer.ptr_, expected.data(), size) == 0) {
+      std::memcpy(buffer.ptr_, desired.data(), desired.size());
+      auto put_task = client_.AsyncPutBlob(
+          tag_id, blob_name, 0, desired.size(),
+          buffer.shm_.template Cast<void>(), score, Context(), 0, here);
+      co_await put_task;
+      if (put_task->return_code_ != 0) {
+        result = 7;
+      } else {
+        task->swapped_ = true;
+      }
+    }
+  } catch (const std::exception &e) {
+    HLOG(kError, "CompareAndSwapBlob failed: {}", e.what());
+    result = 1;
+  }
+
+  // Step 4: Release the buffer and the claim
+  if (!buffer.IsNull()) {
+    ipc_manager->FreeBuffer(buffer);
+  }
+  {
+    chi::ScopedCoMutex lock(swap_lock_);
+    swaps_in_flight_.erase(key);
+  }
+  task->return_code_ = result;
+  co_return;
+}
+
 chi::TaskResume Runtime::GetContainedBlobs(
     hipc::FullPtr<GetContainedBlobsTask> task, chi::RunContext &ctx) {
   try {
@@ -2963,6 +3053,61 @@ chi::TaskResume Runtime::GetContainedBlobs(
   co_return;
 }
 
+chi::TaskResume Runtime::ListBlobs(hipc::FullPtr<ListBlobsTask> task,
+                                   chi::RunContext &ctx) {
+  try {
+    TagId tag_id = task->tag_id_;
+
+    // Validate tag exists
+    TagInfo *tag_info_ptr = tag_id_to_info_.find(tag_id);
+    if (tag_info_ptr == nullptr) {
+      task->return_code_ = 1;  // Tag not found
+      co_return;
+    }
+
+    task->blob_names_.clear();
+    task->common_prefixes_.clear();
+
+    // Composite keys of this tag's blobs start with the tag prefix
+    std::string tag_prefix = std::to_string(tag_id.major_) + "." +
+                             std::to_string(tag_id.minor_) + ".";
+    std::string name_prefix = task->prefix_.str();
+    std::string delimiter = task->delimiter_.str();
+    std::unordered_set<std::string> rolled_up;
+
+    tag_blob_name_to_info_.for_each(
+        [&](const std::string &composite_key, const BlobInfo &blob_info) {
+          (void)blob_info;
+          if (composite_key.rfind(tag_prefix, 0) != 0) return;
+          std::string blob_name = composite_key.substr(tag_prefix.length());
+          if (blob_name.rfind(name_prefix, 0) != 0) return;
+          // Roll names with the delimiter past the listed prefix up to it
+          size_t pos = delimiter.empty()
+                           ? std::string::npos
+                           : blob_name.find(delimiter, name_prefix.length());
+          if (pos == std::string::npos) {
+            task->blob_names_.push_back(blob_name);
+            return;
+          }
+          std::string common = blob_name.substr(0, pos + delimiter.size());
+          if (rolled_up.insert(common).second) {
+            task->common_prefixes_.push_back(common);
+          }
+        });
+
+    task->return_code_ = 0;
+    HLOG(kDebug,
+         "ListBlobs successful: tag_id={},{}, {} blobs, {} common prefixes",
+         tag_id.major_, tag_id.minor_, task->blob_names_.size(),
+         task->common_prefixes_.size());
+
+  } catch (const std::exception &e) {
+    task->return_code_ = 1;  // Error during operation
+    HLOG(kError, "ListBlobs failed: {}", e.what());
+  }
+  co_return;
+}
+
 chi::TaskResume Runtime::TagQuery(hipc::FullPtr<TagQueryTask> task,
                                   chi::RunContext &ctx) {
   try {
@@ -3099,6 +3244,19 @@ chi::PoolQuery Runtime::HashBlobToContainer(const TagId &tag_id,
 
 chi::TaskResume Runtime::Monitor(hipc::FullPtr<MonitorTask> task,
                                  chi::RunContext &rctx) {
+  // "version": the runtime version, then the methods it dispatches as a
+  // comma-separated list of "id:Name", so clients can check them against the
+  // method IDs they were built with.
+  if (task->query_ == "version") {
+    std::string info = WRP_CTE_VERSION;
+    info += '\n';
+    const auto &names = Method::GetMethodNames();
+    for (size_t i = 0; i < names.size(); ++i) {
+      if (names[i].empty()) continue;
+      info += std::to_string(i) + ':' + names[i] + ',';
+    }
+    task->results_[container_id_] = info;
+  }
   task->SetReturnCode(0);
   (void)rctx;
   co_return;
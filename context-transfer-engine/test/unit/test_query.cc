@@ -43,6 +43,8 @@
  * 2. BlobQuery with tag and blob regex combinations
  * 3. Broadcast pool query behavior
  * 4. Empty result sets and edge cases
+ * 5. ListBlobs prefix filtering and delimiter roll-up
+ * 6. The "version" Monitor query clients negotiate with
  *
  * Following CLAUDE.md requirements:
  * - Use simple_test.h framework (NOT Catch2 - Catch2 causes segfaults with Chimaera runtime)
@@ -58,6 +60,7 @@
  #include <unistd.h>
  #endif
 
+ #include <algorithm>
  #include <cstdlib>
  #include <filesystem>
  #include <thread>
@@ -547,5 +550,101 @@
    REQUIRE(blob_results.size() > 0);
  }
 
+ /**
+  * Test ListBlobs with prefixes and delimiters, and that GetContainedBlobs
+  * still lists every blob
+  */
+ TEST_CASE("ListBlobs - Prefix and Delimiter", "[query][listblobs]") {
+   auto *fixture = hshm::Singleton<CTEQueryTestFixture>::GetInstance();
+  (void)fixture; // Suppress unused variable warning
+
+   wrp_cte::core::Tag tag("listing_tree");
+   std::vector<char> data(64, 'L');
+   for (const char *name : {"run42/step001/field.bin", "run42/step001/mask.bin",
+                            "run42/step002/field.bin", "run42/readme",
+                            "run43/readme", "top.txt"}) {
+     tag.PutBlob(name, data.data(), data.size());
+   }
+
+   auto *cte_client = WRP_CTE_CLIENT;
+   auto list = [&](const std::string &prefix, const std::string &delim) {
+     auto task = cte_client->AsyncListBlobs(tag.GetTagId(), prefix, delim,
+                                            chi::PoolQuery::Broadcast());
+     task.Wait();
+     REQUIRE(task->GetReturnCode() == 0);
+     std::vector<std::string> prefixes = task->common_prefixes_;
+     std::vector<std::string> blobs = task->blob_names_;
+     std::sort(prefixes.begin(), prefixes.end());
+     std::sort(blobs.begin(), blobs.end());
+     return std::make_pair(prefixes, blobs);
+   };
+   using Names = std::vector<std::string>;
+
+   // One level down: each rolled-up prefix once, however many blobs are in it
+   auto level = list("run42/", "/");
+   REQUIRE(level.first == Names({"run42/step001/", "run42/step002/"}));
+   REQUIRE(level.second == Names({"run42/readme"}));
+
+   // The top level
+   level = list("", "/");
+   REQUIRE(level.first == Names({"run42/", "run43/"}));
+   REQUIRE(level.second == Names({"top.txt"}));
+
+   // No delimiter: every name under the prefix, nothing rolled up
+   level = list("run42/step001/", "");
+   REQUIRE(level.first.empty());
+   REQUIRE(level.second ==
+           Names({"run42/step001/field.bin", "run42/step001/mask.bin"}));
+
+   // A multi-character delimiter ends at its last character
+   level = list("run42/", "/f");
+   REQUIRE(level.first == Names({"run42/step001/f", "run42/step002/f"}));
+   REQUIRE(level.second == Names({"run42/readme", "run42/step001/mask.bin"}));
+
+   // GetContainedBlobs keeps its wire format and lists everything
+   auto all = cte_client->AsyncGetContainedBlobs(tag.GetTagId(),
+                                                 chi::PoolQuery::Broadcast());
+   all.Wait();
+   REQUIRE(all->GetReturnCode() == 0);
+   REQUIRE(all->blob_names_.size() == 6);
+ }
+
+ /**
+  * Test the "version" Monitor query: the runtime version, then its method
+  * table as "id:Name," entries
+  */
+ TEST_CASE("Monitor - Version", "[query][monitor][version]") {
+   auto *fixture = hshm::Singleton<CTEQueryTestFixture>::GetInstance();
+  (void)fixture; // Suppress unused variable warning
+
+   auto *cte_client = WRP_CTE_CLIENT;
+   auto task = cte_client->AsyncMonitor(chi::PoolQuery::Local(), "version");
+   task.Wait();
+   REQUIRE(task->GetReturnCode() == 0);
+   REQUIRE(!task->results_.empty());
+
+   const std::string &info = task->results_.begin()->second;
+   size_t newline = info.find('\n');
+   REQUIRE(newline != std::string::npos);
+   // Built from the same tree, runtime and client library agree
+   REQUIRE(info.substr(0, newline) == WrpCteCoreClientVersion());
+
+   const auto &names = wrp_cte::core::Method::GetMethodNames();
+   for (size_t i = 0; i < names.size(); ++i) {
+     if (names[i].empty()) continue;
+     std::string entry = std::to_string(i) + ":" + names[i] + ",";
+     INFO("Looking for " << entry);
+     REQUIRE(info.find(entry, newline) != std::string::npos);
+   }
+   REQUIRE(info.find(std::to_string(wrp_cte::core::Method::kListBlobs) +
+                     ":ListBlobs,") != std::string::npos);
+
+   // Other queries are answered without a version
+   auto other = cte_client->AsyncMonitor(chi::PoolQuery::Local(), "nothing");
+   other.Wait();
+   REQUIRE(other->GetReturnCode() == 0);
+   REQUIRE(other->results_.empty());
+ }
+
 // Main function using simple_test.h framework
 SIMPLE_TEST_MAIN()
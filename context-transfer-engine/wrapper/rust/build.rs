@@ -51,4 +51,51 @@ fn main() {
     println!("cargo:rustc-link-arg=-Wl,-rpath,/home/iowarp/miniconda3/lib");
     println!("cargo:rerun-if-changed=shim/shim.h");
     println!("cargo:rerun-if-changed=shim/shim.cc");
+
+    // The version nodes the `.symver` directives of `ffi_c` bind exports to.
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("linux") {
+        let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        println!(
+            "cargo:rustc-cdylib-link-arg=-Wl,--version-script={}/cte_c.map",
+            manifest_dir
+        );
+        println!("cargo:rerun-if-changed=cte_c.map");
+    }
+
+    // Into OUT_DIR, not the source tree, so a build never dirties the checkout.
+    #[cfg(feature = "c-header")]
+    {
+        let config =
+            cbindgen::Config::from_file("cbindgen.toml").expect("failed to read cbindgen.toml");
+        let header =
+            std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("include/cte_c.h");
+        cbindgen::Builder::new()
+            .with_config(config)
+            .with_src("src/ffi_c.rs")
+            .generate()
+            .expect("failed to generate cte_c.h")
+            .write_to_file(&header);
+        println!("cargo:rerun-if-changed=src/ffi_c.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+    }
+
+    #[cfg(feature = "grpc")]
+    {
+        tonic_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/cte.proto"], &["proto"])
+            .expect("failed to compile proto/cte.proto (is protoc installed?)");
+        println!("cargo:rerun-if-changed=proto/cte.proto");
+    }
+
+    #[cfg(feature = "node")]
+    napi_build::setup();
+
+    #[cfg(feature = "cuda")]
+    {
+        let cuda = std::env::var("CUDA_PATH").unwrap_or_else(|_| "/usr/local/cuda".into());
+        println!("cargo:rustc-link-search=native={}/lib64", cuda);
+        println!("cargo:rustc-link-arg=-Wl,-rpath,{}/lib64", cuda);
+        println!("cargo:rerun-if-env-changed=CUDA_PATH");
+    }
 }
@@ -0,0 +1,15 @@
+fn main() {
+    // Keep every symbol of the linked archives (the wrapper, its C++ shim and
+    // the Rust standard library) out of the dynamic symbol table; only the
+    // `cte_c_*` functions defined in this crate are exported, at the version
+    // nodes of the wrapper's `cte_c.map`.
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("linux") {
+        let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        println!("cargo:rustc-cdylib-link-arg=-Wl,--exclude-libs,ALL");
+        println!(
+            "cargo:rustc-cdylib-link-arg=-Wl,--version-script={}/../cte_c.map",
+            manifest_dir
+        );
+        println!("cargo:rerun-if-changed=../cte_c.map");
+    }
+}
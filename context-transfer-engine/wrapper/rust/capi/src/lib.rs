@@ -0,0 +1,122 @@
+//! `libclio_cte.so`: the C ABI of `wrp-cte-rs` (its `ffi_c` module) as a
+//! shared library for language bindings.
+//!
+//! The crate's own cdylib also exports the C++ bridge's callbacks and, with
+//! their features, the JNI, N-API and HDF5 plugin entry points. This one
+//! exports the functions listed here and nothing else: the wrapper is built
+//! with feature `capi`, which leaves its functions unexported, the link hides
+//! every symbol of the libraries it pulls in (see `build.rs`), and each
+//! function below forwards to the wrapper's. The list is the audited surface;
+//! a test checks that it names every function of `ffi_c`, no more and no
+//! fewer, and the declarations are those of `cte_c.h`.
+//!
+//! The contracts of the `unsafe` functions are those `cte_c.h` documents.
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::{c_char, c_void};
+
+use wrp_cte_rs::ffi_c as c;
+
+macro_rules! export {
+    (
+        safe { $(fn $safe:ident($($sarg:ident: $sty:ty),*) $(-> $sret:ty)?;)* }
+        unsafe { $(fn $name:ident($($arg:ident: $ty:ty),*) $(-> $ret:ty)?;)* }
+    ) => {
+        $(
+            #[no_mangle]
+            pub extern "C" fn $safe($($sarg: $sty),*) $(-> $sret)? {
+                c::$safe($($sarg),*)
+            }
+        )*
+        $(
+            #[no_mangle]
+            pub unsafe extern "C" fn $name($($arg: $ty),*) $(-> $ret)? {
+                unsafe { c::$name($($arg),*) }
+            }
+        )*
+
+        #[cfg(target_os = "linux")]
+        std::arch::global_asm!($(concat!(
+            ".symver ", stringify!($safe), ", ", stringify!($safe), "@@CTE_C_1"
+        ),)* $(concat!(
+            ".symver ", stringify!($name), ", ", stringify!($name), "@@CTE_C_1"
+        )),*);
+
+        #[cfg(test)]
+        const EXPORTED: &[&str] = &[$(stringify!($safe),)* $(stringify!($name)),*];
+    };
+}
+
+export! {
+    safe {
+        fn cte_c_abi_version() -> u32;
+        fn cte_c_last_error() -> *const c_char;
+        fn cte_c_runtime_state() -> i32;
+        fn cte_c_set_request_limits(max_chunk: u64, max_read: u64) -> i32;
+        fn cte_c_max_name_len() -> u64;
+    }
+    unsafe {
+        fn cte_c_init(config: *const c_char) -> i32;
+        fn cte_c_tag_new(name: *const c_char) -> *mut c_void;
+        fn cte_c_tag_new_n(name: *const c_char, name_len: u64) -> *mut c_void;
+        fn cte_c_tag_free(tag: *mut c_void);
+        fn cte_c_tag_put_blob(
+            tag: *mut c_void,
+            name: *const c_char,
+            data: *const u8,
+            len: u64,
+            offset: u64,
+            score: f32
+        ) -> i32;
+        fn cte_c_tag_put_blob_n(
+            tag: *mut c_void,
+            name: *const c_char,
+            name_len: u64,
+            data: *const u8,
+            len: u64,
+            offset: u64,
+            score: f32
+        ) -> i32;
+        fn cte_c_tag_get_blob_size(tag: *mut c_void, name: *const c_char) -> u64;
+        fn cte_c_tag_get_blob_size_n(tag: *mut c_void, name: *const c_char, name_len: u64) -> u64;
+        fn cte_c_tag_get_blob(
+            tag: *mut c_void,
+            name: *const c_char,
+            buf: *mut u8,
+            size: u64,
+            offset: u64
+        ) -> i32;
+        fn cte_c_tag_get_blob_n(
+            tag: *mut c_void,
+            name: *const c_char,
+            name_len: u64,
+            buf: *mut u8,
+            size: u64,
+            offset: u64
+        ) -> i32;
+        fn cte_c_tag_get_contained_blobs(tag: *mut c_void, out_json: *mut *mut c_char) -> i32;
+        fn cte_c_tag_list_blobs(tag: *mut c_void, out_buf: *mut *mut u8, out_len: *mut u64) -> i32;
+        fn cte_c_del_tag(name: *const c_char) -> i32;
+        fn cte_c_del_tag_n(name: *const c_char, name_len: u64) -> i32;
+        fn cte_c_register_target(path: *const c_char, size: u64) -> i32;
+        fn cte_c_register_ram_target(name: *const c_char, size: u64) -> i32;
+        fn cte_c_register_remote_target(host: *const c_char, port: u16, capacity: u64) -> i32;
+        fn cte_c_free_buffer(buf: *mut u8, len: u64);
+        fn cte_c_free_string(ptr: *mut c_char);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exports_match_ffi_c() {
+        let mut exported = EXPORTED.to_vec();
+        let mut expected = c::EXPORTS.to_vec();
+        exported.sort_unstable();
+        expected.sort_unstable();
+        assert_eq!(exported, expected);
+        assert_eq!(cte_c_abi_version() >> 16, c::CTE_C_ABI_MAJOR);
+    }
+}
@@ -0,0 +1,120 @@
+//! `#[derive(CteBlob)]` for `wrp-cte-rs` (its `derive` feature).
+//!
+//! Implements `wrp_cte_rs::CteBlob` with a schema made of the type's name and
+//! its fields' names and types, written out without attributes, visibility or
+//! whitespace: `Particle{id:u64,pos:[f32;3]}`. It changes when a field is
+//! added, removed, renamed or retyped, which is what `Tag::get_typed` checks.
+//! The input is parsed by hand, so the crate has no dependencies.
+
+use proc_macro::{Delimiter, TokenStream, TokenTree};
+
+#[proc_macro_derive(CteBlob)]
+pub fn derive_cte_blob(input: TokenStream) -> TokenStream {
+    let code = match schema(input) {
+        Ok((name, schema)) => format!(
+            "impl ::wrp_cte_rs::CteBlob for {} {{ const SCHEMA: &'static str = {:?}; }}",
+            name, schema
+        ),
+        Err(msg) => format!("compile_error!({:?});", msg),
+    };
+    code.parse().expect("generated code parses")
+}
+
+/// The type's name and schema.
+fn schema(input: TokenStream) -> Result<(String, String), String> {
+    let mut tokens = strip(input).into_iter();
+    loop {
+        match tokens.next() {
+            Some(TokenTree::Ident(i)) if i.to_string() == "union" => {
+                return Err("CteBlob can't be derived for unions".into())
+            }
+            Some(TokenTree::Ident(i)) if matches!(i.to_string().as_str(), "struct" | "enum") => {
+                break
+            }
+            Some(_) => continue,
+            None => return Err("CteBlob is derived for structs and enums".into()),
+        }
+    }
+    let Some(TokenTree::Ident(name)) = tokens.next() else {
+        return Err("expected the type's name".into());
+    };
+    let mut body: Vec<TokenTree> = tokens.collect();
+    if matches!(body.first(), Some(TokenTree::Punct(p)) if p.as_char() == '<') {
+        // The schema is a constant, so it can't depend on type parameters.
+        return Err("CteBlob can't be derived for generic types".into());
+    }
+    if matches!(body.last(), Some(TokenTree::Punct(p)) if p.as_char() == ';') {
+        body.pop();
+    }
+    let name = name.to_string();
+    let mut out = name.clone();
+    write(&mut out, body);
+    Ok((name, out))
+}
+
+/// `tokens` without attributes or visibility, at any depth.
+fn strip(tokens: TokenStream) -> Vec<TokenTree> {
+    let mut out = Vec::new();
+    let mut it = tokens.into_iter().peekable();
+    while let Some(tt) = it.next() {
+        match &tt {
+            TokenTree::Punct(p) if p.as_char() == '#' => {
+                if matches!(it.peek(), Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Bracket)
+                {
+                    it.next();
+                    continue;
+                }
+            }
+            TokenTree::Ident(i) if i.to_string() == "pub" => {
+                if matches!(it.peek(), Some(TokenTree::Group(g)) if g.delimiter() == Delimiter::Parenthesis)
+                {
+                    it.next();
+                }
+                continue;
+            }
+            TokenTree::Group(g) => {
+                let inner = strip(g.stream()).into_iter().collect();
+                out.push(TokenTree::Group(proc_macro::Group::new(
+                    g.delimiter(),
+                    inner,
+                )));
+                continue;
+            }
+            _ => {}
+        }
+        out.push(tt);
+    }
+    out
+}
+
+/// Append `tokens` to `out` with the least whitespace that keeps them apart
+/// and no trailing commas, so the result doesn't depend on formatting.
+fn write(out: &mut String, mut tokens: Vec<TokenTree>) {
+    if matches!(tokens.last(), Some(TokenTree::Punct(p)) if p.as_char() == ',') {
+        tokens.pop();
+    }
+    let word = |c: char| c.is_alphanumeric() || c == '_';
+    for tt in tokens {
+        match tt {
+            TokenTree::Group(g) => {
+                let (open, close) = match g.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::None => ("", ""),
+                };
+                out.push_str(open);
+                write(out, g.stream().into_iter().collect());
+                out.push_str(close);
+            }
+            TokenTree::Punct(p) => out.push(p.as_char()),
+            tt => {
+                let s = tt.to_string();
+                if out.ends_with(word) && s.starts_with(word) {
+                    out.push(' ');
+                }
+                out.push_str(&s);
+            }
+        }
+    }
+}
@@ -0,0 +1,33 @@
+package org.iowarp.cte;
+
+/** The CTE client: initialization, targets and tags. */
+public final class Cte {
+    private Cte() {}
+
+    /**
+     * Initializes the client from {@code config}, or from the environment's
+     * configuration if it is {@code null}, and returns the runtime state reached:
+     * {@code client_attached} or {@code embedded_runtime}.
+     */
+    public static String init(String config) {
+        return Native.init(config);
+    }
+
+    public static String init() {
+        return init(null);
+    }
+
+    /** Registers a file storage target of {@code size} bytes. */
+    public static boolean registerTarget(String path, long size) {
+        return Native.registerTarget(path, size);
+    }
+
+    public static boolean tagExists(String name) {
+        return Native.tagExists(name);
+    }
+
+    /** Deletes a tag and its blobs; returns whether it existed. */
+    public static boolean delTag(String name) {
+        return Native.delTag(name);
+    }
+}
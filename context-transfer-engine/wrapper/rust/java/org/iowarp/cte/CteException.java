@@ -0,0 +1,10 @@
+package org.iowarp.cte;
+
+/** A failed CTE call; the message is the wrapper's {@code CteError}. */
+public class CteException extends RuntimeException {
+    private static final long serialVersionUID = 1L;
+
+    public CteException(String message) {
+        super(message);
+    }
+}
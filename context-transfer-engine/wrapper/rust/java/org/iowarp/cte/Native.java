@@ -0,0 +1,40 @@
+package org.iowarp.cte;
+
+import java.nio.ByteBuffer;
+
+/** The JNI exports of {@code wrp-cte-rs} built with feature {@code jni}. */
+final class Native {
+    static {
+        System.loadLibrary("wrp_cte_rs");
+    }
+
+    private Native() {}
+
+    static native String init(String config);
+
+    static native boolean registerTarget(String path, long size);
+
+    static native boolean tagExists(String name);
+
+    static native boolean delTag(String name);
+
+    static native long tagOpen(String name);
+
+    static native void tagFree(long tag);
+
+    static native void putBlob(long tag, String name, byte[] data, long offset);
+
+    static native void putBuffer(
+            long tag, String name, ByteBuffer buffer, int position, int len, long offset);
+
+    static native byte[] getBlob(long tag, String name, long offset, long size);
+
+    static native void getInto(
+            long tag, String name, ByteBuffer buffer, int position, int len, long offset);
+
+    static native long getBlobSize(long tag, String name);
+
+    static native String[] listBlobs(long tag);
+
+    static native boolean delBlob(long tag, String name);
+}
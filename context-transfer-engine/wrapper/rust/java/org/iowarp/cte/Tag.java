@@ -0,0 +1,114 @@
+package org.iowarp.cte;
+
+import java.nio.ByteBuffer;
+
+/**
+ * A CTE tag, opened (and created if missing) by name. Close it to free the
+ * native handle. A tag may be shared between threads, but must not be closed
+ * while calls through it are running.
+ *
+ * <p>Direct {@link ByteBuffer}s are read and filled in place. {@link #getDirect}
+ * reads a blob into a new direct buffer without copying it through the heap.
+ */
+public final class Tag implements AutoCloseable {
+    private final String name;
+    private long handle;
+
+    public Tag(String name) {
+        this.name = name;
+        this.handle = Native.tagOpen(name);
+    }
+
+    public String name() {
+        return name;
+    }
+
+    private synchronized long handle() {
+        if (handle == 0) {
+            throw new CteException("invalid argument: closed tag");
+        }
+        return handle;
+    }
+
+    /** Writes {@code data} into blob {@code blob} at {@code offset}. */
+    public void putBlob(String blob, byte[] data, long offset) {
+        Native.putBlob(handle(), blob, data, offset);
+    }
+
+    public void putBlob(String blob, byte[] data) {
+        putBlob(blob, data, 0);
+    }
+
+    /**
+     * Writes the remaining bytes of {@code data} into blob {@code blob} at
+     * {@code offset}, advancing its position past them.
+     */
+    public void putBlob(String blob, ByteBuffer data, long offset) {
+        int len = data.remaining();
+        if (data.isDirect()) {
+            Native.putBuffer(handle(), blob, data, data.position(), len, offset);
+            data.position(data.position() + len);
+        } else {
+            byte[] bytes = new byte[len];
+            data.get(bytes);
+            Native.putBlob(handle(), blob, bytes, offset);
+        }
+    }
+
+    /** Reads {@code size} bytes of blob {@code blob} from {@code offset}. */
+    public byte[] getBlob(String blob, long offset, long size) {
+        return Native.getBlob(handle(), blob, offset, size);
+    }
+
+    /** Reads the whole blob. */
+    public byte[] getBlob(String blob) {
+        return Native.getBlob(handle(), blob, 0, -1);
+    }
+
+    /**
+     * Fills the remaining bytes of {@code dst} with blob {@code blob}'s data from
+     * {@code offset}, advancing its position past them.
+     */
+    public void getBlob(String blob, ByteBuffer dst, long offset) {
+        int len = dst.remaining();
+        if (dst.isDirect()) {
+            Native.getInto(handle(), blob, dst, dst.position(), len, offset);
+            dst.position(dst.position() + len);
+        } else {
+            dst.put(Native.getBlob(handle(), blob, offset, len));
+        }
+    }
+
+    /** Reads the whole blob into a new direct buffer, positioned at 0. */
+    public ByteBuffer getDirect(String blob) {
+        long size = getBlobSize(blob);
+        if (size > Integer.MAX_VALUE) {
+            throw new CteException("invalid argument: '" + blob + "' is too large for a buffer");
+        }
+        ByteBuffer buffer = ByteBuffer.allocateDirect((int) size);
+        Native.getInto(handle(), blob, buffer, 0, (int) size, 0);
+        return buffer;
+    }
+
+    /** The size of blob {@code blob}; 0 if it doesn't exist. */
+    public long getBlobSize(String blob) {
+        return Native.getBlobSize(handle(), blob);
+    }
+
+    public String[] listBlobs() {
+        return Native.listBlobs(handle());
+    }
+
+    /** Deletes blob {@code blob}; returns whether it existed. */
+    public boolean delBlob(String blob) {
+        return Native.delBlob(handle(), blob);
+    }
+
+    @Override
+    public synchronized void close() {
+        if (handle != 0) {
+            Native.tagFree(handle);
+            handle = 0;
+        }
+    }
+}
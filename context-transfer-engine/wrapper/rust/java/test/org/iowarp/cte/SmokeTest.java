@@ -0,0 +1,100 @@
+package org.iowarp.cte;
+
+import java.nio.ByteBuffer;
+import java.nio.charset.StandardCharsets;
+import java.util.Arrays;
+
+/**
+ * Smoke test of the JNI exports against a live runtime: the {@code byte[]} and
+ * direct {@code ByteBuffer} paths, and failures surfacing as
+ * {@link CteException}. From the crate directory:
+ *
+ * <pre>
+ * cargo build --features jni
+ * javac -d target/java java/org/iowarp/cte/*.java java/test/org/iowarp/cte/SmokeTest.java
+ * java -Djava.library.path=target/debug -cp target/java org.iowarp.cte.SmokeTest
+ * </pre>
+ */
+public final class SmokeTest {
+    private static final String TAG = "java_smoke_tag";
+
+    private SmokeTest() {}
+
+    private static void check(boolean ok, String what) {
+        if (!ok) {
+            throw new AssertionError(what);
+        }
+    }
+
+    private static byte[] bytes(String s) {
+        return s.getBytes(StandardCharsets.UTF_8);
+    }
+
+    /** Runs {@code call} and checks it throws a CteException mentioning {@code message}. */
+    private static void fails(Runnable call, String message) {
+        try {
+            call.run();
+        } catch (CteException e) {
+            check(e.getMessage().contains(message), "'" + e.getMessage() + "' lacks '" + message + "'");
+            return;
+        }
+        throw new AssertionError("no CteException mentioning '" + message + "'");
+    }
+
+    public static void main(String[] args) throws InterruptedException {
+        String state = Cte.init();
+        check(state.equals("client_attached") || state.equals("embedded_runtime"), state);
+        Cte.registerTarget("/tmp/cte_java_test_target", 64L * 1024 * 1024);
+        Thread.sleep(200);
+
+        try (Tag tag = new Tag(TAG)) {
+            // byte[] in and out.
+            tag.putBlob("greeting", bytes("hello world"));
+            check(Arrays.equals(tag.getBlob("greeting"), bytes("hello world")), "whole read");
+            check(Arrays.equals(tag.getBlob("greeting", 6, 5), bytes("world")), "ranged read");
+            check(tag.getBlobSize("greeting") == 11, "size");
+            check(Arrays.asList(tag.listBlobs()).contains("greeting"), "listed");
+
+            // A direct buffer is written in place, a heap one through a copy.
+            ByteBuffer direct = ByteBuffer.allocateDirect(5);
+            direct.put(bytes("HELLO")).flip();
+            tag.putBlob("greeting", direct, 0);
+            check(direct.remaining() == 0, "direct put advances");
+            tag.putBlob("greeting", ByteBuffer.wrap(bytes("w0rld")), 6);
+            check(Arrays.equals(tag.getBlob("greeting"), bytes("HELLO w0rld")), "buffer puts");
+
+            // A direct buffer is filled in place.
+            ByteBuffer into = ByteBuffer.allocateDirect(8);
+            into.position(3);
+            tag.getBlob("greeting", into, 6);
+            check(into.position() == 8, "direct get advances");
+            byte[] filled = new byte[5];
+            into.position(3);
+            into.get(filled);
+            check(Arrays.equals(filled, bytes("w0rld")), "direct get");
+            ByteBuffer whole = tag.getDirect("greeting");
+            check(whole.capacity() == 11 && whole.get(4) == 'O', "getDirect");
+
+            // Errors from the wrapper are thrown as CteException.
+            fails(() -> tag.getBlob("missing"), "blob 'missing' not found");
+            fails(() -> tag.putBlob("greeting", bytes("x"), -1), "negative offset");
+            long handle = Native.tagOpen(TAG);
+            try {
+                fails(() -> Native.getInto(handle, "greeting", ByteBuffer.allocateDirect(4), 2, 4, 0),
+                        "range outside the buffer");
+                fails(() -> Native.putBuffer(handle, "greeting", ByteBuffer.allocate(4), 0, 4, 0),
+                        "JNI");
+                fails(() -> Native.putBlob(handle, "greeting", null, 0), "null data");
+            } finally {
+                Native.tagFree(handle);
+            }
+            fails(() -> Native.getBlobSize(0, "greeting"), "closed tag");
+            Tag closed = new Tag(TAG);
+            closed.close();
+            fails(() -> closed.getBlob("greeting"), "closed tag");
+        }
+
+        check(Cte.delTag(TAG) && !Cte.tagExists(TAG), "tag deleted");
+        System.out.println("ok");
+    }
+}
@@ -1,4 +1,17 @@
+#include <dlfcn.h>
+
+#include <cstring>
+#include <functional>
+#include <limits>
+#include <mutex>
+#include <stdexcept>
+#include <unordered_map>
+
+#include <chimaera/admin/admin_client.h>
 #include <chimaera/bdev/bdev_client.h>
+#include <chimaera/pool_manager.h>
+#include <hermes_shm/util/config_parse.h>
+#include <hermes_shm/serialize/msgpack_wrapper.h>
 #include <wrp_cte/core/content_transfer_engine.h>
 
 // cxx-generated header: defines CteTagId shared struct
@@ -6,6 +19,67 @@
 
 namespace cte_ffi {
 
+// The runtime's routing hash for a blob (Runtime::HashBlobToContainer).
+static uint32_t default_blob_hash(const wrp_cte::core::TagId &id,
+                                  const std::string &blob_name) {
+  std::hash<std::string> string_hasher;
+  std::hash<chi::u32> u32_hasher;
+  chi::u32 hash_value = u32_hasher(id.major_);
+  hash_value ^= u32_hasher(id.minor_) + 0x9e3779b9 + (hash_value << 6) +
+                (hash_value >> 2);
+  hash_value ^= static_cast<chi::u32>(string_hasher(blob_name)) + 0x9e3779b9 +
+                (hash_value << 6) + (hash_value >> 2);
+  return hash_value;
+}
+
+// Container for an operation on one blob: as chosen by the registered
+// PartitionStrategy and shard splits (src/partition.rs, src/shard.rs), else
+// the runtime's own name hash.
+static chi::PoolQuery route(const wrp_cte::core::TagId &id,
+                            const std::string &blob_name) {
+  rust::Slice<const uint8_t> name(
+      reinterpret_cast<const uint8_t *>(blob_name.data()), blob_name.size());
+  int64_t hash = blob_partition(CteTagId{id.major_, id.minor_}, name,
+                                default_blob_hash(id, blob_name));
+  if (hash < 0) return chi::PoolQuery::Dynamic();
+  return chi::PoolQuery::DirectHash(static_cast<chi::u32>(hash));
+}
+
+// Task context for a put: flagged for tracing with the caller's span ID when
+// the registered TraceContextProvider reports a sampled span (src/propagate.rs).
+static wrp_cte::core::Context put_context() {
+  wrp_cte::core::Context context;
+  uint64_t key = trace_key();
+  if (key != 0) {
+    context.trace_ = true;
+    context.trace_key_ = key;
+  }
+  return context;
+}
+
+// Deadlines of the calling thread (src/timeout.rs). Once it has passed,
+// nothing more is submitted, so a read that timed out can't be followed by a
+// write decided from its empty result.
+static bool expired() {
+  if (op_timeout() >= 0) return false;
+  op_timed_out();
+  return true;
+}
+
+// Waits for `task` until the deadline. On expiry the task, and any buffer it
+// uses, is abandoned to the runtime rather than freed under it, and the caller
+// returns an empty result.
+template <typename FutureT>
+static bool wait(FutureT &task) {
+  float limit = op_timeout();
+  // Past the deadline, look once: a limit of 0 would wait forever.
+  if (task.Wait(limit < 0 ? std::numeric_limits<float>::min() : limit)) {
+    return true;
+  }
+  op_timed_out();
+  return false;
+}
+
 bool cte_init(rust::Str config_path) {
   std::string path(config_path.data(), config_path.size());
   bool ok = chi::CHIMAERA_INIT(chi::ChimaeraMode::kClient, true);
@@ -13,9 +87,27 @@ bool cte_init(rust::Str config_path) {
   return wrp_cte::core::WRP_CTE_CLIENT_INIT(path);
 }
 
+uint8_t chimaera_mode() {
+  auto *manager = CHI_CHIMAERA_MANAGER;
+  if (!manager->IsInitialized()) return 0;
+  return manager->IsRuntime() ? 2 : 1;
+}
+
+// Names as the runtime takes them: any bytes, UTF-8 or not.
+static std::string name_of(rust::Str name) {
+  return std::string(name.data(), name.size());
+}
+
+static std::string name_of(rust::Slice<const uint8_t> name) {
+  return std::string(reinterpret_cast<const char *>(name.data()), name.size());
+}
+
 std::unique_ptr<CteTag> tag_new(rust::Str tag_name) {
-  std::string name(tag_name.data(), tag_name.size());
-  return std::make_unique<CteTag>(name);
+  return std::make_unique<CteTag>(name_of(tag_name));
+}
+
+std::unique_ptr<CteTag> tag_new_bytes(rust::Slice<const uint8_t> tag_name) {
+  return std::make_unique<CteTag>(name_of(tag_name));
 }
 
 std::unique_ptr<CteTag> tag_from_id(uint32_t major, uint32_t minor) {
@@ -23,33 +115,130 @@ std::unique_ptr<CteTag> tag_from_id(uint32_t major, uint32_t minor) {
   return std::make_unique<CteTag>(tid);
 }
 
+static void put_blob(const CteTag &tag, const std::string &blob_name,
+                     rust::Slice<const uint8_t> data, uint64_t offset,
+                     float score) {
+  if (expired()) return;
+  const auto &id = tag.inner.GetTagId();
+  auto *ipc_manager = CHI_IPC;
+  hipc::FullPtr<char> shm = ipc_manager->AllocateBuffer(data.size());
+  if (shm.IsNull()) {
+    throw std::runtime_error("Failed to allocate shared memory for PutBlob");
+  }
+  memcpy(shm.ptr_, data.data(), data.size());
+  auto task = WRP_CTE_CLIENT->AsyncPutBlob(
+      id, blob_name, offset, data.size(), hipc::ShmPtr<>(shm.shm_), score,
+      put_context(), 0, route(id, blob_name));
+  if (!wait(task)) return;
+  ipc_manager->FreeBuffer(shm);
+  if (task->GetReturnCode() != 0) {
+    throw std::runtime_error("PutBlob operation failed");
+  }
+}
+
 void tag_put_blob(const CteTag &tag, rust::Str name,
                   rust::Slice<const uint8_t> data, uint64_t offset,
                   float score) {
-  std::string blob_name(name.data(), name.size());
-  tag.inner.PutBlob(blob_name, reinterpret_cast<const char *>(data.data()),
-                    data.size(), static_cast<size_t>(offset), score);
+  put_blob(tag, name_of(name), data, offset, score);
+}
+
+void tag_put_blob_bytes(const CteTag &tag, rust::Slice<const uint8_t> name,
+                        rust::Slice<const uint8_t> data, uint64_t offset,
+                        float score) {
+  put_blob(tag, name_of(name), data, offset, score);
+}
+
+void tag_put_blob_placed(const CteTag &tag, rust::Str name,
+                         rust::Slice<const uint8_t> data, uint64_t offset) {
+  const auto &id = tag.inner.GetTagId();
+  BlobDescriptor desc{CteTagId{id.major_, id.minor_},
+                      rust::String(name.data(), name.size()),
+                      static_cast<uint64_t>(data.size()), offset};
+  float score = placement_score(desc);
+  tag_put_blob(tag, name, data, offset, score);
+}
+
+static std::unique_ptr<std::vector<uint8_t>> get_blob(
+    const CteTag &tag, const std::string &blob_name, uint64_t size,
+    uint64_t offset) {
+  if (size == 0) {
+    throw std::invalid_argument("data_size must be specified for GetBlob");
+  }
+  if (expired()) return std::make_unique<std::vector<uint8_t>>();
+  const auto &id = tag.inner.GetTagId();
+  auto *ipc_manager = CHI_IPC;
+  hipc::FullPtr<char> shm = ipc_manager->AllocateBuffer(size);
+  if (shm.IsNull()) {
+    throw std::runtime_error("Failed to allocate shared memory for GetBlob");
+  }
+  auto task = WRP_CTE_CLIENT->AsyncGetBlob(id, blob_name, offset, size, 0,
+                                           hipc::ShmPtr<>(shm.shm_),
+                                           route(id, blob_name));
+  if (!wait(task)) return std::make_unique<std::vector<uint8_t>>();
+  auto buf = std::make_unique<std::vector<uint8_t>>(size);
+  memcpy(buf->data(), shm.ptr_, size);
+  ipc_manager->FreeBuffer(shm);
+  if (task->GetReturnCode() != 0) {
+    throw std::runtime_error("GetBlob operation failed");
+  }
+  return buf;
 }
 
 std::unique_ptr<std::vector<uint8_t>> tag_get_blob(const CteTag &tag,
                                                     rust::Str name,
                                                     uint64_t size,
                                                     uint64_t offset) {
-  std::string blob_name(name.data(), name.size());
-  auto buf = std::make_unique<std::vector<uint8_t>>(size);
-  tag.inner.GetBlob(blob_name, reinterpret_cast<char *>(buf->data()),
-                    static_cast<size_t>(size), static_cast<size_t>(offset));
-  return buf;
+  return get_blob(tag, name_of(name), size, offset);
+}
+
+std::unique_ptr<std::vector<uint8_t>> tag_get_blob_bytes(
+    const CteTag &tag, rust::Slice<const uint8_t> name, uint64_t size,
+    uint64_t offset) {
+  return get_blob(tag, name_of(name), size, offset);
+}
+
+bool tag_swap_blob(const CteTag &tag, rust::Str name,
+                   rust::Slice<const uint8_t> expected,
+                   rust::Slice<const uint8_t> desired, float score) {
+  if (expired()) return false;
+  std::string blob_name = name_of(name);
+  const auto &id = tag.inner.GetTagId();
+  auto task = WRP_CTE_CLIENT->AsyncCompareAndSwapBlob(
+      id, blob_name, name_of(expected), name_of(desired), score,
+      route(id, blob_name));
+  if (!wait(task)) return false;
+  if (task->GetReturnCode() != 0) {
+    throw std::runtime_error("CompareAndSwapBlob operation failed");
+  }
+  return task->swapped_;
 }
 
 float tag_get_blob_score(const CteTag &tag, rust::Str name) {
+  if (expired()) return 0.0f;
   std::string blob_name(name.data(), name.size());
-  return tag.inner.GetBlobScore(blob_name);
+  const auto &id = tag.inner.GetTagId();
+  auto task = WRP_CTE_CLIENT->AsyncGetBlobScore(id, blob_name,
+                                                route(id, blob_name));
+  if (!wait(task)) return 0.0f;
+  return task->score_;
+}
+
+static uint64_t get_blob_size(const CteTag &tag, const std::string &blob_name) {
+  if (expired()) return 0;
+  const auto &id = tag.inner.GetTagId();
+  auto task = WRP_CTE_CLIENT->AsyncGetBlobSize(id, blob_name,
+                                               route(id, blob_name));
+  if (!wait(task)) return 0;
+  return task->size_;
 }
 
 uint64_t tag_get_blob_size(const CteTag &tag, rust::Str name) {
-  std::string blob_name(name.data(), name.size());
-  return tag.inner.GetBlobSize(blob_name);
+  return get_blob_size(tag, name_of(name));
+}
+
+uint64_t tag_get_blob_size_bytes(const CteTag &tag,
+                                 rust::Slice<const uint8_t> name) {
+  return get_blob_size(tag, name_of(name));
 }
 
 std::unique_ptr<std::vector<std::string>> tag_get_contained_blobs(
@@ -58,9 +247,60 @@ std::unique_ptr<std::vector<std::string>> tag_get_contained_blobs(
   return std::make_unique<std::vector<std::string>>(std::move(blobs));
 }
 
+static rust::Vec<uint8_t> bytes_of(const std::string &s) {
+  rust::Vec<uint8_t> out;
+  out.reserve(s.size());
+  for (char c : s) out.push_back(static_cast<uint8_t>(c));
+  return out;
+}
+
+rust::Vec<ListEntry> tag_list_blobs(const CteTag &tag,
+                                    rust::Slice<const uint8_t> prefix,
+                                    rust::Slice<const uint8_t> delimiter) {
+  rust::Vec<ListEntry> out;
+  if (expired()) return out;
+  auto task = WRP_CTE_CLIENT->AsyncListBlobs(
+      tag.inner.GetTagId(), name_of(prefix), name_of(delimiter));
+  if (!wait(task)) return out;
+  if (task->GetReturnCode() != 0) {
+    throw std::runtime_error("ListBlobs operation failed");
+  }
+  out.reserve(task->common_prefixes_.size() + task->blob_names_.size());
+  for (const auto &p : task->common_prefixes_) {
+    out.push_back(ListEntry{bytes_of(p), true});
+  }
+  for (const auto &name : task->blob_names_) {
+    out.push_back(ListEntry{bytes_of(name), false});
+  }
+  return out;
+}
+
 void tag_reorganize_blob(const CteTag &tag, rust::Str name, float score) {
+  if (expired()) return;
   std::string blob_name(name.data(), name.size());
-  tag.inner.ReorganizeBlob(blob_name, score);
+  const auto &id = tag.inner.GetTagId();
+  auto task = WRP_CTE_CLIENT->AsyncReorganizeBlob(id, blob_name, score,
+                                                  route(id, blob_name));
+  if (!wait(task)) return;
+  if (task->GetReturnCode() != 0) {
+    throw std::runtime_error("ReorganizeBlob operation failed");
+  }
+}
+
+static bool del_blob(const CteTag &tag, const std::string &blob_name) {
+  if (expired()) return false;
+  auto *client = WRP_CTE_CLIENT;
+  const auto &id = tag.inner.GetTagId();
+  auto task = client->AsyncDelBlob(id, blob_name, route(id, blob_name));
+  return wait(task) && task->GetReturnCode() == 0;
+}
+
+bool tag_del_blob(const CteTag &tag, rust::Str name) {
+  return del_blob(tag, name_of(name));
+}
+
+bool tag_del_blob_bytes(const CteTag &tag, rust::Slice<const uint8_t> name) {
+  return del_blob(tag, name_of(name));
 }
 
 CteTagId tag_get_id(const CteTag &tag) {
@@ -68,7 +308,216 @@ CteTagId tag_get_id(const CteTag &tag) {
   return CteTagId{id.major_, id.minor_};
 }
 
+// Batched variants for group commits (src/group.rs): every task is submitted
+// before any is waited on, so a batch costs about one round trip.
+
+rust::Vec<uint64_t> tag_get_blob_sizes(const CteTag &tag,
+                                       rust::Slice<const rust::String> names) {
+  const auto &id = tag.inner.GetTagId();
+  auto *client = WRP_CTE_CLIENT;
+  std::vector<chi::Future<wrp_cte::core::GetBlobSizeTask>> tasks;
+  rust::Vec<uint64_t> sizes;
+  if (expired()) {
+    for (size_t i = 0; i < names.size(); ++i) sizes.push_back(0);
+    return sizes;
+  }
+  tasks.reserve(names.size());
+  for (const auto &name : names) {
+    std::string blob_name(name.data(), name.size());
+    tasks.push_back(
+        client->AsyncGetBlobSize(id, blob_name, route(id, blob_name)));
+  }
+  sizes.reserve(tasks.size());
+  for (auto &task : tasks) {
+    sizes.push_back(wait(task) ? task->size_ : 0);
+  }
+  return sizes;
+}
+
+void tag_put_blobs(const CteTag &tag, rust::Slice<const rust::String> names,
+                   rust::Slice<const uint8_t> data,
+                   rust::Slice<const uint64_t> lens,
+                   rust::Slice<const float> scores) {
+  if (expired()) return;
+  const auto &id = tag.inner.GetTagId();
+  auto *ipc_manager = CHI_IPC;
+  auto *client = WRP_CTE_CLIENT;
+  std::vector<chi::Future<wrp_cte::core::PutBlobTask>> tasks;
+  std::vector<hipc::FullPtr<char>> buffers;
+  tasks.reserve(names.size());
+  buffers.reserve(names.size());
+  size_t off = 0;
+  for (size_t i = 0; i < names.size(); ++i) {
+    std::string blob_name(names[i].data(), names[i].size());
+    size_t len = static_cast<size_t>(lens[i]);
+    hipc::FullPtr<char> shm = ipc_manager->AllocateBuffer(len);
+    if (shm.IsNull()) {
+      for (size_t j = 0; j < tasks.size(); ++j) {
+        if (wait(tasks[j])) ipc_manager->FreeBuffer(buffers[j]);
+      }
+      throw std::runtime_error("Failed to allocate shared memory for PutBlob");
+    }
+    memcpy(shm.ptr_, data.data() + off, len);
+    off += len;
+    tasks.push_back(client->AsyncPutBlob(
+        id, blob_name, 0, len, hipc::ShmPtr<>(shm.shm_), scores[i],
+        put_context(), 0, route(id, blob_name)));
+    buffers.push_back(shm);
+  }
+  bool ok = true, timed_out = false;
+  for (size_t j = 0; j < tasks.size(); ++j) {
+    if (!wait(tasks[j])) {
+      timed_out = true;
+      continue;
+    }
+    ok = ok && tasks[j]->GetReturnCode() == 0;
+    ipc_manager->FreeBuffer(buffers[j]);
+  }
+  if (!ok && !timed_out) {
+    throw std::runtime_error("PutBlob operation failed");
+  }
+}
+
+rust::Vec<uint8_t> tag_del_blobs(const CteTag &tag,
+                                 rust::Slice<const rust::String> names) {
+  const auto &id = tag.inner.GetTagId();
+  auto *client = WRP_CTE_CLIENT;
+  std::vector<chi::Future<wrp_cte::core::DelBlobTask>> tasks;
+  rust::Vec<uint8_t> deleted;
+  if (expired()) {
+    for (size_t i = 0; i < names.size(); ++i) deleted.push_back(0);
+    return deleted;
+  }
+  tasks.reserve(names.size());
+  for (const auto &name : names) {
+    std::string blob_name(name.data(), name.size());
+    tasks.push_back(client->AsyncDelBlob(id, blob_name, route(id, blob_name)));
+  }
+  deleted.reserve(tasks.size());
+  for (auto &task : tasks) {
+    deleted.push_back(wait(task) && task->GetReturnCode() == 0 ? 1 : 0);
+  }
+  return deleted;
+}
+
+// Info of names[i] plus the contents of its sidecar meta_names[i], in three
+// batched rounds: info and sidecar sizes, then sidecar reads.
+rust::Vec<BlobInfoRow> tag_stat_blobs(
+    const CteTag &tag, rust::Slice<const rust::String> names,
+    rust::Slice<const rust::String> meta_names) {
+  const auto &id = tag.inner.GetTagId();
+  auto *ipc_manager = CHI_IPC;
+  auto *client = WRP_CTE_CLIENT;
+  std::vector<chi::Future<wrp_cte::core::GetBlobInfoTask>> infos;
+  std::vector<chi::Future<wrp_cte::core::GetBlobSizeTask>> sizes;
+  rust::Vec<BlobInfoRow> out;
+  if (expired()) {
+    for (size_t i = 0; i < names.size(); ++i) {
+      out.push_back(BlobInfoRow{0, 0.0f, rust::Vec<uint8_t>()});
+    }
+    return out;
+  }
+  infos.reserve(names.size());
+  sizes.reserve(names.size());
+  for (size_t i = 0; i < names.size(); ++i) {
+    std::string blob_name(names[i].data(), names[i].size());
+    std::string meta_name(meta_names[i].data(), meta_names[i].size());
+    infos.push_back(
+        client->AsyncGetBlobInfo(id, blob_name, route(id, blob_name)));
+    sizes.push_back(
+        client->AsyncGetBlobSize(id, meta_name, route(id, meta_name)));
+  }
+  out.reserve(names.size());
+  for (auto &info : infos) {
+    bool ok = wait(info) && info->GetReturnCode() == 0;
+    out.push_back(BlobInfoRow{ok ? info->total_size_ : 0,
+                              ok ? info->score_ : 0.0f, rust::Vec<uint8_t>()});
+  }
+  std::vector<size_t> pending;
+  std::vector<chi::Future<wrp_cte::core::GetBlobTask>> reads;
+  std::vector<hipc::FullPtr<char>> buffers;
+  for (size_t i = 0; i < sizes.size(); ++i) {
+    if (!wait(sizes[i])) continue;
+    uint64_t size = sizes[i]->size_;
+    if (size == 0 || expired()) continue;
+    hipc::FullPtr<char> shm = ipc_manager->AllocateBuffer(size);
+    if (shm.IsNull()) {
+      for (size_t j = 0; j < reads.size(); ++j) {
+        if (wait(reads[j])) ipc_manager->FreeBuffer(buffers[j]);
+      }
+      throw std::runtime_error("Failed to allocate shared memory for GetBlob");
+    }
+    std::string meta_name(meta_names[i].data(), meta_names[i].size());
+    reads.push_back(client->AsyncGetBlob(id, meta_name, 0, size, 0,
+                                         hipc::ShmPtr<>(shm.shm_),
+                                         route(id, meta_name)));
+    buffers.push_back(shm);
+    pending.push_back(i);
+  }
+  for (size_t j = 0; j < reads.size(); ++j) {
+    if (!wait(reads[j])) continue;
+    if (reads[j]->GetReturnCode() == 0) {
+      size_t i = pending[j];
+      uint64_t size = sizes[i]->size_;
+      auto &meta = out[i].meta;
+      meta.reserve(size);
+      for (uint64_t k = 0; k < size; ++k) {
+        meta.push_back(static_cast<uint8_t>(buffers[j].ptr_[k]));
+      }
+    }
+    ipc_manager->FreeBuffer(buffers[j]);
+  }
+  return out;
+}
+
+uint64_t tag_get_size(const CteTag &tag) {
+  if (expired()) return 0;
+  auto task = WRP_CTE_CLIENT->AsyncGetTagSize(tag.inner.GetTagId());
+  return wait(task) && task->GetReturnCode() == 0 ? task->tag_size_ : 0;
+}
+
+uint32_t tag_blob_hash(const CteTag &tag, rust::Str name) {
+  std::string blob_name(name.data(), name.size());
+  return default_blob_hash(tag.inner.GetTagId(), blob_name);
+}
+
+// Paths of the file targets this process registered, by bdev pool. A pool
+// registered under two paths maps to "", since its blocks can't be told apart.
+static std::mutex file_targets_mutex;
+static std::unordered_map<uint64_t, std::string> file_targets;
+
+static void note_file_target(const chi::PoolId &pool_id,
+                             const std::string &path) {
+  std::lock_guard<std::mutex> lock(file_targets_mutex);
+  auto inserted = file_targets.emplace(pool_id.ToU64(), path);
+  if (!inserted.second && inserted.first->second != path) {
+    inserted.first->second.clear();
+  }
+}
+
+rust::Vec<BlobBlockRow> tag_blob_blocks(const CteTag &tag, rust::Str name) {
+  rust::Vec<BlobBlockRow> out;
+  if (expired()) return out;
+  std::string blob_name(name.data(), name.size());
+  const auto &id = tag.inner.GetTagId();
+  auto task =
+      WRP_CTE_CLIENT->AsyncGetBlobInfo(id, blob_name, route(id, blob_name));
+  if (!wait(task)) return out;
+  if (task->GetReturnCode() != 0) {
+    throw std::runtime_error("GetBlobInfo operation failed");
+  }
+  std::lock_guard<std::mutex> lock(file_targets_mutex);
+  for (const auto &block : task->blocks_) {
+    auto it = file_targets.find(block.target_pool_id_.ToU64());
+    std::string path = it == file_targets.end() ? "" : it->second;
+    out.push_back(BlobBlockRow{rust::String(path), block.block_offset_,
+                               block.block_size_});
+  }
+  return out;
+}
+
 bool client_register_target(rust::Str target_path, uint64_t size) {
+  if (expired()) return false;
   std::string path(target_path.data(), target_path.size());
   // Create a bdev pool for this target
   chi::PoolId bdev_pool_id(800, 0);
@@ -76,24 +525,259 @@ bool client_register_target(rust::Str target_path, uint64_t size) {
   auto create_task = bdev_client.AsyncCreate(
       chi::PoolQuery::Dynamic(), path, bdev_pool_id,
       chimaera::bdev::BdevType::kFile);
-  create_task.Wait();
+  if (!wait(create_task) || expired()) return false;
   // Register with CTE
   auto *client = WRP_CTE_CLIENT;
   auto reg_task = client->AsyncRegisterTarget(
       path, chimaera::bdev::BdevType::kFile, size,
       chi::PoolQuery::Local(), bdev_pool_id);
-  reg_task.Wait();
+  if (!wait(reg_task)) return false;
+  note_file_target(bdev_pool_id, path);
   return true;
 }
 
-bool client_del_tag(rust::Str name) {
-  std::string tag_name(name.data(), name.size());
+bool client_register_ram_target(rust::Str name, uint64_t size) {
+  if (expired()) return false;
+  std::string target_name(name.data(), name.size());
+  // A pool per name: RAM targets can't share the file targets' pool, and
+  // a second RAM target must not reuse the first one's memory.
+  chi::PoolId bdev_pool_id(
+      801, static_cast<chi::u32>(std::hash<std::string>{}(target_name)));
+  chimaera::bdev::Client bdev_client(bdev_pool_id);
+  auto create_task = bdev_client.AsyncCreate(
+      chi::PoolQuery::Dynamic(), target_name, bdev_pool_id,
+      chimaera::bdev::BdevType::kRam, size);
+  if (!wait(create_task) || expired()) return false;
+  if (create_task->GetReturnCode() != 0) return false;
   auto *client = WRP_CTE_CLIENT;
-  auto task = client->AsyncDelTag(tag_name);
-  task.Wait();
+  auto reg_task = client->AsyncRegisterTarget(
+      target_name, chimaera::bdev::BdevType::kRam, size,
+      chi::PoolQuery::Local(), bdev_pool_id);
+  return wait(reg_task) && reg_task->GetReturnCode() == 0;
+}
+
+// The runtime's nodes, in hostfile order: a node's id is its offset. Without
+// a hostfile the runtime is the one node at its server address.
+rust::Vec<rust::String> client_hostfile() {
+  rust::Vec<rust::String> out;
+  auto *config = CHI_CONFIG_MANAGER;
+  std::string path = config->GetHostfilePath();
+  if (path.empty()) {
+    out.push_back(rust::String(config->GetServerAddr()));
+    return out;
+  }
+  for (const auto &host : hshm::ConfigParse::ParseHostfile(path)) {
+    out.push_back(rust::String(host));
+  }
+  if (out.empty()) {
+    throw std::runtime_error("no hosts in hostfile " + path);
+  }
+  return out;
+}
+
+uint32_t client_node_port() { return CHI_CONFIG_MANAGER->GetPort(); }
+
+void client_register_remote_target(rust::Str name, uint32_t node_id,
+                                   uint64_t size) {
+  if (expired()) {
+    throw std::runtime_error("timed out before registering the target");
+  }
+  std::string target_name(name.data(), name.size());
+  // As for RAM targets, a pool per name, created on node `node_id` so its
+  // blocks are allocated and moved there only.
+  chi::PoolId bdev_pool_id(
+      802, static_cast<chi::u32>(std::hash<std::string>{}(target_name)));
+  chimaera::bdev::Client bdev_client(bdev_pool_id);
+  auto create_task = bdev_client.AsyncCreate(
+      chi::PoolQuery::Physical(node_id), target_name, bdev_pool_id,
+      chimaera::bdev::BdevType::kRam, size);
+  if (!wait(create_task) || expired()) {
+    throw std::runtime_error("creating the target's device timed out");
+  }
+  if (create_task->GetReturnCode() != 0) {
+    throw std::runtime_error("creating the target's device failed with code " +
+                             std::to_string(create_task->GetReturnCode()));
+  }
+  auto reg_task = WRP_CTE_CLIENT->AsyncRegisterTarget(
+      target_name, chimaera::bdev::BdevType::kRam, size,
+      chi::PoolQuery::Physical(node_id), bdev_pool_id);
+  if (!wait(reg_task)) {
+    throw std::runtime_error("registering the target timed out");
+  }
+  if (reg_task->GetReturnCode() != 0) {
+    throw std::runtime_error("registering the target failed with code " +
+                             std::to_string(reg_task->GetReturnCode()));
+  }
+}
+
+bool client_register_s3_target(rust::Str name, rust::Str endpoint,
+                               rust::Str region, rust::Str bucket,
+                               rust::Str prefix, rust::Str access_key_id,
+                               rust::Str secret_access_key,
+                               rust::Str session_token, uint64_t size) {
+  if (expired()) {
+    throw std::runtime_error("timed out before registering the target");
+  }
+  std::string target_name(name.data(), name.size());
+  chimaera::bdev::CreateParams params(chimaera::bdev::BdevType::kObject, size);
+  params.perf_metrics_ = chimaera::bdev::CreateParams::ObjectStorePerfMetrics();
+  params.persistence_level_ = chimaera::bdev::PersistenceLevel::kLongTerm;
+  auto &store = params.object_store_;
+  store.endpoint_ = std::string(endpoint.data(), endpoint.size());
+  store.region_ = std::string(region.data(), region.size());
+  store.bucket_ = std::string(bucket.data(), bucket.size());
+  store.prefix_ = std::string(prefix.data(), prefix.size());
+  store.access_key_id_ = std::string(access_key_id.data(), access_key_id.size());
+  store.secret_access_key_ =
+      std::string(secret_access_key.data(), secret_access_key.size());
+  store.session_token_ = std::string(session_token.data(), session_token.size());
+  // As for RAM targets, a pool per name. The device is created here, with
+  // the bucket and credentials, so RegisterTarget finds it by name.
+  chi::PoolId bdev_pool_id(
+      803, static_cast<chi::u32>(std::hash<std::string>{}(target_name)));
+  chimaera::bdev::Client bdev_client(bdev_pool_id);
+  auto create_task = bdev_client.AsyncCreate(
+      chi::PoolQuery::Dynamic(), target_name, bdev_pool_id, params);
+  if (!wait(create_task) || expired()) {
+    throw std::runtime_error("creating the target's device timed out");
+  }
+  chi::u32 code = create_task->GetReturnCode();
+  if (code == 6) return false;  // Runtime built without WRP_CORE_ENABLE_S3
+  if (code == 7) {
+    throw std::runtime_error(
+        "the object store is unreachable or refused the credentials (see "
+        "the runtime log)");
+  }
+  if (code != 0) {
+    throw std::runtime_error("creating the target's device failed with code " +
+                             std::to_string(code));
+  }
+  auto reg_task = WRP_CTE_CLIENT->AsyncRegisterTarget(
+      target_name, chimaera::bdev::BdevType::kObject, size,
+      chi::PoolQuery::Local(), bdev_pool_id);
+  if (!wait(reg_task)) {
+    throw std::runtime_error("registering the target timed out");
+  }
+  if (reg_task->GetReturnCode() != 0) {
+    throw std::runtime_error("registering the target failed with code " +
+                             std::to_string(reg_task->GetReturnCode()));
+  }
   return true;
 }
 
+static bool del_tag(const std::string &tag_name) {
+  if (expired()) return false;
+  auto *client = WRP_CTE_CLIENT;
+  auto task = client->AsyncDelTag(tag_name);
+  return wait(task);
+}
+
+bool client_del_tag(rust::Str name) { return del_tag(name_of(name)); }
+
+bool client_del_tag_bytes(rust::Slice<const uint8_t> name) {
+  return del_tag(name_of(name));
+}
+
+uint32_t client_container_count() {
+  auto *pool_manager = CHI_POOL_MANAGER;
+  if (pool_manager == nullptr) return 0;
+  const chi::PoolInfo *info =
+      pool_manager->GetPoolInfo(WRP_CTE_CLIENT->pool_id_);
+  return info == nullptr ? 0 : info->num_containers_;
+}
+
+rust::Vec<TargetInfo> client_list_targets() {
+  if (expired()) return {};
+  auto *client = WRP_CTE_CLIENT;
+  auto list = client->AsyncListTargets();
+  rust::Vec<TargetInfo> out;
+  if (!wait(list) || list->GetReturnCode() != 0) return out;
+  for (const auto &name : list->target_names_) {
+    if (expired()) break;
+    auto info = client->AsyncGetTargetInfo(name);
+    if (!wait(info) || info->GetReturnCode() != 0) continue;
+    out.push_back(TargetInfo{rust::String(name), info->target_score_,
+                             info->remaining_space_, info->bytes_read_,
+                             info->bytes_written_});
+  }
+  return out;
+}
+
+// Queue state of the local runtime's workers, decoded from the admin
+// "worker_stats" monitor query (as chimaera monitor does).
+rust::Vec<WorkerStats> client_worker_stats() {
+  rust::Vec<WorkerStats> out;
+  if (expired()) return out;
+  auto *admin = CHI_ADMIN;
+  if (admin == nullptr) return out;
+  auto task = admin->AsyncMonitor(chi::PoolQuery::Local(), "worker_stats");
+  if (!wait(task) || task->GetReturnCode() != 0) return out;
+  for (const auto &[container_id, blob] : task->results_) {
+    if (blob.empty()) continue;
+    msgpack::object_handle oh = msgpack::unpack(blob.data(), blob.size());
+    const msgpack::object &obj = oh.get();
+    if (obj.type != msgpack::type::ARRAY) continue;
+    for (uint32_t i = 0; i < obj.via.array.size; ++i) {
+      const msgpack::object &item = obj.via.array.ptr[i];
+      if (item.type != msgpack::type::MAP) continue;
+      WorkerStats stats{0, 0, 0, 0, 0, false};
+      for (uint32_t j = 0; j < item.via.map.size; ++j) {
+        const auto &kv = item.via.map.ptr[j];
+        std::string key;
+        kv.key.convert(key);
+        if (key == "worker_id") kv.val.convert(stats.worker_id);
+        else if (key == "num_queued_tasks") kv.val.convert(stats.queued);
+        else if (key == "num_blocked_tasks") kv.val.convert(stats.blocked);
+        else if (key == "num_retry_tasks") kv.val.convert(stats.retry);
+        else if (key == "num_tasks_processed") kv.val.convert(stats.processed);
+        else if (key == "is_active") kv.val.convert(stats.active);
+      }
+      out.push_back(stats);
+    }
+  }
+  return out;
+}
+
+// Versions and method tables on both sides of the bridge (src/handshake.rs):
+// the method IDs this shim was compiled with, the loaded client library's
+// version if it exports one, and the runtime's answer to the core "version"
+// monitor query if it understands it. Older libraries and runtimes leave
+// their fields empty.
+HandshakeInfo runtime_handshake() {
+  HandshakeInfo out;
+  const auto &names = wrp_cte::core::Method::GetMethodNames();
+  for (size_t i = 0; i < names.size(); ++i) {
+    if (names[i].empty()) continue;
+    out.bridge_methods.push_back(
+        rust::String(std::to_string(i) + ":" + names[i]));
+  }
+  using VersionFn = const char *(*)();
+  auto library_version = reinterpret_cast<VersionFn>(
+      dlsym(RTLD_DEFAULT, "WrpCteCoreClientVersion"));
+  if (library_version != nullptr) out.library = rust::String(library_version());
+  if (expired()) return out;
+  auto task =
+      WRP_CTE_CLIENT->AsyncMonitor(chi::PoolQuery::Local(), "version");
+  if (!wait(task) || task->GetReturnCode() != 0) return out;
+  for (const auto &[container_id, info] : task->results_) {
+    size_t newline = info.find('\n');
+    if (newline == std::string::npos) continue;
+    out.runtime = rust::String(info.substr(0, newline));
+    size_t start = newline + 1;
+    while (start < info.size()) {
+      size_t end = info.find(',', start);
+      if (end == std::string::npos) end = info.size();
+      if (end > start) {
+        out.runtime_methods.push_back(
+            rust::String(info.substr(start, end - start)));
+      }
+      start = end + 1;
+    }
+    break;
+  }
+  return out;
+}
+
 std::unique_ptr<std::vector<std::string>> client_tag_query(rust::Str regex,
                                                             uint32_t max_tags) {
   std::string re(regex.data(), regex.size());
@@ -118,4 +802,42 @@ std::unique_ptr<std::vector<std::string>> client_blob_query(rust::Str tag_re,
   return out;
 }
 
+rust::Vec<BlobQueryRow> client_blob_query_stat(rust::Str tag_re,
+                                               rust::Str blob_re,
+                                               uint32_t max_results,
+                                               uint64_t min_size,
+                                               uint64_t max_size,
+                                               float min_score,
+                                               float max_score) {
+  std::string tre(tag_re.data(), tag_re.size());
+  std::string bre(blob_re.data(), blob_re.size());
+  auto *mgr = CTE_MANAGER;
+  auto *client = WRP_CTE_CLIENT;
+  auto pairs = mgr->BlobQuery(tre, bre, max_results);
+  std::unordered_map<std::string, wrp_cte::core::TagId> tag_ids;
+  rust::Vec<BlobQueryRow> out;
+  for (auto &p : pairs) {
+    // Wrapper-internal sidecars (see src/meta.rs RESERVED_PREFIX)
+    if (p.second.rfind(".cte/", 0) == 0) continue;
+    auto it = tag_ids.find(p.first);
+    if (it == tag_ids.end()) {
+      wrp_cte::core::Tag tag(p.first);
+      it = tag_ids.emplace(p.first, tag.GetTagId()).first;
+    }
+    if (expired()) break;
+    auto task = client->AsyncGetBlobInfo(it->second, p.second,
+                                         route(it->second, p.second));
+    if (!wait(task) || task->GetReturnCode() != 0) continue;
+    uint64_t size = task->total_size_;
+    float score = task->score_;
+    if (size < min_size || size > max_size || score < min_score ||
+        score > max_score) {
+      continue;
+    }
+    out.push_back(BlobQueryRow{bytes_of(p.first), bytes_of(p.second), size,
+                               score});
+  }
+  return out;
+}
+
 }  // namespace cte_ffi
@@ -12,6 +12,8 @@ namespace cte_ffi {
 
 // CteTag wraps wrp_cte::core::Tag. Mutable inner allows cxx to pass
 // const CteTag& while Tag methods remain non-const.
+// Read-only after construction, so one may be used from several threads at
+// once (see `Tag` in src/lib.rs).
 struct CteTag {
   mutable wrp_cte::core::Tag inner;
 
@@ -19,28 +21,94 @@ struct CteTag {
   explicit CteTag(const wrp_cte::core::TagId &id) : inner(id) {}
 };
 
-// Forward-declared: defined by cxx-generated code (shared struct)
+// Forward-declared: defined by cxx-generated code (shared structs)
 struct CteTagId;
+struct BlobDescriptor;
+struct BlobQueryRow;
+struct BlobInfoRow;
+struct ListEntry;
+struct BlobBlockRow;
+struct TargetInfo;
+struct WorkerStats;
+struct HandshakeInfo;
 
 bool cte_init(rust::Str config_path);
+// 0 before Chimaera is initialized, 1 for a client only, 2 with an embedded
+// runtime.
+uint8_t chimaera_mode();
 
 std::unique_ptr<CteTag> tag_new(rust::Str tag_name);
+// `_bytes` variants take names that need not be UTF-8 (src/rawname.rs).
+std::unique_ptr<CteTag> tag_new_bytes(rust::Slice<const uint8_t> tag_name);
 std::unique_ptr<CteTag> tag_from_id(uint32_t major, uint32_t minor);
 
 void tag_put_blob(const CteTag &tag, rust::Str name, rust::Slice<const uint8_t> data,
                   uint64_t offset, float score);
+void tag_put_blob_bytes(const CteTag &tag, rust::Slice<const uint8_t> name,
+                        rust::Slice<const uint8_t> data, uint64_t offset, float score);
+// Put with the score chosen by the Rust placement policy hook.
+void tag_put_blob_placed(const CteTag &tag, rust::Str name, rust::Slice<const uint8_t> data,
+                         uint64_t offset);
 std::unique_ptr<std::vector<uint8_t>> tag_get_blob(const CteTag &tag, rust::Str name,
                                                     uint64_t size, uint64_t offset);
+std::unique_ptr<std::vector<uint8_t>> tag_get_blob_bytes(const CteTag &tag,
+                                                          rust::Slice<const uint8_t> name,
+                                                          uint64_t size, uint64_t offset);
+// Overwrite a small blob only if it holds exactly `expected`; whether it did.
+bool tag_swap_blob(const CteTag &tag, rust::Str name, rust::Slice<const uint8_t> expected,
+                   rust::Slice<const uint8_t> desired, float score);
 float tag_get_blob_score(const CteTag &tag, rust::Str name);
 uint64_t tag_get_blob_size(const CteTag &tag, rust::Str name);
+uint64_t tag_get_blob_size_bytes(const CteTag &tag, rust::Slice<const uint8_t> name);
 std::unique_ptr<std::vector<std::string>> tag_get_contained_blobs(const CteTag &tag);
+rust::Vec<ListEntry> tag_list_blobs(const CteTag &tag,
+                                    rust::Slice<const uint8_t> prefix,
+                                    rust::Slice<const uint8_t> delimiter);
 void tag_reorganize_blob(const CteTag &tag, rust::Str name, float score);
+bool tag_del_blob(const CteTag &tag, rust::Str name);
+bool tag_del_blob_bytes(const CteTag &tag, rust::Slice<const uint8_t> name);
 CteTagId tag_get_id(const CteTag &tag);
+uint32_t tag_blob_hash(const CteTag &tag, rust::Str name);
+uint64_t tag_get_size(const CteTag &tag);
+rust::Vec<uint64_t> tag_get_blob_sizes(const CteTag &tag,
+                                       rust::Slice<const rust::String> names);
+void tag_put_blobs(const CteTag &tag, rust::Slice<const rust::String> names,
+                   rust::Slice<const uint8_t> data,
+                   rust::Slice<const uint64_t> lens,
+                   rust::Slice<const float> scores);
+rust::Vec<uint8_t> tag_del_blobs(const CteTag &tag,
+                                 rust::Slice<const rust::String> names);
+rust::Vec<BlobInfoRow> tag_stat_blobs(
+    const CteTag &tag, rust::Slice<const rust::String> names,
+    rust::Slice<const rust::String> meta_names);
+// Where the blocks of a blob are stored, as far as the runtime reports it.
+rust::Vec<BlobBlockRow> tag_blob_blocks(const CteTag &tag, rust::Str name);
 
 bool client_register_target(rust::Str target_path, uint64_t size);
+bool client_register_ram_target(rust::Str name, uint64_t size);
+rust::Vec<rust::String> client_hostfile();
+uint32_t client_node_port();
+void client_register_remote_target(rust::Str name, uint32_t node_id,
+                                   uint64_t size);
+// False if the runtime was built without the object-store device.
+bool client_register_s3_target(rust::Str name, rust::Str endpoint,
+                               rust::Str region, rust::Str bucket,
+                               rust::Str prefix, rust::Str access_key_id,
+                               rust::Str secret_access_key,
+                               rust::Str session_token, uint64_t size);
 bool client_del_tag(rust::Str name);
+bool client_del_tag_bytes(rust::Slice<const uint8_t> name);
+uint32_t client_container_count();
+rust::Vec<TargetInfo> client_list_targets();
+rust::Vec<WorkerStats> client_worker_stats();
+HandshakeInfo runtime_handshake();
 std::unique_ptr<std::vector<std::string>> client_tag_query(rust::Str regex, uint32_t max_tags);
 std::unique_ptr<std::vector<std::string>> client_blob_query(rust::Str tag_re, rust::Str blob_re,
                                                              uint32_t max_results);
+// Blob query with per-blob size/score resolved and range-filtered in the shim.
+rust::Vec<BlobQueryRow> client_blob_query_stat(rust::Str tag_re, rust::Str blob_re,
+                                               uint32_t max_results, uint64_t min_size,
+                                               uint64_t max_size, float min_score,
+                                               float max_score);
 
 }  // namespace cte_ffi
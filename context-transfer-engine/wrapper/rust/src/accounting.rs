@@ -0,0 +1,271 @@
+//! Client labels and per-instance usage accounting.
+//!
+//! A process initialized with `ClientOptions::label` stamps the label on every
+//! change-log record it writes and keeps counters of the puts, gets and deletes it
+//! issues through the wrapper. The counters are published, at most once a second
+//! and on `Client::publish_usage`, to a record keyed by the process's instance id
+//! in the reserved `.cte/accounting` tag, where `Client::usage` on any client of
+//! the deployment can read them back. The runtime's own telemetry has no notion of
+//! clients, so this is how load on a shared deployment is attributed.
+//!
+//! Records of processes that have exited are left in place with their final
+//! counts; `updated_ms` tells them apart from live ones.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::meta::{read_str, read_u64, FieldReader, FieldWriter};
+use crate::oplog::writer_id;
+use crate::{ffi_guard, Client, CteError, RetryPolicy, Tag};
+
+const ACCOUNTING_TAG: &str = ".cte/accounting";
+const PUBLISH_INTERVAL_MS: u64 = 1000;
+
+const FIELD_LABEL: u8 = 1;
+const FIELD_WRITER: u8 = 2;
+const FIELD_PUTS: u8 = 3;
+const FIELD_BYTES_WRITTEN: u8 = 4;
+const FIELD_GETS: u8 = 5;
+const FIELD_BYTES_READ: u8 = 6;
+const FIELD_DELETES: u8 = 7;
+const FIELD_UPDATED: u8 = 8;
+
+/// Process-wide client settings, applied by `init_with_options`.
+#[derive(Debug, Clone, Default)]
+pub struct ClientOptions {
+    pub label: Option<String>,
+    /// Process-wide retry policy (see `retry`).
+    pub retry: Option<RetryPolicy>,
+    /// Longest tag or blob name accepted, in bytes (see `rawname`).
+    pub max_name_len: Option<usize>,
+}
+
+impl ClientOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Label identifying this application or user in change logs and usage
+    /// records, e.g. `"train-job-17"`.
+    pub fn label(mut self, label: &str) -> Self {
+        self.label = Some(label.to_string());
+        self
+    }
+
+    /// Retry transient failures under `policy`, as `set_retry_policy` does.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Refuse tag and blob names longer than `len` bytes.
+    pub fn max_name_len(mut self, len: usize) -> Self {
+        self.max_name_len = Some(len);
+        self
+    }
+}
+
+/// Published usage counters of one client instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientUsage {
+    pub label: String,
+    /// Instance id of the process (see `Change::writer`).
+    pub writer: u64,
+    pub puts: u64,
+    pub bytes_written: u64,
+    pub gets: u64,
+    pub bytes_read: u64,
+    pub deletes: u64,
+    /// When the record was last published, in milliseconds since the Unix epoch.
+    pub updated_ms: u64,
+}
+
+impl ClientUsage {
+    fn encode(&self) -> Vec<u8> {
+        let mut w = FieldWriter::default();
+        w.bytes(FIELD_LABEL, self.label.as_bytes());
+        w.u64(FIELD_WRITER, self.writer);
+        w.u64(FIELD_PUTS, self.puts);
+        w.u64(FIELD_BYTES_WRITTEN, self.bytes_written);
+        w.u64(FIELD_GETS, self.gets);
+        w.u64(FIELD_BYTES_READ, self.bytes_read);
+        w.u64(FIELD_DELETES, self.deletes);
+        w.u64(FIELD_UPDATED, self.updated_ms);
+        w.finish()
+    }
+
+    fn decode(buf: &[u8]) -> Result<Self, String> {
+        let mut u = ClientUsage {
+            label: String::new(),
+            writer: 0,
+            puts: 0,
+            bytes_written: 0,
+            gets: 0,
+            bytes_read: 0,
+            deletes: 0,
+            updated_ms: 0,
+        };
+        for (id, value) in FieldReader::new(buf)? {
+            match id {
+                FIELD_LABEL => u.label = read_str(value)?,
+                FIELD_WRITER => u.writer = read_u64(value)?,
+                FIELD_PUTS => u.puts = read_u64(value)?,
+                FIELD_BYTES_WRITTEN => u.bytes_written = read_u64(value)?,
+                FIELD_GETS => u.gets = read_u64(value)?,
+                FIELD_BYTES_READ => u.bytes_read = read_u64(value)?,
+                FIELD_DELETES => u.deletes = read_u64(value)?,
+                FIELD_UPDATED => u.updated_ms = read_u64(value)?,
+                _ => {}
+            }
+        }
+        Ok(u)
+    }
+}
+
+/// Operations counted towards a client's usage.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Op {
+    Put,
+    Get,
+    Delete,
+}
+
+static LABEL: RwLock<Option<Arc<str>>> = RwLock::new(None);
+/// Mirrors `LABEL.is_some()` so unlabelled clients skip accounting with one load.
+static LABELED: AtomicBool = AtomicBool::new(false);
+
+static PUTS: AtomicU64 = AtomicU64::new(0);
+static BYTES_WRITTEN: AtomicU64 = AtomicU64::new(0);
+static GETS: AtomicU64 = AtomicU64::new(0);
+static BYTES_READ: AtomicU64 = AtomicU64::new(0);
+static DELETES: AtomicU64 = AtomicU64::new(0);
+static LAST_PUBLISH_MS: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) fn set_label(label: Option<&str>) {
+    *LABEL.write().unwrap_or_else(|e| e.into_inner()) = label.map(Arc::from);
+    LABELED.store(label.is_some(), Ordering::Relaxed);
+}
+
+/// This process's client label, if one was configured.
+pub(crate) fn label() -> Option<Arc<str>> {
+    if !LABELED.load(Ordering::Relaxed) {
+        return None;
+    }
+    LABEL.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Count an operation moving `bytes`, publishing the counters if they are due.
+pub(crate) fn record(op: Op, bytes: u64) {
+    if !LABELED.load(Ordering::Relaxed) {
+        return;
+    }
+    match op {
+        Op::Put => {
+            PUTS.fetch_add(1, Ordering::Relaxed);
+            BYTES_WRITTEN.fetch_add(bytes, Ordering::Relaxed);
+        }
+        Op::Get => {
+            GETS.fetch_add(1, Ordering::Relaxed);
+            BYTES_READ.fetch_add(bytes, Ordering::Relaxed);
+        }
+        Op::Delete => {
+            DELETES.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    let now = now_ms();
+    let last = LAST_PUBLISH_MS.load(Ordering::Relaxed);
+    if now.saturating_sub(last) >= PUBLISH_INTERVAL_MS
+        && LAST_PUBLISH_MS
+            .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+    {
+        publish(now);
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+fn publish(now: u64) {
+    let Some(label) = label() else {
+        return;
+    };
+    let usage = ClientUsage {
+        label: label.to_string(),
+        writer: writer_id(),
+        puts: PUTS.load(Ordering::Relaxed),
+        bytes_written: BYTES_WRITTEN.load(Ordering::Relaxed),
+        gets: GETS.load(Ordering::Relaxed),
+        bytes_read: BYTES_READ.load(Ordering::Relaxed),
+        deletes: DELETES.load(Ordering::Relaxed),
+        updated_ms: now,
+    };
+    // A failed publish is made up for by the next one.
+    let Ok(tag) = Tag::try_new(ACCOUNTING_TAG) else {
+        return;
+    };
+    let name = format!("{:016x}", usage.writer);
+    let _ = ffi_guard::tag_put_blob(&tag.inner, &name, &usage.encode(), 0, 1.0);
+}
+
+impl Client {
+    /// Publish this process's usage counters now instead of waiting for the next
+    /// operation after the publish interval, e.g. just before exiting. Does
+    /// nothing without a client label.
+    pub fn publish_usage() {
+        LAST_PUBLISH_MS.store(now_ms(), Ordering::Relaxed);
+        publish(now_ms());
+    }
+
+    /// Usage records of every labelled client instance that has published one,
+    /// optionally only those with `label`.
+    pub fn usage(label: Option<&str>) -> Result<Vec<ClientUsage>, CteError> {
+        if !crate::meta::tag_exists(ACCOUNTING_TAG) {
+            return Ok(Vec::new());
+        }
+        let tag = Tag::new(ACCOUNTING_TAG);
+        let mut out = Vec::new();
+        for name in tag.raw_blob_names() {
+            let size = tag.get_blob_size(&name);
+            if size == 0 {
+                continue;
+            }
+            let buf = ffi_guard::tag_get_blob(&tag.inner, &name, size, 0)?;
+            let usage = ClientUsage::decode(buf.as_slice()).map_err(|reason| {
+                CteError::CorruptMetadata {
+                    blob: format!("{}/{}", ACCOUNTING_TAG, name),
+                    reason,
+                }
+            })?;
+            if label.is_none_or(|l| l == usage.label) {
+                out.push(usage);
+            }
+        }
+        out.sort_by(|a, b| (&a.label, a.writer).cmp(&(&b.label, b.writer)));
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage_roundtrip() {
+        let usage = ClientUsage {
+            label: "train-job-17".into(),
+            writer: 0xabcd,
+            puts: 3,
+            bytes_written: 4096,
+            gets: 5,
+            bytes_read: 1 << 20,
+            deletes: 1,
+            updated_ms: 1_700_000_000_000,
+        };
+        assert_eq!(ClientUsage::decode(&usage.encode()).unwrap(), usage);
+    }
+}
@@ -0,0 +1,325 @@
+//! Bringing existing files under CTE management without copying them.
+//!
+//! `Client::adopt_files` registers files as blobs by reference: each blob gets
+//! a one-byte placeholder in the runtime and a sidecar recording the file's
+//! path, size and modification time, and `Tag::get` and `Tag::stat_blob` serve
+//! it from the file. A site can adopt a scratch directory in one pass, then move
+//! blobs into the runtime's tiers as they are used with `Tag::materialize_blob`,
+//! or by writing them, which replaces the reference with the new data. Running
+//! `adopt_files` again over the same directory adopts only what is new or
+//! changed.
+//!
+//! The files have to stay where they are, readable by every client of the tag;
+//! a file that changes after adoption fails reads of its blob with `Io` until
+//! it is adopted again. The legacy size and get calls see the placeholder, not
+//! the file. Deleting an adopted blob leaves the file alone.
+
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::meta::{meta_lock, read_str, read_u64, FieldReader, FieldWriter};
+use crate::{Client, CteError, GetOptions, PutOptions, Tag};
+
+const FIELD_PATH: u8 = 1;
+const FIELD_SIZE: u8 = 2;
+const FIELD_MTIME: u8 = 3;
+
+/// What the runtime stores for an adopted blob.
+const PLACEHOLDER: &[u8] = &[0];
+
+/// The file an adopted blob's data is in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileRef {
+    /// Absolute path of the file.
+    pub path: PathBuf,
+    /// Size and modification time (milliseconds since the Unix epoch) of the
+    /// file when it was adopted.
+    pub size: u64,
+    pub mtime_ms: u64,
+}
+
+impl FileRef {
+    /// Reference `path` as it is now.
+    fn of(path: &Path) -> Result<Self, CteError> {
+        let path = fs::canonicalize(path)?;
+        let md = fs::metadata(&path)?;
+        if !md.is_file() {
+            return Err(CteError::InvalidArgument(format!(
+                "{} is not a regular file",
+                path.display()
+            )));
+        }
+        Ok(Self {
+            size: md.len(),
+            mtime_ms: mtime_ms(&md),
+            path,
+        })
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut w = FieldWriter::default();
+        w.bytes(FIELD_PATH, self.path.to_string_lossy().as_bytes());
+        w.u64(FIELD_SIZE, self.size);
+        w.u64(FIELD_MTIME, self.mtime_ms);
+        w.finish()
+    }
+
+    pub(crate) fn decode(buf: &[u8]) -> Result<Self, String> {
+        let mut file = Self {
+            path: PathBuf::new(),
+            size: 0,
+            mtime_ms: 0,
+        };
+        for (id, value) in FieldReader::new(buf)? {
+            match id {
+                FIELD_PATH => file.path = PathBuf::from(read_str(value)?),
+                FIELD_SIZE => file.size = read_u64(value)?,
+                FIELD_MTIME => file.mtime_ms = read_u64(value)?,
+                _ => {}
+            }
+        }
+        Ok(file)
+    }
+
+    /// `size` bytes of the file from `offset` (to the end if `None`), for blob
+    /// `name`; fails if the file changed since it was adopted.
+    pub(crate) fn read(
+        &self,
+        name: &str,
+        offset: u64,
+        size: Option<u64>,
+    ) -> Result<Vec<u8>, CteError> {
+        let mut file = fs::File::open(&self.path)?;
+        if !self.matches(&file.metadata()?) {
+            return Err(CteError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "{} changed since it was adopted as '{}'",
+                    self.path.display(),
+                    name
+                ),
+            )));
+        }
+        let start = offset.min(self.size);
+        let len = size.map_or(self.size - start, |s| s.min(self.size - start));
+        file.seek(SeekFrom::Start(start))?;
+        let mut data = Vec::with_capacity(len as usize);
+        file.take(len).read_to_end(&mut data)?;
+        Ok(data)
+    }
+}
+
+impl FileRef {
+    /// True if `md`, of the file, shows it unchanged since it was adopted.
+    pub(crate) fn matches(&self, md: &fs::Metadata) -> bool {
+        md.len() == self.size && mtime_ms(md) == self.mtime_ms
+    }
+}
+
+fn mtime_ms(md: &fs::Metadata) -> u64 {
+    md.modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Files for `Client::adopt_files`.
+#[derive(Debug, Clone)]
+pub enum AdoptSource {
+    /// Every regular file under a directory, named by its path relative to it
+    /// with `/` separators, as `Client::stage_in` names them.
+    Dir(PathBuf),
+    /// The given files, named by their file names.
+    Files(Vec<PathBuf>),
+}
+
+impl From<&Path> for AdoptSource {
+    fn from(dir: &Path) -> Self {
+        AdoptSource::Dir(dir.to_path_buf())
+    }
+}
+
+impl From<PathBuf> for AdoptSource {
+    fn from(dir: PathBuf) -> Self {
+        AdoptSource::Dir(dir)
+    }
+}
+
+impl From<Vec<PathBuf>> for AdoptSource {
+    fn from(files: Vec<PathBuf>) -> Self {
+        AdoptSource::Files(files)
+    }
+}
+
+/// What `Client::adopt_files` did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AdoptReport {
+    /// Blobs adopted, or re-adopted because their file changed.
+    pub adopted: usize,
+    /// Size of the files adopted.
+    pub bytes: u64,
+    /// Blobs already adopted from the same, unchanged file.
+    pub unchanged: usize,
+    /// Names that already held data of their own, left alone.
+    pub skipped: Vec<String>,
+}
+
+enum Outcome {
+    Adopted,
+    Unchanged,
+    Skipped,
+}
+
+impl AdoptSource {
+    /// The files to adopt as `(blob name, path)`, sorted by name.
+    fn entries(&self) -> Result<Vec<(String, PathBuf)>, CteError> {
+        let mut entries = match self {
+            AdoptSource::Dir(dir) => crate::stage::walk_files(dir)?,
+            AdoptSource::Files(files) => files
+                .iter()
+                .map(|path| {
+                    let name = path.file_name().and_then(|n| n.to_str()).ok_or_else(|| {
+                        CteError::InvalidArgument(format!(
+                            "path {} has no UTF-8 file name",
+                            path.display()
+                        ))
+                    })?;
+                    Ok((name.to_string(), path.clone()))
+                })
+                .collect::<Result<_, CteError>>()?,
+        };
+        entries.sort();
+        if let Some(w) = entries.windows(2).find(|w| w[0].0 == w[1].0) {
+            return Err(CteError::InvalidArgument(format!(
+                "{} and {} would both be blob '{}'",
+                w[0].1.display(),
+                w[1].1.display(),
+                w[0].0
+            )));
+        }
+        Ok(entries)
+    }
+}
+
+impl Client {
+    /// Register the files of `source` as blobs of `tag_name` by reference,
+    /// without copying their data (see `adopt`). Blobs that already exist keep
+    /// their data unless they were adopted from a file that has changed since.
+    pub fn adopt_files(
+        source: impl Into<AdoptSource>,
+        tag_name: &str,
+    ) -> Result<AdoptReport, CteError> {
+        let entries = source.into().entries()?;
+        let tag = Tag::new(tag_name);
+        let mut report = AdoptReport::default();
+        for (name, path) in entries {
+            let file = FileRef::of(&path)?;
+            let size = file.size;
+            match tag.adopt(&name, file)? {
+                Outcome::Adopted => {
+                    report.adopted += 1;
+                    report.bytes += size;
+                }
+                Outcome::Unchanged => report.unchanged += 1,
+                Outcome::Skipped => report.skipped.push(name),
+            }
+        }
+        Ok(report)
+    }
+}
+
+impl Tag {
+    fn adopt(&self, name: &str, file: FileRef) -> Result<Outcome, CteError> {
+        if crate::meta::is_reserved(name) {
+            return Err(CteError::InvalidArgument(format!(
+                "blob name '{}' is reserved",
+                name
+            )));
+        }
+        let _guard = meta_lock();
+        let meta = self.load_meta(name)?;
+        let mut meta = match meta {
+            Some(m) if m.reference.as_ref() == Some(&file) => return Ok(Outcome::Unchanged),
+            Some(m) if m.reference.is_some() => m,
+            None if self.get_blob_size(name) == 0 => Default::default(),
+            _ => return Ok(Outcome::Skipped),
+        };
+        meta.reference = Some(file);
+        meta.compressed = None;
+        meta.encrypted = None;
+        meta.summary = None;
+        self.write_blob_locked(name, PLACEHOLDER, 0, None, None, &mut meta)?;
+        Ok(Outcome::Adopted)
+    }
+
+    /// The file `name` was adopted from, if it is an adopted blob.
+    pub fn adopted_file(&self, name: &str) -> Result<Option<FileRef>, CteError> {
+        Ok(self.load_meta(name)?.and_then(|m| m.reference))
+    }
+
+    /// Copy the data of adopted blob `name` from its file into the runtime,
+    /// where it is placed and tiered like any other blob; the file is no longer
+    /// needed afterwards. Returns the blob's new generation, or `None` if it
+    /// wasn't adopted.
+    pub fn materialize_blob(&self, name: &str) -> Result<Option<u64>, CteError> {
+        let Some(file) = self.adopted_file(name)? else {
+            return Ok(None);
+        };
+        let generation = self.load_meta(name)?.map_or(0, |m| m.generation);
+        let data = file.read(name, 0, None)?;
+        let options = PutOptions {
+            if_generation_match: Some(generation),
+            ..Default::default()
+        };
+        self.put(name, &data, &options).map(Some)
+    }
+
+    /// `GetOptions` reads of an adopted blob, served from its file.
+    pub(crate) fn read_adopted(
+        &self,
+        name: &str,
+        file: &FileRef,
+        options: &GetOptions,
+    ) -> Result<Vec<u8>, CteError> {
+        let data = file.read(name, options.offset, options.size)?;
+        self.record_access(
+            name,
+            crate::AccessKind::Read,
+            options.offset,
+            data.len() as u64,
+        );
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_ref() {
+        let dir = std::env::temp_dir().join(format!("cte_adopt_{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("a.dat"), b"0123456789").unwrap();
+        fs::write(dir.join("sub/b.dat"), b"xy").unwrap();
+
+        let entries = AdoptSource::from(dir.as_path()).entries().unwrap();
+        let names: Vec<&str> = entries.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, ["a.dat", "sub/b.dat"]);
+        let clash = AdoptSource::Files(vec![dir.join("a.dat"), dir.join("sub/../a.dat")]);
+        assert!(matches!(clash.entries(), Err(CteError::InvalidArgument(_))));
+
+        let file = FileRef::of(&dir.join("a.dat")).unwrap();
+        assert_eq!(file.size, 10);
+        assert_eq!(FileRef::decode(&file.encode()).unwrap(), file);
+        assert_eq!(file.read("a", 3, Some(4)).unwrap(), b"3456");
+        assert_eq!(file.read("a", 8, None).unwrap(), b"89");
+        assert_eq!(file.read("a", 20, None).unwrap(), b"");
+        fs::write(dir.join("a.dat"), b"changed").unwrap();
+        assert!(matches!(file.read("a", 0, None), Err(CteError::Io(_))));
+        assert!(FileRef::of(&dir.join("sub")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
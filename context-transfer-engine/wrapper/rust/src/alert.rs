@@ -0,0 +1,374 @@
+//! Operational alerts posted to webhooks.
+//!
+//! `Client::start_webhook` starts a sink that POSTs each alert it selects as a
+//! JSON object to a URL, for teams that want to be told when something needs
+//! attention without running a metrics or messaging stack. The alerts are:
+//!
+//! - `CapacityLow`: a target has less space left than
+//!   `WebhookOptions::capacity_low`, checked every `poll_interval`. Raised
+//!   once, and again only after the target has recovered.
+//! - `ChecksumMismatch`: a read, verified put or `Tag::verify_blob` found data
+//!   that no longer matches the checksum recorded when it was written.
+//! - `Expired`: a blob's TTL ran out and it was deleted (see `ttl`).
+//! - `Purged`: the trash dropped a deleted blob at the end of its retention.
+//! - `BlobExpiring`: the advance warning `set_expiry_warning` turns on.
+//!
+//! Alerts are raised by this process's wrapper, so each client reports what
+//! it sees. The runtime doesn't replicate blobs, so there is no replication
+//! lag to report.
+//!
+//! A sink delivers in order, on a thread of its own, retrying a failed POST up
+//! to `max_attempts` times with a doubling wait. A 4xx answer other than 408
+//! or 429 isn't retried. Alerts that can't be delivered are dropped and
+//! counted. With `secret` set, each request carries `X-CTE-Signature:
+//! sha256=<hex HMAC-SHA256 of the body>`; the body's `time_ms` lets the
+//! receiver refuse replays.
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, RwLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::checksum::hmac_sha256;
+use crate::ffi_c::json_string;
+use crate::ttl::now_ms;
+use crate::webhook::Endpoint;
+use crate::{Client, CteError};
+
+/// Something an operator may want to hear about (see `alert`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Alert {
+    CapacityLow {
+        target: String,
+        remaining_space: u64,
+        threshold: u64,
+    },
+    ChecksumMismatch {
+        tag: String,
+        blob: String,
+    },
+    Expired {
+        tag: String,
+        blob: String,
+    },
+    Purged {
+        tag: String,
+        blob: String,
+    },
+    BlobExpiring {
+        tag: String,
+        blob: String,
+        expires_at: SystemTime,
+    },
+}
+
+/// Discriminant of an `Alert`, for selecting them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlertKind {
+    CapacityLow,
+    ChecksumMismatch,
+    Expired,
+    Purged,
+    BlobExpiring,
+}
+
+impl AlertKind {
+    /// The name sent as the body's `alert` field.
+    pub fn name(self) -> &'static str {
+        match self {
+            AlertKind::CapacityLow => "capacity_low",
+            AlertKind::ChecksumMismatch => "checksum_mismatch",
+            AlertKind::Expired => "expired",
+            AlertKind::Purged => "purged",
+            AlertKind::BlobExpiring => "blob_expiring",
+        }
+    }
+}
+
+impl Alert {
+    pub fn kind(&self) -> AlertKind {
+        match self {
+            Alert::CapacityLow { .. } => AlertKind::CapacityLow,
+            Alert::ChecksumMismatch { .. } => AlertKind::ChecksumMismatch,
+            Alert::Expired { .. } => AlertKind::Expired,
+            Alert::Purged { .. } => AlertKind::Purged,
+            Alert::BlobExpiring { .. } => AlertKind::BlobExpiring,
+        }
+    }
+
+    /// The request body, stamped with `time_ms`.
+    fn json(&self, time_ms: u64) -> String {
+        let fields = match self {
+            Alert::CapacityLow {
+                target,
+                remaining_space,
+                threshold,
+            } => format!(
+                "\"target\":{},\"remaining_space\":{},\"threshold\":{}",
+                json_string(target),
+                remaining_space,
+                threshold
+            ),
+            Alert::ChecksumMismatch { tag, blob }
+            | Alert::Expired { tag, blob }
+            | Alert::Purged { tag, blob } => {
+                format!(
+                    "\"tag\":{},\"blob\":{}",
+                    json_string(tag),
+                    json_string(blob)
+                )
+            }
+            Alert::BlobExpiring {
+                tag,
+                blob,
+                expires_at,
+            } => format!(
+                "\"tag\":{},\"blob\":{},\"expires_ms\":{}",
+                json_string(tag),
+                json_string(blob),
+                expires_at
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_millis() as u64)
+            ),
+        };
+        format!(
+            "{{\"alert\":\"{}\",\"time_ms\":{},{}}}",
+            self.kind().name(),
+            time_ms,
+            fields
+        )
+    }
+}
+
+/// Settings for `Client::start_webhook`.
+#[derive(Debug, Clone)]
+pub struct WebhookOptions {
+    /// Alerts to send; all of them if empty.
+    pub kinds: Vec<AlertKind>,
+    /// Key signing each request (see `alert`); unsigned if `None`.
+    pub secret: Option<Vec<u8>>,
+    /// POSTs per alert, the first included.
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled before each one after.
+    pub initial_backoff: Duration,
+    /// Raise `CapacityLow` for a target with fewer bytes left than this.
+    pub capacity_low: Option<u64>,
+    /// How often targets are checked against `capacity_low`.
+    pub poll_interval: Duration,
+}
+
+impl Default for WebhookOptions {
+    fn default() -> Self {
+        Self {
+            kinds: Vec::new(),
+            secret: None,
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            capacity_low: None,
+            poll_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+enum Msg {
+    Alert(Alert),
+    Stop,
+}
+
+struct Sink {
+    id: u64,
+    kinds: Vec<AlertKind>,
+    queue: Sender<Msg>,
+}
+
+static SINKS: RwLock<Vec<Sink>> = RwLock::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Queue `alert` for every sink that selects it.
+pub(crate) fn raise(alert: Alert) {
+    let sinks = SINKS.read().unwrap_or_else(|e| e.into_inner());
+    for sink in sinks.iter() {
+        if sink.kinds.is_empty() || sink.kinds.contains(&alert.kind()) {
+            let _ = sink.queue.send(Msg::Alert(alert.clone()));
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counts {
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+}
+
+/// A running webhook sink from `Client::start_webhook`; stops when dropped.
+pub struct WebhookSink {
+    id: u64,
+    queue: Sender<Msg>,
+    counts: Arc<Counts>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl WebhookSink {
+    /// Alerts delivered so far.
+    pub fn delivered(&self) -> u64 {
+        self.counts.delivered.load(Ordering::Relaxed)
+    }
+
+    /// Alerts given up on so far.
+    pub fn dropped(&self) -> u64 {
+        self.counts.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for WebhookSink {
+    fn drop(&mut self) {
+        SINKS
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|s| s.id != self.id);
+        let _ = self.queue.send(Msg::Stop);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Delivers one sink's alerts.
+struct Worker {
+    endpoint: Endpoint,
+    options: WebhookOptions,
+    counts: Arc<Counts>,
+    /// Targets under `capacity_low` at the last check.
+    low: HashSet<String>,
+}
+
+impl Worker {
+    fn run(mut self, queue: Receiver<Msg>) {
+        let poll = self
+            .options
+            .capacity_low
+            .map(|_| self.options.poll_interval);
+        loop {
+            let msg = match poll {
+                Some(interval) => queue.recv_timeout(interval),
+                None => queue.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            match msg {
+                Ok(Msg::Alert(alert)) => self.deliver(&alert),
+                Err(RecvTimeoutError::Timeout) => self.check_capacity(),
+                Ok(Msg::Stop) | Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    }
+
+    fn check_capacity(&mut self) {
+        let Some(threshold) = self.options.capacity_low else {
+            return;
+        };
+        for target in Client::list_targets() {
+            if target.remaining_space >= threshold {
+                self.low.remove(&target.name);
+            } else if self.low.insert(target.name.clone()) {
+                self.deliver(&Alert::CapacityLow {
+                    target: target.name,
+                    remaining_space: target.remaining_space,
+                    threshold,
+                });
+            }
+        }
+    }
+
+    fn deliver(&self, alert: &Alert) {
+        let body = alert.json(now_ms());
+        let mut headers = vec![("X-CTE-Alert", alert.kind().name().to_string())];
+        if let Some(secret) = &self.options.secret {
+            headers.push(("X-CTE-Signature", signature(secret, &body)));
+        }
+        let attempts = self.options.max_attempts.max(1);
+        let mut wait = self.options.initial_backoff;
+        for attempt in 1..=attempts {
+            match self.endpoint.post(&headers, &body) {
+                Ok(200..=299) => {
+                    self.counts.delivered.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                Ok(code) if (400..500).contains(&code) && code != 408 && code != 429 => break,
+                _ if attempt == attempts => break,
+                _ => {
+                    std::thread::sleep(wait);
+                    wait = wait.saturating_mul(2);
+                }
+            }
+        }
+        self.counts.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// The `X-CTE-Signature` header value for `body`.
+fn signature(secret: &[u8], body: &str) -> String {
+    let mac = hmac_sha256(secret, body.as_bytes());
+    let hex: String = mac.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", hex)
+}
+
+impl Client {
+    /// POST the alerts `options` selects to `url`, an `http://` URL, until the
+    /// returned sink is dropped (see `alert`).
+    pub fn start_webhook(url: &str, options: WebhookOptions) -> Result<WebhookSink, CteError> {
+        let endpoint = Endpoint::parse(url)?;
+        let (queue, alerts) = mpsc::channel();
+        let counts = Arc::new(Counts::default());
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        SINKS.write().unwrap_or_else(|e| e.into_inner()).push(Sink {
+            id,
+            kinds: options.kinds.clone(),
+            queue: queue.clone(),
+        });
+        let worker = Worker {
+            endpoint,
+            options,
+            counts: counts.clone(),
+            low: HashSet::new(),
+        };
+        let worker = std::thread::spawn(move || worker.run(alerts));
+        Ok(WebhookSink {
+            id,
+            queue,
+            counts,
+            worker: Some(worker),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alert_json() {
+        let alert = Alert::CapacityLow {
+            target: "/mnt/nvme".into(),
+            remaining_space: 10,
+            threshold: 100,
+        };
+        assert_eq!(
+            alert.json(5),
+            r#"{"alert":"capacity_low","time_ms":5,"target":"/mnt/nvme","remaining_space":10,"threshold":100}"#
+        );
+        let alert = Alert::Purged {
+            tag: "t".into(),
+            blob: "b\"".into(),
+        };
+        assert_eq!(
+            alert.json(5),
+            r#"{"alert":"purged","time_ms":5,"tag":"t","blob":"b\""}"#
+        );
+        // RFC 4231 test case 2.
+        assert_eq!(
+            signature(b"Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
@@ -0,0 +1,69 @@
+//! Fenced appends for exactly-once producers.
+
+use crate::meta::{decode_meta, meta_lock};
+use crate::{Capability, CteError, Tag};
+
+impl Tag {
+    /// Append `data` to the end of a blob on behalf of the producer at `producer_epoch`.
+    ///
+    /// Each blob remembers the highest epoch that has appended to it. An append from
+    /// an older epoch fails with `CteError::Fenced`, so a producer that has been
+    /// replaced by a restarted instance (with a higher epoch) can't interleave
+    /// duplicate records into the log its successor is writing.
+    ///
+    /// Returns the offset the data was written at. A producer takes over a blob
+    /// by swapping its epoch into the sidecar with a runtime-side
+    /// compare-and-swap, so of two producers racing from the same state only one
+    /// wins, and no write ever lowers the stored epoch. An append that had
+    /// already passed the check when its successor took over can still land;
+    /// every later one is fenced. Needs `Capability::CompareAndSwap`.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(tag = %self.name, blob = name, size = data.len(), epoch = producer_epoch),
+            ret,
+            err
+        )
+    )]
+    pub fn append_blob_fenced(
+        &self,
+        name: &str,
+        data: &[u8],
+        producer_epoch: u64,
+    ) -> Result<u64, CteError> {
+        crate::handshake::require(Capability::CompareAndSwap)?;
+        let _guard = meta_lock();
+        let mut meta = loop {
+            let raw = self.load_meta_raw(name)?;
+            let mut meta = decode_meta(name, &raw)?.unwrap_or_default();
+            if producer_epoch < meta.epoch {
+                return Err(CteError::Fenced {
+                    blob: name.to_string(),
+                    producer_epoch,
+                    current_epoch: meta.epoch,
+                });
+            }
+            if meta.is_transformed() || self.is_encrypted()? {
+                return Err(CteError::InvalidArgument(format!(
+                    "cannot append to compressed, encrypted or adopted blob '{}'",
+                    name
+                )));
+            }
+            if producer_epoch == meta.epoch {
+                break meta;
+            }
+            // Take the blob over; if another writer changed the sidecar since
+            // it was read, look again.
+            meta.epoch = producer_epoch;
+            if self.swap_meta(name, &raw, &meta.encode())? {
+                break meta;
+            }
+        };
+        meta.summary = None;
+        let offset = self.get_blob_size(name);
+        self.write_blob_locked(name, data, offset, None, None, &mut meta)?;
+        Ok(offset)
+    }
+}
@@ -0,0 +1,869 @@
+//! Export of a tag to a tar or zip archive, and import back.
+//!
+//! An archive starts with a manifest, `cte-manifest.tsv`, followed by one entry
+//! per blob under `blobs/`, so collaborators who don't run IOWarp can unpack it
+//! with standard tools. Blob data is exported as written: decompressed and
+//! decrypted. A blob whose name isn't a safe relative path (or is too long for a
+//! tar header) is stored as `other/<n>` instead; the manifest maps every entry
+//! back to its blob, with the blob's score and attributes and the tag's
+//! attributes. Its lines are tab-separated, percent-escaped fields:
+//! a `cte-archive 1` header, then `tag <name>`, `tag-attr <key> <value>`,
+//! `blob <path> <name> <size> <score>` and `blob-attr <name> <key> <value>`.
+//!
+//! Tar archives are ustar, with base-256 sizes for blobs over 8 GiB. Zip
+//! archives are stored uncompressed with data descriptors, and are limited to
+//! 4 GiB and 65535 entries. Import reads archives written by export (it relies
+//! on the manifest coming first) and holds one blob in memory at a time. An
+//! entry is only buffered once its size matches the manifest, and within
+//! `RequestLimits` (or `CteError::TooLarge`); entries the manifest doesn't
+//! list are skipped unread. A manifest naming a blob reserved for the wrapper
+//! (under `.cte/`) is refused before anything is imported.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+use crate::audit::escape;
+use crate::checksum::crc32;
+use crate::io::COPY_CHUNK;
+use crate::ttl::now_ms;
+use crate::{limits, meta, Attrs, Client, CteError, GetOptions, PutOptions, Tag};
+
+const MANIFEST_PATH: &str = "cte-manifest.tsv";
+const MANIFEST_HEADER: &str = "cte-archive\t1";
+
+const TAR_BLOCK: usize = 512;
+const TAR_NAME_MAX: usize = 100;
+
+const ZIP_LOCAL: u32 = 0x0403_4b50;
+const ZIP_DESCRIPTOR: u32 = 0x0807_4b50;
+const ZIP_CENTRAL: u32 = 0x0201_4b50;
+const ZIP_END: u32 = 0x0605_4b50;
+/// Flag bits: sizes and CRC follow the data; names are UTF-8.
+const ZIP_FLAG_DESCRIPTOR: u16 = 0x0008;
+const ZIP_FLAG_UTF8: u16 = 0x0800;
+
+/// Archive container written by `Tag::export_archive`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    Tar,
+    Zip,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ManifestBlob {
+    path: String,
+    name: String,
+    size: u64,
+    score: f32,
+    attrs: Attrs,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Manifest {
+    tag: String,
+    tag_attrs: Attrs,
+    blobs: Vec<ManifestBlob>,
+}
+
+impl Manifest {
+    fn encode(&self) -> String {
+        let mut lines = vec![
+            MANIFEST_HEADER.to_string(),
+            format!("tag\t{}", escape(&self.tag)),
+        ];
+        for (k, v) in &self.tag_attrs {
+            lines.push(format!("tag-attr\t{}\t{}", escape(k), escape(v)));
+        }
+        for b in &self.blobs {
+            lines.push(format!(
+                "blob\t{}\t{}\t{}\t{}",
+                escape(&b.path),
+                escape(&b.name),
+                b.size,
+                b.score
+            ));
+            for (k, v) in &b.attrs {
+                lines.push(format!(
+                    "blob-attr\t{}\t{}\t{}",
+                    escape(&b.name),
+                    escape(k),
+                    escape(v)
+                ));
+            }
+        }
+        let mut out = lines.join("\n");
+        out.push('\n');
+        out
+    }
+
+    fn decode(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines();
+        if lines.next() != Some(MANIFEST_HEADER) {
+            return Err("not a CTE archive manifest".into());
+        }
+        let mut manifest = Manifest::default();
+        for line in lines {
+            let fields = line
+                .split('\t')
+                .map(unescape)
+                .collect::<Result<Vec<_>, _>>()?;
+            match fields.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+                ["tag", name] => manifest.tag = name.to_string(),
+                ["tag-attr", k, v] => manifest.tag_attrs.push((k.to_string(), v.to_string())),
+                ["blob", path, name, size, score] => manifest.blobs.push(ManifestBlob {
+                    path: path.to_string(),
+                    name: name.to_string(),
+                    size: size
+                        .parse()
+                        .map_err(|_| format!("bad size for '{}'", name))?,
+                    score: score
+                        .parse()
+                        .map_err(|_| format!("bad score for '{}'", name))?,
+                    attrs: Vec::new(),
+                }),
+                ["blob-attr", name, k, v] => {
+                    let blob = manifest
+                        .blobs
+                        .iter_mut()
+                        .find(|b| b.name == name)
+                        .ok_or_else(|| format!("attribute for unlisted blob '{}'", name))?;
+                    blob.attrs.push((k.to_string(), v.to_string()));
+                }
+                // Lines added by newer wrappers.
+                _ => {}
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+/// Reverse `audit::escape`.
+pub(crate) fn unescape(s: &str) -> Result<String, String> {
+    let mut out = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'%' {
+            out.push(b);
+            continue;
+        }
+        let hex: Vec<u8> = bytes.by_ref().take(2).collect();
+        let byte = std::str::from_utf8(&hex)
+            .ok()
+            .and_then(|h| u8::from_str_radix(h, 16).ok())
+            .filter(|_| hex.len() == 2)
+            .ok_or_else(|| format!("bad escape in '{}'", s))?;
+        out.push(byte);
+    }
+    String::from_utf8(out).map_err(|_| format!("bad UTF-8 in '{}'", s))
+}
+
+/// Path of blob number `index` inside the archive.
+fn archive_path(name: &str, index: usize) -> String {
+    let path = format!("blobs/{}", name);
+    let safe = !name.contains(['\\', '\0'])
+        && name
+            .split('/')
+            .all(|c| !c.is_empty() && c != "." && c != "..");
+    if safe && path.len() <= TAR_NAME_MAX {
+        path
+    } else {
+        format!("other/{}", index)
+    }
+}
+
+/// Write `value` as zero-padded octal filling `field` but its last byte (NUL).
+fn octal(field: &mut [u8], value: u64) {
+    let width = field.len() - 1;
+    let digits = format!("{:0width$o}", value, width = width);
+    field[..width].copy_from_slice(digits.as_bytes());
+    field[width] = 0;
+}
+
+fn tar_header(path: &str, size: u64, mtime: u64) -> [u8; TAR_BLOCK] {
+    let mut h = [0u8; TAR_BLOCK];
+    h[..path.len()].copy_from_slice(path.as_bytes());
+    octal(&mut h[100..108], 0o644);
+    octal(&mut h[108..116], 0);
+    octal(&mut h[116..124], 0);
+    if size < 1 << 33 {
+        octal(&mut h[124..136], size);
+    } else {
+        // GNU base-256: high bit set, big-endian value.
+        h[124] = 0x80;
+        h[128..136].copy_from_slice(&size.to_be_bytes());
+    }
+    octal(&mut h[136..148], mtime);
+    h[156] = b'0';
+    h[257..263].copy_from_slice(b"ustar\0");
+    h[263..265].copy_from_slice(b"00");
+    // The checksum is computed with its own field read as spaces.
+    h[148..156].fill(b' ');
+    let sum: u64 = h.iter().map(|&b| b as u64).sum();
+    octal(&mut h[148..155], sum);
+    h
+}
+
+fn tar_size(field: &[u8]) -> Result<u64, String> {
+    if field[0] & 0x80 != 0 {
+        return Ok(field[1..]
+            .iter()
+            .fold((field[0] & 0x7f) as u64, |n, &b| (n << 8) | b as u64));
+    }
+    let digits = std::str::from_utf8(field)
+        .map_err(|_| "bad tar size".to_string())?
+        .trim_matches(|c| c == '\0' || c == ' ');
+    u64::from_str_radix(digits, 8).map_err(|_| "bad tar size".to_string())
+}
+
+fn tar_padding(size: u64) -> usize {
+    (TAR_BLOCK - (size % TAR_BLOCK as u64) as usize) % TAR_BLOCK
+}
+
+/// MS-DOS `(time, date)` of a Unix time, as zip headers store it.
+fn dos_datetime(secs: u64) -> (u16, u16) {
+    // Days to civil date (Howard Hinnant's algorithm).
+    let z = (secs / 86_400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+    let rem = secs % 86_400;
+    let time = (rem / 3600) << 11 | (rem % 3600 / 60) << 5 | (rem % 60 / 2);
+    let date = ((year - 1980).clamp(0, 127) << 9) | (month << 5) | day;
+    (time as u16, date as u16)
+}
+
+struct OpenEntry {
+    path: String,
+    size: u64,
+    offset: u64,
+    crc: u32,
+    descriptor: bool,
+}
+
+/// Streams entries into a tar or zip archive.
+pub(crate) struct ArchiveWriter<W: Write> {
+    out: W,
+    format: ArchiveFormat,
+    offset: u64,
+    mtime: u64,
+    entry: Option<OpenEntry>,
+    central: Vec<u8>,
+    entries: u16,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    pub(crate) fn new(out: W, format: ArchiveFormat) -> Self {
+        Self {
+            out,
+            format,
+            offset: 0,
+            mtime: now_ms() / 1000,
+            entry: None,
+            central: Vec::new(),
+            entries: 0,
+        }
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.out.write_all(buf)?;
+        self.offset += buf.len() as u64;
+        Ok(())
+    }
+
+    /// Start an entry of `size` bytes. `crc` is the data's CRC-32 if already known.
+    pub(crate) fn begin(&mut self, path: &str, size: u64, crc: Option<u32>) -> io::Result<()> {
+        let entry = OpenEntry {
+            path: path.to_string(),
+            size,
+            offset: self.offset,
+            // Accumulated as data arrives if not given.
+            crc: crc.unwrap_or(0),
+            descriptor: crc.is_none(),
+        };
+        match self.format {
+            ArchiveFormat::Tar => self.write(&tar_header(path, size, self.mtime))?,
+            ArchiveFormat::Zip => {
+                let header = self.zip_header(ZIP_LOCAL, &entry, crc.is_some());
+                self.write(&header)?;
+            }
+        }
+        self.entry = Some(entry);
+        Ok(())
+    }
+
+    pub(crate) fn data(&mut self, buf: &[u8]) -> io::Result<()> {
+        if let Some(entry) = self.entry.as_mut().filter(|e| e.descriptor) {
+            entry.crc = crc32(entry.crc, buf);
+        }
+        self.write(buf)
+    }
+
+    pub(crate) fn end(&mut self) -> io::Result<()> {
+        let entry = self.entry.take().expect("no archive entry open");
+        match self.format {
+            ArchiveFormat::Tar => self.write(&[0u8; TAR_BLOCK][..tar_padding(entry.size)]),
+            ArchiveFormat::Zip => {
+                if entry.descriptor {
+                    let mut d = Vec::with_capacity(16);
+                    d.extend_from_slice(&ZIP_DESCRIPTOR.to_le_bytes());
+                    d.extend_from_slice(&entry.crc.to_le_bytes());
+                    d.extend_from_slice(&(entry.size as u32).to_le_bytes());
+                    d.extend_from_slice(&(entry.size as u32).to_le_bytes());
+                    self.write(&d)?;
+                }
+                let header = self.zip_header(ZIP_CENTRAL, &entry, true);
+                self.central.extend_from_slice(&header);
+                self.entries += 1;
+                Ok(())
+            }
+        }
+    }
+
+    /// Local (`ZIP_LOCAL`) or central directory (`ZIP_CENTRAL`) header for
+    /// `entry`, with its CRC and sizes if `known`.
+    fn zip_header(&self, signature: u32, entry: &OpenEntry, known: bool) -> Vec<u8> {
+        let (time, date) = dos_datetime(self.mtime);
+        let flags = ZIP_FLAG_UTF8
+            | if entry.descriptor {
+                ZIP_FLAG_DESCRIPTOR
+            } else {
+                0
+            };
+        let (crc, size) = if known {
+            (entry.crc, entry.size as u32)
+        } else {
+            (0, 0)
+        };
+        let mut h = Vec::with_capacity(46 + entry.path.len());
+        h.extend_from_slice(&signature.to_le_bytes());
+        if signature == ZIP_CENTRAL {
+            // Made by: Unix, zip 2.0.
+            h.extend_from_slice(&(3u16 << 8 | 20).to_le_bytes());
+        }
+        for field in [20, flags, 0, time, date] {
+            h.extend_from_slice(&field.to_le_bytes());
+        }
+        for field in [crc, size, size] {
+            h.extend_from_slice(&field.to_le_bytes());
+        }
+        h.extend_from_slice(&(entry.path.len() as u16).to_le_bytes());
+        h.extend_from_slice(&0u16.to_le_bytes());
+        if signature == ZIP_CENTRAL {
+            // Comment length, disk, internal attributes; then mode 0644 and offset.
+            for field in [0u16, 0, 0] {
+                h.extend_from_slice(&field.to_le_bytes());
+            }
+            h.extend_from_slice(&(0o100644u32 << 16).to_le_bytes());
+            h.extend_from_slice(&(entry.offset as u32).to_le_bytes());
+        }
+        h.extend_from_slice(entry.path.as_bytes());
+        h
+    }
+
+    pub(crate) fn finish(mut self) -> io::Result<()> {
+        match self.format {
+            ArchiveFormat::Tar => self.write(&[0u8; 2 * TAR_BLOCK])?,
+            ArchiveFormat::Zip => {
+                let central = std::mem::take(&mut self.central);
+                let start = self.offset;
+                self.write(&central)?;
+                let mut end = Vec::with_capacity(22);
+                end.extend_from_slice(&ZIP_END.to_le_bytes());
+                for field in [0, 0, self.entries, self.entries] {
+                    end.extend_from_slice(&field.to_le_bytes());
+                }
+                end.extend_from_slice(&(central.len() as u32).to_le_bytes());
+                end.extend_from_slice(&(start as u32).to_le_bytes());
+                end.extend_from_slice(&0u16.to_le_bytes());
+                self.write(&end)?;
+            }
+        }
+        self.out.flush()
+    }
+}
+
+/// Zip archive size for `manifest`, to check against the format's limits.
+fn zip_size(manifest: &Manifest, manifest_len: usize) -> u64 {
+    let entry = |path: &str, size: u64| 30 + 46 + 2 * path.len() as u64 + size;
+    let blobs: u64 = manifest
+        .blobs
+        .iter()
+        .map(|b| entry(&b.path, b.size) + 16)
+        .sum();
+    entry(MANIFEST_PATH, manifest_len as u64) + blobs + 22
+}
+
+/// Reads back the entries of an archive written by `ArchiveWriter`.
+struct ArchiveReader<R: Read> {
+    input: R,
+    format: ArchiveFormat,
+    /// Bytes already read while detecting the format.
+    peeked: Vec<u8>,
+}
+
+fn read_u16(b: &[u8]) -> u16 {
+    u16::from_le_bytes(b[..2].try_into().unwrap())
+}
+
+fn read_u32(b: &[u8]) -> u32 {
+    u32::from_le_bytes(b[..4].try_into().unwrap())
+}
+
+fn corrupt(path: &str, reason: impl Into<String>) -> CteError {
+    CteError::CorruptMetadata {
+        blob: path.to_string(),
+        reason: reason.into(),
+    }
+}
+
+/// An entry's header: its path, and its size unless a zip data descriptor
+/// carries it.
+struct EntryHeader {
+    path: String,
+    size: Option<u64>,
+    /// A file, rather than a tar directory or extended header.
+    regular: bool,
+    /// The CRC-32 in a zip header; `None` for tar or a data descriptor.
+    crc: Option<u32>,
+}
+
+impl<R: Read> ArchiveReader<R> {
+    fn new(mut input: R) -> Result<Self, CteError> {
+        let mut peeked = vec![0u8; 4];
+        input.read_exact(&mut peeked)?;
+        let format = if read_u32(&peeked) == ZIP_LOCAL {
+            ArchiveFormat::Zip
+        } else {
+            ArchiveFormat::Tar
+        };
+        Ok(Self {
+            input,
+            format,
+            peeked,
+        })
+    }
+
+    /// `len` bytes of header; never more than a tar block or a zip name.
+    fn read(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        let mut buf = std::mem::take(&mut self.peeked);
+        let have = buf.len();
+        buf.resize(have.max(len), 0);
+        self.input.read_exact(&mut buf[have..])?;
+        Ok(buf)
+    }
+
+    /// Pass over `len` bytes of entry data without buffering them.
+    fn skip(&mut self, len: u64) -> io::Result<()> {
+        let skipped = io::copy(&mut (&mut self.input).take(len), &mut io::sink())?;
+        if skipped < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+
+    /// The next entry's header, or `None` at the end of the archive.
+    fn header(&mut self) -> Result<Option<EntryHeader>, CteError> {
+        match self.format {
+            ArchiveFormat::Tar => {
+                let h = self.read(TAR_BLOCK)?;
+                if h.iter().all(|&b| b == 0) {
+                    return Ok(None);
+                }
+                let field = |r: std::ops::Range<usize>| {
+                    let f = &h[r];
+                    String::from_utf8_lossy(&f[..f.iter().position(|&b| b == 0).unwrap_or(f.len())])
+                        .into_owned()
+                };
+                let mut path = field(0..100);
+                let prefix = field(345..500);
+                if &h[257..262] == b"ustar" && !prefix.is_empty() {
+                    path = format!("{}/{}", prefix, path);
+                }
+                let size = tar_size(&h[124..136]).map_err(|reason| corrupt(&path, reason))?;
+                Ok(Some(EntryHeader {
+                    path,
+                    size: Some(size),
+                    // Directories and extended headers are skipped.
+                    regular: matches!(h[156], b'0' | 0),
+                    crc: None,
+                }))
+            }
+            ArchiveFormat::Zip => {
+                let signature = read_u32(&self.read(4)?);
+                if signature != ZIP_LOCAL {
+                    // Central directory: no more entries.
+                    return Ok(None);
+                }
+                let h = self.read(26)?;
+                let flags = read_u16(&h[2..]);
+                if read_u16(&h[4..]) != 0 {
+                    return Err(CteError::Unsupported("compressed zip entries".into()));
+                }
+                let name = self.read(read_u16(&h[22..]) as usize)?;
+                let path = String::from_utf8_lossy(&name).into_owned();
+                self.read(read_u16(&h[24..]) as usize)?;
+                let in_header = flags & ZIP_FLAG_DESCRIPTOR == 0;
+                Ok(Some(EntryHeader {
+                    path,
+                    size: in_header.then(|| read_u32(&h[14..]) as u64),
+                    regular: true,
+                    crc: in_header.then(|| read_u32(&h[10..])),
+                }))
+            }
+        }
+    }
+
+    /// The `size` bytes of data of entry `h`, or `None` if not `keep`, when
+    /// they are skipped instead. A kept entry is buffered whole, so its size is
+    /// checked against `RequestLimits` first.
+    fn data(
+        &mut self,
+        h: &EntryHeader,
+        size: u64,
+        keep: bool,
+    ) -> Result<Option<Vec<u8>>, CteError> {
+        let data = if keep {
+            let mut buf = limits::read_buffer(&h.path, size, &limits::request_limits())?;
+            self.input.read_exact(&mut buf)?;
+            Some(buf)
+        } else {
+            self.skip(size)?;
+            None
+        };
+        let expected = match self.format {
+            ArchiveFormat::Tar => {
+                self.read(tar_padding(size))?;
+                None
+            }
+            ArchiveFormat::Zip => Some(match h.crc {
+                Some(crc) => crc,
+                None => {
+                    // The descriptor's signature is optional.
+                    let d = self.read(4)?;
+                    let crc = match read_u32(&d) {
+                        ZIP_DESCRIPTOR => read_u32(&self.read(4)?),
+                        crc => crc,
+                    };
+                    self.read(8)?;
+                    crc
+                }
+            }),
+        };
+        if let (Some(data), Some(expected)) = (&data, expected) {
+            if crc32(0, data) != expected {
+                return Err(corrupt(&h.path, "CRC-32 mismatch"));
+            }
+        }
+        Ok(data)
+    }
+
+    /// The manifest, which must be the archive's first file.
+    fn manifest(&mut self) -> Result<Manifest, CteError> {
+        let not_cte =
+            || CteError::InvalidArgument("archive doesn't start with a CTE manifest".into());
+        loop {
+            let h = self.header()?.ok_or_else(not_cte)?;
+            let size = h
+                .size
+                .ok_or_else(|| corrupt(&h.path, "entry without a size"))?;
+            if !h.regular {
+                self.data(&h, size, false)?;
+                continue;
+            }
+            if h.path != MANIFEST_PATH {
+                return Err(not_cte());
+            }
+            let data = self.data(&h, size, true)?.unwrap_or_default();
+            let text = String::from_utf8(data).map_err(|_| corrupt(MANIFEST_PATH, "bad UTF-8"))?;
+            return Manifest::decode(&text).map_err(|reason| corrupt(MANIFEST_PATH, reason));
+        }
+    }
+
+    /// The path and data of the next entry the manifest lists, with
+    /// `listed_size` giving the size it lists for a path, or `None` at the end
+    /// of the archive. Other entries are skipped, and one whose size differs
+    /// from the manifest's is corrupt.
+    fn next_entry(
+        &mut self,
+        listed_size: impl Fn(&str) -> Option<u64>,
+    ) -> Result<Option<(String, Vec<u8>)>, CteError> {
+        loop {
+            let Some(h) = self.header()? else {
+                return Ok(None);
+            };
+            let listed = listed_size(&h.path);
+            let size = match (h.size, listed) {
+                (Some(size), Some(listed)) if size != listed => {
+                    return Err(corrupt(&h.path, "size differs from manifest"))
+                }
+                (Some(size), _) | (None, Some(size)) => size,
+                (None, None) => return Err(corrupt(&h.path, "entry not in manifest")),
+            };
+            let keep = h.regular && listed.is_some();
+            if let Some(data) = self.data(&h, size, keep)? {
+                return Ok(Some((h.path, data)));
+            }
+        }
+    }
+}
+
+impl Tag {
+    /// Write every blob of this tag, with a manifest, to `writer` as a `format`
+    /// archive. Returns the number of blobs exported. Fails with
+    /// `GenerationMismatch` if a blob is rewritten while it is being exported.
+    pub fn export_archive(
+        &self,
+        writer: impl Write,
+        format: ArchiveFormat,
+    ) -> Result<usize, CteError> {
+        let mut manifest = Manifest {
+            tag: self.name().to_string(),
+            tag_attrs: self.get_attrs()?,
+            blobs: Vec::new(),
+        };
+        let mut generations = Vec::new();
+        let mut names = self.blob_names();
+        names.sort();
+        for name in names {
+            let Some(stat) = self.stat_blob(&name)? else {
+                continue;
+            };
+            if stat.size == 0 {
+                continue;
+            }
+            generations.push(stat.generation);
+            manifest.blobs.push(ManifestBlob {
+                path: archive_path(&name, manifest.blobs.len()),
+                attrs: self.get_blob_attrs(&name)?,
+                name,
+                size: stat.size,
+                score: stat.score,
+            });
+        }
+        let text = manifest.encode();
+        if format == ArchiveFormat::Zip
+            && (manifest.blobs.len() >= u16::MAX as usize
+                || zip_size(&manifest, text.len()) > u32::MAX as u64)
+        {
+            return Err(CteError::Unsupported(format!(
+                "tag '{}' is too large for a zip archive; use tar",
+                self.name()
+            )));
+        }
+
+        let mut out = ArchiveWriter::new(writer, format);
+        out.begin(
+            MANIFEST_PATH,
+            text.len() as u64,
+            Some(crc32(0, text.as_bytes())),
+        )?;
+        out.data(text.as_bytes())?;
+        out.end()?;
+        for (blob, generation) in manifest.blobs.iter().zip(generations) {
+            out.begin(&blob.path, blob.size, None)?;
+            self.export_blob(&mut out, blob, generation)?;
+            out.end()?;
+        }
+        out.finish()?;
+        Ok(manifest.blobs.len())
+    }
+
+    fn export_blob<W: Write>(
+        &self,
+        out: &mut ArchiveWriter<W>,
+        blob: &ManifestBlob,
+        generation: u64,
+    ) -> Result<(), CteError> {
+        let transformed = self
+            .load_meta(&blob.name)?
+            .is_some_and(|m| m.is_transformed());
+        // Stored bytes aren't the data for compressed or encrypted blobs, so those
+        // are read whole.
+        let chunk = if transformed { blob.size } else { COPY_CHUNK };
+        let mut offset = 0;
+        while offset < blob.size {
+            let options = GetOptions {
+                offset,
+                size: Some(chunk.min(blob.size - offset)),
+                if_generation_match: Some(generation),
+                ..Default::default()
+            };
+            let data = self.get(&blob.name, &options)?;
+            if data.is_empty() {
+                break;
+            }
+            out.data(&data)?;
+            offset += data.len() as u64;
+        }
+        if offset != blob.size {
+            return Err(CteError::Io(io::Error::other(format!(
+                "'{}' changed size during export",
+                blob.name
+            ))));
+        }
+        Ok(())
+    }
+}
+
+impl Client {
+    /// Import an archive written by `Tag::export_archive` into `tag`, replacing
+    /// blobs of the same names and restoring scores and attributes. Returns the
+    /// number of blobs imported.
+    pub fn import_archive(tag: &str, reader: impl Read) -> Result<usize, CteError> {
+        let mut archive = ArchiveReader::new(reader)?;
+        let manifest = archive.manifest()?;
+        if let Some(blob) = manifest.blobs.iter().find(|b| meta::is_reserved(&b.name)) {
+            return Err(CteError::InvalidArgument(format!(
+                "blob name '{}' is reserved",
+                blob.name
+            )));
+        }
+        let tag = Tag::try_new(tag)?;
+        for (k, v) in &manifest.tag_attrs {
+            tag.set_attr(k, v)?;
+        }
+        let blobs: HashMap<&str, &ManifestBlob> = manifest
+            .blobs
+            .iter()
+            .map(|b| (b.path.as_str(), b))
+            .collect();
+        let mut imported = 0;
+        while let Some((path, data)) = archive.next_entry(|p| blobs.get(p).map(|b| b.size))? {
+            let blob = blobs[path.as_str()];
+            // A put doesn't truncate, so a longer blob of the same name goes first.
+            if tag.get_blob_size(&blob.name) > 0 {
+                tag.del_blob(&blob.name);
+            }
+            let options = PutOptions {
+                score: Some(blob.score),
+                ..Default::default()
+            };
+            tag.put(&blob.name, &data, &options)?;
+            if !blob.attrs.is_empty() {
+                let attrs: Vec<(&str, &str)> = blob
+                    .attrs
+                    .iter()
+                    .map(|(k, v)| (k.as_str(), v.as_str()))
+                    .collect();
+                tag.set_blob_attrs(&blob.name, &attrs)?;
+            }
+            imported += 1;
+        }
+        Ok(imported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_format_pieces() {
+        assert_eq!(crc32(0, b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(crc32(0, b"1234"), b"56789"), 0xCBF4_3926);
+        assert_eq!(dos_datetime(0), (0, (1 << 5) | 1));
+        // 2024-02-29 12:34:56 UTC
+        assert_eq!(
+            dos_datetime(1_709_210_096),
+            (12 << 11 | 34 << 5 | 28, 44 << 9 | 2 << 5 | 29)
+        );
+
+        let header = tar_header("blobs/a", 10_000, 0);
+        assert_eq!(tar_size(&header[124..136]), Ok(10_000));
+        let sum: u64 = header
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| {
+                if (148..156).contains(&i) {
+                    b' ' as u64
+                } else {
+                    b as u64
+                }
+            })
+            .sum();
+        assert_eq!(tar_size(&header[148..155]), Ok(sum));
+        assert_eq!(
+            tar_size(&tar_header("big", 1 << 40, 0)[124..136]),
+            Ok(1 << 40)
+        );
+
+        assert_eq!(archive_path("ckpt/shard-0", 0), "blobs/ckpt/shard-0");
+        assert_eq!(archive_path("../etc/passwd", 3), "other/3");
+        assert_eq!(archive_path("/abs", 4), "other/4");
+
+        let manifest = Manifest {
+            tag: "run 7".into(),
+            tag_attrs: vec![("owner".into(), "lab\tA".into())],
+            blobs: vec![ManifestBlob {
+                path: "blobs/x%y".into(),
+                name: "x%y".into(),
+                size: 5,
+                score: 0.25,
+                attrs: vec![("k".into(), "v".into())],
+            }],
+        };
+        assert_eq!(Manifest::decode(&manifest.encode()), Ok(manifest));
+    }
+
+    #[test]
+    fn test_archive_entry_sizes() {
+        // Only headers: nothing past them is read once a size is refused.
+        let limits = limits::RequestLimits {
+            max_read: Some(100),
+            ..Default::default()
+        };
+        let huge = tar_header(MANIFEST_PATH, 1 << 40, 0);
+        let manifest = limits::scoped(limits, || ArchiveReader::new(&huge[..])?.manifest());
+        assert!(matches!(manifest, Err(CteError::TooLarge { size, .. }) if size == 1 << 40));
+
+        let sizes = HashMap::from([("blobs/a", 5)]);
+        let listed = |p: &str| sizes.get(p).copied();
+        let longer = tar_header("blobs/a", 1 << 40, 0);
+        assert!(matches!(
+            ArchiveReader::new(&longer[..]).unwrap().next_entry(listed),
+            Err(CteError::CorruptMetadata { .. })
+        ));
+
+        let mut tar = tar_header("unlisted", 3, 0).to_vec();
+        tar.extend_from_slice(&[7; TAR_BLOCK]);
+        tar.extend_from_slice(&tar_header("blobs/a", 5, 0));
+        tar.extend_from_slice(b"hello");
+        tar.resize(tar.len() + tar_padding(5) + 2 * TAR_BLOCK, 0);
+        let mut reader = ArchiveReader::new(&tar[..]).unwrap();
+        assert_eq!(
+            reader.next_entry(listed).unwrap(),
+            Some(("blobs/a".to_string(), b"hello".to_vec()))
+        );
+        assert_eq!(reader.next_entry(listed).unwrap(), None);
+    }
+
+    #[test]
+    fn test_import_refuses_reserved_names() {
+        let manifest = Manifest {
+            tag: "t".into(),
+            tag_attrs: Vec::new(),
+            blobs: vec![ManifestBlob {
+                path: "other/0".into(),
+                name: ".cte/meta/x".into(),
+                size: 0,
+                score: 1.0,
+                attrs: Vec::new(),
+            }],
+        };
+        let encoded = manifest.encode().into_bytes();
+        let mut tar = tar_header(MANIFEST_PATH, encoded.len() as u64, 0).to_vec();
+        tar.extend_from_slice(&encoded);
+        tar.resize(
+            tar.len() + tar_padding(encoded.len() as u64) + 2 * TAR_BLOCK,
+            0,
+        );
+        // Refused from the manifest alone, before the tag is touched.
+        let err = Client::import_archive("rust_archive_reserved_tag", &tar[..]).unwrap_err();
+        assert!(matches!(err, CteError::InvalidArgument(m) if m.contains(".cte/meta/x")));
+    }
+}
@@ -0,0 +1,129 @@
+//! User key-value attributes on blobs and tags.
+//!
+//! Blob attributes live in the blob's metadata sidecar and tag attributes in the
+//! tag's (see `meta`), so they are visible to every client of the tag.
+
+use crate::meta::meta_lock;
+use crate::{Client, CteError, Tag};
+
+/// Attributes as `(key, value)` pairs sorted by key.
+pub type Attrs = Vec<(String, String)>;
+
+impl Tag {
+    /// Set attributes on an existing blob. Keys already present are overwritten;
+    /// other attributes are kept.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(tag = %self.name, blob = name), err)
+    )]
+    pub fn set_blob_attrs(&self, name: &str, attrs: &[(&str, &str)]) -> Result<(), CteError> {
+        let _guard = meta_lock();
+        let meta = self.load_meta(name)?;
+        if meta.is_none() && self.get_blob_size(name) == 0 {
+            return Err(CteError::NotFound {
+                blob: name.to_string(),
+            });
+        }
+        let mut meta = meta.unwrap_or_default();
+        for (k, v) in attrs {
+            meta.attrs.insert(k.to_string(), v.to_string());
+        }
+        self.store_meta(name, &meta)
+    }
+
+    /// All attributes of a blob, sorted by key. Empty if none were ever set.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(tag = %self.name, blob = name), err)
+    )]
+    pub fn get_blob_attrs(&self, name: &str) -> Result<Attrs, CteError> {
+        Ok(self
+            .load_meta(name)?
+            .map(|m| m.attrs.into_iter().collect())
+            .unwrap_or_default())
+    }
+}
+
+impl Tag {
+    /// Set a tag-level attribute, overwriting any previous value for `key`.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(tag = %self.name, key = key), err)
+    )]
+    pub fn set_attr(&self, key: &str, value: &str) -> Result<(), CteError> {
+        let _guard = meta_lock();
+        let mut meta = self.load_tag_meta()?;
+        meta.attrs.insert(key.to_string(), value.to_string());
+        self.store_tag_meta(&meta)
+    }
+
+    /// All tag-level attributes, sorted by key.
+    pub fn get_attrs(&self) -> Result<Attrs, CteError> {
+        Ok(self.load_tag_meta()?.attrs.into_iter().collect())
+    }
+}
+
+impl Client {
+    /// `tag_query`, returning each tag's attributes alongside its name.
+    pub fn tag_query_with_attrs(
+        regex: &str,
+        max_tags: u32,
+    ) -> Result<Vec<(String, Attrs)>, CteError> {
+        Self::tag_query(regex, max_tags)
+            .into_iter()
+            .map(|name| {
+                let attrs = Tag::try_new(&name)?.get_attrs()?;
+                Ok((name, attrs))
+            })
+            .collect()
+    }
+
+    /// `tag_query`, keeping only tags whose attributes include every
+    /// `(key, value)` in `attrs`.
+    pub fn tag_query_by_attrs(
+        regex: &str,
+        attrs: &[(&str, &str)],
+        max_tags: u32,
+    ) -> Result<Vec<String>, CteError> {
+        let mut out = Vec::new();
+        for name in Self::tag_query(regex, max_tags) {
+            let meta = Tag::try_new(&name)?.load_tag_meta()?;
+            if attrs
+                .iter()
+                .all(|(k, v)| meta.attrs.get(*k).map(String::as_str) == Some(*v))
+            {
+                out.push(name);
+            }
+        }
+        Ok(out)
+    }
+
+    /// `blob_query`, keeping only blobs whose attributes include every
+    /// `(key, value)` in `attrs`.
+    ///
+    /// Attribute matching happens in the wrapper after the regex query, so
+    /// `max_results` bounds the regex matches examined, not the matches returned.
+    pub fn blob_query_by_attrs(
+        tag_re: &str,
+        blob_re: &str,
+        attrs: &[(&str, &str)],
+        max_results: u32,
+    ) -> Result<Vec<(String, String)>, CteError> {
+        let mut out = Vec::new();
+        let mut current: Option<Tag> = None;
+        for (tag_name, blob) in Self::blob_query(tag_re, blob_re, max_results) {
+            if current.as_ref().map(|t| t.name()) != Some(tag_name.as_str()) {
+                current = Some(Tag::try_new(&tag_name)?);
+            }
+            let tag = current.as_ref().unwrap();
+            let meta = tag.load_meta(&blob)?.unwrap_or_default();
+            if attrs
+                .iter()
+                .all(|(k, v)| meta.attrs.get(*k).map(String::as_str) == Some(*v))
+            {
+                out.push((tag_name, blob));
+            }
+        }
+        Ok(out)
+    }
+}
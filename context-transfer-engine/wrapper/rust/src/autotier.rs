@@ -0,0 +1,409 @@
+//! Scores tuned from access frequency.
+//!
+//! While `Client::start_auto_tier` runs, every read and write in this process
+//! adds one to the blob's heat, and heat halves every
+//! `AutoTierPolicy::half_life`. Each `interval` the background task turns each
+//! blob's heat into a score, linearly from `min_score` at no heat to
+//! `max_score` at `hot_heat` or more, and reorganizes blobs whose score is at
+//! least `min_change` away from the one they have, hottest change first.
+//! Blobs that are used often rise to the fast tiers and blobs left alone sink
+//! to the slow ones, without anyone setting scores by hand. A registered
+//! `ScoreModel` (see `model`) can score them instead. Pinned blobs (see
+//! `Tag::pin_blob`) and reserved sidecars are left where they are. Only this
+//! process's accesses count, and heat is lost when the task stops.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::meta::RESERVED_PREFIX;
+use crate::model::{self, Feedback, RecentAccess, ScoreModel};
+use crate::{ffi_guard, AccessKind, BlobDescriptor, Client, CteError, CteTagId, Tag};
+
+/// How `Client::start_auto_tier` scores blobs.
+#[derive(Debug, Clone)]
+pub struct AutoTierPolicy {
+    /// How often scores are adjusted.
+    pub interval: Duration,
+    /// Time for a blob's heat to halve.
+    pub half_life: Duration,
+    /// Heat at which a blob gets `max_score`.
+    pub hot_heat: f64,
+    pub min_score: f32,
+    pub max_score: f32,
+    /// Smallest score change worth a reorganize.
+    pub min_change: f32,
+    /// Most blobs reorganized per interval.
+    pub max_moves: usize,
+}
+
+impl Default for AutoTierPolicy {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            half_life: Duration::from_secs(600),
+            hot_heat: 16.0,
+            min_score: 0.0,
+            max_score: 1.0,
+            min_change: 0.1,
+            max_moves: 1000,
+        }
+    }
+}
+
+impl AutoTierPolicy {
+    fn check(&self) -> Result<(), CteError> {
+        let scores = 0.0..=1.0;
+        if !scores.contains(&self.min_score)
+            || !scores.contains(&self.max_score)
+            || self.min_score > self.max_score
+        {
+            return Err(CteError::InvalidArgument(format!(
+                "auto-tier scores {}..{} are not within 0.0..=1.0",
+                self.min_score, self.max_score
+            )));
+        }
+        if self.half_life.is_zero()
+            || self.interval.is_zero()
+            || self.hot_heat.is_nan()
+            || self.hot_heat <= 0.0
+        {
+            return Err(CteError::InvalidArgument(
+                "auto-tier interval, half-life and hot heat must be positive".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// `heat` after `elapsed` more time.
+    fn decay(&self, heat: f64, elapsed: Duration) -> f64 {
+        heat * 0.5f64.powf(elapsed.as_secs_f64() / self.half_life.as_secs_f64())
+    }
+
+    fn score(&self, heat: f64) -> f32 {
+        let hot = (heat / self.hot_heat).min(1.0) as f32;
+        self.min_score + (self.max_score - self.min_score) * hot
+    }
+}
+
+struct Heat {
+    heat: f64,
+    at: Instant,
+    /// Score last seen or set; `None` until the blob is first scored.
+    score: Option<f32>,
+    reads: u64,
+    writes: u64,
+    /// Accesses since the last interval.
+    interval_accesses: u64,
+    /// Largest extent accessed.
+    size: u64,
+    first: Instant,
+    last: Instant,
+    /// The score model's last prediction, awaiting feedback.
+    predicted: Option<(RecentAccess, f32)>,
+}
+
+impl Heat {
+    fn new(now: Instant) -> Self {
+        Self {
+            heat: 0.0,
+            at: now,
+            score: None,
+            reads: 0,
+            writes: 0,
+            interval_accesses: 0,
+            size: 0,
+            first: now,
+            last: now,
+            predicted: None,
+        }
+    }
+
+    fn access(&self, now: Instant) -> RecentAccess {
+        RecentAccess {
+            heat: self.heat,
+            reads: self.reads,
+            writes: self.writes,
+            interval_accesses: self.interval_accesses,
+            idle: now - self.last,
+            age: now - self.first,
+        }
+    }
+}
+
+type BlobKey = ((u32, u32), String);
+
+static TRACKING: AtomicBool = AtomicBool::new(false);
+static HEAT: Mutex<Option<(AutoTierPolicy, HashMap<BlobKey, Heat>)>> = Mutex::new(None);
+
+/// Count an access to `blob` of `tag_id` that reached `extent` bytes into it.
+pub(crate) fn record(tag_id: CteTagId, blob: &str, kind: AccessKind, extent: u64) {
+    if !TRACKING.load(Ordering::Relaxed) || blob.starts_with(RESERVED_PREFIX) {
+        return;
+    }
+    let mut tracker = HEAT.lock().unwrap_or_else(|e| e.into_inner());
+    let Some((policy, heats)) = tracker.as_mut() else {
+        return;
+    };
+    let key = ((tag_id.major, tag_id.minor), blob.to_string());
+    if kind == AccessKind::Delete {
+        heats.remove(&key);
+        return;
+    }
+    let now = Instant::now();
+    let entry = heats.entry(key).or_insert_with(|| Heat::new(now));
+    entry.heat = policy.decay(entry.heat, now - entry.at) + 1.0;
+    entry.at = now;
+    entry.last = now;
+    entry.interval_accesses += 1;
+    entry.size = entry.size.max(extent);
+    if kind == AccessKind::Read {
+        entry.reads += 1;
+    } else {
+        entry.writes += 1;
+    }
+}
+
+/// A reorganize `adjust` decided on; `from` is `None` if the current score
+/// has to be asked for.
+struct Move {
+    key: BlobKey,
+    from: Option<f32>,
+    to: f32,
+}
+
+/// Decay every blob's heat and pick the moves of one interval, scoring by
+/// `model` if there is one. Blobs that have cooled off where they belong are
+/// forgotten.
+fn plan(
+    policy: &AutoTierPolicy,
+    heats: &mut HashMap<BlobKey, Heat>,
+    now: Instant,
+    mut model: Option<&mut dyn ScoreModel>,
+) -> Vec<Move> {
+    let mut moves = Vec::new();
+    heats.retain(|key, h| {
+        h.heat = policy.decay(h.heat, now - h.at);
+        h.at = now;
+        let by_heat = policy.score(h.heat);
+        let to = match model.as_deref_mut() {
+            Some(model) => {
+                let ((major, minor), ref name) = *key;
+                let blob = || BlobDescriptor {
+                    tag_id: CteTagId { major, minor },
+                    name: name.clone(),
+                    size: h.size,
+                    offset: 0,
+                };
+                if let Some((access, predicted)) = h.predicted.take() {
+                    let feedback = Feedback {
+                        blob: blob(),
+                        access,
+                        predicted,
+                        observed: policy.score(h.interval_accesses as f64),
+                    };
+                    ffi_guard::hook("score_model_update", (), || model.update(&feedback));
+                }
+                let access = h.access(now);
+                let predicted = ffi_guard::hook("score_model_predict", by_heat, || {
+                    model.predict(&blob(), &access)
+                });
+                h.predicted = Some((access, predicted));
+                predicted.clamp(policy.min_score, policy.max_score)
+            }
+            None => by_heat,
+        };
+        h.interval_accesses = 0;
+        if h.score.is_some_and(|s| (s - to).abs() < policy.min_change) {
+            return h.heat >= 0.01;
+        }
+        moves.push(Move {
+            key: key.clone(),
+            from: h.score,
+            to,
+        });
+        true
+    });
+    // Known big changes first, then blobs not scored yet.
+    moves.sort_by(|a, b| {
+        let change = |m: &Move| m.from.map_or(0.0, |s| (s - m.to).abs());
+        change(b).total_cmp(&change(a))
+    });
+    moves
+}
+
+/// One interval of the background task.
+fn adjust() {
+    let (policy, moves) = {
+        let mut tracker = HEAT.lock().unwrap_or_else(|e| e.into_inner());
+        let Some((policy, heats)) = tracker.as_mut() else {
+            return;
+        };
+        let moves = model::with_model(|model| plan(policy, heats, Instant::now(), model));
+        (policy.clone(), moves)
+    };
+    let mut tags: HashMap<(u32, u32), Tag> = HashMap::new();
+    let mut moved = 0;
+    let mut settled: Vec<(BlobKey, Option<f32>)> = Vec::new();
+    for m in moves {
+        if moved == policy.max_moves {
+            break;
+        }
+        let ((major, minor), ref blob) = m.key;
+        let tag = tags
+            .entry((major, minor))
+            .or_insert_with(|| Tag::from_id(CteTagId { major, minor }));
+        let from = m.from.unwrap_or_else(|| tag.get_blob_score(blob));
+        if (from - m.to).abs() < policy.min_change {
+            settled.push((m.key, Some(from)));
+            continue;
+        }
+        if tag
+            .load_meta(blob)
+            .ok()
+            .flatten()
+            .is_some_and(|meta| meta.pin().is_some() || (meta.is_settling() && m.to < from))
+        {
+            settled.push((m.key, Some(from)));
+            continue;
+        }
+        let score = match tag.move_blob(blob, m.to) {
+            Ok(()) => {
+                moved += 1;
+                Some(m.to)
+            }
+            // Gone, or refused; forget it until it is used again.
+            Err(_) => None,
+        };
+        settled.push((m.key, score));
+    }
+    let mut tracker = HEAT.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((_, heats)) = tracker.as_mut() {
+        for (key, score) in settled {
+            match score {
+                Some(score) => {
+                    if let Some(h) = heats.get_mut(&key) {
+                        h.score = Some(score);
+                    }
+                }
+                None => {
+                    heats.remove(&key);
+                }
+            }
+        }
+    }
+}
+
+/// Background score tuner started by `Client::start_auto_tier`; stops when
+/// dropped.
+pub struct AutoTierTask {
+    stop: Option<Sender<()>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Drop for AutoTierTask {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+        TRACKING.store(false, Ordering::Relaxed);
+        *HEAT.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+impl Client {
+    /// Track blob accesses and tune scores by `policy` (see `autotier`) on a
+    /// background thread until the returned task is dropped. Fails with
+    /// `InvalidArgument` for a policy out of range or while another task runs.
+    pub fn start_auto_tier(policy: AutoTierPolicy) -> Result<AutoTierTask, CteError> {
+        policy.check()?;
+        let interval = policy.interval;
+        {
+            let mut tracker = HEAT.lock().unwrap_or_else(|e| e.into_inner());
+            if tracker.is_some() {
+                return Err(CteError::InvalidArgument(
+                    "auto-tiering is already running".into(),
+                ));
+            }
+            *tracker = Some((policy, HashMap::new()));
+        }
+        TRACKING.store(true, Ordering::Relaxed);
+        let (stop, stopped) = mpsc::channel::<()>();
+        let worker = std::thread::spawn(move || loop {
+            match stopped.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => adjust(),
+                _ => return,
+            }
+        });
+        Ok(AutoTierTask {
+            stop: Some(stop),
+            worker: Some(worker),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_tier_plan() {
+        let policy = AutoTierPolicy {
+            half_life: Duration::from_secs(10),
+            hot_heat: 4.0,
+            ..Default::default()
+        };
+        assert!(policy.check().is_ok());
+        assert!(AutoTierPolicy {
+            min_score: 0.8,
+            max_score: 0.2,
+            ..Default::default()
+        }
+        .check()
+        .is_err());
+        assert!((policy.decay(8.0, Duration::from_secs(20)) - 2.0).abs() < 1e-9);
+        assert_eq!(policy.score(0.0), 0.0);
+        assert_eq!(policy.score(2.0), 0.5);
+        assert_eq!(policy.score(100.0), 1.0);
+
+        let now = Instant::now();
+        let key = |blob: &str| ((1, 2), blob.to_string());
+        let mut heats = HashMap::new();
+        let heat = |heat, score| Heat {
+            heat,
+            score,
+            ..Heat::new(now)
+        };
+        heats.insert(key("hot"), heat(8.0, Some(0.0)));
+        heats.insert(key("warm"), heat(2.0, Some(0.55)));
+        heats.insert(key("new"), heat(1.0, None));
+        heats.insert(key("cold"), heat(0.001, Some(0.0)));
+        let moves = plan(&policy, &mut heats, now, None);
+        let planned: Vec<(&str, f32)> = moves.iter().map(|m| (m.key.1.as_str(), m.to)).collect();
+        // "warm" is close enough to stay, and "cold" has cooled off.
+        assert_eq!(planned, [("hot", 1.0), ("new", 0.25)]);
+        assert!(heats.contains_key(&key("warm")) && !heats.contains_key(&key("cold")));
+
+        // A model scores instead of the heat, and hears how it did next time.
+        struct Fixed(f32, Vec<f32>);
+        impl ScoreModel for Fixed {
+            fn predict(&self, _: &BlobDescriptor, _: &RecentAccess) -> f32 {
+                self.0
+            }
+            fn update(&mut self, feedback: &Feedback) {
+                self.1.push(feedback.observed);
+            }
+        }
+        let mut model = Fixed(0.7, Vec::new());
+        let moves = plan(&policy, &mut heats, now, Some(&mut model));
+        assert!(moves.iter().all(|m| m.to == 0.7));
+        heats.get_mut(&key("hot")).unwrap().interval_accesses = 2;
+        plan(&policy, &mut heats, now, Some(&mut model));
+        model.1.sort_by(f32::total_cmp);
+        assert_eq!(model.1.last(), Some(&0.5));
+    }
+}
@@ -0,0 +1,106 @@
+//! Arrow record batches stored as blobs (feature `arrow`).
+//!
+//! `Tag::put_record_batches` writes batches as one Arrow IPC stream, which
+//! Polars, DataFusion and pyarrow read as is, and records the schema and row
+//! count in the blob's attributes (see `attrs`), so a listing with
+//! `Client::tag_query_with_attrs` or `Tag::get_blob_attrs` shows what a blob
+//! holds without reading it. `Tag::get_record_batches` reads the stream back.
+
+use std::io::Cursor;
+
+use arrow_array::RecordBatch;
+use arrow_ipc::reader::StreamReader;
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{ArrowError, Schema};
+
+use crate::{CteError, GetOptions, PutOptions, Tag};
+
+/// Attribute holding the batches' schema, as `name: type` per column, with a
+/// `?` after the type of a nullable column.
+pub const ARROW_SCHEMA_ATTR: &str = "arrow.schema";
+/// Attribute holding the number of rows in all the batches.
+pub const ARROW_ROWS_ATTR: &str = "arrow.rows";
+
+/// The schema as `ARROW_SCHEMA_ATTR` records it.
+pub(crate) fn describe(schema: &Schema) -> String {
+    schema
+        .fields()
+        .iter()
+        .map(|f| {
+            let nullable = if f.is_nullable() { "?" } else { "" };
+            format!("{}: {}{}", f.name(), f.data_type(), nullable)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn encode_error(e: ArrowError) -> CteError {
+    CteError::InvalidArgument(format!("can't encode record batches: {}", e))
+}
+
+impl Tag {
+    /// Write `batches`, which must share one schema, as blob `name` in Arrow
+    /// IPC stream format (see `batches`). Returns the new generation.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(tag = %self.name, blob = name, batches = batches.len()),
+            err
+        )
+    )]
+    pub fn put_record_batches(&self, name: &str, batches: &[RecordBatch]) -> Result<u64, CteError> {
+        let schema = batches
+            .first()
+            .ok_or_else(|| CteError::InvalidArgument("no record batches to write".into()))?
+            .schema();
+        let mut writer = StreamWriter::try_new(Vec::new(), &schema).map_err(encode_error)?;
+        for batch in batches {
+            writer.write(batch).map_err(encode_error)?;
+        }
+        writer.finish().map_err(encode_error)?;
+        let data = writer.into_inner().map_err(encode_error)?;
+        let generation = self.put(name, &data, &PutOptions::default())?;
+        let rows: usize = batches.iter().map(RecordBatch::num_rows).sum();
+        self.set_blob_attrs(
+            name,
+            &[
+                (ARROW_SCHEMA_ATTR, &describe(&schema)),
+                (ARROW_ROWS_ATTR, &rows.to_string()),
+            ],
+        )?;
+        Ok(generation)
+    }
+
+    /// Read blob `name`, written as an Arrow IPC stream, as record batches.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(tag = %self.name, blob = name), err)
+    )]
+    pub fn get_record_batches(&self, name: &str) -> Result<Vec<RecordBatch>, CteError> {
+        let data = self.get(name, &GetOptions::default())?;
+        let decode_error = |e: ArrowError| {
+            CteError::InvalidArgument(format!("'{}' isn't an Arrow IPC stream: {}", name, e))
+        };
+        StreamReader::try_new(Cursor::new(data), None)
+            .map_err(decode_error)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(decode_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_schema::{DataType, Field};
+
+    #[test]
+    fn test_describe_schema() {
+        let schema = Schema::new(vec![
+            Field::new("step", DataType::Int64, false),
+            Field::new("energy", DataType::Float64, true),
+        ]);
+        assert_eq!(describe(&schema), "step: Int64, energy: Float64?");
+    }
+}
@@ -0,0 +1,125 @@
+//! `clio-grpcd`: serve the wrapper API over gRPC.
+//!
+//! Starts a client with `--config` (the runtime's environment when omitted)
+//! and answers the `wrp.cte.v1.Cte` service of `proto/cte.proto` until killed
+//! (see `wrp_cte_rs::Client::serve_grpc`). Built with the `grpc` feature.
+
+use std::fs;
+use std::process::ExitCode;
+
+use wrp_cte_rs::{init, Client, GrpcOptions};
+
+const DEFAULT_ADDR: &str = "127.0.0.1:50051";
+
+const USAGE: &str = "\
+usage: clio-grpcd [--config PATH] [--addr HOST:PORT] [--token-file PATH]
+                  [--max-message-bytes N]
+
+Serves the CTE gRPC API on --addr (127.0.0.1:50051 by default) and prints the
+address it listens on. Calls must carry the token in the first line of
+--token-file as `authorization: bearer <token>`; without one only loopback
+addresses are served.
+";
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    config: String,
+    addr: String,
+    token_file: Option<String>,
+    max_message_bytes: Option<usize>,
+}
+
+fn parse(args: &[String]) -> Result<Args, String> {
+    let mut parsed = Args {
+        config: String::new(),
+        addr: DEFAULT_ADDR.to_string(),
+        token_file: None,
+        max_message_bytes: None,
+    };
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = || it.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--config" => parsed.config = value()?.clone(),
+            "--addr" => parsed.addr = value()?.clone(),
+            "--token-file" => parsed.token_file = Some(value()?.clone()),
+            "--max-message-bytes" => {
+                let v = value()?;
+                parsed.max_message_bytes = match v.parse() {
+                    Ok(n) if n > 0 => Some(n),
+                    _ => return Err(format!("bad message size '{}'", v)),
+                };
+            }
+            s => return Err(format!("unexpected argument '{}'", s)),
+        }
+    }
+    Ok(parsed)
+}
+
+/// Serve until the process is killed.
+fn serve(args: &Args) -> Result<(), String> {
+    let mut options = GrpcOptions::default();
+    if let Some(path) = &args.token_file {
+        // Read from a file, so the token isn't in `ps`.
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        options.token = Some(text.lines().next().unwrap_or_default().trim().to_string());
+    }
+    if let Some(n) = args.max_message_bytes {
+        options.max_message_bytes = n;
+    }
+    init(&args.config)?;
+    let server = Client::serve_grpc(args.addr.as_str(), &options).map_err(|e| e.to_string())?;
+    println!("{}", server.local_addr());
+    loop {
+        std::thread::park();
+    }
+}
+
+fn main() -> ExitCode {
+    let argv: Vec<String> = std::env::args().skip(1).collect();
+    let result = parse(&argv)
+        .map_err(|msg| (msg, true))
+        .and_then(|args| serve(&args).map_err(|msg| (msg, false)));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err((msg, true)) => {
+            eprintln!("clio-grpcd: {}\n\n{}", msg, USAGE);
+            ExitCode::from(2)
+        }
+        Err((msg, false)) => {
+            eprintln!("clio-grpcd: {}", msg);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Result<Args, String> {
+        let argv: Vec<String> = line.split_whitespace().map(String::from).collect();
+        parse(&argv)
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            args("").unwrap(),
+            Args {
+                config: String::new(),
+                addr: DEFAULT_ADDR.into(),
+                token_file: None,
+                max_message_bytes: None,
+            }
+        );
+        let parsed =
+            args("--addr 0.0.0.0:7000 --token-file /run/t --max-message-bytes 1024").unwrap();
+        assert_eq!(parsed.addr, "0.0.0.0:7000");
+        assert_eq!(parsed.token_file.as_deref(), Some("/run/t"));
+        assert_eq!(parsed.max_message_bytes, Some(1024));
+        assert!(args("--max-message-bytes 0").is_err());
+        assert!(args("--addr").is_err());
+        assert!(args("serve").is_err());
+    }
+}
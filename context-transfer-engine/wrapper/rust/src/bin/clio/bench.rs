@@ -0,0 +1,336 @@
+//! `clio bench`: put/get throughput and latency against the configured runtime.
+//!
+//! Each thread writes its share of `count` blobs of `blob_size` bytes into a
+//! scratch tag, then reads them all back, in name order (`seq`) or shuffled
+//! (`rand`). The two phases are timed separately and reported as one JSON
+//! object, or with `--output table|csv` as a row per phase; the scratch tag is
+//! deleted afterwards.
+
+use std::fmt::Write as _;
+use std::sync::Barrier;
+use std::time::{Duration, Instant};
+
+use wrp_cte_rs::{Client, Tag};
+
+use crate::output::{Report, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    Seq,
+    Rand,
+}
+
+impl Pattern {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "seq" => Some(Pattern::Seq),
+            "rand" => Some(Pattern::Rand),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Pattern::Seq => "seq",
+            Pattern::Rand => "rand",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchOptions {
+    pub blob_size: u64,
+    pub count: u64,
+    pub threads: u64,
+    pub pattern: Pattern,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            blob_size: 1 << 20,
+            count: 1000,
+            threads: 1,
+            pattern: Pattern::Seq,
+        }
+    }
+}
+
+/// Parse a byte count such as `4096`, `64K` or `1M` (binary multiples).
+pub fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let upper = s.to_ascii_uppercase();
+    let digits = upper.trim_end_matches("IB").trim_end_matches('B');
+    let (num, shift) = match digits.chars().last()? {
+        'K' => (&digits[..digits.len() - 1], 10),
+        'M' => (&digits[..digits.len() - 1], 20),
+        'G' => (&digits[..digits.len() - 1], 30),
+        _ => (digits, 0),
+    };
+    num.parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+/// xorshift64*, enough to fill buffers and shuffle without a dependency.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn shuffle<T>(&mut self, v: &mut [T]) {
+        for i in (1..v.len()).rev() {
+            v.swap(i, (self.next() % (i as u64 + 1)) as usize);
+        }
+    }
+}
+
+/// Latencies of one phase, over all threads.
+struct Phase {
+    wall: Duration,
+    latencies_ns: Vec<u64>,
+}
+
+/// Nearest-rank percentile `p` (0 to 100) of `sorted`.
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+impl Phase {
+    fn stats(mut self, blob_size: u64) -> PhaseStats {
+        self.latencies_ns.sort_unstable();
+        let lat = &self.latencies_ns;
+        let ops = lat.len() as u64;
+        let seconds = self.wall.as_secs_f64().max(f64::MIN_POSITIVE);
+        let mean = lat.iter().sum::<u64>() as f64 / ops.max(1) as f64;
+        let us = |ns: u64| ns as f64 / 1000.0;
+        PhaseStats {
+            ops,
+            seconds,
+            ops_per_sec: ops as f64 / seconds,
+            mib_per_sec: (ops * blob_size) as f64 / seconds / (1024.0 * 1024.0),
+            latency_us: [
+                us(lat.first().copied().unwrap_or(0)),
+                mean / 1000.0,
+                us(percentile(lat, 50.0)),
+                us(percentile(lat, 90.0)),
+                us(percentile(lat, 99.0)),
+                us(percentile(lat, 99.9)),
+                us(lat.last().copied().unwrap_or(0)),
+            ],
+        }
+    }
+}
+
+/// Throughput and latency of one phase.
+struct PhaseStats {
+    ops: u64,
+    seconds: f64,
+    ops_per_sec: f64,
+    mib_per_sec: f64,
+    /// Min, mean, p50, p90, p99, p99.9 and max.
+    latency_us: [f64; 7],
+}
+
+impl PhaseStats {
+    fn json(&self) -> String {
+        let [min, mean, p50, p90, p99, p999, max] = self.latency_us;
+        let mut out = String::new();
+        let _ = write!(
+            out,
+            "{{\"ops\":{},\"seconds\":{:.6},\"ops_per_sec\":{:.1},\"mib_per_sec\":{:.3},\
+             \"latency_us\":{{\"min\":{:.1},\"mean\":{:.1},\"p50\":{:.1},\"p90\":{:.1},\
+             \"p99\":{:.1},\"p999\":{:.1},\"max\":{:.1}}}}}",
+            self.ops,
+            self.seconds,
+            self.ops_per_sec,
+            self.mib_per_sec,
+            min,
+            mean,
+            p50,
+            p90,
+            p99,
+            p999,
+            max,
+        );
+        out
+    }
+}
+
+/// A benchmark's results.
+pub struct BenchReport {
+    options: BenchOptions,
+    put: PhaseStats,
+    get: PhaseStats,
+}
+
+/// Columns of `BenchReport::rows`: the options, then a phase's figures.
+const COLUMNS: &[&str] = &[
+    "phase",
+    "blob_size",
+    "count",
+    "threads",
+    "pattern",
+    "ops",
+    "seconds",
+    "ops_per_sec",
+    "mib_per_sec",
+    "latency_us_min",
+    "latency_us_mean",
+    "latency_us_p50",
+    "latency_us_p90",
+    "latency_us_p99",
+    "latency_us_p999",
+    "latency_us_max",
+];
+
+impl BenchReport {
+    /// The report as one JSON object, with an object per phase.
+    pub fn json(&self) -> String {
+        let o = &self.options;
+        format!(
+            "{{\"blob_size\":{},\"count\":{},\"threads\":{},\"pattern\":\"{}\",\"put\":{},\"get\":{}}}",
+            o.blob_size,
+            o.count,
+            o.threads,
+            o.pattern.name(),
+            self.put.json(),
+            self.get.json(),
+        )
+    }
+
+    /// The report flattened to a row per phase, for `--output table|csv`.
+    pub fn rows(&self) -> Report {
+        let o = &self.options;
+        let mut report = Report::new(COLUMNS);
+        for (name, phase) in [("put", &self.put), ("get", &self.get)] {
+            let mut row: Vec<Value> = vec![
+                name.into(),
+                o.blob_size.into(),
+                o.count.into(),
+                o.threads.into(),
+                o.pattern.name().into(),
+                phase.ops.into(),
+                phase.seconds.into(),
+                phase.ops_per_sec.into(),
+                phase.mib_per_sec.into(),
+            ];
+            row.extend(phase.latency_us.iter().map(|&us| Value::from(us)));
+            report.push(row);
+        }
+        report
+    }
+}
+
+/// Names of thread `t`'s blobs, in the order it touches them.
+fn blob_names(options: &BenchOptions, t: u64, seed: u64) -> Vec<String> {
+    let share = options.count / options.threads + u64::from(t < options.count % options.threads);
+    let mut names: Vec<String> = (0..share).map(|i| format!("t{}-{:010}", t, i)).collect();
+    if options.pattern == Pattern::Rand {
+        Rng(seed | 1).shuffle(&mut names);
+    }
+    names
+}
+
+/// Run `op` on every thread's blobs at once, timing each call.
+fn run_phase(
+    tag_name: &str,
+    options: &BenchOptions,
+    seed: u64,
+    op: impl Fn(&Tag, &str) + Sync,
+) -> Phase {
+    let barrier = Barrier::new(options.threads as usize + 1);
+    let (wall, latencies_ns) = std::thread::scope(|s| {
+        let workers: Vec<_> = (0..options.threads)
+            .map(|t| {
+                let (barrier, op) = (&barrier, &op);
+                s.spawn(move || {
+                    let tag = Tag::new(tag_name);
+                    let names = blob_names(options, t, seed.wrapping_add(t));
+                    let mut lat = Vec::with_capacity(names.len());
+                    barrier.wait();
+                    for name in &names {
+                        let start = Instant::now();
+                        op(&tag, name);
+                        lat.push(start.elapsed().as_nanos() as u64);
+                    }
+                    lat
+                })
+            })
+            .collect();
+        barrier.wait();
+        let start = Instant::now();
+        let lat: Vec<u64> = workers
+            .into_iter()
+            .flat_map(|w| w.join().expect("bench worker panicked"))
+            .collect();
+        (start.elapsed(), lat)
+    });
+    Phase { wall, latencies_ns }
+}
+
+/// Run the benchmark.
+pub fn run(options: &BenchOptions) -> BenchReport {
+    let seed = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(1, |d| d.as_nanos() as u64);
+    let tag_name = format!("clio-bench-{}-{}", std::process::id(), seed);
+    let mut data = vec![0u8; options.blob_size as usize];
+    let mut rng = Rng(seed | 1);
+    for chunk in data.chunks_mut(8) {
+        let bytes = rng.next().to_le_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+
+    let put = run_phase(&tag_name, options, seed, |tag, name| {
+        tag.put_blob(name, &data)
+    });
+    let get = run_phase(&tag_name, options, seed >> 1, |tag, name| {
+        tag.get_blob(name, options.blob_size);
+    });
+    Client::del_tag(&tag_name);
+
+    BenchReport {
+        options: options.clone(),
+        put: put.stats(options.blob_size),
+        get: get.stats(options.blob_size),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bench_pieces() {
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size("64K"), Some(64 << 10));
+        assert_eq!(parse_size("1MiB"), Some(1 << 20));
+        assert_eq!(parse_size("2g"), Some(2 << 30));
+        assert_eq!(parse_size("lots"), None);
+
+        let sorted: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&sorted, 50.0), 50);
+        assert_eq!(percentile(&sorted, 99.9), 100);
+        assert_eq!(percentile(&sorted, 0.0), 1);
+
+        let options = BenchOptions {
+            count: 10,
+            threads: 3,
+            pattern: Pattern::Rand,
+            ..Default::default()
+        };
+        let shares: Vec<usize> = (0..3).map(|t| blob_names(&options, t, 7).len()).collect();
+        assert_eq!(shares, [4, 3, 3]);
+        let mut names = blob_names(&options, 0, 7);
+        names.sort();
+        assert_eq!(names[0], "t0-0000000000");
+    }
+}
@@ -0,0 +1,386 @@
+//! Shell completion: `clio completions <shell>` prints a script for bash, zsh
+//! or fish, which calls `clio __complete <words>` at each tab.
+//!
+//! `__complete` is given the words after `clio` up to the one being
+//! completed (possibly empty) and prints a candidate per line: commands,
+//! options and their fixed values from `COMMANDS`, or tag and blob names
+//! from the runtime. Tags come from an anchored `tag_query` and blobs from a
+//! `list` under the typed prefix, rolled up at `/` so a deep tag completes a
+//! directory at a time. A single `@files` line asks the shell for file
+//! names. The client is only started for tag and blob names, with the
+//! `--config` on the line; if it can't start there are no candidates, and
+//! nothing is printed to stderr.
+//!
+//! `COMMANDS` is also what `parse` checks options against (`accepts`), so
+//! the two take the same options.
+
+use wrp_cte_rs::{init, Client, Tag};
+
+/// Tags or blobs listed at most per tab.
+const MAX_CANDIDATES: u32 = 200;
+
+/// The line `__complete` prints for file names.
+pub const FILES: &str = "@files";
+
+pub const SHELLS: &[&str] = &["bash", "zsh", "fish"];
+
+/// What a positional argument of a command is.
+#[derive(Clone, Copy)]
+enum Arg {
+    Tag,
+    /// A blob of the tag before it.
+    Blob,
+    File,
+    Choice(&'static [&'static str]),
+    Other,
+}
+
+type Names = &'static [&'static str];
+
+struct Spec {
+    name: &'static str,
+    flags: Names,
+    /// Options that take a value.
+    options: Names,
+    args: &'static [Arg],
+    /// Whether the last argument repeats.
+    repeats: bool,
+}
+
+const fn spec(
+    name: &'static str,
+    flags: &'static [&'static str],
+    options: &'static [&'static str],
+    args: &'static [Arg],
+) -> Spec {
+    Spec {
+        name,
+        flags,
+        options,
+        args,
+        repeats: false,
+    }
+}
+
+const GLOBAL_FLAGS: &[&str] = &["-q", "--quiet", "-h", "--help"];
+const GLOBAL_OPTIONS: &[&str] = &["-c", "--config"];
+const OUTPUT: &[&str] = &["-o", "--output"];
+
+/// The commands and options `parse` takes; the hidden `__complete` aside.
+const COMMANDS: &[Spec] = &[
+    spec("put", &[], &["--score"], &[Arg::Tag, Arg::Blob, Arg::File]),
+    spec("get", &[], &[], &[Arg::Tag, Arg::Blob, Arg::File]),
+    spec(
+        "ls",
+        &["-l"],
+        &["-d", "-o", "--output"],
+        &[Arg::Tag, Arg::Blob],
+    ),
+    Spec {
+        repeats: true,
+        ..spec("rm", &["-r", "--dry-run"], &[], &[Arg::Tag, Arg::Blob])
+    },
+    spec("stat", &[], OUTPUT, &[Arg::Tag, Arg::Blob]),
+    spec(
+        "query",
+        &[],
+        &["-n", "-o", "--output"],
+        &[Arg::Other, Arg::Other],
+    ),
+    spec("targets", &[], OUTPUT, &[]),
+    spec("gc", &["--dry-run"], OUTPUT, &[]),
+    spec(
+        "rebalance",
+        &["--dry-run"],
+        &["--score", "-o", "--output"],
+        &[Arg::Tag],
+    ),
+    spec("publish", &[], &[], &[Arg::Tag, Arg::Blob, Arg::File]),
+    spec("close", &[], &[], &[Arg::Tag]),
+    spec("subscribe", &[], &["--timeout"], &[Arg::Tag]),
+    spec(
+        "bench",
+        &[],
+        &[
+            "--blob-size",
+            "--count",
+            "--threads",
+            "--pattern",
+            "-o",
+            "--output",
+        ],
+        &[],
+    ),
+    spec("top", &[], &["-i", "--interval", "-n", "--tags"], &[]),
+    spec("warmup", &[], &["--score"], &[Arg::File]),
+    spec(
+        "serve",
+        &["--notebook", "--rest", "--flight", "--read-only"],
+        &["--addr", "--token"],
+        &[],
+    ),
+    spec("doctor", &[], &[], &[]),
+    spec("completions", &[], &[], &[Arg::Choice(SHELLS)]),
+    spec("help", &[], &[], &[]),
+];
+
+/// Whether `name` is a command.
+pub fn is_command(name: &str) -> bool {
+    COMMANDS.iter().any(|c| c.name == name)
+}
+
+/// Whether command `name` takes option `opt`, as a flag or with a value.
+pub fn accepts(name: &str, opt: &str) -> bool {
+    COMMANDS
+        .iter()
+        .find(|c| c.name == name)
+        .is_some_and(|c| c.flags.contains(&opt) || c.options.contains(&opt))
+}
+
+/// Each command of `COMMANDS` with its flags and its options that take a
+/// value.
+#[cfg(test)]
+pub fn commands() -> impl Iterator<Item = (&'static str, Names, Names)> {
+    COMMANDS.iter().map(|c| (c.name, c.flags, c.options))
+}
+
+/// The fixed values of option `opt`, if it has any.
+fn values(opt: &str) -> Option<&'static [&'static str]> {
+    match opt {
+        "-o" | "--output" => Some(&["json", "table", "csv"]),
+        "--pattern" => Some(&["seq", "rand"]),
+        _ => None,
+    }
+}
+
+/// What the word being completed should be.
+#[derive(Debug, PartialEq)]
+enum Want {
+    Words(Vec<&'static str>),
+    Files,
+    Tags,
+    Blobs { tag: String },
+}
+
+/// `words` from `clio`'s first argument to the one being completed, and the
+/// `--config` among them.
+fn want(words: &[String]) -> (Want, String) {
+    let (current, before) = match words.split_last() {
+        Some((current, before)) => (current.as_str(), before),
+        None => ("", &[][..]),
+    };
+    let mut config = String::new();
+    let mut it = before.iter().map(String::as_str);
+    let mut command = None;
+    // The value of an option is the word being completed.
+    let mut pending = None;
+    while let Some(word) = it.next() {
+        if GLOBAL_OPTIONS.contains(&word) {
+            match it.next() {
+                Some(path) => config = path.to_string(),
+                None => pending = Some(word),
+            }
+        } else if !GLOBAL_FLAGS.contains(&word) {
+            command = COMMANDS.iter().find(|c| c.name == word);
+            if command.is_none() {
+                return (Want::Words(Vec::new()), config);
+            }
+            break;
+        }
+    }
+    let Some(command) = command else {
+        let want = match pending {
+            Some(_) => Want::Files,
+            None if current.starts_with('-') => {
+                Want::Words([GLOBAL_FLAGS, GLOBAL_OPTIONS].concat())
+            }
+            None => Want::Words(COMMANDS.iter().map(|c| c.name).collect()),
+        };
+        return (want, config);
+    };
+
+    let mut args = Vec::new();
+    while let Some(word) = it.next() {
+        if command.options.contains(&word) {
+            if it.next().is_none() {
+                pending = Some(word);
+            }
+        } else if word == "-" || !word.starts_with('-') {
+            args.push(word);
+        }
+    }
+    if let Some(opt) = pending {
+        return (
+            Want::Words(values(opt).unwrap_or_default().to_vec()),
+            config,
+        );
+    }
+    if current.starts_with('-') {
+        return (
+            Want::Words([command.flags, command.options].concat()),
+            config,
+        );
+    }
+    let arg = match command.args.get(args.len()) {
+        Some(arg) => Some(*arg),
+        None if command.repeats => command.args.last().copied(),
+        None => None,
+    };
+    let want = match arg {
+        Some(Arg::Tag) => Want::Tags,
+        Some(Arg::Blob) => Want::Blobs {
+            tag: args[0].to_string(),
+        },
+        Some(Arg::File) => Want::Files,
+        Some(Arg::Choice(choices)) => Want::Words(choices.to_vec()),
+        Some(Arg::Other) | None => Want::Words(Vec::new()),
+    };
+    (want, config)
+}
+
+/// Regex matching names that start with `prefix`.
+fn starting_with(prefix: &str) -> String {
+    let mut re = String::from("^");
+    for c in prefix.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            re.push('\\');
+        }
+        re.push(c);
+    }
+    re
+}
+
+/// The candidates for the last of `words`, a line each.
+pub fn candidates(words: &[String]) -> Vec<String> {
+    let current = words.last().map_or("", String::as_str);
+    let (want, config) = want(words);
+    let names = match want {
+        Want::Words(words) => words.into_iter().map(String::from).collect(),
+        Want::Files => return vec![FILES.to_string()],
+        Want::Tags | Want::Blobs { .. } if init(&config).is_err() => Vec::new(),
+        Want::Tags => Client::tag_query(&starting_with(current), MAX_CANDIDATES),
+        Want::Blobs { tag } => {
+            if !Client::tag_exists(&tag) {
+                return Vec::new();
+            }
+            match Tag::new(&tag).list(current, "/") {
+                Ok(listing) => listing
+                    .common_prefixes
+                    .into_iter()
+                    .chain(listing.blobs)
+                    .take(MAX_CANDIDATES as usize)
+                    .collect(),
+                Err(_) => Vec::new(),
+            }
+        }
+    };
+    names
+        .into_iter()
+        .filter(|name: &String| name.starts_with(current))
+        .collect()
+}
+
+const BASH: &str = r#"_clio() {
+    local IFS=$'\n'
+    local out
+    out=$(clio __complete "${COMP_WORDS[@]:1:COMP_CWORD}" 2>/dev/null)
+    if [[ $out == @files ]]; then
+        COMPREPLY=($(compgen -f -- "${COMP_WORDS[COMP_CWORD]}"))
+        return
+    fi
+    COMPREPLY=($out)
+    # A directory of blobs is completed further, not ended with a space.
+    if [[ ${#COMPREPLY[@]} -eq 1 && ${COMPREPLY[0]} == */ ]]; then
+        compopt -o nospace
+    fi
+}
+complete -F _clio clio
+"#;
+
+const ZSH: &str = r#"#compdef clio
+_clio() {
+    local -a out
+    out=(${(f)"$(clio __complete "${(@)words[2,CURRENT]}" 2>/dev/null)"})
+    if [[ $out[1] == @files ]]; then
+        _files
+        return
+    fi
+    compadd -S '' -- ${(M)out:#*/}
+    compadd -- ${out:#*/}
+}
+compdef _clio clio
+"#;
+
+const FISH: &str = r#"function __clio_complete
+    set -l out (clio __complete (commandline -opc)[2..-1] (commandline -ct) 2>/dev/null)
+    if test "$out" = @files
+        __fish_complete_path (commandline -ct)
+    else
+        printf '%s\n' $out
+    end
+end
+complete -c clio -f -a '(__clio_complete)'
+"#;
+
+/// The completion script for `shell`, one of `SHELLS`.
+pub fn script(shell: &str) -> Option<&'static str> {
+    match shell {
+        "bash" => Some(BASH),
+        "zsh" => Some(ZSH),
+        "fish" => Some(FISH),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn want_of(line: &str) -> (Want, String) {
+        let mut words: Vec<String> = line.split_whitespace().map(String::from).collect();
+        if line.is_empty() || line.ends_with(' ') {
+            words.push(String::new());
+        }
+        want(&words)
+    }
+
+    #[test]
+    fn test_want() {
+        let (Want::Words(commands), _) = want_of("") else {
+            panic!("commands expected");
+        };
+        assert!(commands.contains(&"put") && commands.contains(&"completions"));
+        assert_eq!(want_of("-c ").0, Want::Files);
+        assert_eq!(want_of("-c cte.yaml -q st").1, "cte.yaml");
+        assert_eq!(want_of("stat ").0, Want::Tags);
+        assert_eq!(
+            want_of("-c cte.yaml stat ckpt ru"),
+            (Want::Blobs { tag: "ckpt".into() }, "cte.yaml".into())
+        );
+        assert_eq!(want_of("rm ckpt a b").0, Want::Blobs { tag: "ckpt".into() });
+        assert_eq!(want_of("rm -r ").0, Want::Tags);
+        assert_eq!(want_of("put t b ").0, Want::Files);
+        assert_eq!(want_of("put t b f ").0, Want::Words(Vec::new()));
+        assert_eq!(
+            want_of("ls -o ").0,
+            Want::Words(vec!["json", "table", "csv"])
+        );
+        assert_eq!(
+            want_of("bench --count 3 --pattern r").0,
+            Want::Words(vec!["seq", "rand"])
+        );
+        assert_eq!(
+            want_of("gc --").0,
+            Want::Words(vec!["--dry-run", "-o", "--output"])
+        );
+        assert_eq!(
+            want_of("ls -d / ").0,
+            Want::Tags,
+            "an option's value isn't an argument"
+        );
+        assert_eq!(want_of("completions ").0, Want::Words(SHELLS.to_vec()));
+        assert_eq!(want_of("frobnicate ").0, Want::Words(Vec::new()));
+        assert_eq!(starting_with("run.1/"), "^run\\.1/");
+        assert!(SHELLS.iter().all(|shell| script(shell).is_some()));
+    }
+}
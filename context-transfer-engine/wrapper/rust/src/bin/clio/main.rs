@@ -0,0 +1,1322 @@
+//! `clio`: inspect and manage the data in a CTE deployment from the shell.
+//!
+//! Connects as a client with the same configuration `wrp_cte_rs::init` uses
+//! (`--config`, or the runtime's environment when omitted). Built with the
+//! `cli` feature. See `USAGE` for the commands and exit statuses, `output`
+//! for the formats of `--output`, and `complete` for shell completion.
+
+mod bench;
+mod complete;
+mod output;
+mod top;
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use wrp_cte_rs::{
+    init, set_placement_policy, BlobDescriptor, BulkOptions, BulkReport, Channel, ChannelOptions,
+    CheckStatus, Client, Compression, CteError, GetOptions, PutOptions, Tag, WarmupManifest,
+    WarmupOptions,
+};
+
+use bench::{BenchOptions, Pattern};
+use output::{Format, Report, Value};
+use top::TopOptions;
+
+/// Where `serve --notebook` listens unless `--addr` is given.
+const DEFAULT_NOTEBOOK_ADDR: &str = "127.0.0.1:8765";
+/// Where `serve --rest` listens unless `--addr` is given.
+const DEFAULT_REST_ADDR: &str = "127.0.0.1:8766";
+/// Where `serve --flight` listens unless `--addr` is given.
+const DEFAULT_FLIGHT_ADDR: &str = "127.0.0.1:8815";
+
+const USAGE: &str = "\
+usage: clio [--config PATH] [--quiet] <command> [args]
+
+commands:
+  put <tag> <blob> [FILE] [--score S]   write FILE (or stdin) as a blob
+  get <tag> <blob> [FILE]               read a blob to FILE (or stdout)
+  ls [-l] [TAG [PREFIX]] [-d DELIM]     list tags, or the blobs of TAG (under
+                                        PREFIX, rolled up at DELIM like
+                                        directories)
+  rm <tag> <blob>... [--dry-run]       delete blobs
+  rm -r <tag> [--dry-run]               delete a tag and its blobs
+  stat <tag> <blob>                     show a blob's size, score and metadata
+  query <tag-regex> [blob-regex] [-n N] list matching tags, or tag/blob pairs
+  targets                               list storage targets and their usage
+  gc [--dry-run]                        reclaim expired blobs, dead sessions'
+                                        tags, orphaned metadata and old trash,
+                                        listing what was (or would be) removed
+  rebalance <tag> --score S [--dry-run] move the tag's blobs to score S (and
+                                        the tier it maps to), listing what was
+                                        (or would be) moved
+  publish <tag> <blob> [FILE]           write FILE (or stdin) as a blob and mark
+                                        it ready on the tag's channel
+  close <tag>                           mark the tag's channel finished
+  subscribe <tag> [--timeout SECS]      print each blob marked ready, as it is,
+                                        until the channel is closed
+  bench [--blob-size N] [--count N] [--threads N] [--pattern seq|rand]
+                                        measure put/get throughput and latency,
+                                        printed as JSON
+  top [-i SECS] [-n N] [--tags N]       refresh a view of target usage and
+                                        bandwidth, tag sizes and queue depths
+  warmup <manifest> [--score S]         promote a job's working set (lines of
+                                        <tag> or <tag><TAB><blob-glob>) to the
+                                        hot tiers and report when it's resident
+  serve --notebook [--addr HOST:PORT] [--token T]
+                                        serve the notebook API (tags, blob
+                                        previews, tier occupancy) until
+                                        interrupted; needs the `notebook`
+                                        feature
+  serve --rest [--addr HOST:PORT] [--token T] [--read-only]
+                                        serve the REST API (tags, ranged blob
+                                        reads and writes, queries, targets,
+                                        an event WebSocket) until interrupted;
+                                        needs the `http` feature
+  serve --flight [--addr HOST:PORT] [--token T]
+                                        stream blobs, and Arrow blobs as record
+                                        batches, over Arrow Flight until
+                                        interrupted; needs the `flight` feature
+  doctor                                check the configuration against this
+                                        host (targets, shared memory, libraries,
+                                        port) without starting a client
+  completions <bash|zsh|fish>           print a completion script for the shell,
+                                        which completes tag and blob names too
+
+ls, stat, query, targets, gc, rebalance and bench take -o, --output
+json|table|csv to print their results with stable field names for scripts.
+rm --dry-run lists the blobs it would delete, as gc --dry-run does.
+
+-q, --quiet prints nothing but usage errors (not for top or serve), so the
+exit status is the answer: `clio -q stat t b` tests that a blob exists.
+
+exit status:
+  0  done
+  1  failed (the runtime, a file or the network)
+  2  bad usage
+  3  a named tag or blob doesn't exist (get, ls, rm, stat, close, ...)
+  4  done, but not all of it: warmup left blobs cold or missing, or doctor
+     found a failing check
+";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Put {
+        tag: String,
+        blob: String,
+        file: Option<String>,
+        score: Option<f32>,
+    },
+    Get {
+        tag: String,
+        blob: String,
+        file: Option<String>,
+    },
+    Ls {
+        tag: Option<String>,
+        prefix: String,
+        delimiter: String,
+        long: bool,
+    },
+    Rm {
+        tag: String,
+        blobs: Vec<String>,
+        dry_run: bool,
+    },
+    RmTag {
+        tag: String,
+        dry_run: bool,
+    },
+    Stat {
+        tag: String,
+        blob: String,
+    },
+    Query {
+        tag_re: String,
+        blob_re: Option<String>,
+        max: u32,
+    },
+    Targets,
+    Gc {
+        dry_run: bool,
+    },
+    Rebalance {
+        tag: String,
+        score: f32,
+        dry_run: bool,
+    },
+    Publish {
+        tag: String,
+        blob: String,
+        file: Option<String>,
+    },
+    Close {
+        tag: String,
+    },
+    Subscribe {
+        tag: String,
+        timeout: Option<Duration>,
+    },
+    Bench(BenchOptions),
+    Top(TopOptions),
+    Warmup {
+        manifest: String,
+        score: Option<f32>,
+    },
+    Serve {
+        addr: String,
+        token: Option<String>,
+    },
+    ServeRest {
+        addr: String,
+        token: Option<String>,
+        read_only: bool,
+    },
+    ServeFlight {
+        addr: String,
+        token: Option<String>,
+    },
+    /// Carries `--config`, as it runs without a client.
+    Doctor {
+        config: String,
+    },
+    Completions {
+        shell: String,
+    },
+    /// `__complete`: the words to complete, as the completion scripts pass
+    /// them.
+    Complete {
+        words: Vec<String>,
+    },
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    config: String,
+    command: Command,
+    /// `--output`; `None` for the command's usual output.
+    output: Option<Format>,
+    quiet: bool,
+}
+
+/// Failure of a command, by the exit status it gives (see `USAGE`).
+enum Failure {
+    Usage(String),
+    Cte(CteError),
+    Io(io::Error),
+    Other(String),
+    NotFound(String),
+    Incomplete(String),
+}
+
+impl From<CteError> for Failure {
+    fn from(e: CteError) -> Self {
+        Failure::Cte(e)
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(e: io::Error) -> Self {
+        Failure::Io(e)
+    }
+}
+
+fn usage(msg: impl Into<String>) -> Failure {
+    Failure::Usage(msg.into())
+}
+
+/// The value following option `opt`.
+fn value_of<'a>(opt: &str, it: &mut impl Iterator<Item = &'a String>) -> Result<&'a str, Failure> {
+    it.next()
+        .map(String::as_str)
+        .ok_or_else(|| usage(format!("{} needs a value", opt)))
+}
+
+fn parse(args: &[String]) -> Result<Args, Failure> {
+    let (mut config, mut quiet) = (String::new(), false);
+    let mut it = args.iter();
+    let name = loop {
+        match it.next().map(String::as_str) {
+            Some("-c" | "--config") => config = value_of("--config", &mut it)?.to_string(),
+            Some("-q" | "--quiet") => quiet = true,
+            Some("-h" | "--help") | None => {
+                return Ok(Args {
+                    config,
+                    command: Command::Help,
+                    output: None,
+                    quiet,
+                })
+            }
+            // The completion scripts pass the words as typed.
+            Some("__complete") => {
+                return Ok(Args {
+                    config,
+                    command: Command::Complete {
+                        words: it.cloned().collect(),
+                    },
+                    output: None,
+                    quiet,
+                })
+            }
+            Some(name) => break name,
+        }
+    };
+    if !complete::is_command(name) {
+        return Err(usage(format!("unknown command '{}'", name)));
+    }
+
+    let (mut pos, mut long, mut recursive) = (Vec::new(), false, false);
+    let (mut score, mut max, mut delimiter) = (None, 0, String::new());
+    let mut bench = BenchOptions::default();
+    let mut top = TopOptions::default();
+    let mut timeout = None;
+    let (mut notebook, mut addr, mut token) = (false, None, None);
+    let (mut rest, mut flight, mut read_only) = (false, false, false);
+    let (mut output, mut dry_run) = (None, false);
+    let positive = |opt: &str, v: &str| match v.parse::<u64>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(usage(format!(
+            "{} must be a positive number, not '{}'",
+            opt, v
+        ))),
+    };
+    while let Some(arg) = it.next() {
+        match arg.as_str() {
+            // A lone `-` is a positional (stdin or stdout).
+            opt if opt.starts_with('-') && opt != "-" && !complete::accepts(name, opt) => {
+                return Err(usage(format!("unknown option '{}' for {}", opt, name)))
+            }
+            "-l" if name == "ls" => long = true,
+            "-d" if name == "ls" => delimiter = value_of("-d", &mut it)?.to_string(),
+            "-r" if name == "rm" => recursive = true,
+            "-o" | "--output" => {
+                let v = value_of(arg, &mut it)?;
+                output = Some(Format::parse(v).ok_or_else(|| {
+                    usage(format!("bad output format '{}' (json, table or csv)", v))
+                })?);
+            }
+            "--dry-run" => dry_run = true,
+            "--score" => {
+                let v = value_of("--score", &mut it)?;
+                score = Some(v.parse().map_err(|_| usage(format!("bad score '{}'", v)))?);
+            }
+            "-n" if name == "query" => {
+                let v = value_of("-n", &mut it)?;
+                max = v.parse().map_err(|_| usage(format!("bad count '{}'", v)))?;
+            }
+            "--blob-size" if name == "bench" => {
+                let v = value_of(arg, &mut it)?;
+                bench.blob_size = match bench::parse_size(v) {
+                    Some(n) if n > 0 => n,
+                    _ => return Err(usage(format!("bad blob size '{}'", v))),
+                };
+            }
+            "--count" if name == "bench" => bench.count = positive(arg, value_of(arg, &mut it)?)?,
+            "--threads" if name == "bench" => {
+                bench.threads = positive(arg, value_of(arg, &mut it)?)?
+            }
+            "--pattern" if name == "bench" => {
+                let v = value_of(arg, &mut it)?;
+                bench.pattern =
+                    Pattern::parse(v).ok_or_else(|| usage(format!("bad pattern '{}'", v)))?;
+            }
+            "-i" | "--interval" if name == "top" => {
+                let v = value_of(arg, &mut it)?;
+                top.interval = match v.parse::<f64>() {
+                    Ok(secs) if secs > 0.0 && secs.is_finite() => Duration::from_secs_f64(secs),
+                    _ => return Err(usage(format!("bad interval '{}'", v))),
+                };
+            }
+            "--timeout" if name == "subscribe" => {
+                let v = value_of(arg, &mut it)?;
+                timeout = match v.parse::<f64>() {
+                    Ok(secs) if secs >= 0.0 && secs.is_finite() => {
+                        Some(Duration::from_secs_f64(secs))
+                    }
+                    _ => return Err(usage(format!("bad timeout '{}'", v))),
+                };
+            }
+            "--notebook" if name == "serve" => notebook = true,
+            "--rest" if name == "serve" => rest = true,
+            "--flight" if name == "serve" => flight = true,
+            "--read-only" if name == "serve" => read_only = true,
+            "--addr" if name == "serve" => addr = Some(value_of(arg, &mut it)?.to_string()),
+            "--token" if name == "serve" => token = Some(value_of(arg, &mut it)?.to_string()),
+            "-n" if name == "top" => top.iterations = positive(arg, value_of(arg, &mut it)?)?,
+            "--tags" if name == "top" => {
+                let v = value_of(arg, &mut it)?;
+                top.tags = v.parse().map_err(|_| usage(format!("bad count '{}'", v)))?;
+            }
+            opt if opt.starts_with('-') && opt != "-" => {
+                unreachable!("{} takes {} but doesn't parse it", name, opt)
+            }
+            _ => pos.push(arg.clone()),
+        }
+    }
+    let count = |min: usize, max: usize| {
+        if pos.len() < min || pos.len() > max {
+            Err(usage(format!("wrong number of arguments for {}", name)))
+        } else {
+            Ok(())
+        }
+    };
+    let stdio = |file: Option<&String>| file.filter(|f| *f != "-").cloned();
+
+    let command = match name {
+        "put" => {
+            count(2, 3)?;
+            Command::Put {
+                tag: pos[0].clone(),
+                blob: pos[1].clone(),
+                file: stdio(pos.get(2)),
+                score,
+            }
+        }
+        "get" => {
+            count(2, 3)?;
+            Command::Get {
+                tag: pos[0].clone(),
+                blob: pos[1].clone(),
+                file: stdio(pos.get(2)),
+            }
+        }
+        "ls" => {
+            count(0, 2)?;
+            if pos.is_empty() && !delimiter.is_empty() {
+                return Err(usage("ls -d needs a tag"));
+            }
+            Command::Ls {
+                tag: pos.first().cloned(),
+                prefix: pos.get(1).cloned().unwrap_or_default(),
+                delimiter,
+                long,
+            }
+        }
+        "rm" if recursive => {
+            count(1, 1)?;
+            Command::RmTag {
+                tag: pos[0].clone(),
+                dry_run,
+            }
+        }
+        "rm" => {
+            count(2, usize::MAX)?;
+            Command::Rm {
+                tag: pos[0].clone(),
+                blobs: pos[1..].to_vec(),
+                dry_run,
+            }
+        }
+        "stat" => {
+            count(2, 2)?;
+            Command::Stat {
+                tag: pos[0].clone(),
+                blob: pos[1].clone(),
+            }
+        }
+        "query" => {
+            count(1, 2)?;
+            Command::Query {
+                tag_re: pos[0].clone(),
+                blob_re: pos.get(1).cloned(),
+                max,
+            }
+        }
+        "targets" => {
+            count(0, 0)?;
+            Command::Targets
+        }
+        "gc" => {
+            count(0, 0)?;
+            Command::Gc { dry_run }
+        }
+        "rebalance" => {
+            count(1, 1)?;
+            Command::Rebalance {
+                tag: pos[0].clone(),
+                score: score.ok_or_else(|| usage("rebalance needs --score"))?,
+                dry_run,
+            }
+        }
+        "publish" => {
+            count(2, 3)?;
+            Command::Publish {
+                tag: pos[0].clone(),
+                blob: pos[1].clone(),
+                file: stdio(pos.get(2)),
+            }
+        }
+        "close" => {
+            count(1, 1)?;
+            Command::Close {
+                tag: pos[0].clone(),
+            }
+        }
+        "subscribe" => {
+            count(1, 1)?;
+            Command::Subscribe {
+                tag: pos[0].clone(),
+                timeout,
+            }
+        }
+        "bench" => {
+            count(0, 0)?;
+            Command::Bench(bench)
+        }
+        "top" => {
+            count(0, 0)?;
+            Command::Top(top)
+        }
+        "warmup" => {
+            count(1, 1)?;
+            Command::Warmup {
+                manifest: pos[0].clone(),
+                score,
+            }
+        }
+        "serve" => {
+            count(0, 0)?;
+            match (notebook, rest, flight) {
+                (true, false, false) if !read_only => Command::Serve {
+                    addr: addr.unwrap_or_else(|| DEFAULT_NOTEBOOK_ADDR.to_string()),
+                    token,
+                },
+                (false, true, false) => Command::ServeRest {
+                    addr: addr.unwrap_or_else(|| DEFAULT_REST_ADDR.to_string()),
+                    token,
+                    read_only,
+                },
+                (false, false, true) if !read_only => Command::ServeFlight {
+                    addr: addr.unwrap_or_else(|| DEFAULT_FLIGHT_ADDR.to_string()),
+                    token,
+                },
+                (true, false, false) | (false, false, true) => {
+                    return Err(usage("--read-only is for serve --rest"))
+                }
+                _ => return Err(usage("serve needs one of --notebook, --rest and --flight")),
+            }
+        }
+        "doctor" => {
+            count(0, 0)?;
+            Command::Doctor {
+                config: config.clone(),
+            }
+        }
+        "completions" => {
+            count(1, 1)?;
+            if complete::script(&pos[0]).is_none() {
+                return Err(usage(format!(
+                    "no completions for '{}' ({})",
+                    pos[0],
+                    complete::SHELLS.join(", ")
+                )));
+            }
+            Command::Completions {
+                shell: pos[0].clone(),
+            }
+        }
+        "help" => Command::Help,
+        other => unreachable!("command '{}' isn't parsed", other),
+    };
+    if quiet
+        && matches!(
+            command,
+            Command::Top(_)
+                | Command::Serve { .. }
+                | Command::ServeRest { .. }
+                | Command::ServeFlight { .. }
+        )
+    {
+        return Err(usage(format!("{} can't be --quiet", name)));
+    }
+    Ok(Args {
+        config,
+        command,
+        output,
+        quiet,
+    })
+}
+
+/// Open `name`, failing rather than creating it if it doesn't exist.
+fn existing_tag(name: &str) -> Result<Tag, Failure> {
+    if !Client::tag_exists(name) {
+        return Err(Failure::NotFound(format!("no such tag '{}'", name)));
+    }
+    Ok(Tag::new(name))
+}
+
+/// Columns of `ls TAG --output`, and with `-l`.
+const LS_COLUMNS: &[&str] = &["name", "kind"];
+const LS_LONG_COLUMNS: &[&str] = &["name", "kind", "size", "score", "generation"];
+const STAT_COLUMNS: &[&str] = &[
+    "tag",
+    "blob",
+    "size",
+    "stored_size",
+    "score",
+    "generation",
+    "compression",
+    "checksum",
+    "pinned",
+    "expires_ms",
+    "written_ms",
+];
+const TARGET_COLUMNS: &[&str] = &[
+    "target",
+    "score",
+    "remaining_bytes",
+    "bytes_read",
+    "bytes_written",
+];
+
+/// Milliseconds since the Unix epoch.
+fn unix_ms(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// `compression` as `stat --output` shows it: `none`, `zstd:<level>` or `lz4`.
+fn compression_name(compression: Compression) -> String {
+    match compression {
+        Compression::Zstd(level) => format!("zstd:{}", level),
+        Compression::Lz4 => "lz4".into(),
+        Compression::None => "none".into(),
+    }
+}
+
+/// The blobs of `report` and their total, which were (or with `dry_run` would
+/// be) `done`.
+fn write_report(
+    out: &mut impl Write,
+    report: &BulkReport,
+    output: Option<Format>,
+    done: &str,
+) -> Result<(), Failure> {
+    if let Some(format) = output {
+        let mut rows = Report::new(&["tag", "blob", "size"]);
+        for b in &report.blobs {
+            rows.push(vec![
+                b.tag.as_str().into(),
+                b.blob.as_str().into(),
+                b.size.into(),
+            ]);
+        }
+        return Ok(rows.write(out, format)?);
+    }
+    for b in &report.blobs {
+        writeln!(out, "{}\t{}\t{}", b.tag, b.blob, b.size)?;
+    }
+    let would = if report.dry_run { "would be " } else { "" };
+    writeln!(
+        out,
+        "{} blobs, {} bytes {}{}",
+        report.blobs.len(),
+        report.bytes,
+        would,
+        done
+    )?;
+    Ok(())
+}
+
+fn run(command: Command, output: Option<Format>, quiet: bool) -> Result<(), Failure> {
+    let mut out: Box<dyn Write> = if quiet {
+        Box::new(io::sink())
+    } else {
+        Box::new(io::stdout().lock())
+    };
+    match command {
+        Command::Put {
+            tag,
+            blob,
+            file,
+            score,
+        } => {
+            let data = match file {
+                Some(path) => fs::read(path)?,
+                None => {
+                    let mut buf = Vec::new();
+                    io::stdin().lock().read_to_end(&mut buf)?;
+                    buf
+                }
+            };
+            let options = PutOptions {
+                score,
+                ..Default::default()
+            };
+            Tag::new(&tag).put(&blob, &data, &options)?;
+        }
+        Command::Get { tag, blob, file } => {
+            let data = existing_tag(&tag)?.get(&blob, &GetOptions::default())?;
+            match file {
+                Some(path) => fs::write(path, data)?,
+                None => out.write_all(&data)?,
+            }
+        }
+        Command::Ls { tag: None, .. } => {
+            let tags = Client::tag_query(".*", 0);
+            match output {
+                Some(format) => {
+                    let mut report = Report::new(&["tag"]);
+                    for tag in tags {
+                        report.push(vec![tag.into()]);
+                    }
+                    report.write(&mut out, format)?;
+                }
+                None => {
+                    for tag in tags {
+                        writeln!(out, "{}", tag)?;
+                    }
+                }
+            }
+        }
+        Command::Ls {
+            tag: Some(tag),
+            prefix,
+            delimiter,
+            long,
+        } => {
+            let tag = existing_tag(&tag)?;
+            let listing = tag.list(&prefix, &delimiter)?;
+            let blobs = listing.blobs;
+            let stats = if long {
+                let names: Vec<&str> = blobs.iter().map(String::as_str).collect();
+                tag.stat_blobs(&names)?
+            } else {
+                Vec::new()
+            };
+            if let Some(format) = output {
+                let columns = if long { LS_LONG_COLUMNS } else { LS_COLUMNS };
+                let mut report = Report::new(columns);
+                for common in &listing.common_prefixes {
+                    let mut row = vec![common.as_str().into(), "prefix".into()];
+                    row.resize(columns.len(), Value::Null);
+                    report.push(row);
+                }
+                for (i, blob) in blobs.iter().enumerate() {
+                    let mut row = vec![blob.as_str().into(), "blob".into()];
+                    if long {
+                        // Deleted since it was listed.
+                        let Some(stat) = &stats[i] else { continue };
+                        row.extend([stat.size.into(), stat.score.into(), stat.generation.into()]);
+                    }
+                    report.push(row);
+                }
+                report.write(&mut out, format)?;
+            } else if !long {
+                for name in listing.common_prefixes.iter().chain(&blobs) {
+                    writeln!(out, "{}", name)?;
+                }
+            } else {
+                writeln!(out, "size\tscore\tgeneration\tblob")?;
+                for common in &listing.common_prefixes {
+                    writeln!(out, "-\t-\t-\t{}", common)?;
+                }
+                for (blob, stat) in blobs.iter().zip(&stats) {
+                    if let Some(stat) = stat {
+                        writeln!(
+                            out,
+                            "{}\t{:.3}\t{}\t{}",
+                            stat.size, stat.score, stat.generation, blob
+                        )?;
+                    }
+                }
+            }
+        }
+        Command::Rm {
+            tag,
+            blobs,
+            dry_run,
+        } => {
+            let tag = existing_tag(&tag)?;
+            let names: Vec<&str> = blobs.iter().map(String::as_str).collect();
+            let report = tag.del_blobs(&names, &BulkOptions { dry_run });
+            if dry_run {
+                write_report(&mut out, &report, None, "deleted")?;
+            }
+            let missing: Vec<&str> = names
+                .into_iter()
+                .filter(|b| !report.blobs.iter().any(|a| a.blob == *b))
+                .collect();
+            if !missing.is_empty() {
+                return Err(Failure::NotFound(format!(
+                    "not deleted: {}",
+                    missing.join(", ")
+                )));
+            }
+        }
+        Command::RmTag { tag, dry_run } => {
+            existing_tag(&tag)?;
+            let Some(report) = Client::del_tag_report(&tag, &BulkOptions { dry_run }) else {
+                return Err(Failure::Other(format!("failed to delete tag '{}'", tag)));
+            };
+            if dry_run {
+                write_report(&mut out, &report, None, "deleted")?;
+            }
+        }
+        Command::Stat { tag, blob } => {
+            let stat = existing_tag(&tag)?
+                .stat_blob(&blob)?
+                .ok_or_else(|| CteError::NotFound { blob: blob.clone() })?;
+            if let Some(format) = output {
+                let row = vec![
+                    tag.into(),
+                    blob.into(),
+                    stat.size.into(),
+                    stat.stored_size.into(),
+                    stat.score.into(),
+                    stat.generation.into(),
+                    compression_name(stat.compression).into(),
+                    stat.checksum.as_ref().map(|c| c.to_string()).into(),
+                    stat.pinned.into(),
+                    stat.expires.map(unix_ms).into(),
+                    stat.written.map(unix_ms).into(),
+                ];
+                Report::one(STAT_COLUMNS, row).write(&mut out, format)?;
+            } else {
+                writeln!(out, "size:        {}", stat.size)?;
+                writeln!(out, "stored size: {}", stat.stored_size)?;
+                writeln!(out, "score:       {:.3}", stat.score)?;
+                writeln!(out, "generation:  {}", stat.generation)?;
+                writeln!(out, "compression: {:?}", stat.compression)?;
+                if let Some(sum) = &stat.checksum {
+                    writeln!(out, "checksum:    {}", sum)?;
+                }
+                if let Some(score) = stat.pinned {
+                    writeln!(out, "pinned at:   {:.3}", score)?;
+                }
+                if let Some(expires) = stat.expires {
+                    let left = expires
+                        .duration_since(std::time::SystemTime::now())
+                        .unwrap_or_default();
+                    writeln!(out, "expires in:  {}s", left.as_secs())?;
+                }
+            }
+        }
+        Command::Query {
+            tag_re,
+            blob_re: None,
+            max,
+        } => {
+            let tags = Client::tag_query(&tag_re, max);
+            match output {
+                Some(format) => {
+                    let mut report = Report::new(&["tag"]);
+                    for tag in tags {
+                        report.push(vec![tag.into()]);
+                    }
+                    report.write(&mut out, format)?;
+                }
+                None => {
+                    for tag in tags {
+                        writeln!(out, "{}", tag)?;
+                    }
+                }
+            }
+        }
+        Command::Query {
+            tag_re,
+            blob_re: Some(blob_re),
+            max,
+        } => {
+            let matches = Client::blob_query(&tag_re, &blob_re, max);
+            match output {
+                Some(format) => {
+                    let mut report = Report::new(&["tag", "blob"]);
+                    for (tag, blob) in matches {
+                        report.push(vec![tag.into(), blob.into()]);
+                    }
+                    report.write(&mut out, format)?;
+                }
+                None => {
+                    for (tag, blob) in matches {
+                        writeln!(out, "{}\t{}", tag, blob)?;
+                    }
+                }
+            }
+        }
+        Command::Targets => {
+            let targets = Client::list_targets();
+            match output {
+                Some(format) => {
+                    let mut report = Report::new(TARGET_COLUMNS);
+                    for t in targets {
+                        report.push(vec![
+                            t.name.into(),
+                            t.score.into(),
+                            t.remaining_space.into(),
+                            t.bytes_read.into(),
+                            t.bytes_written.into(),
+                        ]);
+                    }
+                    report.write(&mut out, format)?;
+                }
+                None => {
+                    writeln!(out, "score\tremaining\tread\twritten\ttarget")?;
+                    for t in targets {
+                        writeln!(
+                            out,
+                            "{:.3}\t{}\t{}\t{}\t{}",
+                            t.score, t.remaining_space, t.bytes_read, t.bytes_written, t.name
+                        )?;
+                    }
+                }
+            }
+        }
+        Command::Gc { dry_run } => {
+            let report = Client::gc(&BulkOptions { dry_run });
+            write_report(&mut out, &report, output, "reclaimed")?;
+        }
+        Command::Rebalance {
+            tag,
+            score,
+            dry_run,
+        } => {
+            let tag = existing_tag(&tag)?;
+            set_placement_policy(move |_: &BlobDescriptor| score);
+            let report = tag.rebalance(&BulkOptions { dry_run });
+            write_report(&mut out, &report, output, "moved")?;
+        }
+        Command::Publish { tag, blob, file } => {
+            let data = match file {
+                Some(path) => fs::read(path)?,
+                None => {
+                    let mut buf = Vec::new();
+                    io::stdin().lock().read_to_end(&mut buf)?;
+                    buf
+                }
+            };
+            Channel::open(&tag)?.publish(&blob, &data)?;
+        }
+        Command::Close { tag } => {
+            existing_tag(&tag)?;
+            Channel::open(&tag)?.close()?;
+        }
+        Command::Subscribe { tag, timeout } => {
+            let channel = Channel::open(&tag)?;
+            let options = ChannelOptions {
+                timeout,
+                ..Default::default()
+            };
+            for blob in channel.reader(options) {
+                // A line per blob as soon as it is ready, for `while read`.
+                writeln!(out, "{}", blob?)?;
+                out.flush()?;
+            }
+        }
+        Command::Bench(options) => {
+            let report = bench::run(&options);
+            match output {
+                Some(format @ (Format::Table | Format::Csv)) => {
+                    report.rows().write(&mut out, format)?
+                }
+                Some(Format::Json) | None => writeln!(out, "{}", report.json())?,
+            }
+        }
+        Command::Top(options) => {
+            drop(out);
+            return Ok(top::run(&options)?);
+        }
+        Command::Warmup { manifest, score } => {
+            let manifest = WarmupManifest::load(&manifest)?;
+            let mut options = WarmupOptions::default();
+            if let Some(score) = score {
+                options.score = score;
+            }
+            let report = Client::warmup(&manifest, &options)?;
+            for entry in &report.missing {
+                writeln!(out, "missing\t{}", entry)?;
+            }
+            for blob in &report.cold {
+                writeln!(out, "cold\t{}", blob)?;
+            }
+            writeln!(
+                out,
+                "{} blobs, {} bytes: {} promoted, {} cold; {} after {:.2}s",
+                report.blobs,
+                report.bytes,
+                report.promoted,
+                report.cold.len(),
+                if report.resident() {
+                    "resident"
+                } else {
+                    "gave up"
+                },
+                report.elapsed.as_secs_f64()
+            )?;
+            out.flush()?;
+            if !report.resident() || !report.missing.is_empty() {
+                return Err(Failure::Incomplete(
+                    "working set is not fully resident".into(),
+                ));
+            }
+        }
+        Command::Serve { addr, token } => {
+            drop(out);
+            return serve_notebook(&addr, token);
+        }
+        Command::ServeRest {
+            addr,
+            token,
+            read_only,
+        } => {
+            drop(out);
+            return serve_rest(&addr, token, read_only);
+        }
+        Command::ServeFlight { addr, token } => {
+            drop(out);
+            return serve_flight(&addr, token);
+        }
+        Command::Doctor { config } => {
+            let report = Client::preflight(&config);
+            for check in &report.checks {
+                writeln!(out, "{:<4}  {}: {}", check.status, check.name, check.detail)?;
+                if let Some(fix) = &check.fix {
+                    writeln!(out, "      fix: {}", fix)?;
+                }
+            }
+            out.flush()?;
+            if report.worst() == CheckStatus::Fail {
+                return Err(Failure::Incomplete("pre-flight checks failed".into()));
+            }
+        }
+        Command::Completions { shell } => {
+            out.write_all(complete::script(&shell).unwrap_or_default().as_bytes())?
+        }
+        Command::Complete { words } => {
+            for candidate in complete::candidates(&words) {
+                writeln!(out, "{}", candidate)?;
+            }
+        }
+        Command::Help => out.write_all(USAGE.as_bytes())?,
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Serve the notebook API until the process is killed, with a fresh token
+/// unless one was given.
+#[cfg(feature = "notebook")]
+fn serve_notebook(addr: &str, token: Option<String>) -> Result<(), Failure> {
+    let token = match token {
+        Some(token) => token,
+        None => {
+            let mut secret = [0u8; 16];
+            fs::File::open("/dev/urandom")?.read_exact(&mut secret)?;
+            secret.iter().map(|b| format!("{:02x}", b)).collect()
+        }
+    };
+    let options = wrp_cte_rs::NotebookOptions {
+        token: Some(token.clone()),
+        ..Default::default()
+    };
+    let server = Client::serve_notebook(addr, &options)?;
+    println!("http://{}/api/tags?token={}", server.local_addr(), token);
+    loop {
+        std::thread::park();
+    }
+}
+
+#[cfg(not(feature = "notebook"))]
+fn serve_notebook(_addr: &str, _token: Option<String>) -> Result<(), Failure> {
+    Err(Failure::Other(
+        "clio was built without the notebook feature".into(),
+    ))
+}
+
+/// Serve the REST API until the process is killed, printing its address.
+#[cfg(feature = "http")]
+fn serve_rest(addr: &str, token: Option<String>, read_only: bool) -> Result<(), Failure> {
+    let options = wrp_cte_rs::RestOptions {
+        token,
+        read_only,
+        ..Default::default()
+    };
+    let server = Client::serve_rest(addr, &options)?;
+    println!("http://{}/v1/tags", server.local_addr());
+    loop {
+        std::thread::park();
+    }
+}
+
+#[cfg(not(feature = "http"))]
+fn serve_rest(_addr: &str, _token: Option<String>, _read_only: bool) -> Result<(), Failure> {
+    Err(Failure::Other(
+        "clio was built without the http feature".into(),
+    ))
+}
+
+/// Serve blobs over Arrow Flight until the process is killed, printing the
+/// service's location.
+#[cfg(feature = "flight")]
+fn serve_flight(addr: &str, token: Option<String>) -> Result<(), Failure> {
+    let options = wrp_cte_rs::FlightOptions {
+        token,
+        ..Default::default()
+    };
+    let server = Client::serve_flight(addr, &options)?;
+    println!("grpc://{}", server.local_addr());
+    loop {
+        std::thread::park();
+    }
+}
+
+#[cfg(not(feature = "flight"))]
+fn serve_flight(_addr: &str, _token: Option<String>) -> Result<(), Failure> {
+    Err(Failure::Other(
+        "clio was built without the flight feature".into(),
+    ))
+}
+
+fn main() -> ExitCode {
+    let argv: Vec<String> = std::env::args().skip(1).collect();
+    let mut quiet = false;
+    let result = parse(&argv).and_then(|args| {
+        quiet = args.quiet;
+        // `__complete` starts a client only if it needs names.
+        let offline = matches!(
+            args.command,
+            Command::Help
+                | Command::Doctor { .. }
+                | Command::Completions { .. }
+                | Command::Complete { .. }
+        );
+        if !offline {
+            init(&args.config).map_err(Failure::Other)?;
+        }
+        run(args.command, args.output, args.quiet)
+    });
+    let (status, msg) = match result {
+        Ok(()) => return ExitCode::SUCCESS,
+        Err(Failure::Usage(msg)) => {
+            eprintln!("clio: {}\n\n{}", msg, USAGE);
+            return ExitCode::from(2);
+        }
+        // A closed pipe (`clio get ... | head`) isn't an error.
+        Err(Failure::Io(e)) if e.kind() == io::ErrorKind::BrokenPipe => return ExitCode::SUCCESS,
+        Err(Failure::Cte(e @ CteError::NotFound { .. })) => (3, e.to_string()),
+        Err(Failure::Cte(e)) => (1, e.to_string()),
+        Err(Failure::Io(e)) => (1, e.to_string()),
+        Err(Failure::Other(msg)) => (1, msg),
+        Err(Failure::NotFound(msg)) => (3, msg),
+        Err(Failure::Incomplete(msg)) => (4, msg),
+    };
+    if !quiet {
+        eprintln!("clio: {}", msg);
+    }
+    ExitCode::from(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Result<Args, Failure> {
+        let argv: Vec<String> = line.split_whitespace().map(String::from).collect();
+        parse(&argv)
+    }
+
+    #[test]
+    fn test_parse() {
+        let parsed = args("--config /etc/cte.yaml put t b - --score 0.5")
+            .ok()
+            .unwrap();
+        assert_eq!(parsed.config, "/etc/cte.yaml");
+        assert_eq!(
+            parsed.command,
+            Command::Put {
+                tag: "t".into(),
+                blob: "b".into(),
+                file: None,
+                score: Some(0.5),
+            }
+        );
+        assert_eq!(
+            args("rm -r t").ok().unwrap().command,
+            Command::RmTag {
+                tag: "t".into(),
+                dry_run: false,
+            }
+        );
+        assert_eq!(
+            args("rm t a b --dry-run").ok().unwrap().command,
+            Command::Rm {
+                tag: "t".into(),
+                blobs: vec!["a".into(), "b".into()],
+                dry_run: true,
+            }
+        );
+        assert_eq!(
+            args("rebalance t --score 0.2 --dry-run -o json")
+                .ok()
+                .unwrap()
+                .command,
+            Command::Rebalance {
+                tag: "t".into(),
+                score: 0.2,
+                dry_run: true,
+            }
+        );
+        assert!(matches!(args("rebalance t"), Err(Failure::Usage(_))));
+        assert_eq!(
+            args("query ^ckpt .* -n 10").ok().unwrap().command,
+            Command::Query {
+                tag_re: "^ckpt".into(),
+                blob_re: Some(".*".into()),
+                max: 10,
+            }
+        );
+        assert_eq!(
+            args("bench --blob-size 4K --threads 8 --pattern rand")
+                .ok()
+                .unwrap()
+                .command,
+            Command::Bench(BenchOptions {
+                blob_size: 4096,
+                threads: 8,
+                pattern: Pattern::Rand,
+                ..Default::default()
+            })
+        );
+        assert!(matches!(args("bench --count 0"), Err(Failure::Usage(_))));
+        assert_eq!(
+            args("top -i 0.5 -n 3").ok().unwrap().command,
+            Command::Top(TopOptions {
+                interval: Duration::from_millis(500),
+                iterations: 3,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            args("subscribe t --timeout 1.5").ok().unwrap().command,
+            Command::Subscribe {
+                tag: "t".into(),
+                timeout: Some(Duration::from_millis(1500)),
+            }
+        );
+        assert!(matches!(
+            args("publish t --timeout 1 b"),
+            Err(Failure::Usage(_))
+        ));
+        assert_eq!(
+            args("serve --notebook --token abc").ok().unwrap().command,
+            Command::Serve {
+                addr: DEFAULT_NOTEBOOK_ADDR.into(),
+                token: Some("abc".into()),
+            }
+        );
+        assert!(matches!(args("serve"), Err(Failure::Usage(_))));
+        assert_eq!(
+            args("serve --rest --read-only").ok().unwrap().command,
+            Command::ServeRest {
+                addr: DEFAULT_REST_ADDR.into(),
+                token: None,
+                read_only: true,
+            }
+        );
+        assert!(matches!(
+            args("serve --rest --notebook"),
+            Err(Failure::Usage(_))
+        ));
+        assert_eq!(
+            args("serve --flight --addr 0.0.0.0:9000 --token t")
+                .ok()
+                .unwrap()
+                .command,
+            Command::ServeFlight {
+                addr: "0.0.0.0:9000".into(),
+                token: Some("t".into()),
+            }
+        );
+        assert!(matches!(
+            args("serve --flight --read-only"),
+            Err(Failure::Usage(_))
+        ));
+        assert_eq!(
+            args("warmup job.manifest --score 0.9")
+                .ok()
+                .unwrap()
+                .command,
+            Command::Warmup {
+                manifest: "job.manifest".into(),
+                score: Some(0.9),
+            }
+        );
+        assert_eq!(
+            args("-c wrp.yaml doctor").ok().unwrap().command,
+            Command::Doctor {
+                config: "wrp.yaml".into()
+            }
+        );
+        assert!(args("doctor now").is_err());
+        assert_eq!(
+            args("ls -d / t run42/").ok().unwrap().command,
+            Command::Ls {
+                tag: Some("t".into()),
+                prefix: "run42/".into(),
+                delimiter: "/".into(),
+                long: false,
+            }
+        );
+        assert!(matches!(args("ls -d /"), Err(Failure::Usage(_))));
+        let parsed = args("ls t -l --output json").ok().unwrap();
+        assert_eq!(parsed.output, Some(Format::Json));
+        assert_eq!(
+            args("gc --dry-run -o csv").ok().unwrap(),
+            Args {
+                config: String::new(),
+                command: Command::Gc { dry_run: true },
+                output: Some(Format::Csv),
+                quiet: false,
+            }
+        );
+        assert!(matches!(args("ls -o yaml"), Err(Failure::Usage(_))));
+        let parsed = args("-q stat t b").ok().unwrap();
+        assert!(parsed.quiet);
+        assert!(matches!(args("--quiet top"), Err(Failure::Usage(_))));
+        assert_eq!(
+            args("completions zsh").ok().unwrap().command,
+            Command::Completions {
+                shell: "zsh".into()
+            }
+        );
+        assert!(matches!(args("completions tcsh"), Err(Failure::Usage(_))));
+        assert_eq!(
+            args("-c wrp.yaml __complete -c cte.yaml ls -o")
+                .ok()
+                .unwrap()
+                .command,
+            Command::Complete {
+                words: vec!["-c".into(), "cte.yaml".into(), "ls".into(), "-o".into()]
+            }
+        );
+        assert!(matches!(args("get t b -o json"), Err(Failure::Usage(_))));
+        assert_eq!(args("").ok().unwrap().command, Command::Help);
+        assert!(matches!(args("rm t"), Err(Failure::Usage(_))));
+        assert!(matches!(args("ls -r"), Err(Failure::Usage(_))));
+        assert!(matches!(args("frobnicate"), Err(Failure::Usage(_))));
+    }
+
+    #[test]
+    fn test_parse_takes_completed_options() {
+        for (name, flags, options) in complete::commands() {
+            // Parses, or fails on the arguments, but doesn't panic.
+            let _ = args(name);
+            let lines = flags
+                .iter()
+                .map(|f| format!("{} {}", name, f))
+                .chain(options.iter().map(|o| format!("{} {} 1", name, o)));
+            for line in lines {
+                if let Err(Failure::Usage(msg)) = args(&line) {
+                    assert!(!msg.starts_with("unknown option"), "{}: {}", line, msg);
+                }
+            }
+        }
+        assert!(matches!(
+            args("gc -l"),
+            Err(Failure::Usage(msg)) if msg.starts_with("unknown option")
+        ));
+    }
+}
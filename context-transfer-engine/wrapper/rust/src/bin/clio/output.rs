@@ -0,0 +1,267 @@
+//! `--output`: command results as a table, CSV or JSON.
+//!
+//! A command given `--output` builds a `Report` of named columns and rows, and
+//! it is written in the chosen format instead of the command's usual output.
+//! The column names are the CSV header and the JSON field names and don't
+//! change between releases, so scripts can rely on them. Null values are `-`
+//! in a table, empty in CSV and `null` in JSON. JSON is an array of one object
+//! per row, or a single object for a command about one thing (`Report::one`),
+//! so `jq '.[].blob'` or `jq .size` picks a field out.
+
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Table,
+    Csv,
+    Json,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "table" => Some(Format::Table),
+            "csv" => Some(Format::Csv),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Int(u64),
+    Float(f64),
+    Text(String),
+}
+
+impl From<u64> for Value {
+    fn from(n: u64) -> Self {
+        Value::Int(n)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(x: f64) -> Self {
+        Value::Float(x)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(x: f32) -> Self {
+        Value::Float(x.into())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Text(s)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::Text(s.to_string())
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(Value::Null, Into::into)
+    }
+}
+
+/// `s` as a JSON string literal.
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// `s` as a CSV field, quoted if it has to be.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+impl Value {
+    fn table(&self) -> String {
+        match self {
+            Value::Null => "-".into(),
+            Value::Int(n) => n.to_string(),
+            Value::Float(x) => format!("{:.3}", x),
+            Value::Text(s) => s.clone(),
+        }
+    }
+
+    fn csv(&self) -> String {
+        match self {
+            Value::Null => String::new(),
+            Value::Int(n) => n.to_string(),
+            Value::Float(x) => x.to_string(),
+            Value::Text(s) => csv_field(s),
+        }
+    }
+
+    fn json(&self) -> String {
+        match self {
+            Value::Int(n) => n.to_string(),
+            Value::Float(x) if x.is_finite() => x.to_string(),
+            Value::Null | Value::Float(_) => "null".into(),
+            Value::Text(s) => json_string(s),
+        }
+    }
+}
+
+/// A command's results, to write in a `Format`.
+#[derive(Debug)]
+pub struct Report {
+    columns: &'static [&'static str],
+    rows: Vec<Vec<Value>>,
+    /// Written as one JSON object rather than an array.
+    one: bool,
+}
+
+impl Report {
+    pub fn new(columns: &'static [&'static str]) -> Self {
+        Self {
+            columns,
+            rows: Vec::new(),
+            one: false,
+        }
+    }
+
+    /// A report of the single row `row`.
+    pub fn one(columns: &'static [&'static str], row: Vec<Value>) -> Self {
+        let mut report = Self::new(columns);
+        report.push(row);
+        report.one = true;
+        report
+    }
+
+    /// Add a row, with a value per column.
+    pub fn push(&mut self, row: Vec<Value>) {
+        debug_assert_eq!(row.len(), self.columns.len());
+        self.rows.push(row);
+    }
+
+    pub fn write(&self, out: &mut impl Write, format: Format) -> io::Result<()> {
+        match format {
+            Format::Table => self.write_table(out),
+            Format::Csv => {
+                writeln!(out, "{}", self.columns.join(","))?;
+                for row in &self.rows {
+                    let fields: Vec<String> = row.iter().map(Value::csv).collect();
+                    writeln!(out, "{}", fields.join(","))?;
+                }
+                Ok(())
+            }
+            Format::Json => {
+                let objects: Vec<String> = self
+                    .rows
+                    .iter()
+                    .map(|row| {
+                        let fields: Vec<String> = self
+                            .columns
+                            .iter()
+                            .zip(row)
+                            .map(|(c, v)| format!("{}:{}", json_string(c), v.json()))
+                            .collect();
+                        format!("{{{}}}", fields.join(","))
+                    })
+                    .collect();
+                match (self.one, objects.first()) {
+                    (true, Some(object)) => writeln!(out, "{}", object),
+                    _ => writeln!(out, "[{}]", objects.join(",")),
+                }
+            }
+        }
+    }
+
+    /// Columns padded to their widest value, two spaces apart.
+    fn write_table(&self, out: &mut impl Write) -> io::Result<()> {
+        let cells: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .map(|row| row.iter().map(Value::table).collect())
+            .collect();
+        let widths: Vec<usize> = (0..self.columns.len())
+            .map(|i| {
+                cells
+                    .iter()
+                    .map(|row| row[i].chars().count())
+                    .chain([self.columns[i].len()])
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let header: Vec<String> = self.columns.iter().map(|c| c.to_string()).collect();
+        for row in [header].iter().chain(&cells) {
+            let mut line = String::new();
+            for (i, cell) in row.iter().enumerate() {
+                if i + 1 < row.len() {
+                    let pad = widths[i] - cell.chars().count();
+                    line.push_str(&format!("{}{}  ", cell, " ".repeat(pad)));
+                } else {
+                    line.push_str(cell);
+                }
+            }
+            writeln!(out, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(report: &Report, format: Format) -> String {
+        let mut out = Vec::new();
+        report.write(&mut out, format).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_formats() {
+        let mut report = Report::new(&["blob", "size", "score"]);
+        report.push(vec!["a,\"b\"".into(), 10u64.into(), 0.5f64.into()]);
+        report.push(vec!["long-name".into(), Value::Null, f64::NAN.into()]);
+        assert_eq!(
+            written(&report, Format::Table),
+            "blob       size  score\n\
+             a,\"b\"      10    0.500\n\
+             long-name  -     NaN\n"
+        );
+        assert_eq!(
+            written(&report, Format::Csv),
+            "blob,size,score\n\"a,\"\"b\"\"\",10,0.5\nlong-name,,NaN\n"
+        );
+        assert_eq!(
+            written(&report, Format::Json),
+            "[{\"blob\":\"a,\\\"b\\\"\",\"size\":10,\"score\":0.5},\
+             {\"blob\":\"long-name\",\"size\":null,\"score\":null}]\n"
+        );
+        let one = Report::one(&["tag"], vec!["t\n".into()]);
+        assert_eq!(written(&one, Format::Json), "{\"tag\":\"t\\n\"}\n");
+        assert_eq!(written(&Report::new(&["tag"]), Format::Json), "[]\n");
+        assert_eq!(Format::parse("csv"), Some(Format::Csv));
+        assert_eq!(Format::parse("yaml"), None);
+    }
+}
@@ -0,0 +1,176 @@
+//! `clio top`: a periodically refreshed view of the deployment.
+//!
+//! Each refresh shows every storage target's fill and its read and write
+//! bandwidth since the previous refresh, the largest tags, and the queue depths
+//! of the local runtime's workers. On a terminal the screen is redrawn in
+//! place; otherwise each refresh is appended, so the output can be logged.
+
+use std::collections::HashMap;
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+use wrp_cte_rs::{Client, Tag, TargetInfo, WorkerStats};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopOptions {
+    pub interval: Duration,
+    /// Refreshes before exiting; 0 runs until interrupted.
+    pub iterations: u64,
+    /// Largest tags shown.
+    pub tags: usize,
+}
+
+impl Default for TopOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            iterations: 0,
+            tags: 10,
+        }
+    }
+}
+
+/// `bytes` with a binary unit, e.g. `1.5G`.
+fn human(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "K", "M", "G", "T"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{}{}", value as u64, UNITS[0])
+    } else {
+        format!("{:.1}{}", value, UNITS[unit])
+    }
+}
+
+/// Bytes per second moved between two readings of one counter.
+fn rate(before: Option<u64>, after: u64, elapsed: Duration) -> Option<f64> {
+    let secs = elapsed.as_secs_f64();
+    let before = before.filter(|_| secs > 0.0)?;
+    Some(after.saturating_sub(before) as f64 / secs)
+}
+
+/// Counters from the previous refresh, for bandwidth.
+#[derive(Default)]
+struct Previous {
+    at: Option<Instant>,
+    targets: HashMap<String, (u64, u64)>,
+}
+
+fn render(
+    out: &mut impl Write,
+    targets: &[TargetInfo],
+    tags: &[(String, u64)],
+    workers: &[WorkerStats],
+    prev: &Previous,
+    elapsed: Duration,
+) -> io::Result<()> {
+    writeln!(
+        out,
+        "{:<32} {:>6} {:>10} {:>10} {:>10}",
+        "TARGET", "SCORE", "FREE", "READ/s", "WRITE/s"
+    )?;
+    for t in targets {
+        let before = prev.targets.get(&t.name);
+        let show = |r: Option<f64>| r.map_or_else(|| "-".to_string(), human);
+        writeln!(
+            out,
+            "{:<32} {:>6.3} {:>10} {:>10} {:>10}",
+            t.name,
+            t.score,
+            human(t.remaining_space as f64),
+            show(rate(before.map(|b| b.0), t.bytes_read, elapsed)),
+            show(rate(before.map(|b| b.1), t.bytes_written, elapsed)),
+        )?;
+    }
+    writeln!(out)?;
+    writeln!(out, "{:<43} {:>10}", "TAG", "SIZE")?;
+    for (name, size) in tags {
+        writeln!(out, "{:<43} {:>10}", name, human(*size as f64))?;
+    }
+    writeln!(out)?;
+    let queued: u64 = workers.iter().map(|w| u64::from(w.queued)).sum();
+    let blocked: u64 = workers.iter().map(|w| u64::from(w.blocked)).sum();
+    writeln!(
+        out,
+        "{:<10} {:>8} {:>8} {:>8} {:>14} {:>6}",
+        "WORKER", "QUEUED", "BLOCKED", "RETRY", "PROCESSED", "ACTIVE"
+    )?;
+    for w in workers {
+        writeln!(
+            out,
+            "{:<10} {:>8} {:>8} {:>8} {:>14} {:>6}",
+            w.worker_id,
+            w.queued,
+            w.blocked,
+            w.retry,
+            w.processed,
+            if w.active { "yes" } else { "no" }
+        )?;
+    }
+    writeln!(out, "{:<10} {:>8} {:>8}", "total", queued, blocked)
+}
+
+/// Refresh the view every `options.interval` until done or interrupted.
+pub fn run(options: &TopOptions) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    let redraw = out.is_terminal();
+    let mut prev = Previous::default();
+    let mut n = 0;
+    loop {
+        let targets = Client::list_targets();
+        let mut tags: Vec<(String, u64)> = Client::tag_query(".*", 0)
+            .into_iter()
+            .map(|name| {
+                let size = Tag::new(&name).total_size();
+                (name, size)
+            })
+            .collect();
+        tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        tags.truncate(options.tags);
+        let workers = Client::worker_stats();
+
+        let now = Instant::now();
+        let elapsed = prev.at.map_or(Duration::ZERO, |at| now - at);
+        if redraw {
+            write!(out, "\x1b[2J\x1b[H")?;
+        } else if n > 0 {
+            writeln!(out)?;
+        }
+        render(&mut out, &targets, &tags, &workers, &prev, elapsed)?;
+        out.flush()?;
+
+        prev.at = Some(now);
+        prev.targets = targets
+            .iter()
+            .map(|t| (t.name.clone(), (t.bytes_read, t.bytes_written)))
+            .collect();
+        n += 1;
+        if options.iterations != 0 && n >= options.iterations {
+            return Ok(());
+        }
+        std::thread::sleep(options.interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_top_pieces() {
+        assert_eq!(human(512.0), "512B");
+        assert_eq!(human(1536.0), "1.5K");
+        assert_eq!(human(3.0 * 1024.0 * 1024.0 * 1024.0), "3.0G");
+
+        let second = Duration::from_secs(2);
+        assert_eq!(rate(Some(100), 300, second), Some(100.0));
+        assert_eq!(rate(None, 300, second), None);
+        assert_eq!(rate(Some(100), 300, Duration::ZERO), None);
+        // A restarted runtime resets its counters.
+        assert_eq!(rate(Some(300), 100, second), Some(0.0));
+    }
+}
@@ -0,0 +1,409 @@
+//! The bindings a scenario runs through.
+//!
+//! The Rust API and the C ABI run in this process. Any other binding runs as a
+//! driver: a child process that speaks the line protocol below on its stdin
+//! and stdout, so a binding for a new language only needs a small script to be
+//! checked. The Python driver is built in; a Node (or other) binding is added
+//! with `--driver NAME=COMMAND`.
+//!
+//! A driver first prints `ready`, or `unavailable<TAB>reason` if its binding
+//! isn't installed. It then answers each request line with one response line.
+//! Fields are tab-separated; names and data are hex, numbers decimal:
+//!
+//! ```text
+//! put   TAG BLOB DATA OFFSET    ->  ok
+//! get   TAG BLOB SIZE OFFSET    ->  ok DATA
+//! size  TAG BLOB                ->  ok SIZE
+//! blobs TAG                     ->  ok BLOB,BLOB...
+//! del   TAG BLOB                ->  ok
+//! ```
+//!
+//! or `error<TAB>message`, or `unsupported` for calls the binding lacks.
+
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use wrp_cte_rs::{GetOptions, PutOptions, Tag};
+
+use crate::scenario::{hex, unhex, Op, Outcome};
+
+pub trait Binding {
+    fn name(&self) -> &str;
+
+    /// Run `op` against `tag`, created on first use.
+    fn run(&mut self, tag: &str, op: &Op) -> Outcome;
+}
+
+/// The Rust API, through `Tag`'s fallible calls.
+pub struct RustApi {
+    tags: HashMap<String, Tag>,
+}
+
+impl RustApi {
+    pub fn new() -> Self {
+        Self {
+            tags: HashMap::new(),
+        }
+    }
+}
+
+impl Binding for RustApi {
+    fn name(&self) -> &str {
+        "rust"
+    }
+
+    fn run(&mut self, tag: &str, op: &Op) -> Outcome {
+        let tag = match self.tags.get(tag) {
+            Some(t) => t,
+            None => match Tag::try_new(tag) {
+                Ok(t) => self.tags.entry(tag.to_string()).or_insert(t),
+                Err(e) => return Outcome::Error(e.to_string()),
+            },
+        };
+        let result = match op {
+            Op::Put { blob, data, offset } => {
+                let options = PutOptions {
+                    offset: *offset,
+                    ..Default::default()
+                };
+                tag.put(blob, data, &options).map(|_| Outcome::Done)
+            }
+            Op::Get { blob, size, offset } => {
+                let options = GetOptions {
+                    offset: *offset,
+                    size: Some(*size),
+                    ..Default::default()
+                };
+                tag.get(blob, &options).map(Outcome::Data)
+            }
+            Op::Size { blob } => Ok(Outcome::Size(tag.get_blob_size(blob))),
+            Op::Blobs => tag.try_get_contained_blobs().map(names),
+            Op::Del { blob } => Ok(match tag.del_blob(blob) {
+                true => Outcome::Done,
+                false => Outcome::Error("del_blob returned false".into()),
+            }),
+        };
+        result.unwrap_or_else(|e| Outcome::Error(e.to_string()))
+    }
+}
+
+fn names(mut names: Vec<String>) -> Outcome {
+    names.sort();
+    Outcome::Names(names)
+}
+
+extern "C" {
+    fn cte_c_last_error() -> *const c_char;
+    fn cte_c_tag_new(name: *const c_char) -> *mut c_void;
+    fn cte_c_tag_free(tag: *mut c_void);
+    fn cte_c_tag_put_blob(
+        tag: *mut c_void,
+        name: *const c_char,
+        data: *const u8,
+        len: u64,
+        offset: u64,
+        score: f32,
+    ) -> i32;
+    fn cte_c_tag_get_blob_size(tag: *mut c_void, name: *const c_char) -> u64;
+    fn cte_c_tag_get_blob(
+        tag: *mut c_void,
+        name: *const c_char,
+        buf: *mut u8,
+        size: u64,
+        offset: u64,
+    ) -> i32;
+    fn cte_c_tag_list_blobs(tag: *mut c_void, out_buf: *mut *mut u8, out_len: *mut u64) -> i32;
+    fn cte_c_free_buffer(buf: *mut u8, len: u64);
+}
+
+/// The C ABI, called as a C program would call it.
+pub struct CAbi {
+    tags: HashMap<String, *mut c_void>,
+}
+
+impl CAbi {
+    pub fn new() -> Self {
+        Self {
+            tags: HashMap::new(),
+        }
+    }
+
+    fn last_error() -> Outcome {
+        // Safety: the string stays valid until the next `cte_c_*` call.
+        let msg = unsafe { CStr::from_ptr(cte_c_last_error()) };
+        Outcome::Error(msg.to_string_lossy().into_owned())
+    }
+
+    fn tag(&mut self, name: &str) -> Result<*mut c_void, Outcome> {
+        if let Some(&tag) = self.tags.get(name) {
+            return Ok(tag);
+        }
+        let c_name = cstring(name)?;
+        // Safety: `c_name` is a valid NUL-terminated string.
+        let tag = unsafe { cte_c_tag_new(c_name.as_ptr()) };
+        if tag.is_null() {
+            return Err(Self::last_error());
+        }
+        self.tags.insert(name.to_string(), tag);
+        Ok(tag)
+    }
+
+    fn call(&mut self, tag: &str, op: &Op) -> Result<Outcome, Outcome> {
+        let tag = self.tag(tag)?;
+        let status = |rc: i32| match rc {
+            0 => Ok(()),
+            _ => Err(Self::last_error()),
+        };
+        // Safety: `tag` is a live handle and every pointer passed below is
+        // valid for the length given with it.
+        unsafe {
+            Ok(match op {
+                Op::Put { blob, data, offset } => {
+                    let blob = cstring(blob)?;
+                    let len = data.len() as u64;
+                    status(cte_c_tag_put_blob(
+                        tag,
+                        blob.as_ptr(),
+                        data.as_ptr(),
+                        len,
+                        *offset,
+                        1.0,
+                    ))?;
+                    Outcome::Done
+                }
+                Op::Get { blob, size, offset } => {
+                    let blob = cstring(blob)?;
+                    let mut buf = vec![0u8; *size as usize];
+                    status(cte_c_tag_get_blob(
+                        tag,
+                        blob.as_ptr(),
+                        buf.as_mut_ptr(),
+                        *size,
+                        *offset,
+                    ))?;
+                    Outcome::Data(buf)
+                }
+                Op::Size { blob } => {
+                    let blob = cstring(blob)?;
+                    Outcome::Size(cte_c_tag_get_blob_size(tag, blob.as_ptr()))
+                }
+                Op::Blobs => {
+                    let (mut buf, mut len) = (std::ptr::null_mut(), 0u64);
+                    status(cte_c_tag_list_blobs(tag, &mut buf, &mut len))?;
+                    let records = std::slice::from_raw_parts(buf, len as usize);
+                    let listed = records_to_names(records);
+                    cte_c_free_buffer(buf, len);
+                    names(listed.ok_or_else(|| Outcome::Error("bad listing".into()))?)
+                }
+                // The C ABI has no blob delete.
+                Op::Del { .. } => Outcome::Unsupported,
+            })
+        }
+    }
+}
+
+impl Drop for CAbi {
+    fn drop(&mut self) {
+        for (_, tag) in self.tags.drain() {
+            // Safety: each handle came from `cte_c_tag_new` and is freed once.
+            unsafe { cte_c_tag_free(tag) };
+        }
+    }
+}
+
+impl Binding for CAbi {
+    fn name(&self) -> &str {
+        "c"
+    }
+
+    fn run(&mut self, tag: &str, op: &Op) -> Outcome {
+        self.call(tag, op).unwrap_or_else(|e| e)
+    }
+}
+
+fn cstring(s: &str) -> Result<CString, Outcome> {
+    CString::new(s).map_err(|_| Outcome::Error(format!("'{}' contains NUL", s)))
+}
+
+/// Names from `cte_c_tag_list_blobs`' `(u64 length, bytes)` records.
+fn records_to_names(mut records: &[u8]) -> Option<Vec<String>> {
+    let mut out = Vec::new();
+    while !records.is_empty() {
+        let len = u64::from_le_bytes(records.get(..8)?.try_into().ok()?) as usize;
+        let name = records.get(8..8 + len)?;
+        out.push(String::from_utf8_lossy(name).into_owned());
+        records = &records[8 + len..];
+    }
+    Some(out)
+}
+
+/// The Python driver, over the `wrp_cte_core_ext` module. Its argument is the
+/// CTE configuration path.
+pub const PYTHON_DRIVER: &str = r#"
+import sys
+try:
+    import wrp_cte_core_ext as cte
+except ImportError as e:
+    print("unavailable\t%s" % e, flush=True)
+    sys.exit(0)
+cte.chimaera_init(cte.ChimaeraMode.kClient, False)
+cte.initialize_cte(sys.argv[1], cte.PoolQuery.Dynamic())
+print("ready", flush=True)
+tags = {}
+def name(h):
+    return bytes.fromhex(h).decode()
+for line in sys.stdin:
+    f = line.rstrip("\n").split("\t")
+    try:
+        if f[1] not in tags:
+            tags[f[1]] = cte.Tag(name(f[1]))
+        tag = tags[f[1]]
+        if f[0] == "put":
+            tag.PutBlob(name(f[2]), bytes.fromhex(f[3]), int(f[4]))
+            out = "ok"
+        elif f[0] == "get":
+            data = tag.GetBlob(name(f[2]), int(f[3]), int(f[4]))
+            if isinstance(data, str):
+                data = data.encode()
+            out = "ok\t" + data.hex()
+        elif f[0] == "size":
+            out = "ok\t%d" % tag.GetBlobSize(name(f[2]))
+        elif f[0] == "blobs":
+            out = "ok\t" + ",".join(b.encode().hex() for b in tag.GetContainedBlobs())
+        elif f[0] == "del":
+            ok = cte.get_cte_client().DelBlob(tag.GetTagId(), name(f[2]))
+            out = "ok" if ok else "error\tDelBlob returned false"
+        else:
+            out = "unsupported"
+    except Exception as e:
+        out = "error\t" + " ".join(str(e).split())
+    print(out, flush=True)
+"#;
+
+/// A binding run as a driver process (see the module docs).
+pub struct Driver {
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Driver {
+    /// Start `command` (run by `sh -c`) with `args`. `Ok(None)` if the
+    /// driver reports its binding unavailable, with the reason printed.
+    pub fn start(name: &str, command: &str, args: &[&str]) -> Result<Option<Driver>, String> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$@\"", command))
+            .arg(name)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("can't start the {} driver: {}", name, e))?;
+        let stdin = child.stdin.take().expect("piped stdin");
+        let mut stdout = BufReader::new(child.stdout.take().expect("piped stdout"));
+        let mut hello = String::new();
+        stdout
+            .read_line(&mut hello)
+            .map_err(|e| format!("{} driver: {}", name, e))?;
+        match hello.trim_end().split_once('\t') {
+            _ if hello.trim_end() == "ready" => Ok(Some(Driver {
+                name: name.to_string(),
+                child,
+                stdin,
+                stdout,
+            })),
+            Some(("unavailable", why)) => {
+                eprintln!("cte-conformance: skipping {}: {}", name, why);
+                let _ = child.wait();
+                Ok(None)
+            }
+            _ => {
+                let _ = child.kill();
+                Err(format!(
+                    "{} driver didn't start: '{}'",
+                    name,
+                    hello.trim_end()
+                ))
+            }
+        }
+    }
+
+    fn request(op: &Op, tag: &str) -> String {
+        let tag = hex(tag.as_bytes());
+        match op {
+            Op::Put { blob, data, offset } => format!(
+                "put\t{}\t{}\t{}\t{}",
+                tag,
+                hex(blob.as_bytes()),
+                hex(data),
+                offset
+            ),
+            Op::Get { blob, size, offset } => {
+                format!(
+                    "get\t{}\t{}\t{}\t{}",
+                    tag,
+                    hex(blob.as_bytes()),
+                    size,
+                    offset
+                )
+            }
+            Op::Size { blob } => format!("size\t{}\t{}", tag, hex(blob.as_bytes())),
+            Op::Blobs => format!("blobs\t{}", tag),
+            Op::Del { blob } => format!("del\t{}\t{}", tag, hex(blob.as_bytes())),
+        }
+    }
+}
+
+/// The `Outcome` of a driver's response to `op`.
+fn response(op: &Op, line: &str) -> Outcome {
+    let bad = || Outcome::Error(format!("bad driver response '{}'", line));
+    let (status, value) = line.split_once('\t').unwrap_or((line, ""));
+    match status {
+        "unsupported" => return Outcome::Unsupported,
+        "error" => return Outcome::Error(value.to_string()),
+        "ok" => {}
+        _ => return bad(),
+    }
+    match op {
+        Op::Put { .. } | Op::Del { .. } => Outcome::Done,
+        Op::Get { .. } => unhex(value).map_or_else(bad, Outcome::Data),
+        Op::Size { .. } => value.parse().map_or_else(|_| bad(), Outcome::Size),
+        Op::Blobs => value
+            .split(',')
+            .filter(|h| !h.is_empty())
+            .map(|h| unhex(h).map(|b| String::from_utf8_lossy(&b).into_owned()))
+            .collect::<Option<Vec<_>>>()
+            .map_or_else(bad, names),
+    }
+}
+
+impl Binding for Driver {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn run(&mut self, tag: &str, op: &Op) -> Outcome {
+        if let Err(e) = writeln!(self.stdin, "{}", Self::request(op, tag)) {
+            return Outcome::Error(format!("driver exited: {}", e));
+        }
+        let mut line = String::new();
+        match self.stdout.read_line(&mut line) {
+            Ok(0) => Outcome::Error("driver exited".into()),
+            Ok(_) => response(op, line.trim_end_matches('\n')),
+            Err(e) => Outcome::Error(format!("driver: {}", e)),
+        }
+    }
+}
+
+impl Drop for Driver {
+    fn drop(&mut self) {
+        // Closing stdin ends the driver's loop.
+        let _ = self.stdin.flush();
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,254 @@
+//! `cte-conformance`: check that the bindings behave the same against one
+//! deployment.
+//!
+//! Runs a scenario file (see `scenario`) through each binding in turn, the
+//! Rust API, the C ABI, the Python binding when it is installed and any
+//! `--driver` given, each on a tag of its own, and reports every step where a
+//! binding's result differs from the others' or from the scenario's
+//! expectation. Exits 0 if none did. Built with the `conformance` feature;
+//! `basic.cte` is a scenario to start from.
+
+mod bindings;
+mod scenario;
+
+use std::fs;
+use std::process::ExitCode;
+
+use wrp_cte_rs::{init, Client};
+
+use bindings::{Binding, CAbi, Driver, RustApi, PYTHON_DRIVER};
+use scenario::{Outcome, Step};
+
+const USAGE: &str = "\
+usage: cte-conformance [--config PATH] [--binding rust|c|python]...
+                       [--driver NAME=COMMAND]... SCENARIO
+
+Runs SCENARIO through each binding (all of them unless --binding is given)
+and reports where their results differ. --driver adds a binding run as
+COMMAND, speaking the driver protocol on stdin and stdout.
+";
+
+const BINDINGS: [&str; 3] = ["rust", "c", "python"];
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    config: String,
+    bindings: Vec<String>,
+    drivers: Vec<(String, String)>,
+    scenario: String,
+}
+
+fn parse(args: &[String]) -> Result<Args, String> {
+    let mut config = String::new();
+    let mut bindings = Vec::new();
+    let mut drivers = Vec::new();
+    let mut scenario = None;
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let mut value = || it.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--config" => config = value()?.clone(),
+            "--binding" => {
+                let name = value()?;
+                if !BINDINGS.contains(&name.as_str()) {
+                    return Err(format!("unknown binding '{}'", name));
+                }
+                bindings.push(name.clone());
+            }
+            "--driver" => match value()?.split_once('=') {
+                Some((name, command)) if !name.is_empty() && !command.is_empty() => {
+                    drivers.push((name.to_string(), command.to_string()))
+                }
+                _ => return Err("--driver takes NAME=COMMAND".into()),
+            },
+            s if s.starts_with('-') => return Err(format!("unknown option '{}'", s)),
+            s if scenario.is_none() => scenario = Some(s.to_string()),
+            s => return Err(format!("unexpected argument '{}'", s)),
+        }
+    }
+    if bindings.is_empty() && drivers.is_empty() {
+        bindings = BINDINGS.iter().map(|b| b.to_string()).collect();
+    }
+    Ok(Args {
+        config,
+        bindings,
+        drivers,
+        scenario: scenario.ok_or("no scenario given")?,
+    })
+}
+
+/// The bindings to run; an unavailable Python binding is skipped unless it
+/// was asked for by name.
+fn start(args: &Args, explicit: bool) -> Result<Vec<Box<dyn Binding>>, String> {
+    let mut out: Vec<Box<dyn Binding>> = Vec::new();
+    for name in &args.bindings {
+        match name.as_str() {
+            "rust" => out.push(Box::new(RustApi::new())),
+            "c" => out.push(Box::new(CAbi::new())),
+            _ => {
+                let command = format!("python3 -c '{}'", PYTHON_DRIVER.replace('\'', r"'\''"));
+                match Driver::start(name, &command, &[&args.config])? {
+                    Some(driver) => out.push(Box::new(driver)),
+                    None if explicit => return Err(format!("the {} binding is unavailable", name)),
+                    None => {}
+                }
+            }
+        }
+    }
+    for (name, command) in &args.drivers {
+        let driver = Driver::start(name, command, &[&args.config])?
+            .ok_or_else(|| format!("the {} binding is unavailable", name))?;
+        out.push(Box::new(driver));
+    }
+    Ok(out)
+}
+
+/// The outcomes of a step that disagree: with each other, ignoring bindings
+/// that lack the call, or with the step's expectation.
+fn diverges(step: &Step, outcomes: &[Outcome]) -> bool {
+    let mut made = outcomes.iter().filter(|o| **o != Outcome::Unsupported);
+    let Some(first) = made.next() else {
+        return false;
+    };
+    made.any(|o| !o.agrees(first)) || step.expect.as_ref().is_some_and(|e| !e.agrees(first))
+}
+
+/// Run every step through every binding, printing divergences; the number of
+/// steps that diverged.
+fn run(path: &str, steps: &[Step], bindings: &mut [Box<dyn Binding>], tags: &[String]) -> usize {
+    let mut diverged = 0;
+    for step in steps {
+        let outcomes: Vec<Outcome> = bindings
+            .iter_mut()
+            .zip(tags)
+            .map(|(binding, tag)| binding.run(tag, &step.op))
+            .collect();
+        if !diverges(step, &outcomes) {
+            continue;
+        }
+        diverged += 1;
+        println!("{}:{}: {}", path, step.line, step.text);
+        if let Some(expect) = &step.expect {
+            println!("  {:<10} {}", "expected", expect);
+        }
+        for (binding, outcome) in bindings.iter().zip(&outcomes) {
+            println!("  {:<10} {}", binding.name(), outcome);
+        }
+    }
+    diverged
+}
+
+fn main() -> ExitCode {
+    let argv: Vec<String> = std::env::args().skip(1).collect();
+    let args = match parse(&argv) {
+        Ok(args) => args,
+        Err(msg) => {
+            eprintln!("cte-conformance: {}\n\n{}", msg, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    let explicit = argv.iter().any(|a| a == "--binding");
+    let result = fs::read_to_string(&args.scenario)
+        .map_err(|e| format!("{}: {}", args.scenario, e))
+        .and_then(|text| scenario::parse(&text).map_err(|e| format!("{}: {}", args.scenario, e)))
+        .and_then(|steps| {
+            init(&args.config)?;
+            let mut bindings = start(&args, explicit)?;
+            let tags: Vec<String> = bindings
+                .iter()
+                .map(|b| format!("cte-conformance.{}.{}", b.name(), std::process::id()))
+                .collect();
+            let diverged = run(&args.scenario, &steps, &mut bindings, &tags);
+            let names: Vec<&str> = bindings.iter().map(|b| b.name()).collect();
+            println!(
+                "{} steps, {} diverged ({})",
+                steps.len(),
+                diverged,
+                names.join(", ")
+            );
+            drop(bindings);
+            for tag in &tags {
+                Client::del_tag(tag);
+            }
+            Ok(diverged)
+        });
+    match result {
+        Ok(0) => ExitCode::SUCCESS,
+        Ok(_) => ExitCode::FAILURE,
+        Err(msg) => {
+            eprintln!("cte-conformance: {}", msg);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scenario::Op;
+
+    fn args(line: &str) -> Result<Args, String> {
+        let argv: Vec<String> = line.split_whitespace().map(String::from).collect();
+        parse(&argv)
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let all = args("basic.cte").unwrap();
+        assert_eq!(all.bindings, BINDINGS);
+        assert_eq!(all.scenario, "basic.cte");
+        let some = args("--binding c --driver node=./drv --config c.yaml s").unwrap();
+        assert_eq!(some.bindings, ["c"]);
+        assert_eq!(some.drivers, [("node".to_string(), "./drv".to_string())]);
+        assert_eq!(some.config, "c.yaml");
+        assert!(args("--binding java s").is_err());
+        assert!(args("--driver node s").is_err());
+        assert!(args("a b").is_err());
+        assert!(args("").is_err());
+    }
+
+    #[test]
+    fn test_scenario() {
+        let steps = scenario::parse(
+            "# a comment\n\
+             put a hello\n\
+             put hex:0041 hex:0102 4\n\
+             get a 5 => hello\n\
+             get b 1 => error\n\
+             size a => 5\n\
+             blobs => a,hex:0041\n\
+             del a => ok\n",
+        )
+        .unwrap();
+        assert_eq!(steps.len(), 7);
+        assert_eq!(steps[0].line, 2);
+        assert_eq!(
+            steps[1].op,
+            Op::Put {
+                blob: "\0A".into(),
+                data: vec![1, 2],
+                offset: 4
+            }
+        );
+        assert_eq!(steps[2].expect, Some(Outcome::Data(b"hello".to_vec())));
+        let mut names = vec!["a".to_string(), "\0A".to_string()];
+        names.sort();
+        assert_eq!(steps[5].expect, Some(Outcome::Names(names)));
+        assert!(scenario::parse("put a").is_err());
+        assert!(scenario::parse("size a => big").is_err());
+        assert!(scenario::parse("put a b => 3").is_err());
+
+        // Errors agree whatever their messages; an unsupported call is ignored,
+        // and an expectation must hold.
+        let get = &steps[3];
+        let failed = [Outcome::Error("x".into()), Outcome::Error("y".into())];
+        assert!(!diverges(get, &failed));
+        let mixed = [Outcome::Error("x".into()), Outcome::Data(vec![1])];
+        assert!(diverges(get, &mixed));
+        let del = &steps[6];
+        assert!(!diverges(del, &[Outcome::Done, Outcome::Unsupported]));
+        assert!(diverges(del, &[Outcome::Error("gone".into())]));
+        assert_eq!(Outcome::Data(vec![0, 1]).to_string(), "hex:0001");
+        assert_eq!(Outcome::Names(Vec::new()).to_string(), "-");
+    }
+}
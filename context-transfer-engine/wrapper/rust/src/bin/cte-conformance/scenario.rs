@@ -0,0 +1,196 @@
+//! Scenario files: the steps every binding runs, one per line.
+//!
+//! ```text
+//! # comments and blank lines are skipped
+//! put <blob> <data> [offset]
+//! get <blob> <size> [offset] [=> <data>|error]
+//! size <blob> [=> <n>]
+//! blobs [=> <blob>,<blob>...|-]
+//! del <blob> [=> ok|error]
+//! ```
+//!
+//! Data and blob names are taken as written, or as hex after `hex:`. A step
+//! with `=>` must also produce the expected result; without one, the bindings
+//! only have to agree with each other.
+
+use std::fmt;
+
+/// One operation, run against a binding's own tag.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Put {
+        blob: String,
+        data: Vec<u8>,
+        offset: u64,
+    },
+    Get {
+        blob: String,
+        size: u64,
+        offset: u64,
+    },
+    Size {
+        blob: String,
+    },
+    Blobs,
+    Del {
+        blob: String,
+    },
+}
+
+/// What a binding observed for a step.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Done,
+    Data(Vec<u8>),
+    Size(u64),
+    /// Blob names, sorted.
+    Names(Vec<String>),
+    /// The call failed; messages differ between bindings and aren't compared.
+    Error(String),
+    /// The binding has no way to make the call.
+    Unsupported,
+}
+
+impl Outcome {
+    /// True if `self` and `other` are the same observable behavior.
+    pub fn agrees(&self, other: &Outcome) -> bool {
+        match (self, other) {
+            (Outcome::Error(_), Outcome::Error(_)) => true,
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Done => write!(f, "ok"),
+            Outcome::Data(data) => write!(f, "{}", show(data)),
+            Outcome::Size(n) => write!(f, "{}", n),
+            Outcome::Names(names) if names.is_empty() => write!(f, "-"),
+            Outcome::Names(names) => write!(f, "{}", names.join(",")),
+            Outcome::Error(msg) => write!(f, "error ({})", msg),
+            Outcome::Unsupported => write!(f, "unsupported"),
+        }
+    }
+}
+
+/// `data` as a scenario token: as is if it is plain text, else `hex:`.
+fn show(data: &[u8]) -> String {
+    let plain = !data.is_empty()
+        && !data.starts_with(b"hex:")
+        && data.iter().all(|b| b.is_ascii_graphic() && *b != b',');
+    match plain {
+        true => String::from_utf8_lossy(data).into_owned(),
+        false => format!("hex:{}", hex(data)),
+    }
+}
+
+pub fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn token(s: &str) -> Result<Vec<u8>, String> {
+    match s.strip_prefix("hex:") {
+        Some(h) => unhex(h).ok_or_else(|| format!("'{}' is not hex", h)),
+        None => Ok(s.as_bytes().to_vec()),
+    }
+}
+
+fn name(s: &str) -> Result<String, String> {
+    String::from_utf8(token(s)?).map_err(|_| format!("blob name '{}' is not UTF-8", s))
+}
+
+/// A step of a scenario and where it was written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub line: usize,
+    pub text: String,
+    pub op: Op,
+    pub expect: Option<Outcome>,
+}
+
+pub fn parse(text: &str) -> Result<Vec<Step>, String> {
+    let mut steps = Vec::new();
+    for (i, raw) in text.lines().enumerate() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let at = |msg: String| format!("line {}: {}", i + 1, msg);
+        let (call, expect) = match line.split_once("=>") {
+            Some((call, expect)) => (call.trim(), Some(expect.trim())),
+            None => (line, None),
+        };
+        let words: Vec<&str> = call.split_whitespace().collect();
+        let op = op(&words).map_err(at)?;
+        let expect = match expect {
+            Some(e) => Some(expected(&op, e).map_err(at)?),
+            None => None,
+        };
+        steps.push(Step {
+            line: i + 1,
+            text: line.to_string(),
+            op,
+            expect,
+        });
+    }
+    Ok(steps)
+}
+
+fn number(word: &str) -> Result<u64, String> {
+    word.parse()
+        .map_err(|_| format!("'{}' is not a number", word))
+}
+
+/// The `Op` of a step's words.
+fn op(words: &[&str]) -> Result<Op, String> {
+    let offset = |w: Option<&&str>| w.map_or(Ok(0), |w| number(w));
+    Ok(match *words {
+        ["put", blob, data, ref rest @ ..] if rest.len() <= 1 => Op::Put {
+            blob: name(blob)?,
+            data: token(data)?,
+            offset: offset(rest.first())?,
+        },
+        ["get", blob, size, ref rest @ ..] if rest.len() <= 1 => Op::Get {
+            blob: name(blob)?,
+            size: number(size)?,
+            offset: offset(rest.first())?,
+        },
+        ["size", blob] => Op::Size { blob: name(blob)? },
+        ["blobs"] => Op::Blobs,
+        ["del", blob] => Op::Del { blob: name(blob)? },
+        _ => return Err(format!("can't parse '{}'", words.join(" "))),
+    })
+}
+
+fn expected(op: &Op, text: &str) -> Result<Outcome, String> {
+    if text == "error" {
+        return Ok(Outcome::Error(String::new()));
+    }
+    Ok(match op {
+        Op::Put { .. } | Op::Del { .. } if text == "ok" => Outcome::Done,
+        Op::Get { .. } => Outcome::Data(token(text)?),
+        Op::Size { .. } => Outcome::Size(
+            text.parse()
+                .map_err(|_| format!("'{}' is not a size", text))?,
+        ),
+        Op::Blobs if text == "-" => Outcome::Names(Vec::new()),
+        Op::Blobs => {
+            let mut names = text.split(',').map(name).collect::<Result<Vec<_>, _>>()?;
+            names.sort();
+            Outcome::Names(names)
+        }
+        _ => return Err(format!("can't expect '{}' here", text)),
+    })
+}
@@ -0,0 +1,174 @@
+//! Zero-copy reads of blobs stored in local files.
+//!
+//! A file target keeps a blob's data at an offset in its backing file (for a
+//! shared-memory target, a file under `/dev/shm`), so a process on the same
+//! node can map that range and read the data in place. `Tag::map_blob` does so
+//! when it can: the blob is in a single block, on a file target this process
+//! registered, and stored as written (not compressed or encrypted). An adopted
+//! blob (see `adopt`) maps its file. Anything else, a RAM or remote target,
+//! a blob split over several blocks, or a runtime that doesn't report block
+//! placement, is read into a buffer the usual way, so callers get the same
+//! data either way and `BlobMap::is_mapped` only tells them what it cost.
+//!
+//! A mapping shows the bytes where the blob was when it was made, and nothing
+//! stops the runtime from writing them again: a later write, reorganize or
+//! delete of the blob may move it and reuse that space, and truncating the file
+//! under a mapping faults on the next read. Because a `&[u8]` must not change
+//! under its reader, `Tag::map_blob` is `unsafe` and its caller keeps the blob
+//! and its file still while the view lives; `Tag::get` is the safe way to read.
+
+use std::fs::{self, File};
+use std::ops::Deref;
+use std::path::Path;
+
+use memmap2::{Mmap, MmapOptions};
+
+use crate::{ffi_guard, handshake, writeback, Capability, CteError, FileRef, GetOptions, Tag};
+
+enum Data {
+    Mapped(Mmap),
+    Buffered(Vec<u8>),
+}
+
+/// A read-only view of a blob's data, mapped or copied (see `blobmap`).
+pub struct BlobMap {
+    data: Data,
+    generation: u64,
+}
+
+impl BlobMap {
+    /// True if the data is mapped in place rather than copied.
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.data, Data::Mapped(_))
+    }
+
+    /// Generation of the blob the view was taken at.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+impl Deref for BlobMap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.data {
+            Data::Mapped(map) => map,
+            Data::Buffered(buf) => buf,
+        }
+    }
+}
+
+/// Map `len` bytes of the file at `path` from `offset`, if possible.
+///
+/// # Safety
+///
+/// The range must not be written, nor the file truncated, while the mapping
+/// lives.
+unsafe fn map_range(path: impl AsRef<Path>, offset: u64, len: u64) -> Option<Mmap> {
+    let path = path.as_ref();
+    if path.as_os_str().is_empty() || len == 0 {
+        return None;
+    }
+    let file = File::open(path).ok()?;
+    if file.metadata().ok()?.len() < offset.checked_add(len)? {
+        return None;
+    }
+    // Safety: the mapping is read-only, and the caller keeps the range still.
+    unsafe {
+        MmapOptions::new()
+            .offset(offset)
+            .len(usize::try_from(len).ok()?)
+            .map(&file)
+            .ok()
+    }
+}
+
+impl Tag {
+    /// A read-only view of the whole of `name`: mapped in place when it is in
+    /// a local file, else read into a buffer (see `blobmap`). `NotFound` if
+    /// there is no such blob.
+    ///
+    /// # Safety
+    ///
+    /// While the view lives, nothing may write, reorganize or delete `name`
+    /// (in this process or another), and an adopted blob's file must not be
+    /// written or truncated.
+    pub unsafe fn map_blob(&self, name: &str) -> Result<BlobMap, CteError> {
+        writeback::flush_blob(self, name);
+        let stat = self.stat_blob(name)?.ok_or_else(|| CteError::NotFound {
+            blob: name.to_string(),
+        })?;
+        let generation = stat.generation;
+        if let Some(map) = unsafe { self.try_map(name, stat.size, stat.file.as_ref())? } {
+            // Written while the mapping was made: it may show either version.
+            if self.load_meta(name)?.map_or(0, |m| m.generation) == generation {
+                return Ok(BlobMap {
+                    data: Data::Mapped(map),
+                    generation,
+                });
+            }
+        }
+        let options = GetOptions {
+            if_generation_match: Some(generation),
+            ..Default::default()
+        };
+        Ok(BlobMap {
+            data: Data::Buffered(self.get(name, &options)?),
+            generation,
+        })
+    }
+
+    /// Safety: as for `map_blob`.
+    unsafe fn try_map(
+        &self,
+        name: &str,
+        size: u64,
+        file: Option<&FileRef>,
+    ) -> Result<Option<Mmap>, CteError> {
+        if let Some(file) = file {
+            let unchanged = fs::metadata(&file.path).is_ok_and(|md| file.matches(&md));
+            return Ok(unchanged
+                .then(|| unsafe { map_range(&file.path, 0, file.size) })
+                .flatten());
+        }
+        if !handshake::supports(Capability::StatBlobs) || self.is_encrypted()? {
+            return Ok(None);
+        }
+        if self.load_meta(name)?.is_some_and(|m| m.is_transformed()) {
+            return Ok(None);
+        }
+        let blocks = ffi_guard::tag_blob_blocks(&self.inner, name)?;
+        Ok(match blocks.as_slice() {
+            [block] if block.size >= size => unsafe {
+                map_range(&block.target, block.offset, size)
+            },
+            _ => None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_range() {
+        let path = std::env::temp_dir().join(format!("cte_blobmap_{}", std::process::id()));
+        fs::write(&path, b"....payload..").unwrap();
+        // Safety: nothing else has the file.
+        let map = unsafe { map_range(&path, 4, 7) }.unwrap();
+        assert_eq!(&map[..], b"payload");
+        // Past the end of the file, or nothing to map.
+        assert!(unsafe { map_range(&path, 10, 7) }.is_none());
+        assert!(unsafe { map_range(&path, 0, 0) }.is_none());
+        assert!(unsafe { map_range("", 0, 1) }.is_none());
+        let view = BlobMap {
+            data: Data::Mapped(map),
+            generation: 3,
+        };
+        assert!(view.is_mapped());
+        assert_eq!(view.to_vec(), b"payload");
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,182 @@
+//! Bulk destructive operations with dry-run support.
+//!
+//! Every bulk operation takes `BulkOptions` and returns a `BulkReport` listing
+//! the blobs it touched. With `dry_run` set, nothing is changed and the report
+//! lists exactly the blobs and bytes the real run would affect.
+
+use std::collections::{BTreeSet, HashSet};
+
+use crate::meta::META_PREFIX;
+use crate::{trash, Client, CteError, Tag};
+
+/// Options shared by bulk operations.
+#[derive(Debug, Clone, Default)]
+pub struct BulkOptions {
+    /// Report what would be affected without changing anything.
+    pub dry_run: bool,
+}
+
+/// A blob touched by a bulk operation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AffectedBlob {
+    pub tag: String,
+    pub blob: String,
+    pub size: u64,
+}
+
+/// A blob a bulk operation tried and failed to change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailedBlob {
+    pub tag: String,
+    pub blob: String,
+    /// The error, as displayed.
+    pub error: String,
+}
+
+/// What a bulk operation did, or would do under `dry_run`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BulkReport {
+    pub dry_run: bool,
+    pub blobs: Vec<AffectedBlob>,
+    /// Sum of `blobs[..].size`.
+    pub bytes: u64,
+    /// Blobs left unchanged because the runtime failed them; not in `blobs`.
+    pub failed: Vec<FailedBlob>,
+}
+
+impl BulkReport {
+    pub(crate) fn new(options: &BulkOptions) -> Self {
+        Self {
+            dry_run: options.dry_run,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn merge(&mut self, other: BulkReport) {
+        self.bytes += other.bytes;
+        self.blobs.extend(other.blobs);
+        self.failed.extend(other.failed);
+    }
+
+    pub(crate) fn push(&mut self, tag: &str, blob: &str, size: u64) {
+        self.bytes += size;
+        self.blobs.push(AffectedBlob {
+            tag: tag.to_string(),
+            blob: blob.to_string(),
+            size,
+        });
+    }
+
+    pub(crate) fn push_failed(&mut self, tag: &str, blob: &str, error: &CteError) {
+        self.failed.push(FailedBlob {
+            tag: tag.to_string(),
+            blob: blob.to_string(),
+            error: error.to_string(),
+        });
+    }
+}
+
+impl Tag {
+    /// Delete the blobs `names` (through the trash, if enabled). Blobs that
+    /// don't exist are left out of the report.
+    pub fn del_blobs(&self, names: &[&str], options: &BulkOptions) -> BulkReport {
+        let mut report = BulkReport::new(options);
+        for name in names {
+            let Ok(Some(stat)) = self.stat_blob(name) else {
+                continue;
+            };
+            if options.dry_run || self.del_blob(name) {
+                report.push(&self.name, name, stat.size);
+            }
+        }
+        if !options.dry_run {
+            trash::purge_expired_of(&self.name);
+        }
+        report
+    }
+}
+
+impl Client {
+    /// `del_tag` with a report of the tag's blobs. `None` if the tag doesn't
+    /// exist, or (unless `dry_run`) wasn't deleted.
+    pub fn del_tag_report(name: &str, options: &BulkOptions) -> Option<BulkReport> {
+        if !Self::tag_exists(name) {
+            return None;
+        }
+        let tag = Tag::new(name);
+        let mut report = BulkReport::new(options);
+        for blob in tag.blob_names() {
+            let size = tag.get_blob_size(&blob);
+            report.push(name, &blob, size);
+        }
+        if !options.dry_run && !Self::del_tag(name) {
+            return None;
+        }
+        Some(report)
+    }
+
+    /// Delete every blob matching the tag and blob regexes (through the trash,
+    /// if enabled).
+    pub fn del_blobs_matching(tag_re: &str, blob_re: &str, options: &BulkOptions) -> BulkReport {
+        let mut report = BulkReport::new(options);
+        let mut current: Option<Tag> = None;
+        let mut touched = BTreeSet::new();
+        for (tag_name, blob) in Self::blob_query(tag_re, blob_re, 0) {
+            if current.as_ref().map(|t| t.name()) != Some(tag_name.as_str()) {
+                current = Some(Tag::new(&tag_name));
+            }
+            let tag = current.as_ref().unwrap();
+            let size = tag.get_blob_size(&blob);
+            if options.dry_run || tag.del_blob(&blob) {
+                report.push(&tag_name, &blob, size);
+                touched.insert(tag_name);
+            }
+        }
+        if !options.dry_run {
+            for tag_name in &touched {
+                trash::purge_expired_of(tag_name);
+            }
+        }
+        report
+    }
+
+    /// Reclaim wrapper garbage: blobs past their TTL, ephemeral tags of dead
+    /// sessions, metadata sidecars whose blob is gone, and trash entries past the
+    /// retention window. Trash entries are reported under their original tag,
+    /// named `<deleted-at-ms>.<seq>/<blob>`. Interrupted transaction commits are rolled
+    /// forward on the way (unless `dry_run`).
+    pub fn gc(options: &BulkOptions) -> BulkReport {
+        let mut report = Self::expire(options);
+        report.merge(Self::reap_sessions(options));
+        for tag_name in Self::tag_query(".*", 0) {
+            let tag = Tag::new(&tag_name);
+            if !options.dry_run {
+                let _ = tag.recover_transactions();
+            }
+            let names = tag.raw_blob_names();
+            let live: HashSet<&str> = names.iter().map(String::as_str).collect();
+            for name in &names {
+                let Some(blob) = name.strip_prefix(META_PREFIX) else {
+                    continue;
+                };
+                if live.contains(blob) {
+                    continue;
+                }
+                let size = tag.get_blob_size(name);
+                if options.dry_run || crate::ffi::tag_del_blob(&tag.inner, name) {
+                    report.push(&tag_name, name, size);
+                }
+            }
+        }
+        if let Some(retention) = trash::retention() {
+            for tag_name in trash::trashed_tags() {
+                for (entry, size) in
+                    trash::purge_tag(&tag_name, Some(retention), None, options.dry_run)
+                {
+                    report.push(&tag_name, &entry, size);
+                }
+            }
+        }
+        report
+    }
+}
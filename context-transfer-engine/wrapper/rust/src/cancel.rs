@@ -0,0 +1,64 @@
+//! Cooperative cancellation of long-running operations.
+//!
+//! A `CancellationToken` is handed to an operation through its options
+//! (`StageOptions::cancel`, `WarmupOptions::cancel`, `DelTagOptions::cancel`)
+//! and cancelled from any thread. The operation checks it between parts (a
+//! chunk of a staged file, a blob to warm up or delete): the part in flight
+//! finishes, nothing more is started, whatever was left half done is removed,
+//! and the operation returns `CteError::Cancelled`. Clones share their state,
+//! so one token can stop several operations at once.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::CteError;
+
+/// A flag that asks the operations holding it to stop.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every operation holding this token, or a clone of it, to stop.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Whether the optional token of an operation's options has been cancelled.
+pub(crate) fn cancelled(token: &Option<CancellationToken>) -> bool {
+    token.as_ref().is_some_and(CancellationToken::is_cancelled)
+}
+
+/// `Cancelled` if the optional token has been cancelled.
+pub(crate) fn check(token: &Option<CancellationToken>) -> Result<(), CteError> {
+    if cancelled(token) {
+        return Err(CteError::Cancelled);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancellation_token() {
+        let token = CancellationToken::new();
+        let held = Some(token.clone());
+        assert!(check(&held).is_ok());
+        assert!(!cancelled(&None));
+        std::thread::spawn(move || token.cancel()).join().unwrap();
+        assert!(held.as_ref().unwrap().is_cancelled());
+        assert!(matches!(check(&held), Err(CteError::Cancelled)));
+    }
+}
@@ -0,0 +1,222 @@
+//! Tags as workflow channels.
+//!
+//! Pipelines often pass data between steps through a shared filesystem and a
+//! "done" file. A `Channel` does the same through a tag: a producer writes a
+//! blob and then marks it ready (`Channel::publish` does both), and a consumer
+//! reads the names of ready blobs, in the order they were marked, from a
+//! `ChannelReader` that polls the tag until the producer calls
+//! `Channel::close`. A blob is never seen half-written, since it is marked only
+//! once its put has returned. Producers and consumers can be in different
+//! processes or on different hosts; `clio publish`, `clio close` and `clio
+//! subscribe` expose the same to the shell scripts that Nextflow and Snakemake
+//! processes run.
+//!
+//! Each mark is a sidecar named after the time it was made and the blob, so a
+//! reader finds new marks with one prefix listing per poll. Marks are ordered
+//! by the publishers' clocks and then by blob name. Publishing a blob again
+//! marks it again, and readers see it a second time.
+
+use std::collections::{HashSet, VecDeque};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::ttl::now_ms;
+use crate::{ffi_guard, listing, CteError, PutOptions, Tag};
+
+/// Prefix of ready marks: `READY_PREFIX + <ms as 16 hex digits> + "/" + blob`.
+const READY_PREFIX: &str = ".cte/channel/ready/";
+/// Written by `Channel::close`.
+const CLOSED_NAME: &str = ".cte/channel/closed";
+
+/// A tag used as a channel of ready blobs (see `channel`).
+pub struct Channel {
+    tag: Tag,
+}
+
+/// How a `ChannelReader` waits for blobs.
+#[derive(Debug, Clone)]
+pub struct ChannelOptions {
+    /// Time between looks at the tag while nothing new is ready.
+    pub poll_interval: Duration,
+    /// Give up with `CteError::Timeout` after this long with nothing new and
+    /// the channel still open; `None` to wait for as long as it takes.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for ChannelOptions {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_millis(200),
+            timeout: None,
+        }
+    }
+}
+
+fn mark_name(ms: u64, blob: &str) -> String {
+    format!("{}{:016x}/{}", READY_PREFIX, ms, blob)
+}
+
+/// The blob a mark is for.
+fn marked_blob(mark: &str) -> Option<&str> {
+    let (ms, blob) = mark.strip_prefix(READY_PREFIX)?.split_once('/')?;
+    (ms.len() == 16 && u64::from_str_radix(ms, 16).is_ok()).then_some(blob)
+}
+
+impl Channel {
+    /// The channel over tag `name`, created if missing; fails like
+    /// `Tag::try_new`.
+    pub fn open(name: &str) -> Result<Self, CteError> {
+        Ok(Self {
+            tag: Tag::try_new(name)?,
+        })
+    }
+
+    /// The tag the channel's blobs are in.
+    pub fn tag(&self) -> &Tag {
+        &self.tag
+    }
+
+    /// Write `data` as blob `name` and mark it ready.
+    pub fn publish(&self, name: &str, data: &[u8]) -> Result<(), CteError> {
+        self.tag.put(name, data, &PutOptions::default())?;
+        self.mark_ready(name)
+    }
+
+    /// Mark the existing blob `name` ready, for blobs written some other way;
+    /// `NotFound` if there is no such blob.
+    pub fn mark_ready(&self, name: &str) -> Result<(), CteError> {
+        if self.tag.stat_blob(name)?.is_none() {
+            return Err(CteError::NotFound {
+                blob: name.to_string(),
+            });
+        }
+        let mark = mark_name(now_ms(), name);
+        ffi_guard::tag_put_blob(&self.tag.inner, &mark, &[], 0, 1.0)
+    }
+
+    /// Tell readers nothing more will be published. Blobs marked before this
+    /// returns are still read.
+    pub fn close(&self) -> Result<(), CteError> {
+        ffi_guard::tag_put_blob(&self.tag.inner, CLOSED_NAME, &[1], 0, 1.0)
+    }
+
+    /// Whether `close` has been called on the channel, by any process.
+    pub fn is_closed(&self) -> bool {
+        self.tag.get_blob_size(CLOSED_NAME) > 0
+    }
+
+    /// Marks made so far, oldest first.
+    fn marks(&self) -> Result<Vec<String>, CteError> {
+        let entries = listing::list_entries(&self.tag, READY_PREFIX.as_bytes(), b"")?;
+        let mut marks: Vec<String> = entries
+            .into_iter()
+            .filter_map(|e| String::from_utf8(e.name).ok())
+            .filter(|m| marked_blob(m).is_some())
+            .collect();
+        marks.sort();
+        Ok(marks)
+    }
+
+    /// Names of the blobs marked ready so far, in the order they were marked.
+    pub fn ready(&self) -> Result<Vec<String>, CteError> {
+        Ok(self
+            .marks()?
+            .iter()
+            .filter_map(|m| marked_blob(m).map(str::to_string))
+            .collect())
+    }
+
+    /// Read the channel from the start: every blob marked ready, then each
+    /// one marked later, until the channel is closed.
+    pub fn reader(&self, options: ChannelOptions) -> ChannelReader<'_> {
+        ChannelReader {
+            channel: self,
+            options,
+            seen: HashSet::new(),
+            pending: VecDeque::new(),
+            done: false,
+        }
+    }
+}
+
+/// Names of ready blobs as they are marked (see `Channel::reader`). Ends once
+/// the channel is closed and every mark has been read; yields
+/// `CteError::Timeout` and ends if `ChannelOptions::timeout` passes first.
+pub struct ChannelReader<'a> {
+    channel: &'a Channel,
+    options: ChannelOptions,
+    /// Marks already queued or yielded.
+    seen: HashSet<String>,
+    pending: VecDeque<String>,
+    done: bool,
+}
+
+impl ChannelReader<'_> {
+    /// Queue the marks not seen yet; returns how many there were.
+    fn poll(&mut self) -> Result<usize, CteError> {
+        let mut found = 0;
+        for mark in self.channel.marks()? {
+            if self.seen.insert(mark.clone()) {
+                if let Some(blob) = marked_blob(&mark) {
+                    self.pending.push_back(blob.to_string());
+                    found += 1;
+                }
+            }
+        }
+        Ok(found)
+    }
+}
+
+impl Iterator for ChannelReader<'_> {
+    type Item = Result<String, CteError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = Instant::now();
+        loop {
+            if let Some(blob) = self.pending.pop_front() {
+                return Some(Ok(blob));
+            }
+            if self.done {
+                return None;
+            }
+            // Look for the close first, so marks made before it are read.
+            let closed = self.channel.is_closed();
+            match self.poll() {
+                Ok(0) => {}
+                Ok(_) => continue,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+            if closed {
+                self.done = true;
+                return None;
+            }
+            if let Some(timeout) = self.options.timeout {
+                if start.elapsed() >= timeout {
+                    self.done = true;
+                    return Some(Err(CteError::Timeout(timeout)));
+                }
+            }
+            thread::sleep(self.options.poll_interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ready_marks() {
+        let a = mark_name(5, "step/1.bin");
+        let b = mark_name(0x10, "0.bin");
+        assert_eq!(a, ".cte/channel/ready/0000000000000005/step/1.bin");
+        assert_eq!(marked_blob(&a), Some("step/1.bin"));
+        // Hex digits are fixed width, so marks sort by time.
+        assert!(a < b);
+        assert_eq!(marked_blob(".cte/channel/ready/xyz/blob"), None);
+        assert_eq!(marked_blob(".cte/meta/blob"), None);
+    }
+}
@@ -0,0 +1,551 @@
+//! Per-blob checksums for detecting silent corruption.
+//!
+//! A put made with `PutOptions::checksum` records a digest of the whole blob in
+//! its metadata sidecar; `Tag::verify_blob` and `GetOptions::verify_checksum`
+//! recompute it from the stored data. Any later write that doesn't ask for a
+//! checksum drops the recorded one, since it would no longer describe the blob.
+//!
+//! The algorithms are implemented here to keep the wrapper free of dependencies.
+
+use std::fmt;
+
+use crate::alert::{self, Alert};
+use crate::io::COPY_CHUNK;
+use crate::{ffi_guard, CteError, Tag};
+
+/// Checksum algorithm recorded with a blob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    /// CRC-32C (Castagnoli). Cheapest; catches bit flips and torn writes.
+    Crc32c,
+    /// XXH64 with seed 0. Fast 64-bit non-cryptographic hash.
+    XxHash64,
+    /// SHA-256, for when the digest has to stand up to deliberate tampering.
+    Sha256,
+}
+
+impl ChecksumAlgorithm {
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            ChecksumAlgorithm::Crc32c => 1,
+            ChecksumAlgorithm::XxHash64 => 2,
+            ChecksumAlgorithm::Sha256 => 3,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(ChecksumAlgorithm::Crc32c),
+            2 => Some(ChecksumAlgorithm::XxHash64),
+            3 => Some(ChecksumAlgorithm::Sha256),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ChecksumAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ChecksumAlgorithm::Crc32c => "crc32c",
+            ChecksumAlgorithm::XxHash64 => "xxhash64",
+            ChecksumAlgorithm::Sha256 => "sha256",
+        })
+    }
+}
+
+/// A digest and the algorithm that produced it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    pub algorithm: ChecksumAlgorithm,
+    /// Big-endian digest bytes.
+    pub digest: Vec<u8>,
+}
+
+impl Checksum {
+    /// Checksum of `data` under `algorithm`.
+    pub fn compute(algorithm: ChecksumAlgorithm, data: &[u8]) -> Self {
+        let mut h = Hasher::new(algorithm);
+        h.update(data);
+        h.finish()
+    }
+
+    /// Lowercase hex form of the digest.
+    pub fn to_hex(&self) -> String {
+        self.digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Sidecar encoding: the algorithm id followed by the digest.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(1 + self.digest.len());
+        out.push(self.algorithm.id());
+        out.extend_from_slice(&self.digest);
+        out
+    }
+
+    pub(crate) fn decode(buf: &[u8]) -> Result<Self, String> {
+        let (&id, digest) = buf.split_first().ok_or("empty checksum field")?;
+        let algorithm =
+            ChecksumAlgorithm::from_id(id).ok_or_else(|| format!("unknown checksum id {}", id))?;
+        Ok(Self {
+            algorithm,
+            digest: digest.to_vec(),
+        })
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm, self.to_hex())
+    }
+}
+
+/// Streaming hasher over any of the supported algorithms.
+pub(crate) enum Hasher {
+    Crc32c(u32),
+    XxHash64(Xxh64),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    pub(crate) fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Crc32c => Hasher::Crc32c(!0),
+            ChecksumAlgorithm::XxHash64 => Hasher::XxHash64(Xxh64::new(0)),
+            ChecksumAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Crc32c(crc) => *crc = crc32c_update(*crc, data),
+            Hasher::XxHash64(h) => h.update(data),
+            Hasher::Sha256(h) => h.update(data),
+        }
+    }
+
+    pub(crate) fn finish(self) -> Checksum {
+        let (algorithm, digest) = match self {
+            Hasher::Crc32c(crc) => (ChecksumAlgorithm::Crc32c, (!crc).to_be_bytes().to_vec()),
+            Hasher::XxHash64(h) => (
+                ChecksumAlgorithm::XxHash64,
+                h.finish().to_be_bytes().to_vec(),
+            ),
+            Hasher::Sha256(h) => (ChecksumAlgorithm::Sha256, h.finish().to_vec()),
+        };
+        Checksum { algorithm, digest }
+    }
+}
+
+impl Tag {
+    /// Recompute `name`'s checksum from the stored data and compare it with the
+    /// one recorded at write time.
+    ///
+    /// Returns `Ok(true)` if it matches, `Ok(false)` if the blob has no recorded
+    /// checksum, and `CteError::ChecksumMismatch` if the data has changed.
+    pub fn verify_blob(&self, name: &str) -> Result<bool, CteError> {
+        let size = self.get_blob_size(name);
+        let meta = self.load_meta(name)?;
+        if size == 0 && meta.is_none() {
+            return Err(CteError::NotFound {
+                blob: name.to_string(),
+            });
+        }
+        let Some(expected) = meta.and_then(|m| m.checksum) else {
+            return Ok(false);
+        };
+        let actual = self.compute_checksum(name, expected.algorithm, size)?;
+        check_checksum(&self.name, name, &expected, &actual)?;
+        Ok(true)
+    }
+
+    /// Checksum of the first `size` bytes of `name`, read in bounded chunks.
+    pub(crate) fn compute_checksum(
+        &self,
+        name: &str,
+        algorithm: ChecksumAlgorithm,
+        size: u64,
+    ) -> Result<Checksum, CteError> {
+        let mut h = Hasher::new(algorithm);
+        let mut offset = 0;
+        while offset < size {
+            let len = COPY_CHUNK.min(size - offset);
+            h.update(ffi_guard::tag_get_blob(&self.inner, name, len, offset)?.as_slice());
+            offset += len;
+        }
+        Ok(h.finish())
+    }
+}
+
+/// `Ok` if `actual` is `expected`; otherwise raises an `Alert::ChecksumMismatch`
+/// and returns the error.
+pub(crate) fn check_checksum(
+    tag: &str,
+    blob: &str,
+    expected: &Checksum,
+    actual: &Checksum,
+) -> Result<(), CteError> {
+    if expected == actual {
+        Ok(())
+    } else {
+        alert::raise(Alert::ChecksumMismatch {
+            tag: tag.to_string(),
+            blob: blob.to_string(),
+        });
+        Err(CteError::ChecksumMismatch {
+            blob: blob.to_string(),
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+        })
+    }
+}
+
+/// Lookup table for a reflected CRC-32 with polynomial `poly`.
+const fn crc_table(poly: u32) -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ poly
+            } else {
+                crc >> 1
+            };
+            k += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+const CRC32C_TABLE: [u32; 256] = crc_table(0x82F6_3B78);
+const CRC32_TABLE: [u32; 256] = crc_table(0xEDB8_8320);
+
+fn crc_update(table: &[u32; 256], mut crc: u32, data: &[u8]) -> u32 {
+    for &b in data {
+        crc = table[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+fn crc32c_update(crc: u32, data: &[u8]) -> u32 {
+    crc_update(&CRC32C_TABLE, crc, data)
+}
+
+/// Running CRC-32 (IEEE, as used by zip) of `data`; start from 0 and feed the
+/// previous result back in to continue.
+pub(crate) fn crc32(crc: u32, data: &[u8]) -> u32 {
+    !crc_update(&CRC32_TABLE, !crc, data)
+}
+
+const P64_1: u64 = 0x9E37_79B1_85EB_CA87;
+const P64_2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const P64_3: u64 = 0x1656_67B1_9E37_79F9;
+const P64_4: u64 = 0x85EB_CA77_C2B2_AE63;
+const P64_5: u64 = 0x27D4_EB2F_1656_67C5;
+
+pub(crate) struct Xxh64 {
+    seed: u64,
+    acc: [u64; 4],
+    buf: [u8; 32],
+    buf_len: usize,
+    total: u64,
+}
+
+fn xxh64_round(acc: u64, lane: u64) -> u64 {
+    acc.wrapping_add(lane.wrapping_mul(P64_2))
+        .rotate_left(31)
+        .wrapping_mul(P64_1)
+}
+
+fn xxh64_merge(h: u64, acc: u64) -> u64 {
+    (h ^ xxh64_round(0, acc))
+        .wrapping_mul(P64_1)
+        .wrapping_add(P64_4)
+}
+
+fn le_u64(b: &[u8]) -> u64 {
+    u64::from_le_bytes(b[..8].try_into().unwrap())
+}
+
+/// One-shot XXH64 of `data`.
+pub(crate) fn xxh64(seed: u64, data: &[u8]) -> u64 {
+    let mut h = Xxh64::new(seed);
+    h.update(data);
+    h.finish()
+}
+
+impl Xxh64 {
+    fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            acc: [
+                seed.wrapping_add(P64_1).wrapping_add(P64_2),
+                seed.wrapping_add(P64_2),
+                seed,
+                seed.wrapping_sub(P64_1),
+            ],
+            buf: [0; 32],
+            buf_len: 0,
+            total: 0,
+        }
+    }
+
+    fn stripe(&mut self, block: &[u8]) {
+        for (i, acc) in self.acc.iter_mut().enumerate() {
+            *acc = xxh64_round(*acc, le_u64(&block[i * 8..]));
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        self.total += data.len() as u64;
+        if self.buf_len > 0 {
+            let take = (32 - self.buf_len).min(data.len());
+            self.buf[self.buf_len..self.buf_len + take].copy_from_slice(&data[..take]);
+            self.buf_len += take;
+            data = &data[take..];
+            if self.buf_len < 32 {
+                return;
+            }
+            let block = self.buf;
+            self.stripe(&block);
+            self.buf_len = 0;
+        }
+        while data.len() >= 32 {
+            self.stripe(&data[..32]);
+            data = &data[32..];
+        }
+        self.buf[..data.len()].copy_from_slice(data);
+        self.buf_len = data.len();
+    }
+
+    fn finish(&self) -> u64 {
+        let mut h = if self.total >= 32 {
+            let [a, b, c, d] = self.acc;
+            let mut h = a
+                .rotate_left(1)
+                .wrapping_add(b.rotate_left(7))
+                .wrapping_add(c.rotate_left(12))
+                .wrapping_add(d.rotate_left(18));
+            for acc in self.acc {
+                h = xxh64_merge(h, acc);
+            }
+            h
+        } else {
+            self.seed.wrapping_add(P64_5)
+        };
+        h = h.wrapping_add(self.total);
+        let mut rest = &self.buf[..self.buf_len];
+        while rest.len() >= 8 {
+            h ^= xxh64_round(0, le_u64(rest));
+            h = h.rotate_left(27).wrapping_mul(P64_1).wrapping_add(P64_4);
+            rest = &rest[8..];
+        }
+        if rest.len() >= 4 {
+            let lane = u32::from_le_bytes(rest[..4].try_into().unwrap()) as u64;
+            h ^= lane.wrapping_mul(P64_1);
+            h = h.rotate_left(23).wrapping_mul(P64_2).wrapping_add(P64_3);
+            rest = &rest[4..];
+        }
+        for &b in rest {
+            h ^= (b as u64).wrapping_mul(P64_5);
+            h = h.rotate_left(11).wrapping_mul(P64_1);
+        }
+        h ^= h >> 33;
+        h = h.wrapping_mul(P64_2);
+        h ^= h >> 29;
+        h = h.wrapping_mul(P64_3);
+        h ^ (h >> 32)
+    }
+}
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+pub(crate) struct Sha256 {
+    state: [u32; 8],
+    buf: [u8; 64],
+    buf_len: usize,
+    total: u64,
+}
+
+impl Sha256 {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: [
+                0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+                0x5be0cd19,
+            ],
+            buf: [0; 64],
+            buf_len: 0,
+            total: 0,
+        }
+    }
+
+    fn compress(&mut self, block: &[u8]) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes(word.try_into().unwrap());
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA256_K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (s, v) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+
+    pub(crate) fn update(&mut self, mut data: &[u8]) {
+        self.total += data.len() as u64;
+        if self.buf_len > 0 {
+            let take = (64 - self.buf_len).min(data.len());
+            self.buf[self.buf_len..self.buf_len + take].copy_from_slice(&data[..take]);
+            self.buf_len += take;
+            data = &data[take..];
+            if self.buf_len < 64 {
+                return;
+            }
+            let block = self.buf;
+            self.compress(&block);
+            self.buf_len = 0;
+        }
+        while data.len() >= 64 {
+            self.compress(&data[..64]);
+            data = &data[64..];
+        }
+        self.buf[..data.len()].copy_from_slice(data);
+        self.buf_len = data.len();
+    }
+
+    pub(crate) fn finish(mut self) -> [u8; 32] {
+        let bits = self.total.wrapping_mul(8);
+        let mut pad = vec![0x80u8];
+        let padded = (self.buf_len + 1) % 64;
+        pad.resize(
+            1 + if padded <= 56 {
+                56 - padded
+            } else {
+                120 - padded
+            },
+            0,
+        );
+        pad.extend_from_slice(&bits.to_be_bytes());
+        // update() would count the padding towards the length; it's already captured.
+        let total = self.total;
+        self.update(&pad);
+        self.total = total;
+        let mut out = [0u8; 32];
+        for (chunk, s) in out.chunks_exact_mut(4).zip(self.state) {
+            chunk.copy_from_slice(&s.to_be_bytes());
+        }
+        out
+    }
+}
+
+/// HMAC-SHA256 (RFC 2104) of `message` under `key`.
+pub(crate) fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; 64];
+    if key.len() > 64 {
+        let mut h = Sha256::new();
+        h.update(key);
+        block[..32].copy_from_slice(&h.finish());
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(&block.map(|b| b ^ 0x36));
+    inner.update(message);
+    let mut outer = Sha256::new();
+    outer.update(&block.map(|b| b ^ 0x5c));
+    outer.update(&inner.finish());
+    outer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(alg: ChecksumAlgorithm, data: &[u8]) -> String {
+        Checksum::compute(alg, data).to_hex()
+    }
+
+    #[test]
+    fn test_known_vectors() {
+        assert_eq!(hex(ChecksumAlgorithm::Crc32c, b"123456789"), "e3069283");
+        assert_eq!(hex(ChecksumAlgorithm::XxHash64, b""), "ef46db3751d8e999");
+        assert_eq!(hex(ChecksumAlgorithm::XxHash64, b"abc"), "44bc2cf5ad770999");
+        assert_eq!(
+            hex(ChecksumAlgorithm::Sha256, b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex(ChecksumAlgorithm::Sha256, b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231 test case 2.
+        let mac = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        let hex: String = mac.iter().map(|b| format!("{:02x}", b)).collect();
+        assert_eq!(
+            hex,
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_streaming_matches_one_shot() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i * 31 % 251) as u8).collect();
+        for alg in [
+            ChecksumAlgorithm::Crc32c,
+            ChecksumAlgorithm::XxHash64,
+            ChecksumAlgorithm::Sha256,
+        ] {
+            let mut h = Hasher::new(alg);
+            for chunk in data.chunks(7) {
+                h.update(chunk);
+            }
+            let sum = h.finish();
+            assert_eq!(sum, Checksum::compute(alg, &data));
+            assert_eq!(Checksum::decode(&sum.encode()).unwrap(), sum);
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! Parquet files stored as blobs (feature `parquet`).
+//!
+//! `Tag::parquet_writer` writes Arrow record batches to a blob as a Parquet
+//! file, putting it in `FLUSH_SIZE` pieces at consecutive offsets as row groups
+//! fill, so the file never has to fit in memory at once. It replaces any blob
+//! of that name when it opens (through the trash, if on), and the blob is a
+//! readable Parquet file only once `ParquetWriter::close` has written the
+//! footer. Closing also records the schema and row count in the blob's
+//! attributes, as `Tag::put_record_batches` does (see `batches`).
+//!
+//! `Tag::parquet_reader` reads the file back. It fetches the footer and then
+//! only the byte ranges of the column chunks it decodes, with ranged gets, so
+//! a projection or a reader that stops early doesn't read the whole blob.
+
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use arrow_array::RecordBatch;
+use arrow_schema::SchemaRef;
+use bytes::Bytes;
+use parquet::arrow::arrow_reader::{ParquetRecordBatchReader, ParquetRecordBatchReaderBuilder};
+use parquet::arrow::ArrowWriter;
+use parquet::errors::ParquetError;
+use parquet::file::reader::{ChunkReader, Length};
+
+use crate::batches::{describe, ARROW_ROWS_ATTR, ARROW_SCHEMA_ATTR};
+use crate::{CteError, GetOptions, PutOptions, Tag};
+
+/// Bytes a `ParquetWriter` gathers before putting them.
+const FLUSH_SIZE: usize = 4 << 20;
+
+fn parquet_error(blob: &str, e: ParquetError) -> CteError {
+    CteError::InvalidArgument(format!("Parquet file '{}': {}", blob, e))
+}
+
+/// Appends to a blob, `FLUSH_SIZE` bytes at a time.
+struct BlobSink<'a> {
+    tag: &'a Tag,
+    name: String,
+    buf: Vec<u8>,
+    /// Where `buf` goes.
+    offset: u64,
+}
+
+impl BlobSink<'_> {
+    fn send(&mut self) -> Result<(), CteError> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let options = PutOptions {
+            offset: self.offset,
+            ..Default::default()
+        };
+        self.tag.put(&self.name, &self.buf, &options)?;
+        self.offset += self.buf.len() as u64;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+impl Write for BlobSink<'_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= FLUSH_SIZE {
+            self.send().map_err(io::Error::other)?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Row groups are flushed as they fill; only `close` has to send.
+        Ok(())
+    }
+}
+
+/// Writes record batches to a blob as a Parquet file (see `columnar`).
+pub struct ParquetWriter<'a> {
+    writer: ArrowWriter<BlobSink<'a>>,
+    schema: SchemaRef,
+    rows: usize,
+}
+
+impl ParquetWriter<'_> {
+    /// Add `batch`, which must have the writer's schema.
+    pub fn write(&mut self, batch: &RecordBatch) -> Result<(), CteError> {
+        self.writer
+            .write(batch)
+            .map_err(|e| parquet_error(&self.writer.inner().name, e))?;
+        self.rows += batch.num_rows();
+        Ok(())
+    }
+
+    /// Write the last row group and the footer. Returns the file's size.
+    pub fn close(self) -> Result<u64, CteError> {
+        let name = self.writer.inner().name.clone();
+        let mut sink = self
+            .writer
+            .into_inner()
+            .map_err(|e| parquet_error(&name, e))?;
+        sink.send()?;
+        sink.tag.set_blob_attrs(
+            &name,
+            &[
+                (ARROW_SCHEMA_ATTR, &describe(&self.schema)),
+                (ARROW_ROWS_ATTR, &self.rows.to_string()),
+            ],
+        )?;
+        Ok(sink.offset)
+    }
+}
+
+/// A blob as Parquet reads it: ranges fetched on demand.
+struct BlobChunks {
+    tag: Arc<Tag>,
+    name: String,
+    size: u64,
+}
+
+impl BlobChunks {
+    fn read(&self, start: u64, len: u64) -> Result<Vec<u8>, CteError> {
+        let options = GetOptions {
+            offset: start,
+            size: Some(len),
+            ..Default::default()
+        };
+        self.tag.get(&self.name, &options)
+    }
+}
+
+impl Length for BlobChunks {
+    fn len(&self) -> u64 {
+        self.size
+    }
+}
+
+impl ChunkReader for BlobChunks {
+    type T = BlobRange;
+
+    fn get_read(&self, start: u64) -> parquet::errors::Result<BlobRange> {
+        Ok(BlobRange {
+            chunks: BlobChunks {
+                tag: self.tag.clone(),
+                name: self.name.clone(),
+                size: self.size,
+            },
+            at: start.min(self.size),
+        })
+    }
+
+    fn get_bytes(&self, start: u64, length: usize) -> parquet::errors::Result<Bytes> {
+        let data = self
+            .read(start, length as u64)
+            .map_err(|e| ParquetError::External(Box::new(e)))?;
+        Ok(Bytes::from(data))
+    }
+}
+
+/// The rest of a blob from some offset, read `FLUSH_SIZE` bytes at a time.
+struct BlobRange {
+    chunks: BlobChunks,
+    at: u64,
+}
+
+impl Read for BlobRange {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = (buf.len().min(FLUSH_SIZE) as u64).min(self.chunks.size - self.at);
+        if len == 0 {
+            return Ok(0);
+        }
+        let data = self.chunks.read(self.at, len).map_err(io::Error::other)?;
+        buf[..data.len()].copy_from_slice(&data);
+        self.at += data.len() as u64;
+        Ok(data.len())
+    }
+}
+
+impl Tag {
+    /// Write blob `name` as a Parquet file of `schema` (see `columnar`),
+    /// replacing any blob of that name.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(tag = %self.name, blob = name), err)
+    )]
+    pub fn parquet_writer(
+        &self,
+        name: &str,
+        schema: SchemaRef,
+    ) -> Result<ParquetWriter<'_>, CteError> {
+        if self.stat_blob(name)?.is_some() {
+            self.del_blob(name);
+        }
+        let sink = BlobSink {
+            tag: self,
+            name: name.to_string(),
+            buf: Vec::new(),
+            offset: 0,
+        };
+        let writer =
+            ArrowWriter::try_new(sink, schema.clone(), None).map_err(|e| parquet_error(name, e))?;
+        Ok(ParquetWriter {
+            writer,
+            schema,
+            rows: 0,
+        })
+    }
+
+    /// Read blob `name`, a Parquet file, as record batches (see `columnar`).
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(tag = %self.name, blob = name), err)
+    )]
+    pub fn parquet_reader(&self, name: &str) -> Result<ParquetRecordBatchReader, CteError> {
+        let size = self
+            .stat_blob(name)?
+            .ok_or_else(|| CteError::NotFound {
+                blob: name.to_string(),
+            })?
+            .size;
+        let chunks = BlobChunks {
+            // Parquet keeps the reader past this call, so it gets its own
+            // handle on the tag.
+            tag: Arc::new(Tag::from_id(self.get_tag_id())),
+            name: name.to_string(),
+            size,
+        };
+        ParquetRecordBatchReaderBuilder::try_new(chunks)
+            .and_then(|builder| builder.build())
+            .map_err(|e| parquet_error(name, e))
+    }
+}
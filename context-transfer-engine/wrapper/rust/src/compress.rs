@@ -0,0 +1,148 @@
+//! Transparent blob compression.
+//!
+//! `PutOptions::compression` compresses the data before it crosses into the
+//! runtime and records the codec and uncompressed size in the blob's metadata;
+//! `Tag::get` and `Tag::stat_blob` undo it, so callers see the original bytes and
+//! size. The runtime, and `get_blob_size`/`get_blob`, see the compressed form.
+//!
+//! A compressed blob is always written whole, at offset 0. Codecs are behind the
+//! `zstd` and `lz4` cargo features.
+
+use crate::CteError;
+
+/// Compression applied to a blob on put.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    /// Zstandard at the given level (1-22; 3 is zstd's default).
+    Zstd(i32),
+    /// LZ4 block format. Faster than zstd, with a lower ratio.
+    Lz4,
+}
+
+impl Compression {
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Zstd(_) => 1,
+            Compression::Lz4 => 2,
+        }
+    }
+
+    pub(crate) fn compress(self, data: &[u8]) -> Result<Vec<u8>, CteError> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(level) => Ok(zstd::bulk::compress(data, level)?),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Ok(lz4_flex::block::compress(data)),
+            #[allow(unreachable_patterns)]
+            other => Err(missing_feature(other)),
+        }
+    }
+
+    pub(crate) fn decompress(
+        self,
+        blob: &str,
+        data: &[u8],
+        raw_size: u64,
+    ) -> Result<Vec<u8>, CteError> {
+        let corrupt = |reason: String| CteError::CorruptMetadata {
+            blob: blob.to_string(),
+            reason,
+        };
+        let out = match self {
+            Compression::None => data.to_vec(),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(_) => zstd::bulk::decompress(data, raw_size as usize)
+                .map_err(|e| corrupt(format!("zstd: {}", e)))?,
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => lz4_flex::block::decompress(data, raw_size as usize)
+                .map_err(|e| corrupt(format!("lz4: {}", e)))?,
+            #[allow(unreachable_patterns)]
+            other => return Err(missing_feature(other)),
+        };
+        if out.len() as u64 != raw_size {
+            return Err(corrupt(format!(
+                "decompressed to {} bytes, expected {}",
+                out.len(),
+                raw_size
+            )));
+        }
+        Ok(out)
+    }
+}
+
+/// How a compressed blob is stored, kept in its metadata sidecar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Compressed {
+    pub codec: Compression,
+    /// Size of the data before compression.
+    pub raw_size: u64,
+    /// Bytes of compressed payload at the start of the blob. An overwrite can
+    /// leave a longer previous version's bytes behind it.
+    pub stored_size: u64,
+}
+
+impl Compressed {
+    /// Sidecar encoding: codec id, zstd level, raw size, stored size.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let level = match self.codec {
+            Compression::Zstd(level) => level,
+            _ => 0,
+        };
+        let mut out = vec![self.codec.id()];
+        out.extend_from_slice(&level.to_le_bytes());
+        out.extend_from_slice(&self.raw_size.to_le_bytes());
+        out.extend_from_slice(&self.stored_size.to_le_bytes());
+        out
+    }
+
+    pub(crate) fn decode(buf: &[u8]) -> Result<Self, String> {
+        if buf.len() != 21 {
+            return Err("bad compression field".into());
+        }
+        let level = i32::from_le_bytes(buf[1..5].try_into().unwrap());
+        let codec = match buf[0] {
+            0 => Compression::None,
+            1 => Compression::Zstd(level),
+            2 => Compression::Lz4,
+            id => return Err(format!("unknown compression codec {}", id)),
+        };
+        Ok(Self {
+            codec,
+            raw_size: u64::from_le_bytes(buf[5..13].try_into().unwrap()),
+            stored_size: u64::from_le_bytes(buf[13..21].try_into().unwrap()),
+        })
+    }
+}
+
+#[allow(dead_code)]
+fn missing_feature(codec: Compression) -> CteError {
+    let feature = match codec {
+        Compression::Zstd(_) => "zstd",
+        _ => "lz4",
+    };
+    CteError::Unsupported(format!(
+        "{:?} compression needs the `{}` feature",
+        codec, feature
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_roundtrip() {
+        for codec in [Compression::None, Compression::Zstd(19), Compression::Lz4] {
+            let c = Compressed {
+                codec,
+                raw_size: 5 << 20,
+                stored_size: 1 << 20,
+            };
+            assert_eq!(Compressed::decode(&c.encode()).unwrap(), c);
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! Blob reads and writes of CUDA device memory (feature `cuda`).
+//!
+//! `Tag::put_blob_device` and `Tag::get_blob_device` move data between a blob
+//! and device (or managed) memory, ordered on the caller's CUDA stream, so a
+//! training loop can checkpoint tensors without copying them to the host
+//! itself. The runtime has no target that reads or writes device memory
+//! directly (GPUDirect Storage), so the data is staged through pinned host
+//! buffers of `STAGE_BYTES`: while one chunk is written to or read from the
+//! blob, the next is copied over PCIe into or out of the other buffer. Staging
+//! buffers are kept for later calls. Chunks are the blob's bytes as stored, so
+//! encrypted tags and compressed, encrypted or referencing blobs are refused
+//! with `CteError::Unsupported`.
+//!
+//! The CUDA runtime (`libcudart`) is linked from `$CUDA_PATH/lib64`,
+//! `/usr/local/cuda/lib64` if it isn't set.
+
+use std::ffi::{c_char, c_int, c_uint, c_void, CStr};
+use std::ptr;
+use std::sync::Mutex;
+
+use crate::{CteError, Tag};
+
+/// A CUDA stream (`cudaStream_t`); null for the default stream.
+pub type CudaStream = *mut c_void;
+
+type CudaEvent = *mut c_void;
+
+const CUDA_SUCCESS: c_int = 0;
+/// `cudaMemcpyDefault`: the direction follows from the addresses, so managed
+/// memory works as well as device memory.
+const MEMCPY_DEFAULT: c_int = 4;
+const EVENT_DISABLE_TIMING: c_uint = 0x02;
+
+/// Size of each staging buffer, and so of each write or read of the blob.
+const STAGE_BYTES: usize = 8 << 20;
+
+/// Staging buffers kept for later calls.
+const POOL_BUFFERS: usize = 4;
+
+#[link(name = "cudart")]
+extern "C" {
+    fn cudaMallocHost(ptr: *mut *mut c_void, size: usize) -> c_int;
+    fn cudaFreeHost(ptr: *mut c_void) -> c_int;
+    fn cudaMemcpyAsync(
+        dst: *mut c_void,
+        src: *const c_void,
+        count: usize,
+        kind: c_int,
+        stream: CudaStream,
+    ) -> c_int;
+    fn cudaEventCreateWithFlags(event: *mut CudaEvent, flags: c_uint) -> c_int;
+    fn cudaEventRecord(event: CudaEvent, stream: CudaStream) -> c_int;
+    fn cudaEventSynchronize(event: CudaEvent) -> c_int;
+    fn cudaEventDestroy(event: CudaEvent) -> c_int;
+    fn cudaGetErrorString(error: c_int) -> *const c_char;
+}
+
+fn check(call: &str, status: c_int) -> Result<(), CteError> {
+    if status == CUDA_SUCCESS {
+        return Ok(());
+    }
+    let message = unsafe { CStr::from_ptr(cudaGetErrorString(status)) };
+    Err(CteError::Device(format!(
+        "{} failed: {}",
+        call,
+        message.to_string_lossy()
+    )))
+}
+
+/// `STAGE_BYTES` of page-locked host memory.
+struct Pinned(*mut u8);
+
+// The buffer is plain memory, used by one call at a time.
+unsafe impl Send for Pinned {}
+
+impl Pinned {
+    fn new() -> Result<Self, CteError> {
+        let mut buf = ptr::null_mut();
+        check("cudaMallocHost", unsafe {
+            cudaMallocHost(&mut buf, STAGE_BYTES)
+        })?;
+        Ok(Pinned(buf as *mut u8))
+    }
+
+    fn slice(&mut self, len: usize) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.0, len.min(STAGE_BYTES)) }
+    }
+}
+
+impl Drop for Pinned {
+    fn drop(&mut self) {
+        unsafe { cudaFreeHost(self.0 as *mut c_void) };
+    }
+}
+
+static POOL: Mutex<Vec<Pinned>> = Mutex::new(Vec::new());
+
+/// Two staging buffers, and an event per buffer marking the end of the last
+/// copy queued into or out of it. Dropping waits for those copies before the
+/// buffers go back to the pool.
+struct Staging {
+    buffers: [Pinned; 2],
+    events: [CudaEvent; 2],
+}
+
+impl Staging {
+    fn new() -> Result<Self, CteError> {
+        let pooled = || POOL.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        let first = pooled().map_or_else(Pinned::new, Ok)?;
+        let second = pooled().map_or_else(Pinned::new, Ok)?;
+        let mut events = [ptr::null_mut(); 2];
+        for event in &mut events {
+            let created = unsafe { cudaEventCreateWithFlags(event, EVENT_DISABLE_TIMING) };
+            if let Err(e) = check("cudaEventCreateWithFlags", created) {
+                Self::destroy(&events);
+                return Err(e);
+            }
+        }
+        Ok(Self {
+            buffers: [first, second],
+            events,
+        })
+    }
+
+    fn destroy(events: &[CudaEvent; 2]) {
+        for event in events.iter().filter(|e| !e.is_null()) {
+            unsafe { cudaEventDestroy(*event) };
+        }
+    }
+
+    /// Queue a copy of `len` bytes on `stream` and mark its end with buffer
+    /// `k`'s event.
+    fn copy(
+        &self,
+        k: usize,
+        dst: *mut c_void,
+        src: *const c_void,
+        len: usize,
+        stream: CudaStream,
+    ) -> Result<(), CteError> {
+        check("cudaMemcpyAsync", unsafe {
+            cudaMemcpyAsync(dst, src, len, MEMCPY_DEFAULT, stream)
+        })?;
+        check("cudaEventRecord", unsafe {
+            cudaEventRecord(self.events[k], stream)
+        })
+    }
+
+    /// Wait for the last copy into or out of buffer `k`; at once if there was
+    /// none.
+    fn wait(&self, k: usize) -> Result<(), CteError> {
+        check("cudaEventSynchronize", unsafe {
+            cudaEventSynchronize(self.events[k])
+        })
+    }
+}
+
+impl Drop for Staging {
+    fn drop(&mut self) {
+        let idle = (0..2).all(|k| self.wait(k).is_ok());
+        Self::destroy(&self.events);
+        if idle {
+            let mut pool = POOL.lock().unwrap_or_else(|e| e.into_inner());
+            let [first, second] = std::mem::replace(
+                &mut self.buffers,
+                [Pinned(ptr::null_mut()), Pinned(ptr::null_mut())],
+            );
+            for buffer in [first, second] {
+                if pool.len() < POOL_BUFFERS {
+                    pool.push(buffer);
+                }
+            }
+        } else {
+            // A copy may still use the buffers; leak them rather than free
+            // memory the device may write to.
+            for buffer in &mut self.buffers {
+                buffer.0 = ptr::null_mut();
+            }
+        }
+    }
+}
+
+/// `(offset, len)` of each staged chunk of `len` bytes.
+fn stages(len: usize) -> impl Iterator<Item = (usize, usize)> {
+    (0..len.div_ceil(STAGE_BYTES)).map(move |i| {
+        let start = i * STAGE_BYTES;
+        (start, STAGE_BYTES.min(len - start))
+    })
+}
+
+impl Tag {
+    /// `Unsupported` unless `name`'s stored bytes are its data, as chunked
+    /// staging needs.
+    fn check_untransformed(&self, name: &str) -> Result<(), CteError> {
+        let transformed = self.is_encrypted()?
+            || self
+                .load_meta(name)?
+                .is_some_and(|meta| meta.is_transformed());
+        if transformed {
+            return Err(CteError::Unsupported(format!(
+                "'{}' is stored compressed, encrypted or by reference; \
+                 device transfers need it stored as written",
+                name
+            )));
+        }
+        Ok(())
+    }
+
+    /// Write `len` bytes of device (or managed) memory at `device_ptr` into
+    /// blob `name` from offset 0, as `put_blob` does with host memory. The copy
+    /// starts after the work already queued on `stream` and the call returns
+    /// once the data is in the blob (see `cuda`). Fails with `Unsupported` in
+    /// an encrypted tag or over a compressed or encrypted blob.
+    ///
+    /// # Safety
+    ///
+    /// `device_ptr` must be valid for `len` bytes on the current device, and
+    /// nothing may write to it until the call returns.
+    pub unsafe fn put_blob_device(
+        &self,
+        name: &str,
+        device_ptr: *const c_void,
+        len: usize,
+        stream: CudaStream,
+    ) -> Result<(), CteError> {
+        self.check_untransformed(name)?;
+        if len == 0 {
+            return self.try_write_blob(name, &[], 0, None).map(drop);
+        }
+        let mut staging = Staging::new()?;
+        let chunks: Vec<(usize, usize)> = stages(len).collect();
+        let device = device_ptr as *const u8;
+        let queue = |staging: &mut Staging, i: usize| -> Result<(), CteError> {
+            let (offset, n) = chunks[i];
+            let k = i % 2;
+            let dst = staging.buffers[k].slice(n).as_mut_ptr() as *mut c_void;
+            let src = unsafe { device.add(offset) } as *const c_void;
+            staging.copy(k, dst, src, n, stream)
+        };
+        queue(&mut staging, 0)?;
+        for (i, &(offset, n)) in chunks.iter().enumerate() {
+            if i + 1 < chunks.len() {
+                queue(&mut staging, i + 1)?;
+            }
+            let k = i % 2;
+            staging.wait(k)?;
+            let data = staging.buffers[k].slice(n);
+            self.try_write_blob(name, data, offset as u64, None)?;
+        }
+        Ok(())
+    }
+
+    /// Read the first `len` bytes of blob `name` into device (or managed)
+    /// memory at `device_ptr`. The copies are queued on `stream` and the call
+    /// returns once they are done, so later work on `stream` sees the data.
+    /// Fails with `NotFound` for a missing blob, `InvalidArgument` for one
+    /// shorter than `len` and `Unsupported` for one stored transformed.
+    ///
+    /// # Safety
+    ///
+    /// `device_ptr` must be valid for writes of `len` bytes on the current
+    /// device, and nothing else may use that memory until the call returns.
+    pub unsafe fn get_blob_device(
+        &self,
+        name: &str,
+        device_ptr: *mut c_void,
+        len: usize,
+        stream: CudaStream,
+    ) -> Result<(), CteError> {
+        if len == 0 {
+            return Ok(());
+        }
+        self.check_untransformed(name)?;
+        let size = self.get_blob_size(name);
+        if size == 0 {
+            return Err(CteError::NotFound {
+                blob: name.to_string(),
+            });
+        }
+        if size < len as u64 {
+            return Err(CteError::InvalidArgument(format!(
+                "'{}' has {} bytes, fewer than {}",
+                name, size, len
+            )));
+        }
+        let mut staging = Staging::new()?;
+        let device = device_ptr as *mut u8;
+        for (i, (offset, n)) in stages(len).enumerate() {
+            let k = i % 2;
+            // The copy out of this buffer two chunks ago must be done.
+            staging.wait(k)?;
+            let buf = staging.buffers[k].slice(n);
+            self.read_blob_into(name, buf, offset as u64)?;
+            let src = buf.as_ptr() as *const c_void;
+            let dst = unsafe { device.add(offset) } as *mut c_void;
+            staging.copy(k, dst, src, n, stream)?;
+        }
+        staging.wait(0)?;
+        staging.wait(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stages() {
+        assert_eq!(stages(0).count(), 0);
+        assert_eq!(stages(5).collect::<Vec<_>>(), [(0, 5)]);
+        assert_eq!(
+            stages(2 * STAGE_BYTES + 1).collect::<Vec<_>>(),
+            [
+                (0, STAGE_BYTES),
+                (STAGE_BYTES, STAGE_BYTES),
+                (2 * STAGE_BYTES, 1)
+            ]
+        );
+    }
+}
@@ -0,0 +1,181 @@
+//! Rate-limited background tag deletion.
+//!
+//! `Client::del_tag` hands the whole tag to the runtime in one call, which blocks
+//! the caller and competes with foreground I/O for as long as the runtime takes to
+//! drop millions of blobs. `Client::del_tag_async` instead deletes the tag's blobs
+//! one at a time from a background thread, paced to a configured rate, and only
+//! removes the (by then empty) tag at the end.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::{CancellationToken, Client, Tag};
+
+/// Options for `Client::del_tag_async`.
+#[derive(Debug, Clone)]
+pub struct DelTagOptions {
+    /// Upper bound on blob deletions per second; `None` deletes as fast as the
+    /// runtime allows (still off the caller's thread).
+    pub max_blobs_per_sec: Option<u32>,
+    /// Stops the deletion like `DelTagHandle::cancel`, for callers that share
+    /// one token across operations.
+    pub cancel: Option<CancellationToken>,
+}
+
+impl Default for DelTagOptions {
+    fn default() -> Self {
+        Self {
+            max_blobs_per_sec: Some(1000),
+            cancel: None,
+        }
+    }
+}
+
+/// Snapshot of a background tag deletion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DelTagProgress {
+    /// Blobs in the tag when the deletion started.
+    pub total: u64,
+    pub deleted: u64,
+    /// Blobs the runtime refused to delete; they are left in place.
+    pub failed: u64,
+    /// The worker has stopped, because it finished or was cancelled.
+    pub finished: bool,
+    /// The tag itself was removed. False while running, after a cancel, or if the
+    /// final `del_tag` failed.
+    pub tag_deleted: bool,
+}
+
+#[derive(Default)]
+struct State {
+    total: AtomicU64,
+    deleted: AtomicU64,
+    failed: AtomicU64,
+    finished: AtomicBool,
+    tag_deleted: AtomicBool,
+    cancel: CancellationToken,
+}
+
+/// Handle to a deletion started by `Client::del_tag_async`.
+///
+/// Dropping the handle does not stop the deletion; call `cancel` for that.
+pub struct DelTagHandle {
+    tag: String,
+    state: Arc<State>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl DelTagHandle {
+    /// Name of the tag being deleted.
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    pub fn progress(&self) -> DelTagProgress {
+        let s = &self.state;
+        DelTagProgress {
+            total: s.total.load(Ordering::Relaxed),
+            deleted: s.deleted.load(Ordering::Relaxed),
+            failed: s.failed.load(Ordering::Relaxed),
+            finished: s.finished.load(Ordering::Acquire),
+            tag_deleted: s.tag_deleted.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state.finished.load(Ordering::Acquire)
+    }
+
+    /// Ask the worker to stop after the blob it is currently deleting. Blobs not
+    /// yet reached, and the tag, are left in place.
+    pub fn cancel(&self) {
+        self.state.cancel.cancel();
+    }
+
+    /// Block until the worker stops and return the final progress.
+    pub fn wait(mut self) -> DelTagProgress {
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+        self.progress()
+    }
+}
+
+impl Client {
+    /// Delete tag `name` in the background, pacing blob deletions according to
+    /// `options`.
+    ///
+    /// Blobs go through `Tag::del_blob`, so they land in the trash when it is
+    /// enabled, and each deletion is recorded and delivered to subscribers as
+    /// usual. Blobs written to the tag after the deletion started are not
+    /// reached; the final `del_tag` removes them with the tag.
+    pub fn del_tag_async(name: &str, options: &DelTagOptions) -> DelTagHandle {
+        let state = Arc::new(State {
+            cancel: options.cancel.clone().unwrap_or_default(),
+            ..State::default()
+        });
+        let worker = {
+            let name = name.to_string();
+            let state = state.clone();
+            let rate = options.max_blobs_per_sec.filter(|&r| r > 0);
+            std::thread::spawn(move || {
+                run(&name, rate, &state);
+                state.finished.store(true, Ordering::Release);
+            })
+        };
+        DelTagHandle {
+            tag: name.to_string(),
+            state,
+            worker: Some(worker),
+        }
+    }
+}
+
+fn run(name: &str, rate: Option<u32>, state: &State) {
+    let tag = Tag::new(name);
+    let blobs = tag.blob_names();
+    state.total.store(blobs.len() as u64, Ordering::Relaxed);
+    let start = Instant::now();
+    for (i, blob) in blobs.iter().enumerate() {
+        if state.cancel.is_cancelled() {
+            return;
+        }
+        if let Some(rate) = rate {
+            let due = start + pace(i as u64, rate);
+            let now = Instant::now();
+            if due > now {
+                std::thread::sleep(due - now);
+            }
+        }
+        let counter = if tag.del_blob(blob) {
+            &state.deleted
+        } else {
+            &state.failed
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+    if !state.cancel.is_cancelled() {
+        state
+            .tag_deleted
+            .store(Client::del_tag(name), Ordering::Relaxed);
+    }
+}
+
+/// Time after the start at which deletion number `n` may begin.
+fn pace(n: u64, rate: u32) -> Duration {
+    Duration::from_nanos(n.saturating_mul(1_000_000_000) / rate as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pace() {
+        assert_eq!(pace(0, 100), Duration::ZERO);
+        assert_eq!(pace(50, 100), Duration::from_millis(500));
+        assert_eq!(pace(3, 1000), Duration::from_millis(3));
+    }
+}
@@ -0,0 +1,369 @@
+//! Diagnostics bundles for bug reports.
+//!
+//! Every call the wrapper makes into the shim through `ffi_guard` (puts, gets,
+//! reorganizes, batched puts and stats, tag creation, init) is kept in an
+//! in-memory journal: the last `JOURNAL_LEN` that finished, with their latency
+//! and outcome, and every one still waiting on the runtime. Alongside it are the
+//! last `ERRORS_LEN` errors reported on any thread, including timeouts.
+//!
+//! `Client::dump_diagnostics` writes these to a tar file together with the
+//! versions agreed at `init`, the runtime configuration and environment, a
+//! `Client::preflight` report, worker queues and target states, so a report of
+//! a slow or hung client says what it was waiting on. Runtime queries made for
+//! the bundle are bounded by `RUNTIME_QUERY_TIMEOUT`, so a stuck runtime doesn't
+//! stop the dump; calls still in flight show how long they have been waiting.
+//! Blob names and errors are percent-escaped as in the access log.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::archive::{ArchiveFormat, ArchiveWriter};
+use crate::audit::escape;
+use crate::ttl::now_ms;
+use crate::{
+    ffi, runtime_state, Client, CteError, CteTagId, OpOptions, PreflightReport, RuntimeState,
+};
+
+/// Finished calls kept in the journal.
+const JOURNAL_LEN: usize = 256;
+/// Errors kept for the bundle.
+const ERRORS_LEN: usize = 32;
+/// Deadline of each runtime query the dump makes.
+const RUNTIME_QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Environment variables that steer the runtime, the loader or the wrapper.
+const ENV_PREFIXES: [&str; 5] = ["CHI_", "WRP_", "HSHM_", "IOWARP_", "LD_LIBRARY_PATH"];
+
+#[derive(Debug, Clone)]
+struct JournalEntry {
+    started_ms: u64,
+    started: Instant,
+    elapsed: Duration,
+    thread: String,
+    op: &'static str,
+    tag: Option<CteTagId>,
+    blob: Option<String>,
+    /// The error, once finished with one.
+    error: Option<String>,
+}
+
+#[derive(Default)]
+struct Journal {
+    next_id: u64,
+    in_flight: BTreeMap<u64, JournalEntry>,
+    done: VecDeque<JournalEntry>,
+    errors: VecDeque<(u64, String, String)>,
+}
+
+static JOURNAL: Mutex<Journal> = Mutex::new(Journal {
+    next_id: 0,
+    in_flight: BTreeMap::new(),
+    done: VecDeque::new(),
+    errors: VecDeque::new(),
+});
+
+fn journal() -> std::sync::MutexGuard<'static, Journal> {
+    JOURNAL.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn thread_name() -> String {
+    let thread = std::thread::current();
+    match thread.name() {
+        Some(name) => name.to_string(),
+        None => format!("{:?}", thread.id()),
+    }
+}
+
+/// A journaled call in flight; finish it with `end`.
+pub(crate) struct InFlight {
+    id: u64,
+}
+
+/// Journal the start of `op`.
+pub(crate) fn begin(op: &'static str, tag: Option<CteTagId>, blob: Option<&str>) -> InFlight {
+    let entry = JournalEntry {
+        started_ms: now_ms(),
+        started: Instant::now(),
+        elapsed: Duration::ZERO,
+        thread: thread_name(),
+        op,
+        tag,
+        blob: blob.map(str::to_string),
+        error: None,
+    };
+    let mut journal = journal();
+    let id = journal.next_id;
+    journal.next_id += 1;
+    journal.in_flight.insert(id, entry);
+    InFlight { id }
+}
+
+impl InFlight {
+    /// Journal the end of the call, with its error if it failed.
+    pub(crate) fn end(self, error: Option<&CteError>) {
+        self.finish(error.map(CteError::to_string));
+    }
+
+    fn finish(&self, error: Option<String>) {
+        let mut journal = journal();
+        let Some(mut entry) = journal.in_flight.remove(&self.id) else {
+            return;
+        };
+        entry.elapsed = entry.started.elapsed();
+        entry.error = error;
+        if journal.done.len() == JOURNAL_LEN {
+            journal.done.pop_front();
+        }
+        journal.done.push_back(entry);
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        // Still in flight only if `end` wasn't reached.
+        self.finish(Some("unwound by a panic".into()));
+    }
+}
+
+/// Keep `e` among the errors the bundle reports.
+pub(crate) fn note_error(e: &CteError) {
+    let mut journal = journal();
+    if journal.errors.len() == ERRORS_LEN {
+        journal.errors.pop_front();
+    }
+    journal
+        .errors
+        .push_back((now_ms(), thread_name(), e.to_string()));
+}
+
+fn tag_field(tag: Option<CteTagId>) -> String {
+    tag.map_or("-".into(), |t| format!("{}.{}", t.major, t.minor))
+}
+
+fn opt_field(s: Option<&str>) -> String {
+    s.map_or("-".into(), escape)
+}
+
+/// The journal as TSV: calls in flight, longest waiting first, then finished
+/// ones, oldest first.
+fn journal_tsv() -> String {
+    let journal = journal();
+    let mut out = String::from("state\tstarted_ms\telapsed_us\tthread\top\ttag\tblob\terror\n");
+    let mut in_flight: Vec<_> = journal.in_flight.values().collect();
+    in_flight.sort_by_key(|e| e.started);
+    let rows = in_flight
+        .into_iter()
+        .map(|e| ("waiting", e, e.started.elapsed()))
+        .chain(journal.done.iter().map(|e| {
+            let state = if e.error.is_some() { "error" } else { "ok" };
+            (state, e, e.elapsed)
+        }));
+    for (state, e, elapsed) in rows {
+        let _ = writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            state,
+            e.started_ms,
+            elapsed.as_micros(),
+            escape(&e.thread),
+            e.op,
+            tag_field(e.tag),
+            opt_field(e.blob.as_deref()),
+            opt_field(e.error.as_deref()),
+        );
+    }
+    out
+}
+
+fn errors_tsv() -> String {
+    let journal = journal();
+    let mut out = String::from("time_ms\tthread\terror\n");
+    for (ms, thread, error) in &journal.errors {
+        let _ = writeln!(out, "{}\t{}\t{}", ms, escape(thread), escape(error));
+    }
+    out
+}
+
+fn versions_txt() -> String {
+    let mut out = format!("wrapper\t{}\n", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(out, "state\t{:?}", runtime_state());
+    match Client::runtime_info() {
+        Some(info) => {
+            let _ = writeln!(out, "library\t{}", info.library);
+            let _ = writeln!(out, "runtime\t{}", info.runtime);
+            let _ = writeln!(out, "disabled\t{:?}", info.disabled);
+        }
+        None => out.push_str("handshake\tnot run\n"),
+    }
+    out
+}
+
+fn env_txt() -> String {
+    let mut vars: Vec<_> = std::env::vars()
+        .filter(|(k, _)| ENV_PREFIXES.iter().any(|p| k.starts_with(p)))
+        .collect();
+    vars.sort();
+    vars.iter().fold(String::new(), |mut out, (k, v)| {
+        let _ = writeln!(out, "{}={}", k, v);
+        out
+    })
+}
+
+fn preflight_tsv(report: &PreflightReport) -> String {
+    let mut out = String::from("status\tcheck\tdetail\tfix\n");
+    for c in &report.checks {
+        let _ = writeln!(
+            out,
+            "{}\t{}\t{}\t{}",
+            c.status,
+            escape(&c.name),
+            escape(&c.detail),
+            opt_field(c.fix.as_deref())
+        );
+    }
+    out
+}
+
+/// A runtime query under the dump's deadline, or why it has no answer.
+fn query<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    if runtime_state() == RuntimeState::Uninitialized {
+        return Err("not initialized".into());
+    }
+    Client::with_op_options(
+        &OpOptions {
+            timeout: RUNTIME_QUERY_TIMEOUT,
+        },
+        f,
+    )
+    .map_err(|e| e.to_string())
+}
+
+fn runtime_tsv() -> String {
+    let mut out = String::new();
+    match query(ffi::client_container_count) {
+        Ok(n) => {
+            let _ = writeln!(out, "containers\t{}\n", n);
+        }
+        Err(e) => {
+            let _ = writeln!(out, "containers\t{}\n", e);
+        }
+    }
+    out.push_str("worker\tqueued\tblocked\tretry\tprocessed\tactive\n");
+    match query(ffi::client_worker_stats) {
+        Ok(workers) => {
+            for w in workers {
+                let _ = writeln!(
+                    out,
+                    "{}\t{}\t{}\t{}\t{}\t{}",
+                    w.worker_id, w.queued, w.blocked, w.retry, w.processed, w.active
+                );
+            }
+        }
+        Err(e) => {
+            let _ = writeln!(out, "# {}", e);
+        }
+    }
+    out
+}
+
+fn targets_tsv() -> String {
+    let mut out = String::from("target\tscore\tremaining_space\tbytes_read\tbytes_written\n");
+    match query(Client::list_targets) {
+        Ok(targets) => {
+            for t in targets {
+                let _ = writeln!(
+                    out,
+                    "{}\t{}\t{}\t{}\t{}",
+                    escape(&t.name),
+                    t.score,
+                    t.remaining_space,
+                    t.bytes_read,
+                    t.bytes_written
+                );
+            }
+        }
+        Err(e) => {
+            let _ = writeln!(out, "# {}", e);
+        }
+    }
+    out
+}
+
+fn write_entry<W: io::Write>(
+    archive: &mut ArchiveWriter<W>,
+    path: &str,
+    data: &[u8],
+) -> io::Result<()> {
+    archive.begin(path, data.len() as u64, None)?;
+    archive.data(data)?;
+    archive.end()
+}
+
+impl Client {
+    /// Write a diagnostics bundle to `path` as a tar file, to attach to a bug
+    /// report (see `diag`). Needs no `init`; runtime sections then say so.
+    pub fn dump_diagnostics(path: impl AsRef<Path>) -> Result<(), CteError> {
+        let dir = "cte-diagnostics";
+        let preflight = Client::preflight("");
+        let mut entries = vec![
+            ("versions.tsv", versions_txt()),
+            ("journal.tsv", journal_tsv()),
+            ("errors.tsv", errors_tsv()),
+            ("runtime.tsv", runtime_tsv()),
+            ("targets.tsv", targets_tsv()),
+            ("preflight.tsv", preflight_tsv(&preflight)),
+            ("env.txt", env_txt()),
+        ];
+        if let Some(config) = &preflight.config {
+            let text = fs::read_to_string(config)
+                .unwrap_or_else(|e| format!("# {}: {}\n", config.display(), e));
+            entries.push(("config.yaml", text));
+        }
+        let out = BufWriter::new(File::create(path)?);
+        let mut archive = ArchiveWriter::new(out, ArchiveFormat::Tar);
+        for (name, text) in &entries {
+            write_entry(&mut archive, &format!("{}/{}", dir, name), text.as_bytes())?;
+        }
+        archive.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal() {
+        let done = begin(
+            "put_blob",
+            Some(CteTagId { major: 1, minor: 2 }),
+            Some("a b"),
+        );
+        let waiting = begin("get_blob", None, Some("stuck"));
+        done.end(Some(&CteError::InvalidArgument("bad".into())));
+        let row = |state: &str, op: &str| {
+            journal_tsv()
+                .lines()
+                .find(|l| l.starts_with(state) && l.contains(op))
+                .map(|l| l.split('\t').map(str::to_string).collect::<Vec<_>>())
+        };
+        let failed = row("error", "put_blob").expect("finished call journaled");
+        assert_eq!(&failed[5..], ["1.2", "a%20b", "invalid%20argument:%20bad"]);
+        let stuck = row("waiting", "get_blob").expect("call in flight journaled");
+        assert_eq!(stuck[6], "stuck");
+        drop(waiting);
+        assert!(row("waiting", "get_blob").is_none());
+
+        note_error(&CteError::Cancelled);
+        assert!(errors_tsv().contains("operation%20cancelled"));
+        for _ in 0..JOURNAL_LEN + 1 {
+            begin("put_blob", None, None).end(None);
+        }
+        assert_eq!(journal().done.len(), JOURNAL_LEN);
+    }
+}
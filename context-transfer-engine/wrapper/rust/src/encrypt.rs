@@ -0,0 +1,423 @@
+//! Client-side encryption at rest (`encryption` feature).
+//!
+//! With a `KeyProvider` registered, every put to a tag the provider has a key for
+//! is sealed with AES-256-GCM in the wrapper, after compression, so the runtime
+//! and its storage tiers only ever hold ciphertext. `Tag::get` opens the data
+//! again. `get_blob`/`get_blob_size` keep returning the stored (encrypted) form.
+//!
+//! The provider's key is a per-tag master key and never touches blob data.
+//! Blobs are sealed with a random data key, generated on the tag's first
+//! encrypted put, that is stored in the tag's metadata sidecar wrapped (itself
+//! AES-GCM encrypted) by the master key. Data keys are versioned:
+//! `Tag::rotate_key` adds a new version that later puts use, and older blobs are
+//! re-encrypted under it by `Tag::reencrypt`, or one at a time with
+//! `Tag::reencrypt_blob`; until then they stay readable with their old key.
+//! Reads never rewrite a blob. Rotating the master key only needs the data keys re-wrapped
+//! (`Tag::rewrap_keys`); blob data is untouched. Blobs sealed directly with the
+//! provider key by earlier wrappers (key version 0) remain readable.
+//!
+//! Like compression, encryption needs the whole blob: puts to an encrypted tag
+//! must be at offset 0. Data key creation and rotation update the tag sidecar
+//! under the in-process metadata lock only, so two processes rotating or making
+//! the first encrypted put to the same tag at once can lose a key. Builds without
+//! the feature can't read encrypted blobs and fail with `CteError::Unsupported`.
+
+use crate::{CteError, Tag};
+
+/// How an encrypted blob is stored, kept in its metadata sidecar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Encrypted {
+    pub nonce: [u8; 12],
+    /// Bytes of ciphertext (including the GCM tag) at the start of the blob.
+    pub stored_size: u64,
+    /// Data key version the blob is sealed with; 0 is the master key itself.
+    pub key_version: u32,
+}
+
+impl Encrypted {
+    /// Sidecar encoding: nonce, stored size, key version.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut out = self.nonce.to_vec();
+        out.extend_from_slice(&self.stored_size.to_le_bytes());
+        out.extend_from_slice(&self.key_version.to_le_bytes());
+        out
+    }
+
+    pub(crate) fn decode(buf: &[u8]) -> Result<Self, String> {
+        let key_version = match buf.len() {
+            // Written before data keys existed.
+            20 => 0,
+            24 => u32::from_le_bytes(buf[20..24].try_into().unwrap()),
+            _ => return Err("bad encryption field".into()),
+        };
+        Ok(Self {
+            nonce: buf[..12].try_into().unwrap(),
+            stored_size: u64::from_le_bytes(buf[12..20].try_into().unwrap()),
+            key_version,
+        })
+    }
+}
+
+/// Supplies the AES-256 master key for each tag.
+///
+/// Called on every put and get of an encrypted tag, so providers backed by a key
+/// service should cache. A provider error fails the operation rather than falling
+/// back to plaintext.
+#[cfg(feature = "encryption")]
+pub trait KeyProvider: Send + Sync {
+    /// Master key for `tag`, or `None` to store the tag's blobs unencrypted.
+    fn key(&self, tag: &str) -> Result<Option<[u8; 32]>, CteError>;
+}
+
+#[cfg(feature = "encryption")]
+impl<F> KeyProvider for F
+where
+    F: Fn(&str) -> Result<Option<[u8; 32]>, CteError> + Send + Sync,
+{
+    fn key(&self, tag: &str) -> Result<Option<[u8; 32]>, CteError> {
+        self(tag)
+    }
+}
+
+#[cfg(feature = "encryption")]
+mod imp {
+    use std::collections::HashMap;
+    use std::sync::{Mutex, RwLock};
+
+    use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+    use aes_gcm::{Aes256Gcm, Key, Nonce};
+
+    use super::{Encrypted, KeyProvider};
+    use crate::meta::{meta_lock, TagMeta};
+    use crate::{BulkOptions, BulkReport, CteError, Tag};
+
+    /// Unwrapped data keys by (tag, version, wrapped key).
+    type KeyCache = HashMap<(String, u32, Vec<u8>), [u8; 32]>;
+
+    static PROVIDER: RwLock<Option<Box<dyn KeyProvider>>> = RwLock::new(None);
+    /// Keyed by the wrapped key too: a tag deleted and re-created, here or by
+    /// another process, starts over at version 1 with a new key, which must not
+    /// hit the old one's entry. `observe` drops a deleted tag's entries.
+    static DATA_KEYS: Mutex<Option<KeyCache>> = Mutex::new(None);
+
+    /// Register the process-wide key provider, replacing any previous one.
+    pub fn set_key_provider(provider: impl KeyProvider + 'static) {
+        *PROVIDER.write().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(provider));
+    }
+
+    /// Remove the key provider. Later puts are stored unencrypted and encrypted
+    /// blobs can no longer be read.
+    pub fn clear_key_provider() {
+        *PROVIDER.write().unwrap_or_else(|e| e.into_inner()) = None;
+        *DATA_KEYS.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    /// Drop the cached data keys `event` makes stale. Called for every local
+    /// event.
+    pub(crate) fn observe(event: &crate::Event) {
+        if let crate::Event::TagDeleted { tag } = event {
+            if let Some(keys) = DATA_KEYS.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+                keys.retain(|(name, _, _), _| name != tag);
+            }
+        }
+    }
+
+    pub(crate) fn master_key(tag: &str) -> Result<Option<[u8; 32]>, CteError> {
+        let provider = PROVIDER.read().unwrap_or_else(|e| e.into_inner());
+        match provider.as_ref() {
+            Some(p) => p.key(tag),
+            None => Ok(None),
+        }
+    }
+
+    fn cipher(key: &[u8; 32]) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+    }
+
+    pub(crate) fn seal(
+        key: &[u8; 32],
+        key_version: u32,
+        data: &[u8],
+    ) -> Result<(Vec<u8>, Encrypted), CteError> {
+        let generated = Aes256Gcm::generate_nonce(&mut OsRng);
+        let mut nonce = [0u8; 12];
+        nonce.copy_from_slice(&generated);
+        let sealed = cipher(key)
+            .encrypt(Nonce::from_slice(&nonce), data)
+            .map_err(|e| CteError::Encryption(format!("encrypt failed: {}", e)))?;
+        let info = Encrypted {
+            nonce,
+            stored_size: sealed.len() as u64,
+            key_version,
+        };
+        Ok((sealed, info))
+    }
+
+    pub(crate) fn open(
+        key: &[u8; 32],
+        blob: &str,
+        data: &[u8],
+        info: &Encrypted,
+    ) -> Result<Vec<u8>, CteError> {
+        cipher(key)
+            .decrypt(Nonce::from_slice(&info.nonce), data)
+            .map_err(|_| {
+                CteError::Encryption(format!(
+                    "'{}' failed authentication: wrong key or corrupt data",
+                    blob
+                ))
+            })
+    }
+
+    /// Wrapped form of a data key: nonce followed by the sealed key.
+    fn wrap(master: &[u8; 32], dek: &[u8; 32]) -> Result<Vec<u8>, CteError> {
+        let (sealed, info) = seal(master, 0, dek)?;
+        let mut out = info.nonce.to_vec();
+        out.extend_from_slice(&sealed);
+        Ok(out)
+    }
+
+    fn unwrap(master: &[u8; 32], tag: &str, wrapped: &[u8]) -> Result<[u8; 32], CteError> {
+        let bad = || {
+            CteError::Encryption(format!(
+                "data key of tag '{}' won't unwrap: wrong master key or corrupt metadata",
+                tag
+            ))
+        };
+        if wrapped.len() < 12 {
+            return Err(bad());
+        }
+        let info = Encrypted {
+            nonce: wrapped[..12].try_into().unwrap(),
+            stored_size: (wrapped.len() - 12) as u64,
+            key_version: 0,
+        };
+        let dek = open(master, tag, &wrapped[12..], &info).map_err(|_| bad())?;
+        dek.try_into().map_err(|_| bad())
+    }
+
+    fn random_key() -> [u8; 32] {
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&Aes256Gcm::generate_key(&mut OsRng));
+        key
+    }
+
+    impl Tag {
+        fn master_key_required(&self) -> Result<[u8; 32], CteError> {
+            master_key(self.name())?.ok_or_else(|| {
+                CteError::Encryption(format!("no key is available for tag '{}'", self.name()))
+            })
+        }
+
+        /// Data key `version` from the tag sidecar, unwrapped unless cached.
+        pub(crate) fn data_key(
+            &self,
+            master: &[u8; 32],
+            version: u32,
+        ) -> Result<[u8; 32], CteError> {
+            if version == 0 {
+                return Ok(*master);
+            }
+            let meta = self.load_tag_meta()?;
+            let wrapped = meta.data_keys.get(&version).ok_or_else(|| {
+                CteError::Encryption(format!(
+                    "tag '{}' has no data key version {}",
+                    self.name(),
+                    version
+                ))
+            })?;
+            let cache_key = (self.name().to_string(), version, wrapped.clone());
+            if let Some(key) = DATA_KEYS
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .as_ref()
+                .and_then(|m| m.get(&cache_key))
+            {
+                return Ok(*key);
+            }
+            let key = unwrap(master, self.name(), wrapped)?;
+            DATA_KEYS
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .get_or_insert_with(HashMap::new)
+                .insert(cache_key, key);
+            Ok(key)
+        }
+
+        /// Newest data key version, creating version 1 if the tag has none.
+        /// Caller holds the meta lock.
+        pub(crate) fn current_key_version(&self, master: &[u8; 32]) -> Result<u32, CteError> {
+            let mut meta = self.load_tag_meta()?;
+            if let Some(&version) = meta.data_keys.keys().next_back() {
+                return Ok(version);
+            }
+            self.add_data_key(master, &mut meta)
+        }
+
+        fn add_data_key(&self, master: &[u8; 32], meta: &mut TagMeta) -> Result<u32, CteError> {
+            let version = meta.data_keys.keys().next_back().map_or(1, |v| v + 1);
+            meta.data_keys.insert(version, wrap(master, &random_key())?);
+            self.store_tag_meta(meta)?;
+            Ok(version)
+        }
+
+        /// Add a new data key version for this tag and return it.
+        ///
+        /// Later puts are sealed with the new key. Existing blobs stay readable
+        /// with their old key until `reencrypt` (or `reencrypt_blob`) seals them
+        /// with the new one.
+        pub fn rotate_key(&self) -> Result<u32, CteError> {
+            let master = self.master_key_required()?;
+            let _guard = meta_lock();
+            let mut meta = self.load_tag_meta()?;
+            self.add_data_key(&master, &mut meta)
+        }
+
+        /// Re-wrap every data key of this tag, currently wrapped by `previous`
+        /// master key, with the provider's current one. Returns the number of
+        /// keys re-wrapped. Blobs are untouched.
+        ///
+        /// Blobs written before data keys existed (key version 0) are sealed
+        /// with the master key itself; `reencrypt` them first, while the
+        /// provider still returns `previous`.
+        pub fn rewrap_keys(&self, previous: &[u8; 32]) -> Result<usize, CteError> {
+            let master = self.master_key_required()?;
+            let _guard = meta_lock();
+            let mut meta = self.load_tag_meta()?;
+            for wrapped in meta.data_keys.values_mut() {
+                let dek = unwrap(previous, self.name(), wrapped)?;
+                *wrapped = wrap(&master, &dek)?;
+            }
+            self.store_tag_meta(&meta)?;
+            Ok(meta.data_keys.len())
+        }
+
+        /// Re-encrypt every blob not sealed with the newest data key.
+        pub fn reencrypt(&self, options: &BulkOptions) -> Result<BulkReport, CteError> {
+            let mut report = BulkReport::new(options);
+            let master = self.master_key_required()?;
+            let current = {
+                let _guard = meta_lock();
+                self.current_key_version(&master)?
+            };
+            for name in self.blob_names() {
+                let Some(info) = self.load_meta(&name)?.and_then(|m| m.encrypted) else {
+                    continue;
+                };
+                if info.key_version >= current {
+                    continue;
+                }
+                if !options.dry_run && !self.reencrypt_blob(&name)? {
+                    continue;
+                }
+                report.push(self.name(), &name, info.stored_size);
+            }
+            Ok(report)
+        }
+
+        /// Re-seal `name` with the newest data key if it uses an older one,
+        /// without bumping its generation. Returns whether it was rewritten;
+        /// `reencrypt` does this for every blob of the tag.
+        pub fn reencrypt_blob(&self, name: &str) -> Result<bool, CteError> {
+            let master = self.master_key_required()?;
+            let _guard = meta_lock();
+            let current = self.current_key_version(&master)?;
+            let mut meta = match self.load_meta(name)? {
+                Some(meta) => meta,
+                None => return Ok(false),
+            };
+            let Some(info) = meta.encrypted.filter(|e| e.key_version < current) else {
+                return Ok(false);
+            };
+            let stored = crate::ffi_guard::tag_get_blob(&self.inner, name, info.stored_size, 0)?;
+            let old_key = self.data_key(&master, info.key_version)?;
+            let plain = open(&old_key, name, stored.as_slice(), &info)?;
+            let new_key = self.data_key(&master, current)?;
+            let (sealed, new_info) = seal(&new_key, current, &plain)?;
+            let score = self.get_blob_score(name);
+            crate::ffi_guard::tag_put_blob(&self.inner, name, &sealed, 0, score)?;
+            meta.encrypted = Some(new_info);
+            if let Some(sum) = &meta.checksum {
+                let size = self.get_blob_size(name);
+                meta.checksum = Some(self.compute_checksum(name, sum.algorithm, size)?);
+            }
+            self.store_meta(name, &meta)?;
+            Ok(true)
+        }
+    }
+}
+
+#[cfg(feature = "encryption")]
+pub(crate) use imp::observe;
+#[cfg(feature = "encryption")]
+pub use imp::{clear_key_provider, set_key_provider};
+
+impl Tag {
+    /// Encrypt a put's payload if this tag has a key. Returns `None` for tags
+    /// stored in plaintext. Caller holds the meta lock.
+    #[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+    pub(crate) fn seal(&self, data: &[u8]) -> Result<Option<(Vec<u8>, Encrypted)>, CteError> {
+        #[cfg(feature = "encryption")]
+        if let Some(master) = imp::master_key(self.name())? {
+            let version = self.current_key_version(&master)?;
+            let key = self.data_key(&master, version)?;
+            return imp::seal(&key, version, data).map(Some);
+        }
+        Ok(None)
+    }
+
+    /// True if puts to this tag are encrypted.
+    pub(crate) fn is_encrypted(&self) -> Result<bool, CteError> {
+        #[cfg(feature = "encryption")]
+        return Ok(imp::master_key(self.name())?.is_some());
+        #[cfg(not(feature = "encryption"))]
+        Ok(false)
+    }
+
+    /// Decrypt the stored bytes of `blob`, with whichever data key sealed it.
+    #[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+    pub(crate) fn open(
+        &self,
+        blob: &str,
+        data: &[u8],
+        info: &Encrypted,
+    ) -> Result<Vec<u8>, CteError> {
+        #[cfg(feature = "encryption")]
+        {
+            let master = imp::master_key(self.name())?.ok_or_else(|| {
+                CteError::Encryption(format!(
+                    "'{}' is encrypted but no key is available for tag '{}'",
+                    blob,
+                    self.name()
+                ))
+            })?;
+            let key = self.data_key(&master, info.key_version)?;
+            imp::open(&key, blob, data, info)
+        }
+        #[cfg(not(feature = "encryption"))]
+        Err(CteError::Unsupported(format!(
+            "'{}' is encrypted; reading it needs the `encryption` feature",
+            blob
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypted_roundtrip() {
+        let info = Encrypted {
+            nonce: [9; 12],
+            stored_size: 4112,
+            key_version: 3,
+        };
+        assert_eq!(Encrypted::decode(&info.encode()).unwrap(), info);
+        // Records from before data keys decode as version 0.
+        assert_eq!(
+            Encrypted::decode(&info.encode()[..20]).unwrap().key_version,
+            0
+        );
+    }
+}
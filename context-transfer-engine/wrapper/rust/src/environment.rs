@@ -0,0 +1,186 @@
+//! Recording the storage environment that produced a dataset.
+//!
+//! `Client::capture_environment` writes an `Environment` as a JSON blob next to
+//! a job's results, so whoever reads them later knows which wrapper, client
+//! library and runtime stored them, under which configuration files (by
+//! SHA-256 of their contents), placement and spill policies, and target
+//! topology. Nothing in it changes while the deployment doesn't: targets are
+//! listed by name with their tier score but not their fill, keys come in a
+//! fixed order and no time is recorded, so the same setup always captures the
+//! same bytes and two captures can be compared with `==` or by
+//! `Environment::digest`. Configuration files are read on this host; the
+//! runtime's is the one `Client::preflight` would find.
+
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::ffi_c::json_string;
+use crate::{
+    ffi, placement, preflight, spill, Checksum, ChecksumAlgorithm, Client, CteError, PutOptions,
+    Tag,
+};
+
+static INIT_CONFIG: OnceLock<String> = OnceLock::new();
+
+/// Remember the configuration `init` succeeded with.
+pub(crate) fn record_config(path: &str) {
+    let _ = INIT_CONFIG.set(path.to_string());
+}
+
+/// A configuration file and the SHA-256 of its contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigDigest {
+    pub path: String,
+    /// Lowercase hex.
+    pub sha256: String,
+}
+
+/// The storage environment of this process (see `environment`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Environment {
+    /// `RuntimeInfo::wrapper`.
+    pub wrapper: String,
+    /// `RuntimeInfo::library`.
+    pub library: String,
+    /// `RuntimeInfo::runtime`.
+    pub runtime: String,
+    /// The configuration passed to `init`, if any, then the runtime's.
+    pub configs: Vec<ConfigDigest>,
+    /// `PlacementPolicy::describe` of the registered policy.
+    pub placement: Option<String>,
+    /// `SpillPolicy::min_score` of the spill policy, if one is set.
+    pub spill_min_score: Option<f32>,
+    /// Metadata containers of the CTE pool.
+    pub containers: u32,
+    /// Name and score of every target, sorted by name.
+    pub targets: Vec<(String, f32)>,
+}
+
+impl Environment {
+    /// The JSON form `capture_environment` stores.
+    pub fn to_json(&self) -> String {
+        let opt_str = |s: &Option<String>| s.as_deref().map_or("null".into(), json_string);
+        let configs: Vec<String> = self
+            .configs
+            .iter()
+            .map(|c| {
+                format!(
+                    "{{\"path\":{},\"sha256\":{}}}",
+                    json_string(&c.path),
+                    json_string(&c.sha256)
+                )
+            })
+            .collect();
+        let targets: Vec<String> = self
+            .targets
+            .iter()
+            .map(|(name, score)| format!("{{\"name\":{},\"score\":{}}}", json_string(name), score))
+            .collect();
+        format!(
+            "{{\"wrapper\":{},\"library\":{},\"runtime\":{},\"configs\":[{}],\
+             \"placement\":{},\"spill_min_score\":{},\"containers\":{},\"targets\":[{}]}}",
+            json_string(&self.wrapper),
+            json_string(&self.library),
+            json_string(&self.runtime),
+            configs.join(","),
+            opt_str(&self.placement),
+            self.spill_min_score
+                .map_or("null".into(), |s| s.to_string()),
+            self.containers,
+            targets.join(",")
+        )
+    }
+
+    /// SHA-256 of `to_json`, in lowercase hex: equal for equal environments.
+    pub fn digest(&self) -> String {
+        Checksum::compute(ChecksumAlgorithm::Sha256, self.to_json().as_bytes()).to_hex()
+    }
+}
+
+fn digest_file(path: &Path) -> Result<ConfigDigest, CteError> {
+    let data = fs::read(path)?;
+    Ok(ConfigDigest {
+        path: path.display().to_string(),
+        sha256: Checksum::compute(ChecksumAlgorithm::Sha256, &data).to_hex(),
+    })
+}
+
+impl Client {
+    /// The storage environment of this process (see `environment`). Fails
+    /// before `init`, or with `CteError::Io` if a configuration file can't be
+    /// read.
+    pub fn environment() -> Result<Environment, CteError> {
+        let info = Client::runtime_info()
+            .ok_or_else(|| CteError::Unsupported("CTE is not initialized".into()))?;
+        let mut configs = Vec::new();
+        let init_config = INIT_CONFIG.get().filter(|p| !p.is_empty());
+        if let Some(path) = init_config {
+            configs.push(digest_file(Path::new(path))?);
+        }
+        if let (Some(path), _) = preflight::locate("") {
+            let runtime = digest_file(&path)?;
+            if !configs.contains(&runtime) {
+                configs.push(runtime);
+            }
+        }
+        let mut targets: Vec<(String, f32)> = Client::list_targets()
+            .into_iter()
+            .map(|t| (t.name, t.score))
+            .collect();
+        targets.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(Environment {
+            wrapper: info.wrapper.to_string(),
+            library: info.library,
+            runtime: info.runtime,
+            configs,
+            placement: placement::describe_policy(),
+            spill_min_score: spill::policy().map(|p| p.min_score),
+            containers: ffi::client_container_count(),
+            targets,
+        })
+    }
+
+    /// Store `Client::environment` in `tag` as blob `name`, as JSON, and
+    /// return it.
+    pub fn capture_environment(tag: &Tag, name: &str) -> Result<Environment, CteError> {
+        let env = Client::environment()?;
+        tag.put(name, env.to_json().as_bytes(), &PutOptions::default())?;
+        Ok(env)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_environment_json() {
+        let env = Environment {
+            wrapper: "0.3.0".into(),
+            library: "2.1.0".into(),
+            runtime: "2.1.0".into(),
+            configs: vec![ConfigDigest {
+                path: "/etc/cte \"prod\".yaml".into(),
+                sha256: "ab".into(),
+            }],
+            placement: None,
+            spill_min_score: Some(0.25),
+            containers: 4,
+            targets: vec![("hdd".into(), 0.1), ("ram".into(), 1.0)],
+        };
+        assert_eq!(
+            env.to_json(),
+            "{\"wrapper\":\"0.3.0\",\"library\":\"2.1.0\",\"runtime\":\"2.1.0\",\
+             \"configs\":[{\"path\":\"/etc/cte \\\"prod\\\".yaml\",\"sha256\":\"ab\"}],\
+             \"placement\":null,\"spill_min_score\":0.25,\"containers\":4,\
+             \"targets\":[{\"name\":\"hdd\",\"score\":0.1},{\"name\":\"ram\",\"score\":1}]}"
+        );
+        assert_eq!(env.digest(), env.clone().digest());
+        let moved = Environment {
+            targets: vec![("hdd".into(), 0.2), ("ram".into(), 1.0)],
+            ..env.clone()
+        };
+        assert_ne!(moved.digest(), env.digest());
+    }
+}
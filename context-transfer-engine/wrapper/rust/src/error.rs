@@ -0,0 +1,180 @@
+//! Error type shared by the fallible parts of the wrapper API.
+
+use std::fmt;
+
+use crate::RuntimeCode;
+
+/// Errors returned by CTE wrapper operations.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum CteError {
+    /// A fenced append came from a producer epoch older than the blob's current one.
+    Fenced {
+        blob: String,
+        producer_epoch: u64,
+        current_epoch: u64,
+    },
+    /// A blob's generation didn't match the caller's precondition.
+    GenerationMismatch {
+        blob: String,
+        expected: u64,
+        actual: u64,
+    },
+    /// A blob's data no longer matches the checksum recorded when it was written.
+    /// Digests are formatted as `algorithm:hex`.
+    ChecksumMismatch {
+        blob: String,
+        expected: String,
+        actual: String,
+    },
+    /// A typed object was written with a schema other than the reader's (see
+    /// `Tag::get_typed`); `actual` is empty if it recorded none.
+    SchemaMismatch {
+        blob: String,
+        expected: String,
+        actual: String,
+    },
+    /// The named blob does not exist.
+    NotFound { blob: String },
+    /// A handoff token was redeemed after its lease ran out.
+    LeaseExpired { blob: String },
+    /// Wrapper metadata stored alongside a blob could not be decoded.
+    CorruptMetadata { blob: String, reason: String },
+    /// A key was unavailable, or encrypted data failed to decrypt.
+    Encryption(String),
+    /// The operation needs something this tag or deployment doesn't provide.
+    Unsupported(String),
+    /// An argument was rejected before reaching the runtime.
+    InvalidArgument(String),
+    /// A CUDA call failed while copying to or from device memory (see
+    /// `Tag::put_blob_device`).
+    Device(String),
+    /// A name the runtime returned isn't UTF-8 and `NamePolicy::Error` is set.
+    InvalidName { name: Vec<u8> },
+    /// A read was over `RequestLimits::max_read` (`limit`), or its buffer
+    /// couldn't be allocated (`limit` is `None`).
+    TooLarge {
+        blob: String,
+        size: u64,
+        limit: Option<u64>,
+    },
+    /// The runtime didn't answer within `OpOptions::timeout`.
+    Timeout(std::time::Duration),
+    /// The operation was stopped through its `CancellationToken`.
+    Cancelled,
+    /// `op` failed below the bridge: the shim or runtime threw, or a panic was
+    /// caught before it reached foreign code (see `ffi_guard`).
+    Runtime {
+        code: RuntimeCode,
+        message: String,
+        op: &'static str,
+        blob: Option<String>,
+    },
+    /// A local filesystem operation failed.
+    Io(std::io::Error),
+}
+
+impl fmt::Display for CteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CteError::Fenced {
+                blob,
+                producer_epoch,
+                current_epoch,
+            } => write!(
+                f,
+                "append to '{}' rejected: producer epoch {} is older than current epoch {}",
+                blob, producer_epoch, current_epoch
+            ),
+            CteError::GenerationMismatch {
+                blob,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "'{}' is at generation {}, expected {}",
+                blob, actual, expected
+            ),
+            CteError::ChecksumMismatch {
+                blob,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "checksum mismatch for '{}': expected {}, got {}",
+                blob, expected, actual
+            ),
+            CteError::SchemaMismatch {
+                blob,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "'{}' has schema '{}', expected '{}'",
+                blob, actual, expected
+            ),
+            CteError::NotFound { blob } => write!(f, "blob '{}' not found", blob),
+            CteError::LeaseExpired { blob } => {
+                write!(f, "handoff token for '{}' has expired", blob)
+            }
+            CteError::CorruptMetadata { blob, reason } => {
+                write!(f, "corrupt metadata for '{}': {}", blob, reason)
+            }
+            CteError::Encryption(msg) => write!(f, "encryption: {}", msg),
+            CteError::Unsupported(msg) => write!(f, "unsupported: {}", msg),
+            CteError::InvalidArgument(msg) => write!(f, "invalid argument: {}", msg),
+            CteError::Device(msg) => write!(f, "CUDA: {}", msg),
+            CteError::InvalidName { name } => {
+                write!(f, "name '{}' is not valid UTF-8", name.escape_ascii())
+            }
+            CteError::TooLarge {
+                blob,
+                size,
+                limit: Some(limit),
+            } => write!(
+                f,
+                "read of {} bytes of '{}' exceeds the limit of {} bytes",
+                size, blob, limit
+            ),
+            CteError::TooLarge {
+                blob,
+                size,
+                limit: None,
+            } => write!(
+                f,
+                "read of {} bytes of '{}' doesn't fit in memory",
+                size, blob
+            ),
+            CteError::Timeout(after) => write!(f, "runtime didn't answer within {:?}", after),
+            CteError::Cancelled => write!(f, "operation cancelled"),
+            CteError::Runtime {
+                message,
+                op,
+                blob: Some(blob),
+                ..
+            } => write!(f, "{} of '{}' failed: {}", op, blob, message),
+            CteError::Runtime {
+                message,
+                op,
+                blob: None,
+                ..
+            } => write!(f, "{} failed: {}", op, message),
+            CteError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CteError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for CteError {
+    fn from(e: std::io::Error) -> Self {
+        CteError::Io(e)
+    }
+}
@@ -0,0 +1,469 @@
+//! Blob and tag lifecycle events.
+//!
+//! Mutations made through this process's wrapper are delivered to subscribers
+//! synchronously, on the thread that made them. A filter with `poll_remote` set
+//! also starts a background poller that picks up changes made by other clients:
+//! tag creation/deletion from a diff of `tag_query` results, and blob changes
+//! from the change logs of tags that have one (`Tag::enable_change_log`).
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, RwLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::ffi_c::json_string;
+use crate::oplog::writer_id;
+use crate::{ChangeKind, Client, Tag};
+
+/// A lifecycle event.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    BlobPut {
+        tag: String,
+        blob: String,
+        offset: u64,
+        size: u64,
+    },
+    BlobDeleted {
+        tag: String,
+        blob: String,
+    },
+    BlobReorganized {
+        tag: String,
+        blob: String,
+        score: f32,
+    },
+    /// The blob's TTL runs out at `expires_at`, within the lead time
+    /// `set_expiry_warning` set.
+    BlobExpiring {
+        tag: String,
+        blob: String,
+        expires_at: SystemTime,
+    },
+    TagCreated {
+        tag: String,
+    },
+    TagDeleted {
+        tag: String,
+    },
+}
+
+/// Discriminant of an `Event`, for filtering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    BlobPut,
+    BlobDeleted,
+    BlobReorganized,
+    BlobExpiring,
+    TagCreated,
+    TagDeleted,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 6] = [
+        EventKind::BlobPut,
+        EventKind::BlobDeleted,
+        EventKind::BlobReorganized,
+        EventKind::BlobExpiring,
+        EventKind::TagCreated,
+        EventKind::TagDeleted,
+    ];
+
+    /// The name events of this kind carry as their JSON `kind` field.
+    pub fn name(self) -> &'static str {
+        match self {
+            EventKind::BlobPut => "blob_put",
+            EventKind::BlobDeleted => "blob_deleted",
+            EventKind::BlobReorganized => "blob_reorganized",
+            EventKind::BlobExpiring => "blob_expiring",
+            EventKind::TagCreated => "tag_created",
+            EventKind::TagDeleted => "tag_deleted",
+        }
+    }
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::BlobPut { .. } => EventKind::BlobPut,
+            Event::BlobDeleted { .. } => EventKind::BlobDeleted,
+            Event::BlobReorganized { .. } => EventKind::BlobReorganized,
+            Event::BlobExpiring { .. } => EventKind::BlobExpiring,
+            Event::TagCreated { .. } => EventKind::TagCreated,
+            Event::TagDeleted { .. } => EventKind::TagDeleted,
+        }
+    }
+
+    /// Name of the tag the event concerns.
+    pub fn tag(&self) -> &str {
+        match self {
+            Event::BlobPut { tag, .. }
+            | Event::BlobDeleted { tag, .. }
+            | Event::BlobReorganized { tag, .. }
+            | Event::BlobExpiring { tag, .. }
+            | Event::TagCreated { tag }
+            | Event::TagDeleted { tag } => tag,
+        }
+    }
+
+    /// Blob name, for blob events.
+    pub fn blob(&self) -> Option<&str> {
+        match self {
+            Event::BlobPut { blob, .. }
+            | Event::BlobDeleted { blob, .. }
+            | Event::BlobReorganized { blob, .. }
+            | Event::BlobExpiring { blob, .. } => Some(blob),
+            Event::TagCreated { .. } | Event::TagDeleted { .. } => None,
+        }
+    }
+}
+
+impl Event {
+    /// The event as a JSON object: its `kind`, `tag`, `blob` for blob events
+    /// and the variant's other fields, with `expires_at` as `expires_ms` since
+    /// the Unix epoch.
+    pub(crate) fn json(&self) -> String {
+        let mut out = format!(
+            "{{\"kind\":\"{}\",\"tag\":{}",
+            self.kind().name(),
+            json_string(self.tag())
+        );
+        if let Some(blob) = self.blob() {
+            out += &format!(",\"blob\":{}", json_string(blob));
+        }
+        match self {
+            Event::BlobPut { offset, size, .. } => {
+                out += &format!(",\"offset\":{},\"size\":{}", offset, size)
+            }
+            Event::BlobReorganized { score, .. } => out += &format!(",\"score\":{}", score),
+            Event::BlobExpiring { expires_at, .. } => {
+                let ms = expires_at
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_millis());
+                out += &format!(",\"expires_ms\":{}", ms)
+            }
+            _ => {}
+        }
+        out + "}"
+    }
+}
+
+/// Which events a subscription receives. The default matches everything.
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    tag: Option<String>,
+    blob_prefix: Option<String>,
+    kinds: Option<Vec<EventKind>>,
+    poll_interval: Option<Duration>,
+}
+
+impl EventFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only events for the tag with exactly this name.
+    pub fn tag(mut self, name: &str) -> Self {
+        self.tag = Some(name.to_string());
+        self
+    }
+
+    /// Only blob events whose blob name starts with `prefix` (tag events still pass).
+    pub fn blob_prefix(mut self, prefix: &str) -> Self {
+        self.blob_prefix = Some(prefix.to_string());
+        self
+    }
+
+    /// Only events of the given kinds.
+    pub fn kinds(mut self, kinds: &[EventKind]) -> Self {
+        self.kinds = Some(kinds.to_vec());
+        self
+    }
+
+    /// Also poll the runtime every `interval` for changes made by other clients.
+    pub fn poll_remote(mut self, interval: Duration) -> Self {
+        self.poll_interval = Some(interval);
+        self
+    }
+
+    pub fn matches(&self, event: &Event) -> bool {
+        if let Some(kinds) = &self.kinds {
+            if !kinds.contains(&event.kind()) {
+                return false;
+            }
+        }
+        if let Some(tag) = &self.tag {
+            if event.tag() != tag {
+                return false;
+            }
+        }
+        match (&self.blob_prefix, event.blob()) {
+            (Some(prefix), Some(blob)) => blob.starts_with(prefix.as_str()),
+            _ => true,
+        }
+    }
+}
+
+type Callback = Arc<dyn Fn(&Event) + Send + Sync>;
+
+struct Subscriber {
+    id: u64,
+    filter: EventFilter,
+    callback: Callback,
+}
+
+static SUBSCRIBERS: RwLock<Vec<Subscriber>> = RwLock::new(Vec::new());
+static SUBSCRIBER_COUNT: AtomicUsize = AtomicUsize::new(0);
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// True if anyone is subscribed, so callers can skip building events.
+pub(crate) fn has_subscribers() -> bool {
+    SUBSCRIBER_COUNT.load(Ordering::Relaxed) > 0
+}
+
+/// Deliver a locally generated event to matching subscribers.
+pub(crate) fn emit(event: Event) {
+    #[cfg(feature = "encryption")]
+    crate::encrypt::observe(&event);
+    crate::negcache::observe(&event);
+    crate::readahead::observe(&event);
+    crate::readcache::observe(&event);
+    crate::tagcache::observe(&event);
+    if !has_subscribers()
+        || crate::meta::is_reserved(event.tag())
+        || event.blob().is_some_and(crate::meta::is_reserved)
+    {
+        return;
+    }
+    // Collect first so a callback can subscribe or unsubscribe without deadlocking.
+    let targets: Vec<Callback> = SUBSCRIBERS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .filter(|s| s.filter.matches(&event))
+        .map(|s| s.callback.clone())
+        .collect();
+    for cb in targets {
+        cb(&event);
+    }
+}
+
+/// Regex matching exactly `name`, for `tag_query`.
+pub(crate) fn exact(name: &str) -> String {
+    let mut re = String::with_capacity(name.len() + 8);
+    for c in name.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            re.push('\\');
+        }
+        re.push(c);
+    }
+    re
+}
+
+/// An active subscription; dropping it unsubscribes and stops its poller.
+pub struct Subscription {
+    id: u64,
+    poller: Option<(Arc<AtomicBool>, JoinHandle<()>)>,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let mut subs = SUBSCRIBERS.write().unwrap_or_else(|e| e.into_inner());
+        subs.retain(|s| s.id != self.id);
+        SUBSCRIBER_COUNT.store(subs.len(), Ordering::Relaxed);
+        drop(subs);
+        if let Some((stop, handle)) = self.poller.take() {
+            stop.store(true, Ordering::Relaxed);
+            handle.thread().unpark();
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Channel-backed subscription. Iterating blocks until the next event.
+pub struct EventStream {
+    rx: Receiver<Event>,
+    _subscription: Subscription,
+}
+
+impl EventStream {
+    pub fn try_recv(&self) -> Option<Event> {
+        self.rx.try_recv().ok()
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Event> {
+        self.rx.recv_timeout(timeout).ok()
+    }
+}
+
+impl Iterator for EventStream {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        self.rx.recv().ok()
+    }
+}
+
+impl Client {
+    /// Subscribe to events matching `filter`, delivered through a channel.
+    pub fn subscribe(filter: EventFilter) -> EventStream {
+        let (tx, rx) = mpsc::channel();
+        let subscription = Self::subscribe_with(filter, move |e| {
+            let _ = tx.send(e.clone());
+        });
+        EventStream {
+            rx,
+            _subscription: subscription,
+        }
+    }
+
+    /// Subscribe to events matching `filter`, delivered to `callback`.
+    ///
+    /// Local events are delivered on the mutating thread, possibly while the
+    /// wrapper holds internal locks: the callback should be quick and must not
+    /// write to CTE itself. Use `subscribe` to react to events with further I/O.
+    pub fn subscribe_with(
+        filter: EventFilter,
+        callback: impl Fn(&Event) + Send + Sync + 'static,
+    ) -> Subscription {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let callback: Callback = Arc::new(callback);
+        let poller = filter.poll_interval.map(|interval| {
+            let stop = Arc::new(AtomicBool::new(false));
+            let (filter, callback, stop2) = (filter.clone(), callback.clone(), stop.clone());
+            let handle =
+                std::thread::spawn(move || poll_loop(&filter, &callback, interval, &stop2));
+            (stop, handle)
+        });
+        let mut subs = SUBSCRIBERS.write().unwrap_or_else(|e| e.into_inner());
+        subs.push(Subscriber {
+            id,
+            filter,
+            callback,
+        });
+        SUBSCRIBER_COUNT.store(subs.len(), Ordering::Relaxed);
+        Subscription { id, poller }
+    }
+}
+
+/// Background poller for changes made by other clients.
+fn poll_loop(filter: &EventFilter, callback: &Callback, interval: Duration, stop: &AtomicBool) {
+    let pattern = filter
+        .tag
+        .as_deref()
+        .map_or_else(|| ".*".to_string(), exact);
+    let deliver = |e: Event| {
+        if filter.matches(&e) {
+            callback(&e);
+        }
+    };
+    // tag name -> change log cursor
+    let mut cursors: HashMap<String, u64> = HashMap::new();
+    let mut first = true;
+    while !stop.load(Ordering::Relaxed) {
+        let names = Client::tag_names(&pattern, 0);
+        cursors.retain(|name, _| {
+            let alive = names.contains(name);
+            if !alive {
+                deliver(Event::TagDeleted { tag: name.clone() });
+            }
+            alive
+        });
+        for name in names {
+            // A tag the runtime can't open now is retried on the next poll.
+            let Ok(tag) = Tag::try_new(&name) else {
+                continue;
+            };
+            let cursor = cursors.entry(name.clone()).or_insert_with(|| {
+                if !first {
+                    deliver(Event::TagCreated { tag: name.clone() });
+                }
+                // Tags present at subscription time start at the log's end;
+                // tags that appear later are replayed from the beginning.
+                if first {
+                    tag.change_seq()
+                } else {
+                    0
+                }
+            });
+            if let Ok(changes) = tag.changes_since(*cursor) {
+                *cursor = changes.next_seq;
+                for change in changes.changes {
+                    if change.writer == writer_id() {
+                        continue;
+                    }
+                    let (tag, blob) = (name.clone(), change.blob);
+                    deliver(match change.kind {
+                        ChangeKind::Put { offset, size } => Event::BlobPut {
+                            tag,
+                            blob,
+                            offset,
+                            size,
+                        },
+                        ChangeKind::Reorganize { score } => {
+                            Event::BlobReorganized { tag, blob, score }
+                        }
+                        ChangeKind::Delete => Event::BlobDeleted { tag, blob },
+                    });
+                }
+            }
+        }
+        first = false;
+        std::thread::park_timeout(interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_matching() {
+        let put = Event::BlobPut {
+            tag: "sim".into(),
+            blob: "run42/step1".into(),
+            offset: 0,
+            size: 8,
+        };
+        assert!(EventFilter::new().matches(&put));
+        assert!(EventFilter::new()
+            .tag("sim")
+            .blob_prefix("run42/")
+            .matches(&put));
+        assert!(!EventFilter::new().tag("other").matches(&put));
+        assert!(!EventFilter::new().blob_prefix("run43/").matches(&put));
+        assert!(!EventFilter::new()
+            .kinds(&[EventKind::BlobDeleted])
+            .matches(&put));
+
+        let created = Event::TagCreated { tag: "sim".into() };
+        assert!(EventFilter::new().blob_prefix("run42/").matches(&created));
+    }
+
+    #[test]
+    fn test_event_json() {
+        assert_eq!(
+            Event::BlobPut {
+                tag: "t".into(),
+                blob: "a\"b".into(),
+                offset: 0,
+                size: 8
+            }
+            .json(),
+            r#"{"kind":"blob_put","tag":"t","blob":"a\"b","offset":0,"size":8}"#
+        );
+        assert_eq!(
+            Event::TagDeleted { tag: "t".into() }.json(),
+            r#"{"kind":"tag_deleted","tag":"t"}"#
+        );
+        assert!(EventKind::ALL.iter().all(|k| !k.name().is_empty()));
+    }
+
+    #[test]
+    fn test_exact_escapes_regex_metacharacters() {
+        assert_eq!(exact("a.b+c"), "a\\.b\\+c");
+        assert_eq!(exact("plain"), "plain");
+    }
+}
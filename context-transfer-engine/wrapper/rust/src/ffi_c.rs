@@ -1,71 +1,255 @@
 //! C-ABI exports for calling CTE from non-Rust languages (e.g., TypeScript via Bun FFI).
 //!
-//! All functions use C-compatible types and return 0 on success, -1 on failure.
+//! All functions use C-compatible types. Those returning `i32` return
+//! `CTE_C_OK` (0) on success, `CTE_C_ERROR` (-1) for invalid arguments and
+//! other failures, `CTE_C_EXCEPTION` (-2) if the runtime threw,
+//! `CTE_C_PANIC` (-3) if a panic was caught and `CTE_C_TOO_LARGE` (-4) for a
+//! read over the limits `cte_c_set_request_limits` sets, the values of
+//! `RuntimeCode`; `cte_c_last_error` then describes the failure.
 //! Opaque `*mut c_void` pointers represent `Box<Tag>` handles.
 //!
-//! All functions that call into CXX (which may panic on C++ exceptions) are wrapped
-//! in `catch_unwind` to prevent UB at the `extern "C"` boundary.
+//! Names are NUL-terminated UTF-8, except in the `_n` variants, which take a
+//! pointer and a length and any bytes, NULs included (see `rawname`), up to
+//! `cte_c_max_name_len`.
+//!
+//! Every export runs under `ffi_guard::c_status`, so no panic unwinds across the
+//! `extern "C"` boundary.
+//!
+//! With feature `c-header` the build writes these declarations to
+//! `include/cte_c.h` under its `OUT_DIR` (`target/<profile>/build/wrp-cte-rs-*/out`)
+//! with cbindgen. `cte_c_abi_version` tells callers which
+//! ABI the library they loaded has, and on Linux every export is versioned
+//! `CTE_C_<major>` (see `cte_c.map`), so a program linked against another
+//! major fails to load instead of calling into the wrong functions.
+//! The crate's cdylib exports more than these; `capi/` builds
+//! `libclio_cte.so`, which exports nothing else.
+//!
+//! Node.js can use the typed N-API addon of `node` (feature `node`) instead,
+//! and Java the JNI bindings of `java` (feature `jni`).
 
+use std::cell::RefCell;
 use std::ffi::{c_char, c_void, CStr, CString};
-use std::panic::catch_unwind;
 use std::ptr;
 use std::slice;
 
-use crate::{Client, Tag};
+use crate::{ffi_guard, Client, CteError, RequestLimits, RuntimeState, Tag};
+
+/// ABI major version, bumped when an export is removed or changes its
+/// signature or meaning.
+pub const CTE_C_ABI_MAJOR: u32 = 1;
+
+/// ABI minor version, bumped when exports are added.
+pub const CTE_C_ABI_MINOR: u32 = 0;
+
+/// Status of a successful call.
+pub const CTE_C_OK: i32 = 0;
+
+/// Status of an invalid argument or another failure.
+pub const CTE_C_ERROR: i32 = -1;
+
+/// Status when the runtime threw (`RuntimeCode::Exception`).
+pub const CTE_C_EXCEPTION: i32 = -2;
+
+/// Status when a panic was caught (`RuntimeCode::Panic`).
+pub const CTE_C_PANIC: i32 = -3;
+
+/// Status of a read over the request limits (`RuntimeCode::TooLarge`).
+pub const CTE_C_TOO_LARGE: i32 = -4;
+
+/// Bind each export to the version node of `CTE_C_ABI_MAJOR` that `cte_c.map`
+/// declares. With feature `capi` nothing is exported from here: `capi/`
+/// exports the functions `EXPORTS` names instead.
+macro_rules! versioned {
+    ($($name:ident),* $(,)?) => {
+        #[cfg(all(target_os = "linux", not(feature = "capi")))]
+        std::arch::global_asm!($(concat!(
+            ".symver ", stringify!($name), ", ", stringify!($name), "@@CTE_C_1"
+        )),*);
+
+        /// Every export, in the order they are declared.
+        #[cfg(any(test, feature = "capi"))]
+        pub const EXPORTS: &[&str] = &[$(stringify!($name)),*];
+    };
+}
+
+versioned!(
+    cte_c_abi_version,
+    cte_c_last_error,
+    cte_c_init,
+    cte_c_runtime_state,
+    cte_c_set_request_limits,
+    cte_c_max_name_len,
+    cte_c_tag_new,
+    cte_c_tag_new_n,
+    cte_c_tag_free,
+    cte_c_tag_put_blob,
+    cte_c_tag_put_blob_n,
+    cte_c_tag_get_blob_size,
+    cte_c_tag_get_blob_size_n,
+    cte_c_tag_get_blob,
+    cte_c_tag_get_blob_n,
+    cte_c_tag_get_contained_blobs,
+    cte_c_tag_list_blobs,
+    cte_c_del_tag,
+    cte_c_del_tag_n,
+    cte_c_register_target,
+    cte_c_register_ram_target,
+    cte_c_register_remote_target,
+    cte_c_free_buffer,
+    cte_c_free_string,
+);
+
+thread_local! {
+    /// Backing store of the string `cte_c_last_error` returns.
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
 
-/// Helper: convert a `*const c_char` to `&str`, returning `Err` on null or invalid UTF-8.
-unsafe fn cstr_to_str<'a>(p: *const c_char) -> Result<&'a str, ()> {
+/// Helper: convert a `*const c_char` to `&str`, failing on null or invalid UTF-8.
+unsafe fn cstr_to_str<'a>(p: *const c_char) -> Result<&'a str, CteError> {
     if p.is_null() {
-        return Err(());
+        return Err(CteError::InvalidArgument("null string".into()));
     }
-    unsafe { CStr::from_ptr(p) }.to_str().map_err(|_| ())
+    unsafe { CStr::from_ptr(p) }
+        .to_str()
+        .map_err(|_| CteError::InvalidArgument("string is not UTF-8".into()))
 }
 
-/// Initialize CTE runtime. `config` may be null or empty for defaults.
-/// Returns 0 on success, -1 on failure.
-#[no_mangle]
-pub unsafe extern "C" fn cte_c_init(config: *const c_char) -> i32 {
-    let path = if config.is_null() {
-        ""
-    } else {
-        match unsafe { cstr_to_str(config) } {
-            Ok(s) => s,
-            Err(_) => return -1,
+/// Helper: the `Tag` behind a handle, failing on null.
+unsafe fn tag_ref<'a>(tag: *mut c_void) -> Result<&'a Tag, CteError> {
+    if tag.is_null() {
+        return Err(CteError::InvalidArgument("null tag handle".into()));
+    }
+    Ok(unsafe { &*(tag as *const Tag) })
+}
+
+/// Helper: `s` as a JSON string literal.
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
         }
-    };
-    let path = path.to_owned();
-    match catch_unwind(move || crate::init(&path)) {
-        Ok(Ok(_)) => 0,
-        _ => -1,
     }
+    out.push('"');
+    out
+}
+
+/// Helper: `len` bytes at `p`, failing on null.
+unsafe fn bytes_arg<'a>(p: *const c_char, len: u64) -> Result<&'a [u8], CteError> {
+    not_null(p, "name")?;
+    Ok(unsafe { slice::from_raw_parts(p as *const u8, len as usize) })
+}
+
+fn not_null<T>(p: *const T, what: &str) -> Result<(), CteError> {
+    if p.is_null() {
+        return Err(CteError::InvalidArgument(format!("null {}", what)));
+    }
+    Ok(())
+}
+
+/// The ABI version of this library, `CTE_C_ABI_MAJOR << 16 | CTE_C_ABI_MINOR`.
+/// A caller built against `cte_c.h` should refuse a library whose major
+/// differs from the header's or whose minor is lower.
+#[cfg_attr(not(feature = "capi"), no_mangle)]
+pub extern "C" fn cte_c_abi_version() -> u32 {
+    (CTE_C_ABI_MAJOR << 16) | CTE_C_ABI_MINOR
+}
+
+/// Describe the last failure of a `cte_c_*` call on this thread, or return an
+/// empty string if the last call succeeded. The string stays valid until the
+/// next `cte_c_*` call on the same thread; don't free it.
+#[cfg_attr(not(feature = "capi"), no_mangle)]
+pub extern "C" fn cte_c_last_error() -> *const c_char {
+    let message = ffi_guard::last_error().unwrap_or_default();
+    // Interior NULs can't cross the boundary; cut the message at the first.
+    let end = message.find('\0').unwrap_or(message.len());
+    let message = CString::new(&message[..end]).unwrap_or_default();
+    LAST_ERROR.with_borrow_mut(|s| {
+        *s = message;
+        s.as_ptr()
+    })
+}
+
+/// Initialize CTE runtime. `config` may be null or empty for defaults.
+#[cfg_attr(not(feature = "capi"), no_mangle)]
+pub unsafe extern "C" fn cte_c_init(config: *const c_char) -> i32 {
+    ffi_guard::c_status("init", || {
+        let path = if config.is_null() {
+            ""
+        } else {
+            unsafe { cstr_to_str(config) }?
+        };
+        crate::try_init(path).map(|_| ())
+    })
+}
+
+/// How this process is connected to CTE: 0 uninitialized, 1 a client of a
+/// runtime in another process, 2 with an embedded runtime.
+#[cfg_attr(not(feature = "capi"), no_mangle)]
+pub extern "C" fn cte_c_runtime_state() -> i32 {
+    let mut state = 0;
+    ffi_guard::c_status("runtime_state", || {
+        state = match crate::runtime_state() {
+            RuntimeState::Uninitialized => 0,
+            RuntimeState::ClientAttached => 1,
+            RuntimeState::EmbeddedRuntime => 2,
+        };
+        Ok(())
+    });
+    state
+}
+
+/// Set the process-wide request limits (see `RequestLimits`): requests larger
+/// than `max_chunk` bytes are split, and reads larger than `max_read` bytes
+/// fail with `CTE_C_TOO_LARGE`; `max_read` 0 means no limit.
+#[cfg_attr(not(feature = "capi"), no_mangle)]
+pub extern "C" fn cte_c_set_request_limits(max_chunk: u64, max_read: u64) -> i32 {
+    ffi_guard::c_status("set_request_limits", || {
+        crate::set_request_limits(RequestLimits {
+            max_chunk,
+            max_read: (max_read != 0).then_some(max_read),
+        });
+        Ok(())
+    })
+}
+
+/// Longest tag or blob name accepted, in bytes.
+#[cfg_attr(not(feature = "capi"), no_mangle)]
+pub extern "C" fn cte_c_max_name_len() -> u64 {
+    crate::rawname::max_name_len() as u64
 }
 
 /// Create or open a tag by name. Returns an opaque pointer (owned `Box<Tag>`).
 /// Returns null on failure.
-#[no_mangle]
+#[cfg_attr(not(feature = "capi"), no_mangle)]
 pub unsafe extern "C" fn cte_c_tag_new(name: *const c_char) -> *mut c_void {
-    let name = match unsafe { cstr_to_str(name) } {
-        Ok(s) => s.to_owned(),
-        Err(_) => return ptr::null_mut(),
-    };
-    match catch_unwind(move || Box::new(Tag::new(&name))) {
-        Ok(tag) => Box::into_raw(tag) as *mut c_void,
-        Err(e) => {
-            let msg = if let Some(s) = e.downcast_ref::<String>() {
-                s.clone()
-            } else if let Some(s) = e.downcast_ref::<&str>() {
-                s.to_string()
-            } else {
-                "unknown panic".to_string()
-            };
-            eprintln!("cte_c_tag_new panic: {}", msg);
-            ptr::null_mut()
-        }
-    }
+    let mut out = ptr::null_mut();
+    ffi_guard::c_status("tag_new", || {
+        let name = unsafe { cstr_to_str(name) }?;
+        out = Box::into_raw(Box::new(Tag::try_new(name)?)) as *mut c_void;
+        Ok(())
+    });
+    out
+}
+
+/// `cte_c_tag_new` with a name of `name_len` bytes.
+#[cfg_attr(not(feature = "capi"), no_mangle)]
+pub unsafe extern "C" fn cte_c_tag_new_n(name: *const c_char, name_len: u64) -> *mut c_void {
+    let mut out = ptr::null_mut();
+    ffi_guard::c_status("tag_new", || {
+        let name = unsafe { bytes_arg(name, name_len) }?;
+        out = Box::into_raw(Box::new(Tag::try_new_bytes_name(name)?)) as *mut c_void;
+        Ok(())
+    });
+    out
 }
 
 /// Free a tag handle previously returned by `cte_c_tag_new`.
-#[no_mangle]
+#[cfg_attr(not(feature = "capi"), no_mangle)]
 pub unsafe extern "C" fn cte_c_tag_free(tag: *mut c_void) {
     if !tag.is_null() {
         drop(unsafe { Box::from_raw(tag as *mut Tag) });
@@ -73,8 +257,7 @@ pub unsafe extern "C" fn cte_c_tag_free(tag: *mut c_void) {
 }
 
 /// Write data into a blob.
-/// Returns 0 on success, -1 on failure.
-#[no_mangle]
+#[cfg_attr(not(feature = "capi"), no_mangle)]
 pub unsafe extern "C" fn cte_c_tag_put_blob(
     tag: *mut c_void,
     name: *const c_char,
@@ -83,58 +266,73 @@ pub unsafe extern "C" fn cte_c_tag_put_blob(
     offset: u64,
     score: f32,
 ) -> i32 {
-    if tag.is_null() || data.is_null() {
-        return -1;
-    }
-    let tag_ref = unsafe { &*(tag as *const Tag) };
-    let name = match unsafe { cstr_to_str(name) } {
-        Ok(s) => s,
-        Err(_) => return -1,
-    };
-    let data = unsafe { slice::from_raw_parts(data, len as usize) };
-    // Tag is not UnwindSafe, so use AssertUnwindSafe
-    let tag_ptr = std::panic::AssertUnwindSafe(tag_ref as *const Tag);
-    let data_ptr = data.as_ptr();
-    let data_len = data.len();
-    let name = name.to_owned();
-    match catch_unwind(move || {
-        let tag = unsafe { &*tag_ptr.0 };
-        let data = unsafe { slice::from_raw_parts(data_ptr, data_len) };
-        tag.put_blob_with_options(&name, data, offset, score);
-    }) {
-        Ok(_) => 0,
-        Err(_) => -1,
-    }
+    ffi_guard::c_status("put_blob", || {
+        let tag = unsafe { tag_ref(tag) }?;
+        not_null(data, "data")?;
+        let name = unsafe { cstr_to_str(name) }?;
+        let data = unsafe { slice::from_raw_parts(data, len as usize) };
+        tag.try_write_blob(name, data, offset, Some(score))?;
+        Ok(())
+    })
+}
+
+/// `cte_c_tag_put_blob` with a name of `name_len` bytes.
+#[cfg_attr(not(feature = "capi"), no_mangle)]
+pub unsafe extern "C" fn cte_c_tag_put_blob_n(
+    tag: *mut c_void,
+    name: *const c_char,
+    name_len: u64,
+    data: *const u8,
+    len: u64,
+    offset: u64,
+    score: f32,
+) -> i32 {
+    ffi_guard::c_status("put_blob", || {
+        let tag = unsafe { tag_ref(tag) }?;
+        not_null(data, "data")?;
+        let name = unsafe { bytes_arg(name, name_len) }?;
+        let data = unsafe { slice::from_raw_parts(data, len as usize) };
+        tag.put_blob_bytes_name(name, data, offset, score)
+    })
 }
 
 /// Get the size of a blob in bytes.
 /// Returns 0 if the tag or name is invalid.
-#[no_mangle]
+#[cfg_attr(not(feature = "capi"), no_mangle)]
 pub unsafe extern "C" fn cte_c_tag_get_blob_size(
     tag: *mut c_void,
     name: *const c_char,
 ) -> u64 {
-    if tag.is_null() {
-        return 0;
-    }
-    let tag_ref = unsafe { &*(tag as *const Tag) };
-    let name = match unsafe { cstr_to_str(name) } {
-        Ok(s) => s.to_owned(),
-        Err(_) => return 0,
-    };
-    let tag_ptr = std::panic::AssertUnwindSafe(tag_ref as *const Tag);
-    match catch_unwind(move || {
-        let tag = unsafe { &*tag_ptr.0 };
-        tag.get_blob_size(&name)
-    }) {
-        Ok(size) => size,
-        Err(_) => 0,
-    }
+    let mut size = 0;
+    ffi_guard::c_status("get_blob_size", || {
+        let tag = unsafe { tag_ref(tag) }?;
+        let name = unsafe { cstr_to_str(name) }?;
+        size = tag.get_blob_size(name);
+        Ok(())
+    });
+    size
+}
+
+/// `cte_c_tag_get_blob_size` with a name of `name_len` bytes.
+#[cfg_attr(not(feature = "capi"), no_mangle)]
+pub unsafe extern "C" fn cte_c_tag_get_blob_size_n(
+    tag: *mut c_void,
+    name: *const c_char,
+    name_len: u64,
+) -> u64 {
+    let mut size = 0;
+    ffi_guard::c_status("get_blob_size", || {
+        let tag = unsafe { tag_ref(tag) }?;
+        let name = unsafe { bytes_arg(name, name_len) }?;
+        size = tag.get_blob_size_bytes_name(name);
+        Ok(())
+    });
+    size
 }
 
-/// Read blob data into a caller-allocated buffer.
-/// Returns 0 on success, -1 on failure.
-#[no_mangle]
+/// Read `size` bytes of blob data into a caller-allocated buffer of at least
+/// that size, in chunks, without buffering the blob.
+#[cfg_attr(not(feature = "capi"), no_mangle)]
 pub unsafe extern "C" fn cte_c_tag_get_blob(
     tag: *mut c_void,
     name: *const c_char,
@@ -142,103 +340,220 @@ pub unsafe extern "C" fn cte_c_tag_get_blob(
     size: u64,
     offset: u64,
 ) -> i32 {
-    if tag.is_null() || buf.is_null() {
-        return -1;
-    }
-    let tag_ref = unsafe { &*(tag as *const Tag) };
-    let name = match unsafe { cstr_to_str(name) } {
-        Ok(s) => s.to_owned(),
-        Err(_) => return -1,
-    };
-    let tag_ptr = std::panic::AssertUnwindSafe(tag_ref as *const Tag);
-    let buf_ptr = buf;
-    match catch_unwind(move || {
-        let tag = unsafe { &*tag_ptr.0 };
-        let data = tag.get_blob_with_offset(&name, size, offset);
-        let copy_len = std::cmp::min(data.len(), size as usize);
-        unsafe { ptr::copy_nonoverlapping(data.as_ptr(), buf_ptr, copy_len) };
-    }) {
-        Ok(_) => 0,
-        Err(_) => -1,
-    }
+    ffi_guard::c_status("get_blob", || {
+        let tag = unsafe { tag_ref(tag) }?;
+        not_null(buf, "buffer")?;
+        let name = unsafe { cstr_to_str(name) }?;
+        let len = usize::try_from(size).map_err(|_| CteError::TooLarge {
+            blob: name.to_string(),
+            size,
+            limit: None,
+        })?;
+        let buf = unsafe { slice::from_raw_parts_mut(buf, len) };
+        tag.read_blob_into(name, buf, offset)
+    })
+}
+
+/// `cte_c_tag_get_blob` with a name of `name_len` bytes.
+#[cfg_attr(not(feature = "capi"), no_mangle)]
+pub unsafe extern "C" fn cte_c_tag_get_blob_n(
+    tag: *mut c_void,
+    name: *const c_char,
+    name_len: u64,
+    buf: *mut u8,
+    size: u64,
+    offset: u64,
+) -> i32 {
+    ffi_guard::c_status("get_blob", || {
+        let tag = unsafe { tag_ref(tag) }?;
+        not_null(buf, "buffer")?;
+        let name = unsafe { bytes_arg(name, name_len) }?;
+        let len = usize::try_from(size).map_err(|_| CteError::TooLarge {
+            blob: String::from_utf8_lossy(name).into_owned(),
+            size,
+            limit: None,
+        })?;
+        let buf = unsafe { slice::from_raw_parts_mut(buf, len) };
+        tag.read_blob_into_bytes_name(name, buf, offset)
+    })
 }
 
 /// List all blob names in a tag. Returns a JSON array string via `out_json`.
-/// The caller must free the string with `cte_c_free_string`.
-/// Returns 0 on success, -1 on failure.
-#[no_mangle]
+/// The caller must free the string with `cte_c_free_string`. Names that
+/// aren't UTF-8 are handled as `NamePolicy` says (under `Error` this returns
+/// -1); `cte_c_tag_list_blobs` lists them exactly.
+#[cfg_attr(not(feature = "capi"), no_mangle)]
 pub unsafe extern "C" fn cte_c_tag_get_contained_blobs(
     tag: *mut c_void,
     out_json: *mut *mut c_char,
 ) -> i32 {
-    if tag.is_null() || out_json.is_null() {
-        return -1;
-    }
-    let tag_ref = unsafe { &*(tag as *const Tag) };
-    let tag_ptr = std::panic::AssertUnwindSafe(tag_ref as *const Tag);
-    let out = out_json;
-    match catch_unwind(move || {
-        let tag = unsafe { &*tag_ptr.0 };
-        let blobs = tag.get_contained_blobs();
+    ffi_guard::c_status("get_contained_blobs", || {
+        let tag = unsafe { tag_ref(tag) }?;
+        not_null(out_json, "output pointer")?;
+        let blobs = tag.try_get_contained_blobs()?;
 
         // Build JSON array manually to avoid serde dependency
         let json = format!(
             "[{}]",
             blobs
                 .iter()
-                .map(|s| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")))
+                .map(|s| json_string(s))
                 .collect::<Vec<_>>()
                 .join(",")
         );
 
-        match CString::new(json) {
-            Ok(cs) => {
-                unsafe { *out = cs.into_raw() };
-                0i32
-            }
-            Err(_) => -1i32,
+        let cs = CString::new(json)
+            .map_err(|_| CteError::InvalidArgument("blob name contains NUL".into()))?;
+        unsafe { *out_json = cs.into_raw() };
+        Ok(())
+    })
+}
+
+/// List all blob names in a tag, byte for byte, as `(u64 length, bytes)`
+/// records (lengths little-endian) in a buffer returned via `out_buf` and
+/// `out_len`. The caller must free it with `cte_c_free_buffer`.
+#[cfg_attr(not(feature = "capi"), no_mangle)]
+pub unsafe extern "C" fn cte_c_tag_list_blobs(
+    tag: *mut c_void,
+    out_buf: *mut *mut u8,
+    out_len: *mut u64,
+) -> i32 {
+    ffi_guard::c_status("get_contained_blobs", || {
+        let tag = unsafe { tag_ref(tag) }?;
+        not_null(out_buf, "output pointer")?;
+        not_null(out_len, "output length")?;
+        let mut buf = Vec::new();
+        for name in tag.get_contained_blobs_bytes() {
+            buf.extend_from_slice(&(name.len() as u64).to_le_bytes());
+            buf.extend_from_slice(&name);
         }
-    }) {
-        Ok(rc) => rc,
-        Err(_) => -1,
-    }
+        let buf = buf.into_boxed_slice();
+        unsafe {
+            *out_len = buf.len() as u64;
+            *out_buf = Box::into_raw(buf) as *mut u8;
+        }
+        Ok(())
+    })
 }
 
 /// Delete a tag by name.
-/// Returns 0 on success, -1 on failure.
-#[no_mangle]
+#[cfg_attr(not(feature = "capi"), no_mangle)]
 pub unsafe extern "C" fn cte_c_del_tag(name: *const c_char) -> i32 {
-    let name = match unsafe { cstr_to_str(name) } {
-        Ok(s) => s.to_owned(),
-        Err(_) => return -1,
-    };
-    match catch_unwind(move || Client::del_tag(&name)) {
-        Ok(true) => 0,
-        _ => -1,
-    }
+    ffi_guard::c_status("del_tag", || {
+        let name = unsafe { cstr_to_str(name) }?;
+        if !Client::del_tag(name) {
+            return Err(CteError::NotFound {
+                blob: format!("tag '{}'", name),
+            });
+        }
+        Ok(())
+    })
+}
+
+/// `cte_c_del_tag` with a name of `name_len` bytes.
+#[cfg_attr(not(feature = "capi"), no_mangle)]
+pub unsafe extern "C" fn cte_c_del_tag_n(name: *const c_char, name_len: u64) -> i32 {
+    ffi_guard::c_status("del_tag", || {
+        let name = unsafe { bytes_arg(name, name_len) }?;
+        if !Client::del_tag_bytes_name(name) {
+            return Err(CteError::NotFound {
+                blob: format!("tag '{}'", String::from_utf8_lossy(name)),
+            });
+        }
+        Ok(())
+    })
 }
 
 /// Register a file-backed storage target.
-/// Returns 0 on success, -1 on failure.
-#[no_mangle]
+#[cfg_attr(not(feature = "capi"), no_mangle)]
 pub unsafe extern "C" fn cte_c_register_target(
     path: *const c_char,
     size: u64,
 ) -> i32 {
-    let path = match unsafe { cstr_to_str(path) } {
-        Ok(s) => s.to_owned(),
-        Err(_) => return -1,
-    };
-    match catch_unwind(move || Client::register_target(&path, size)) {
-        Ok(true) => 0,
-        _ => -1,
+    ffi_guard::c_status("register_target", || {
+        let path = unsafe { cstr_to_str(path) }?;
+        if !Client::register_target(path, size) {
+            return Err(CteError::Unsupported(format!(
+                "the runtime refused target '{}'",
+                path
+            )));
+        }
+        Ok(())
+    })
+}
+
+/// Register a RAM-backed storage target of `size` bytes named `name`.
+#[cfg_attr(not(feature = "capi"), no_mangle)]
+pub unsafe extern "C" fn cte_c_register_ram_target(name: *const c_char, size: u64) -> i32 {
+    ffi_guard::c_status("register_target", || {
+        let name = unsafe { cstr_to_str(name) }?;
+        if !Client::register_ram_target(name, size) {
+            return Err(CteError::Unsupported(format!(
+                "the runtime refused RAM target '{}'",
+                name
+            )));
+        }
+        Ok(())
+    })
+}
+
+/// Register `capacity` bytes of memory on node `host` as a storage target;
+/// `port` 0 for the deployment's port.
+#[cfg_attr(not(feature = "capi"), no_mangle)]
+pub unsafe extern "C" fn cte_c_register_remote_target(
+    host: *const c_char,
+    port: u16,
+    capacity: u64,
+) -> i32 {
+    ffi_guard::c_status("register_target", || {
+        let host = unsafe { cstr_to_str(host) }?;
+        Client::register_remote_target(host, port, capacity).map(|_| ())
+    })
+}
+
+/// Free a buffer of `len` bytes returned by `cte_c_tag_list_blobs`.
+#[cfg_attr(not(feature = "capi"), no_mangle)]
+pub unsafe extern "C" fn cte_c_free_buffer(buf: *mut u8, len: u64) {
+    if !buf.is_null() {
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(buf, len as usize)) });
     }
 }
 
 /// Free a string previously allocated by CTE (e.g., from `cte_c_tag_get_contained_blobs`).
-#[no_mangle]
+#[cfg_attr(not(feature = "capi"), no_mangle)]
 pub unsafe extern "C" fn cte_c_free_string(ptr: *mut c_char) {
     if !ptr.is_null() {
         drop(unsafe { CString::from_raw(ptr) });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exports_versioned() {
+        let source = include_str!("ffi_c.rs");
+        let exports: Vec<&str> = source
+            .lines()
+            .zip(source.lines().skip(1))
+            .filter(|(attr, _)| attr.contains("no_mangle)]"))
+            .filter_map(|(_, item)| {
+                let name = item.split(" fn ").nth(1)?;
+                Some(&name[..name.find('(')?])
+            })
+            .collect();
+        assert_eq!(exports, EXPORTS);
+        assert_eq!(cte_c_abi_version() >> 16, CTE_C_ABI_MAJOR);
+    }
+
+    #[test]
+    fn test_status_codes() {
+        use crate::RuntimeCode;
+        assert_eq!(CTE_C_EXCEPTION, RuntimeCode::Exception.status());
+        assert_eq!(CTE_C_PANIC, RuntimeCode::Panic.status());
+        assert_eq!(CTE_C_TOO_LARGE, RuntimeCode::TooLarge.status());
+        let status = unsafe { cte_c_tag_get_blob(ptr::null_mut(), ptr::null(), ptr::null_mut(), 0, 0) };
+        assert_eq!(status, CTE_C_ERROR);
+    }
+}
@@ -0,0 +1,447 @@
+//! The one place failures cross the FFI boundary.
+//!
+//! The shim and the runtime client throw C++ exceptions when they can't
+//! allocate a buffer, a put or read fails, or the runtime can't create a tag.
+//! The bridge functions that can throw are declared fallible, and the
+//! wrappers here are the only callers of them: each turns an exception into
+//! `CteError::Runtime` naming the operation and blob, or `CteError::Timeout`
+//! if a wait under `Client::with_op_options` ran out, so nothing above
+//! handles `cxx::Exception`.
+//!
+//! Rust panics must not unwind into foreign code either. Hooks the shim calls
+//! run under `hook`, which falls back to a default, and every C ABI export in
+//! `ffi_c` runs under `c_status`, which turns a panic into
+//! `CteError::Runtime` with `RuntimeCode::Panic` and returns a status code.
+//! The last error on each thread is kept for `cte_c_last_error`.
+//!
+//! With the `backtrace` feature, messages also carry a backtrace: of the panic
+//! itself, or of the Rust side of the call that threw.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use cxx::{CxxVector, UniquePtr};
+
+use crate::{diag, ffi, rawname, timeout, CteError, S3Credentials};
+
+/// What kind of failure `CteError::Runtime` reports, plus `TooLarge`. The
+/// values are the status codes the C ABI returns for them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(i32)]
+pub enum RuntimeCode {
+    /// A C++ exception thrown below the bridge.
+    Exception = -2,
+    /// A Rust panic, caught before it reached foreign code.
+    Panic = -3,
+    /// A read over the request limits (`CteError::TooLarge`); only a C ABI
+    /// status, never the code of a `CteError::Runtime`.
+    TooLarge = -4,
+}
+
+impl RuntimeCode {
+    /// The C ABI status code.
+    pub fn status(self) -> i32 {
+        self as i32
+    }
+}
+
+thread_local! {
+    /// The last error recorded on this thread, as displayed.
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Remember `e` as this thread's last error, and for `diag`.
+pub(crate) fn record(e: &CteError) {
+    LAST_ERROR.set(Some(e.to_string()));
+    diag::note_error(e);
+}
+
+/// This thread's last error, if any.
+pub(crate) fn last_error() -> Option<String> {
+    LAST_ERROR.with_borrow(|e| e.clone())
+}
+
+fn runtime_error(
+    code: RuntimeCode,
+    op: &'static str,
+    blob: Option<&str>,
+    message: String,
+) -> CteError {
+    let e = CteError::Runtime {
+        code,
+        message: backtrace::attach(message),
+        op,
+        blob: blob.map(str::to_string),
+    };
+    record(&e);
+    e
+}
+
+/// Run a fallible bridge call, journaled (see `diag`).
+fn call<T>(
+    op: &'static str,
+    tag: Option<&ffi::CteTag>,
+    blob: Option<&str>,
+    f: impl FnOnce() -> Result<T, cxx::Exception>,
+) -> Result<T, CteError> {
+    let journaled = diag::begin(op, tag.map(ffi::tag_get_id), blob);
+    let result = f().map_err(|e| match timeout::check() {
+        // The shim throws when a deadline passes mid-call.
+        Err(timeout) => {
+            record(&timeout);
+            timeout
+        }
+        Ok(()) => runtime_error(RuntimeCode::Exception, op, blob, e.what().to_string()),
+    });
+    journaled.end(result.as_ref().err());
+    result
+}
+
+/// `result`, or `CteError::Timeout` if a wait in the current scope ran out:
+/// past a deadline the shim returns early with nothing read or written, which
+/// must not pass for an empty blob or a landed put.
+fn waited<T>(result: Result<T, CteError>) -> Result<T, CteError> {
+    let value = result?;
+    if let Err(e) = timeout::check() {
+        record(&e);
+        return Err(e);
+    }
+    Ok(value)
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// Run `f`, turning a panic into `CteError::Runtime`.
+pub(crate) fn catch<T>(
+    op: &'static str,
+    f: impl FnOnce() -> Result<T, CteError>,
+) -> Result<T, CteError> {
+    backtrace::install();
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(payload) => Err(runtime_error(
+            RuntimeCode::Panic,
+            op,
+            None,
+            panic_message(&*payload),
+        )),
+    }
+}
+
+/// Run a hook the shim calls, returning `default` if it panics.
+pub(crate) fn hook<T>(op: &'static str, default: T, f: impl FnOnce() -> T) -> T {
+    catch(op, || Ok(f())).unwrap_or(default)
+}
+
+/// Run a C ABI export: 0 on success, the `RuntimeCode` status for `Runtime`
+/// and `TooLarge`, or -1 for other `CteError`s. The error is kept for
+/// `cte_c_last_error`.
+pub(crate) fn c_status(op: &'static str, f: impl FnOnce() -> Result<(), CteError>) -> i32 {
+    LAST_ERROR.set(None);
+    match catch(op, f) {
+        Ok(()) => 0,
+        // Runtime errors were recorded where they were raised.
+        Err(CteError::Runtime { code, .. }) => code.status(),
+        Err(e @ CteError::TooLarge { .. }) => {
+            record(&e);
+            RuntimeCode::TooLarge.status()
+        }
+        Err(e) => {
+            record(&e);
+            -1
+        }
+    }
+}
+
+/// Blob data read through the bridge; empty if the read failed.
+#[derive(Default)]
+pub(crate) struct BlobBuf(Option<UniquePtr<CxxVector<u8>>>);
+
+impl BlobBuf {
+    pub(crate) fn as_slice(&self) -> &[u8] {
+        match &self.0 {
+            Some(v) if !v.is_null() => v.as_slice(),
+            _ => &[],
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+}
+
+pub(crate) fn cte_init(config_path: &str) -> Result<bool, CteError> {
+    call("init", None, None, || ffi::cte_init(config_path))
+}
+
+pub(crate) fn tag_new(name: &str) -> Result<UniquePtr<ffi::CteTag>, CteError> {
+    rawname::check_name(name.as_bytes())?;
+    call("tag_new", None, None, || ffi::tag_new(name))
+}
+
+pub(crate) fn tag_new_bytes(name: &[u8]) -> Result<UniquePtr<ffi::CteTag>, CteError> {
+    rawname::check_name(name)?;
+    call("tag_new", None, None, || ffi::tag_new_bytes(name))
+}
+
+pub(crate) fn tag_put_blob(
+    tag: &ffi::CteTag,
+    name: &str,
+    data: &[u8],
+    offset: u64,
+    score: f32,
+) -> Result<(), CteError> {
+    rawname::check_name(name.as_bytes())?;
+    waited(call("put_blob", Some(tag), Some(name), || {
+        ffi::tag_put_blob(tag, name, data, offset, score)
+    }))
+}
+
+pub(crate) fn tag_put_blob_placed(
+    tag: &ffi::CteTag,
+    name: &str,
+    data: &[u8],
+    offset: u64,
+) -> Result<(), CteError> {
+    rawname::check_name(name.as_bytes())?;
+    waited(call("put_blob", Some(tag), Some(name), || {
+        ffi::tag_put_blob_placed(tag, name, data, offset)
+    }))
+}
+
+pub(crate) fn tag_get_blob(
+    tag: &ffi::CteTag,
+    name: &str,
+    size: u64,
+    offset: u64,
+) -> Result<BlobBuf, CteError> {
+    waited(call("get_blob", Some(tag), Some(name), || {
+        ffi::tag_get_blob(tag, name, size, offset)
+    }))
+    .map(|v| BlobBuf(Some(v)))
+}
+
+/// Overwrite `name` with `desired` only if it holds exactly `expected`;
+/// whether it did.
+pub(crate) fn tag_swap_blob(
+    tag: &ffi::CteTag,
+    name: &str,
+    expected: &[u8],
+    desired: &[u8],
+    score: f32,
+) -> Result<bool, CteError> {
+    rawname::check_name(name.as_bytes())?;
+    // Past a deadline the shim reports no swap without trying.
+    waited(call("swap_blob", Some(tag), Some(name), || {
+        ffi::tag_swap_blob(tag, name, expected, desired, score)
+    }))
+}
+
+pub(crate) fn tag_put_blob_bytes(
+    tag: &ffi::CteTag,
+    name: &[u8],
+    data: &[u8],
+    offset: u64,
+    score: f32,
+) -> Result<(), CteError> {
+    rawname::check_name(name)?;
+    let blob = String::from_utf8_lossy(name);
+    waited(call("put_blob", Some(tag), Some(&blob), || {
+        ffi::tag_put_blob_bytes(tag, name, data, offset, score)
+    }))
+}
+
+pub(crate) fn tag_get_blob_bytes(
+    tag: &ffi::CteTag,
+    name: &[u8],
+    size: u64,
+    offset: u64,
+) -> Result<BlobBuf, CteError> {
+    let blob = String::from_utf8_lossy(name);
+    waited(call("get_blob", Some(tag), Some(&blob), || {
+        ffi::tag_get_blob_bytes(tag, name, size, offset)
+    }))
+    .map(|v| BlobBuf(Some(v)))
+}
+
+pub(crate) fn tag_list_blobs(
+    tag: &ffi::CteTag,
+    prefix: &[u8],
+    delimiter: &[u8],
+) -> Result<Vec<ffi::ListEntry>, CteError> {
+    call("list_blobs", Some(tag), None, || {
+        ffi::tag_list_blobs(tag, prefix, delimiter)
+    })
+}
+
+pub(crate) fn tag_blob_blocks(
+    tag: &ffi::CteTag,
+    name: &str,
+) -> Result<Vec<ffi::BlobBlockRow>, CteError> {
+    call("blob_blocks", Some(tag), Some(name), || {
+        ffi::tag_blob_blocks(tag, name)
+    })
+}
+
+pub(crate) fn tag_reorganize_blob(
+    tag: &ffi::CteTag,
+    name: &str,
+    score: f32,
+) -> Result<(), CteError> {
+    call("reorganize_blob", Some(tag), Some(name), || {
+        ffi::tag_reorganize_blob(tag, name, score)
+    })
+}
+
+pub(crate) fn tag_put_blobs(
+    tag: &ffi::CteTag,
+    names: &[String],
+    data: &[u8],
+    lens: &[u64],
+    scores: &[f32],
+) -> Result<(), CteError> {
+    for name in names {
+        rawname::check_name(name.as_bytes())?;
+    }
+    call("put_blobs", Some(tag), None, || {
+        ffi::tag_put_blobs(tag, names, data, lens, scores)
+    })
+}
+
+pub(crate) fn tag_stat_blobs(
+    tag: &ffi::CteTag,
+    names: &[String],
+    meta_names: &[String],
+) -> Result<Vec<ffi::BlobInfoRow>, CteError> {
+    call("stat_blobs", Some(tag), None, || {
+        ffi::tag_stat_blobs(tag, names, meta_names)
+    })
+}
+
+pub(crate) fn client_hostfile() -> Result<Vec<String>, CteError> {
+    call("hostfile", None, None, ffi::client_hostfile)
+}
+
+pub(crate) fn client_register_remote_target(
+    name: &str,
+    node_id: u32,
+    size: u64,
+) -> Result<(), CteError> {
+    call("register_target", None, None, || {
+        ffi::client_register_remote_target(name, node_id, size)
+    })
+}
+
+/// `false` if the runtime has no object-store device.
+pub(crate) fn client_register_s3_target(
+    name: &str,
+    store: &S3Credentials,
+    bucket: &str,
+    prefix: &str,
+    size: u64,
+) -> Result<bool, CteError> {
+    call("register_target", None, None, || {
+        ffi::client_register_s3_target(
+            name,
+            store.endpoint.as_deref().unwrap_or(""),
+            &store.region,
+            bucket,
+            prefix,
+            &store.access_key_id,
+            &store.secret_access_key,
+            store.session_token.as_deref().unwrap_or(""),
+            size,
+        )
+    })
+}
+
+#[cfg(feature = "backtrace")]
+mod backtrace {
+    use std::backtrace::Backtrace;
+    use std::cell::RefCell;
+    use std::sync::Once;
+
+    thread_local! {
+        /// Backtrace of the last panic on this thread, taken where it happened.
+        static PANIC_TRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+    }
+
+    /// Chain a panic hook that keeps the panic's backtrace for `attach`.
+    pub(super) fn install() {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| {
+            let previous = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                PANIC_TRACE.set(Some(Backtrace::force_capture()));
+                previous(info);
+            }));
+        });
+    }
+
+    pub(super) fn attach(message: String) -> String {
+        let trace = PANIC_TRACE.take().unwrap_or_else(Backtrace::force_capture);
+        format!("{}\n{}", message, trace)
+    }
+}
+
+#[cfg(not(feature = "backtrace"))]
+mod backtrace {
+    pub(super) fn install() {}
+
+    pub(super) fn attach(message: String) -> String {
+        message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_panics_become_errors() {
+        let result: Result<(), _> = catch("put_blob", || panic!("boom"));
+        match result {
+            Err(CteError::Runtime {
+                code,
+                message,
+                op,
+                blob,
+            }) => {
+                assert_eq!(code, RuntimeCode::Panic);
+                assert!(message.starts_with("boom"));
+                assert_eq!((op, blob), ("put_blob", None));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(hook("placement_score", 0.5, || panic!("policy bug")), 0.5);
+
+        assert_eq!(c_status("del_tag", || Ok(())), 0);
+        assert_eq!(last_error(), None);
+        assert_eq!(
+            c_status("del_tag", || panic!("bad")),
+            RuntimeCode::Panic.status()
+        );
+        assert!(last_error().unwrap().contains("bad"));
+        let invalid = || Err(CteError::InvalidArgument("name is not UTF-8".into()));
+        assert_eq!(c_status("tag_new", invalid), -1);
+        assert!(last_error().unwrap().contains("UTF-8"));
+        let too_large = || {
+            Err(CteError::TooLarge {
+                blob: "b".into(),
+                size: 2,
+                limit: Some(1),
+            })
+        };
+        assert_eq!(c_status("get_blob", too_large), -4);
+        assert!(BlobBuf::default().as_slice().is_empty());
+    }
+}
@@ -0,0 +1,428 @@
+//! Blobs as Arrow Flight streams (feature `flight`).
+//!
+//! `Client::serve_flight` answers the Arrow Flight protocol, so a remote
+//! Spark, DataFusion or pyarrow cluster can stream blobs out of a node's
+//! burst buffer without going through a file system. A flight is one blob:
+//! its descriptor is the path `[tag, blob]` and its ticket `tag\0blob`.
+//! `ListFlights` with a tag name as the criteria expression lists the tag's
+//! blobs.
+//!
+//! A blob written by `Tag::put_record_batches` (one with `ARROW_SCHEMA_ATTR`
+//! holding an Arrow IPC stream) is sent as its own record batches, decoded as
+//! the client reads them. Any other blob, Parquet files included, is sent as
+//! batches of one `offset: UInt64, data: Binary` row each, `chunk_bytes` of
+//! the blob per row. A `DoGet` reads one batch ahead of the client: the next
+//! one is only read from the blob once gRPC's flow control has taken the
+//! last, so a slow reader holds back the service's reads rather than filling
+//! its memory. The service is read-only; `DoPut`, `DoExchange` and actions
+//! answer `UNIMPLEMENTED`. Tokens and addresses are as for the gRPC service
+//! (see `grpc`).
+
+use std::io::BufReader;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+
+use arrow_array::{BinaryArray, RecordBatch, UInt64Array};
+use arrow_flight::encode::FlightDataEncoderBuilder;
+use arrow_flight::error::FlightError;
+use arrow_flight::flight_service_server::{FlightService, FlightServiceServer};
+use arrow_flight::{
+    Action, ActionType, Criteria, Empty, FlightData, FlightDescriptor, FlightEndpoint, FlightInfo,
+    HandshakeRequest, HandshakeResponse, PollInfo, PutResult, SchemaAsIpc, SchemaResult, Ticket,
+};
+use arrow_ipc::reader::StreamReader;
+use arrow_ipc::writer::IpcWriteOptions;
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+use futures::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use tonic::service::Routes;
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::rpc::{self, blocking, checked, existing};
+use crate::{BlobReader, Client, CteError, ARROW_ROWS_ATTR, ARROW_SCHEMA_ATTR};
+
+/// What an Arrow IPC stream starts with: the continuation marker of its
+/// schema message.
+const IPC_CONTINUATION: [u8; 4] = [0xff; 4];
+
+/// Settings for `Client::serve_flight`.
+#[derive(Debug, Clone)]
+pub struct FlightOptions {
+    /// Secret every call must present as `authorization: bearer <token>`;
+    /// `None` to accept any local call.
+    pub token: Option<String>,
+    /// Bytes of a blob that isn't an Arrow stream per row sent.
+    pub chunk_bytes: usize,
+}
+
+impl Default for FlightOptions {
+    fn default() -> Self {
+        Self {
+            token: None,
+            chunk_bytes: 1 << 20,
+        }
+    }
+}
+
+/// The schema of a blob sent as bytes.
+fn raw_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("offset", DataType::UInt64, false),
+        Field::new("data", DataType::Binary, false),
+    ]))
+}
+
+fn ticket_of(tag: &str, blob: &str) -> Ticket {
+    Ticket::new(format!("{}\0{}", tag, blob).into_bytes())
+}
+
+/// The tag and blob a ticket names.
+fn names_of(ticket: &[u8]) -> Result<(String, String), CteError> {
+    let bad = || CteError::InvalidArgument("a ticket is <tag>\\0<blob>".into());
+    let text = std::str::from_utf8(ticket).map_err(|_| bad())?;
+    let (tag, blob) = text.split_once('\0').ok_or_else(bad)?;
+    checked("blob", blob)?;
+    Ok((tag.to_string(), blob.to_string()))
+}
+
+fn path_of(descriptor: &FlightDescriptor) -> Result<(String, String), CteError> {
+    match &descriptor.path[..] {
+        [tag, blob] => {
+            checked("blob", blob)?;
+            Ok((tag.clone(), blob.clone()))
+        }
+        _ => Err(CteError::InvalidArgument(
+            "a flight descriptor is the path [tag, blob]".into(),
+        )),
+    }
+}
+
+/// Where a `DoGet` takes its batches from.
+enum Source {
+    /// An Arrow IPC stream, decoded as read.
+    Arrow {
+        name: String,
+        stream: StreamReader<BufReader<BlobReader>>,
+        rows: Option<i64>,
+    },
+    /// Any other blob, `chunk` bytes per row.
+    Raw {
+        reader: BlobReader,
+        offset: u64,
+        chunk: usize,
+    },
+}
+
+impl Source {
+    /// Blob `blob` of the existing tag `tag`; fails with `NotFound` if the blob
+    /// doesn't exist.
+    fn open(tag: &str, blob: &str, chunk: usize) -> Result<Source, CteError> {
+        let tag = existing(tag)?;
+        let reader = tag.reader(blob)?;
+        let mut head = [0u8; 4];
+        let attrs = if reader.read_at(0, &mut head)? == head.len() && head == IPC_CONTINUATION {
+            tag.get_blob_attrs(blob)?
+        } else {
+            Vec::new()
+        };
+        if !attrs.iter().any(|(k, _)| k == ARROW_SCHEMA_ATTR) {
+            return Ok(Source::Raw {
+                reader,
+                offset: 0,
+                chunk,
+            });
+        }
+        let rows = attrs
+            .iter()
+            .find(|(k, _)| k == ARROW_ROWS_ATTR)
+            .and_then(|(_, v)| v.parse().ok());
+        let stream = StreamReader::try_new(BufReader::new(reader), None)
+            .map_err(|e| decode_error(blob, e))?;
+        Ok(Source::Arrow {
+            name: blob.to_string(),
+            stream,
+            rows,
+        })
+    }
+
+    fn schema(&self) -> SchemaRef {
+        match self {
+            Source::Arrow { stream, .. } => stream.schema(),
+            Source::Raw { .. } => raw_schema(),
+        }
+    }
+
+    fn info(&self, tag: &str, blob: &str) -> Result<FlightInfo, CteError> {
+        let (bytes, records) = match self {
+            Source::Arrow { stream, rows, .. } => {
+                (stream.get_ref().get_ref().len(), rows.unwrap_or(-1))
+            }
+            Source::Raw { reader, chunk, .. } => {
+                (reader.len(), reader.len().div_ceil(*chunk as u64) as i64)
+            }
+        };
+        let endpoint = FlightEndpoint::new().with_ticket(ticket_of(tag, blob));
+        Ok(FlightInfo::new()
+            .try_with_schema(&self.schema())
+            .map_err(|e| decode_error(blob, e))?
+            .with_descriptor(FlightDescriptor::new_path(vec![
+                tag.to_string(),
+                blob.to_string(),
+            ]))
+            .with_endpoint(endpoint)
+            .with_total_bytes(bytes as i64)
+            .with_total_records(records))
+    }
+
+    /// The next batch, or `None` at the end of the blob.
+    fn next_batch(&mut self) -> Result<Option<RecordBatch>, CteError> {
+        match self {
+            Source::Arrow { name, stream, .. } => {
+                stream.next().transpose().map_err(|e| decode_error(name, e))
+            }
+            Source::Raw {
+                reader,
+                offset,
+                chunk,
+            } => {
+                let mut data = vec![0u8; *chunk];
+                let n = reader.read_at(*offset, &mut data)?;
+                if n == 0 {
+                    return Ok(None);
+                }
+                data.truncate(n);
+                let batch = RecordBatch::try_new(
+                    raw_schema(),
+                    vec![
+                        Arc::new(UInt64Array::from(vec![*offset])),
+                        Arc::new(BinaryArray::from_vec(vec![&data[..]])),
+                    ],
+                )
+                .expect("the columns are raw_schema's");
+                *offset += n as u64;
+                Ok(Some(batch))
+            }
+        }
+    }
+}
+
+fn decode_error(blob: &str, e: ArrowError) -> CteError {
+    CteError::InvalidArgument(format!("'{}' isn't an Arrow IPC stream: {}", blob, e))
+}
+
+/// The batches of `source`, each read on a blocking thread once the one
+/// before has been taken.
+fn batches(source: Source) -> BoxStream<'static, Result<RecordBatch, FlightError>> {
+    stream::unfold(Some(source), |source| async move {
+        let mut source = source?;
+        let read = tokio::task::spawn_blocking(move || {
+            let next = source.next_batch();
+            (source, next)
+        })
+        .await;
+        match read {
+            Ok((source, Ok(Some(batch)))) => Some((Ok(batch), Some(source))),
+            Ok((_, Ok(None))) => None,
+            Ok((_, Err(e))) => Some((Err(FlightError::from(rpc::status(&e))), None)),
+            Err(e) => Some((Err(FlightError::ExternalError(Box::new(e))), None)),
+        }
+    })
+    .boxed()
+}
+
+fn unimplemented<T>(call: &str) -> Result<Response<T>, Status> {
+    Err(Status::unimplemented(format!(
+        "{} isn't offered: the service is read-only",
+        call
+    )))
+}
+
+struct Service {
+    chunk_bytes: usize,
+}
+
+#[tonic::async_trait]
+impl FlightService for Service {
+    type HandshakeStream = BoxStream<'static, Result<HandshakeResponse, Status>>;
+    type ListFlightsStream = BoxStream<'static, Result<FlightInfo, Status>>;
+    type DoGetStream = BoxStream<'static, Result<FlightData, Status>>;
+    type DoPutStream = BoxStream<'static, Result<PutResult, Status>>;
+    type DoActionStream = BoxStream<'static, Result<arrow_flight::Result, Status>>;
+    type ListActionsStream = BoxStream<'static, Result<ActionType, Status>>;
+    type DoExchangeStream = BoxStream<'static, Result<FlightData, Status>>;
+
+    /// The token, if any, was checked with the call's metadata, so there is
+    /// nothing left to negotiate.
+    async fn handshake(
+        &self,
+        _request: Request<Streaming<HandshakeRequest>>,
+    ) -> Result<Response<Self::HandshakeStream>, Status> {
+        let response = HandshakeResponse::default();
+        Ok(Response::new(stream::once(async { Ok(response) }).boxed()))
+    }
+
+    async fn list_flights(
+        &self,
+        request: Request<Criteria>,
+    ) -> Result<Response<Self::ListFlightsStream>, Status> {
+        let expression = request.into_inner().expression;
+        let chunk = self.chunk_bytes;
+        let infos = blocking(move || {
+            let tag = std::str::from_utf8(&expression)
+                .map_err(|_| CteError::InvalidArgument("criteria must be a tag name".into()))?
+                .to_string();
+            existing(&tag)?
+                .list("", "")?
+                .blobs
+                .iter()
+                .map(|blob| Source::open(&tag, blob, chunk)?.info(&tag, blob))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .await?
+        .into_inner();
+        Ok(Response::new(
+            stream::iter(infos.into_iter().map(Ok)).boxed(),
+        ))
+    }
+
+    async fn get_flight_info(
+        &self,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<FlightInfo>, Status> {
+        let descriptor = request.into_inner();
+        let chunk = self.chunk_bytes;
+        blocking(move || {
+            let (tag, blob) = path_of(&descriptor)?;
+            Source::open(&tag, &blob, chunk)?.info(&tag, &blob)
+        })
+        .await
+    }
+
+    async fn poll_flight_info(
+        &self,
+        _request: Request<FlightDescriptor>,
+    ) -> Result<Response<PollInfo>, Status> {
+        unimplemented("PollFlightInfo")
+    }
+
+    async fn get_schema(
+        &self,
+        request: Request<FlightDescriptor>,
+    ) -> Result<Response<SchemaResult>, Status> {
+        let descriptor = request.into_inner();
+        let chunk = self.chunk_bytes;
+        let schema = blocking(move || {
+            let (tag, blob) = path_of(&descriptor)?;
+            Ok(Source::open(&tag, &blob, chunk)?.schema())
+        })
+        .await?
+        .into_inner();
+        let result = SchemaAsIpc::new(&schema, &IpcWriteOptions::default())
+            .try_into()
+            .map_err(|e: ArrowError| Status::internal(e.to_string()))?;
+        Ok(Response::new(result))
+    }
+
+    async fn do_get(
+        &self,
+        request: Request<Ticket>,
+    ) -> Result<Response<Self::DoGetStream>, Status> {
+        let ticket = request.into_inner().ticket;
+        let chunk = self.chunk_bytes;
+        let source = blocking(move || {
+            let (tag, blob) = names_of(&ticket)?;
+            Source::open(&tag, &blob, chunk)
+        })
+        .await?
+        .into_inner();
+        let data = FlightDataEncoderBuilder::new()
+            .with_schema(source.schema())
+            .build(batches(source))
+            .map_err(Status::from);
+        Ok(Response::new(data.boxed()))
+    }
+
+    async fn do_put(
+        &self,
+        _request: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoPutStream>, Status> {
+        unimplemented("DoPut")
+    }
+
+    async fn do_exchange(
+        &self,
+        _request: Request<Streaming<FlightData>>,
+    ) -> Result<Response<Self::DoExchangeStream>, Status> {
+        unimplemented("DoExchange")
+    }
+
+    async fn do_action(
+        &self,
+        _request: Request<Action>,
+    ) -> Result<Response<Self::DoActionStream>, Status> {
+        unimplemented("DoAction")
+    }
+
+    async fn list_actions(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<Self::ListActionsStream>, Status> {
+        Ok(Response::new(stream::empty().boxed()))
+    }
+}
+
+/// A service from `Client::serve_flight`. Stops when dropped, letting streams
+/// in progress finish.
+pub struct FlightServer {
+    server: rpc::Server,
+}
+
+impl FlightServer {
+    /// The address the service listens on, with the port chosen if 0 was asked.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.server.local_addr()
+    }
+}
+
+impl Client {
+    /// Serve blobs over Arrow Flight at `addr` (see `flight`) until the
+    /// returned server is dropped. Fails with `InvalidArgument` for an address
+    /// that isn't loopback unless `options.token` is set.
+    pub fn serve_flight(
+        addr: impl ToSocketAddrs,
+        options: &FlightOptions,
+    ) -> Result<FlightServer, CteError> {
+        if options.chunk_bytes == 0 {
+            return Err(CteError::InvalidArgument(
+                "chunk_bytes must be positive".into(),
+            ));
+        }
+        let addrs = rpc::listen_addrs(addr, options.token.as_deref(), "Flight")?;
+        let service = FlightServiceServer::new(Service {
+            chunk_bytes: options.chunk_bytes,
+        });
+        let service = tonic::service::interceptor::InterceptedService::new(
+            service,
+            rpc::authorize(options.token.clone()),
+        );
+        let server = rpc::serve(&addrs, Routes::new(service))?;
+        Ok(FlightServer { server })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tickets() {
+        let ticket = ticket_of("runs", "step/1");
+        assert_eq!(
+            names_of(&ticket.ticket).unwrap(),
+            ("runs".to_string(), "step/1".to_string())
+        );
+        assert!(names_of(b"runs").is_err());
+        assert!(names_of(b"runs\0").is_err());
+        let descriptor = FlightDescriptor::new_path(vec!["runs".into()]);
+        assert!(path_of(&descriptor).is_err());
+    }
+}
@@ -0,0 +1,299 @@
+//! Read-only HTTP mirror of published tags (feature `gateway`).
+//!
+//! `Client::serve_gateway` answers anonymous `GET` and `HEAD` requests for the
+//! blobs of the tags listed in `GatewayOptions::tags` at `/<tag>/<blob>`, with
+//! each path segment percent-encoded and `/` allowed in the blob part. Nothing
+//! can be written, listed or deleted, wrapper sidecars are never served, and any
+//! other tag is answered as missing.
+//!
+//! Responses carry the blob's generation as a strong `ETag`, so caches can
+//! revalidate with `If-None-Match`, and `Cache-Control: public` with
+//! `GatewayOptions::max_age`. With `immutable_urls`, a blob is also served at
+//! `/@<generation>/<tag>/<blob>`, which names one version for good and is cached
+//! for a year as `immutable` (a tag whose name starts with `@` must then be
+//! written `%40...`); the current URL points at it in `Content-Location`. Old
+//! generations stay reachable there only while the tag keeps them (see
+//! `Tag::enable_versioning`). Blobs with no generation, written around the
+//! wrapper, are served without an `ETag` or immutable URL.
+
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::archive::unescape;
+use crate::http::{self, Request, Response};
+use crate::{meta, Client, CteError, GetOptions, Tag};
+
+/// `max-age` of immutable URLs: a year, as RFC 8246 suggests.
+const IMMUTABLE_MAX_AGE: u64 = 365 * 24 * 3600;
+
+/// Reads retried when the blob changes while being served.
+const READ_ATTEMPTS: usize = 3;
+
+/// Settings for `Client::serve_gateway`.
+#[derive(Debug, Clone)]
+pub struct GatewayOptions {
+    /// Tags served. Each must exist and be stored in plaintext.
+    pub tags: Vec<String>,
+    /// How long caches may reuse a response for a blob's current URL.
+    pub max_age: Duration,
+    /// Also serve every generation at its own immutable URL.
+    pub immutable_urls: bool,
+}
+
+impl Default for GatewayOptions {
+    fn default() -> Self {
+        Self {
+            tags: Vec::new(),
+            max_age: Duration::from_secs(60),
+            immutable_urls: false,
+        }
+    }
+}
+
+/// What a request path names.
+#[derive(Debug, PartialEq)]
+struct Target {
+    tag: String,
+    blob: String,
+    /// Set for immutable URLs.
+    generation: Option<u64>,
+}
+
+impl Target {
+    fn parse(path: &str, immutable_urls: bool) -> Option<Self> {
+        let mut rest = path.strip_prefix('/')?;
+        let mut generation = None;
+        if immutable_urls {
+            if let Some(versioned) = rest.strip_prefix('@') {
+                let (g, after) = versioned.split_once('/')?;
+                generation = Some(g.parse().ok()?);
+                rest = after;
+            }
+        }
+        let (tag, blob) = rest.split_once('/')?;
+        let (tag, blob) = (unescape(tag).ok()?, unescape(blob).ok()?);
+        if tag.is_empty() || blob.is_empty() {
+            return None;
+        }
+        Some(Self {
+            tag,
+            blob,
+            generation,
+        })
+    }
+}
+
+/// Percent-encode `s` for a URL path, leaving `/` as is if `keep_slash`.
+fn encode(s: &str, keep_slash: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) || (keep_slash && b == b'/') {
+            out.push(b as char);
+        } else {
+            let _ = write!(out, "%{:02X}", b);
+        }
+    }
+    out
+}
+
+/// True if an `If-None-Match` value covers `etag`.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(|t| t.trim().trim_start_matches("W/"))
+        .any(|t| t == "*" || t == etag)
+}
+
+fn not_found() -> Response {
+    Response::new("404 Not Found", "not found\n")
+}
+
+fn failed(e: &CteError) -> Response {
+    match e {
+        CteError::NotFound { .. } => not_found(),
+        _ => Response::new("500 Internal Server Error", format!("{}\n", e)),
+    }
+}
+
+/// Caching headers for a response about generation `generation`.
+fn cached(response: Response, cache_control: &str, generation: u64) -> Response {
+    let response = response.header("Cache-Control", cache_control);
+    if generation == 0 {
+        return response;
+    }
+    response.header("ETag", format!("\"{}\"", generation))
+}
+
+struct Gateway {
+    tags: HashSet<String>,
+    cache_control: String,
+    immutable_urls: bool,
+}
+
+impl Gateway {
+    fn handle(&self, request: &Request) -> Response {
+        if request.method != "GET" && request.method != "HEAD" {
+            return Response::new("405 Method Not Allowed", "read-only\n")
+                .header("Allow", "GET, HEAD");
+        }
+        let Some(target) = Target::parse(&request.path, self.immutable_urls) else {
+            return not_found();
+        };
+        if !self.tags.contains(&target.tag) || meta::is_reserved(&target.blob) {
+            return not_found();
+        }
+        let revalidated = |generation: u64| {
+            generation != 0
+                && request
+                    .header("If-None-Match")
+                    .is_some_and(|v| etag_matches(v, &format!("\"{}\"", generation)))
+        };
+        let tag = match Tag::try_new(&target.tag) {
+            Ok(tag) => tag,
+            Err(e) => return failed(&e),
+        };
+        if let Some(generation) = target.generation {
+            let immutable = format!("public, max-age={}, immutable", IMMUTABLE_MAX_AGE);
+            if revalidated(generation) {
+                return cached(
+                    Response::new("304 Not Modified", ""),
+                    &immutable,
+                    generation,
+                );
+            }
+            return match tag.get_blob_version(&target.blob, generation) {
+                Ok(data) => cached(self.ok(data), &immutable, generation),
+                Err(e) => failed(&e),
+            };
+        }
+        for _ in 0..READ_ATTEMPTS {
+            let stat = match tag.stat_blob(&target.blob) {
+                Ok(Some(stat)) => stat,
+                Ok(None) => return not_found(),
+                Err(e) => return failed(&e),
+            };
+            let generation = stat.generation;
+            let mut response = if revalidated(generation) {
+                Response::new("304 Not Modified", "")
+            } else {
+                let options = GetOptions {
+                    if_generation_match: (generation != 0).then_some(generation),
+                    ..Default::default()
+                };
+                match tag.get(&target.blob, &options) {
+                    Ok(data) => self.ok(data),
+                    Err(CteError::GenerationMismatch { .. }) => continue,
+                    Err(e) => return failed(&e),
+                }
+            };
+            if self.immutable_urls && generation != 0 {
+                let location = format!(
+                    "/@{}/{}/{}",
+                    generation,
+                    encode(&target.tag, false),
+                    encode(&target.blob, true)
+                );
+                response = response.header("Content-Location", location);
+            }
+            return cached(response, &self.cache_control, generation);
+        }
+        Response::new("503 Service Unavailable", "blob is being rewritten\n")
+            .header("Retry-After", "1")
+    }
+
+    fn ok(&self, data: Vec<u8>) -> Response {
+        Response::new("200 OK", data).header("Content-Type", "application/octet-stream")
+    }
+}
+
+/// A gateway from `Client::serve_gateway`. Stops accepting when dropped.
+pub struct GatewayServer {
+    server: http::Server,
+}
+
+impl GatewayServer {
+    /// The address the gateway listens on, with the port chosen if 0 was asked.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.server.local_addr()
+    }
+}
+
+impl Client {
+    /// Serve `options.tags` read-only over HTTP at `addr` (see `gateway`) until
+    /// the returned server is dropped. Fails if a tag doesn't exist, is
+    /// encrypted or is reserved, so nothing is published by mistake.
+    pub fn serve_gateway(
+        addr: impl ToSocketAddrs,
+        options: &GatewayOptions,
+    ) -> Result<GatewayServer, CteError> {
+        for name in &options.tags {
+            if meta::is_reserved(name) || !Client::tag_exists(name) {
+                return Err(CteError::InvalidArgument(format!(
+                    "no tag '{}' to serve",
+                    name
+                )));
+            }
+            if Tag::try_new(name)?.is_encrypted()? {
+                return Err(CteError::InvalidArgument(format!(
+                    "tag '{}' is encrypted and can't be served anonymously",
+                    name
+                )));
+            }
+        }
+        let gateway = Gateway {
+            tags: options.tags.iter().cloned().collect(),
+            cache_control: format!("public, max-age={}", options.max_age.as_secs()),
+            immutable_urls: options.immutable_urls,
+        };
+        let server = http::serve(addr, move |request| gateway.handle(request))?;
+        Ok(GatewayServer { server })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gateway_urls() {
+        let target = |tag: &str, blob: &str, generation| Target {
+            tag: tag.into(),
+            blob: blob.into(),
+            generation,
+        };
+        assert_eq!(
+            Target::parse("/climate/run1/t%2042.nc", false),
+            Some(target("climate", "run1/t 42.nc", None))
+        );
+        assert_eq!(
+            Target::parse("/@7/climate/a", true),
+            Some(target("climate", "a", Some(7)))
+        );
+        // Without immutable URLs `@7` is just a tag name.
+        assert_eq!(
+            Target::parse("/@7/climate/a", false),
+            Some(target("@7", "climate/a", None))
+        );
+        assert_eq!(Target::parse("/@x/climate/a", true), None);
+        assert_eq!(Target::parse("/climate", false), None);
+        assert_eq!(Target::parse("/climate/", false), None);
+        assert_eq!(Target::parse("/climate/%zz", false), None);
+
+        let name = "run 1/a?b#c";
+        assert_eq!(encode(name, true), "run%201/a%3Fb%23c");
+        assert_eq!(encode("a/b", false), "a%2Fb");
+        assert_eq!(
+            Target::parse(&format!("/t/{}", encode(name, true)), false)
+                .unwrap()
+                .blob,
+            name
+        );
+
+        assert!(etag_matches("\"3\"", "\"3\""));
+        assert!(etag_matches("\"1\", W/\"3\"", "\"3\""));
+        assert!(etag_matches("*", "\"3\""));
+        assert!(!etag_matches("\"31\"", "\"3\""));
+    }
+}
@@ -0,0 +1,447 @@
+//! Group commit of small-blob mutations.
+//!
+//! Every put, attribute set and delete costs several runtime round trips once
+//! its metadata sidecar is counted, which dominates ingest of many small
+//! objects. A `GroupCommit` (from `Tag::group_commit`) queues these mutations
+//! and applies them in batches, either when `GroupCommitOptions::max_ops` are
+//! queued, every `flush_interval` on a background thread, on `flush`, or when
+//! it is dropped. A batch is applied under the meta lock: the mutations of each
+//! blob are collapsed into one (the last put wins and a delete discards what
+//! came before it), sizes and sidecars are fetched in one round of calls, and
+//! all deletes and then all writes go to the runtime as one batch each. Change
+//! records, access records, accounting and events follow for every blob as if
+//! it had been written on its own, but each blob's generation moves by one per
+//! batch however many times it was put.
+//!
+//! Queued mutations are invisible to readers until flushed. Encrypted and
+//! versioned tags, and deletes with the trash enabled, need per-blob work that
+//! doesn't batch, so there the queue is flushed by applying each mutation with
+//! the ordinary API, in order.
+//!
+//! A flush applies every mutation it can. Errors from a background flush, such
+//! as `NotFound` for attributes set on a missing blob, are returned by the next
+//! call on the handle.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::audit::AccessKind;
+use crate::meta::{meta_lock, BlobMeta, META_PREFIX};
+use crate::oplog::ChangeKind;
+use crate::placement::placement_score;
+use crate::{
+    accounting, events, ffi, ffi_guard, trash, BlobDescriptor, CteError, Event, PutOptions, Tag,
+};
+
+/// When a `GroupCommit` applies its queued mutations.
+#[derive(Debug, Clone)]
+pub struct GroupCommitOptions {
+    /// How often the background thread flushes the queue.
+    pub flush_interval: Duration,
+    /// Queue length at which the caller's own call flushes.
+    pub max_ops: usize,
+}
+
+impl Default for GroupCommitOptions {
+    fn default() -> Self {
+        Self {
+            flush_interval: Duration::from_millis(10),
+            max_ops: 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Op {
+    Put(String, Vec<u8>),
+    SetAttrs(String, Vec<(String, String)>),
+    Delete(String),
+}
+
+/// The net effect of a batch's mutations on one blob.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct Pending {
+    /// Delete the stored blob and its sidecar before anything else.
+    reset: bool,
+    /// Data to write at offset 0, if the blob was put.
+    data: Option<Vec<u8>>,
+    /// Attributes to merge into the sidecar.
+    attrs: BTreeMap<String, String>,
+}
+
+impl Pending {
+    /// True if the blob ends the batch deleted.
+    fn deleted(&self) -> bool {
+        self.reset && self.data.is_none() && self.attrs.is_empty()
+    }
+}
+
+/// Collapse `ops` into one `Pending` per blob, in order of first mention. Sets
+/// on a blob the batch itself deleted fail here with `NotFound`.
+fn collapse(ops: Vec<Op>) -> (Vec<(String, Pending)>, Option<CteError>) {
+    let mut out: Vec<(String, Pending)> = Vec::new();
+    let mut index = HashMap::new();
+    let mut error = None;
+    for op in ops {
+        let name = match &op {
+            Op::Put(name, _) | Op::SetAttrs(name, _) | Op::Delete(name) => name.clone(),
+        };
+        let i = *index.entry(name.clone()).or_insert_with(|| {
+            out.push((name.clone(), Pending::default()));
+            out.len() - 1
+        });
+        let pending = &mut out[i].1;
+        match op {
+            Op::Put(_, data) => pending.data = Some(data),
+            Op::SetAttrs(_, attrs) => {
+                if pending.deleted() {
+                    error.get_or_insert(CteError::NotFound { blob: name });
+                    continue;
+                }
+                pending.attrs.extend(attrs);
+            }
+            Op::Delete(_) => {
+                *pending = Pending {
+                    reset: true,
+                    ..Default::default()
+                }
+            }
+        }
+    }
+    (out, error)
+}
+
+#[derive(Default)]
+struct Queue {
+    ops: Mutex<Vec<Op>>,
+    /// Held for the length of a flush, so batches are applied in order.
+    flushing: Mutex<()>,
+    /// First error of a background flush, not yet reported.
+    error: Mutex<Option<CteError>>,
+}
+
+impl Queue {
+    fn take_error(&self) -> Result<(), CteError> {
+        match self.error.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Apply everything queued so far to `tag`. Returns the number of
+    /// mutations applied.
+    fn flush(&self, tag: &Tag) -> Result<usize, CteError> {
+        let _flushing = self.flushing.lock().unwrap_or_else(|e| e.into_inner());
+        let ops = std::mem::take(&mut *self.ops.lock().unwrap_or_else(|e| e.into_inner()));
+        if ops.is_empty() {
+            return Ok(0);
+        }
+        if tag.needs_single_ops()? {
+            return tag.apply_singly(ops);
+        }
+        let count = ops.len();
+        let (pending, error) = collapse(ops);
+        tag.apply_batch(pending)?;
+        error.map_or(Ok(count), Err)
+    }
+}
+
+/// Queued mutations of one tag, from `Tag::group_commit`. Flushes and stops its
+/// background thread when dropped.
+pub struct GroupCommit {
+    tag: Arc<Tag>,
+    queue: Arc<Queue>,
+    max_ops: usize,
+    stop: Option<Sender<()>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl GroupCommit {
+    /// Queue a write of `data` as the whole of blob `name`, placed by the
+    /// registered `PlacementPolicy`.
+    pub fn put(&self, name: &str, data: &[u8]) -> Result<(), CteError> {
+        self.push(Op::Put(name.to_string(), data.to_vec()))
+    }
+
+    /// Queue `Tag::set_blob_attrs` on `name`, which must exist by the time the
+    /// batch is applied.
+    pub fn set_blob_attrs(&self, name: &str, attrs: &[(&str, &str)]) -> Result<(), CteError> {
+        let attrs = attrs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        self.push(Op::SetAttrs(name.to_string(), attrs))
+    }
+
+    /// Queue a delete of `name`.
+    pub fn delete(&self, name: &str) -> Result<(), CteError> {
+        self.push(Op::Delete(name.to_string()))
+    }
+
+    /// Number of mutations queued and not yet applied.
+    pub fn pending(&self) -> usize {
+        self.queue
+            .ops
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .len()
+    }
+
+    /// Apply every queued mutation now. Returns how many were applied.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(tag = %self.tag.name), ret, err)
+    )]
+    pub fn flush(&self) -> Result<usize, CteError> {
+        self.queue.take_error()?;
+        self.queue.flush(&self.tag)
+    }
+
+    fn push(&self, op: Op) -> Result<(), CteError> {
+        self.queue.take_error()?;
+        let len = {
+            let mut ops = self.queue.ops.lock().unwrap_or_else(|e| e.into_inner());
+            ops.push(op);
+            ops.len()
+        };
+        if len >= self.max_ops {
+            self.queue.flush(&self.tag)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for GroupCommit {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+        let _ = self.queue.flush(&self.tag);
+    }
+}
+
+impl Tag {
+    /// Start queueing mutations of this tag for group commit (see `group`).
+    pub fn group_commit(&self, options: GroupCommitOptions) -> GroupCommit {
+        let queue = Arc::new(Queue::default());
+        let (stop, stopped) = mpsc::channel::<()>();
+        let tag = Arc::new(Tag::reopen(self.get_tag_id(), self.name.clone()));
+        let worker = {
+            let queue = Arc::clone(&queue);
+            let tag = Arc::clone(&tag);
+            std::thread::spawn(move || loop {
+                match stopped.recv_timeout(options.flush_interval) {
+                    Err(RecvTimeoutError::Timeout) => {
+                        if let Err(e) = queue.flush(&tag) {
+                            let mut error = queue.error.lock().unwrap_or_else(|e| e.into_inner());
+                            error.get_or_insert(e);
+                        }
+                    }
+                    _ => return,
+                }
+            })
+        };
+        GroupCommit {
+            tag,
+            queue,
+            max_ops: options.max_ops.max(1),
+            stop: Some(stop),
+            worker: Some(worker),
+        }
+    }
+
+    /// A second handle on the tag `id`, keeping its name for events.
+    pub(crate) fn reopen(id: crate::CteTagId, name: String) -> Tag {
+        let mut tag = Tag::from_id(id);
+        tag.name = name;
+        tag
+    }
+
+    /// True if mutations of this tag can't be batched (see `group`).
+    fn needs_single_ops(&self) -> Result<bool, CteError> {
+        Ok(self.is_encrypted()?
+            || self.versioning_enabled()
+            || (trash::enabled() && !trash::is_trash_tag(self.name())))
+    }
+
+    fn apply_singly(&self, ops: Vec<Op>) -> Result<usize, CteError> {
+        let (mut applied, mut error) = (0, None);
+        for op in ops {
+            let result = match op {
+                Op::Put(name, data) => self.put(&name, &data, &PutOptions::default()).map(drop),
+                Op::SetAttrs(name, attrs) => {
+                    let attrs: Vec<(&str, &str)> = attrs
+                        .iter()
+                        .map(|(k, v)| (k.as_str(), v.as_str()))
+                        .collect();
+                    self.set_blob_attrs(&name, &attrs)
+                }
+                Op::Delete(name) => {
+                    self.del_blob(&name);
+                    Ok(())
+                }
+            };
+            match result {
+                Ok(()) => applied += 1,
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        error.map_or(Ok(applied), Err)
+    }
+
+    fn apply_batch(&self, pending: Vec<(String, Pending)>) -> Result<(), CteError> {
+        let _guard = meta_lock();
+        let names: Vec<String> = pending.iter().map(|(name, _)| name.clone()).collect();
+        let meta_names: Vec<String> = names
+            .iter()
+            .map(|name| format!("{}{}", META_PREFIX, name))
+            .collect();
+        let sizes = ffi::tag_get_blob_sizes(&self.inner, &names);
+        let meta_sizes = ffi::tag_get_blob_sizes(&self.inner, &meta_names);
+
+        let mut deletes = Vec::new();
+        let mut deleted = Vec::new();
+        let (mut put_names, mut data, mut lens, mut scores) =
+            (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        let mut written = Vec::new();
+        let mut error = None;
+        for (i, (name, p)) in pending.iter().enumerate() {
+            let exists = sizes[i] > 0 || meta_sizes[i] > 0;
+            if p.reset && exists {
+                deletes.push(name.clone());
+                deletes.push(meta_names[i].clone());
+                if sizes[i] > 0 {
+                    deleted.push(name.as_str());
+                }
+            }
+            if p.deleted() {
+                continue;
+            }
+            if p.data.is_none() && !exists {
+                error.get_or_insert(CteError::NotFound { blob: name.clone() });
+                continue;
+            }
+            let mut meta = if p.reset || meta_sizes[i] == 0 {
+                BlobMeta::default()
+            } else {
+                // As with plain puts, a corrupt sidecar is replaced rather than fatal.
+                let buf =
+                    match ffi_guard::tag_get_blob(&self.inner, &meta_names[i], meta_sizes[i], 0) {
+                        Ok(buf) => buf,
+                        Err(e) => {
+                            error.get_or_insert(e);
+                            continue;
+                        }
+                    };
+                match BlobMeta::decode(buf.as_slice()) {
+                    Ok(meta) => meta,
+                    Err(_) if p.data.is_some() => BlobMeta::default(),
+                    Err(reason) => {
+                        error.get_or_insert(CteError::CorruptMetadata {
+                            blob: name.clone(),
+                            reason,
+                        });
+                        continue;
+                    }
+                }
+            };
+            meta.attrs.extend(p.attrs.clone());
+            if let Some(bytes) = &p.data {
+                meta.generation += 1;
+                meta.written_ms = crate::ttl::now_ms();
+                meta.checksum = None;
+                meta.compressed = None;
+                meta.encrypted = None;
+                meta.reference = None;
+                if meta.is_expired() {
+                    meta.expires_ms = 0;
+                }
+                let desc = BlobDescriptor {
+                    tag_id: self.get_tag_id(),
+                    name: name.clone(),
+                    size: bytes.len() as u64,
+                    offset: 0,
+                };
+                put_names.push(name.clone());
+                lens.push(bytes.len() as u64);
+                scores.push(meta.pin().unwrap_or_else(|| placement_score(&desc)));
+                data.extend_from_slice(bytes);
+                written.push((name.as_str(), bytes.len() as u64));
+            }
+            let encoded = meta.encode();
+            put_names.push(meta_names[i].clone());
+            lens.push(encoded.len() as u64);
+            scores.push(1.0);
+            data.extend_from_slice(&encoded);
+        }
+        if !deletes.is_empty() {
+            ffi::tag_del_blobs(&self.inner, &deletes);
+        }
+        if !put_names.is_empty() {
+            ffi_guard::tag_put_blobs(&self.inner, &put_names, &data, &lens, &scores)?;
+        }
+
+        for name in deleted {
+            accounting::record(accounting::Op::Delete, 0);
+            #[cfg(feature = "metrics")]
+            crate::metrics::record(self.name(), accounting::Op::Delete, 0, None);
+            self.record_access(name, AccessKind::Delete, 0, 0);
+            self.record_change(name, ChangeKind::Delete);
+            events::emit(Event::BlobDeleted {
+                tag: self.name().to_string(),
+                blob: name.to_string(),
+            });
+        }
+        for (name, size) in written {
+            accounting::record(accounting::Op::Put, size);
+            #[cfg(feature = "metrics")]
+            crate::metrics::record(self.name(), accounting::Op::Put, size, None);
+            self.record_access(name, AccessKind::Write, 0, size);
+            self.record_change(name, ChangeKind::Put { offset: 0, size });
+            events::emit(Event::BlobPut {
+                tag: self.name().to_string(),
+                blob: name.to_string(),
+                offset: 0,
+                size,
+            });
+        }
+        error.map_or(Ok(()), Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collapse() {
+        let put = |n: &str, d: &[u8]| Op::Put(n.to_string(), d.to_vec());
+        let set = |n: &str, k: &str| Op::SetAttrs(n.to_string(), vec![(k.into(), "v".into())]);
+        let del = |n: &str| Op::Delete(n.to_string());
+        let (pending, error) = collapse(vec![
+            put("a", b"1"),
+            put("b", b"x"),
+            set("a", "k1"),
+            put("a", b"2"),
+            del("b"),
+            del("c"),
+            put("c", b"3"),
+            set("c", "k2"),
+            del("b"),
+            set("b", "k3"),
+        ]);
+        let names: Vec<&str> = pending.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, ["a", "b", "c"]);
+        let (a, b, c) = (&pending[0].1, &pending[1].1, &pending[2].1);
+        assert!(!a.reset && a.data.as_deref() == Some(&b"2"[..]) && a.attrs.contains_key("k1"));
+        assert!(b.deleted());
+        assert!(c.reset && c.data.as_deref() == Some(&b"3"[..]) && c.attrs.contains_key("k2"));
+        assert!(matches!(error, Some(CteError::NotFound { blob }) if blob == "b"));
+    }
+}
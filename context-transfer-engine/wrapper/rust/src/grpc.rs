@@ -0,0 +1,275 @@
+//! The Tag and Client API over gRPC (feature `grpc`).
+//!
+//! `Client::serve_grpc`, or the `clio-grpcd` daemon, answers the `wrp.cte.v1.Cte`
+//! service of `proto/cte.proto`, so Go services, remote notebooks and anything
+//! else with a gRPC stack can put, get, list and query blobs without linking
+//! the C++ client. Each call runs on a blocking thread of the service's own
+//! Tokio runtime, as the client's calls block. Messages are limited to
+//! `GrpcOptions::max_message_bytes` both ways; a `Get` that would be larger
+//! fails with `RESOURCE_EXHAUSTED`, so big blobs are read in ranges. As with
+//! the notebook service, a service without a token only listens on loopback
+//! addresses.
+
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::UNIX_EPOCH;
+
+use tonic::service::Routes;
+use tonic::{Request, Response, Status};
+
+use crate::rpc::{self, blocking, checked, existing};
+use crate::{Client, CteError, GetOptions, PutOptions, Tag};
+
+pub(crate) mod proto {
+    tonic::include_proto!("wrp.cte.v1");
+}
+
+use proto::cte_server::{Cte, CteServer};
+use proto::*;
+
+/// Settings for `Client::serve_grpc`.
+#[derive(Debug, Clone)]
+pub struct GrpcOptions {
+    /// Secret every call must present as `authorization: bearer <token>`;
+    /// `None` to accept any local call.
+    pub token: Option<String>,
+    /// Largest request or response message, in bytes.
+    pub max_message_bytes: usize,
+}
+
+impl Default for GrpcOptions {
+    fn default() -> Self {
+        Self {
+            token: None,
+            max_message_bytes: 64 << 20,
+        }
+    }
+}
+
+/// The tag and blob of a request, the tag existing.
+fn blob_of(tag: &str, blob: &str) -> Result<Tag, CteError> {
+    checked("blob", blob)?;
+    existing(tag)
+}
+
+fn unix_ms(t: std::time::SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+struct Service {
+    max_message_bytes: usize,
+}
+
+#[tonic::async_trait]
+impl Cte for Service {
+    async fn put(&self, request: Request<PutRequest>) -> Result<Response<PutResponse>, Status> {
+        let r = request.into_inner();
+        blocking(move || {
+            checked("tag", &r.tag)?;
+            checked("blob", &r.blob)?;
+            let options = PutOptions {
+                offset: r.offset,
+                score: r.score,
+                ..Default::default()
+            };
+            Tag::try_new(&r.tag)?.put(&r.blob, &r.data, &options)?;
+            Ok(PutResponse {})
+        })
+        .await
+    }
+
+    async fn get(&self, request: Request<GetRequest>) -> Result<Response<GetResponse>, Status> {
+        let r = request.into_inner();
+        let limit = self.max_message_bytes as u64;
+        blocking(move || {
+            let tag = blob_of(&r.tag, &r.blob)?;
+            let size = match r.size {
+                Some(size) => size,
+                None => {
+                    let stat = tag.stat_blob(&r.blob)?.ok_or_else(|| CteError::NotFound {
+                        blob: r.blob.clone(),
+                    })?;
+                    stat.size.saturating_sub(r.offset)
+                }
+            };
+            if size > limit {
+                return Err(CteError::TooLarge {
+                    blob: r.blob,
+                    size,
+                    limit: Some(limit),
+                });
+            }
+            let options = GetOptions {
+                offset: r.offset,
+                size: Some(size),
+                ..Default::default()
+            };
+            let data = tag.get(&r.blob, &options)?;
+            Ok(GetResponse { data })
+        })
+        .await
+    }
+
+    async fn stat(&self, request: Request<BlobRequest>) -> Result<Response<StatResponse>, Status> {
+        let r = request.into_inner();
+        blocking(move || {
+            let stat = blob_of(&r.tag, &r.blob)?
+                .stat_blob(&r.blob)?
+                .ok_or(CteError::NotFound { blob: r.blob })?;
+            Ok(StatResponse {
+                size: stat.size,
+                stored_size: stat.stored_size,
+                score: stat.score,
+                generation: stat.generation,
+                written_ms: stat.written.map(unix_ms),
+                expires_ms: stat.expires.map(unix_ms),
+            })
+        })
+        .await
+    }
+
+    async fn delete_blob(
+        &self,
+        request: Request<BlobRequest>,
+    ) -> Result<Response<DeleteResponse>, Status> {
+        let r = request.into_inner();
+        blocking(move || {
+            let deleted = blob_of(&r.tag, &r.blob)?.del_blob(&r.blob);
+            Ok(DeleteResponse { deleted })
+        })
+        .await
+    }
+
+    async fn reorganize_blob(
+        &self,
+        request: Request<ReorganizeRequest>,
+    ) -> Result<Response<ReorganizeResponse>, Status> {
+        let r = request.into_inner();
+        blocking(move || {
+            if !(0.0..=1.0).contains(&r.score) {
+                return Err(CteError::InvalidArgument(format!(
+                    "score {} is outside 0 to 1",
+                    r.score
+                )));
+            }
+            let tag = blob_of(&r.tag, &r.blob)?;
+            if tag.stat_blob(&r.blob)?.is_none() {
+                return Err(CteError::NotFound { blob: r.blob });
+            }
+            tag.try_reorganize_blob(&r.blob, r.score)?;
+            Ok(ReorganizeResponse {})
+        })
+        .await
+    }
+
+    async fn list(&self, request: Request<ListRequest>) -> Result<Response<ListResponse>, Status> {
+        let r = request.into_inner();
+        blocking(move || {
+            let listing = existing(&r.tag)?.list(&r.prefix, &r.delimiter)?;
+            Ok(ListResponse {
+                common_prefixes: listing.common_prefixes,
+                blobs: listing.blobs,
+            })
+        })
+        .await
+    }
+
+    async fn delete_tag(
+        &self,
+        request: Request<TagRequest>,
+    ) -> Result<Response<DeleteResponse>, Status> {
+        let r = request.into_inner();
+        blocking(move || {
+            existing(&r.tag)?;
+            Ok(DeleteResponse {
+                deleted: Client::del_tag(&r.tag),
+            })
+        })
+        .await
+    }
+
+    async fn query_tags(
+        &self,
+        request: Request<QueryTagsRequest>,
+    ) -> Result<Response<QueryTagsResponse>, Status> {
+        let r = request.into_inner();
+        blocking(move || {
+            Ok(QueryTagsResponse {
+                tags: Client::tag_query(&r.regex, r.max),
+            })
+        })
+        .await
+    }
+
+    async fn query_blobs(
+        &self,
+        request: Request<QueryBlobsRequest>,
+    ) -> Result<Response<QueryBlobsResponse>, Status> {
+        let r = request.into_inner();
+        blocking(move || {
+            let blobs = Client::blob_query(&r.tag_regex, &r.blob_regex, r.max)
+                .into_iter()
+                .map(|(tag, blob)| BlobRef { tag, blob })
+                .collect();
+            Ok(QueryBlobsResponse { blobs })
+        })
+        .await
+    }
+
+    async fn list_targets(
+        &self,
+        _request: Request<ListTargetsRequest>,
+    ) -> Result<Response<ListTargetsResponse>, Status> {
+        blocking(|| {
+            let targets = Client::list_targets()
+                .into_iter()
+                .map(|t| Target {
+                    name: t.name,
+                    score: t.score,
+                    remaining_bytes: t.remaining_space,
+                    bytes_read: t.bytes_read,
+                    bytes_written: t.bytes_written,
+                })
+                .collect();
+            Ok(ListTargetsResponse { targets })
+        })
+        .await
+    }
+}
+
+/// A service from `Client::serve_grpc`. Stops when dropped, letting calls in
+/// progress finish.
+pub struct GrpcServer {
+    server: rpc::Server,
+}
+
+impl GrpcServer {
+    /// The address the service listens on, with the port chosen if 0 was asked.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.server.local_addr()
+    }
+}
+
+impl Client {
+    /// Serve the gRPC API at `addr` (see `grpc`) until the returned server is
+    /// dropped. Fails with `InvalidArgument` for an address that isn't
+    /// loopback unless `options.token` is set.
+    pub fn serve_grpc(
+        addr: impl ToSocketAddrs,
+        options: &GrpcOptions,
+    ) -> Result<GrpcServer, CteError> {
+        let addrs = rpc::listen_addrs(addr, options.token.as_deref(), "gRPC")?;
+        let service = Service {
+            max_message_bytes: options.max_message_bytes,
+        };
+        let service = CteServer::new(service)
+            .max_decoding_message_size(options.max_message_bytes)
+            .max_encoding_message_size(options.max_message_bytes);
+        let service = tonic::service::interceptor::InterceptedService::new(
+            service,
+            rpc::authorize(options.token.clone()),
+        );
+        let server = rpc::serve(&addrs, Routes::new(service))?;
+        Ok(GrpcServer { server })
+    }
+}
@@ -0,0 +1,186 @@
+//! Handoff tokens: references to one version of a blob, passed between processes.
+//!
+//! A producer that tells a consumer "read blob X" by name races with whoever
+//! writes X next. `Tag::export_handle` instead captures the tag id, blob name and
+//! current generation in a `HandoffToken`, which serializes to a few dozen bytes
+//! (or a hex string) for a queue, RPC or command line. Redeeming it reads that
+//! exact version, or fails with `GenerationMismatch` if the blob has since been
+//! rewritten, and with `LeaseExpired` once an optional lease has run out. A lease
+//! bounds how long the token is honoured; it doesn't stop the blob from changing.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::meta::{read_str, read_u64, FieldReader, FieldWriter};
+use crate::ttl::now_ms;
+use crate::{CteError, CteTagId, GetOptions, Tag};
+
+const FIELD_TAG_MAJOR: u8 = 1;
+const FIELD_TAG_MINOR: u8 = 2;
+const FIELD_BLOB: u8 = 3;
+const FIELD_GENERATION: u8 = 4;
+const FIELD_LEASE: u8 = 5;
+
+/// A serializable reference to one version of a blob (see `handoff`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandoffToken {
+    pub tag_id: CteTagId,
+    pub blob: String,
+    pub generation: u64,
+    /// End of the lease in milliseconds since the Unix epoch; 0 means none.
+    lease_until_ms: u64,
+}
+
+impl HandoffToken {
+    /// When the lease runs out, if the token has one.
+    pub fn lease_until(&self) -> Option<SystemTime> {
+        (self.lease_until_ms != 0).then(|| UNIX_EPOCH + Duration::from_millis(self.lease_until_ms))
+    }
+
+    /// Open the tag the blob lives in.
+    pub fn tag(&self) -> Tag {
+        Tag::from_id(self.tag_id)
+    }
+
+    /// Read the referenced version of the blob.
+    pub fn redeem(&self) -> Result<Vec<u8>, CteError> {
+        self.check_lease()?;
+        let options = GetOptions {
+            if_generation_match: Some(self.generation),
+            ..Default::default()
+        };
+        self.tag().get(&self.blob, &options)
+    }
+
+    fn check_lease(&self) -> Result<(), CteError> {
+        if self.lease_until_ms != 0 && self.lease_until_ms <= now_ms() {
+            return Err(CteError::LeaseExpired {
+                blob: self.blob.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = FieldWriter::default();
+        w.u64(FIELD_TAG_MAJOR, self.tag_id.major as u64);
+        w.u64(FIELD_TAG_MINOR, self.tag_id.minor as u64);
+        w.bytes(FIELD_BLOB, self.blob.as_bytes());
+        w.u64(FIELD_GENERATION, self.generation);
+        if self.lease_until_ms != 0 {
+            w.u64(FIELD_LEASE, self.lease_until_ms);
+        }
+        w.finish()
+    }
+
+    pub fn from_bytes(buf: &[u8]) -> Result<Self, CteError> {
+        Self::decode(buf).map_err(|reason| {
+            CteError::InvalidArgument(format!("malformed handoff token: {}", reason))
+        })
+    }
+
+    fn decode(buf: &[u8]) -> Result<Self, String> {
+        let mut token = HandoffToken {
+            tag_id: CteTagId { major: 0, minor: 0 },
+            blob: String::new(),
+            generation: 0,
+            lease_until_ms: 0,
+        };
+        let mut has_blob = false;
+        for (id, value) in FieldReader::new(buf)? {
+            match id {
+                FIELD_TAG_MAJOR => token.tag_id.major = read_u32(value)?,
+                FIELD_TAG_MINOR => token.tag_id.minor = read_u32(value)?,
+                FIELD_BLOB => {
+                    token.blob = read_str(value)?;
+                    has_blob = true;
+                }
+                FIELD_GENERATION => token.generation = read_u64(value)?,
+                FIELD_LEASE => token.lease_until_ms = read_u64(value)?,
+                _ => {}
+            }
+        }
+        if !has_blob {
+            return Err("missing blob name".into());
+        }
+        Ok(token)
+    }
+}
+
+fn read_u32(value: &[u8]) -> Result<u32, String> {
+    u32::try_from(read_u64(value)?).map_err(|_| "tag id out of range".to_string())
+}
+
+/// Lowercase hex of `to_bytes`.
+impl fmt::Display for HandoffToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in self.to_bytes() {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for HandoffToken {
+    type Err = CteError;
+
+    fn from_str(s: &str) -> Result<Self, CteError> {
+        let bad = || CteError::InvalidArgument("handoff token is not valid hex".into());
+        if !s.len().is_multiple_of(2) || !s.is_ascii() {
+            return Err(bad());
+        }
+        let bytes = (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(|_| bad()))
+            .collect::<Result<Vec<u8>, _>>()?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+impl Tag {
+    /// Token for the current version of `name`.
+    pub fn export_handle(&self, name: &str) -> Result<HandoffToken, CteError> {
+        self.export(name, 0)
+    }
+
+    /// Token for the current version of `name` that is honoured for `lease`.
+    pub fn export_handle_with_lease(
+        &self,
+        name: &str,
+        lease: Duration,
+    ) -> Result<HandoffToken, CteError> {
+        self.export(name, crate::ttl::expiry_after(lease))
+    }
+
+    fn export(&self, name: &str, lease_until_ms: u64) -> Result<HandoffToken, CteError> {
+        let stat = self.stat_blob(name)?.ok_or_else(|| CteError::NotFound {
+            blob: name.to_string(),
+        })?;
+        Ok(HandoffToken {
+            tag_id: self.get_tag_id(),
+            blob: name.to_string(),
+            generation: stat.generation,
+            lease_until_ms,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_roundtrip() {
+        let token = HandoffToken {
+            tag_id: CteTagId { major: 7, minor: 3 },
+            blob: "shard-0042".into(),
+            generation: 12,
+            lease_until_ms: 1_700_000_000_000,
+        };
+        assert_eq!(HandoffToken::from_bytes(&token.to_bytes()).unwrap(), token);
+        assert_eq!(token.to_string().parse::<HandoffToken>().unwrap(), token);
+        assert!(token.check_lease().is_err());
+        assert!("abc".parse::<HandoffToken>().is_err());
+    }
+}
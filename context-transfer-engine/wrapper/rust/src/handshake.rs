@@ -0,0 +1,308 @@
+//! Version and feature negotiation with the C++ side at `init`.
+//!
+//! The shim is compiled against the CTE headers found at build time but runs
+//! against whichever `libwrp_cte_core_client` gets loaded and whichever runtime
+//! answers, and a runtime that numbers its methods differently from those
+//! headers would run the shim's tasks as other methods. So `init` asks for the
+//! library's and the runtime's versions and for the runtime's method table, and
+//! fails with an error naming the culprit if either is from another major
+//! version or older than `MIN_CORE_VERSION` (including builds that predate the
+//! handshake and can't answer), or if an operation every tag handle relies on
+//! is missing or has another ID.
+//!
+//! Optional operations the runtime lacks disable the features built on them
+//! for the process, as listed in `RuntimeInfo::disabled`: `Tag::stat_blobs`,
+//! `Client::query` and `Tag::append_blob_fenced` then return
+//! `CteError::Unsupported`, calls that can't fail (`Client::tag_query`,
+//! `Client::blob_query`, `Client::list_targets`) return nothing, and metadata
+//! sidecars are overwritten rather than swapped, and `Tag::list` rolls names
+//! up in the wrapper from the whole listing, instead of sending tasks the
+//! runtime would misread.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use crate::{ffi, rawname, timeout, Client, CteError};
+
+/// Oldest library and runtime this wrapper works with; newer ones must share
+/// its major version.
+const MIN_CORE_VERSION: [u64; 3] = [1, 0, 3];
+
+/// Runtime methods that tag handles and target registration use directly.
+const REQUIRED: &[&str] = &[
+    "GetOrCreateTag",
+    "PutBlob",
+    "GetBlob",
+    "GetBlobScore",
+    "GetBlobSize",
+    "GetContainedBlobs",
+    "ReorganizeBlob",
+    "DelBlob",
+    "DelTag",
+    "GetTagSize",
+    "RegisterTarget",
+    "ListTargets",
+];
+
+/// Optional features that depend on what the runtime supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Capability {
+    /// Size, score and metadata of many blobs at once (`Tag::stat_blobs`).
+    StatBlobs,
+    /// Tag and blob queries (`Client::tag_query`, `Client::blob_query`,
+    /// `Client::query`).
+    Query,
+    /// Per-target statistics (`Client::list_targets`).
+    TargetInfo,
+    /// Compare-and-swap of metadata sidecars, which fences out stale producers
+    /// across processes (`Tag::append_blob_fenced`).
+    CompareAndSwap,
+    /// Prefix and delimiter listings rolled up by the runtime (`Tag::list`).
+    ListBlobs,
+}
+
+impl Capability {
+    const ALL: [Capability; 5] = [
+        Capability::StatBlobs,
+        Capability::Query,
+        Capability::TargetInfo,
+        Capability::CompareAndSwap,
+        Capability::ListBlobs,
+    ];
+
+    /// Runtime methods the feature sends.
+    fn methods(self) -> &'static [&'static str] {
+        match self {
+            Capability::StatBlobs => &["GetBlobInfo"],
+            Capability::Query => &["TagQuery", "BlobQuery"],
+            Capability::TargetInfo => &["GetTargetInfo"],
+            Capability::CompareAndSwap => &["CompareAndSwapBlob"],
+            Capability::ListBlobs => &["ListBlobs"],
+        }
+    }
+}
+
+/// What `init` agreed on with the C++ side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeInfo {
+    /// Version of this wrapper.
+    pub wrapper: &'static str,
+    /// Version of the loaded `libwrp_cte_core_client`.
+    pub library: String,
+    /// Version of the runtime serving this client.
+    pub runtime: String,
+    /// Features turned off because the runtime doesn't support them.
+    pub disabled: Vec<Capability>,
+    /// Longest tag or blob name accepted, in bytes (see `rawname`).
+    pub max_name_len: usize,
+}
+
+impl RuntimeInfo {
+    /// Whether `capability` is available in this process.
+    pub fn supports(&self, capability: Capability) -> bool {
+        !self.disabled.contains(&capability)
+    }
+}
+
+static NEGOTIATED: RwLock<Option<RuntimeInfo>> = RwLock::new(None);
+
+/// `major.minor.patch`, ignoring anything after the patch number.
+fn parse_version(version: &str) -> Option<[u64; 3]> {
+    let mut parts = version.splitn(3, '.');
+    let mut out = [0; 3];
+    for (i, slot) in out.iter_mut().enumerate() {
+        let part = parts.next()?;
+        let part = if i == 2 {
+            let end = part
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(part.len());
+            &part[..end]
+        } else {
+            part
+        };
+        *slot = part.parse().ok()?;
+    }
+    Some(out)
+}
+
+fn check_version(what: &str, version: &str) -> Result<(), String> {
+    let [major, minor, patch] = MIN_CORE_VERSION;
+    let needs = format!(
+        "this wrapper needs {}.{}.{} or a newer {}.x",
+        major, minor, patch, major
+    );
+    if version.is_empty() {
+        return Err(format!("{} predates version negotiation; {}", what, needs));
+    }
+    match parse_version(version) {
+        Some(v) if v[0] == major && v >= MIN_CORE_VERSION => Ok(()),
+        Some(_) => Err(format!("{} is version {}; {}", what, version, needs)),
+        None => Err(format!(
+            "{} reports an unreadable version {:?}",
+            what, version
+        )),
+    }
+}
+
+/// Method name to ID, from a list of `id:Name`.
+fn method_table(methods: &[String]) -> HashMap<&str, u32> {
+    methods
+        .iter()
+        .filter_map(|m| {
+            let (id, name) = m.split_once(':')?;
+            Some((name, id.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Compare versions and method tables; the agreed settings, or why the two
+/// sides can't work together.
+fn check(info: &ffi::HandshakeInfo) -> Result<RuntimeInfo, String> {
+    check_version("libwrp_cte_core_client", &info.library)?;
+    check_version("the CTE runtime", &info.runtime)?;
+    let bridge = method_table(&info.bridge_methods);
+    let runtime = method_table(&info.runtime_methods);
+    let mismatch = |name: &str| match (bridge.get(name), runtime.get(name)) {
+        (Some(b), Some(r)) if b == r => None,
+        (Some(b), Some(r)) => Some(format!(
+            "the CTE runtime handles {} as method {}, but this wrapper sends it as {}",
+            name, r, b
+        )),
+        (None, _) => Some(format!("this wrapper was built without {}", name)),
+        (_, None) => Some(format!("the CTE runtime doesn't handle {}", name)),
+    };
+    if let Some(reason) = REQUIRED.iter().find_map(|m| mismatch(m)) {
+        return Err(format!(
+            "{}; rebuild wrp-cte-rs against the headers of the installed runtime",
+            reason
+        ));
+    }
+    let disabled = Capability::ALL
+        .into_iter()
+        .filter(|c| c.methods().iter().any(|m| mismatch(m).is_some()))
+        .collect();
+    Ok(RuntimeInfo {
+        wrapper: env!("CARGO_PKG_VERSION"),
+        library: info.library.clone(),
+        runtime: info.runtime.clone(),
+        disabled,
+        max_name_len: rawname::max_name_len(),
+    })
+}
+
+/// Run the handshake for `init`.
+pub(crate) fn negotiate() -> Result<(), String> {
+    let info = ffi::runtime_handshake();
+    if timeout::timed_out() {
+        return Err("the CTE runtime didn't answer the version handshake in time".into());
+    }
+    let agreed = check(&info)?;
+    *NEGOTIATED.write().unwrap_or_else(|e| e.into_inner()) = Some(agreed);
+    Ok(())
+}
+
+/// Whether `capability` is available; before `init`, assume it is.
+pub(crate) fn supports(capability: Capability) -> bool {
+    let negotiated = NEGOTIATED.read().unwrap_or_else(|e| e.into_inner());
+    negotiated
+        .as_ref()
+        .is_none_or(|info| info.supports(capability))
+}
+
+/// `Unsupported` if `capability` is disabled.
+pub(crate) fn require(capability: Capability) -> Result<(), CteError> {
+    if supports(capability) {
+        return Ok(());
+    }
+    let runtime = Client::runtime_info()
+        .map(|info| info.runtime)
+        .unwrap_or_default();
+    Err(CteError::Unsupported(format!(
+        "{:?} needs {}, which CTE runtime {} lacks",
+        capability,
+        capability.methods().join(" and "),
+        runtime
+    )))
+}
+
+impl Client {
+    /// Versions and features agreed with the runtime by `init`, and the limits
+    /// in force; `None` before `init`.
+    pub fn runtime_info() -> Option<RuntimeInfo> {
+        let negotiated = NEGOTIATED.read().unwrap_or_else(|e| e.into_inner());
+        negotiated.clone().map(|info| RuntimeInfo {
+            max_name_len: rawname::max_name_len(),
+            ..info
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn methods(table: &[(u32, &str)]) -> Vec<String> {
+        table
+            .iter()
+            .map(|(id, n)| format!("{}:{}", id, n))
+            .collect()
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let mut table: Vec<(u32, &str)> = (10..).zip(REQUIRED.iter().copied()).collect();
+        table.extend([
+            (25, "GetBlobInfo"),
+            (35, "CompareAndSwapBlob"),
+            (36, "ListBlobs"),
+            (30, "TagQuery"),
+            (31, "BlobQuery"),
+            (32, "GetTargetInfo"),
+        ]);
+        let info = ffi::HandshakeInfo {
+            library: "1.0.3".into(),
+            runtime: "1.2.0-rc1".into(),
+            bridge_methods: methods(&table),
+            runtime_methods: methods(&table),
+        };
+        let agreed = check(&info).unwrap();
+        assert!(agreed.disabled.is_empty());
+        assert_eq!(agreed.runtime, "1.2.0-rc1");
+
+        // Optional methods the runtime lacks turn their features off.
+        let older = ffi::HandshakeInfo {
+            runtime_methods: methods(&table[..table.len() - 2]),
+            ..info.clone()
+        };
+        let agreed = check(&older).unwrap();
+        assert_eq!(agreed.disabled, [Capability::Query, Capability::TargetInfo]);
+        assert!(agreed.supports(Capability::StatBlobs));
+
+        // A required method under another ID is fatal.
+        let mut shifted = table.clone();
+        shifted[1].0 = 99;
+        let shifted = ffi::HandshakeInfo {
+            runtime_methods: methods(&shifted),
+            ..info.clone()
+        };
+        let err = check(&shifted).unwrap_err();
+        assert!(err.contains("PutBlob as method 99"), "{}", err);
+
+        for (library, runtime) in [
+            ("", "1.0.3"),
+            ("1.0.2", "1.0.3"),
+            ("1.0.3", "2.0.0"),
+            ("1.0.3", ""),
+        ] {
+            let mismatched = ffi::HandshakeInfo {
+                library: library.into(),
+                runtime: runtime.into(),
+                ..info.clone()
+            };
+            assert!(check(&mismatched).is_err(), "{} / {}", library, runtime);
+        }
+        assert_eq!(parse_version("1.10.3+git"), Some([1, 10, 3]));
+        assert_eq!(parse_version("1.0"), None);
+    }
+}
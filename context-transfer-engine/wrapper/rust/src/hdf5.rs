@@ -0,0 +1,493 @@
+//! An HDF5 virtual file driver over blobs (feature `hdf5-vfd`).
+//!
+//! Built with this feature, the library is also an HDF5 VFD plugin named
+//! `cte`: with `HDF5_PLUGIN_PATH` naming the directory of `libwrp_cte_rs.so`
+//! and `HDF5_DRIVER=cte`, HDF5 1.14 or later opens `cte://<tag>/<blob>` paths
+//! as blobs, so an HDF5 code writes its files into CTE's tiers without being
+//! changed. A program linking the library can instead register the driver
+//! with `H5FDregister(H5PLget_plugin_info())` and select it with
+//! `H5Pset_driver`. No HDF5 library is needed to build it.
+//!
+//! The driver reads through a `BlobReader` and writes through a `BlobWriter`
+//! (see `stream`), so HDF5's many small metadata writes reach the runtime as
+//! fewer, larger puts; they are put before an overlapping read, on `H5Fflush`
+//! and at close. Reads past the end of the blob return zeros, as from a file.
+//! The client is initialized on the first open if the process hasn't done it,
+//! with the runtime's environment (as `init("")`). A call that fails returns a
+//! negative status to HDF5, and `cte_c_last_error` says why.
+
+use std::ffi::{c_char, c_int, c_uint, c_ulong, c_void, CStr};
+use std::ptr;
+
+use crate::{ffi_guard, BlobReader, BlobWriter, CteError, CteTagId, Tag};
+
+/// Prefix of the paths the driver opens.
+pub const CTE_URL_PREFIX: &str = "cte://";
+
+/// `H5FD_class_value_t` of the driver, from the range HDF5 leaves to drivers
+/// it doesn't ship.
+const DRIVER_VALUE: c_int = 455;
+/// `H5FD_CLASS_VERSION` of HDF5 1.14.
+const CLASS_VERSION: c_uint = 1;
+/// `H5PL_TYPE_VFD`.
+const PLUGIN_TYPE_VFD: c_int = 2;
+/// `H5F_CLOSE_WEAK`, as the sec2 driver has.
+const CLOSE_WEAK: c_int = 1;
+/// `HADDR_UNDEF`.
+const ADDR_UNDEF: u64 = u64::MAX;
+/// `H5FD_MEM_NTYPES`.
+const MEM_NTYPES: usize = 7;
+
+/// `H5F_ACC_*` flags of `open`.
+const ACC_RDWR: c_uint = 0x0001;
+const ACC_TRUNC: c_uint = 0x0002;
+const ACC_EXCL: c_uint = 0x0004;
+const ACC_CREAT: c_uint = 0x0010;
+
+/// `H5FD_FEAT_*` flags of `query`: let HDF5 gather metadata and small raw
+/// data into larger blocks and sieve reads, as for the sec2 driver.
+const FEAT_AGGREGATE_METADATA: c_ulong = 0x0001;
+const FEAT_ACCUMULATE_METADATA: c_ulong = 0x0006;
+const FEAT_DATA_SIEVE: c_ulong = 0x0008;
+const FEAT_AGGREGATE_SMALLDATA: c_ulong = 0x0010;
+
+type Hid = i64;
+type Herr = c_int;
+type Mem = c_int;
+
+/// `H5FD_t`, the part of an open file HDF5 fills in.
+#[repr(C)]
+#[allow(dead_code)]
+struct RawFile {
+    driver_id: Hid,
+    cls: *const FileClass,
+    fileno: c_ulong,
+    access_flags: c_uint,
+    feature_flags: c_ulong,
+    maxaddr: u64,
+    base_addr: u64,
+    threshold: u64,
+    alignment: u64,
+    paged_aggr: bool,
+}
+
+type Unused = Option<unsafe extern "C" fn()>;
+
+/// `H5FD_class_t` of HDF5 1.14; the callbacks the driver leaves to HDF5 are
+/// `Unused`.
+#[repr(C)]
+#[allow(dead_code)]
+struct FileClass {
+    version: c_uint,
+    value: c_int,
+    name: *const c_char,
+    maxaddr: u64,
+    fc_degree: c_int,
+    terminate: Unused,
+    sb_size: Unused,
+    sb_encode: Unused,
+    sb_decode: Unused,
+    fapl_size: usize,
+    fapl_get: Unused,
+    fapl_copy: Unused,
+    fapl_free: Unused,
+    dxpl_size: usize,
+    dxpl_copy: Unused,
+    dxpl_free: Unused,
+    open: Option<unsafe extern "C" fn(*const c_char, c_uint, Hid, u64) -> *mut RawFile>,
+    close: Option<unsafe extern "C" fn(*mut RawFile) -> Herr>,
+    cmp: Option<unsafe extern "C" fn(*const RawFile, *const RawFile) -> c_int>,
+    query: Option<unsafe extern "C" fn(*const RawFile, *mut c_ulong) -> Herr>,
+    get_type_map: Unused,
+    alloc: Unused,
+    free: Unused,
+    get_eoa: Option<unsafe extern "C" fn(*const RawFile, Mem) -> u64>,
+    set_eoa: Option<unsafe extern "C" fn(*mut RawFile, Mem, u64) -> Herr>,
+    get_eof: Option<unsafe extern "C" fn(*const RawFile, Mem) -> u64>,
+    get_handle: Unused,
+    read: Option<unsafe extern "C" fn(*mut RawFile, Mem, Hid, u64, usize, *mut c_void) -> Herr>,
+    write: Option<unsafe extern "C" fn(*mut RawFile, Mem, Hid, u64, usize, *const c_void) -> Herr>,
+    read_vector: Unused,
+    write_vector: Unused,
+    read_selection: Unused,
+    write_selection: Unused,
+    flush: Option<unsafe extern "C" fn(*mut RawFile, Hid, bool) -> Herr>,
+    truncate: Option<unsafe extern "C" fn(*mut RawFile, Hid, bool) -> Herr>,
+    lock: Unused,
+    unlock: Unused,
+    del: Option<unsafe extern "C" fn(*const c_char, Hid) -> Herr>,
+    ctl: Unused,
+    fl_map: [Mem; MEM_NTYPES],
+}
+
+/// The class is only read, by HDF5 and the callbacks.
+struct Class(FileClass);
+
+unsafe impl Sync for Class {}
+
+static CLASS: Class = Class(FileClass {
+    version: CLASS_VERSION,
+    value: DRIVER_VALUE,
+    name: c"cte".as_ptr(),
+    maxaddr: (1 << 63) - 1,
+    fc_degree: CLOSE_WEAK,
+    terminate: None,
+    sb_size: None,
+    sb_encode: None,
+    sb_decode: None,
+    fapl_size: 0,
+    fapl_get: None,
+    fapl_copy: None,
+    fapl_free: None,
+    dxpl_size: 0,
+    dxpl_copy: None,
+    dxpl_free: None,
+    open: Some(vfd_open),
+    close: Some(vfd_close),
+    cmp: Some(vfd_cmp),
+    query: Some(vfd_query),
+    get_type_map: None,
+    alloc: None,
+    free: None,
+    get_eoa: Some(vfd_get_eoa),
+    set_eoa: Some(vfd_set_eoa),
+    get_eof: Some(vfd_get_eof),
+    get_handle: None,
+    read: Some(vfd_read),
+    write: Some(vfd_write),
+    read_vector: None,
+    write_vector: None,
+    read_selection: None,
+    write_selection: None,
+    flush: Some(vfd_flush),
+    truncate: Some(vfd_truncate),
+    lock: None,
+    unlock: None,
+    del: Some(vfd_del),
+    ctl: None,
+    // `H5FD_FLMAP_DEFAULT`: one free list for every kind of data.
+    fl_map: [0; MEM_NTYPES],
+});
+
+/// The tag and blob of a `cte://<tag>/<blob>` path.
+fn blob_path(path: &str) -> Result<(&str, &str), CteError> {
+    path.strip_prefix(CTE_URL_PREFIX)
+        .and_then(|rest| rest.split_once('/'))
+        .filter(|(tag, blob)| !tag.is_empty() && !blob.is_empty())
+        .ok_or_else(|| {
+            CteError::InvalidArgument(format!(
+                "'{}' isn't a {}<tag>/<blob> path",
+                path, CTE_URL_PREFIX
+            ))
+        })
+}
+
+/// An open file: `RawFile` first, as HDF5 sees it.
+#[repr(C)]
+pub(crate) struct File {
+    raw: RawFile,
+    tag: Tag,
+    blob: String,
+    writable: bool,
+    /// Opened by the first read, and again once the blob has grown.
+    reader: Option<BlobReader>,
+    writer: BlobWriter,
+    /// End of the address space HDF5 has allocated.
+    eoa: u64,
+}
+
+impl File {
+    /// Open `path` with `H5F_ACC_*` `flags`.
+    pub(crate) fn open(path: &str, flags: c_uint) -> Result<Self, CteError> {
+        let (tag, blob) = blob_path(path)?;
+        crate::try_init("")?;
+        let tag = Tag::try_new(tag)?;
+        let exists = tag.stat_blob(blob)?.is_some();
+        if exists && flags & ACC_EXCL != 0 {
+            return Err(CteError::InvalidArgument(format!(
+                "'{}' already exists",
+                path
+            )));
+        }
+        if !exists && flags & ACC_CREAT == 0 {
+            return Err(CteError::NotFound {
+                blob: blob.to_string(),
+            });
+        }
+        if exists && flags & ACC_TRUNC != 0 {
+            tag.del_blob(blob);
+        }
+        let writer = tag.writer(blob)?;
+        Ok(Self {
+            raw: RawFile {
+                driver_id: 0,
+                cls: &CLASS.0,
+                fileno: 0,
+                access_flags: 0,
+                feature_flags: 0,
+                maxaddr: 0,
+                base_addr: 0,
+                threshold: 0,
+                alignment: 0,
+                paged_aggr: false,
+            },
+            tag,
+            blob: blob.to_string(),
+            writable: flags & ACC_RDWR != 0,
+            reader: None,
+            writer,
+            eoa: 0,
+        })
+    }
+
+    pub(crate) fn eof(&self) -> u64 {
+        self.writer.len()
+    }
+
+    /// Fill `buf` from `addr`, with zeros past the end of the file.
+    pub(crate) fn read(&mut self, addr: u64, buf: &mut [u8]) -> Result<(), CteError> {
+        let mut filled = 0;
+        if addr < self.eof() {
+            self.writer.send()?;
+            // A reader from before the blob grew stops at its old end.
+            let stale = match &self.reader {
+                Some(reader) => reader.len() != self.eof(),
+                None => true,
+            };
+            if stale {
+                self.reader = Some(self.tag.reader(&self.blob)?);
+            }
+            let reader = self.reader.as_ref().unwrap();
+            while filled < buf.len() {
+                let n = reader.read_at(addr + filled as u64, &mut buf[filled..])?;
+                if n == 0 {
+                    break;
+                }
+                filled += n;
+            }
+        }
+        buf[filled..].fill(0);
+        Ok(())
+    }
+
+    pub(crate) fn write(&mut self, addr: u64, data: &[u8]) -> Result<(), CteError> {
+        if !self.writable {
+            return Err(CteError::InvalidArgument(format!(
+                "'{}' is open read-only",
+                self.blob
+            )));
+        }
+        self.writer.write_at(addr, data)
+    }
+
+    /// Make the blob end at `eoa`, as `ftruncate` makes a file.
+    pub(crate) fn truncate(&mut self) -> Result<(), CteError> {
+        let (eoa, eof) = (self.eoa, self.eof());
+        if !self.writable || eoa == eof {
+            return Ok(());
+        }
+        if eoa > eof {
+            return self.writer.write_at(eoa - 1, &[0]);
+        }
+        // Blobs only grow, so a shorter file is written again.
+        let mut data = vec![0; eoa as usize];
+        self.read(0, &mut data)?;
+        self.tag.del_blob(&self.blob);
+        let mut writer = self.tag.writer(&self.blob)?;
+        writer.write_at(0, &data)?;
+        writer.send()?;
+        self.writer = writer;
+        self.reader = None;
+        Ok(())
+    }
+
+    pub(crate) fn flush(&mut self) -> Result<(), CteError> {
+        self.writer.send()
+    }
+
+    fn key(&self) -> (CteTagId, &str) {
+        (self.tag.get_tag_id(), &self.blob)
+    }
+}
+
+/// The `File` behind a pointer HDF5 passes back.
+unsafe fn file<'a>(raw: *const RawFile) -> Result<&'a mut File, CteError> {
+    if raw.is_null() {
+        return Err(CteError::InvalidArgument("null HDF5 file".into()));
+    }
+    Ok(unsafe { &mut *(raw as *mut File) })
+}
+
+unsafe fn path_arg<'a>(name: *const c_char) -> Result<&'a str, CteError> {
+    if name.is_null() {
+        return Err(CteError::InvalidArgument("null file name".into()));
+    }
+    unsafe { CStr::from_ptr(name) }
+        .to_str()
+        .map_err(|_| CteError::InvalidArgument("file name is not UTF-8".into()))
+}
+
+unsafe extern "C" fn vfd_open(
+    name: *const c_char,
+    flags: c_uint,
+    _fapl: Hid,
+    _maxaddr: u64,
+) -> *mut RawFile {
+    let mut opened = ptr::null_mut();
+    ffi_guard::c_status("h5fd_open", || {
+        let file = File::open(unsafe { path_arg(name) }?, flags)?;
+        opened = Box::into_raw(Box::new(file)) as *mut RawFile;
+        Ok(())
+    });
+    opened
+}
+
+unsafe extern "C" fn vfd_close(raw: *mut RawFile) -> Herr {
+    ffi_guard::c_status("h5fd_close", || {
+        unsafe { file(raw) }?;
+        let mut file = unsafe { Box::from_raw(raw as *mut File) };
+        file.flush()
+    })
+}
+
+unsafe extern "C" fn vfd_cmp(a: *const RawFile, b: *const RawFile) -> c_int {
+    let mut order = 0;
+    ffi_guard::c_status("h5fd_cmp", || {
+        let (a, b) = unsafe { (file(a)?, file(b)?) };
+        let (ka, kb) = (a.key(), b.key());
+        let ka = (ka.0.major, ka.0.minor, ka.1);
+        let kb = (kb.0.major, kb.0.minor, kb.1);
+        order = ka.cmp(&kb) as c_int;
+        Ok(())
+    });
+    order
+}
+
+unsafe extern "C" fn vfd_query(_raw: *const RawFile, flags: *mut c_ulong) -> Herr {
+    if !flags.is_null() {
+        unsafe {
+            *flags = FEAT_AGGREGATE_METADATA
+                | FEAT_ACCUMULATE_METADATA
+                | FEAT_DATA_SIEVE
+                | FEAT_AGGREGATE_SMALLDATA
+        };
+    }
+    0
+}
+
+unsafe extern "C" fn vfd_get_eoa(raw: *const RawFile, _kind: Mem) -> u64 {
+    unsafe { file(raw) }.map_or(ADDR_UNDEF, |f| f.eoa)
+}
+
+unsafe extern "C" fn vfd_set_eoa(raw: *mut RawFile, _kind: Mem, addr: u64) -> Herr {
+    ffi_guard::c_status("h5fd_set_eoa", || {
+        unsafe { file(raw) }?.eoa = addr;
+        Ok(())
+    })
+}
+
+unsafe extern "C" fn vfd_get_eof(raw: *const RawFile, _kind: Mem) -> u64 {
+    unsafe { file(raw) }.map_or(ADDR_UNDEF, |f| f.eof())
+}
+
+unsafe extern "C" fn vfd_read(
+    raw: *mut RawFile,
+    _kind: Mem,
+    _dxpl: Hid,
+    addr: u64,
+    size: usize,
+    buf: *mut c_void,
+) -> Herr {
+    ffi_guard::c_status("h5fd_read", || {
+        let file = unsafe { file(raw) }?;
+        if size == 0 {
+            return Ok(());
+        }
+        if buf.is_null() {
+            return Err(CteError::InvalidArgument("null read buffer".into()));
+        }
+        file.read(addr, unsafe {
+            std::slice::from_raw_parts_mut(buf as *mut u8, size)
+        })
+    })
+}
+
+unsafe extern "C" fn vfd_write(
+    raw: *mut RawFile,
+    _kind: Mem,
+    _dxpl: Hid,
+    addr: u64,
+    size: usize,
+    buf: *const c_void,
+) -> Herr {
+    ffi_guard::c_status("h5fd_write", || {
+        let file = unsafe { file(raw) }?;
+        if size == 0 {
+            return Ok(());
+        }
+        if buf.is_null() {
+            return Err(CteError::InvalidArgument("null write buffer".into()));
+        }
+        file.write(addr, unsafe {
+            std::slice::from_raw_parts(buf as *const u8, size)
+        })
+    })
+}
+
+unsafe extern "C" fn vfd_flush(raw: *mut RawFile, _dxpl: Hid, _closing: bool) -> Herr {
+    ffi_guard::c_status("h5fd_flush", || unsafe { file(raw) }?.flush())
+}
+
+unsafe extern "C" fn vfd_truncate(raw: *mut RawFile, _dxpl: Hid, _closing: bool) -> Herr {
+    ffi_guard::c_status("h5fd_truncate", || unsafe { file(raw) }?.truncate())
+}
+
+unsafe extern "C" fn vfd_del(name: *const c_char, _fapl: Hid) -> Herr {
+    ffi_guard::c_status("h5fd_del", || {
+        let path = unsafe { path_arg(name) }?;
+        let (tag, blob) = blob_path(path)?;
+        crate::try_init("")?;
+        if !Tag::try_new(tag)?.del_blob(blob) {
+            return Err(CteError::NotFound {
+                blob: blob.to_string(),
+            });
+        }
+        Ok(())
+    })
+}
+
+/// The kind of HDF5 plugin this library is: a file driver.
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "C" fn H5PLget_plugin_type() -> c_int {
+    PLUGIN_TYPE_VFD
+}
+
+/// The driver's `H5FD_class_t`, for HDF5's plugin loader or `H5FDregister`.
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "C" fn H5PLget_plugin_info() -> *const c_void {
+    &CLASS.0 as *const FileClass as *const c_void
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blob_path() {
+        assert_eq!(
+            blob_path("cte://sim/out/step1.h5").unwrap(),
+            ("sim", "out/step1.h5")
+        );
+        assert!(blob_path("cte://sim/").is_err());
+        assert!(blob_path("cte:///f.h5").is_err());
+        assert!(blob_path("/scratch/f.h5").is_err());
+
+        // The layout HDF5 1.14 expects of `H5FD_class_t` on 64-bit hosts.
+        assert_eq!(std::mem::offset_of!(FileClass, open), 120);
+        assert_eq!(std::mem::offset_of!(FileClass, ctl), 296);
+        assert_eq!(std::mem::size_of::<FileClass>(), 336);
+        assert_eq!(H5PLget_plugin_type(), PLUGIN_TYPE_VFD);
+    }
+}
@@ -0,0 +1,261 @@
+//! Minimal HTTP/1.1 server for the wrapper's endpoints (`metrics`, `gateway`,
+//! `notebook`).
+//!
+//! One request per connection, answered on a thread of its own and then closed;
+//! enough for scrapers, CDNs and `curl` without pulling in an HTTP stack.
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::archive::unescape;
+
+/// Connections answered at once; more are turned away with a 503.
+const MAX_CONNECTIONS: usize = 64;
+
+/// Longest request head read before giving up on a client.
+const MAX_HEAD: usize = 16 << 10;
+
+pub(crate) struct Request {
+    pub method: String,
+    /// The path with any query string removed, still percent-encoded.
+    pub path: String,
+    /// The query string without the `?`; empty if there was none.
+    pub query: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    fn parse(head: &str) -> Option<Self> {
+        let mut lines = head.split("\r\n");
+        let mut words = lines.next()?.split_whitespace();
+        let method = words.next()?.to_string();
+        let target = words.next()?;
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let (path, query) = (path.to_string(), query.to_string());
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+        Some(Self {
+            method,
+            path,
+            query,
+            headers,
+        })
+    }
+
+    /// Value of header `name` (case-insensitive), if sent.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Percent-decoded value of query parameter `name`, the first if it is
+    /// repeated; `None` if it wasn't sent or doesn't decode.
+    pub fn param(&self, name: &str) -> Option<String> {
+        self.query
+            .split('&')
+            .filter_map(|pair| pair.split_once('=').or(Some((pair, ""))))
+            .find(|(n, _)| *n == name)
+            .and_then(|(_, v)| unescape(&v.replace('+', " ")).ok())
+    }
+}
+
+pub(crate) struct Response {
+    status: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: &'static str, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    pub fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    fn write(&self, out: &mut impl Write, head_only: bool) -> io::Result<()> {
+        write!(out, "HTTP/1.1 {}\r\n", self.status)?;
+        for (name, value) in &self.headers {
+            write!(out, "{}: {}\r\n", name, value)?;
+        }
+        write!(
+            out,
+            "Content-Length: {}\r\nConnection: close\r\n\r\n",
+            self.body.len()
+        )?;
+        if !head_only {
+            out.write_all(&self.body)?;
+        }
+        out.flush()
+    }
+}
+
+/// Equal without stopping at the first difference, for comparing tokens.
+pub(crate) fn same_secret(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+type Handler = dyn Fn(&Request) -> Response + Send + Sync;
+
+/// Read one request from `stream` and answer it; a handler that panics is
+/// answered with a 500.
+fn answer(mut stream: TcpStream, handler: &Handler) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        if head.len() > MAX_HEAD {
+            return Response::new("431 Request Header Fields Too Large", "")
+                .write(&mut stream, false);
+        }
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    let Some(request) = Request::parse(&String::from_utf8_lossy(&head)) else {
+        return Response::new("400 Bad Request", "bad request\n").write(&mut stream, false);
+    };
+    let response = catch_unwind(AssertUnwindSafe(|| handler(&request)))
+        .unwrap_or_else(|_| Response::new("500 Internal Server Error", "handler panicked\n"));
+    response.write(&mut stream, request.method == "HEAD")
+}
+
+/// A connection counted against `MAX_CONNECTIONS` until dropped.
+struct Slot(Arc<AtomicUsize>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// A running server, from `serve`. Stops accepting when dropped; requests
+/// already being answered finish on their own threads.
+pub(crate) struct Server {
+    addr: SocketAddr,
+    stop: Option<Sender<()>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Server {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Answer requests to `addr` with `handler` on background threads.
+pub(crate) fn serve(
+    addr: impl ToSocketAddrs,
+    handler: impl Fn(&Request) -> Response + Send + Sync + 'static,
+) -> io::Result<Server> {
+    let listener = TcpListener::bind(addr)?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+    let handler: Arc<Handler> = Arc::new(handler);
+    let busy = Arc::new(AtomicUsize::new(0));
+    let (stop, stopped) = mpsc::channel::<()>();
+    let worker = std::thread::spawn(move || loop {
+        match listener.accept() {
+            Ok((mut stream, _)) => {
+                if busy.fetch_add(1, Ordering::AcqRel) >= MAX_CONNECTIONS {
+                    busy.fetch_sub(1, Ordering::AcqRel);
+                    let _ = Response::new("503 Service Unavailable", "busy\n")
+                        .header("Retry-After", "1")
+                        .write(&mut stream, false);
+                    continue;
+                }
+                let (handler, slot) = (Arc::clone(&handler), Slot(Arc::clone(&busy)));
+                std::thread::spawn(move || {
+                    let _slot = slot;
+                    // A failed exchange only affects that client.
+                    let _ = answer(stream, &*handler);
+                });
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                match stopped.recv_timeout(Duration::from_millis(50)) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => return,
+                }
+            }
+            Err(_) => std::thread::sleep(Duration::from_millis(50)),
+        }
+    });
+    Ok(Server {
+        addr,
+        stop: Some(stop),
+        worker: Some(worker),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_and_response() {
+        let request = Request::parse(
+            "GET /data/a%20b?x=1 HTTP/1.1\r\nHost: cte\r\nIf-None-Match: \"g3\"\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/data/a%20b");
+        assert_eq!(request.param("x").as_deref(), Some("1"));
+        assert_eq!(request.param("y"), None);
+        assert_eq!(request.header("if-none-match"), Some("\"g3\""));
+        assert_eq!(request.header("Accept"), None);
+        assert!(Request::parse("").is_none());
+
+        let response = Response::new("200 OK", "hello").header("ETag", "\"g3\"");
+        let mut out = Vec::new();
+        response.write(&mut out, true).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "HTTP/1.1 200 OK\r\nETag: \"g3\"\r\nContent-Length: 5\r\nConnection: close\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_handler_panic_frees_its_slot() {
+        let server = serve("127.0.0.1:0", |_| panic!("handler bug")).unwrap();
+        // More panics than connection slots: each is a 500, never a 503.
+        for _ in 0..MAX_CONNECTIONS + 1 {
+            let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+            stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+            let mut reply = String::new();
+            stream.read_to_string(&mut reply).unwrap();
+            assert!(reply.starts_with("HTTP/1.1 500 "), "{}", reply);
+        }
+    }
+}
@@ -0,0 +1,377 @@
+//! Client-side prefix index of a tag's blob names.
+//!
+//! Listing a tag is a broadcast to every container, which interactive tools
+//! can't afford on each keystroke. A `NameIndex` (from `Tag::name_index`) lists
+//! the tag once into a byte trie and keeps it current from the events of this
+//! process's writes; listings by prefix or glob are then answered locally. Other
+//! clients' writes reach it through their change logs when
+//! `NameIndexOptions::poll_remote` is set, and otherwise only with the next full
+//! listing, which happens once the last one is `max_staleness` old (or on
+//! `rebuild`).
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::{Client, Event, EventFilter, EventKind, Subscription, Tag};
+
+/// Settings for `Tag::name_index`.
+#[derive(Debug, Clone)]
+pub struct NameIndexOptions {
+    /// Age of the last full listing past which the next query relists the tag.
+    pub max_staleness: Duration,
+    /// Also apply other clients' changes, polling change logs every interval.
+    pub poll_remote: Option<Duration>,
+}
+
+impl Default for NameIndexOptions {
+    fn default() -> Self {
+        Self {
+            max_staleness: Duration::from_secs(30),
+            poll_remote: None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    children: BTreeMap<u8, usize>,
+    terminal: bool,
+}
+
+/// Byte trie of names. Removal only unmarks; a rebuild compacts.
+#[derive(Debug)]
+struct Trie {
+    nodes: Vec<Node>,
+    len: usize,
+}
+
+impl Default for Trie {
+    fn default() -> Self {
+        Self {
+            nodes: vec![Node::default()],
+            len: 0,
+        }
+    }
+}
+
+impl Trie {
+    fn insert(&mut self, name: &str) {
+        let mut at = 0;
+        for &b in name.as_bytes() {
+            at = match self.nodes[at].children.get(&b) {
+                Some(&next) => next,
+                None => {
+                    self.nodes.push(Node::default());
+                    let next = self.nodes.len() - 1;
+                    self.nodes[at].children.insert(b, next);
+                    next
+                }
+            };
+        }
+        if !std::mem::replace(&mut self.nodes[at].terminal, true) {
+            self.len += 1;
+        }
+    }
+
+    fn remove(&mut self, name: &str) {
+        if let Some(at) = self.find(name.as_bytes()) {
+            if std::mem::replace(&mut self.nodes[at].terminal, false) {
+                self.len -= 1;
+            }
+        }
+    }
+
+    fn find(&self, prefix: &[u8]) -> Option<usize> {
+        prefix
+            .iter()
+            .try_fold(0, |at, b| self.nodes[at].children.get(b).copied())
+    }
+
+    /// Names starting with `prefix`, sorted.
+    fn with_prefix(&self, prefix: &str) -> Vec<String> {
+        let mut out = Vec::new();
+        let Some(start) = self.find(prefix.as_bytes()) else {
+            return out;
+        };
+        let mut name = prefix.as_bytes().to_vec();
+        // Depth-first in byte order: (node, depth of name, next child byte).
+        let mut stack = vec![(start, name.len(), None::<u8>)];
+        while let Some((at, depth, after)) = stack.pop() {
+            name.truncate(depth);
+            if after.is_none() && self.nodes[at].terminal {
+                out.push(String::from_utf8_lossy(&name).into_owned());
+            }
+            let next = match after {
+                None => self.nodes[at].children.iter().next(),
+                Some(b) => self.nodes[at].children.range(b..).nth(1),
+            };
+            if let Some((&b, &child)) = next {
+                stack.push((at, depth, Some(b)));
+                name.push(b);
+                stack.push((child, depth + 1, None));
+            }
+        }
+        out
+    }
+}
+
+/// True if `name` matches glob `pattern`: `*` matches any run of characters,
+/// `?` any one, and `[...]` one from a set (`[a-z]`, `[!0-9]`).
+pub(crate) fn glob_match(pattern: &str, name: &str) -> bool {
+    let (p, n): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+    let (mut pi, mut ni) = (0, 0);
+    // Where to resume after the last `*`: (pattern index, name index).
+    let mut star = None;
+    while ni < n.len() {
+        match p.get(pi) {
+            Some('*') => {
+                star = Some((pi + 1, ni));
+                pi += 1;
+                continue;
+            }
+            Some('?') => {
+                pi += 1;
+                ni += 1;
+                continue;
+            }
+            Some('[') => {
+                if let Some((matched, len)) = class_match(&p[pi..], n[ni]) {
+                    if matched {
+                        pi += len;
+                        ni += 1;
+                        continue;
+                    }
+                } else if n[ni] == '[' {
+                    // An unclosed `[` is a literal.
+                    pi += 1;
+                    ni += 1;
+                    continue;
+                }
+            }
+            Some(&c) if c == n[ni] => {
+                pi += 1;
+                ni += 1;
+                continue;
+            }
+            _ => {}
+        }
+        match star {
+            Some((sp, sn)) => {
+                pi = sp;
+                ni = sn + 1;
+                star = Some((sp, sn + 1));
+            }
+            None => return false,
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+/// Match `c` against the class at the start of `p`; returns whether it matched
+/// and the class's length, or `None` if the class is unclosed.
+fn class_match(p: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negate = matches!(p.get(i), Some('!' | '^'));
+    if negate {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let lo = *p.get(i)?;
+        if lo == ']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+        if p.get(i + 1) == Some(&'-') && p.get(i + 2).is_some_and(|&hi| hi != ']') {
+            matched |= (lo..=p[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            matched |= lo == c;
+            i += 1;
+        }
+    }
+}
+
+struct State {
+    trie: Trie,
+    built: Instant,
+    /// Changes seen while a rebuild's listing is in flight, replayed onto it.
+    pending: Option<Vec<(bool, String)>>,
+}
+
+impl State {
+    fn apply(&mut self, event: &Event) {
+        let change = match event {
+            Event::BlobPut { blob, .. } => (true, blob.clone()),
+            Event::BlobDeleted { blob, .. } => (false, blob.clone()),
+            Event::TagDeleted { .. } => {
+                self.trie = Trie::default();
+                if let Some(pending) = self.pending.as_mut() {
+                    pending.clear();
+                }
+                return;
+            }
+            _ => return,
+        };
+        match &change {
+            (true, blob) => self.trie.insert(blob),
+            (false, blob) => self.trie.remove(blob),
+        }
+        if let Some(pending) = self.pending.as_mut() {
+            pending.push(change);
+        }
+    }
+}
+
+/// Local index of one tag's blob names, from `Tag::name_index`. Stops
+/// following changes when dropped.
+pub struct NameIndex {
+    tag: Tag,
+    max_staleness: Duration,
+    state: Arc<Mutex<State>>,
+    _subscriptions: Vec<Subscription>,
+}
+
+impl NameIndex {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Relist the tag if the last listing is older than `max_staleness`.
+    fn refresh(&self) {
+        if self.age() > self.max_staleness {
+            self.rebuild();
+        }
+    }
+
+    /// Blob names starting with `prefix`, sorted.
+    pub fn list_prefix(&self, prefix: &str) -> Vec<String> {
+        self.refresh();
+        self.state().trie.with_prefix(prefix)
+    }
+
+    /// Blob names matching glob `pattern` (`*`, `?` and `[...]`), sorted.
+    pub fn glob(&self, pattern: &str) -> Vec<String> {
+        self.refresh();
+        let literal: String = pattern
+            .chars()
+            .take_while(|c| !matches!(c, '*' | '?' | '['))
+            .collect();
+        let mut names = self.state().trie.with_prefix(&literal);
+        names.retain(|name| glob_match(pattern, name));
+        names
+    }
+
+    /// Number of names indexed.
+    pub fn len(&self) -> usize {
+        self.refresh();
+        self.state().trie.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Time since the tag was last listed in full.
+    pub fn age(&self) -> Duration {
+        self.state().built.elapsed()
+    }
+
+    /// Relist the tag now, replacing the index.
+    pub fn rebuild(&self) {
+        self.state().pending = Some(Vec::new());
+        let built = Instant::now();
+        let mut trie = Trie::default();
+        for name in self.tag.blob_names() {
+            trie.insert(&name);
+        }
+        let mut state = self.state();
+        for (put, name) in state.pending.take().unwrap_or_default() {
+            if put {
+                trie.insert(&name);
+            } else {
+                trie.remove(&name);
+            }
+        }
+        state.trie = trie;
+        state.built = built;
+    }
+}
+
+impl Tag {
+    /// List this tag into a local `NameIndex` kept current as `options`
+    /// describes (see `index`).
+    pub fn name_index(&self, options: NameIndexOptions) -> NameIndex {
+        let state = Arc::new(Mutex::new(State {
+            trie: Trie::default(),
+            built: Instant::now(),
+            pending: None,
+        }));
+        let id = self.get_tag_id();
+        let names = [
+            self.name().to_string(),
+            format!("#{}.{}", id.major, id.minor),
+        ];
+        let kinds = [
+            EventKind::BlobPut,
+            EventKind::BlobDeleted,
+            EventKind::TagDeleted,
+        ];
+        let follow = |filter: EventFilter| {
+            let (state, names) = (Arc::clone(&state), names.clone());
+            Client::subscribe_with(filter.kinds(&kinds), move |event| {
+                if names.iter().any(|n| n == event.tag()) {
+                    state.lock().unwrap_or_else(|e| e.into_inner()).apply(event);
+                }
+            })
+        };
+        // Local events may name the tag by ID, so they aren't filtered by name.
+        let mut subscriptions = vec![follow(EventFilter::new())];
+        if let Some(interval) = options.poll_remote {
+            subscriptions.push(follow(
+                EventFilter::new().tag(self.name()).poll_remote(interval),
+            ));
+        }
+        let index = NameIndex {
+            tag: Tag::from_id(id),
+            max_staleness: options.max_staleness,
+            state,
+            _subscriptions: subscriptions,
+        };
+        index.rebuild();
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trie_and_glob() {
+        let mut trie = Trie::default();
+        for name in ["run1/a", "run1/b", "run10/a", "run2/a", "run"] {
+            trie.insert(name);
+        }
+        trie.insert("run1/a");
+        trie.remove("run2/a");
+        trie.remove("absent");
+        assert_eq!(trie.len, 4);
+        assert_eq!(trie.with_prefix("run1"), ["run1/a", "run1/b", "run10/a"]);
+        assert_eq!(trie.with_prefix("run1/"), ["run1/a", "run1/b"]);
+        assert_eq!(trie.with_prefix("").len(), 4);
+        assert!(trie.with_prefix("run3").is_empty());
+
+        assert!(glob_match("run*/a", "run10/a"));
+        assert!(glob_match("run?/[ab]", "run1/b"));
+        assert!(!glob_match("run?/[!ab]", "run1/b"));
+        assert!(glob_match("ckpt-[0-9][0-9]", "ckpt-42"));
+        assert!(!glob_match("ckpt-[0-9]", "ckpt-x"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("a*b", "aXbY"));
+        assert!(glob_match("[", "["));
+    }
+}
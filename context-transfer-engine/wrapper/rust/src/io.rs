@@ -0,0 +1,667 @@
+//! Option-driven blob I/O: generation-checked put/get and stat.
+//!
+//! Every write made through the wrapper bumps the blob's generation, kept in its
+//! metadata sidecar. Callers can read it back from `put`/`stat_blob` and make a
+//! put or get conditional on it (`if_generation_match`), giving optimistic
+//! concurrency control without a separate lock service.
+//!
+//! Puts can also record a checksum of the blob, which gets and
+//! `Tag::verify_blob` check against the stored data (see `checksum`), and
+//! compress it on the way in (see `compress`).
+
+use std::borrow::Cow;
+use std::time::{Duration, SystemTime};
+
+use crate::accounting;
+use crate::adopt::FileRef;
+use crate::checksum::check_checksum;
+use crate::compress::Compressed;
+use crate::meta::{meta_lock, BlobMeta, META_PREFIX};
+use crate::{
+    events, ffi, ffi_guard, AccessKind, Capability, ChangeKind, Checksum, ChecksumAlgorithm,
+    Compression, CteError, Event, Spill, Tag,
+};
+use crate::{handshake, negcache, readcache, retry, summary, txn, writeback};
+
+/// Options for `Tag::put`.
+#[derive(Debug, Clone, Default)]
+pub struct PutOptions {
+    /// Byte offset within the blob to write at.
+    pub offset: u64,
+    /// Placement score; `None` defers to the registered `PlacementPolicy`.
+    pub score: Option<f32>,
+    /// Only write if the blob is currently at this generation. `Some(0)` means
+    /// "only if the blob doesn't exist yet".
+    pub if_generation_match: Option<u64>,
+    /// Record a checksum of the whole blob after this write. Writes without one
+    /// drop any previously recorded checksum.
+    pub checksum: Option<ChecksumAlgorithm>,
+    /// Read the blob back after writing and fail with `ChecksumMismatch` unless it
+    /// matches. Requires `checksum`.
+    pub verify: bool,
+    /// Compress the data before writing it. Compressed puts must be at offset 0
+    /// and replace the whole blob; the checksum, if any, covers the compressed
+    /// bytes.
+    pub compression: Compression,
+    /// Expire the blob this long after the write. `None` keeps any TTL the blob
+    /// already has (see `ttl`).
+    pub ttl: Option<Duration>,
+}
+
+/// Options for `Tag::get`.
+#[derive(Debug, Clone, Default)]
+pub struct GetOptions {
+    /// Byte offset within the blob to read from.
+    pub offset: u64,
+    /// Bytes to read; `None` reads to the end of the blob.
+    pub size: Option<u64>,
+    /// Fail unless the blob is at this generation for the whole read.
+    pub if_generation_match: Option<u64>,
+    /// Check the whole blob against its recorded checksum before returning data.
+    /// Blobs written without a checksum are returned unchecked.
+    pub verify_checksum: bool,
+}
+
+/// Size, score, generation, checksum and compression of a blob.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlobStat {
+    /// Size of the data as written, before compression.
+    pub size: u64,
+    /// Bytes the blob occupies in the runtime.
+    pub stored_size: u64,
+    pub score: f32,
+    pub generation: u64,
+    pub checksum: Option<Checksum>,
+    pub compression: Compression,
+    /// When the blob expires, if it has a TTL.
+    pub expires: Option<SystemTime>,
+    /// Where writes to the blob spilled to a lower tier, if they did.
+    pub spill: Option<Spill>,
+    /// The score the blob is pinned at, if it is (see `Tag::pin_blob`).
+    pub pinned: Option<f32>,
+    /// The file the blob's data is in, if it was adopted (see `adopt`).
+    pub file: Option<FileRef>,
+    /// When the blob was last written through the wrapper, if recorded.
+    pub written: Option<SystemTime>,
+}
+
+impl BlobStat {
+    fn new(stored_size: u64, score: f32, meta: BlobMeta) -> Self {
+        Self {
+            size: match &meta.reference {
+                Some(file) => file.size,
+                None => meta.compressed.map_or(stored_size, |c| c.raw_size),
+            },
+            stored_size,
+            score,
+            generation: meta.generation,
+            compression: meta.compressed.map_or(Compression::None, |c| c.codec),
+            expires: meta.expires_at(),
+            written: meta.written_at(),
+            spill: meta.spill,
+            pinned: meta.pin(),
+            checksum: meta.checksum,
+            file: meta.reference,
+        }
+    }
+}
+
+impl Tag {
+    /// Write `data` into `name` according to `options`, returning the blob's new
+    /// generation.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(tag = %self.name, blob = name, size = data.len(), offset = options.offset),
+            ret,
+            err
+        )
+    )]
+    pub fn put(&self, name: &str, data: &[u8], options: &PutOptions) -> Result<u64, CteError> {
+        writeback::flush_blob(self, name);
+        if options.if_generation_match.is_some() {
+            // See `retry`: a timed-out attempt may have met the precondition.
+            return self.put_once(name, data, options);
+        }
+        retry::retrying(|| self.put_once(name, data, options))
+    }
+
+    fn put_once(&self, name: &str, data: &[u8], options: &PutOptions) -> Result<u64, CteError> {
+        if options.verify && options.checksum.is_none() {
+            return Err(CteError::InvalidArgument(
+                "PutOptions::verify requires a checksum algorithm".into(),
+            ));
+        }
+        if options.compression != Compression::None && options.offset != 0 {
+            return Err(CteError::InvalidArgument(
+                "compressed puts must write the whole blob at offset 0".into(),
+            ));
+        }
+        let _guard = meta_lock();
+        let mut meta = self.load_meta(name)?.unwrap_or_default();
+        if let Some(expected) = options.if_generation_match {
+            // An expired blob that hasn't been collected yet counts as absent.
+            let actual = if meta.is_expired() {
+                0
+            } else {
+                meta.generation
+            };
+            check_generation(name, expected, actual)?;
+            if expected == 0 && !meta.is_expired() && self.get_blob_size(name) > 0 {
+                // Written below the wrapper: no generation, but it exists.
+                return Err(CteError::GenerationMismatch {
+                    blob: name.to_string(),
+                    expected,
+                    actual,
+                });
+            }
+        }
+        if options.offset != 0 && (meta.is_transformed() || self.is_encrypted()?) {
+            return Err(CteError::InvalidArgument(format!(
+                "cannot write into compressed, encrypted or adopted blob '{}' at an offset",
+                name
+            )));
+        }
+        meta.summary = match options.offset {
+            0 => summary::summarize(name, data)
+                .filter(|_| self.get_blob_size(name) <= data.len() as u64),
+            _ => None,
+        };
+        let mut payload = Cow::Borrowed(data);
+        meta.compressed = None;
+        meta.reference = None;
+        if options.compression != Compression::None {
+            let compressed = options.compression.compress(data)?;
+            meta.compressed = Some(Compressed {
+                codec: options.compression,
+                raw_size: data.len() as u64,
+                stored_size: compressed.len() as u64,
+            });
+            payload = Cow::Owned(compressed);
+        }
+        meta.encrypted = None;
+        if let Some((sealed, info)) = self.seal(&payload)? {
+            meta.encrypted = Some(info);
+            payload = Cow::Owned(sealed);
+        }
+        if let Some(ttl) = options.ttl {
+            meta.expires_ms = crate::ttl::expiry_after(ttl);
+        }
+        let generation = self.write_blob_locked(
+            name,
+            &payload,
+            options.offset,
+            options.score,
+            options.checksum,
+            &mut meta,
+        )?;
+        if options.verify {
+            if let Some(expected) = &meta.checksum {
+                let actual =
+                    self.compute_checksum(name, expected.algorithm, self.get_blob_size(name))?;
+                check_checksum(&self.name, name, expected, &actual)?;
+            }
+        }
+        Ok(generation)
+    }
+
+    /// Create `name` with `data` unless it already exists. Returns whether it was
+    /// written; of several producers racing to create the same blob in one
+    /// process, exactly one gets `true`.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(tag = %self.name, blob = name, size = data.len()),
+            ret,
+            err
+        )
+    )]
+    pub fn put_blob_if_absent(&self, name: &str, data: &[u8]) -> Result<bool, CteError> {
+        self.put_blob_if_match(name, data, 0).map(|g| g.is_some())
+    }
+
+    /// Compare-and-swap: replace `name` with `data` only if it is still at
+    /// generation `version` (as returned by `put` or `stat_blob`; 0 for a blob
+    /// that doesn't exist). Returns the new generation, or `None` if the blob
+    /// had moved on and nothing was written.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(tag = %self.name, blob = name, size = data.len(), version = version),
+            ret,
+            err
+        )
+    )]
+    pub fn put_blob_if_match(
+        &self,
+        name: &str,
+        data: &[u8],
+        version: u64,
+    ) -> Result<Option<u64>, CteError> {
+        let options = PutOptions {
+            if_generation_match: Some(version),
+            ..Default::default()
+        };
+        match self.put(name, data, &options) {
+            Ok(generation) => Ok(Some(generation)),
+            Err(CteError::GenerationMismatch { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Read from `name` according to `options`.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(tag = %self.name, blob = name, offset = options.offset, size = ?options.size),
+            err
+        )
+    )]
+    pub fn get(&self, name: &str, options: &GetOptions) -> Result<Vec<u8>, CteError> {
+        writeback::flush_blob(self, name);
+        if options.if_generation_match.is_some() || options.verify_checksum {
+            return retry::retrying(|| self.get_once(name, options));
+        }
+        let range = (options.offset, options.size, false);
+        if let Some(data) = self.cached_read(name, range) {
+            return Ok(data);
+        }
+        let epoch = readcache::epoch();
+        let data = retry::retrying(|| self.get_once(name, options))?;
+        self.cache_read(name, range, &data, epoch);
+        Ok(data)
+    }
+
+    fn get_once(&self, name: &str, options: &GetOptions) -> Result<Vec<u8>, CteError> {
+        let _txn = txn::read_guard();
+        if self.known_missing(name) {
+            return Err(CteError::NotFound {
+                blob: name.to_string(),
+            });
+        }
+        let epoch = negcache::epoch();
+        let meta = self.load_meta(name)?;
+        if let Some(id) = meta.as_ref().and_then(|m| m.pending_txn.as_deref()) {
+            drop(_txn);
+            self.roll_forward(id, name)?;
+            return self.get_once(name, options);
+        }
+        if self.expire_if_due(name, meta.as_ref()) {
+            return Err(CteError::NotFound {
+                blob: name.to_string(),
+            });
+        }
+        let generation = match options.if_generation_match {
+            Some(expected) => {
+                let actual = meta.as_ref().map_or(0, |m| m.generation);
+                check_generation(name, expected, actual)?;
+                Some(expected)
+            }
+            None => None,
+        };
+        if let Some(file) = meta.as_ref().and_then(|m| m.reference.as_ref()) {
+            let data = self.read_adopted(name, file, options)?;
+            if let Some(expected) = generation {
+                let actual = self.load_meta(name)?.unwrap_or_default().generation;
+                check_generation(name, expected, actual)?;
+            }
+            return Ok(data);
+        }
+        let blob_size = self.get_blob_size(name);
+        if blob_size == 0 {
+            if meta.is_none() {
+                self.note_missing(name, epoch);
+            }
+            return Err(CteError::NotFound {
+                blob: name.to_string(),
+            });
+        }
+        let (checksum, compressed, encrypted) = match meta {
+            Some(m) => (
+                m.checksum.filter(|_| options.verify_checksum),
+                m.compressed,
+                m.encrypted,
+            ),
+            None => (None, None, None),
+        };
+        let data = if checksum.is_some() || compressed.is_some() || encrypted.is_some() {
+            // Checksums, compression and encryption cover the whole blob, so read
+            // all of it and slice.
+            let whole = self.read_blob(name, blob_size, 0)?;
+            if let Some(expected) = &checksum {
+                let actual = Checksum::compute(expected.algorithm, &whole);
+                check_checksum(&self.name, name, expected, &actual)?;
+            }
+            let whole = match encrypted {
+                Some(e) => {
+                    let stored = &whole[..(e.stored_size.min(blob_size)) as usize];
+                    self.open(name, stored, &e)?
+                }
+                None => whole,
+            };
+            let whole = match compressed {
+                Some(c) => {
+                    let stored = &whole[..(c.stored_size as usize).min(whole.len())];
+                    c.codec.decompress(name, stored, c.raw_size)?
+                }
+                None => whole,
+            };
+            let len = whole.len() as u64;
+            let start = options.offset.min(len);
+            let end = match options.size {
+                Some(size) => start.saturating_add(size).min(len),
+                None => len,
+            };
+            whole[start as usize..end as usize].to_vec()
+        } else {
+            let size = options
+                .size
+                .unwrap_or_else(|| blob_size.saturating_sub(options.offset));
+            if size == 0 {
+                Vec::new()
+            } else if generation.is_some() {
+                self.read_blob(name, size, options.offset)?
+            } else {
+                self.read_blob_ahead(name, size, options.offset)?
+            }
+        };
+        if let Some(expected) = generation {
+            // A write that landed during the read makes the data a mix of versions.
+            let actual = self.load_meta(name)?.unwrap_or_default().generation;
+            check_generation(name, expected, actual)?;
+        }
+        Ok(data)
+    }
+
+    /// Size, score, generation and checksum of `name`, or `None` if it doesn't
+    /// exist.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(tag = %self.name, blob = name), err)
+    )]
+    pub fn stat_blob(&self, name: &str) -> Result<Option<BlobStat>, CteError> {
+        writeback::flush_blob(self, name);
+        retry::retrying(|| self.stat_blob_once(name))
+    }
+
+    fn stat_blob_once(&self, name: &str) -> Result<Option<BlobStat>, CteError> {
+        let _txn = txn::read_guard();
+        if self.known_missing(name) {
+            return Ok(None);
+        }
+        let epoch = negcache::epoch();
+        let stored_size = self.get_blob_size(name);
+        let Some(meta) = self
+            .load_meta(name)?
+            .or_else(|| (stored_size > 0).then(BlobMeta::default))
+        else {
+            self.note_missing(name, epoch);
+            return Ok(None);
+        };
+        if let Some(id) = &meta.pending_txn {
+            drop(_txn);
+            self.roll_forward(id, name)?;
+            return self.stat_blob_once(name);
+        }
+        if self.expire_if_due(name, Some(&meta)) {
+            return Ok(None);
+        }
+        Ok(Some(BlobStat::new(
+            stored_size,
+            self.get_blob_score(name),
+            meta,
+        )))
+    }
+
+    /// `stat_blob` of each of `names`, in order, fetched in a few batched round
+    /// trips rather than several per blob.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(tag = %self.name, count = names.len()),
+            err
+        )
+    )]
+    pub fn stat_blobs(&self, names: &[&str]) -> Result<Vec<Option<BlobStat>>, CteError> {
+        retry::retrying(|| self.stat_blobs_once(names))
+    }
+
+    fn stat_blobs_once(&self, requested: &[&str]) -> Result<Vec<Option<BlobStat>>, CteError> {
+        handshake::require(Capability::StatBlobs)?;
+        let _txn = txn::read_guard();
+        let mut out = vec![None; requested.len()];
+        // Only look up the names not already known to be missing.
+        let (slots, names): (Vec<usize>, Vec<String>) = requested
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| !self.known_missing(n))
+            .map(|(i, n)| (i, n.to_string()))
+            .unzip();
+        let meta_names: Vec<String> = names
+            .iter()
+            .map(|n| format!("{}{}", META_PREFIX, n))
+            .collect();
+        let epoch = negcache::epoch();
+        let rows = ffi_guard::tag_stat_blobs(&self.inner, &names, &meta_names)?;
+        for ((slot, name), row) in slots.into_iter().zip(&names).zip(rows.iter()) {
+            let meta = if row.meta.is_empty() {
+                if row.size == 0 {
+                    self.note_missing(name, epoch);
+                }
+                (row.size > 0).then(BlobMeta::default)
+            } else {
+                let meta = BlobMeta::decode(row.meta.as_slice()).map_err(|reason| {
+                    CteError::CorruptMetadata {
+                        blob: name.clone(),
+                        reason,
+                    }
+                })?;
+                if let Some(id) = &meta.pending_txn {
+                    drop(_txn);
+                    self.roll_forward(id, name)?;
+                    return self.stat_blobs_once(requested);
+                }
+                Some(meta)
+            };
+            out[slot] = match meta {
+                Some(meta) if !self.expire_if_due(name, Some(&meta)) => {
+                    Some(BlobStat::new(row.size, row.score, meta))
+                }
+                _ => None,
+            };
+        }
+        Ok(out)
+    }
+
+    /// Write path shared by all public puts: write, bump the generation, log.
+    ///
+    /// Panics if the write fails, since the legacy put API has no way to report
+    /// it; in particular an encrypted tag's put that can't be sealed must not
+    /// store plaintext instead.
+    pub(crate) fn write_blob(&self, name: &str, data: &[u8], offset: u64, score: Option<f32>) {
+        if writeback::buffer(self, name, data, offset, score) {
+            return;
+        }
+        if let Err(e) = self.try_write_blob(name, data, offset, score) {
+            panic!("put of '{}' to tag '{}' failed: {}", name, self.name(), e);
+        }
+    }
+
+    /// `write_blob`, returning the error instead. Returns the new generation.
+    pub(crate) fn try_write_blob(
+        &self,
+        name: &str,
+        data: &[u8],
+        offset: u64,
+        score: Option<f32>,
+    ) -> Result<u64, CteError> {
+        if !matches!(self.is_encrypted(), Ok(false)) {
+            let options = PutOptions {
+                offset,
+                score,
+                ..Default::default()
+            };
+            // Not `put`, which would flush write-back buffers this may be sending.
+            return retry::retrying(|| self.put_once(name, data, &options));
+        }
+        let _guard = meta_lock();
+        // A corrupt sidecar shouldn't make unconditional writes fail; start over.
+        let mut meta = self.load_meta(name).ok().flatten().unwrap_or_default();
+        // Raw bytes over a compressed blob replace (or corrupt) the compressed form.
+        meta.compressed = None;
+        meta.encrypted = None;
+        meta.summary = None;
+        meta.reference = None;
+        self.write_blob_locked(name, data, offset, score, None, &mut meta)
+    }
+
+    /// `write_blob` for callers already holding the meta lock, with `meta` loaded.
+    /// Records a checksum of the resulting blob if `checksum` is set and clears
+    /// it otherwise. Returns the new generation.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            level = "trace",
+            skip_all,
+            fields(tag = %self.name, blob = name, size = data.len(), offset = offset)
+        )
+    )]
+    pub(crate) fn write_blob_locked(
+        &self,
+        name: &str,
+        data: &[u8],
+        offset: u64,
+        score: Option<f32>,
+        checksum: Option<ChecksumAlgorithm>,
+        meta: &mut BlobMeta,
+    ) -> Result<u64, CteError> {
+        self.archive_version(name, meta.generation);
+        if meta.generation == 0 {
+            meta.generation = self.generation_floor(name);
+        }
+        // A pinned blob is written where it is pinned.
+        let score = meta.pin().or(score);
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
+        self.put_spilling(name, data, offset, score, meta)?;
+        #[cfg(feature = "metrics")]
+        let latency = start.elapsed();
+        meta.generation += 1;
+        meta.written_ms = crate::ttl::now_ms();
+        if meta.is_expired() {
+            // Rewriting an expired (not yet collected) blob starts it afresh.
+            meta.expires_ms = 0;
+        }
+        meta.checksum = match checksum {
+            Some(algorithm) => {
+                let size = self.get_blob_size(name);
+                Some(if offset == 0 && size == data.len() as u64 {
+                    Checksum::compute(algorithm, data)
+                } else {
+                    // Partial write: the digest has to cover what's stored around it.
+                    self.compute_checksum(name, algorithm, size)?
+                })
+            }
+            None => None,
+        };
+        self.store_meta(name, meta)?;
+        let size = data.len() as u64;
+        accounting::record(accounting::Op::Put, size);
+        #[cfg(feature = "metrics")]
+        crate::metrics::record(self.name(), accounting::Op::Put, size, Some(latency));
+        self.record_access(name, AccessKind::Write, offset, size);
+        self.record_change(name, ChangeKind::Put { offset, size });
+        events::emit(Event::BlobPut {
+            tag: self.name().to_string(),
+            blob: name.to_string(),
+            offset,
+            size,
+        });
+        Ok(meta.generation)
+    }
+}
+
+/// Bytes moved per call when the wrapper copies blobs internally.
+pub(crate) const COPY_CHUNK: u64 = 4 * 1024 * 1024;
+
+impl Tag {
+    /// Copy a blob's data, score and metadata to `dst_name` in `dst`, below the
+    /// public API: no generation bump, change record or event. Returns the bytes
+    /// copied.
+    pub(crate) fn copy_blob_raw(
+        &self,
+        name: &str,
+        dst: &Tag,
+        dst_name: &str,
+    ) -> Result<u64, CteError> {
+        let size = self.get_blob_size(name);
+        let score = self.get_blob_score(name);
+        let mut offset = 0;
+        while offset < size {
+            let len = COPY_CHUNK.min(size - offset);
+            let v = ffi_guard::tag_get_blob(&self.inner, name, len, offset)?;
+            ffi_guard::tag_put_blob(&dst.inner, dst_name, v.as_slice(), offset, score)?;
+            offset += len;
+        }
+        if let Some(meta) = self.load_meta(name)? {
+            dst.store_meta(dst_name, &meta)?;
+        }
+        Ok(size)
+    }
+}
+
+impl Tag {
+    /// Replace `name` with a raw copy of `src` (data, score and metadata), as a new
+    /// write of `name`: its generation carries on from the old one, the replaced
+    /// version is kept if the tag is versioned, and the change is recorded and
+    /// published. Returns the new generation.
+    pub(crate) fn install_copy(&self, src: &str, name: &str) -> Result<u64, CteError> {
+        let (generation, size) = {
+            let _guard = meta_lock();
+            let old = self.load_meta(name).ok().flatten().unwrap_or_default();
+            self.archive_version(name, old.generation);
+            let base = match old.generation {
+                0 => self.generation_floor(name),
+                g => g,
+            };
+            // Drop the old data first; a shorter copy wouldn't truncate it.
+            ffi::tag_del_blob(&self.inner, name);
+            ffi::tag_del_blob(&self.inner, &format!("{}{}", META_PREFIX, name));
+            let size = self.copy_blob_raw(src, self, name)?;
+            let mut meta = self.load_meta(name)?.unwrap_or_default();
+            meta.generation = base + 1;
+            self.store_meta(name, &meta)?;
+            (meta.generation, size)
+        };
+        self.record_change(name, ChangeKind::Put { offset: 0, size });
+        events::emit(Event::BlobPut {
+            tag: self.name().to_string(),
+            blob: name.to_string(),
+            offset: 0,
+            size,
+        });
+        Ok(generation)
+    }
+}
+
+fn check_generation(blob: &str, expected: u64, actual: u64) -> Result<(), CteError> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(CteError::GenerationMismatch {
+            blob: blob.to_string(),
+            expected,
+            actual,
+        })
+    }
+}
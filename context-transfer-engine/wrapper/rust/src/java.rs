@@ -0,0 +1,328 @@
+//! JNI exports for the Java bindings in `java/` (feature `jni`).
+//!
+//! The crate's cdylib, loaded with `System.loadLibrary("wrp_cte_rs")`,
+//! implements the native methods of `org.iowarp.cte.Native`, which the public
+//! `Cte` and `Tag` classes wrap. A `Tag` is a `Box<Tag>` handle held as a
+//! `long` until `Tag.close`. Data goes in and out as `byte[]`, or as
+//! `ByteBuffer`s: a direct buffer is read from or filled in place, which is
+//! how `Tag.getDirect` reads a blob without copying it through the Java heap.
+//!
+//! Every export runs under `ffi_guard::catch`, so no panic unwinds into the
+//! JVM; a failure throws `org.iowarp.cte.CteException` with the `CteError`
+//! as its message, and the return value is then ignored.
+//!
+//! The exports need a JVM to call them, so they are tested from Java:
+//! `java/test/org/iowarp/cte/SmokeTest.java` runs them against a live runtime.
+
+use std::ffi::c_void;
+use std::ptr;
+
+use jni::objects::{JByteArray, JByteBuffer, JClass, JObject, JString, ReleaseMode};
+use jni::sys::{jboolean, jbyteArray, jint, jlong, jobjectArray, jstring, JNI_FALSE};
+use jni::JNIEnv;
+
+use crate::{ffi_guard, Client, CteError, GetOptions, PutOptions, RuntimeState, Tag};
+
+const EXCEPTION: &str = "org/iowarp/cte/CteException";
+
+fn jni_error(e: jni::errors::Error) -> CteError {
+    CteError::InvalidArgument(format!("JNI: {}", e))
+}
+
+/// Run an export: `f`'s value, or `default` after throwing its error (unless
+/// a Java exception is already pending, as when a JNI call in `f` failed).
+fn guarded<'local, T>(
+    env: &mut JNIEnv<'local>,
+    op: &'static str,
+    default: T,
+    f: impl FnOnce(&mut JNIEnv<'local>) -> Result<T, CteError>,
+) -> T {
+    match ffi_guard::catch(op, || f(env)) {
+        Ok(value) => value,
+        Err(e) => {
+            // Runtime errors were recorded where they were raised.
+            if !matches!(e, CteError::Runtime { .. }) {
+                ffi_guard::record(&e);
+            }
+            if !env.exception_check().unwrap_or(true) {
+                let _ = env.throw_new(EXCEPTION, e.to_string());
+            }
+            default
+        }
+    }
+}
+
+fn string(env: &mut JNIEnv, s: &JString) -> Result<String, CteError> {
+    if s.is_null() {
+        return Err(CteError::InvalidArgument("null string".into()));
+    }
+    Ok(env.get_string(s).map_err(jni_error)?.into())
+}
+
+/// The `Tag` behind a handle, failing on 0.
+fn tag_ref<'a>(handle: jlong) -> Result<&'a Tag, CteError> {
+    if handle == 0 {
+        return Err(CteError::InvalidArgument("closed tag".into()));
+    }
+    Ok(unsafe { &*(handle as *const Tag) })
+}
+
+fn unsigned(what: &str, n: jlong) -> Result<u64, CteError> {
+    u64::try_from(n).map_err(|_| CteError::InvalidArgument(format!("negative {}", what)))
+}
+
+/// `len` bytes from `position` of a direct buffer.
+fn direct<'a>(
+    env: &mut JNIEnv,
+    buffer: &JByteBuffer,
+    position: jint,
+    len: jint,
+) -> Result<&'a mut [u8], CteError> {
+    if buffer.is_null() {
+        return Err(CteError::InvalidArgument("null buffer".into()));
+    }
+    let address = env.get_direct_buffer_address(buffer).map_err(jni_error)?;
+    let capacity = env.get_direct_buffer_capacity(buffer).map_err(jni_error)?;
+    let (Ok(position), Ok(len)) = (usize::try_from(position), usize::try_from(len)) else {
+        return Err(CteError::InvalidArgument("negative buffer range".into()));
+    };
+    if position.checked_add(len).is_none_or(|end| end > capacity) {
+        return Err(CteError::InvalidArgument("range outside the buffer".into()));
+    }
+    Ok(unsafe { std::slice::from_raw_parts_mut(address.add(position), len) })
+}
+
+/// Initialize the client as `init` does; returns the runtime state's name.
+#[no_mangle]
+pub extern "system" fn Java_org_iowarp_cte_Native_init<'local>(
+    mut env: JNIEnv<'local>,
+    _: JClass<'local>,
+    config: JString<'local>,
+) -> jstring {
+    guarded(&mut env, "init", ptr::null_mut(), |env| {
+        let config = if config.is_null() {
+            String::new()
+        } else {
+            string(env, &config)?
+        };
+        let state = match crate::try_init(&config)? {
+            RuntimeState::Uninitialized => "uninitialized",
+            RuntimeState::ClientAttached => "client_attached",
+            RuntimeState::EmbeddedRuntime => "embedded_runtime",
+        };
+        Ok(env.new_string(state).map_err(jni_error)?.into_raw())
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_org_iowarp_cte_Native_registerTarget<'local>(
+    mut env: JNIEnv<'local>,
+    _: JClass<'local>,
+    path: JString<'local>,
+    size: jlong,
+) -> jboolean {
+    guarded(&mut env, "register_target", JNI_FALSE, |env| {
+        let path = string(env, &path)?;
+        let size = unsigned("size", size)?;
+        Ok(Client::register_target(&path, size).into())
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_org_iowarp_cte_Native_tagExists<'local>(
+    mut env: JNIEnv<'local>,
+    _: JClass<'local>,
+    name: JString<'local>,
+) -> jboolean {
+    guarded(&mut env, "tag_exists", JNI_FALSE, |env| {
+        Ok(Client::tag_exists(&string(env, &name)?).into())
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_org_iowarp_cte_Native_delTag<'local>(
+    mut env: JNIEnv<'local>,
+    _: JClass<'local>,
+    name: JString<'local>,
+) -> jboolean {
+    guarded(&mut env, "del_tag", JNI_FALSE, |env| {
+        Ok(Client::del_tag(&string(env, &name)?).into())
+    })
+}
+
+/// Open (or create) a tag; the handle is freed by `tagFree`.
+#[no_mangle]
+pub extern "system" fn Java_org_iowarp_cte_Native_tagOpen<'local>(
+    mut env: JNIEnv<'local>,
+    _: JClass<'local>,
+    name: JString<'local>,
+) -> jlong {
+    guarded(&mut env, "tag_new", 0, |env| {
+        let tag = Tag::try_new(&string(env, &name)?)?;
+        Ok(Box::into_raw(Box::new(tag)) as *mut c_void as jlong)
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_org_iowarp_cte_Native_tagFree<'local>(
+    mut env: JNIEnv<'local>,
+    _: JClass<'local>,
+    handle: jlong,
+) {
+    guarded(&mut env, "tag_free", (), |_| {
+        if handle != 0 {
+            drop(unsafe { Box::from_raw(handle as *mut Tag) });
+        }
+        Ok(())
+    })
+}
+
+fn put(tag: &Tag, name: &str, data: &[u8], offset: jlong) -> Result<(), CteError> {
+    let options = PutOptions {
+        offset: unsigned("offset", offset)?,
+        ..Default::default()
+    };
+    tag.put(name, data, &options).map(drop)
+}
+
+/// Write all of `data` into blob `name` at `offset`.
+#[no_mangle]
+pub extern "system" fn Java_org_iowarp_cte_Native_putBlob<'local>(
+    mut env: JNIEnv<'local>,
+    _: JClass<'local>,
+    handle: jlong,
+    name: JString<'local>,
+    data: JByteArray<'local>,
+    offset: jlong,
+) {
+    guarded(&mut env, "put_blob", (), |env| {
+        let tag = tag_ref(handle)?;
+        let name = string(env, &name)?;
+        if data.is_null() {
+            return Err(CteError::InvalidArgument("null data".into()));
+        }
+        let elements =
+            unsafe { env.get_array_elements(&data, ReleaseMode::NoCopyBack) }.map_err(jni_error)?;
+        let data =
+            unsafe { std::slice::from_raw_parts(elements.as_ptr() as *const u8, elements.len()) };
+        put(tag, &name, data, offset)
+    })
+}
+
+/// Write `len` bytes of a direct buffer from `position` into blob `name` at
+/// `offset`, reading them in place.
+#[no_mangle]
+pub extern "system" fn Java_org_iowarp_cte_Native_putBuffer<'local>(
+    mut env: JNIEnv<'local>,
+    _: JClass<'local>,
+    handle: jlong,
+    name: JString<'local>,
+    buffer: JByteBuffer<'local>,
+    position: jint,
+    len: jint,
+    offset: jlong,
+) {
+    guarded(&mut env, "put_blob", (), |env| {
+        let tag = tag_ref(handle)?;
+        let name = string(env, &name)?;
+        let data = direct(env, &buffer, position, len)?;
+        put(tag, &name, data, offset)
+    })
+}
+
+/// Read `size` bytes of blob `name` from `offset` (to the end of the blob
+/// for a negative `size`) into a new array.
+#[no_mangle]
+pub extern "system" fn Java_org_iowarp_cte_Native_getBlob<'local>(
+    mut env: JNIEnv<'local>,
+    _: JClass<'local>,
+    handle: jlong,
+    name: JString<'local>,
+    offset: jlong,
+    size: jlong,
+) -> jbyteArray {
+    guarded(&mut env, "get_blob", ptr::null_mut(), |env| {
+        let tag = tag_ref(handle)?;
+        let name = string(env, &name)?;
+        let options = GetOptions {
+            offset: unsigned("offset", offset)?,
+            size: u64::try_from(size).ok(),
+            ..Default::default()
+        };
+        let data = tag.get(&name, &options)?;
+        Ok(env
+            .byte_array_from_slice(&data)
+            .map_err(jni_error)?
+            .into_raw())
+    })
+}
+
+/// Fill `len` bytes of a direct buffer from `position` with blob `name`'s
+/// data from `offset`, without an intermediate copy of the blob.
+#[no_mangle]
+pub extern "system" fn Java_org_iowarp_cte_Native_getInto<'local>(
+    mut env: JNIEnv<'local>,
+    _: JClass<'local>,
+    handle: jlong,
+    name: JString<'local>,
+    buffer: JByteBuffer<'local>,
+    position: jint,
+    len: jint,
+    offset: jlong,
+) {
+    guarded(&mut env, "get_blob", (), |env| {
+        let tag = tag_ref(handle)?;
+        let name = string(env, &name)?;
+        let buf = direct(env, &buffer, position, len)?;
+        tag.read_blob_into(&name, buf, unsigned("offset", offset)?)
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_org_iowarp_cte_Native_getBlobSize<'local>(
+    mut env: JNIEnv<'local>,
+    _: JClass<'local>,
+    handle: jlong,
+    name: JString<'local>,
+) -> jlong {
+    guarded(&mut env, "get_blob_size", 0, |env| {
+        let tag = tag_ref(handle)?;
+        let size = tag.get_blob_size(&string(env, &name)?);
+        jlong::try_from(size)
+            .map_err(|_| CteError::InvalidArgument(format!("{} is too large", size)))
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_org_iowarp_cte_Native_listBlobs<'local>(
+    mut env: JNIEnv<'local>,
+    _: JClass<'local>,
+    handle: jlong,
+) -> jobjectArray {
+    guarded(&mut env, "get_contained_blobs", ptr::null_mut(), |env| {
+        let names = tag_ref(handle)?.try_get_contained_blobs()?;
+        let array = env
+            .new_object_array(names.len() as jint, "java/lang/String", JObject::null())
+            .map_err(jni_error)?;
+        for (i, name) in names.iter().enumerate() {
+            let name = env.new_string(name).map_err(jni_error)?;
+            env.set_object_array_element(&array, i as jint, &name)
+                .map_err(jni_error)?;
+            env.delete_local_ref(name).map_err(jni_error)?;
+        }
+        Ok(array.into_raw())
+    })
+}
+
+#[no_mangle]
+pub extern "system" fn Java_org_iowarp_cte_Native_delBlob<'local>(
+    mut env: JNIEnv<'local>,
+    _: JClass<'local>,
+    handle: jlong,
+    name: JString<'local>,
+) -> jboolean {
+    guarded(&mut env, "del_blob", JNI_FALSE, |env| {
+        let tag = tag_ref(handle)?;
+        Ok(tag.del_blob(&string(env, &name)?).into())
+    })
+}
@@ -1,118 +1,876 @@
+// Lets `#[derive(CteBlob)]`'s `::wrp_cte_rs` paths resolve in this crate's tests.
+#[cfg(all(test, feature = "derive"))]
+extern crate self as wrp_cte_rs;
+
+mod accounting;
+mod adopt;
+mod alert;
+mod append;
+mod archive;
+mod attrs;
+mod audit;
+mod autotier;
+#[cfg(feature = "arrow")]
+mod batches;
+#[cfg(feature = "mmap")]
+mod blobmap;
+mod bulk;
+mod cancel;
+mod channel;
+mod checksum;
+#[cfg(feature = "parquet")]
+mod columnar;
+mod compress;
+mod connector;
+#[cfg(feature = "cuda")]
+mod cuda;
+mod delete;
+mod diag;
+mod encrypt;
+mod environment;
+mod error;
+mod events;
+#[cfg(not(feature = "capi"))]
 mod ffi_c;
+/// The C ABI, unexported, for the `clio-cte` library of `capi/` to export
+/// (feature `capi`).
+#[cfg(feature = "capi")]
+#[doc(hidden)]
+pub mod ffi_c;
+mod ffi_guard;
+#[cfg(feature = "flight")]
+mod flight;
+#[cfg(feature = "gateway")]
+mod gateway;
+mod group;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod handoff;
+mod handshake;
+#[cfg(feature = "hdf5-vfd")]
+mod hdf5;
+#[cfg(any(
+    feature = "metrics",
+    feature = "gateway",
+    feature = "notebook",
+    feature = "http"
+))]
+mod http;
+mod index;
+mod io;
+#[cfg(feature = "jni")]
+mod java;
+mod limits;
+mod listing;
+mod meta;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod model;
+mod names;
+mod negcache;
+#[cfg(feature = "node")]
+mod node;
+#[cfg(feature = "notebook")]
+mod notebook;
+#[cfg(feature = "objects")]
+mod object;
+mod oplog;
+mod partition;
+mod pin;
+mod placement;
+mod preflight;
+mod pressure;
+mod propagate;
+mod query;
+mod rawname;
+mod readahead;
+mod readcache;
+mod remote;
+#[cfg(feature = "http")]
+mod rest;
+mod retry;
+#[cfg(any(feature = "grpc", feature = "flight"))]
+mod rpc;
+mod s3;
+mod session;
+mod settle;
+mod shard;
+#[cfg(feature = "shm")]
+mod shm;
+mod simulate;
+mod snapshot;
+mod spill;
+mod stage;
+mod state;
+mod stream;
+mod summary;
+#[cfg(feature = "datafusion")]
+mod table;
+mod tagcache;
+mod timeout;
+mod trash;
+mod ttl;
+mod txn;
+mod vectored;
+mod versions;
+mod warmup;
+mod webhook;
+mod writeback;
+#[cfg(feature = "zarr")]
+mod zarr;
 
 #[cxx::bridge(namespace = "cte_ffi")]
 mod ffi {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
     struct CteTagId {
         major: u32,
         minor: u32,
     }
 
+    /// What a placement policy knows about a blob being written.
+    struct BlobDescriptor {
+        tag_id: CteTagId,
+        name: String,
+        size: u64,
+        offset: u64,
+    }
+
+    /// A blob matched by `client_blob_query_stat`, with its size and score.
+    struct BlobQueryRow {
+        tag: Vec<u8>,
+        blob: Vec<u8>,
+        size: u64,
+        score: f32,
+    }
+
+    /// Size, score and raw metadata sidecar of a blob, from `tag_stat_blobs`.
+    struct BlobInfoRow {
+        size: u64,
+        score: f32,
+        /// Encoded `BlobMeta`; empty if the blob has no sidecar.
+        meta: Vec<u8>,
+    }
+
+    /// A block of a blob, from `tag_blob_blocks`.
+    struct BlobBlockRow {
+        /// Path of the file target holding the block, if this process
+        /// registered it; empty otherwise.
+        target: String,
+        /// Offset of the block within the target.
+        offset: u64,
+        size: u64,
+    }
+
+    /// A blob name or common prefix listed by `tag_list_blobs`.
+    struct ListEntry {
+        name: Vec<u8>,
+        /// A common prefix rather than a blob.
+        prefix: bool,
+    }
+
+    /// A storage target, as listed by `client_list_targets`.
+    struct TargetInfo {
+        name: String,
+        /// Normalized bandwidth score, 0 to 1.
+        score: f32,
+        remaining_space: u64,
+        bytes_read: u64,
+        bytes_written: u64,
+    }
+
+    /// Queue state of one runtime worker, from `client_worker_stats`.
+    struct WorkerStats {
+        worker_id: u32,
+        /// Tasks waiting to run.
+        queued: u32,
+        /// Tasks blocked on others.
+        blocked: u32,
+        /// Tasks waiting to be retried.
+        retry: u32,
+        /// Tasks run since the worker started.
+        processed: u64,
+        /// Whether the worker is busy processing tasks.
+        active: bool,
+    }
+
+    /// Both sides of the bridge, from `runtime_handshake`. Method tables list
+    /// `id:Name`; fields a library or runtime predating the handshake can't
+    /// fill are empty.
+    #[derive(Clone)]
+    struct HandshakeInfo {
+        /// Version of the loaded client library.
+        library: String,
+        /// Version of the runtime.
+        runtime: String,
+        /// Methods the shim was compiled to send.
+        bridge_methods: Vec<String>,
+        /// Methods the runtime dispatches.
+        runtime_methods: Vec<String>,
+    }
+
+    extern "Rust" {
+        fn placement_score(desc: &BlobDescriptor) -> f32;
+        fn blob_partition(tag_id: &CteTagId, name: &[u8], default_hash: u32) -> i64;
+        fn trace_key() -> u64;
+        fn op_timeout() -> f32;
+        fn op_timed_out();
+    }
+
     unsafe extern "C++" {
         include!("shim/shim.h");
 
         type CteTag;
 
-        fn cte_init(config_path: &str) -> bool;
-        fn tag_new(tag_name: &str) -> UniquePtr<CteTag>;
+        fn cte_init(config_path: &str) -> Result<bool>;
+        fn chimaera_mode() -> u8;
+        fn tag_new(tag_name: &str) -> Result<UniquePtr<CteTag>>;
+        fn tag_new_bytes(tag_name: &[u8]) -> Result<UniquePtr<CteTag>>;
         fn tag_from_id(major: u32, minor: u32) -> UniquePtr<CteTag>;
-        fn tag_put_blob(tag: &CteTag, name: &str, data: &[u8], offset: u64, score: f32);
+        fn tag_put_blob(
+            tag: &CteTag,
+            name: &str,
+            data: &[u8],
+            offset: u64,
+            score: f32,
+        ) -> Result<()>;
+        fn tag_put_blob_bytes(
+            tag: &CteTag,
+            name: &[u8],
+            data: &[u8],
+            offset: u64,
+            score: f32,
+        ) -> Result<()>;
+        fn tag_put_blob_placed(tag: &CteTag, name: &str, data: &[u8], offset: u64) -> Result<()>;
         fn tag_get_blob(
             tag: &CteTag,
             name: &str,
             size: u64,
             offset: u64,
-        ) -> UniquePtr<CxxVector<u8>>;
+        ) -> Result<UniquePtr<CxxVector<u8>>>;
+        fn tag_get_blob_bytes(
+            tag: &CteTag,
+            name: &[u8],
+            size: u64,
+            offset: u64,
+        ) -> Result<UniquePtr<CxxVector<u8>>>;
+        fn tag_swap_blob(
+            tag: &CteTag,
+            name: &str,
+            expected: &[u8],
+            desired: &[u8],
+            score: f32,
+        ) -> Result<bool>;
         fn tag_get_blob_score(tag: &CteTag, name: &str) -> f32;
         fn tag_get_blob_size(tag: &CteTag, name: &str) -> u64;
+        fn tag_get_blob_size_bytes(tag: &CteTag, name: &[u8]) -> u64;
         fn tag_get_contained_blobs(tag: &CteTag) -> UniquePtr<CxxVector<CxxString>>;
-        fn tag_reorganize_blob(tag: &CteTag, name: &str, score: f32);
+        fn tag_list_blobs(tag: &CteTag, prefix: &[u8], delimiter: &[u8]) -> Result<Vec<ListEntry>>;
+        fn tag_reorganize_blob(tag: &CteTag, name: &str, score: f32) -> Result<()>;
+        fn tag_blob_blocks(tag: &CteTag, name: &str) -> Result<Vec<BlobBlockRow>>;
+        fn tag_del_blob(tag: &CteTag, name: &str) -> bool;
+        fn tag_del_blob_bytes(tag: &CteTag, name: &[u8]) -> bool;
         fn tag_get_id(tag: &CteTag) -> CteTagId;
+        fn tag_blob_hash(tag: &CteTag, name: &str) -> u32;
+        fn tag_get_size(tag: &CteTag) -> u64;
+        fn tag_get_blob_sizes(tag: &CteTag, names: &[String]) -> Vec<u64>;
+        fn tag_put_blobs(
+            tag: &CteTag,
+            names: &[String],
+            data: &[u8],
+            lens: &[u64],
+            scores: &[f32],
+        ) -> Result<()>;
+        fn tag_del_blobs(tag: &CteTag, names: &[String]) -> Vec<u8>;
+        fn tag_stat_blobs(
+            tag: &CteTag,
+            names: &[String],
+            meta_names: &[String],
+        ) -> Result<Vec<BlobInfoRow>>;
         fn client_register_target(target_path: &str, size: u64) -> bool;
+        fn client_register_ram_target(name: &str, size: u64) -> bool;
+        fn client_hostfile() -> Result<Vec<String>>;
+        fn client_node_port() -> u32;
+        fn client_register_remote_target(name: &str, node_id: u32, size: u64) -> Result<()>;
+        #[allow(clippy::too_many_arguments)]
+        fn client_register_s3_target(
+            name: &str,
+            endpoint: &str,
+            region: &str,
+            bucket: &str,
+            prefix: &str,
+            access_key_id: &str,
+            secret_access_key: &str,
+            session_token: &str,
+            size: u64,
+        ) -> Result<bool>;
         fn client_del_tag(name: &str) -> bool;
+        fn client_del_tag_bytes(name: &[u8]) -> bool;
+        fn client_container_count() -> u32;
+        fn client_list_targets() -> Vec<TargetInfo>;
+        fn client_worker_stats() -> Vec<WorkerStats>;
+        fn runtime_handshake() -> HandshakeInfo;
         fn client_tag_query(regex: &str, max_tags: u32) -> UniquePtr<CxxVector<CxxString>>;
         fn client_blob_query(
             tag_re: &str,
             blob_re: &str,
             max_results: u32,
         ) -> UniquePtr<CxxVector<CxxString>>;
+        fn client_blob_query_stat(
+            tag_re: &str,
+            blob_re: &str,
+            max_results: u32,
+            min_size: u64,
+            max_size: u64,
+            min_score: f32,
+            max_score: f32,
+        ) -> Vec<BlobQueryRow>;
     }
 }
 
-pub use ffi::CteTagId;
+// SAFETY: a `CteTag` is only read after construction (its tag ID, by every
+// shim function), and the tasks submitted for it go through the Chimaera
+// client, which serves concurrent callers.
+unsafe impl Send for ffi::CteTag {}
+unsafe impl Sync for ffi::CteTag {}
+
+pub use accounting::{ClientOptions, ClientUsage};
+pub use adopt::{AdoptReport, AdoptSource, FileRef};
+pub use alert::{Alert, AlertKind, WebhookOptions, WebhookSink};
+pub use archive::ArchiveFormat;
+pub use attrs::Attrs;
+pub use audit::{AccessEntry, AccessKind, AccessReport, AccessStat};
+pub use autotier::{AutoTierPolicy, AutoTierTask};
+#[cfg(feature = "arrow")]
+pub use batches::{ARROW_ROWS_ATTR, ARROW_SCHEMA_ATTR};
+#[cfg(feature = "mmap")]
+pub use blobmap::BlobMap;
+pub use bulk::{AffectedBlob, BulkOptions, BulkReport, FailedBlob};
+pub use cancel::CancellationToken;
+pub use channel::{Channel, ChannelOptions, ChannelReader};
+pub use checksum::{Checksum, ChecksumAlgorithm};
+#[cfg(feature = "parquet")]
+pub use columnar::ParquetWriter;
+pub use compress::Compression;
+pub use connector::{Broker, ConnectorOptions, EventConnector, EventEncoding};
+#[cfg(feature = "cuda")]
+pub use cuda::CudaStream;
+pub use delete::{DelTagHandle, DelTagOptions, DelTagProgress};
+#[cfg(feature = "encryption")]
+pub use encrypt::{clear_key_provider, set_key_provider, KeyProvider};
+pub use environment::{ConfigDigest, Environment};
+pub use error::CteError;
+pub use events::{Event, EventFilter, EventKind, EventStream, Subscription};
+pub use ffi::{BlobDescriptor, CteTagId, TargetInfo, WorkerStats};
+pub use ffi_guard::RuntimeCode;
+#[cfg(feature = "flight")]
+pub use flight::{FlightOptions, FlightServer};
+#[cfg(feature = "gateway")]
+pub use gateway::{GatewayOptions, GatewayServer};
+pub use group::{GroupCommit, GroupCommitOptions};
+#[cfg(feature = "grpc")]
+pub use grpc::{GrpcOptions, GrpcServer};
+pub use handoff::HandoffToken;
+pub use handshake::{Capability, RuntimeInfo};
+#[cfg(feature = "hdf5-vfd")]
+pub use hdf5::CTE_URL_PREFIX;
+pub use index::{NameIndex, NameIndexOptions};
+pub use io::{BlobStat, GetOptions, PutOptions};
+pub use limits::{request_limits, set_request_limits, RequestLimits};
+pub use listing::BlobListing;
+#[cfg(feature = "metrics")]
+pub use metrics::MetricsServer;
+pub use model::{
+    clear_score_model, set_score_model, Feedback, LinearScoreModel, RecentAccess, ScoreModel,
+};
+pub use names::{name_policy, set_name_policy, NamePolicy};
+pub use negcache::NegativeCacheOptions;
+#[cfg(feature = "notebook")]
+pub use notebook::{NotebookOptions, NotebookServer};
+#[cfg(feature = "objects")]
+pub use object::{CteBlob, ObjectFormat, FORMAT_ATTR, SCHEMA_ATTR, TYPE_ATTR};
+pub use oplog::{Change, ChangeKind, Changes, Listing};
+use partition::blob_partition;
+pub use partition::{
+    clear_partition_strategy, set_partition_strategy, HashPartitioner, PartitionStrategy,
+    RangePartitioner,
+};
+pub use pin::{PinInfo, PinLease, PIN_LEASE_TTL};
+use placement::placement_score;
+pub use placement::{clear_placement_policy, set_placement_policy, PlacementPolicy};
+pub use preflight::{CheckStatus, PreflightCheck, PreflightReport};
+pub use pressure::{MemoryPressureOptions, MemoryPressureWatch};
+use propagate::trace_key;
+pub use propagate::{
+    clear_trace_context_provider, set_trace_context_provider, TraceContext, TraceContextProvider,
+};
+pub use query::{Cmp, Predicate, QueryBuilder, QueryResult};
+pub use readahead::{ReadAhead, ADAPTIVE_MAX};
+pub use readcache::ReadCacheOptions;
+#[cfg(feature = "http")]
+pub use rest::{RestOptions, RestServer};
+pub use retry::{clear_retry_policy, set_retry_policy, ErrorClass, RetryPolicy};
+pub use s3::S3Credentials;
+pub use settle::{set_settle_time, settle_time};
+pub use shard::{AutoSplitOptions, AutoSplitTask, ShardLoad};
+#[cfg(feature = "shm")]
+pub use shm::{ShmBlob, SHM_MAX_SIZE};
+pub use simulate::{
+    simulate, SimOp, SimOptions, SimReport, SimTarget, SyntheticMix, TargetProjection, Workload,
+};
+pub use snapshot::SnapshotInfo;
+pub use spill::{clear_spill_policy, set_spill_policy, Spill, SpillPolicy};
+pub use stage::{ProgressFn, StageOptions, StageProgress, StageReport};
+pub use state::{runtime_state, RuntimeState};
+pub use stream::{BlobReader, BlobWriter, WRITE_BUFFER};
+pub use summary::{
+    add_summarizer, clear_summarizers, BlobSummary, ColumnSummary, HeadSummarizer, Summarizer,
+    TableSummarizer,
+};
+#[cfg(feature = "datafusion")]
+pub use table::{CteTableOptions, CteTableProvider};
+pub use timeout::OpOptions;
+use timeout::{op_timed_out, op_timeout};
+pub use trash::TrashEntry;
+pub use ttl::{clear_expiry_warning, set_expiry_warning, ExpiryTask};
+pub use txn::Txn;
+pub use versions::BlobVersion;
+pub use warmup::{WarmupManifest, WarmupOptions, WarmupReport};
+pub use writeback::{WriteBackMode, WriteBackOptions};
+#[cfg(feature = "derive")]
+pub use wrp_cte_derive::CteBlob;
+#[cfg(feature = "zarr")]
+pub use zarr::{ZarrArray, ZarrArrayMeta, ZarrDataType, ZarrStore, ZARR_JSON};
 
 /// Initialize CTE with an embedded runtime.
 ///
-/// Must be called once before any other CTE operations.
+/// Must be called before any other CTE operations.
 /// `config_path` can be empty to use default configuration.
-pub fn init(config_path: &str) -> Result<(), String> {
-    if ffi::cte_init(config_path) {
-        Ok(())
-    } else {
-        Err("CTE initialization failed".into())
+///
+/// Safe to call more than once and from several threads: the first call that
+/// succeeds initializes the process, concurrent calls wait for it, and later
+/// ones return the state it reached and ignore `config_path` (see `state`).
+///
+/// Fails if the installed client library or the runtime is too old for this
+/// wrapper or was built with different method IDs, and disables optional
+/// features the runtime lacks (see `handshake`).
+///
+/// Also reaps ephemeral tags left behind by crashed sessions (see
+/// `Tag::ephemeral`).
+#[cfg_attr(
+    feature = "trace",
+    tracing::instrument(level = "debug", skip_all, fields(config = config_path), err)
+)]
+pub fn init(config_path: &str) -> Result<RuntimeState, String> {
+    try_init(config_path).map_err(|e| match e {
+        CteError::Unsupported(msg) => msg,
+        e => e.to_string(),
+    })
+}
+
+/// `init`, keeping a `CteError::Runtime` from the C++ side as such; other
+/// failures are `Unsupported`.
+pub(crate) fn try_init(config_path: &str) -> Result<RuntimeState, CteError> {
+    state::INIT.run(|| {
+        if !ffi_guard::cte_init(config_path)? {
+            return Err(CteError::Unsupported("CTE initialization failed".into()));
+        }
+        handshake::negotiate().map_err(CteError::Unsupported)?;
+        environment::record_config(config_path);
+        Client::reap_sessions(&BulkOptions::default());
+        Ok(RuntimeState::current())
+    })
+}
+
+/// `init` with process-wide client settings.
+///
+/// A `label` is recorded on this process's change-log entries and usage records
+/// (see `Client::usage`), so operators can attribute load to it. The settings
+/// are applied on every call, including after the first.
+#[cfg_attr(
+    feature = "trace",
+    tracing::instrument(level = "debug", skip_all, fields(config = config_path), err)
+)]
+pub fn init_with_options(
+    config_path: &str,
+    options: &ClientOptions,
+) -> Result<RuntimeState, String> {
+    let state = init(config_path)?;
+    accounting::set_label(options.label.as_deref());
+    if let Some(policy) = &options.retry {
+        set_retry_policy(policy.clone());
+    }
+    if let Some(len) = options.max_name_len {
+        rawname::set_max_name_len(len);
     }
+    Ok(state)
 }
 
 /// A handle to a CTE tag (bucket / container).
+///
+/// Handles are `Send` and `Sync`: share one (in an `Arc`, or by reference
+/// across scoped threads) rather than reopening the tag per request. Calls
+/// through a shared handle run concurrently and are ordered only as the
+/// runtime orders them; the wrapper's own read-modify-write steps (metadata,
+/// appends, versions) are serialized whichever handle they come through.
+/// Per-call settings such as `Client::with_op_options` deadlines and
+/// cancellation apply to the calling thread, not to the handle.
 pub struct Tag {
     inner: cxx::UniquePtr<ffi::CteTag>,
+    /// Whether this tag keeps a change log (see `oplog`), probed on first mutation.
+    changelog: oplog::LogState,
+    /// Whether this tag keeps an access log (see `audit`), probed on first access.
+    accesslog: oplog::LogState,
+    /// Whether this tag keeps blob versions (see `versions`), probed on first write.
+    versioning: oplog::LogState,
+    /// Tag name, or `#major.minor` for handles opened by ID.
+    name: String,
 }
 
 impl Tag {
     /// Create or get a tag by name.
+    ///
+    /// Panics on any error `try_new` returns.
     pub fn new(name: &str) -> Self {
-        Self {
-            inner: ffi::tag_new(name),
+        Self::try_new(name).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Create or get a tag by name, failing with `CteError::Runtime` if the
+    /// runtime can't create it.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(tag = name), err)
+    )]
+    pub fn try_new(name: &str) -> Result<Self, CteError> {
+        // Only pay for the existence check when someone is listening.
+        let created = events::has_subscribers()
+            && !meta::is_reserved(name)
+            && Client::tag_names(&events::exact(name), 1).is_empty();
+        let tag = Self {
+            inner: ffi_guard::tag_new(name)?,
+            changelog: oplog::LogState::default(),
+            accesslog: oplog::LogState::default(),
+            versioning: oplog::LogState::default(),
+            name: name.to_string(),
+        };
+        shard::load_splits(&tag);
+        if created {
+            events::emit(Event::TagCreated {
+                tag: name.to_string(),
+            });
         }
+        Ok(tag)
     }
 
     /// Open an existing tag by its ID.
     pub fn from_id(id: CteTagId) -> Self {
-        Self {
+        let tag = Self {
             inner: ffi::tag_from_id(id.major, id.minor),
-        }
+            changelog: oplog::LogState::default(),
+            accesslog: oplog::LogState::default(),
+            versioning: oplog::LogState::default(),
+            name: format!("#{}.{}", id.major, id.minor),
+        };
+        shard::load_splits(&tag);
+        tag
     }
 
-    /// Write data into a blob with default offset (0) and score (1.0).
+    /// The tag's name (`#major.minor` if it was opened by ID).
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Write data into a blob with default offset (0).
+    ///
+    /// The score comes from the registered `PlacementPolicy`, or 1.0 if none is set.
+    ///
+    /// Panics if the tag is encrypted and the data can't be sealed; use `Tag::put`
+    /// to get an error instead.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(tag = %self.name, blob = name, size = data.len())
+        )
+    )]
     pub fn put_blob(&self, name: &str, data: &[u8]) {
-        ffi::tag_put_blob(&self.inner, name, data, 0, 1.0);
+        self.write_blob(name, data, 0, None);
     }
 
     /// Write data into a blob with explicit offset and score.
+    ///
+    /// Panics under the same conditions as `put_blob`, and for a non-zero offset
+    /// into an encrypted tag.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(tag = %self.name, blob = name, size = data.len(), offset = offset, score = score)
+        )
+    )]
     pub fn put_blob_with_options(&self, name: &str, data: &[u8], offset: u64, score: f32) {
-        ffi::tag_put_blob(&self.inner, name, data, offset, score);
+        self.write_blob(name, data, offset, Some(score));
     }
 
-    /// Read blob data. Returns a `Vec<u8>` of `size` bytes starting at `offset`.
+    /// Read `size` bytes of blob data from offset 0.
+    ///
+    /// Panics on any error `try_get_blob` returns, a missing blob included.
     pub fn get_blob(&self, name: &str, size: u64) -> Vec<u8> {
-        let v = ffi::tag_get_blob(&self.inner, name, size, 0);
-        v.iter().copied().collect()
+        self.try_get_blob(name, size)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Read `size` bytes of blob data from offset 0. Reads larger than
+    /// `RequestLimits::max_chunk` are made in chunks.
+    ///
+    /// Fails with `CteError::Runtime` if the runtime fails the read, as it does
+    /// for a missing blob, and with `CteError::TooLarge` for a `size` over
+    /// `RequestLimits::max_read` or that can't be allocated; the allocation
+    /// itself never aborts.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(tag = %self.name, blob = name, size = size),
+            err
+        )
+    )]
+    pub fn try_get_blob(&self, name: &str, size: u64) -> Result<Vec<u8>, CteError> {
+        writeback::flush_blob(self, name);
+        self.read_blob_cached(name, size, 0)
     }
 
     /// Read blob data with explicit offset.
+    ///
+    /// Panics on any error `try_get_blob_with_offset` returns.
     pub fn get_blob_with_offset(&self, name: &str, size: u64, offset: u64) -> Vec<u8> {
-        let v = ffi::tag_get_blob(&self.inner, name, size, offset);
-        v.iter().copied().collect()
+        self.try_get_blob_with_offset(name, size, offset)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Read blob data with explicit offset, failing as `try_get_blob` does.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(tag = %self.name, blob = name, size = size, offset = offset),
+            err
+        )
+    )]
+    pub fn try_get_blob_with_offset(
+        &self,
+        name: &str,
+        size: u64,
+        offset: u64,
+    ) -> Result<Vec<u8>, CteError> {
+        writeback::flush_blob(self, name);
+        self.read_blob_cached(name, size, offset)
+    }
+
+    /// Read path shared by all public gets.
+    pub(crate) fn read_blob(
+        &self,
+        name: &str,
+        size: u64,
+        offset: u64,
+    ) -> Result<Vec<u8>, CteError> {
+        let mut buf = limits::read_buffer(name, size, &limits::request_limits())?;
+        self.read_blob_into(name, &mut buf, offset)?;
+        Ok(buf)
+    }
+
+    /// `read_blob` through the read cache (see `readcache`).
+    fn read_blob_cached(&self, name: &str, size: u64, offset: u64) -> Result<Vec<u8>, CteError> {
+        let range = (offset, Some(size), true);
+        if let Some(data) = self.cached_read(name, range) {
+            return Ok(data);
+        }
+        let epoch = readcache::epoch();
+        let data = self.read_blob_ahead(name, size, offset)?;
+        self.cache_read(name, range, &data, epoch);
+        Ok(data)
+    }
+
+    /// Read `buf.len()` bytes from `offset` into `buf`, in chunks of at most
+    /// `RequestLimits::max_chunk`.
+    pub(crate) fn read_blob_into(
+        &self,
+        name: &str,
+        buf: &mut [u8],
+        offset: u64,
+    ) -> Result<(), CteError> {
+        let limits = limits::request_limits();
+        let size = buf.len() as u64;
+        limits::check_read(name, size, &limits)?;
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
+        let mut at = 0;
+        for (chunk_offset, len) in limits::chunks(offset, size, limits.max_chunk) {
+            let v = ffi_guard::tag_get_blob(&self.inner, name, len, chunk_offset)?;
+            let n = v.len().min(buf.len() - at);
+            buf[at..at + n].copy_from_slice(&v.as_slice()[..n]);
+            at += n;
+        }
+        accounting::record(accounting::Op::Get, size);
+        #[cfg(feature = "metrics")]
+        metrics::record(&self.name, accounting::Op::Get, size, Some(start.elapsed()));
+        self.record_access(name, AccessKind::Read, offset, size);
+        Ok(())
     }
 
     /// Get the placement score of a blob.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(tag = %self.name, blob = name), ret)
+    )]
     pub fn get_blob_score(&self, name: &str) -> f32 {
         ffi::tag_get_blob_score(&self.inner, name)
     }
 
     /// Get the size of a blob in bytes.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(tag = %self.name, blob = name), ret)
+    )]
     pub fn get_blob_size(&self, name: &str) -> u64 {
         ffi::tag_get_blob_size(&self.inner, name)
     }
 
-    /// List all blob names in this tag.
+    /// List all blob names in this tag (wrapper-internal sidecars excluded),
+    /// with names that aren't UTF-8 returned as `NamePolicy` says.
+    ///
+    /// Panics under `NamePolicy::Error` if a name isn't UTF-8.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(tag = %self.name))
+    )]
     pub fn get_contained_blobs(&self) -> Vec<String> {
-        let v = ffi::tag_get_contained_blobs(&self.inner);
-        v.iter().map(|s| s.to_string_lossy().into_owned()).collect()
+        match self.try_get_contained_blobs() {
+            Ok(names) => names,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// `get_contained_blobs`, failing with `CteError::InvalidName` instead of
+    /// panicking.
+    pub fn try_get_contained_blobs(&self) -> Result<Vec<String>, CteError> {
+        let names = self.get_contained_blobs_bytes();
+        names::decode_all(names.iter().map(Vec::as_slice), names::name_policy())
+    }
+
+    /// Blob names for the wrapper's own walks over the tag: sidecars excluded,
+    /// names that aren't UTF-8 replaced whatever the `NamePolicy`.
+    pub(crate) fn blob_names(&self) -> Vec<String> {
+        let names = self.get_contained_blobs_bytes();
+        names
+            .iter()
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect()
     }
 
-    /// Change the placement score of a blob, triggering data migration.
+    /// Change the placement score of a blob, as `try_reorganize_blob` does.
+    ///
+    /// Panics if the runtime fails the move.
     pub fn reorganize_blob(&self, name: &str, score: f32) {
-        ffi::tag_reorganize_blob(&self.inner, name, score);
+        if let Err(e) = self.try_reorganize_blob(name, score) {
+            panic!("{}", e);
+        }
+    }
+
+    /// Change the placement score of a blob, triggering data migration. A
+    /// pinned blob (see `Tag::pin_blob`) is left where it is, as is a blob
+    /// asked to move down before it has settled (see `settle`).
+    ///
+    /// Fails with `CteError::Runtime` if the runtime fails the move.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(tag = %self.name, blob = name, score = score),
+            err
+        )
+    )]
+    pub fn try_reorganize_blob(&self, name: &str, score: f32) -> Result<(), CteError> {
+        writeback::flush_blob(self, name);
+        // An unreadable sidecar holds no pin the blob could be kept to.
+        let meta = self.load_meta(name).ok().flatten();
+        if let Some(meta) = meta {
+            if meta.pin().is_some() || self.demotes_settling(name, &meta, score) {
+                return Ok(());
+            }
+        }
+        self.move_blob(name, score)
+    }
+
+    /// Reorganize `name` to `score`, pinned or not.
+    pub(crate) fn move_blob(&self, name: &str, score: f32) -> Result<(), CteError> {
+        #[cfg(feature = "metrics")]
+        let _moving = metrics::reorganizing(self.get_blob_score(name), score);
+        ffi_guard::tag_reorganize_blob(&self.inner, name, score)?;
+        self.record_change(name, ChangeKind::Reorganize { score });
+        events::emit(Event::BlobReorganized {
+            tag: self.name.clone(),
+            blob: name.to_string(),
+            score,
+        });
+        Ok(())
+    }
+
+    /// Delete a blob and its wrapper metadata.
+    ///
+    /// With the trash enabled (`Client::enable_trash`) the blob is moved to the
+    /// tag's trash instead, from where `Client::restore` can bring it back.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(tag = %self.name, blob = name), ret)
+    )]
+    pub fn del_blob(&self, name: &str) -> bool {
+        writeback::flush_blob(self, name);
+        if trash::enabled() && !trash::is_trash_tag(&self.name) {
+            return self.soft_del_blob(name).is_ok();
+        }
+        self.hard_del_blob(name)
+    }
+
+    /// Delete a blob and its wrapper metadata, bypassing the trash.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "trace", skip_all, fields(tag = %self.name, blob = name), ret)
+    )]
+    pub(crate) fn hard_del_blob(&self, name: &str) -> bool {
+        let _guard = meta::meta_lock();
+        self.archive_deleted(name);
+        #[cfg(feature = "metrics")]
+        let start = std::time::Instant::now();
+        let ok = ffi::tag_del_blob(&self.inner, name);
+        #[cfg(feature = "metrics")]
+        let latency = start.elapsed();
+        ffi::tag_del_blob(&self.inner, &format!("{}{}", meta::META_PREFIX, name));
+        if ok {
+            accounting::record(accounting::Op::Delete, 0);
+            #[cfg(feature = "metrics")]
+            metrics::record(&self.name, accounting::Op::Delete, 0, Some(latency));
+            self.record_access(name, AccessKind::Delete, 0, 0);
+            self.record_change(name, ChangeKind::Delete);
+            events::emit(Event::BlobDeleted {
+                tag: self.name.clone(),
+                blob: name.to_string(),
+            });
+        }
+        ok
+    }
+
+    /// Total bytes stored in the tag, wrapper sidecars included.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(tag = %self.name), ret)
+    )]
+    pub fn total_size(&self) -> u64 {
+        ffi::tag_get_size(&self.inner)
     }
 
     /// Get the tag's unique ID.
@@ -121,35 +879,184 @@ impl Tag {
     }
 }
 
+/// Where `Client::register_shm_target` puts its files.
+const SHM_DIR: &str = "/dev/shm";
+
+/// Path of the shared-memory target `name`, if it is a plain file name.
+fn shm_target_path(name: &str) -> Option<String> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\0']) {
+        return None;
+    }
+    Some(format!("{}/{}", SHM_DIR, name))
+}
+
 /// Static client operations (no tag context needed).
 pub struct Client;
 
 impl Client {
     /// Register a file-backed storage target with the CTE pool.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(target = target_path, size = size),
+            ret
+        )
+    )]
     pub fn register_target(target_path: &str, size: u64) -> bool {
         ffi::client_register_target(target_path, size)
     }
 
+    /// Register a storage target of `size` bytes of the runtime's memory,
+    /// listed as `name`. The runtime scores it like any other target, so it
+    /// is normally the fastest tier and blobs put at a high score land there;
+    /// its contents are lost when the runtime stops.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(target = name, size = size), ret)
+    )]
+    pub fn register_ram_target(name: &str, size: u64) -> bool {
+        ffi::client_register_ram_target(name, size)
+    }
+
+    /// Register a file-backed target in the shared-memory filesystem
+    /// (`/dev/shm/<name>`): its data is held in memory like a RAM target's, but
+    /// counts against the host's shared-memory limit rather than the
+    /// runtime's own memory. `false` if `name` isn't a plain file name or there
+    /// is no `/dev/shm`.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(target = name, size = size), ret)
+    )]
+    pub fn register_shm_target(name: &str, size: u64) -> bool {
+        match shm_target_path(name) {
+            Some(path) if std::path::Path::new(SHM_DIR).is_dir() => {
+                Client::register_target(&path, size)
+            }
+            _ => false,
+        }
+    }
+
+    /// The storage targets registered with the CTE pool.
+    #[cfg_attr(feature = "trace", tracing::instrument(level = "debug", skip_all))]
+    pub fn list_targets() -> Vec<TargetInfo> {
+        if !handshake::supports(Capability::TargetInfo) {
+            return Vec::new();
+        }
+        ffi::client_list_targets()
+    }
+
+    /// Queue depths of the local runtime's workers.
+    pub fn worker_stats() -> Vec<WorkerStats> {
+        ffi::client_worker_stats()
+    }
+
+    /// True if a tag named `name` exists. Unlike `Tag::new`, doesn't create it.
+    pub fn tag_exists(name: &str) -> bool {
+        meta::tag_exists(name)
+    }
+
     /// Delete a tag by name.
+    ///
+    /// With the trash enabled, the tag's blobs are moved to its trash first.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(tag = name), ret)
+    )]
     pub fn del_tag(name: &str) -> bool {
-        ffi::client_del_tag(name)
+        if trash::enabled() && !trash::is_trash_tag(name) && trash::trash_tag_blobs(name).is_err() {
+            return false;
+        }
+        let ok = ffi::client_del_tag(name);
+        if ok {
+            events::emit(Event::TagDeleted {
+                tag: name.to_string(),
+            });
+        }
+        ok
     }
 
-    /// Query tags matching a regex pattern.
+    /// Query tags matching a regex pattern (wrapper-internal tags excluded),
+    /// with names that aren't UTF-8 returned as `NamePolicy` says.
+    ///
+    /// Panics under `NamePolicy::Error` if a name isn't UTF-8.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(regex = regex, max_tags = max_tags))
+    )]
     pub fn tag_query(regex: &str, max_tags: u32) -> Vec<String> {
+        let tags = Self::tag_query_bytes(regex, max_tags);
+        match names::decode_all(tags.iter().map(Vec::as_slice), names::name_policy()) {
+            Ok(tags) => tags,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// `tag_query` with names exactly as stored.
+    pub fn tag_query_bytes(regex: &str, max_tags: u32) -> Vec<Vec<u8>> {
+        if !handshake::supports(Capability::Query) {
+            return Vec::new();
+        }
         let v = ffi::client_tag_query(regex, max_tags);
-        v.iter().map(|s| s.to_string_lossy().into_owned()).collect()
+        v.iter()
+            .map(|s| s.as_bytes())
+            .filter(|s| !s.starts_with(meta::RESERVED_PREFIX.as_bytes()))
+            .map(<[u8]>::to_vec)
+            .collect()
+    }
+
+    /// Tag names for the wrapper's own lookups: `tag_query` with names that
+    /// aren't UTF-8 replaced whatever the `NamePolicy`.
+    pub(crate) fn tag_names(regex: &str, max_tags: u32) -> Vec<String> {
+        Self::tag_query_bytes(regex, max_tags)
+            .iter()
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect()
     }
 
     /// Query blobs matching tag and blob regex patterns.
-    /// Returns pairs of (tag_name, blob_name).
+    /// Returns pairs of (tag_name, blob_name), with names that aren't UTF-8
+    /// returned as `NamePolicy` says; a pair with a name left out is left out.
+    ///
+    /// Panics under `NamePolicy::Error` if a name isn't UTF-8.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(tag_re = tag_re, blob_re = blob_re, max_results = max_results)
+        )
+    )]
     pub fn blob_query(tag_re: &str, blob_re: &str, max_results: u32) -> Vec<(String, String)> {
+        let policy = names::name_policy();
+        let mut out = Vec::new();
+        for (tag, blob) in Self::blob_query_bytes(tag_re, blob_re, max_results) {
+            match (names::decode(&tag, policy), names::decode(&blob, policy)) {
+                (Ok(Some(tag)), Ok(Some(blob))) => out.push((tag, blob)),
+                (Err(e), _) | (_, Err(e)) => panic!("{}", e),
+                _ => {}
+            }
+        }
+        out
+    }
+
+    /// `blob_query` with names exactly as stored.
+    pub fn blob_query_bytes(
+        tag_re: &str,
+        blob_re: &str,
+        max_results: u32,
+    ) -> Vec<(Vec<u8>, Vec<u8>)> {
+        if !handshake::supports(Capability::Query) {
+            return Vec::new();
+        }
+        let reserved = |name: &[u8]| name.starts_with(meta::RESERVED_PREFIX.as_bytes());
         let v = ffi::client_blob_query(tag_re, blob_re, max_results);
-        let flat: Vec<String> = v.iter().map(|s| s.to_string_lossy().into_owned()).collect();
+        let flat: Vec<&[u8]> = v.iter().map(|s| s.as_bytes()).collect();
         flat.chunks(2)
             .filter_map(|c| {
-                if c.len() == 2 {
-                    Some((c[0].clone(), c[1].clone()))
+                if c.len() == 2 && !reserved(c[0]) && !reserved(c[1]) {
+                    Some((c[0].to_vec(), c[1].to_vec()))
                 } else {
                     None
                 }
@@ -192,30 +1099,2427 @@ mod tests {
     }
 
     #[test]
-    fn test_config_based_init() {
-        // Use CHI_SERVER_CONF like the memorybench does
-        std::env::set_var(
-            "CHI_SERVER_CONF",
-            "/workspace/context-transfer-engine/benchmark/memorybench/cte_config.yaml",
+    fn test_generation_preconditions() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_generation_tag");
+        let create = PutOptions {
+            if_generation_match: Some(0),
+            ..Default::default()
+        };
+        let g1 = tag.put("blob", b"v1", &create).expect("create failed");
+        assert!(matches!(
+            tag.put("blob", b"v1 again", &create),
+            Err(CteError::GenerationMismatch { .. })
+        ));
+
+        let g2 = tag
+            .put(
+                "blob",
+                b"v2",
+                &PutOptions {
+                    if_generation_match: Some(g1),
+                    ..Default::default()
+                },
+            )
+            .expect("conditional update failed");
+        assert_eq!(g2, g1 + 1);
+        assert_eq!(tag.stat_blob("blob").unwrap().unwrap().generation, g2);
+
+        let stale = GetOptions {
+            if_generation_match: Some(g1),
+            ..Default::default()
+        };
+        assert!(tag.get("blob", &stale).is_err());
+
+        Client::del_tag("rust_generation_tag");
+    }
+
+    #[test]
+    fn test_stat_blobs() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_stat_blobs_tag");
+        tag.put("a", b"alpha", &PutOptions::default()).unwrap();
+        tag.put_blob("raw", b"xy");
+        let stats = tag.stat_blobs(&["a", "missing", "raw"]).unwrap();
+        assert_eq!(stats.len(), 3);
+        assert_eq!(stats[0], tag.stat_blob("a").unwrap());
+        assert!(stats[1].is_none());
+        assert_eq!(stats[2].as_ref().unwrap().size, 2);
+        Client::del_tag("rust_stat_blobs_tag");
+    }
+
+    #[test]
+    fn test_negative_cache() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        Client::enable_negative_cache(NegativeCacheOptions::default());
+        let tag = Tag::new("rust_negcache_tag");
+        let opts = GetOptions::default();
+        assert!(matches!(
+            tag.get("late", &opts),
+            Err(CteError::NotFound { .. })
+        ));
+        assert!(tag.stat_blob("late").unwrap().is_none());
+        // A put through another handle invalidates the cached miss.
+        Tag::from_id(tag.get_tag_id()).put_blob("late", b"here");
+        assert_eq!(tag.get("late", &opts).unwrap(), b"here");
+        assert!(tag.stat_blobs(&["late"]).unwrap()[0].is_some());
+        Client::disable_negative_cache();
+        Client::del_tag("rust_negcache_tag");
+    }
+
+    #[test]
+    fn test_name_index() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_name_index_tag");
+        tag.put_blob("run1/a", b"1");
+        tag.put_blob("run2/a", b"2");
+        let index = tag.name_index(NameIndexOptions::default());
+        assert_eq!(index.len(), 2);
+        tag.put_blob("run1/b", b"3");
+        tag.del_blob("run2/a");
+        assert_eq!(index.list_prefix("run1/"), ["run1/a", "run1/b"]);
+        assert_eq!(index.glob("run[0-9]/a"), ["run1/a"]);
+        index.rebuild();
+        assert_eq!(index.len(), 2);
+        Client::del_tag("rust_name_index_tag");
+    }
+
+    #[cfg(feature = "gateway")]
+    #[test]
+    fn test_gateway() {
+        use std::io::{Read, Write};
+
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_gateway_tag");
+        tag.put_blob("run1/out.bin", b"published");
+        let generation = tag.stat_blob("run1/out.bin").unwrap().unwrap().generation;
+        let options = GatewayOptions {
+            tags: vec!["rust_gateway_tag".into()],
+            immutable_urls: true,
+            ..Default::default()
+        };
+        assert!(Client::serve_gateway(
+            "127.0.0.1:0",
+            &GatewayOptions {
+                tags: vec!["rust_gateway_missing".into()],
+                ..Default::default()
+            }
+        )
+        .is_err());
+        let gateway = Client::serve_gateway("127.0.0.1:0", &options).unwrap();
+        let fetch = |request: &str| {
+            let mut stream = std::net::TcpStream::connect(gateway.local_addr()).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let current = fetch("GET /rust_gateway_tag/run1/out.bin HTTP/1.1\r\n\r\n");
+        assert!(current.starts_with("HTTP/1.1 200"));
+        assert!(current.contains(&format!("ETag: \"{}\"", generation)));
+        assert!(current.contains(&format!("Content-Location: /@{}/", generation)));
+        assert!(current.ends_with("published"));
+        let revalidated = fetch(&format!(
+            "GET /rust_gateway_tag/run1/out.bin HTTP/1.1\r\nIf-None-Match: \"{}\"\r\n\r\n",
+            generation
+        ));
+        assert!(revalidated.starts_with("HTTP/1.1 304"));
+        let pinned = fetch(&format!(
+            "GET /@{}/rust_gateway_tag/run1/out.bin HTTP/1.1\r\n\r\n",
+            generation
+        ));
+        assert!(pinned.contains("immutable") && pinned.ends_with("published"));
+        assert!(fetch("PUT /rust_gateway_tag/x HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 405"));
+        assert!(fetch("GET /rust_cas_tag/leader HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404"));
+        drop(gateway);
+        Client::del_tag("rust_gateway_tag");
+    }
+
+    #[cfg(feature = "notebook")]
+    #[test]
+    fn test_notebook() {
+        use std::io::{Read, Write};
+
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_notebook_tag");
+        tag.put_blob("runs/loss.csv", b"step,loss\n1,0.9\n2,0.7\n");
+        tag.put_blob("runs/state.bin", &[0u8, 1, 2, 255]);
+        let options = NotebookOptions {
+            token: Some("nb-token".into()),
+            ..Default::default()
+        };
+        assert!(Client::serve_notebook("0.0.0.0:0", &NotebookOptions::default()).is_err());
+        let notebook = Client::serve_notebook("127.0.0.1:0", &options).unwrap();
+        let fetch = |path: &str| {
+            let mut stream = std::net::TcpStream::connect(notebook.local_addr()).unwrap();
+            let request = format!(
+                "GET {} HTTP/1.1\r\nAuthorization: token nb-token\r\n\r\n",
+                path
+            );
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        assert!(fetch("/api/tags").contains("\"rust_notebook_tag\""));
+        let listing = fetch("/api/tags/rust_notebook_tag?prefix=runs%2F&delimiter=%2F");
+        assert!(listing.ends_with(
+            "{\"common_prefixes\":[],\"blobs\":[\"runs/loss.csv\",\"runs/state.bin\"]}"
+        ));
+        let table = fetch("/api/tags/rust_notebook_tag/blobs/runs/loss.csv");
+        assert!(table.contains("\"kind\":\"table\",\"rows\":[[\"step\",\"loss\"],"));
+        let binary = fetch("/api/tags/rust_notebook_tag/blobs/runs/state.bin?bytes=2");
+        assert!(binary.contains("\"truncated\":true,\"kind\":\"binary\",\"hex\":\"0001\""));
+        assert!(fetch("/api/occupancy.svg").contains("<svg"));
+        assert!(fetch("/api/tags/rust_notebook_tag/blobs/missing").starts_with("HTTP/1.1 404"));
+        let mut stream = std::net::TcpStream::connect(notebook.local_addr()).unwrap();
+        stream
+            .write_all(b"GET /api/tags?token=wrong HTTP/1.1\r\n\r\n")
+            .unwrap();
+        let mut refused = String::new();
+        stream.read_to_string(&mut refused).unwrap();
+        assert!(refused.starts_with("HTTP/1.1 403"));
+        drop(notebook);
+        Client::del_tag("rust_notebook_tag");
+    }
+
+    #[test]
+    fn test_conditional_puts() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_cas_tag");
+        let winners: usize = (0..4)
+            .map(|i| {
+                std::thread::spawn(move || {
+                    Tag::new("rust_cas_tag")
+                        .put_blob_if_absent("leader", format!("worker-{}", i).as_bytes())
+                        .unwrap()
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|h| h.join().unwrap() as usize)
+            .sum();
+        assert_eq!(winners, 1);
+
+        let version = tag.stat_blob("leader").unwrap().unwrap().generation;
+        let next = tag
+            .put_blob_if_match("leader", b"worker-9", version)
+            .unwrap();
+        assert_eq!(next, Some(version + 1));
+        assert_eq!(
+            tag.put_blob_if_match("leader", b"late", version).unwrap(),
+            None
         );
-        std::env::set_var("CHI_WITH_RUNTIME", "1");
 
+        // Blobs written without the wrapper have no generation but still exist.
+        ffi::tag_put_blob(&tag.inner, "raw", b"x", 0, 1.0).unwrap();
+        assert!(!tag.put_blob_if_absent("raw", b"y").unwrap());
+        Client::del_tag("rust_cas_tag");
+    }
+
+    #[test]
+    fn test_handoff_token() {
         init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
 
-        let tag = Tag::new("config_test_tag");
-        let id = tag.get_tag_id();
-        eprintln!("tag id: major={}, minor={}", id.major, id.minor);
-        assert!(id.major != 0 || id.minor != 0, "tag ID should be non-null");
+        let tag = Tag::new("rust_handoff_tag");
+        tag.put("stage1/out", b"v1", &PutOptions::default())
+            .unwrap();
+        let token = tag.export_handle("stage1/out").unwrap();
+        let wire = token.to_string();
 
-        let data = b"hello from config test";
-        tag.put_blob("test_blob", data);
+        let received: HandoffToken = wire.parse().unwrap();
+        assert_eq!(received.redeem().unwrap(), b"v1");
+        tag.put("stage1/out", b"v2", &PutOptions::default())
+            .unwrap();
+        assert!(matches!(
+            received.redeem(),
+            Err(CteError::GenerationMismatch { .. })
+        ));
 
-        let size = tag.get_blob_size("test_blob");
-        assert_eq!(size, data.len() as u64);
+        let leased = tag
+            .export_handle_with_lease("stage1/out", std::time::Duration::from_millis(10))
+            .unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        assert!(matches!(
+            leased.redeem(),
+            Err(CteError::LeaseExpired { .. })
+        ));
+        assert!(tag.export_handle("missing").is_err());
+        Client::del_tag("rust_handoff_tag");
+    }
 
-        let got = tag.get_blob("test_blob", size);
-        assert_eq!(got, data);
+    #[test]
+    fn test_blob_versions() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
 
-        Client::del_tag("config_test_tag");
+        let tag = Tag::new("rust_versions_tag");
+        tag.enable_versioning();
+        for step in ["epoch-1", "epoch-2", "epoch-3"] {
+            tag.put("model.ckpt", step.as_bytes(), &PutOptions::default())
+                .unwrap();
+        }
+        let versions = tag.list_blob_versions("model.ckpt").unwrap();
+        let numbers: Vec<u64> = versions.iter().map(|v| v.version).collect();
+        assert_eq!(numbers, [1, 2, 3]);
+        assert!(versions[2].current && !versions[0].current);
+        assert_eq!(tag.get_blob_version("model.ckpt", 1).unwrap(), b"epoch-1");
+        assert_eq!(tag.get_blob_version("model.ckpt", 3).unwrap(), b"epoch-3");
+
+        assert_eq!(tag.rollback_blob("model.ckpt", 1).unwrap(), 4);
+        assert_eq!(
+            tag.get("model.ckpt", &GetOptions::default()).unwrap(),
+            b"epoch-1"
+        );
+        assert_eq!(tag.prune_blob_versions("model.ckpt", 1), 2);
+        assert!(tag.get_blob_version("model.ckpt", 1).is_err());
+        assert!(tag.get_contained_blobs() == ["model.ckpt"]);
+        Client::del_tag("rust_versions_tag");
+    }
+
+    #[test]
+    fn test_tag_snapshot() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_snapshot_tag");
+        let opts = PutOptions::default();
+        tag.put("a", b"alpha", &opts).unwrap();
+        tag.put("b", b"beta", &opts).unwrap();
+        let info = tag.snapshot("baseline").unwrap();
+        assert_eq!((info.blobs, info.bytes), (2, 9));
+        assert!(tag.snapshot("baseline").is_err());
+
+        tag.put("a", b"changed", &opts).unwrap();
+        tag.del_blob("b");
+        tag.put("c", b"new", &opts).unwrap();
+        assert_eq!(
+            Client::restore_snapshot("rust_snapshot_tag", "baseline").unwrap(),
+            3
+        );
+        let mut blobs = tag.get_contained_blobs();
+        blobs.sort();
+        assert_eq!(blobs, ["a", "b"]);
+        assert_eq!(tag.get("a", &GetOptions::default()).unwrap(), b"alpha");
+        assert_eq!(tag.get("b", &GetOptions::default()).unwrap(), b"beta");
+
+        assert_eq!(tag.list_snapshots().unwrap()[0].label, "baseline");
+        assert!(tag.delete_snapshot("baseline"));
+        Client::del_tag("rust_snapshot_tag");
+    }
+
+    #[test]
+    fn test_group_commit() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_group_tag");
+        tag.put_blob("old", b"stale");
+        let options = GroupCommitOptions {
+            flush_interval: std::time::Duration::from_secs(3600),
+            max_ops: 64,
+        };
+        let group = tag.group_commit(options);
+        for i in 0..100 {
+            group.put(&format!("file-{}", i), b"small").unwrap();
+        }
+        group
+            .set_blob_attrs("file-1", &[("owner", "ingest")])
+            .unwrap();
+        group.delete("old").unwrap();
+        assert!(group.pending() < 64);
+        assert_eq!(group.flush().unwrap(), 100 + 2 - 64);
+        assert_eq!(tag.get_contained_blobs().len(), 100);
+        assert_eq!(tag.stat_blob("file-7").unwrap().unwrap().generation, 1);
+        assert_eq!(
+            tag.get_blob_attrs("file-1").unwrap(),
+            [("owner".to_string(), "ingest".to_string())]
+        );
+
+        group.set_blob_attrs("missing", &[("k", "v")]).unwrap();
+        assert!(matches!(group.flush(), Err(CteError::NotFound { .. })));
+        group.put("last", b"x").unwrap();
+        drop(group);
+        assert_eq!(tag.get_blob_size("last"), 1);
+        Client::del_tag("rust_group_tag");
+    }
+
+    #[test]
+    fn test_archive_roundtrip() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_archive_src");
+        let opts = PutOptions {
+            score: Some(0.5),
+            ..Default::default()
+        };
+        tag.put("ckpt/shard-0", b"shard zero", &opts).unwrap();
+        tag.put("../odd name", b"odd", &opts).unwrap();
+        tag.set_blob_attrs("ckpt/shard-0", &[("step", "100")])
+            .unwrap();
+        tag.set_attr("owner", "lab a").unwrap();
+
+        for (format, dst_name) in [
+            (ArchiveFormat::Tar, "rust_archive_tar"),
+            (ArchiveFormat::Zip, "rust_archive_zip"),
+        ] {
+            let mut archive = Vec::new();
+            assert_eq!(tag.export_archive(&mut archive, format).unwrap(), 2);
+            assert_eq!(
+                Client::import_archive(dst_name, archive.as_slice()).unwrap(),
+                2
+            );
+            let dst = Tag::new(dst_name);
+            assert_eq!(
+                dst.get("ckpt/shard-0", &GetOptions::default()).unwrap(),
+                b"shard zero"
+            );
+            assert_eq!(
+                dst.get("../odd name", &GetOptions::default()).unwrap(),
+                b"odd"
+            );
+            assert_eq!(dst.get_blob_score("ckpt/shard-0"), 0.5);
+            assert_eq!(
+                dst.get_blob_attrs("ckpt/shard-0").unwrap(),
+                [("step".to_string(), "100".to_string())]
+            );
+            assert_eq!(dst.get_attrs().unwrap()[0].1, "lab a");
+            Client::del_tag(dst_name);
+        }
+        Client::del_tag("rust_archive_src");
+    }
+
+    #[cfg(feature = "shm")]
+    #[test]
+    fn test_publish_shm() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_shm_tag");
+        tag.put("ctl/epoch", b"17", &PutOptions::default()).unwrap();
+        tag.publish_shm("ctl/epoch").unwrap();
+        let region = Tag::new("rust_shm_tag").open_shm("ctl/epoch").unwrap();
+        assert_eq!(&region[..], b"17");
+        assert_eq!(region.generation(), 1);
+
+        tag.put("ctl/epoch", b"18", &PutOptions::default()).unwrap();
+        tag.publish_shm("ctl/epoch").unwrap();
+        assert_eq!(&region[..], b"17");
+        assert_eq!(&tag.open_shm("ctl/epoch").unwrap()[..], b"18");
+
+        assert!(tag.unpublish_shm("ctl/epoch").unwrap());
+        assert!(matches!(
+            tag.open_shm("ctl/epoch"),
+            Err(CteError::NotFound { .. })
+        ));
+        Client::del_tag("rust_shm_tag");
+    }
+
+    #[test]
+    fn test_checksum_verification() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_checksum_tag");
+        let opts = PutOptions {
+            checksum: Some(ChecksumAlgorithm::Crc32c),
+            verify: true,
+            ..Default::default()
+        };
+        tag.put("blob", b"climate output", &opts)
+            .expect("put failed");
+        assert!(tag.verify_blob("blob").unwrap());
+
+        // Corrupt the data below the wrapper, as a failing tier would.
+        ffi::tag_put_blob(&tag.inner, "blob", b"X", 0, 1.0).unwrap();
+        assert!(matches!(
+            tag.verify_blob("blob"),
+            Err(CteError::ChecksumMismatch { .. })
+        ));
+        let verified = GetOptions {
+            verify_checksum: true,
+            ..Default::default()
+        };
+        assert!(tag.get("blob", &verified).is_err());
+
+        // An unchecked write drops the stale checksum.
+        tag.put_blob("blob", b"rewritten");
+        assert!(!tag.verify_blob("blob").unwrap());
+
+        Client::del_tag("rust_checksum_tag");
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_compressed_roundtrip() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_compress_tag");
+        let data = vec![7u8; 64 * 1024];
+        let opts = PutOptions {
+            compression: Compression::Zstd(3),
+            ..Default::default()
+        };
+        tag.put("blob", &data, &opts).expect("put failed");
+        assert!(tag.get_blob_size("blob") < data.len() as u64);
+
+        let stat = tag.stat_blob("blob").unwrap().unwrap();
+        assert_eq!(stat.size, data.len() as u64);
+        assert_eq!(stat.compression, Compression::Zstd(3));
+        assert_eq!(tag.get("blob", &GetOptions::default()).unwrap(), data);
+
+        Client::del_tag("rust_compress_tag");
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_encrypted_roundtrip() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        set_key_provider(|tag: &str| Ok((tag == "rust_encrypt_tag").then_some([42u8; 32])));
+        let tag = Tag::new("rust_encrypt_tag");
+        let data = b"patient 0042: controlled access".to_vec();
+        tag.put_blob("record", &data);
+        let stored = tag.get_blob("record", tag.get_blob_size("record"));
+        assert!(!stored.windows(7).any(|w| w == b"patient"));
+        assert_eq!(tag.get("record", &GetOptions::default()).unwrap(), data);
+
+        // Rotation: the old blob stays readable, and only `reencrypt` moves it
+        // to the new key.
+        let version = tag.rotate_key().expect("rotate failed");
+        assert!(version >= 2);
+        assert_eq!(tag.get("record", &GetOptions::default()).unwrap(), data);
+        let report = tag.reencrypt(&BulkOptions::default()).unwrap();
+        assert_eq!(report.blobs.len(), 1);
+        assert!(!tag.reencrypt_blob("record").unwrap());
+        assert_eq!(tag.get("record", &GetOptions::default()).unwrap(), data);
+
+        // A re-created tag gets a new data key, not the deleted tag's cached one:
+        // what it seals opens with the key in its sidecar once the cache is gone.
+        Client::del_tag("rust_encrypt_tag");
+        let tag = Tag::new("rust_encrypt_tag");
+        tag.put_blob("record", &data);
+        clear_key_provider();
+        set_key_provider(|tag: &str| Ok((tag == "rust_encrypt_tag").then_some([42u8; 32])));
+        assert_eq!(tag.get("record", &GetOptions::default()).unwrap(), data);
+
+        clear_key_provider();
+        assert!(matches!(
+            tag.get("record", &GetOptions::default()),
+            Err(CteError::Encryption(_))
+        ));
+        Client::del_tag("rust_encrypt_tag");
+    }
+
+    #[test]
+    fn test_del_tag_async() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_del_async_tag");
+        for i in 0..20 {
+            tag.put_blob(&format!("blob_{}", i), b"data");
+        }
+        let handle = Client::del_tag_async(
+            "rust_del_async_tag",
+            &DelTagOptions {
+                max_blobs_per_sec: Some(200),
+                ..Default::default()
+            },
+        );
+        let progress = handle.wait();
+        assert!(progress.finished && progress.tag_deleted);
+        assert_eq!(progress.deleted, 20);
+        assert!(Client::tag_query("rust_del_async_tag", 1).is_empty());
+    }
+
+    #[test]
+    fn test_blob_ttl() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_ttl_tag");
+        let short = PutOptions {
+            ttl: Some(std::time::Duration::from_millis(50)),
+            ..Default::default()
+        };
+        tag.put("scratch", b"temporary", &short).unwrap();
+        tag.put("kept", b"durable", &PutOptions::default()).unwrap();
+        assert!(tag.stat_blob("scratch").unwrap().unwrap().expires.is_some());
+        tag.set_blob_ttl("kept", std::time::Duration::from_millis(50))
+            .unwrap();
+        tag.clear_blob_ttl("kept").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(100));
+
+        assert!(matches!(
+            tag.get("scratch", &GetOptions::default()),
+            Err(CteError::NotFound { .. })
+        ));
+        assert_eq!(tag.get_blob_size("scratch"), 0);
+        assert_eq!(tag.get("kept", &GetOptions::default()).unwrap(), b"durable");
+        assert!(tag
+            .set_blob_ttl("missing", std::time::Duration::ZERO)
+            .is_err());
+        Client::del_tag("rust_ttl_tag");
+    }
+
+    #[test]
+    fn test_transaction() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_txn_tag");
+        tag.put_blob("ckpt/old", b"stale");
+        let mut txn = tag.transaction();
+        txn.put("ckpt/data", b"weights", &PutOptions::default())
+            .unwrap();
+        txn.put("ckpt/manifest", b"data", &PutOptions::default())
+            .unwrap();
+        txn.delete("ckpt/old");
+        assert_eq!(tag.get_blob_size("ckpt/manifest"), 0);
+        assert_eq!(tag.get_contained_blobs().len(), 1);
+        txn.commit().unwrap();
+        assert_eq!(
+            tag.get("ckpt/data", &GetOptions::default()).unwrap(),
+            b"weights"
+        );
+        assert_eq!(
+            tag.stat_blob("ckpt/manifest").unwrap().unwrap().generation,
+            1
+        );
+        assert_eq!(tag.get_blob_size("ckpt/old"), 0);
+
+        // A failed precondition applies nothing.
+        let mut txn = tag.transaction();
+        txn.put("ckpt/data", b"v2", &PutOptions::default()).unwrap();
+        let stale = PutOptions {
+            if_generation_match: Some(0),
+            ..Default::default()
+        };
+        txn.put("ckpt/manifest", b"v2", &stale).unwrap();
+        assert!(matches!(
+            txn.commit(),
+            Err(CteError::GenerationMismatch { .. })
+        ));
+        assert_eq!(
+            tag.get("ckpt/data", &GetOptions::default()).unwrap(),
+            b"weights"
+        );
+        assert_eq!(tag.recover_transactions().unwrap(), 0);
+
+        // A failure midway through applying leaves the journal, which readers
+        // finish before answering.
+        let mut txn = tag.transaction();
+        txn.put("ckpt/data", b"v3", &PutOptions::default()).unwrap();
+        txn.put("ckpt/manifest", b"v3", &PutOptions::default())
+            .unwrap();
+        txn::fail_apply_at(Some(1));
+        let failed = txn.commit();
+        txn::fail_apply_at(None);
+        assert!(matches!(failed, Err(CteError::Io(_))));
+        assert_eq!(tag.get("ckpt/data", &GetOptions::default()).unwrap(), b"v3");
+        assert_eq!(
+            tag.get("ckpt/manifest", &GetOptions::default()).unwrap(),
+            b"v3"
+        );
+        assert_eq!(tag.recover_transactions().unwrap(), 0);
+        assert_eq!(tag.get_contained_blobs().len(), 2);
+
+        // So do stats, and recovery finishes what no reader has touched.
+        let mut txn = tag.transaction();
+        txn.put("ckpt/data", b"v4", &PutOptions::default()).unwrap();
+        txn.put("ckpt/manifest", b"v4", &PutOptions::default())
+            .unwrap();
+        txn.delete("ckpt/old");
+        txn::fail_apply_at(Some(1));
+        assert!(txn.commit().is_err());
+        txn::fail_apply_at(None);
+        let stat = tag.stat_blob("ckpt/manifest").unwrap().unwrap();
+        assert_eq!(stat.size, 2);
+        assert_eq!(
+            tag.get("ckpt/manifest", &GetOptions::default()).unwrap(),
+            b"v4"
+        );
+        assert_eq!(tag.recover_transactions().unwrap(), 0);
+        let mut txn = tag.transaction();
+        txn.put("ckpt/manifest", b"v5", &PutOptions::default())
+            .unwrap();
+        txn::fail_apply_at(Some(0));
+        assert!(txn.commit().is_err());
+        txn::fail_apply_at(None);
+        assert_eq!(tag.recover_transactions().unwrap(), 1);
+        assert_eq!(
+            tag.get("ckpt/manifest", &GetOptions::default()).unwrap(),
+            b"v5"
+        );
+        assert_eq!(tag.get_contained_blobs().len(), 2);
+        Client::del_tag("rust_txn_tag");
+    }
+
+    #[test]
+    fn test_ephemeral_tags() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let a = Tag::ephemeral();
+        let b = Tag::ephemeral();
+        assert!(a.is_ephemeral() && a.name() != b.name());
+        a.put_blob("shuffle_0", b"partition");
+        assert_eq!(a.get_blob("shuffle_0", 9), b"partition");
+        assert!(!Client::tag_query(".*", 0).iter().any(|t| t == a.name()));
+
+        // Our own session is alive, so reaping leaves its tags alone.
+        assert!(Client::reap_sessions(&BulkOptions::default())
+            .blobs
+            .is_empty());
+        let report = Client::finalize();
+        assert!(report.blobs.iter().any(|b| b.blob == "shuffle_0"));
+        assert_eq!(Tag::new(a.name()).get_blob_size("shuffle_0"), 0);
+    }
+
+    #[test]
+    fn test_op_timeout() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_timeout_tag");
+        let options = OpOptions::default();
+        Client::with_op_options(&options, || tag.put_blob("b", b"in time")).unwrap();
+        let got = Client::with_op_options(&options, || tag.get_blob("b", 7)).unwrap();
+        assert_eq!(got, b"in time");
+
+        // A deadline that has already passed submits nothing.
+        let expired = OpOptions {
+            timeout: std::time::Duration::ZERO,
+        };
+        let put = Client::with_op_options(&expired, || tag.put_blob("late", b"x"));
+        assert!(matches!(put, Err(CteError::Timeout(_))));
+        assert_eq!(tag.get_blob_size("late"), 0);
+        // Reads and writes inside the scope fail themselves instead of passing
+        // off zeros as data or bumping the generation of an abandoned put.
+        let generation = tag.stat_blob("b").unwrap().unwrap().generation;
+        let mut inner = Vec::new();
+        let _ = Client::with_op_options(&expired, || {
+            inner.push(tag.try_get_blob_with_offset("b", 7, 0).map(drop));
+            inner.push(tag.put("b", b"too late", &PutOptions::default()).map(drop));
+            inner.push(tag.get_blob_bytes_name(b"b\xff", 1, 0).map(drop));
+        });
+        assert!(
+            inner.iter().all(|r| matches!(r, Err(CteError::Timeout(_)))),
+            "{:?}",
+            inner
+        );
+        assert_eq!(tag.stat_blob("b").unwrap().unwrap().generation, generation);
+        assert_eq!(tag.get_blob("b", 7), b"in time");
+        Client::del_tag("rust_timeout_tag");
+    }
+
+    #[test]
+    fn test_runtime_handshake() {
+        init("").expect("CTE init failed");
+        let info = Client::runtime_info().expect("init records the handshake");
+        assert_eq!(info.wrapper, env!("CARGO_PKG_VERSION"));
+        assert!(!info.library.is_empty() && !info.runtime.is_empty());
+        // Built from the same tree, the runtime supports everything.
+        assert!(info.disabled.is_empty(), "{:?}", info.disabled);
+    }
+
+    #[test]
+    fn test_init_is_idempotent() {
+        let states: Vec<_> = (0..4)
+            .map(|_| std::thread::spawn(|| init("").expect("CTE init failed")))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|t| t.join().unwrap())
+            .collect();
+        assert!(states.iter().all(|s| *s == states[0]));
+        assert_ne!(states[0], RuntimeState::Uninitialized);
+        assert_eq!(runtime_state(), states[0]);
+        // Later calls return the same state without initializing again.
+        assert_eq!(init("ignored.yaml").unwrap(), states[0]);
+    }
+
+    #[test]
+    fn test_dump_diagnostics() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_diag_tag");
+        tag.put_blob_with_options("journaled", b"x", 0, 1.0);
+        let path = std::env::temp_dir().join(format!("cte_diag_{}.tar", std::process::id()));
+        Client::dump_diagnostics(&path).unwrap();
+        let bundle = String::from_utf8_lossy(&std::fs::read(&path).unwrap()).into_owned();
+        assert!(bundle.contains("cte-diagnostics/journal.tsv"));
+        assert!(bundle.contains("put_blob"), "the put is journaled");
+        assert!(bundle.contains(env!("CARGO_PKG_VERSION")));
+        std::fs::remove_file(&path).unwrap();
+        Client::del_tag("rust_diag_tag");
+    }
+
+    #[test]
+    fn test_shared_tag() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Tag>();
+
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_shared_tag");
+        std::thread::scope(|s| {
+            for i in 0..4u8 {
+                let tag = &tag;
+                s.spawn(move || {
+                    let name = format!("blob_{}", i);
+                    tag.put_blob(&name, &[i; 64]);
+                    assert_eq!(tag.get_blob(&name, 64), [i; 64]);
+                });
+            }
+        });
+        assert_eq!(tag.get_contained_blobs().len(), 4);
+        Client::del_tag("rust_shared_tag");
+    }
+
+    #[test]
+    fn test_open_cached() {
+        init("").expect("CTE init failed");
+
+        let first = Tag::open_cached("rust_cached_tag").unwrap();
+        let second = Tag::open_cached("rust_cached_tag").unwrap();
+        assert!(std::sync::Arc::ptr_eq(&first, &second));
+        Client::del_tag("rust_cached_tag");
+        let reopened = Tag::open_cached("rust_cached_tag").unwrap();
+        assert!(!std::sync::Arc::ptr_eq(&first, &reopened));
+        Client::del_tag("rust_cached_tag");
+    }
+
+    #[test]
+    fn test_bytes_names() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::try_new_bytes_name(b"rust_bytes_tag_\xff").unwrap();
+        let name: &[u8] = b"latin1_\xe9t\xe9.dat";
+        tag.put_blob_bytes_name(name, b"raw", 0, 1.0).unwrap();
+        assert_eq!(tag.get_blob_size_bytes_name(name), 3);
+        assert_eq!(tag.get_blob_bytes_name(name, 3, 0).unwrap(), b"raw");
+        assert_eq!(tag.get_contained_blobs_bytes(), [name.to_vec()]);
+        assert_eq!(tag.get_contained_blobs(), ["latin1_\u{fffd}t\u{fffd}.dat"]);
+        set_name_policy(NamePolicy::Raw);
+        assert!(tag.get_contained_blobs().is_empty());
+        assert_eq!(tag.list_bytes(b"", b"").unwrap().blobs, [name.to_vec()]);
+        set_name_policy(NamePolicy::Error);
+        let listed = tag.try_get_contained_blobs();
+        set_name_policy(NamePolicy::Lossy);
+        assert!(matches!(listed, Err(CteError::InvalidName { .. })));
+        // UTF-8 names take the ordinary path.
+        tag.put_blob_bytes_name(b"plain", b"x", 0, 1.0).unwrap();
+        assert_eq!(tag.get_blob("plain", 1), b"x");
+        assert!(tag.del_blob_bytes_name(name));
+
+        tag.put_blob_bytes_name(b"nul\0inside", b"y", 0, 1.0)
+            .unwrap();
+        assert_eq!(tag.get_blob_size_bytes_name(b"nul\0inside"), 1);
+        assert_eq!(tag.get_blob_size_bytes_name(b"nul"), 0);
+        let long = vec![b'n'; Client::runtime_info().unwrap().max_name_len + 1];
+        let refused = tag.put_blob_bytes_name(&long, b"z", 0, 1.0);
+        assert!(matches!(refused, Err(CteError::InvalidArgument(_))));
+        assert!(Client::del_tag_bytes_name(b"rust_bytes_tag_\xff"));
+    }
+
+    #[test]
+    fn test_list() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_list_tag");
+        for name in [
+            "run42/step001/field.bin",
+            "run42/step001/meta.json",
+            "run42/step002/field.bin",
+            "run42/readme",
+            "run43/readme",
+        ] {
+            tag.put_blob(name, b"x");
+        }
+        tag.set_attr("owner", "ci").unwrap();
+
+        let top = tag.list("", "/").unwrap();
+        assert_eq!(top.common_prefixes, ["run42/", "run43/"]);
+        assert!(top.blobs.is_empty(), "sidecars stay hidden");
+        let run = tag.list("run42/", "/").unwrap();
+        assert_eq!(run.common_prefixes, ["run42/step001/", "run42/step002/"]);
+        assert_eq!(run.blobs, ["run42/readme"]);
+        let flat = tag.list("run42/step001/", "").unwrap();
+        assert_eq!(
+            flat.blobs,
+            ["run42/step001/field.bin", "run42/step001/meta.json"]
+        );
+        assert_eq!(tag.list("run44/", "/").unwrap(), BlobListing::default());
+        // Only sidecars sit under "." here.
+        assert!(tag.list("", ".").unwrap().common_prefixes.is_empty());
+
+        Client::del_tag("rust_list_tag");
+    }
+
+    #[test]
+    fn test_capture_environment() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_env_tag");
+        let env = Client::capture_environment(&tag, "environment.json").unwrap();
+        assert_eq!(env.wrapper, env!("CARGO_PKG_VERSION"));
+        assert!(env
+            .targets
+            .iter()
+            .any(|(name, _)| name.contains("cte_rust_test_target")));
+        let stored = tag.get("environment.json", &GetOptions::default()).unwrap();
+        assert_eq!(stored, env.to_json().as_bytes());
+        // Filling a target doesn't change the capture.
+        tag.put_blob("filler", &[0u8; 4096]);
+        assert_eq!(Client::environment().unwrap(), env);
+
+        Client::del_tag("rust_env_tag");
+    }
+
+    #[test]
+    fn test_memory_targets() {
+        init("").expect("CTE init failed");
+
+        assert!(Client::register_ram_target(
+            "rust_test_ram",
+            8 * 1024 * 1024
+        ));
+        assert!(Client::register_shm_target(
+            "cte_rust_test_shm",
+            8 * 1024 * 1024
+        ));
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        let targets = Client::list_targets();
+        for name in ["rust_test_ram", "/dev/shm/cte_rust_test_shm"] {
+            assert!(targets.iter().any(|t| t.name == name), "{} missing", name);
+        }
+        assert!(!Client::register_shm_target(
+            "../cte_rust_test_escape",
+            4096
+        ));
+
+        let tag = Tag::new("rust_ram_tag");
+        tag.put_blob_with_options("hot", b"pinned", 0, 1.0);
+        assert_eq!(tag.get_blob("hot", 6), b"pinned");
+        Client::del_tag("rust_ram_tag");
+    }
+
+    #[test]
+    fn test_spill_to_lower_tier() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        assert!(Client::register_ram_target("rust_spill_ram", 1024 * 1024));
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        struct ClearPolicy;
+        impl Drop for ClearPolicy {
+            fn drop(&mut self) {
+                clear_spill_policy();
+            }
+        }
+        set_spill_policy(SpillPolicy::default());
+        let _clear = ClearPolicy;
+
+        let targets = Client::list_targets();
+        let ram = targets.iter().find(|t| t.name == "rust_spill_ram").unwrap();
+        // More than every target at the RAM tier's score has left.
+        let hot: u64 = targets
+            .iter()
+            .filter(|t| t.score >= ram.score)
+            .map(|t| t.remaining_space)
+            .sum();
+        let data: Vec<u8> = (0..hot + (1 << 20)).map(|i| (i % 251) as u8).collect();
+        let options = PutOptions {
+            score: Some(ram.score),
+            ..Default::default()
+        };
+        let tag = Tag::new("rust_spill_tag");
+        let chunks = RequestLimits {
+            max_chunk: 256 * 1024,
+            ..Default::default()
+        };
+        limits::scoped(chunks, || tag.put("big", &data, &options)).unwrap();
+
+        let spill = tag
+            .stat_blob("big")
+            .unwrap()
+            .unwrap()
+            .spill
+            .expect("spilled");
+        assert!(spill.score < ram.score, "{:?}", spill);
+        assert!(spill.offset > 0 && spill.offset <= hot, "{:?}", spill);
+        assert_eq!(tag.get_blob("big", data.len() as u64), data);
+        Client::del_tag("rust_spill_tag");
+    }
+
+    #[test]
+    fn test_shm_target_path() {
+        assert_eq!(shm_target_path("tier0").as_deref(), Some("/dev/shm/tier0"));
+        for bad in ["", ".", "..", "a/b", "nul\0"] {
+            assert_eq!(shm_target_path(bad), None, "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_oversized_requests() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_limits_tag");
+        let data: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+        // Limits scoped to this thread, so other tests keep the defaults.
+        let small_chunks = RequestLimits {
+            max_chunk: 4096,
+            ..Default::default()
+        };
+        // Split into three puts and three gets.
+        let got = limits::scoped(small_chunks, || {
+            tag.put_blob("chunked", &data);
+            tag.get_blob("chunked", data.len() as u64)
+        });
+        assert_eq!(got, data);
+
+        let capped = RequestLimits {
+            max_read: Some(100),
+            ..Default::default()
+        };
+        let over = limits::scoped(capped, || tag.try_get_blob("chunked", 101));
+        assert!(matches!(
+            over,
+            Err(CteError::TooLarge {
+                limit: Some(100),
+                ..
+            })
+        ));
+        let huge = tag.try_get_blob("chunked", u64::MAX / 2);
+        assert!(matches!(huge, Err(CteError::TooLarge { limit: None, .. })));
+        Client::del_tag("rust_limits_tag");
+    }
+
+    #[test]
+    fn test_cancel_stage_in() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let dir = std::env::temp_dir().join(format!("cte_cancel_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("big.bin"), vec![7u8; 64 * 1024]).unwrap();
+        // Cancel after the first chunk: the half-staged blob is removed.
+        let token = CancellationToken::new();
+        let stop = token.clone();
+        let options = StageOptions {
+            parallelism: 1,
+            chunk_size: 4096,
+            ..Default::default()
+        }
+        .with_progress(move |_| stop.cancel())
+        .with_cancel(token);
+        let result = Client::stage_in(&dir, "rust_cancel_tag", &options);
+        assert!(matches!(result, Err(CteError::Cancelled)));
+        assert_eq!(Tag::new("rust_cancel_tag").get_blob_size("big.bin"), 0);
+        // Staging out a tag that was never created is NotFound, not empty.
+        Client::del_tag("rust_stage_missing_tag");
+        let missing = Client::stage_out("rust_stage_missing_tag", &dir);
+        assert!(matches!(missing, Err(CteError::NotFound { .. })));
+        assert!(!Client::tag_exists("rust_stage_missing_tag"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        Client::del_tag("rust_cancel_tag");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_stage_non_utf8_paths() {
+        use std::os::unix::ffi::OsStrExt;
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let base = std::env::temp_dir().join(format!("cte_stage_raw_{}", std::process::id()));
+        let (src, dst) = (base.join("in"), base.join("out"));
+        let name = std::ffi::OsStr::from_bytes(b"caf\xe9.bin");
+        std::fs::create_dir_all(src.join("sub")).unwrap();
+        std::fs::write(src.join("sub").join(name), b"latin-1 name").unwrap();
+
+        Client::del_tag("rust_stage_raw_tag");
+        let report = Client::stage_in(&src, "rust_stage_raw_tag", &StageOptions::default());
+        assert_eq!(report.unwrap().files, 1);
+        let tag = Tag::new("rust_stage_raw_tag");
+        assert_eq!(
+            tag.get_contained_blobs_bytes(),
+            vec![b"sub/caf\xe9.bin".to_vec()]
+        );
+        Client::stage_out("rust_stage_raw_tag", &dst).unwrap();
+        assert_eq!(
+            std::fs::read(dst.join("sub").join(name)).unwrap(),
+            b"latin-1 name"
+        );
+
+        std::fs::remove_dir_all(&base).unwrap();
+        Client::del_tag("rust_stage_raw_tag");
+    }
+
+    #[test]
+    fn test_changes_since_is_continuous() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        Client::del_tag("rust_oplog_seq_tag");
+        let tag = Tag::new("rust_oplog_seq_tag");
+        tag.enable_change_log();
+        let start = tag.change_seq();
+        tag.put_blob("a", b"1");
+        tag.put_blob("b", b"22");
+        let first = tag.changes_since(start).unwrap();
+        assert_eq!(first.changes.len(), 2);
+        assert_eq!(first.changes[0].seq, start);
+        assert_eq!(first.next_seq, tag.change_seq());
+
+        tag.put_blob("c", b"333");
+        assert!(tag.del_blob("a"));
+        // The next batch starts exactly where the last one ended.
+        let second = tag.changes_since(first.next_seq).unwrap();
+        assert_eq!(second.changes.len(), 2);
+        assert_eq!(second.changes[0].seq, first.next_seq);
+        assert_eq!(second.changes[1].kind, ChangeKind::Delete);
+        let idle = tag.changes_since(second.next_seq).unwrap();
+        assert!(idle.changes.is_empty());
+        assert_eq!(idle.next_seq, second.next_seq);
+
+        // Reading in batches sees what one read from the start sees.
+        let whole = tag.changes_since(start).unwrap();
+        let batched: Vec<_> = first.changes.into_iter().chain(second.changes).collect();
+        assert_eq!(whole.changes, batched);
+        assert!(whole.changes.windows(2).all(|w| w[0].seq < w[1].seq));
+        Client::del_tag("rust_oplog_seq_tag");
+    }
+
+    #[test]
+    fn test_list_consistent_under_writers() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        Client::del_tag("rust_oplog_list_tag");
+        let tag = Tag::new("rust_oplog_list_tag");
+        tag.enable_change_log();
+        for i in 0..8 {
+            tag.put_blob(&format!("stable_{}", i), b"s");
+        }
+        let done = std::sync::atomic::AtomicBool::new(false);
+        let listings = std::thread::scope(|s| {
+            let writers: Vec<_> = (0..4)
+                .map(|w| {
+                    s.spawn(move || {
+                        let tag = Tag::new("rust_oplog_list_tag");
+                        for j in 0..50 {
+                            let name = format!("w{}_{}", w, j);
+                            tag.put_blob(&name, b"x");
+                            if j % 2 == 1 {
+                                assert!(tag.del_blob(&name));
+                            }
+                        }
+                    })
+                })
+                .collect();
+            let reader = s.spawn(|| {
+                let mut listings = Vec::new();
+                while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                    listings.push(tag.list_consistent().unwrap());
+                }
+                listings
+            });
+            for w in writers {
+                w.join().unwrap();
+            }
+            done.store(true, std::sync::atomic::Ordering::Relaxed);
+            reader.join().unwrap()
+        });
+
+        // Each listing, replayed forward with the changes logged after it,
+        // gives the namespace the writers left behind.
+        let mut end: Vec<String> = tag.blob_names();
+        end.sort();
+        assert_eq!(end.len(), 8 + 4 * 25);
+        assert!(!listings.is_empty());
+        for listing in listings {
+            assert!((0..8).all(|i| listing.blobs.contains(&format!("stable_{}", i))));
+            let mut blobs: std::collections::BTreeSet<String> = listing.blobs.into_iter().collect();
+            for change in tag.changes_since(listing.seq).unwrap().changes {
+                match change.kind {
+                    ChangeKind::Delete => blobs.remove(&change.blob),
+                    _ => blobs.insert(change.blob),
+                };
+            }
+            assert_eq!(blobs.into_iter().collect::<Vec<_>>(), end);
+        }
+        Client::del_tag("rust_oplog_list_tag");
+    }
+
+    #[test]
+    fn test_config_based_init() {
+        // Use CHI_SERVER_CONF like the memorybench does
+        std::env::set_var(
+            "CHI_SERVER_CONF",
+            "/workspace/context-transfer-engine/benchmark/memorybench/cte_config.yaml",
+        );
+        std::env::set_var("CHI_WITH_RUNTIME", "1");
+
+        init("").expect("CTE init failed");
+
+        let tag = Tag::new("config_test_tag");
+        let id = tag.get_tag_id();
+        eprintln!("tag id: major={}, minor={}", id.major, id.minor);
+        assert!(id.major != 0 || id.minor != 0, "tag ID should be non-null");
+
+        let data = b"hello from config test";
+        tag.put_blob("test_blob", data);
+
+        let size = tag.get_blob_size("test_blob");
+        assert_eq!(size, data.len() as u64);
+
+        let got = tag.get_blob("test_blob", size);
+        assert_eq!(got, data);
+
+        Client::del_tag("config_test_tag");
+    }
+
+    #[test]
+    fn test_channel() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let channel = Channel::open("rust_channel_tag").unwrap();
+        channel.tag().put_blob("unmarked", b"x");
+        channel.publish("step1.out", b"one").unwrap();
+        channel.publish("step2.out", b"two").unwrap();
+        assert!(matches!(
+            channel.mark_ready("missing"),
+            Err(CteError::NotFound { .. })
+        ));
+        assert!(!channel.is_closed());
+        let waiting = ChannelOptions {
+            poll_interval: std::time::Duration::from_millis(10),
+            timeout: Some(std::time::Duration::from_millis(50)),
+        };
+        let mut reader = channel.reader(waiting.clone());
+        assert_eq!(reader.next().unwrap().unwrap(), "step1.out");
+        assert_eq!(reader.next().unwrap().unwrap(), "step2.out");
+        assert!(matches!(reader.next(), Some(Err(CteError::Timeout(_)))));
+
+        channel.close().unwrap();
+        let read: Vec<String> = channel.reader(waiting).map(Result::unwrap).collect();
+        assert_eq!(read, ["step1.out", "step2.out"]);
+        assert_eq!(
+            channel.tag().get_contained_blobs().len(),
+            3,
+            "marks stay hidden"
+        );
+
+        Client::del_tag("rust_channel_tag");
+    }
+
+    #[test]
+    fn test_remote_target() {
+        init("").expect("CTE init failed");
+
+        let port = ffi::client_node_port() as u16;
+        let refused = Client::register_remote_target("no-such-node.invalid", 0, 1 << 20);
+        assert!(matches!(refused, Err(CteError::InvalidArgument(_))));
+        let first = ffi::client_hostfile().unwrap().remove(0);
+        let wrong_port = Client::register_remote_target(&first, port.wrapping_add(1), 1 << 20);
+        assert!(matches!(wrong_port, Err(CteError::InvalidArgument(_))));
+        // Past the deadline the shim throws, which is a timeout, not a runtime error.
+        let expired = OpOptions {
+            timeout: std::time::Duration::ZERO,
+        };
+        let mut late = None;
+        let _ = Client::with_op_options(&expired, || {
+            late = Some(Client::register_remote_target(&first, port, 1 << 20));
+        });
+        assert!(matches!(late, Some(Err(CteError::Timeout(_)))));
+        let name = Client::register_remote_target(&first, port, 16 * 1024 * 1024).unwrap();
+        assert_eq!(name, format!("{}:{}", first, port));
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        assert!(Client::list_targets().iter().any(|t| t.name == name));
+    }
+
+    #[test]
+    fn test_s3_target() {
+        init("").expect("CTE init failed");
+
+        // Nothing listens on the discard port, so the runtime's probe fails
+        // (or the device isn't built in); either way no target is added.
+        let credentials = S3Credentials {
+            access_key_id: "AKIDEXAMPLE".into(),
+            secret_access_key: "wJalrXUtnFEMI".into(),
+            session_token: None,
+            region: "us-east-1".into(),
+            endpoint: Some("http://127.0.0.1:9".into()),
+        };
+        let unreachable = Client::register_s3_target("cold-tier", "cte", &credentials, 1 << 30);
+        assert!(matches!(
+            unreachable,
+            Err(CteError::Runtime { .. }) | Err(CteError::Unsupported(_))
+        ));
+        assert!(!Client::list_targets()
+            .iter()
+            .any(|t| t.name == "s3://cold-tier/cte"));
+    }
+
+    #[test]
+    fn test_pin_blob() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_pin_tag");
+        tag.put_blob("hot", b"needed soon");
+        let _lease = tag.pin_blob("hot", 1.0).unwrap();
+        let stat = tag.stat_blob("hot").unwrap().unwrap();
+        assert_eq!(stat.pinned, Some(1.0));
+        assert_eq!(stat.score, 1.0);
+        // Neither a move nor a put's score takes it off its tier.
+        tag.reorganize_blob("hot", 0.1);
+        let options = PutOptions {
+            score: Some(0.1),
+            ..Default::default()
+        };
+        tag.put("hot", b"rewritten", &options).unwrap();
+        assert_eq!(tag.get_blob_score("hot"), 1.0);
+
+        assert!(tag.unpin_blob("hot").unwrap());
+        assert!(!tag.unpin_blob("hot").unwrap());
+        assert_eq!(tag.stat_blob("hot").unwrap().unwrap().pinned, None);
+        tag.reorganize_blob("hot", 0.1);
+        assert!(tag.get_blob_score("hot") < 1.0);
+        assert!(matches!(
+            tag.pin_blob("missing", 1.0),
+            Err(CteError::NotFound { .. })
+        ));
+        assert!(matches!(
+            tag.pin_blob("hot", 1.5),
+            Err(CteError::InvalidArgument(_))
+        ));
+
+        Client::del_tag("rust_pin_tag");
+    }
+
+    #[test]
+    fn test_auto_tier() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let policy = AutoTierPolicy {
+            interval: std::time::Duration::from_millis(100),
+            hot_heat: 3.0,
+            ..Default::default()
+        };
+        let task = Client::start_auto_tier(policy.clone()).unwrap();
+        assert!(Client::start_auto_tier(policy).is_err());
+        let tag = Tag::new("rust_autotier_tag");
+        let options = PutOptions {
+            score: Some(0.0),
+            ..Default::default()
+        };
+        tag.put("hot", b"read often", &options).unwrap();
+        for _ in 0..3 {
+            tag.get_blob("hot", 10);
+        }
+        std::thread::sleep(std::time::Duration::from_millis(500));
+        assert!(tag.get_blob_score("hot") > 0.9);
+        drop(task);
+
+        Client::del_tag("rust_autotier_tag");
+    }
+
+    #[test]
+    fn test_blob_summary() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_summary_tag");
+        tag.put_blob("run/0.csv", b"step,loss\n1,0.9\n");
+        assert_eq!(tag.blob_summary("run/0.csv").unwrap(), None);
+        let summary = BlobSummary {
+            rows: Some(1),
+            ..Default::default()
+        };
+        tag.set_blob_summary("run/0.csv", &summary).unwrap();
+        assert_eq!(
+            tag.blob_summary("run/0.csv").unwrap(),
+            Some(summary.clone())
+        );
+        let summaries = tag.summaries("run/").unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries["run/0.csv"], summary);
+        // A write into the blob makes the summary stale.
+        let options = PutOptions {
+            offset: 10,
+            ..Default::default()
+        };
+        tag.put("run/0.csv", b"2", &options).unwrap();
+        assert_eq!(tag.blob_summary("run/0.csv").unwrap(), None);
+        assert!(matches!(
+            tag.set_blob_summary("missing", &summary),
+            Err(CteError::NotFound { .. })
+        ));
+
+        Client::del_tag("rust_summary_tag");
+    }
+
+    #[test]
+    fn test_write_back() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_writeback_tag");
+        let options = WriteBackOptions {
+            mode: WriteBackMode::Manual,
+            ..Default::default()
+        };
+        tag.enable_write_back(options).unwrap();
+        assert!(tag.write_back_enabled());
+        for (i, sample) in [b"s0;", b"s1;", b"s2;"].iter().enumerate() {
+            tag.put_blob_with_options("sensor", *sample, 3 * i as u64, 1.0);
+        }
+        assert_eq!(tag.write_back_pending(), 9);
+        assert_eq!(tag.get_blob_size("sensor"), 0);
+        // A read of the blob sends its writes first.
+        assert_eq!(tag.get_blob("sensor", 9), b"s0;s1;s2;");
+        assert_eq!(tag.write_back_pending(), 0);
+
+        tag.put_blob("other", b"later");
+        assert_eq!(tag.flush().unwrap(), 5);
+        assert_eq!(tag.get_blob_size("other"), 5);
+        tag.disable_write_back().unwrap();
+        assert!(!tag.write_back_enabled());
+
+        Client::del_tag("rust_writeback_tag");
+    }
+
+    #[test]
+    fn test_read_cache() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        Client::enable_read_cache(ReadCacheOptions::default());
+        let tag = Tag::new("rust_readcache_tag");
+        tag.put_blob("config.json", b"{\"v\":1}");
+        let opts = GetOptions::default();
+        assert_eq!(tag.get("config.json", &opts).unwrap(), b"{\"v\":1}");
+        assert_eq!(Client::read_cache_size(), 7);
+        assert_eq!(tag.get("config.json", &opts).unwrap(), b"{\"v\":1}");
+        // A put through another handle drops the cached read.
+        Tag::from_id(tag.get_tag_id()).put_blob("config.json", b"{\"v\":2}");
+        assert_eq!(Client::read_cache_size(), 0);
+        assert_eq!(tag.get("config.json", &opts).unwrap(), b"{\"v\":2}");
+        Client::disable_read_cache();
+        Client::del_tag("rust_readcache_tag");
+    }
+
+    #[test]
+    fn test_adopt_files() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let dir = std::path::Path::new("/tmp/cte_rust_adopt_dir");
+        std::fs::create_dir_all(dir.join("run1")).unwrap();
+        std::fs::write(dir.join("run1/out.dat"), b"existing output").unwrap();
+        let report = Client::adopt_files(dir, "rust_adopt_tag").unwrap();
+        assert_eq!((report.adopted, report.bytes), (1, 15));
+
+        let tag = Tag::new("rust_adopt_tag");
+        let stat = tag.stat_blob("run1/out.dat").unwrap().unwrap();
+        assert_eq!(stat.size, 15);
+        assert!(stat.file.is_some());
+        let opts = GetOptions {
+            offset: 9,
+            ..Default::default()
+        };
+        assert_eq!(tag.get("run1/out.dat", &opts).unwrap(), b"output");
+
+        // Adopting again only picks up what is new.
+        std::fs::write(dir.join("new.dat"), b"n").unwrap();
+        let report = Client::adopt_files(dir, "rust_adopt_tag").unwrap();
+        assert_eq!((report.adopted, report.unchanged), (1, 1));
+
+        assert!(tag.materialize_blob("run1/out.dat").unwrap().is_some());
+        assert_eq!(tag.adopted_file("run1/out.dat").unwrap(), None);
+        assert_eq!(tag.get_blob_size("run1/out.dat"), 15);
+
+        Client::del_tag("rust_adopt_tag");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_map_blob() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_mmap_tag");
+        let data: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+        let generation = tag.put("frame", &data, &PutOptions::default()).unwrap();
+        // Mapped or buffered, the view holds the data. Safety: nothing writes
+        // the tag while the view lives.
+        let view = unsafe { tag.map_blob("frame") }.unwrap();
+        assert_eq!(&view[..], &data[..]);
+        assert_eq!(view.generation(), generation);
+        assert!(matches!(
+            unsafe { tag.map_blob("missing") },
+            Err(CteError::NotFound { .. })
+        ));
+        drop(view);
+
+        Client::del_tag("rust_mmap_tag");
+    }
+
+    #[test]
+    fn test_vectored_io() {
+        use std::io::{IoSlice, IoSliceMut};
+
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_vectored_tag");
+        let rows: Vec<Vec<u8>> = (0..4u8).map(|r| vec![r; 16]).collect();
+        let iovecs: Vec<IoSlice> = rows.iter().map(|r| IoSlice::new(r)).collect();
+        tag.put_blob_vectored("slab", &iovecs, 8).unwrap();
+        assert_eq!(tag.get_blob_size("slab"), 8 + 64);
+        assert_eq!(tag.get_blob_with_offset("slab", 16, 24), vec![1; 16]);
+
+        // Read the middle two rows back into buffers of other sizes.
+        let (mut a, mut b) = ([0u8; 10], [0u8; 22]);
+        let mut out = [IoSliceMut::new(&mut a), IoSliceMut::new(&mut b)];
+        tag.get_blob_vectored("slab", &mut out, 24).unwrap();
+        assert_eq!(a, [1; 10]);
+        assert_eq!(b[..6], [1; 6]);
+        assert_eq!(b[6..], [2; 16]);
+        assert!(tag
+            .get_blob_vectored("missing", &mut [IoSliceMut::new(&mut a)], 0)
+            .is_err());
+
+        Client::del_tag("rust_vectored_tag");
+    }
+
+    #[test]
+    fn test_expiry_warning() {
+        use std::io::{Read, Write};
+        use std::time::Duration;
+
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(Duration::from_millis(200));
+
+        // A webhook that records the one request it gets.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/expiring", listener.local_addr().unwrap());
+        let hook = std::thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut request = vec![0u8; 4096];
+            let n = conn.read(&mut request).unwrap();
+            conn.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+            String::from_utf8_lossy(&request[..n]).into_owned()
+        });
+        set_expiry_warning(Duration::from_secs(3600), Some(&url)).unwrap();
+
+        let tag = Tag::new("rust_expiry_warning_tag");
+        let soon = PutOptions {
+            ttl: Some(Duration::from_secs(600)),
+            ..Default::default()
+        };
+        tag.put("soon", b"x", &soon).unwrap();
+        tag.put("later", b"y", &PutOptions::default()).unwrap();
+        tag.set_blob_ttl("later", Duration::from_secs(7200))
+            .unwrap();
+
+        let events = Client::subscribe(
+            EventFilter::new()
+                .tag("rust_expiry_warning_tag")
+                .kinds(&[EventKind::BlobExpiring]),
+        );
+        tag.expire_blobs(&BulkOptions::default());
+        tag.expire_blobs(&BulkOptions::default());
+        match events.recv_timeout(Duration::from_secs(1)) {
+            Some(Event::BlobExpiring { blob, .. }) => assert_eq!(blob, "soon"),
+            other => panic!("expected a warning, got {:?}", other),
+        }
+        // Once per expiry time.
+        assert_eq!(events.recv_timeout(Duration::from_millis(100)), None);
+        let request = hook.join().unwrap();
+        assert!(request.starts_with("POST /expiring HTTP/1.1"));
+        assert!(request.contains("\"blob\":\"soon\""));
+
+        clear_expiry_warning();
+        Client::del_tag("rust_expiry_warning_tag");
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn test_typed_objects() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize, CteBlob)]
+        struct Particle {
+            id: u64,
+            pos: [f32; 3],
+        }
+        #[derive(Debug, serde::Serialize, serde::Deserialize, CteBlob)]
+        struct Tracer {
+            id: u64,
+        }
+        assert_eq!(Particle::SCHEMA, "Particle{id:u64,pos:[f32;3]}");
+
+        let tag = Tag::new("rust_object_tag");
+        let p = Particle {
+            id: 7,
+            pos: [1.0, 2.0, 3.0],
+        };
+        tag.put_object("plain", &p).unwrap();
+        let size = tag.get_blob_size("plain");
+        assert!(tag.get_blob("plain", size).starts_with(b"{\"id\":7,"));
+        assert_eq!(tag.get_object::<Particle>("plain").unwrap(), p);
+        let attrs = tag.get_blob_attrs("plain").unwrap();
+        assert!(attrs.contains(&(FORMAT_ATTR.to_string(), "json".to_string())));
+
+        tag.put_typed("typed", &p, ObjectFormat::Json).unwrap();
+        assert_eq!(tag.get_typed::<Particle>("typed").unwrap(), p);
+        assert!(matches!(
+            tag.get_typed::<Tracer>("typed"),
+            Err(CteError::SchemaMismatch { .. })
+        ));
+        // `put_object` records no schema.
+        assert!(matches!(
+            tag.get_typed::<Particle>("plain"),
+            Err(CteError::SchemaMismatch { .. })
+        ));
+
+        Client::del_tag("rust_object_tag");
+    }
+
+    #[test]
+    fn test_webhook_alerts() {
+        use std::io::{Read, Write};
+        use std::time::Duration;
+
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(Duration::from_millis(200));
+
+        // A webhook that fails the first request and records the second.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/alerts", listener.local_addr().unwrap());
+        let hook = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for status in ["503 Service Unavailable", "200 OK"] {
+                let (mut conn, _) = listener.accept().unwrap();
+                let mut request = vec![0u8; 4096];
+                let n = conn.read(&mut request).unwrap();
+                write!(conn, "HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status).unwrap();
+                requests.push(String::from_utf8_lossy(&request[..n]).into_owned());
+            }
+            requests
+        });
+        let options = WebhookOptions {
+            kinds: vec![AlertKind::Expired],
+            secret: Some(b"hook-secret".to_vec()),
+            initial_backoff: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let sink = Client::start_webhook(&url, options).unwrap();
+
+        let tag = Tag::new("rust_webhook_tag");
+        let short = PutOptions {
+            ttl: Some(Duration::from_millis(20)),
+            ..Default::default()
+        };
+        tag.put("scratch", b"temporary", &short).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        tag.expire_blobs(&BulkOptions::default());
+
+        let requests = hook.join().unwrap();
+        let request = &requests[1];
+        let (head, body) = request.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("POST /alerts HTTP/1.1"));
+        assert!(head.contains("X-CTE-Alert: expired"));
+        assert!(body.contains("\"blob\":\"scratch\""));
+        let mac = checksum::hmac_sha256(b"hook-secret", body.as_bytes());
+        let hex: String = mac.iter().map(|b| format!("{:02x}", b)).collect();
+        assert!(head.contains(&format!("X-CTE-Signature: sha256={}", hex)));
+        while sink.delivered() == 0 {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(sink.dropped(), 0);
+        drop(sink);
+        assert!(Client::start_webhook("https://example.com/", WebhookOptions::default()).is_err());
+
+        Client::del_tag("rust_webhook_tag");
+    }
+
+    #[cfg(feature = "arrow")]
+    #[test]
+    fn test_record_batches() {
+        use arrow_array::{ArrayRef, Float64Array, Int64Array, RecordBatch};
+        use std::sync::Arc;
+
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let batch = |steps: Vec<i64>, energy: Vec<Option<f64>>| {
+            RecordBatch::try_from_iter_with_nullable([
+                ("step", Arc::new(Int64Array::from(steps)) as ArrayRef, false),
+                (
+                    "energy",
+                    Arc::new(Float64Array::from(energy)) as ArrayRef,
+                    true,
+                ),
+            ])
+            .unwrap()
+        };
+        let batches = [
+            batch(vec![0, 1], vec![Some(1.5), None]),
+            batch(vec![2], vec![Some(0.25)]),
+        ];
+
+        let tag = Tag::new("rust_arrow_tag");
+        tag.put_record_batches("run", &batches).unwrap();
+        assert_eq!(tag.get_record_batches("run").unwrap(), batches);
+        let attrs = tag.get_blob_attrs("run").unwrap();
+        assert!(attrs.contains(&(ARROW_ROWS_ATTR.to_string(), "3".to_string())));
+        assert!(attrs.contains(&(
+            ARROW_SCHEMA_ATTR.to_string(),
+            "step: Int64, energy: Float64?".to_string()
+        )));
+
+        assert!(tag.put_record_batches("empty", &[]).is_err());
+        tag.put("junk", b"not arrow", &PutOptions::default())
+            .unwrap();
+        assert!(matches!(
+            tag.get_record_batches("junk"),
+            Err(CteError::InvalidArgument(_))
+        ));
+
+        Client::del_tag("rust_arrow_tag");
+    }
+
+    #[test]
+    fn test_readahead() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_readahead_tag");
+        assert_eq!(tag.readahead(), ReadAhead::None);
+        tag.set_readahead(ReadAhead::Sequential(4));
+        assert_eq!(
+            Tag::new("rust_readahead_tag").readahead(),
+            ReadAhead::Sequential(4)
+        );
+
+        let data: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+        tag.put("scan", &data, &PutOptions::default()).unwrap();
+        let read = |offset: u64| {
+            let options = GetOptions {
+                offset,
+                size: Some(4096),
+                ..Default::default()
+            };
+            tag.get("scan", &options).unwrap()
+        };
+        for offset in (0..data.len() as u64).step_by(4096) {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            assert_eq!(read(offset), data[offset as usize..][..4096]);
+        }
+
+        // A write drops what was read ahead of it.
+        read(0);
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let options = PutOptions {
+            offset: 4096,
+            ..Default::default()
+        };
+        tag.put("scan", &[9; 4096], &options).unwrap();
+        assert_eq!(read(4096), [9; 4096]);
+
+        tag.set_readahead(ReadAhead::Adaptive);
+        for offset in (0..data.len() as u64).step_by(4096).skip(2) {
+            assert_eq!(read(offset), data[offset as usize..][..4096]);
+        }
+        tag.set_readahead(ReadAhead::None);
+        assert_eq!(tag.readahead(), ReadAhead::None);
+
+        Client::del_tag("rust_readahead_tag");
+    }
+
+    #[test]
+    fn test_memory_pressure() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        Client::enable_read_cache(ReadCacheOptions::default());
+        let tag = Tag::new("rust_pressure_tag");
+        tag.put("cached", &[5; 4096], &PutOptions::default())
+            .unwrap();
+        tag.get("cached", &GetOptions::default()).unwrap();
+        assert_eq!(Client::read_cache_size(), 4096);
+
+        // Any stall at all counts, so the watch shrinks at its first look.
+        let options = MemoryPressureOptions {
+            psi_threshold: 0.0,
+            usage_ratio: Some(0.0),
+            poll_interval: std::time::Duration::from_millis(20),
+        };
+        match Client::watch_memory_pressure(options) {
+            Ok(watch) => {
+                std::thread::sleep(std::time::Duration::from_millis(200));
+                assert!(watch.shrinks() > 0);
+                assert_eq!(Client::read_cache_size(), 0);
+            }
+            Err(CteError::Unsupported(_)) => {}
+            Err(e) => panic!("{}", e),
+        }
+
+        Client::disable_read_cache();
+        Client::del_tag("rust_pressure_tag");
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parquet() {
+        use arrow_array::{ArrayRef, Int64Array, RecordBatch};
+        use std::sync::Arc;
+
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let batch = |from: i64| {
+            let steps: Vec<i64> = (from..from + 1000).collect();
+            RecordBatch::try_from_iter_with_nullable([(
+                "step",
+                Arc::new(Int64Array::from(steps)) as ArrayRef,
+                false,
+            )])
+            .unwrap()
+        };
+        let tag = Tag::new("rust_parquet_tag");
+        tag.put(
+            "out.parquet",
+            b"an older, longer blob",
+            &PutOptions::default(),
+        )
+        .unwrap();
+        let mut writer = tag
+            .parquet_writer("out.parquet", batch(0).schema())
+            .unwrap();
+        writer.write(&batch(0)).unwrap();
+        writer.write(&batch(1000)).unwrap();
+        let size = writer.close().unwrap();
+        assert_eq!(tag.get_blob_size("out.parquet"), size);
+        let attrs = tag.get_blob_attrs("out.parquet").unwrap();
+        assert!(attrs.contains(&(ARROW_ROWS_ATTR.to_string(), "2000".to_string())));
+
+        let data = tag.get("out.parquet", &GetOptions::default()).unwrap();
+        assert!(data.starts_with(b"PAR1") && data.ends_with(b"PAR1"));
+        let rows: usize = tag
+            .parquet_reader("out.parquet")
+            .unwrap()
+            .map(|b| b.unwrap().num_rows())
+            .sum();
+        assert_eq!(rows, 2000);
+        assert!(matches!(
+            tag.parquet_reader("missing.parquet"),
+            Err(CteError::NotFound { .. })
+        ));
+
+        Client::del_tag("rust_parquet_tag");
+    }
+
+    #[test]
+    fn test_pin_leases() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_pin_lease_tag");
+        for name in ["a", "b", "c"] {
+            tag.put_blob(name, b"data");
+        }
+        let lease = tag.pin_blob("a", 1.0).unwrap();
+        assert!(lease.is_held());
+        let pins: Vec<PinInfo> = Client::list_pins()
+            .into_iter()
+            .filter(|p| p.tag == "rust_pin_lease_tag")
+            .collect();
+        assert_eq!(pins.len(), 1);
+        assert_eq!(pins[0].pid, Some(std::process::id()));
+        assert!(!pins[0].stale);
+        drop(lease);
+        assert_eq!(tag.stat_blob("a").unwrap().unwrap().pinned, None);
+
+        // A kept pin outlives its lease.
+        assert!(tag.pin_blob("b", 1.0).unwrap().persist().unwrap());
+        assert_eq!(tag.stat_blob("b").unwrap().unwrap().pinned, Some(1.0));
+
+        // A lease its holder stopped renewing no longer pins, and operators
+        // can break it.
+        let lease = tag.pin_blob("c", 1.0).unwrap();
+        let mut meta = tag.load_meta("c").unwrap().unwrap();
+        meta.pin_lease.as_mut().unwrap().expires_ms = 1;
+        tag.store_meta("c", &meta).unwrap();
+        assert_eq!(tag.stat_blob("c").unwrap().unwrap().pinned, None);
+        let stale = Client::list_pins()
+            .into_iter()
+            .find(|p| p.tag == "rust_pin_lease_tag" && p.blob == "c")
+            .unwrap();
+        assert!(stale.stale);
+        assert!(Client::break_pin("rust_pin_lease_tag", "c").unwrap());
+        assert!(!lease.release().unwrap());
+        assert!(Client::break_pin("rust_pin_lease_tag", "b").unwrap());
+
+        Client::del_tag("rust_pin_lease_tag");
+    }
+
+    #[cfg(feature = "datafusion")]
+    #[test]
+    fn test_table_provider() {
+        use arrow_array::{ArrayRef, Int64Array, RecordBatch};
+        use datafusion::catalog::TableProvider;
+        use datafusion::prelude::SessionContext;
+        use std::sync::Arc;
+
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let batch = |rows: i64| {
+            RecordBatch::try_from_iter_with_nullable([(
+                "step",
+                Arc::new(Int64Array::from((0..rows).collect::<Vec<_>>())) as ArrayRef,
+                false,
+            )])
+            .unwrap()
+        };
+        let tag = Tag::new("rust_table_tag");
+        tag.put_record_batches("run1.arrow", &[batch(2)]).unwrap();
+        tag.set_blob_attrs("run1.arrow", &[("run", "1")]).unwrap();
+        tag.put_record_batches("run2.arrow", &[batch(3)]).unwrap();
+        tag.set_blob_attrs("run2.arrow", &[("run", "2")]).unwrap();
+        let mut writer = tag
+            .parquet_writer("run3.parquet", batch(1).schema())
+            .unwrap();
+        writer.write(&batch(4)).unwrap();
+        writer.close().unwrap();
+        tag.set_blob_attrs("run3.parquet", &[("run", "3")]).unwrap();
+        tag.put("notes.txt", b"not a table", &PutOptions::default())
+            .unwrap();
+
+        let options = CteTableOptions {
+            pattern: r"run.*".into(),
+            attr_columns: vec!["run".into()],
+            ..Default::default()
+        };
+        let provider = CteTableProvider::try_new("rust_table_tag", options).unwrap();
+        let columns: Vec<String> = provider
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| f.name().clone())
+            .collect();
+        assert_eq!(columns, ["step", "run"]);
+        let ctx = SessionContext::new();
+        ctx.register_table("steps", Arc::new(provider)).unwrap();
+        let count = |sql: &str| {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            let batches = runtime
+                .block_on(async { ctx.sql(sql).await?.collect().await })
+                .unwrap();
+            let column = batches[0].column(0);
+            column
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap()
+                .value(0)
+        };
+        assert_eq!(count("SELECT count(*) FROM steps"), 9);
+        assert_eq!(
+            count("SELECT count(*) FROM steps WHERE run IN ('1', '3')"),
+            6
+        );
+        assert_eq!(
+            count("SELECT count(*) FROM steps WHERE run <> '2' AND step > 1"),
+            2
+        );
+
+        let missing = CteTableOptions {
+            pattern: r"none.*".into(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            CteTableProvider::try_new("rust_table_tag", missing),
+            Err(CteError::InvalidArgument(_))
+        ));
+
+        Client::del_tag("rust_table_tag");
+    }
+
+    #[cfg(feature = "zarr")]
+    #[test]
+    fn test_zarr_store() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let store = ZarrStore::open("rust_zarr_tag").unwrap();
+        store.create_group("").unwrap();
+        store.set("notes", b"a longer first value").unwrap();
+        store.set("notes", b"short").unwrap();
+        assert_eq!(store.get("notes").unwrap().unwrap(), b"short");
+        assert_eq!(
+            store.get_range("notes", 1, Some(3)).unwrap().unwrap(),
+            b"hor"
+        );
+        assert_eq!(store.get("missing").unwrap(), None);
+
+        let meta = ZarrArrayMeta::new(&[4, 6], &[2, 4], ZarrDataType::UInt16);
+        let array = store.create_array("temp", meta.clone()).unwrap();
+        assert_eq!(array.grid_shape(), [2, 2]);
+        assert_eq!(array.chunk_key(&[1, 0]).unwrap(), "temp/c/1/0");
+        let chunk: Vec<u8> = (0..8u16).flat_map(|v| v.to_le_bytes()).collect();
+        array.write_chunk(&[0, 1], &chunk).unwrap();
+        assert!(array.write_chunk(&[2, 0], &chunk).is_err());
+        assert!(array.write_chunk(&[0, 0], &chunk[1..]).is_err());
+
+        let array = store.open_array("temp").unwrap();
+        assert_eq!(array.meta(), &meta);
+        assert_eq!(array.read_chunk(&[0, 1]).unwrap(), chunk);
+        assert_eq!(array.read_chunk(&[1, 1]).unwrap(), vec![0; 16]);
+        // Rows 0-1, columns 3-4 touch chunks (0, 0) and (0, 1); only the
+        // second was written.
+        assert_eq!(array.set_region_score(&[0, 3], &[2, 5], 1.0).unwrap(), 1);
+
+        assert_eq!(
+            store.list().unwrap(),
+            ["notes", "temp/c/0/1", "temp/zarr.json", "zarr.json"]
+        );
+        let dir = store.list_dir("").unwrap();
+        assert_eq!(dir.common_prefixes, ["temp/"]);
+        store.erase_prefix("temp/").unwrap();
+        assert!(matches!(
+            store.open_array("temp"),
+            Err(CteError::NotFound { .. })
+        ));
+
+        Client::del_tag("rust_zarr_tag");
+    }
+
+    #[test]
+    fn test_blob_streams() {
+        use std::io::{Read, Seek, SeekFrom, Write};
+
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_stream_tag");
+        assert!(matches!(
+            tag.reader("missing"),
+            Err(CteError::NotFound { .. })
+        ));
+        let mut writer = tag.writer("log").unwrap();
+        writer.write_all(b"hello ").unwrap();
+        writer.write_all(b"world").unwrap();
+        assert!(writer.is_dirty());
+        writer.seek(SeekFrom::Start(0)).unwrap();
+        writer.write_all(b"J").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.len(), 11);
+        drop(writer);
+
+        let mut reader = tag.reader("log").unwrap();
+        let mut text = String::new();
+        reader.read_to_string(&mut text).unwrap();
+        assert_eq!(text, "Jello world");
+        reader.seek(SeekFrom::End(-5)).unwrap();
+        let mut word = [0u8; 8];
+        assert_eq!(reader.read(&mut word).unwrap(), 5);
+        assert_eq!(&word[..5], b"world");
+        assert!(reader.seek(SeekFrom::Current(-20)).is_err());
+
+        Client::del_tag("rust_stream_tag");
+    }
+
+    #[cfg(feature = "hdf5-vfd")]
+    #[test]
+    fn test_hdf5_file() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        // H5F_ACC_RDWR | H5F_ACC_CREAT, then H5F_ACC_RDONLY.
+        let path = "cte://rust_hdf5_tag/sim.h5";
+        assert!(hdf5::File::open(path, 0).is_err());
+        let mut file = hdf5::File::open(path, 0x0011).unwrap();
+        file.write(0, b"\x89HDF\r\n").unwrap();
+        file.write(100, b"data").unwrap();
+        let mut buf = [1u8; 8];
+        file.read(100, &mut buf).unwrap();
+        assert_eq!(buf, *b"data\0\0\0\0");
+        file.flush().unwrap();
+        assert_eq!(file.eof(), 104);
+        drop(file);
+
+        let mut file = hdf5::File::open(path, 0).unwrap();
+        let mut magic = [0u8; 6];
+        file.read(0, &mut magic).unwrap();
+        assert_eq!(&magic, b"\x89HDF\r\n");
+        assert!(file.write(0, b"x").is_err());
+
+        Client::del_tag("rust_hdf5_tag");
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_rest() {
+        use std::io::{Read, Write};
+
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let options = RestOptions {
+            token: Some("rest-token".into()),
+            ..Default::default()
+        };
+        assert!(Client::serve_rest("0.0.0.0:0", &RestOptions::default()).is_err());
+        let rest = Client::serve_rest("127.0.0.1:0", &options).unwrap();
+        let call = |method: &str, path: &str, headers: &str, body: &[u8]| {
+            let mut stream = std::net::TcpStream::connect(rest.local_addr()).unwrap();
+            let head = format!(
+                "{} {} HTTP/1.1\r\nAuthorization: Bearer rest-token\r\n{}\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n",
+                method,
+                path,
+                headers,
+                body.len()
+            );
+            stream.write_all(head.as_bytes()).unwrap();
+            stream.write_all(body).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let blob = "/v1/tags/rust_rest_tag/blobs/runs%2Fa.bin";
+        assert!(call("PUT", blob, "", b"0123456789").starts_with("HTTP/1.1 204"));
+        assert!(call("PUT", &format!("{}?offset=10", blob), "", b"ab").starts_with("HTTP/1.1 204"));
+        assert!(call("GET", blob, "", b"").ends_with("\r\n\r\n0123456789ab"));
+        let partial = call("GET", blob, "Range: bytes=2-4\r\n", b"");
+        assert!(partial.starts_with("HTTP/1.1 206"));
+        assert!(partial.contains("content-range: bytes 2-4/12\r\n"));
+        assert!(partial.ends_with("\r\n\r\n234"));
+        assert!(call("GET", blob, "Range: bytes=20-\r\n", b"").starts_with("HTTP/1.1 416"));
+        let stat = call("GET", "/v1/tags/rust_rest_tag/stat/runs/a.bin", "", b"");
+        assert!(stat.contains("{\"size\":12,"));
+        let listing = call("GET", "/v1/tags/rust_rest_tag?prefix=runs%2F", "", b"");
+        assert!(listing.ends_with("{\"common_prefixes\":[],\"blobs\":[\"runs/a.bin\"]}"));
+        assert!(call("GET", "/v1/query?tag=%5Erust_rest&blob=a", "", b"")
+            .ends_with("[{\"tag\":\"rust_rest_tag\",\"blob\":\"runs/a.bin\"}]"));
+        assert!(call("GET", "/v1/targets", "", b"").contains("\"remaining_bytes\":"));
+        assert!(call("GET", "/v1/tags/missing_rest_tag", "", b"").starts_with("HTTP/1.1 404"));
+        assert!(call("DELETE", blob, "", b"").starts_with("HTTP/1.1 204"));
+        assert!(call("GET", blob, "", b"").starts_with("HTTP/1.1 404"));
+
+        let mut stream = std::net::TcpStream::connect(rest.local_addr()).unwrap();
+        stream
+            .write_all(b"GET /v1/tags HTTP/1.1\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut refused = String::new();
+        stream.read_to_string(&mut refused).unwrap();
+        assert!(refused.starts_with("HTTP/1.1 403"));
+        drop(rest);
+        Client::del_tag("rust_rest_tag");
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_rest_events() {
+        use std::io::{Read, Write};
+
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let rest = Client::serve_rest("127.0.0.1:0", &RestOptions::default()).unwrap();
+        let mut stream = std::net::TcpStream::connect(rest.local_addr()).unwrap();
+        stream
+            .write_all(
+                b"GET /v1/events?tag=rust_ws_.*&blob=keep%2F.*&kinds=blob_put HTTP/1.1\r\n\
+                  Upgrade: websocket\r\nConnection: Upgrade\r\n\
+                  Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                  Sec-WebSocket-Version: 13\r\n\r\n",
+            )
+            .unwrap();
+        let mut head = Vec::new();
+        let mut byte = [0u8; 1];
+        while !head.ends_with(b"\r\n\r\n") {
+            stream.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+        let head = String::from_utf8(head).unwrap();
+        assert!(head.starts_with("HTTP/1.1 101"));
+        assert!(head.contains("sec-websocket-accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_ws_tag");
+        tag.put_blob("skip/a", b"0123");
+        tag.put_blob("keep/b", b"01234567");
+        let mut frame = [0u8; 2];
+        stream.read_exact(&mut frame).unwrap();
+        assert_eq!(frame[0], 0x81);
+        let mut text = vec![0u8; frame[1] as usize];
+        stream.read_exact(&mut text).unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "{\"kind\":\"blob_put\",\"tag\":\"rust_ws_tag\",\"blob\":\"keep/b\",\
+             \"offset\":0,\"size\":8}"
+        );
+
+        let mut bad = std::net::TcpStream::connect(rest.local_addr()).unwrap();
+        bad.write_all(b"GET /v1/events HTTP/1.1\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut refused = String::new();
+        bad.read_to_string(&mut refused).unwrap();
+        assert!(refused.starts_with("HTTP/1.1 426"));
+        drop(stream);
+        drop(rest);
+        Client::del_tag("rust_ws_tag");
+    }
+
+    #[test]
+    fn test_connector_nats() {
+        use std::io::{BufRead, BufReader, Read, Write};
+
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        // A NATS server taking one connection and handing back what is published.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("nats://tok@{}", listener.local_addr().unwrap());
+        let (published, received) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream
+                .write_all(b"INFO {\"server_id\":\"test\"}\r\n")
+                .unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap_or(0) > 0 {
+                let command = line.trim_end().to_string();
+                line.clear();
+                if command == "PING" {
+                    stream.write_all(b"PONG\r\n").unwrap();
+                } else if let Some(args) = command.strip_prefix("PUB ") {
+                    let (subject, len) = args.rsplit_once(' ').unwrap();
+                    let mut payload = vec![0u8; len.parse::<usize>().unwrap() + 2];
+                    reader.read_exact(&mut payload).unwrap();
+                    payload.truncate(payload.len() - 2);
+                    let payload = String::from_utf8(payload).unwrap();
+                    let _ = published.send((subject.to_string(), payload));
+                } else if command.starts_with("CONNECT ") {
+                    assert!(command.contains("\"auth_token\":\"tok\""));
+                }
+            }
+        });
+
+        let options = ConnectorOptions {
+            filter: ConnectorOptions::default().filter.tag("rust_connector_tag"),
+            ..Default::default()
+        };
+        let connector = Client::start_connector(Broker::Nats(url), options).unwrap();
+        let tag = Tag::new("rust_connector_tag");
+        tag.put_blob("b", b"0123");
+        tag.del_blob("b");
+        let timeout = std::time::Duration::from_secs(10);
+        assert_eq!(
+            received.recv_timeout(timeout).unwrap(),
+            (
+                "cte.events.blob_put".to_string(),
+                "{\"kind\":\"blob_put\",\"tag\":\"rust_connector_tag\",\"blob\":\"b\",\
+                 \"offset\":0,\"size\":4}"
+                    .to_string()
+            )
+        );
+        let (subject, _) = received.recv_timeout(timeout).unwrap();
+        assert_eq!(subject, "cte.events.blob_deleted");
+        drop(connector);
+        Client::del_tag("rust_connector_tag");
+    }
+
+    #[test]
+    fn test_try_get_blob() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_try_get_tag");
+        tag.put_blob("b", b"0123456789");
+        assert_eq!(tag.try_get_blob("b", 4).unwrap(), b"0123");
+        assert_eq!(tag.try_get_blob_with_offset("b", 4, 6).unwrap(), b"6789");
+        // A missing blob is an error, not a panic.
+        assert!(tag.try_get_blob("missing", 4).is_err());
+        tag.try_reorganize_blob("b", 0.5).unwrap();
+        Client::del_tag("rust_try_get_tag");
+    }
+
+    #[test]
+    fn test_bulk_dry_run() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_bulk_tag");
+        tag.put_blob("a", b"0123");
+        tag.put_blob("b", b"01234567");
+        let dry_run = BulkOptions { dry_run: true };
+        let report = tag.del_blobs(&["a", "missing"], &dry_run);
+        assert!(report.dry_run);
+        assert_eq!(report.blobs.len(), 1);
+        assert_eq!(report.bytes, 4);
+        assert_eq!(tag.get_blob_size("a"), 4);
+        let report = Client::del_tag_report("rust_bulk_tag", &dry_run).unwrap();
+        assert_eq!(report.bytes, 12);
+        assert!(Client::tag_exists("rust_bulk_tag"));
+
+        // The policy is process-wide: leave other tests' tags at the default
+        // score, and clear it even if an assertion fails.
+        struct ClearPolicy;
+        impl Drop for ClearPolicy {
+            fn drop(&mut self) {
+                clear_placement_policy();
+            }
+        }
+        let tag_id = tag.get_tag_id();
+        set_placement_policy(
+            move |desc: &BlobDescriptor| {
+                if desc.tag_id == tag_id {
+                    0.25
+                } else {
+                    1.0
+                }
+            },
+        );
+        let clear = ClearPolicy;
+        assert_eq!(tag.rebalance(&dry_run).blobs.len(), 2);
+        assert_ne!(tag.get_blob_score("a"), 0.25);
+        drop(clear);
+
+        let report = tag.del_blobs(&["a"], &BulkOptions::default());
+        assert_eq!(report.bytes, 4);
+        assert_eq!(tag.get_blob_size("a"), 0);
+        let report = Client::del_tag_report("rust_bulk_tag", &BulkOptions::default()).unwrap();
+        assert_eq!(report.bytes, 8);
+        assert!(!Client::tag_exists("rust_bulk_tag"));
+        assert!(Client::del_tag_report("rust_bulk_tag", &dry_run).is_none());
+    }
+
+    #[test]
+    fn test_append_blob_fenced() {
+        init("").expect("CTE init failed");
+        Client::register_target("/tmp/cte_rust_test_target", 64 * 1024 * 1024);
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let tag = Tag::new("rust_fenced_tag");
+        assert_eq!(tag.append_blob_fenced("log", b"one", 1).unwrap(), 0);
+        assert_eq!(tag.append_blob_fenced("log", b"two", 2).unwrap(), 3);
+        // The replaced producer is fenced out and its record isn't written.
+        match tag.append_blob_fenced("log", b"old", 1) {
+            Err(CteError::Fenced {
+                producer_epoch: 1,
+                current_epoch: 2,
+                ..
+            }) => {}
+            other => panic!("expected Fenced, got {:?}", other),
+        }
+        assert_eq!(tag.append_blob_fenced("log", b"three", 3).unwrap(), 6);
+        assert_eq!(tag.get_blob("log", 11), b"onetwothree");
+
+        // A stale writer storing the metadata it read earlier doesn't lower the
+        // epoch.
+        tag.store_meta("log", &meta::BlobMeta::default()).unwrap();
+        assert_eq!(tag.load_meta("log").unwrap().unwrap().epoch, 3);
+        assert!(matches!(
+            tag.append_blob_fenced("log", b"old", 2),
+            Err(CteError::Fenced { .. })
+        ));
+        Client::del_tag("rust_fenced_tag");
     }
 }
@@ -0,0 +1,148 @@
+//! Size limits on blob requests.
+//!
+//! The shim stages every get and put through a shared-memory buffer of the
+//! request's full size, allocated from the client's data segment, and the
+//! wrapper then copies a read into a `Vec`. A huge request would otherwise fail
+//! that allocation or abort the process when the `Vec` can't be had. So reads
+//! and writes larger than `RequestLimits::max_chunk` are split into requests of
+//! at most that size, at consecutive offsets. A read larger than
+//! `RequestLimits::max_read`, or whose buffer can't be reserved up front, fails
+//! with `CteError::TooLarge` before anything is sent. A split put isn't atomic:
+//! a reader can see some of its chunks before the rest land.
+
+use std::sync::RwLock;
+
+use crate::CteError;
+
+/// Limits applied to every get and put.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestLimits {
+    /// Largest single request sent to the runtime; larger ones are split.
+    pub max_chunk: u64,
+    /// Largest read the wrapper buffers in memory; `None` for no limit but
+    /// the memory available.
+    pub max_read: Option<u64>,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        Self {
+            max_chunk: 64 << 20,
+            max_read: None,
+        }
+    }
+}
+
+static LIMITS: RwLock<RequestLimits> = RwLock::new(RequestLimits {
+    max_chunk: 64 << 20,
+    max_read: None,
+});
+
+#[cfg(test)]
+thread_local! {
+    /// Limits of this thread only, set by `scoped`.
+    static SCOPED: std::cell::Cell<Option<RequestLimits>> = const { std::cell::Cell::new(None) };
+}
+
+fn normalized(limits: RequestLimits) -> RequestLimits {
+    RequestLimits {
+        max_chunk: limits.max_chunk.max(1),
+        ..limits
+    }
+}
+
+/// Set the process-wide request limits. A `max_chunk` of 0 is taken as 1.
+pub fn set_request_limits(limits: RequestLimits) {
+    *LIMITS.write().unwrap_or_else(|e| e.into_inner()) = normalized(limits);
+}
+
+/// The process-wide request limits.
+pub fn request_limits() -> RequestLimits {
+    #[cfg(test)]
+    if let Some(limits) = SCOPED.get() {
+        return limits;
+    }
+    *LIMITS.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Run `f` with `limits` in place of the process-wide ones on this thread, so
+/// a test can change them without racing the tests running beside it.
+#[cfg(test)]
+pub(crate) fn scoped<R>(limits: RequestLimits, f: impl FnOnce() -> R) -> R {
+    let previous = SCOPED.replace(Some(normalized(limits)));
+    let result = f();
+    SCOPED.set(previous);
+    result
+}
+
+/// `(offset, len)` of the requests covering `size` bytes from `offset`; an
+/// empty request is sent as is, for the runtime to judge.
+pub(crate) fn chunks(offset: u64, size: u64, max_chunk: u64) -> impl Iterator<Item = (u64, u64)> {
+    (0..size.div_ceil(max_chunk).max(1)).map(move |i| {
+        let start = i * max_chunk;
+        (offset + start, max_chunk.min(size - start))
+    })
+}
+
+fn too_large(blob: &str, size: u64, limit: Option<u64>) -> CteError {
+    CteError::TooLarge {
+        blob: blob.to_string(),
+        size,
+        limit,
+    }
+}
+
+/// `TooLarge` if a read of `size` bytes of `blob` is over `max_read`.
+pub(crate) fn check_read(blob: &str, size: u64, limits: &RequestLimits) -> Result<(), CteError> {
+    match limits.max_read {
+        Some(limit) if size > limit => Err(too_large(blob, size, Some(limit))),
+        _ => Ok(()),
+    }
+}
+
+/// A zeroed buffer for a read of `size` bytes of `blob`, or `TooLarge`.
+pub(crate) fn read_buffer(
+    blob: &str,
+    size: u64,
+    limits: &RequestLimits,
+) -> Result<Vec<u8>, CteError> {
+    check_read(blob, size, limits)?;
+    let len = usize::try_from(size).map_err(|_| too_large(blob, size, None))?;
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(len)
+        .map_err(|_| too_large(blob, size, None))?;
+    buf.resize(len, 0);
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_limits() {
+        let split: Vec<_> = chunks(10, 25, 10).collect();
+        assert_eq!(split, [(10, 10), (20, 10), (30, 5)]);
+        assert_eq!(chunks(0, 0, 10).collect::<Vec<_>>(), [(0, 0)]);
+        assert_eq!(chunks(0, 10, 10).collect::<Vec<_>>(), [(0, 10)]);
+
+        let limits = RequestLimits {
+            max_read: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(read_buffer("b", 100, &limits).unwrap().len(), 100);
+        assert!(matches!(
+            read_buffer("b", 101, &limits),
+            Err(CteError::TooLarge {
+                limit: Some(100),
+                ..
+            })
+        ));
+        // Far more than any machine has: refused rather than aborting.
+        let unbounded = RequestLimits::default();
+        assert!(matches!(
+            read_buffer("b", u64::MAX / 2, &unbounded),
+            Err(CteError::TooLarge { limit: None, .. })
+        ));
+    }
+}
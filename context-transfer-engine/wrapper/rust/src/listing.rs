@@ -0,0 +1,221 @@
+//! Directory-style listing of blob names.
+//!
+//! `Tag::list` lists the blobs of a tag whose names start with a prefix, the
+//! way S3's ListObjectsV2 does. With a delimiter, every such name that has the
+//! delimiter after the prefix is rolled up into a common prefix, the name
+//! through that delimiter: listing `run42/` with `/` in a tag of
+//! `run42/step001/field.bin` names returns `run42/step001/` once instead of its
+//! blobs. The runtime filters and rolls up the names, so browsing a large tag a
+//! level at a time only transfers that level; a runtime without
+//! `Capability::ListBlobs` sends every name and the wrapper rolls them up.
+//! Wrapper sidecars are left out as in `get_contained_blobs`.
+
+use std::collections::HashSet;
+
+use crate::handshake::{self, Capability};
+use crate::{ffi, ffi_guard, meta, names, CteError, Tag};
+
+/// One level of a tag's blob names (see `listing`); `BlobListing<Vec<u8>>`
+/// from `Tag::list_bytes` has them exactly as stored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlobListing<N = String> {
+    /// Sorted prefixes that names were rolled up into, each ending with the
+    /// delimiter.
+    pub common_prefixes: Vec<N>,
+    /// Sorted names of the blobs at this level.
+    pub blobs: Vec<N>,
+}
+
+fn is_reserved(name: &[u8]) -> bool {
+    name.starts_with(meta::RESERVED_PREFIX.as_bytes())
+}
+
+/// The names in `tag` under `prefix`, rolled up at `delimiter` by the runtime,
+/// or here if it can't.
+pub(crate) fn list_entries(
+    tag: &Tag,
+    prefix: &[u8],
+    delimiter: &[u8],
+) -> Result<Vec<ffi::ListEntry>, CteError> {
+    if handshake::supports(Capability::ListBlobs) {
+        return ffi_guard::tag_list_blobs(&tag.inner, prefix, delimiter);
+    }
+    let names = ffi::tag_get_contained_blobs(&tag.inner);
+    Ok(roll_up(
+        names.iter().map(|s| s.as_bytes()),
+        prefix,
+        delimiter,
+    ))
+}
+
+/// What the runtime's ListBlobs returns for `names`.
+fn roll_up<'a>(
+    names: impl Iterator<Item = &'a [u8]>,
+    prefix: &[u8],
+    delimiter: &[u8],
+) -> Vec<ffi::ListEntry> {
+    let mut rolled_up = HashSet::new();
+    let mut out = Vec::new();
+    for name in names.filter(|n| n.starts_with(prefix)) {
+        let at = match delimiter.len() {
+            0 => None,
+            len => name[prefix.len()..]
+                .windows(len)
+                .position(|w| w == delimiter),
+        };
+        match at {
+            Some(at) => {
+                let common = name[..prefix.len() + at + delimiter.len()].to_vec();
+                if rolled_up.insert(common.clone()) {
+                    out.push(ffi::ListEntry {
+                        name: common,
+                        prefix: true,
+                    });
+                }
+            }
+            None => out.push(ffi::ListEntry {
+                name: name.to_vec(),
+                prefix: false,
+            }),
+        }
+    }
+    out
+}
+
+impl BlobListing<Vec<u8>> {
+    fn from_entries(entries: Vec<ffi::ListEntry>) -> Self {
+        let mut listing = Self::default();
+        for entry in entries {
+            if is_reserved(&entry.name) {
+                continue;
+            }
+            if entry.prefix {
+                listing.common_prefixes.push(entry.name);
+            } else {
+                listing.blobs.push(entry.name);
+            }
+        }
+        listing.common_prefixes.sort();
+        listing.blobs.sort();
+        listing
+    }
+
+    /// As `String`s, with names that aren't UTF-8 handled as `NamePolicy` says.
+    fn decode(&self) -> Result<BlobListing, CteError> {
+        let policy = names::name_policy();
+        let decode = |names: &[Vec<u8>]| -> Result<Vec<String>, CteError> {
+            let mut out = names::decode_all(names.iter().map(Vec::as_slice), policy)?;
+            out.sort();
+            Ok(out)
+        };
+        Ok(BlobListing {
+            common_prefixes: decode(&self.common_prefixes)?,
+            blobs: decode(&self.blobs)?,
+        })
+    }
+}
+
+impl Tag {
+    /// The blobs under `prefix`, with names rolled up at the first `delimiter`
+    /// after it, or not at all if `delimiter` is empty (see `listing`). Names
+    /// that aren't UTF-8 are returned as `NamePolicy` says.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(tag = %self.name, prefix = prefix), err)
+    )]
+    pub fn list(&self, prefix: &str, delimiter: &str) -> Result<BlobListing, CteError> {
+        self.list_bytes(prefix.as_bytes(), delimiter.as_bytes())?
+            .decode()
+    }
+
+    /// `list` with names exactly as stored.
+    pub fn list_bytes(
+        &self,
+        prefix: &[u8],
+        delimiter: &[u8],
+    ) -> Result<BlobListing<Vec<u8>>, CteError> {
+        let entries = list_entries(self, prefix, delimiter)?;
+        let mut listing = BlobListing::from_entries(entries);
+        // A common prefix that starts the sidecar namespace may hold nothing
+        // but sidecars; keep it only if listing it finds something else.
+        let reserved = meta::RESERVED_PREFIX.as_bytes();
+        let mut kept = Vec::with_capacity(listing.common_prefixes.len());
+        for common in listing.common_prefixes {
+            if reserved.starts_with(&common) {
+                let rest = &reserved[common.len()..];
+                let under = list_entries(self, &common, rest)?;
+                if BlobListing::from_entries(under) == BlobListing::default() {
+                    continue;
+                }
+            }
+            kept.push(common);
+        }
+        listing.common_prefixes = kept;
+        Ok(listing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blob_listing() {
+        let entry = |name: &[u8], prefix| ffi::ListEntry {
+            name: name.to_vec(),
+            prefix,
+        };
+        let raw = BlobListing::from_entries(vec![
+            entry(b"run42/step002/", true),
+            entry(b"run42/step001/", true),
+            entry(b"run42/readme", false),
+            entry(b"run42/\xff", false),
+            entry(b".cte/", true),
+            entry(b".cte/meta/run42/readme", false),
+        ]);
+        assert_eq!(raw.common_prefixes, [b"run42/step001/", b"run42/step002/"]);
+        assert_eq!(raw.blobs, [&b"run42/readme"[..], b"run42/\xff"]);
+        let listing = raw.decode().unwrap();
+        assert_eq!(
+            listing.common_prefixes,
+            ["run42/step001/", "run42/step002/"]
+        );
+        assert_eq!(listing.blobs, ["run42/readme", "run42/\u{fffd}"]);
+    }
+
+    #[test]
+    fn test_roll_up() {
+        let names: [&[u8]; 6] = [
+            b"run42/step001/field.bin",
+            b"run42/step001/mask.bin",
+            b"run42/step002/field.bin",
+            b"run42/readme",
+            b"run43/readme",
+            b"top.txt",
+        ];
+        let listed = |prefix: &[u8], delimiter: &[u8]| {
+            BlobListing::from_entries(roll_up(names.into_iter(), prefix, delimiter))
+        };
+        let level = listed(b"run42/", b"/");
+        assert_eq!(
+            level.common_prefixes,
+            [b"run42/step001/", b"run42/step002/"]
+        );
+        assert_eq!(level.blobs, [b"run42/readme"]);
+        let top = listed(b"", b"/");
+        assert_eq!(top.common_prefixes, [b"run42/", b"run43/"]);
+        assert_eq!(top.blobs, [b"top.txt"]);
+        let flat = listed(b"run42/step001/", b"");
+        assert!(flat.common_prefixes.is_empty());
+        assert_eq!(flat.blobs.len(), 2);
+        let long = listed(b"run42/", b"/f");
+        assert_eq!(
+            long.common_prefixes,
+            [b"run42/step001/f", b"run42/step002/f"]
+        );
+        assert_eq!(
+            long.blobs,
+            [&b"run42/readme"[..], b"run42/step001/mask.bin"]
+        );
+    }
+}
@@ -0,0 +1,499 @@
+//! Wrapper-level blob metadata kept in sidecar blobs.
+//!
+//! The runtime only tracks a blob's name, size, score and placement. Anything the
+//! wrapper needs on top of that is stored in a small sidecar blob in the same tag,
+//! named `META_PREFIX + blob_name`, so every client of the tag sees the same state.
+//! Names under `RESERVED_PREFIX` are hidden from blob listings.
+//!
+//! Encoding: a `u32` payload length followed by `(id: u8, len: u32, bytes)` fields,
+//! all little-endian. Unknown field ids are skipped so older wrappers can read
+//! metadata written by newer ones. The length prefix lets a shorter record be
+//! written over a longer one without truncating the sidecar.
+
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::adopt::FileRef;
+use crate::compress::Compressed;
+use crate::encrypt::Encrypted;
+use crate::pin::Lease;
+use crate::spill::Spill;
+use crate::summary::BlobSummary;
+use crate::{Checksum, CteError, Tag};
+
+/// Prefix for blob names reserved by the wrapper.
+pub(crate) const RESERVED_PREFIX: &str = ".cte/";
+/// Prefix for per-blob metadata sidecars.
+pub(crate) const META_PREFIX: &str = ".cte/meta/";
+/// Tag-level metadata sidecar.
+pub(crate) const TAG_META_NAME: &str = ".cte/tag";
+
+const FIELD_EPOCH: u8 = 1;
+const FIELD_GENERATION: u8 = 2;
+const FIELD_ATTR: u8 = 3;
+const FIELD_CHECKSUM: u8 = 4;
+const FIELD_COMPRESSED: u8 = 5;
+const FIELD_ENCRYPTED: u8 = 6;
+const FIELD_DATA_KEY: u8 = 7;
+const FIELD_EXPIRES: u8 = 8;
+const FIELD_SPILL: u8 = 9;
+const FIELD_PINNED: u8 = 10;
+const FIELD_SUMMARY: u8 = 11;
+const FIELD_REFERENCE: u8 = 12;
+const FIELD_WRITTEN: u8 = 13;
+const FIELD_PIN_LEASE: u8 = 14;
+const FIELD_PENDING_TXN: u8 = 15;
+
+/// Serializes read-modify-write cycles on sidecars within this process. Sidecar
+/// updates from different processes are not atomic with respect to each other,
+/// except that `store_meta` never lowers the stored producer epoch.
+static META_LOCK: Mutex<()> = Mutex::new(());
+
+pub(crate) fn meta_lock() -> MutexGuard<'static, ()> {
+    META_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Returns true if `name` is reserved for wrapper bookkeeping.
+pub(crate) fn is_reserved(name: &str) -> bool {
+    name.starts_with(RESERVED_PREFIX)
+}
+
+/// Returns true if a tag named `name` exists, reserved or not.
+pub(crate) fn tag_exists(name: &str) -> bool {
+    !crate::ffi::client_tag_query(&crate::events::exact(name), 1).is_empty()
+}
+
+/// Wrapper metadata for a single blob.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct BlobMeta {
+    /// Highest producer epoch that has appended to the blob.
+    pub epoch: u64,
+    /// Number of writes made to the blob; 0 means it has never been written.
+    pub generation: u64,
+    /// User key-value attributes.
+    pub attrs: BTreeMap<String, String>,
+    /// Checksum of the whole blob as of the last write, if one was requested.
+    pub checksum: Option<Checksum>,
+    /// Set if the stored data is compressed (see `compress`).
+    pub compressed: Option<Compressed>,
+    /// Set if the stored data is encrypted (see `encrypt`).
+    pub encrypted: Option<Encrypted>,
+    /// Expiry time in milliseconds since the Unix epoch; 0 means no TTL.
+    pub expires_ms: u64,
+    /// Set if writes spilled to a lower tier (see `spill`).
+    pub spill: Option<Spill>,
+    /// Score the blob is pinned at (see `pin`).
+    pub pinned: Option<f32>,
+    /// The lease holding `pinned`; `None` for a pin with no lease.
+    pub pin_lease: Option<Lease>,
+    /// What the summarizers found in the data (see `summary`).
+    pub summary: Option<BlobSummary>,
+    /// Set if the data is in a file the blob was adopted from (see `adopt`).
+    pub reference: Option<FileRef>,
+    /// Time of the last write in milliseconds since the Unix epoch; 0 if not
+    /// recorded (see `settle`).
+    pub written_ms: u64,
+    /// ID of a committed transaction that has yet to replace or delete the
+    /// blob; readers finish it first (see `txn`).
+    pub pending_txn: Option<String>,
+}
+
+impl BlobMeta {
+    /// True if the stored bytes aren't the caller's data as written, so the blob
+    /// can only be rewritten whole.
+    pub(crate) fn is_transformed(&self) -> bool {
+        self.compressed.is_some() || self.encrypted.is_some() || self.reference.is_some()
+    }
+}
+
+impl BlobMeta {
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut w = FieldWriter::default();
+        w.u64(FIELD_EPOCH, self.epoch);
+        w.u64(FIELD_GENERATION, self.generation);
+        for (k, v) in &self.attrs {
+            w.pair(FIELD_ATTR, k, v);
+        }
+        if let Some(sum) = &self.checksum {
+            w.bytes(FIELD_CHECKSUM, &sum.encode());
+        }
+        if let Some(c) = &self.compressed {
+            w.bytes(FIELD_COMPRESSED, &c.encode());
+        }
+        if let Some(e) = &self.encrypted {
+            w.bytes(FIELD_ENCRYPTED, &e.encode());
+        }
+        if self.expires_ms != 0 {
+            w.u64(FIELD_EXPIRES, self.expires_ms);
+        }
+        if let Some(spill) = &self.spill {
+            w.bytes(FIELD_SPILL, &spill.encode());
+        }
+        if let Some(score) = self.pinned {
+            w.bytes(FIELD_PINNED, &score.to_le_bytes());
+        }
+        if let Some(lease) = &self.pin_lease {
+            w.bytes(FIELD_PIN_LEASE, &lease.encode());
+        }
+        if let Some(summary) = &self.summary {
+            w.bytes(FIELD_SUMMARY, &summary.encode());
+        }
+        if let Some(file) = &self.reference {
+            w.bytes(FIELD_REFERENCE, &file.encode());
+        }
+        if self.written_ms != 0 {
+            w.u64(FIELD_WRITTEN, self.written_ms);
+        }
+        if let Some(id) = &self.pending_txn {
+            w.bytes(FIELD_PENDING_TXN, id.as_bytes());
+        }
+        w.finish()
+    }
+
+    pub(crate) fn decode(buf: &[u8]) -> Result<Self, String> {
+        let mut meta = BlobMeta::default();
+        for (id, value) in FieldReader::new(buf)? {
+            match id {
+                FIELD_EPOCH => meta.epoch = read_u64(value)?,
+                FIELD_GENERATION => meta.generation = read_u64(value)?,
+                FIELD_ATTR => {
+                    let (k, v) = read_pair(value)?;
+                    meta.attrs.insert(k, v);
+                }
+                FIELD_CHECKSUM => meta.checksum = Some(Checksum::decode(value)?),
+                FIELD_COMPRESSED => meta.compressed = Some(Compressed::decode(value)?),
+                FIELD_ENCRYPTED => meta.encrypted = Some(Encrypted::decode(value)?),
+                FIELD_EXPIRES => meta.expires_ms = read_u64(value)?,
+                FIELD_SPILL => meta.spill = Some(Spill::decode(value)?),
+                FIELD_PINNED => {
+                    let bytes = value.try_into().map_err(|_| "bad pinned score")?;
+                    meta.pinned = Some(f32::from_le_bytes(bytes));
+                }
+                FIELD_PIN_LEASE => meta.pin_lease = Some(Lease::decode(value)?),
+                FIELD_SUMMARY => meta.summary = Some(BlobSummary::decode(value)?),
+                FIELD_REFERENCE => meta.reference = Some(FileRef::decode(value)?),
+                FIELD_WRITTEN => meta.written_ms = read_u64(value)?,
+                FIELD_PENDING_TXN => meta.pending_txn = Some(read_str(value)?),
+                _ => {}
+            }
+        }
+        Ok(meta)
+    }
+}
+
+/// Wrapper metadata for a whole tag, stored in `TAG_META_NAME`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct TagMeta {
+    /// User key-value attributes.
+    pub attrs: BTreeMap<String, String>,
+    /// Data encryption keys by version, each wrapped by the tag's master key
+    /// (see `encrypt`).
+    pub data_keys: BTreeMap<u32, Vec<u8>>,
+}
+
+impl TagMeta {
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut w = FieldWriter::default();
+        for (k, v) in &self.attrs {
+            w.pair(FIELD_ATTR, k, v);
+        }
+        for (version, wrapped) in &self.data_keys {
+            let mut buf = version.to_le_bytes().to_vec();
+            buf.extend_from_slice(wrapped);
+            w.bytes(FIELD_DATA_KEY, &buf);
+        }
+        w.finish()
+    }
+
+    pub(crate) fn decode(buf: &[u8]) -> Result<Self, String> {
+        let mut meta = TagMeta::default();
+        for (id, value) in FieldReader::new(buf)? {
+            match id {
+                FIELD_ATTR => {
+                    let (k, v) = read_pair(value)?;
+                    meta.attrs.insert(k, v);
+                }
+                FIELD_DATA_KEY => {
+                    let version = value
+                        .get(..4)
+                        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+                        .ok_or("truncated data key")?;
+                    meta.data_keys.insert(version, value[4..].to_vec());
+                }
+                _ => {}
+            }
+        }
+        Ok(meta)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct FieldWriter {
+    buf: Vec<u8>,
+}
+
+impl FieldWriter {
+    pub(crate) fn bytes(&mut self, id: u8, value: &[u8]) {
+        self.buf.push(id);
+        self.buf
+            .extend_from_slice(&(value.len() as u32).to_le_bytes());
+        self.buf.extend_from_slice(value);
+    }
+
+    pub(crate) fn u64(&mut self, id: u8, value: u64) {
+        self.bytes(id, &value.to_le_bytes());
+    }
+
+    /// A key-value pair as `(klen: u32, key, value)`.
+    pub(crate) fn pair(&mut self, id: u8, key: &str, value: &str) {
+        let mut buf = Vec::with_capacity(4 + key.len() + value.len());
+        buf.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        buf.extend_from_slice(key.as_bytes());
+        buf.extend_from_slice(value.as_bytes());
+        self.bytes(id, &buf);
+    }
+
+    pub(crate) fn f32(&mut self, id: u8, value: f32) {
+        self.bytes(id, &value.to_le_bytes());
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.buf.len() + 4);
+        out.extend_from_slice(&(self.buf.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.buf);
+        out
+    }
+}
+
+pub(crate) struct FieldReader<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> FieldReader<'a> {
+    /// Bytes occupied by the record at the start of `buf`, header included.
+    pub(crate) fn record_len(buf: &[u8]) -> Option<usize> {
+        let len = u32::from_le_bytes(buf.get(..4)?.try_into().unwrap()) as usize;
+        Some(4 + len)
+    }
+
+    pub(crate) fn new(buf: &'a [u8]) -> Result<Self, String> {
+        if buf.len() < 4 {
+            return Err("truncated header".into());
+        }
+        let len = u32::from_le_bytes(buf[..4].try_into().unwrap()) as usize;
+        let rest = buf[4..]
+            .get(..len)
+            .ok_or_else(|| "payload shorter than header length".to_string())?;
+        Ok(Self { rest })
+    }
+}
+
+impl<'a> Iterator for FieldReader<'a> {
+    type Item = (u8, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.len() < 5 {
+            return None;
+        }
+        let id = self.rest[0];
+        let len = u32::from_le_bytes(self.rest[1..5].try_into().unwrap()) as usize;
+        let value = self.rest.get(5..5 + len)?;
+        self.rest = &self.rest[5 + len..];
+        Some((id, value))
+    }
+}
+
+pub(crate) fn read_u64(value: &[u8]) -> Result<u64, String> {
+    value
+        .try_into()
+        .map(u64::from_le_bytes)
+        .map_err(|_| "bad u64 field".to_string())
+}
+
+pub(crate) fn read_f32(value: &[u8]) -> Result<f32, String> {
+    value
+        .try_into()
+        .map(f32::from_le_bytes)
+        .map_err(|_| "bad f32 field".to_string())
+}
+
+pub(crate) fn read_str(value: &[u8]) -> Result<String, String> {
+    String::from_utf8(value.to_vec()).map_err(|_| "bad string field".to_string())
+}
+
+pub(crate) fn read_pair(value: &[u8]) -> Result<(String, String), String> {
+    let klen = value
+        .get(..4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize)
+        .ok_or("truncated pair")?;
+    let key = value.get(4..4 + klen).ok_or("truncated pair key")?;
+    Ok((read_str(key)?, read_str(&value[4 + klen..])?))
+}
+
+/// Decode a sidecar as read by `Tag::load_meta_raw`.
+pub(crate) fn decode_meta(name: &str, raw: &[u8]) -> Result<Option<BlobMeta>, CteError> {
+    if raw.is_empty() {
+        return Ok(None);
+    }
+    BlobMeta::decode(raw)
+        .map(Some)
+        .map_err(|reason| CteError::CorruptMetadata {
+            blob: name.to_string(),
+            reason,
+        })
+}
+
+impl Tag {
+    /// Load the metadata sidecar for `name`, or `None` if it has never been written.
+    pub(crate) fn load_meta(&self, name: &str) -> Result<Option<BlobMeta>, CteError> {
+        let raw = self.load_meta_raw(name)?;
+        decode_meta(name, &raw)
+    }
+
+    /// The encoded metadata sidecar for `name`; empty if it has never been written.
+    pub(crate) fn load_meta_raw(&self, name: &str) -> Result<Vec<u8>, CteError> {
+        let meta_name = format!("{}{}", META_PREFIX, name);
+        let size = self.get_blob_size(&meta_name);
+        if size == 0 {
+            return Ok(Vec::new());
+        }
+        let buf = crate::ffi_guard::tag_get_blob(&self.inner, &meta_name, size, 0)?;
+        Ok(buf.as_slice().to_vec())
+    }
+
+    /// Overwrite the metadata sidecar for `name`.
+    ///
+    /// Sidecars are small and read on every guarded operation, so they always go to
+    /// the hottest tier rather than through the placement policy. They are written
+    /// below the public put API so they don't show up in the change log.
+    ///
+    /// The producer epoch stored is never lowered: if the sidecar already holds a
+    /// higher one, because a newer producer fenced this writer out, it is kept.
+    /// Where the runtime supports it (`Capability::CompareAndSwap`) the write
+    /// only lands if the sidecar is still as read, and is retried otherwise, so
+    /// this holds across processes too.
+    pub(crate) fn store_meta(&self, name: &str, meta: &BlobMeta) -> Result<(), CteError> {
+        loop {
+            let raw = self.load_meta_raw(name)?;
+            // A corrupt sidecar is overwritten, as before epochs were merged.
+            let current = decode_meta(name, &raw).ok().flatten();
+            let encoded = match current {
+                Some(current) if current.epoch > meta.epoch => BlobMeta {
+                    epoch: current.epoch,
+                    ..meta.clone()
+                }
+                .encode(),
+                _ => meta.encode(),
+            };
+            if self.swap_meta(name, &raw, &encoded)? {
+                return Ok(());
+            }
+            std::thread::yield_now();
+        }
+    }
+
+    /// Replace the sidecar for `name` with `new` if it still holds `current`,
+    /// as read by `load_meta_raw`; whether it did. Without runtime support for
+    /// the swap this is a plain overwrite.
+    pub(crate) fn swap_meta(
+        &self,
+        name: &str,
+        current: &[u8],
+        new: &[u8],
+    ) -> Result<bool, CteError> {
+        let meta_name = format!("{}{}", META_PREFIX, name);
+        if !crate::handshake::supports(crate::Capability::CompareAndSwap) {
+            crate::ffi_guard::tag_put_blob(&self.inner, &meta_name, new, 0, 1.0)?;
+            return Ok(true);
+        }
+        crate::ffi_guard::tag_swap_blob(&self.inner, &meta_name, current, new, 1.0)
+    }
+
+    /// Every blob name in the tag, wrapper-internal ones included.
+    pub(crate) fn raw_blob_names(&self) -> Vec<String> {
+        crate::ffi::tag_get_contained_blobs(&self.inner)
+            .iter()
+            .map(|s| s.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    /// Load the tag-level metadata sidecar (empty if never written).
+    pub(crate) fn load_tag_meta(&self) -> Result<TagMeta, CteError> {
+        let size = self.get_blob_size(TAG_META_NAME);
+        if size == 0 {
+            return Ok(TagMeta::default());
+        }
+        let buf = crate::ffi_guard::tag_get_blob(&self.inner, TAG_META_NAME, size, 0)?;
+        TagMeta::decode(buf.as_slice()).map_err(|reason| CteError::CorruptMetadata {
+            blob: TAG_META_NAME.to_string(),
+            reason,
+        })
+    }
+
+    pub(crate) fn store_tag_meta(&self, meta: &TagMeta) -> Result<(), CteError> {
+        crate::ffi_guard::tag_put_blob(&self.inner, TAG_META_NAME, &meta.encode(), 0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meta_roundtrip() {
+        let mut meta = BlobMeta {
+            epoch: 42,
+            generation: 9,
+            expires_ms: 1_700_000_000_000,
+            pinned: Some(0.75),
+            pin_lease: Some(Lease {
+                process: 7,
+                id: 3,
+                pid: 4242,
+                expires_ms: 1_700_000_030_000,
+            }),
+            summary: Some(BlobSummary {
+                head: b"step,energy".to_vec(),
+                rows: Some(3),
+                ..Default::default()
+            }),
+            pending_txn: Some("00000000000000a1-3".into()),
+            ..Default::default()
+        };
+        meta.attrs.insert("run_id".into(), "r-17".into());
+        meta.attrs.insert("schema".into(), "".into());
+        meta.checksum = Some(Checksum::compute(
+            crate::ChecksumAlgorithm::Crc32c,
+            b"payload",
+        ));
+        assert_eq!(BlobMeta::decode(&meta.encode()).unwrap(), meta);
+    }
+
+    #[test]
+    fn test_tag_meta_roundtrip() {
+        let mut meta = TagMeta::default();
+        meta.attrs.insert("owner".into(), "climate-group".into());
+        meta.data_keys.insert(2, vec![0xab; 60]);
+        assert_eq!(TagMeta::decode(&meta.encode()).unwrap(), meta);
+    }
+
+    #[test]
+    fn test_meta_ignores_trailing_bytes() {
+        // A shorter record written over a longer one leaves stale bytes behind.
+        let mut buf = BlobMeta {
+            epoch: 7,
+            ..Default::default()
+        }
+        .encode();
+        buf.extend_from_slice(&[0xff; 16]);
+        assert_eq!(BlobMeta::decode(&buf).unwrap().epoch, 7);
+    }
+
+    #[test]
+    fn test_meta_skips_unknown_fields() {
+        let mut w = FieldWriter::default();
+        w.bytes(200, b"from a newer wrapper");
+        w.u64(FIELD_EPOCH, 3);
+        assert_eq!(BlobMeta::decode(&w.finish()).unwrap().epoch, 3);
+    }
+}
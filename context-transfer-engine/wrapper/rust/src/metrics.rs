@@ -0,0 +1,642 @@
+//! Prometheus metrics for the wrapper (feature `metrics`).
+//!
+//! Every put, get and delete issued through the wrapper is counted per tag, with
+//! the bytes it moved and a latency histogram. `Client::serve_metrics` exposes
+//! the counts over HTTP at `/metrics` in the Prometheus text format, along with
+//! each storage target's free space and byte counters as reported by the runtime
+//! when scraped. `Client::metrics_text` renders the same page for processes that
+//! already run an HTTP server of their own.
+//!
+//! Tags beyond the first `MAX_TAGS` seen are counted together under the label
+//! `tag="_other"`, so short-lived tags can't grow the page without bound.
+//! Group commits count their blobs but not their latency, which belongs to the
+//! batch rather than to any one blob.
+//!
+//! The page also carries metrics derived from those, so a dashboard can chart
+//! them as they are:
+//!
+//! - `cte_tag_bandwidth_bytes_per_second`: bytes each tag put and got per
+//!   second over the last `RATE_WINDOW_SECS` seconds.
+//! - `cte_tier_read_bytes_total` and `cte_tier_hit_ratio`: the runtime's reads
+//!   summed per tier (targets of equal score, `tier="0"` the fastest), and
+//!   each tier's share of all bytes read since the runtime started; the hit
+//!   ratio of the fastest tier is the usual headline.
+//! - `cte_reorganizations_total` and `cte_reorganization_rate`: blobs moved
+//!   by `Tag::reorganize_blob` to a higher (`direction="promote"`) or lower
+//!   (`"demote"`) score than they had, in all and per second over the window.
+//!   Telling the two apart costs a score lookup per move.
+//! - `cte_reorganizations_in_flight` and `cte_runtime_queued_tasks`: moves
+//!   still being carried out, and the tasks waiting in the local runtime's
+//!   worker queues, as the backlog of reorganization.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::accounting::Op;
+use crate::http::{self, Response};
+use crate::{Client, TargetInfo};
+
+/// Distinct tags labelled on their own; the rest share `tag="_other"`.
+const MAX_TAGS: usize = 1000;
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+const BUCKETS: [f64; 14] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0,
+];
+
+/// Seconds over which the per-second rates are taken.
+const RATE_WINDOW_SECS: u64 = 60;
+
+/// Width of a bucket of `Rate`, in seconds.
+const RATE_BUCKET_SECS: u64 = 5;
+
+const RATE_BUCKETS: usize = (RATE_WINDOW_SECS / RATE_BUCKET_SECS) as usize;
+
+/// Reads one of a target's values for the page.
+type TargetValue = fn(&TargetInfo) -> String;
+
+/// Bucket number of the current time, counted from the first use.
+fn bucket_now() -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_secs() / RATE_BUCKET_SECS
+}
+
+/// Amounts added over the last `RATE_WINDOW_SECS` seconds, in buckets.
+#[derive(Debug, Clone, Default)]
+struct Rate {
+    buckets: [u64; RATE_BUCKETS],
+    /// Bucket number the newest amounts went into.
+    newest: u64,
+}
+
+impl Rate {
+    fn add(&mut self, now: u64, amount: u64) {
+        if now > self.newest {
+            // Clear the buckets of the numbers skipped, oldest reused first.
+            for n in self.newest + 1..=now.min(self.newest + RATE_BUCKETS as u64) {
+                self.buckets[(n % RATE_BUCKETS as u64) as usize] = 0;
+            }
+            self.newest = now;
+        }
+        self.buckets[(now % RATE_BUCKETS as u64) as usize] += amount;
+    }
+
+    /// Amount per second over the window ending in bucket `now`.
+    fn per_second(&self, now: u64) -> f64 {
+        let total: u64 = (0..RATE_BUCKETS as u64)
+            .filter_map(|age| self.newest.checked_sub(age))
+            .filter(|&n| now.saturating_sub(n) < RATE_BUCKETS as u64)
+            .map(|n| self.buckets[(n % RATE_BUCKETS as u64) as usize])
+            .sum();
+        total as f64 / RATE_WINDOW_SECS as f64
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    /// Observations per bucket (not cumulative); the last is `+Inf`.
+    counts: [u64; BUCKETS.len() + 1],
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, latency: Duration) {
+        let secs = latency.as_secs_f64();
+        let bucket = BUCKETS.partition_point(|&le| le < secs);
+        self.counts[bucket] += 1;
+        self.sum += secs;
+    }
+}
+
+/// Counters of one operation kind on one tag.
+#[derive(Debug, Clone, Default)]
+struct OpMetrics {
+    ops: u64,
+    bytes: u64,
+    latency: Histogram,
+    throughput: Rate,
+}
+
+#[derive(Debug, Clone, Default)]
+struct TagMetrics {
+    put: OpMetrics,
+    get: OpMetrics,
+    delete: OpMetrics,
+}
+
+impl TagMetrics {
+    fn op(&self, op: Op) -> &OpMetrics {
+        match op {
+            Op::Put => &self.put,
+            Op::Get => &self.get,
+            Op::Delete => &self.delete,
+        }
+    }
+
+    fn op_mut(&mut self, op: Op) -> &mut OpMetrics {
+        match op {
+            Op::Put => &mut self.put,
+            Op::Get => &mut self.get,
+            Op::Delete => &mut self.delete,
+        }
+    }
+}
+
+/// Which way a reorganization moved a blob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Promote,
+    Demote,
+}
+
+#[derive(Debug, Default)]
+struct Moves {
+    count: u64,
+    rate: Rate,
+}
+
+impl Moves {
+    const NONE: Moves = Moves {
+        count: 0,
+        rate: Rate {
+            buckets: [0; RATE_BUCKETS],
+            newest: 0,
+        },
+    };
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    tags: BTreeMap<String, TagMetrics>,
+    promotions: Moves,
+    demotions: Moves,
+    reorganizing: u64,
+}
+
+/// What the runtime reports when the page is rendered.
+struct RuntimeView<'a> {
+    targets: &'a [TargetInfo],
+    queued_tasks: u64,
+    /// `bucket_now` at the time.
+    now: u64,
+}
+
+/// Targets by score, fastest tier first: (score, bytes read) per tier.
+fn tier_reads(targets: &[TargetInfo]) -> Vec<(f32, u64)> {
+    let mut tiers: Vec<(f32, u64)> = Vec::new();
+    let mut sorted: Vec<&TargetInfo> = targets.iter().collect();
+    sorted.sort_by(|a, b| b.score.total_cmp(&a.score));
+    for t in sorted {
+        match tiers.last_mut() {
+            Some((score, read)) if *score == t.score => *read += t.bytes_read,
+            _ => tiers.push((t.score, t.bytes_read)),
+        }
+    }
+    tiers
+}
+
+impl Registry {
+    fn record(&mut self, tag: &str, op: Op, bytes: u64, latency: Option<Duration>) {
+        self.record_at(tag, op, bytes, latency, bucket_now());
+    }
+
+    fn record_at(&mut self, tag: &str, op: Op, bytes: u64, latency: Option<Duration>, now: u64) {
+        let tags = &mut self.tags;
+        let entry = if tags.contains_key(tag) || tags.len() < MAX_TAGS {
+            tags.entry(tag.to_string()).or_default()
+        } else {
+            tags.entry("_other".to_string()).or_default()
+        };
+        let m = entry.op_mut(op);
+        m.ops += 1;
+        m.bytes += bytes;
+        m.throughput.add(now, bytes);
+        if let Some(latency) = latency {
+            m.latency.observe(latency);
+        }
+    }
+
+    fn record_move(&mut self, direction: Direction, now: u64) {
+        let moves = match direction {
+            Direction::Promote => &mut self.promotions,
+            Direction::Demote => &mut self.demotions,
+        };
+        moves.count += 1;
+        moves.rate.add(now, 1);
+    }
+
+    fn render(&self, runtime: &RuntimeView) -> String {
+        let targets = runtime.targets;
+        let mut out = String::new();
+        let ops = [("put", Op::Put), ("get", Op::Get), ("delete", Op::Delete)];
+        let each = |f: &mut dyn FnMut(&str, &str, &OpMetrics)| {
+            for (tag, metrics) in &self.tags {
+                for (label, op) in ops {
+                    f(tag, label, metrics.op(op));
+                }
+            }
+        };
+
+        header(
+            &mut out,
+            "cte_ops_total",
+            "counter",
+            "Operations issued through the wrapper.",
+        );
+        each(&mut |tag, op, m| {
+            let _ = writeln!(
+                out,
+                "cte_ops_total{{tag=\"{}\",op=\"{}\"}} {}",
+                escape(tag),
+                op,
+                m.ops
+            );
+        });
+        header(
+            &mut out,
+            "cte_bytes_total",
+            "counter",
+            "Bytes written by puts and read by gets.",
+        );
+        each(&mut |tag, op, m| {
+            if op != "delete" {
+                let _ = writeln!(
+                    out,
+                    "cte_bytes_total{{tag=\"{}\",op=\"{}\"}} {}",
+                    escape(tag),
+                    op,
+                    m.bytes
+                );
+            }
+        });
+        header(
+            &mut out,
+            "cte_op_duration_seconds",
+            "histogram",
+            "Latency of operations issued through the wrapper.",
+        );
+        each(&mut |tag, op, m| {
+            let labels = format!("tag=\"{}\",op=\"{}\"", escape(tag), op);
+            let mut cumulative = 0;
+            for (i, count) in m.latency.counts.iter().enumerate() {
+                cumulative += count;
+                let le = BUCKETS
+                    .get(i)
+                    .map_or_else(|| "+Inf".to_string(), |b| b.to_string());
+                let _ = writeln!(
+                    out,
+                    "cte_op_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, le, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "cte_op_duration_seconds_sum{{{}}} {}",
+                labels, m.latency.sum
+            );
+            let _ = writeln!(
+                out,
+                "cte_op_duration_seconds_count{{{}}} {}",
+                labels, cumulative
+            );
+        });
+
+        let gauges: [(&str, &str, &str, TargetValue); 4] = [
+            (
+                "cte_target_free_bytes",
+                "gauge",
+                "Remaining space on a storage target.",
+                |t| t.remaining_space.to_string(),
+            ),
+            (
+                "cte_target_score",
+                "gauge",
+                "Placement score of a storage target.",
+                |t| t.score.to_string(),
+            ),
+            (
+                "cte_target_read_bytes_total",
+                "counter",
+                "Bytes read from a storage target by the runtime.",
+                |t| t.bytes_read.to_string(),
+            ),
+            (
+                "cte_target_written_bytes_total",
+                "counter",
+                "Bytes written to a storage target by the runtime.",
+                |t| t.bytes_written.to_string(),
+            ),
+        ];
+        for (name, kind, help, value) in gauges {
+            header(&mut out, name, kind, help);
+            for t in targets {
+                let _ = writeln!(
+                    out,
+                    "{}{{target=\"{}\"}} {}",
+                    name,
+                    escape(&t.name),
+                    value(t)
+                );
+            }
+        }
+
+        header(
+            &mut out,
+            "cte_tag_bandwidth_bytes_per_second",
+            "gauge",
+            "Bytes a tag put and got per second over the last minute.",
+        );
+        each(&mut |tag, op, m| {
+            if op != "delete" {
+                let _ = writeln!(
+                    out,
+                    "cte_tag_bandwidth_bytes_per_second{{tag=\"{}\",op=\"{}\"}} {}",
+                    escape(tag),
+                    op,
+                    m.throughput.per_second(runtime.now)
+                );
+            }
+        });
+        let tiers = tier_reads(targets);
+        let total_read: u64 = tiers.iter().map(|(_, read)| read).sum();
+        header(
+            &mut out,
+            "cte_tier_read_bytes_total",
+            "counter",
+            "Bytes the runtime read from the targets of a tier.",
+        );
+        for (i, (score, read)) in tiers.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "cte_tier_read_bytes_total{{tier=\"{}\",score=\"{}\"}} {}",
+                i, score, read
+            );
+        }
+        header(
+            &mut out,
+            "cte_tier_hit_ratio",
+            "gauge",
+            "Share of the bytes read that came from a tier.",
+        );
+        for (i, (score, read)) in tiers.iter().enumerate() {
+            let ratio = if total_read == 0 {
+                0.0
+            } else {
+                *read as f64 / total_read as f64
+            };
+            let _ = writeln!(
+                out,
+                "cte_tier_hit_ratio{{tier=\"{}\",score=\"{}\"}} {}",
+                i, score, ratio
+            );
+        }
+        let moves = [("promote", &self.promotions), ("demote", &self.demotions)];
+        header(
+            &mut out,
+            "cte_reorganizations_total",
+            "counter",
+            "Blobs moved to a higher or lower score.",
+        );
+        for (direction, m) in moves {
+            let _ = writeln!(
+                out,
+                "cte_reorganizations_total{{direction=\"{}\"}} {}",
+                direction, m.count
+            );
+        }
+        header(
+            &mut out,
+            "cte_reorganization_rate",
+            "gauge",
+            "Blobs moved per second over the last minute.",
+        );
+        for (direction, m) in moves {
+            let _ = writeln!(
+                out,
+                "cte_reorganization_rate{{direction=\"{}\"}} {}",
+                direction,
+                m.rate.per_second(runtime.now)
+            );
+        }
+        header(
+            &mut out,
+            "cte_reorganizations_in_flight",
+            "gauge",
+            "Reorganizations issued and not yet finished.",
+        );
+        let _ = writeln!(out, "cte_reorganizations_in_flight {}", self.reorganizing);
+        header(
+            &mut out,
+            "cte_runtime_queued_tasks",
+            "gauge",
+            "Tasks waiting in the local runtime's worker queues.",
+        );
+        let _ = writeln!(out, "cte_runtime_queued_tasks {}", runtime.queued_tasks);
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Escape a label value for the text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    tags: BTreeMap::new(),
+    promotions: Moves::NONE,
+    demotions: Moves::NONE,
+    reorganizing: 0,
+});
+
+fn registry() -> MutexGuard<'static, Registry> {
+    REGISTRY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Count an operation on `tag` moving `bytes`, taking `latency` if timed.
+pub(crate) fn record(tag: &str, op: Op, bytes: u64, latency: Option<Duration>) {
+    registry().record(tag, op, bytes, latency);
+}
+
+/// A reorganization in progress, from `reorganizing`; counted in flight until
+/// dropped.
+pub(crate) struct Reorganizing;
+
+impl Drop for Reorganizing {
+    fn drop(&mut self) {
+        let mut registry = registry();
+        registry.reorganizing = registry.reorganizing.saturating_sub(1);
+    }
+}
+
+/// Count a move of a blob from score `from` to score `to`.
+pub(crate) fn reorganizing(from: f32, to: f32) -> Reorganizing {
+    let mut registry = registry();
+    registry.reorganizing += 1;
+    if to > from {
+        registry.record_move(Direction::Promote, bucket_now());
+    } else if to < from {
+        registry.record_move(Direction::Demote, bucket_now());
+    }
+    Reorganizing
+}
+
+/// An HTTP server for `/metrics`, from `Client::serve_metrics`. Stops when
+/// dropped.
+pub struct MetricsServer {
+    server: http::Server,
+}
+
+impl MetricsServer {
+    /// The address the server listens on, with the port chosen if 0 was asked.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.server.local_addr()
+    }
+}
+
+impl Client {
+    /// The metrics page, in the Prometheus text format (see `metrics`).
+    pub fn metrics_text() -> String {
+        let targets = Client::list_targets();
+        let queued_tasks = Client::worker_stats()
+            .iter()
+            .map(|w| u64::from(w.queued))
+            .sum();
+        registry().render(&RuntimeView {
+            targets: &targets,
+            queued_tasks,
+            now: bucket_now(),
+        })
+    }
+
+    /// Serve the metrics page at `http://addr/metrics` on background threads
+    /// until the returned server is dropped.
+    pub fn serve_metrics(addr: impl ToSocketAddrs) -> io::Result<MetricsServer> {
+        let server = http::serve(addr, |request| {
+            match (request.method.as_str(), request.path.as_str()) {
+                ("GET" | "HEAD", "/metrics") => Response::new("200 OK", Client::metrics_text())
+                    .header("Content-Type", "text/plain; version=0.0.4"),
+                ("GET" | "HEAD", _) => Response::new("404 Not Found", "not found\n"),
+                _ => Response::new("405 Method Not Allowed", "method not allowed\n")
+                    .header("Allow", "GET, HEAD"),
+            }
+        })?;
+        Ok(MetricsServer { server })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut registry = Registry::default();
+        registry.record("ingest", Op::Put, 100, Some(Duration::from_micros(300)));
+        registry.record("ingest", Op::Put, 50, Some(Duration::from_secs(9)));
+        registry.record("ingest", Op::Get, 100, Some(Duration::from_millis(2)));
+        registry.record("say \"hi\"", Op::Delete, 0, None);
+        let targets = [TargetInfo {
+            name: "/mnt/nvme".into(),
+            score: 1.0,
+            remaining_space: 4096,
+            bytes_read: 10,
+            bytes_written: 20,
+        }];
+        let page = registry.render(&RuntimeView {
+            targets: &targets,
+            queued_tasks: 3,
+            now: bucket_now(),
+        });
+        let has = |line: &str| page.lines().any(|l| l == line);
+        assert!(has("# TYPE cte_op_duration_seconds histogram"));
+        assert!(has("cte_ops_total{tag=\"ingest\",op=\"put\"} 2"));
+        assert!(has("cte_bytes_total{tag=\"ingest\",op=\"put\"} 150"));
+        assert!(has("cte_ops_total{tag=\"say \\\"hi\\\"\",op=\"delete\"} 1"));
+        assert!(has(
+            "cte_op_duration_seconds_bucket{tag=\"ingest\",op=\"put\",le=\"0.00025\"} 0"
+        ));
+        assert!(has(
+            "cte_op_duration_seconds_bucket{tag=\"ingest\",op=\"put\",le=\"0.0005\"} 1"
+        ));
+        assert!(has(
+            "cte_op_duration_seconds_bucket{tag=\"ingest\",op=\"put\",le=\"5\"} 1"
+        ));
+        assert!(has(
+            "cte_op_duration_seconds_bucket{tag=\"ingest\",op=\"put\",le=\"+Inf\"} 2"
+        ));
+        assert!(has(
+            "cte_op_duration_seconds_count{tag=\"ingest\",op=\"put\"} 2"
+        ));
+        assert!(has("cte_target_free_bytes{target=\"/mnt/nvme\"} 4096"));
+        assert!(has("cte_runtime_queued_tasks 3"));
+
+        // Derived metrics, at fixed bucket numbers.
+        let mut derived = Registry::default();
+        derived.record_at("ingest", Op::Put, 600, None, 1);
+        derived.record_at("ingest", Op::Put, 600, None, 2);
+        derived.record_move(Direction::Promote, 2);
+        derived.record_move(Direction::Promote, 2);
+        derived.record_move(Direction::Demote, 2);
+        let target = |name: &str, score, bytes_read| TargetInfo {
+            name: name.into(),
+            score,
+            remaining_space: 0,
+            bytes_read,
+            bytes_written: 0,
+        };
+        let tiers = [
+            target("hdd", 0.2, 100),
+            target("ram0", 1.0, 200),
+            target("ram1", 1.0, 100),
+        ];
+        let render = |now| {
+            derived.render(&RuntimeView {
+                targets: &tiers,
+                queued_tasks: 0,
+                now,
+            })
+        };
+        let page = render(3);
+        let has = |line: &str| page.lines().any(|l| l == line);
+        assert!(has(
+            "cte_tag_bandwidth_bytes_per_second{tag=\"ingest\",op=\"put\"} 20"
+        ));
+        assert!(has("cte_tier_read_bytes_total{tier=\"0\",score=\"1\"} 300"));
+        assert!(has("cte_tier_hit_ratio{tier=\"0\",score=\"1\"} 0.75"));
+        assert!(has("cte_tier_hit_ratio{tier=\"1\",score=\"0.2\"} 0.25"));
+        assert!(has("cte_reorganizations_total{direction=\"promote\"} 2"));
+        assert!(has(
+            "cte_reorganization_rate{direction=\"demote\"} 0.016666666666666666"
+        ));
+        // A window later the rates are back to nothing; the totals stay.
+        let page = render(2 + RATE_BUCKETS as u64);
+        let has = |line: &str| page.lines().any(|l| l == line);
+        assert!(has(
+            "cte_tag_bandwidth_bytes_per_second{tag=\"ingest\",op=\"put\"} 0"
+        ));
+        assert!(has("cte_reorganization_rate{direction=\"promote\"} 0"));
+        assert!(has("cte_reorganizations_total{direction=\"promote\"} 2"));
+        let mut rate = Rate::default();
+        rate.add(0, 60);
+        rate.add(RATE_BUCKETS as u64, 120);
+        assert_eq!(rate.per_second(RATE_BUCKETS as u64), 2.0);
+
+        for i in 0..MAX_TAGS + 5 {
+            registry.record(&format!("t{}", i), Op::Get, 1, None);
+        }
+        assert_eq!(registry.tags.len(), MAX_TAGS + 1);
+        assert_eq!(registry.tags["_other"].get.ops, 7);
+    }
+}
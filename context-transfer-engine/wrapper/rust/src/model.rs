@@ -0,0 +1,181 @@
+//! Learned score models for auto-tiering.
+//!
+//! By default `Client::start_auto_tier` maps a blob's heat straight to a score.
+//! A registered `ScoreModel` replaces that mapping: each interval the task asks
+//! it to `predict` the score of every tracked blob from the blob and its
+//! `RecentAccess`, and one interval later tells it, through `update`, what the
+//! blob's accesses in that interval turned out to justify. A model therefore
+//! trains on the campaign it places, and can learn that, say, large blobs
+//! written once are read back once, or that a burst of writes means a burst of
+//! reads. `LinearScoreModel` is a small online least-squares model to start
+//! from. Models are called on the auto-tier thread; one that panics falls back
+//! to the heat mapping for that call.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::BlobDescriptor;
+
+/// What the auto-tier task has seen of a blob's use.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecentAccess {
+    /// Accesses, each halved every `AutoTierPolicy::half_life` since.
+    pub heat: f64,
+    /// Reads and writes since tracking of the blob began.
+    pub reads: u64,
+    pub writes: u64,
+    /// Accesses in the last interval.
+    pub interval_accesses: u64,
+    /// Time since the last access.
+    pub idle: Duration,
+    /// Time since the first access tracked.
+    pub age: Duration,
+}
+
+/// The outcome of one `ScoreModel::predict`, an interval later.
+pub struct Feedback {
+    pub blob: BlobDescriptor,
+    /// What the prediction was made from.
+    pub access: RecentAccess,
+    pub predicted: f32,
+    /// The score the blob's accesses in the interval since would have got,
+    /// taken as its whole heat.
+    pub observed: f32,
+}
+
+/// Predicts a blob's score (0.0 coldest, 1.0 hottest) from its use so far.
+pub trait ScoreModel: Send {
+    fn predict(&self, blob: &BlobDescriptor, access: &RecentAccess) -> f32;
+
+    /// Learn from how a prediction turned out.
+    fn update(&mut self, feedback: &Feedback);
+
+    /// Name the model and its parameters, for logs.
+    fn describe(&self) -> String {
+        "custom".to_string()
+    }
+}
+
+static MODEL: Mutex<Option<Box<dyn ScoreModel>>> = Mutex::new(None);
+
+/// Register the process-wide score model, replacing any previous one.
+pub fn set_score_model(model: impl ScoreModel + 'static) {
+    *MODEL.lock().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(model));
+}
+
+/// Remove the score model; auto-tiering goes back to scoring by heat.
+pub fn clear_score_model() {
+    *MODEL.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Run `f` with the registered model, if any.
+pub(crate) fn with_model<T>(f: impl FnOnce(Option<&mut dyn ScoreModel>) -> T) -> T {
+    let mut model = MODEL.lock().unwrap_or_else(|e| e.into_inner());
+    f(model.as_mut().map(|m| &mut **m as &mut dyn ScoreModel))
+}
+
+const FEATURES: usize = 5;
+
+/// Online linear regression of the observed score, trained by stochastic
+/// gradient descent on each `Feedback`. Features are the log of heat, reads,
+/// writes and size, and how recently the blob was used, each scaled to about
+/// 0..1; predictions are clamped to 0..=1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinearScoreModel {
+    pub weights: [f64; FEATURES],
+    pub bias: f64,
+    pub learning_rate: f64,
+}
+
+impl Default for LinearScoreModel {
+    fn default() -> Self {
+        Self {
+            weights: [0.0; FEATURES],
+            bias: 0.5,
+            learning_rate: 0.05,
+        }
+    }
+}
+
+impl LinearScoreModel {
+    fn features(blob: &BlobDescriptor, access: &RecentAccess) -> [f64; FEATURES] {
+        let log = |x: f64, scale: f64| (1.0 + x).ln() / scale;
+        [
+            log(access.heat, 5.0),
+            log(access.reads as f64, 10.0),
+            log(access.writes as f64, 10.0),
+            (-access.idle.as_secs_f64() / 600.0).exp(),
+            log(blob.size as f64, 40.0),
+        ]
+    }
+
+    fn raw(&self, x: &[f64; FEATURES]) -> f64 {
+        self.bias + self.weights.iter().zip(x).map(|(w, x)| w * x).sum::<f64>()
+    }
+}
+
+impl ScoreModel for LinearScoreModel {
+    fn predict(&self, blob: &BlobDescriptor, access: &RecentAccess) -> f32 {
+        self.raw(&Self::features(blob, access)).clamp(0.0, 1.0) as f32
+    }
+
+    fn update(&mut self, feedback: &Feedback) {
+        let x = Self::features(&feedback.blob, &feedback.access);
+        let error = f64::from(feedback.observed) - self.raw(&x);
+        for (w, x) in self.weights.iter_mut().zip(&x) {
+            *w += self.learning_rate * error * x;
+        }
+        self.bias += self.learning_rate * error;
+    }
+
+    fn describe(&self) -> String {
+        format!("linear(learning_rate={})", self.learning_rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_linear_model_learns() {
+        let blob = |size| BlobDescriptor {
+            tag_id: crate::CteTagId { major: 1, minor: 2 },
+            name: "b".into(),
+            size,
+            offset: 0,
+        };
+        let hot = RecentAccess {
+            heat: 20.0,
+            reads: 40,
+            writes: 1,
+            interval_accesses: 10,
+            idle: Duration::from_secs(1),
+            age: Duration::from_secs(600),
+        };
+        let cold = RecentAccess {
+            heat: 0.1,
+            reads: 1,
+            writes: 1,
+            interval_accesses: 0,
+            idle: Duration::from_secs(3600),
+            age: Duration::from_secs(7200),
+        };
+        let mut model = LinearScoreModel::default();
+        assert_eq!(model.predict(&blob(1 << 20), &hot), 0.5);
+        for _ in 0..500 {
+            for (access, observed) in [(&hot, 1.0), (&cold, 0.0)] {
+                let predicted = model.predict(&blob(1 << 20), access);
+                model.update(&Feedback {
+                    blob: blob(1 << 20),
+                    access: access.clone(),
+                    predicted,
+                    observed,
+                });
+            }
+        }
+        assert!(model.predict(&blob(1 << 20), &hot) > 0.9);
+        assert!(model.predict(&blob(1 << 20), &cold) < 0.1);
+        assert_eq!(model.describe(), "linear(learning_rate=0.05)");
+    }
+}
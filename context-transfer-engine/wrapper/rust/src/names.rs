@@ -0,0 +1,89 @@
+//! Returning names that aren't UTF-8.
+//!
+//! C++ clients can name tags and blobs with any bytes, but the listings and
+//! queries return `String`s. The process-wide `NamePolicy` decides what
+//! `Tag::get_contained_blobs`, `Tag::list`, `Client::tag_query`,
+//! `Client::blob_query` and `Client::query` do with a name that isn't
+//! UTF-8: replace its invalid sequences (`Lossy`, the default), which can make
+//! two names look alike and gives a name that doesn't reach the blob; fail
+//! with `CteError::InvalidName` (`Error`), by panicking where the call can't
+//! fail; or leave it out (`Raw`). The byte variants return every name exactly
+//! whatever the policy: `get_contained_blobs_bytes`, `Tag::list_bytes`,
+//! `Client::tag_query_bytes` and `Client::blob_query_bytes`. The policy doesn't
+//! apply to the wrapper's own walks over a tag, such as snapshots, deletes and
+//! TTL sweeps.
+
+use std::sync::RwLock;
+
+use crate::CteError;
+
+/// What name-returning calls do with names that aren't UTF-8 (see `names`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NamePolicy {
+    /// Replace invalid sequences with U+FFFD.
+    #[default]
+    Lossy,
+    /// Fail with `CteError::InvalidName`.
+    Error,
+    /// Leave the name out; only the byte variants return it.
+    Raw,
+}
+
+static POLICY: RwLock<NamePolicy> = RwLock::new(NamePolicy::Lossy);
+
+/// Set the process-wide name policy.
+pub fn set_name_policy(policy: NamePolicy) {
+    *POLICY.write().unwrap_or_else(|e| e.into_inner()) = policy;
+}
+
+/// The process-wide name policy.
+pub fn name_policy() -> NamePolicy {
+    *POLICY.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// `name` as a `String` under `policy`; `None` if it is to be left out.
+pub(crate) fn decode(name: &[u8], policy: NamePolicy) -> Result<Option<String>, CteError> {
+    if let Ok(name) = std::str::from_utf8(name) {
+        return Ok(Some(name.to_string()));
+    }
+    match policy {
+        NamePolicy::Lossy => Ok(Some(String::from_utf8_lossy(name).into_owned())),
+        NamePolicy::Error => Err(CteError::InvalidName {
+            name: name.to_vec(),
+        }),
+        NamePolicy::Raw => Ok(None),
+    }
+}
+
+/// Every name of `names` that `policy` keeps, in order.
+pub(crate) fn decode_all<'a>(
+    names: impl IntoIterator<Item = &'a [u8]>,
+    policy: NamePolicy,
+) -> Result<Vec<String>, CteError> {
+    let mut out = Vec::new();
+    for name in names {
+        out.extend(decode(name, policy)?);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_policy() {
+        let names: [&[u8]; 3] = [b"plain", b"latin1_\xe9", b"z"];
+        let lossy = decode_all(names, NamePolicy::Lossy).unwrap();
+        assert_eq!(lossy, ["plain", "latin1_\u{fffd}", "z"]);
+        assert_eq!(decode_all(names, NamePolicy::Raw).unwrap(), ["plain", "z"]);
+        match decode_all(names, NamePolicy::Error) {
+            Err(CteError::InvalidName { name }) => assert_eq!(name, b"latin1_\xe9"),
+            other => panic!("expected InvalidName, got {:?}", other),
+        }
+        assert_eq!(
+            decode(b"ok", NamePolicy::Error).unwrap().as_deref(),
+            Some("ok")
+        );
+    }
+}
@@ -0,0 +1,275 @@
+//! Negative caching of blob lookups.
+//!
+//! With `Client::enable_negative_cache`, a `Tag::get` or `Tag::stat_blob` that
+//! finds a blob missing remembers so for `NegativeCacheOptions::ttl`, and
+//! repeats of the lookup are answered without a runtime query. Entries are
+//! dropped by the events of writes made through this process (see `events`),
+//! whether or not anyone subscribes to them, and a lookup that raced with such
+//! a write doesn't cache its miss. Writes by other clients are only seen through
+//! their change logs when `poll_remote` is set, and otherwise once the entry
+//! expires, so the TTL bounds how long another client's new blob can stay
+//! invisible.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::timeout;
+use crate::{Client, CteTagId, Event, EventFilter, EventKind, Subscription, Tag};
+
+/// Settings for `Client::enable_negative_cache`.
+#[derive(Debug, Clone)]
+pub struct NegativeCacheOptions {
+    /// How long a miss is trusted.
+    pub ttl: Duration,
+    /// Misses remembered at once; a full cache drops its expired entries, or
+    /// all of them if none have expired.
+    pub capacity: usize,
+    /// Also drop entries for blobs written by other clients, found by polling
+    /// change logs every interval (see `EventFilter::poll_remote`).
+    pub poll_remote: Option<Duration>,
+}
+
+impl Default for NegativeCacheOptions {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(5),
+            capacity: 100_000,
+            poll_remote: None,
+        }
+    }
+}
+
+struct Cache {
+    ttl: Duration,
+    capacity: usize,
+    /// Expiry of each cached miss, by tag ID and blob name.
+    misses: HashMap<(u32, u32), HashMap<String, Instant>>,
+    len: usize,
+    /// IDs of the tags with cached misses, by name, for invalidating by event.
+    ids: HashMap<String, (u32, u32)>,
+    _remote: Option<Subscription>,
+}
+
+impl Cache {
+    fn new(options: &NegativeCacheOptions, remote: Option<Subscription>) -> Self {
+        Self {
+            ttl: options.ttl,
+            capacity: options.capacity.max(1),
+            misses: HashMap::new(),
+            len: 0,
+            ids: HashMap::new(),
+            _remote: remote,
+        }
+    }
+
+    fn contains(&mut self, id: (u32, u32), blob: &str, now: Instant) -> bool {
+        let Some(blobs) = self.misses.get_mut(&id) else {
+            return false;
+        };
+        match blobs.get(blob) {
+            Some(&expires) if expires > now => true,
+            Some(_) => {
+                blobs.remove(blob);
+                self.len -= 1;
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn insert(&mut self, id: (u32, u32), tag: &str, blob: &str, now: Instant) {
+        if self.len >= self.capacity {
+            self.evict(now);
+        }
+        self.ids.insert(tag.to_string(), id);
+        let blobs = self.misses.entry(id).or_default();
+        if blobs.insert(blob.to_string(), now + self.ttl).is_none() {
+            self.len += 1;
+        }
+    }
+
+    fn evict(&mut self, now: Instant) {
+        for blobs in self.misses.values_mut() {
+            blobs.retain(|_, expires| *expires > now);
+        }
+        self.misses.retain(|_, blobs| !blobs.is_empty());
+        self.len = self.misses.values().map(HashMap::len).sum();
+        if self.len >= self.capacity {
+            self.misses.clear();
+            self.len = 0;
+        }
+        let live = &self.misses;
+        self.ids.retain(|_, id| live.contains_key(id));
+    }
+
+    /// The cached tag an event's tag name refers to, if any.
+    fn resolve(&self, tag: &str) -> Option<(u32, u32)> {
+        if let Some(&id) = self.ids.get(tag) {
+            return Some(id);
+        }
+        // Handles opened by ID are named `#major.minor`.
+        let (major, minor) = tag.strip_prefix('#')?.split_once('.')?;
+        Some((major.parse().ok()?, minor.parse().ok()?))
+    }
+
+    fn observe(&mut self, event: &Event) {
+        let Some(id) = self.resolve(event.tag()) else {
+            return;
+        };
+        match event {
+            Event::BlobPut { blob, .. } | Event::BlobReorganized { blob, .. } => {
+                if let Some(blobs) = self.misses.get_mut(&id) {
+                    if blobs.remove(blob).is_some() {
+                        self.len -= 1;
+                    }
+                }
+            }
+            Event::TagCreated { .. } | Event::TagDeleted { .. } => {
+                if let Some(blobs) = self.misses.remove(&id) {
+                    self.len -= blobs.len();
+                }
+            }
+            Event::BlobDeleted { .. } | Event::BlobExpiring { .. } => {}
+        }
+    }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static CACHE: Mutex<Option<Cache>> = Mutex::new(None);
+/// Bumped by every write event, so a lookup that overlapped one doesn't cache.
+static EPOCH: AtomicU64 = AtomicU64::new(0);
+
+fn cache() -> std::sync::MutexGuard<'static, Option<Cache>> {
+    CACHE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn key(id: CteTagId) -> (u32, u32) {
+    (id.major, id.minor)
+}
+
+/// Drop the entries `event` makes stale. Called for every local event.
+pub(crate) fn observe(event: &Event) {
+    if !ENABLED.load(Ordering::Acquire)
+        || matches!(
+            event.kind(),
+            EventKind::BlobDeleted | EventKind::BlobExpiring
+        )
+    {
+        return;
+    }
+    let mut cache = cache();
+    EPOCH.fetch_add(1, Ordering::AcqRel);
+    if let Some(cache) = cache.as_mut() {
+        cache.observe(event);
+    }
+}
+
+/// Token to take before a lookup whose miss may be cached.
+pub(crate) fn epoch() -> u64 {
+    EPOCH.load(Ordering::Acquire)
+}
+
+impl Tag {
+    /// True if `name` is cached as missing.
+    pub(crate) fn known_missing(&self, name: &str) -> bool {
+        if !ENABLED.load(Ordering::Acquire) {
+            return false;
+        }
+        let id = key(self.get_tag_id());
+        cache()
+            .as_mut()
+            .is_some_and(|c| c.contains(id, name, Instant::now()))
+    }
+
+    /// Cache `name` as missing, found so by a lookup started at `epoch`.
+    pub(crate) fn note_missing(&self, name: &str, epoch: u64) {
+        // Past a deadline lookups come back empty without asking the runtime.
+        if !ENABLED.load(Ordering::Acquire) || timeout::timed_out() {
+            return;
+        }
+        let id = key(self.get_tag_id());
+        let mut cache = cache();
+        if EPOCH.load(Ordering::Acquire) != epoch {
+            return;
+        }
+        if let Some(cache) = cache.as_mut() {
+            cache.insert(id, self.name(), name, Instant::now());
+        }
+    }
+}
+
+impl Client {
+    /// Cache blob misses as `options` describes (see `negcache`), replacing any
+    /// earlier cache.
+    pub fn enable_negative_cache(options: NegativeCacheOptions) {
+        let remote = options.poll_remote.map(|interval| {
+            let filter = EventFilter::new()
+                .kinds(&[EventKind::BlobPut, EventKind::TagCreated])
+                .poll_remote(interval);
+            Client::subscribe_with(filter, |event| {
+                if let Some(cache) = cache().as_mut() {
+                    cache.observe(event);
+                }
+                EPOCH.fetch_add(1, Ordering::AcqRel);
+            })
+        });
+        *cache() = Some(Cache::new(&options, remote));
+        ENABLED.store(true, Ordering::Release);
+    }
+
+    /// Stop caching misses and forget those cached.
+    pub fn disable_negative_cache() {
+        ENABLED.store(false, Ordering::Release);
+        let old = cache().take();
+        // Dropping the remote subscription joins its poller, which may be
+        // waiting on the cache lock.
+        drop(old);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negative_cache() {
+        let options = NegativeCacheOptions {
+            ttl: Duration::from_secs(10),
+            capacity: 3,
+            poll_remote: None,
+        };
+        let mut cache = Cache::new(&options, None);
+        let now = Instant::now();
+        let id = (7, 1);
+        cache.insert(id, "ingest", "a", now);
+        cache.insert(id, "ingest", "b", now);
+        assert!(cache.contains(id, "a", now));
+        assert!(!cache.contains((7, 2), "a", now));
+        assert!(!cache.contains(id, "a", now + Duration::from_secs(11)));
+        assert_eq!(cache.len, 1);
+
+        let put = |tag: &str, blob: &str| Event::BlobPut {
+            tag: tag.into(),
+            blob: blob.into(),
+            offset: 0,
+            size: 1,
+        };
+        cache.observe(&put("ingest", "b"));
+        assert!(!cache.contains(id, "b", now));
+        cache.insert(id, "ingest", "c", now);
+        cache.observe(&put("#7.1", "c"));
+        assert!(!cache.contains(id, "c", now));
+
+        // A full cache with nothing expired starts over.
+        for blob in ["x", "y", "z", "w"] {
+            cache.insert(id, "ingest", blob, now);
+        }
+        assert_eq!(cache.len, 1);
+        cache.observe(&Event::TagDeleted {
+            tag: "ingest".into(),
+        });
+        assert_eq!(cache.len, 0);
+    }
+}
@@ -0,0 +1,226 @@
+//! Node.js addon over N-API (feature `node`).
+//!
+//! Built with the feature, the crate's cdylib is also an N-API module: copy
+//! `libwrp_cte_rs.so` to `wrp_cte.node` and `require` it. Unlike the C ABI of
+//! `ffi_c`, nothing on the JavaScript side handles pointers or parses JSON:
+//!
+//! ```text
+//! const cte = require('./wrp_cte.node');
+//! cte.init();                                   // 'client_attached' or 'embedded_runtime'
+//! const tag = new cte.Tag('sim');
+//! await tag.putBlob('step/1', buffer);          // optional offset
+//! const data = await tag.getBlob('step/1');     // optional offset and size; a Buffer
+//! await tag.getBlobSize('step/1');
+//! await tag.listBlobs();
+//! await tag.delBlob('step/1');
+//! await cte.delTag('sim');
+//! ```
+//!
+//! Blob calls return promises and run on the libuv thread pool, as they wait on
+//! the runtime. `putBlob` reads the caller's `Buffer` in place, so it must not
+//! be modified until the promise settles, and `getBlob` hands the bytes it read
+//! to JavaScript without copying them again. Sizes and offsets are numbers,
+//! exact up to 2^53 bytes. Failures reject with an `Error` whose message is the
+//! `CteError` and whose `code` is `InvalidArg` for bad arguments and
+//! `GenericFailure` otherwise.
+
+use std::sync::Arc;
+
+use napi::bindgen_prelude::{AsyncTask, Buffer, ToNapiValue, TypeName};
+use napi::{Env, Error, Status, Task};
+use napi_derive::napi;
+
+use crate::{Client, CteError, GetOptions, PutOptions, RuntimeState};
+
+fn error(e: CteError) -> Error {
+    let status = match e {
+        CteError::InvalidArgument(_) | CteError::InvalidName { .. } => Status::InvalidArg,
+        _ => Status::GenericFailure,
+    };
+    Error::new(status, e.to_string())
+}
+
+fn unsigned(what: &str, n: i64) -> Result<u64, CteError> {
+    u64::try_from(n).map_err(|_| CteError::InvalidArgument(format!("negative {}", what)))
+}
+
+fn number(n: u64) -> Result<i64, CteError> {
+    i64::try_from(n).map_err(|_| CteError::InvalidArgument(format!("{} is too large", n)))
+}
+
+/// A blocking call run on the libuv thread pool.
+pub struct Call<T>(Option<Box<dyn FnOnce() -> Result<T, CteError> + Send>>);
+
+fn call<T>(f: impl FnOnce() -> Result<T, CteError> + Send + 'static) -> AsyncTask<Call<T>>
+where
+    Call<T>: Task,
+{
+    AsyncTask::new(Call(Some(Box::new(f))))
+}
+
+impl<T: ToNapiValue + TypeName + Send + 'static> Task for Call<T> {
+    type Output = T;
+    type JsValue = T;
+
+    fn compute(&mut self) -> napi::Result<T> {
+        let f = self.0.take().expect("a task is computed once");
+        f().map_err(error)
+    }
+
+    fn resolve(&mut self, _: Env, output: T) -> napi::Result<T> {
+        Ok(output)
+    }
+}
+
+/// `Tag::putBlob`, keeping the caller's `Buffer` (and the reference holding
+/// it) on the JavaScript thread while the pool reads it.
+pub struct PutBlob {
+    tag: Arc<crate::Tag>,
+    name: String,
+    data: Buffer,
+    offset: u64,
+}
+
+impl Task for PutBlob {
+    type Output = ();
+    type JsValue = ();
+
+    fn compute(&mut self) -> napi::Result<()> {
+        let options = PutOptions {
+            offset: self.offset,
+            ..Default::default()
+        };
+        self.tag
+            .put(&self.name, &self.data, &options)
+            .map(drop)
+            .map_err(error)
+    }
+
+    fn resolve(&mut self, _: Env, _: ()) -> napi::Result<()> {
+        Ok(())
+    }
+}
+
+/// Initialize the client as `init` does, from `configPath` or the
+/// environment's configuration. Returns the runtime state reached.
+#[napi]
+pub fn init(config_path: Option<String>) -> napi::Result<String> {
+    let state = crate::try_init(config_path.as_deref().unwrap_or("")).map_err(error)?;
+    Ok(match state {
+        RuntimeState::Uninitialized => "uninitialized",
+        RuntimeState::ClientAttached => "client_attached",
+        RuntimeState::EmbeddedRuntime => "embedded_runtime",
+    }
+    .into())
+}
+
+/// Register a file storage target of `size` bytes.
+#[napi]
+pub fn register_target(path: String, size: i64) -> napi::Result<bool> {
+    let size = unsigned("size", size).map_err(error)?;
+    Ok(Client::register_target(&path, size))
+}
+
+#[napi]
+pub fn tag_exists(name: String) -> bool {
+    Client::tag_exists(&name)
+}
+
+/// Delete a tag and its blobs; resolves to whether it existed.
+#[napi(ts_return_type = "Promise<boolean>")]
+pub fn del_tag(name: String) -> AsyncTask<Call<bool>> {
+    call(move || Ok(Client::del_tag(&name)))
+}
+
+/// A tag, opened (and created if missing) by `new Tag(name)`.
+#[napi(js_name = "Tag")]
+pub struct JsTag {
+    tag: Arc<crate::Tag>,
+}
+
+#[napi]
+impl JsTag {
+    #[napi(constructor)]
+    pub fn new(name: String) -> napi::Result<Self> {
+        let tag = crate::Tag::try_new(&name).map_err(error)?;
+        Ok(Self { tag: Arc::new(tag) })
+    }
+
+    #[napi(getter)]
+    pub fn name(&self) -> String {
+        self.tag.name().to_string()
+    }
+
+    /// Write `data` into blob `name` at `offset` (0 if not given).
+    #[napi(ts_return_type = "Promise<void>")]
+    pub fn put_blob(
+        &self,
+        name: String,
+        data: Buffer,
+        offset: Option<i64>,
+    ) -> napi::Result<AsyncTask<PutBlob>> {
+        let offset = unsigned("offset", offset.unwrap_or(0)).map_err(error)?;
+        Ok(AsyncTask::new(PutBlob {
+            tag: Arc::clone(&self.tag),
+            name,
+            data,
+            offset,
+        }))
+    }
+
+    /// Read `size` bytes of blob `name` from `offset`, to the end of the blob
+    /// if `size` isn't given.
+    #[napi(ts_return_type = "Promise<Buffer>")]
+    pub fn get_blob(
+        &self,
+        name: String,
+        offset: Option<i64>,
+        size: Option<i64>,
+    ) -> napi::Result<AsyncTask<Call<Buffer>>> {
+        let options = GetOptions {
+            offset: unsigned("offset", offset.unwrap_or(0)).map_err(error)?,
+            size: size
+                .map(|n| unsigned("size", n))
+                .transpose()
+                .map_err(error)?,
+            ..Default::default()
+        };
+        let tag = Arc::clone(&self.tag);
+        Ok(call(move || tag.get(&name, &options).map(Buffer::from)))
+    }
+
+    /// Size of blob `name` in bytes; 0 if it doesn't exist.
+    #[napi(ts_return_type = "Promise<number>")]
+    pub fn get_blob_size(&self, name: String) -> AsyncTask<Call<i64>> {
+        let tag = Arc::clone(&self.tag);
+        call(move || number(tag.get_blob_size(&name)))
+    }
+
+    #[napi(ts_return_type = "Promise<string[]>")]
+    pub fn list_blobs(&self) -> AsyncTask<Call<Vec<String>>> {
+        let tag = Arc::clone(&self.tag);
+        call(move || tag.try_get_contained_blobs())
+    }
+
+    /// Delete blob `name`; resolves to whether it existed.
+    #[napi(ts_return_type = "Promise<boolean>")]
+    pub fn del_blob(&self, name: String) -> AsyncTask<Call<bool>> {
+        let tag = Arc::clone(&self.tag);
+        call(move || Ok(tag.del_blob(&name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_js_numbers() {
+        assert_eq!(unsigned("offset", 7).unwrap(), 7);
+        assert!(unsigned("offset", -1).is_err());
+        assert_eq!(number(1 << 40).unwrap(), 1 << 40);
+        assert!(number(u64::MAX).is_err());
+        let e = error(CteError::InvalidArgument("x".into()));
+        assert_eq!(e.status, Status::InvalidArg);
+    }
+}
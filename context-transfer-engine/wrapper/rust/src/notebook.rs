@@ -0,0 +1,517 @@
+//! Local JSON service for notebooks (feature `notebook`).
+//!
+//! `Client::serve_notebook`, or `clio serve --notebook`, answers the requests
+//! an interactive session makes while exploring a deployment, so Python and
+//! JavaScript helpers can do it over plain HTTP without linking the client:
+//!
+//! - `GET /api/tags`: the names of all tags, as a JSON array.
+//! - `GET /api/tags/<tag>?prefix=P&delimiter=D`: one level of the tag's
+//!   blobs, as `Tag::list` returns it; with `summaries=1`, also the summary of
+//!   each blob that has one (see `summary`).
+//! - `GET /api/tags/<tag>/blobs/<blob>?bytes=N`: the blob's size and a
+//!   preview of its first `N` bytes (`NotebookOptions::preview_bytes` if not
+//!   given), decoded as a table when they look like CSV or TSV, as text when
+//!   they are UTF-8, and as hex otherwise.
+//! - `GET /api/targets`: every target with its score, free space and traffic,
+//!   and the same summed per tier (targets of equal score).
+//! - `GET /api/occupancy.svg`: free space per target as a bar chart, fastest
+//!   tier first, for notebooks to display as is. The runtime reports only what
+//!   is left on a target, not its capacity.
+//!
+//! Path segments are percent-encoded and `/` is allowed in the blob part.
+//! Nothing can be written, and wrapper sidecars are never listed or shown.
+//! Every request must carry `NotebookOptions::token`, as `?token=...` or an
+//! `Authorization: token ...` header, when one is set; without one the service
+//! only listens on loopback addresses.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::net::{SocketAddr, ToSocketAddrs};
+
+use crate::archive::unescape;
+use crate::ffi_c::json_string;
+use crate::http::{self, same_secret, Request, Response};
+use crate::{meta, BlobSummary, Client, CteError, GetOptions, Tag, TargetInfo};
+
+/// Settings for `Client::serve_notebook`.
+#[derive(Debug, Clone)]
+pub struct NotebookOptions {
+    /// Secret every request must present; `None` to accept any local request.
+    pub token: Option<String>,
+    /// Bytes previewed when a request doesn't ask for a number.
+    pub preview_bytes: u64,
+    /// Most bytes a preview may ask for.
+    pub max_preview_bytes: u64,
+}
+
+impl Default for NotebookOptions {
+    fn default() -> Self {
+        Self {
+            token: None,
+            preview_bytes: 4096,
+            max_preview_bytes: 1 << 20,
+        }
+    }
+}
+
+/// What the first bytes of a blob look like.
+#[derive(Debug, PartialEq)]
+enum Preview {
+    /// Rows of fields split at a comma or tab, without unquoting.
+    Table(Vec<Vec<String>>),
+    Text(String),
+    Binary,
+}
+
+/// The fields of `lines` if they all split into the same number (two or
+/// more) at `delimiter`.
+fn split_table(lines: &[&str], delimiter: char) -> Option<Vec<Vec<String>>> {
+    let rows: Vec<Vec<String>> = lines
+        .iter()
+        .map(|line| line.split(delimiter).map(str::to_string).collect())
+        .collect();
+    let width = rows.first()?.len();
+    (width >= 2 && rows.iter().all(|r| r.len() == width)).then_some(rows)
+}
+
+/// Decode `data`, the first bytes of a blob, cut short of its end if
+/// `truncated`.
+fn preview(data: &[u8], truncated: bool) -> Preview {
+    let text = match std::str::from_utf8(data) {
+        Ok(text) => text,
+        // A character cut in two by the preview's end.
+        Err(e) if truncated && e.error_len().is_none() => {
+            std::str::from_utf8(&data[..e.valid_up_to()]).unwrap_or_default()
+        }
+        Err(_) => return Preview::Binary,
+    };
+    if text
+        .chars()
+        .any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t'))
+    {
+        return Preview::Binary;
+    }
+    let mut lines: Vec<&str> = text.lines().collect();
+    if truncated && !text.ends_with('\n') {
+        // The last line may be cut short.
+        lines.pop();
+    }
+    if lines.len() >= 2 {
+        for delimiter in [',', '\t'] {
+            if let Some(rows) = split_table(&lines, delimiter) {
+                return Preview::Table(rows);
+            }
+        }
+    }
+    Preview::Text(text.to_string())
+}
+
+fn json_array<'a>(items: impl IntoIterator<Item = &'a String>) -> String {
+    let items: Vec<String> = items.into_iter().map(|s| json_string(s)).collect();
+    format!("[{}]", items.join(","))
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().fold(String::new(), |mut out, b| {
+        let _ = write!(out, "{:02x}", b);
+        out
+    })
+}
+
+/// Escape `s` for SVG text.
+fn summary_json(summary: &BlobSummary) -> String {
+    let columns: Vec<String> = summary
+        .columns
+        .iter()
+        .map(|c| {
+            format!(
+                "{{\"name\":{},\"min\":{},\"max\":{}}}",
+                json_string(&c.name),
+                c.min,
+                c.max
+            )
+        })
+        .collect();
+    let extra: Vec<String> = summary
+        .extra
+        .iter()
+        .map(|(k, v)| format!("{}:{}", json_string(k), json_string(v)))
+        .collect();
+    format!(
+        "{{\"head\":{},\"rows\":{},\"columns\":[{}],\"extra\":{{{}}}}}",
+        match std::str::from_utf8(&summary.head) {
+            Ok(text) => json_string(text),
+            Err(_) => json_string(&hex(&summary.head)),
+        },
+        summary.rows.map_or("null".to_string(), |r| r.to_string()),
+        columns.join(","),
+        extra.join(",")
+    )
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// `n` bytes with a binary unit.
+fn human(n: u64) -> String {
+    let mut value = n as f64;
+    for unit in ["B", "KiB", "MiB", "GiB", "TiB"] {
+        if value < 1024.0 || unit == "TiB" {
+            return format!("{:.1} {}", value, unit);
+        }
+        value /= 1024.0;
+    }
+    unreachable!()
+}
+
+/// Targets by tier, fastest first, each tier's sorted by name.
+fn tiers(targets: &[TargetInfo]) -> Vec<(String, Vec<&TargetInfo>)> {
+    let mut tiers: BTreeMap<String, Vec<&TargetInfo>> = BTreeMap::new();
+    for t in targets {
+        tiers.entry(format!("{:.3}", t.score)).or_default().push(t);
+    }
+    let mut tiers: Vec<(String, Vec<&TargetInfo>)> = tiers.into_iter().rev().collect();
+    for (_, members) in &mut tiers {
+        members.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+    tiers
+}
+
+fn targets_json(targets: &[TargetInfo]) -> String {
+    let target = |t: &TargetInfo| {
+        format!(
+            "{{\"name\":{},\"score\":{},\"remaining_space\":{},\"bytes_read\":{},\"bytes_written\":{}}}",
+            json_string(&t.name),
+            t.score,
+            t.remaining_space,
+            t.bytes_read,
+            t.bytes_written
+        )
+    };
+    let all: Vec<String> = targets.iter().map(target).collect();
+    let tiers: Vec<String> = tiers(targets)
+        .iter()
+        .map(|(score, members)| {
+            let sum = |f: fn(&TargetInfo) -> u64| members.iter().map(|t| f(t)).sum::<u64>();
+            let names: Vec<String> = members.iter().map(|t| t.name.clone()).collect();
+            format!(
+                "{{\"score\":{},\"targets\":{},\"remaining_space\":{},\"bytes_read\":{},\"bytes_written\":{}}}",
+                score,
+                json_array(&names),
+                sum(|t| t.remaining_space),
+                sum(|t| t.bytes_read),
+                sum(|t| t.bytes_written)
+            )
+        })
+        .collect();
+    format!(
+        "{{\"targets\":[{}],\"tiers\":[{}]}}",
+        all.join(","),
+        tiers.join(",")
+    )
+}
+
+/// Bar chart of free space per target, grouped by tier.
+fn occupancy_svg(targets: &[TargetInfo]) -> String {
+    const ROW: usize = 24;
+    const LABEL: usize = 260;
+    const BAR: usize = 400;
+    let most = targets
+        .iter()
+        .map(|t| t.remaining_space)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let rows: Vec<&TargetInfo> = tiers(targets).into_iter().flat_map(|(_, m)| m).collect();
+    let height = ROW * (rows.len() + 1);
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+         font-family=\"sans-serif\" font-size=\"12\">\n\
+         <text x=\"4\" y=\"16\">free space per target (score)</text>\n",
+        LABEL + BAR + 100,
+        height
+    );
+    for (i, t) in rows.iter().enumerate() {
+        let y = ROW * (i + 1);
+        let width = (t.remaining_space as f64 / most as f64 * BAR as f64).round() as usize;
+        let _ = writeln!(
+            svg,
+            "<text x=\"4\" y=\"{}\">{} ({:.3})</text>\
+             <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#4c78a8\"/>\
+             <text x=\"{}\" y=\"{}\">{}</text>",
+            y + 16,
+            xml_escape(&t.name),
+            t.score,
+            LABEL,
+            y + 4,
+            width,
+            ROW - 8,
+            LABEL + width + 6,
+            y + 16,
+            human(t.remaining_space)
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn not_found() -> Response {
+    Response::new("404 Not Found", "not found\n")
+}
+
+fn json(body: String) -> Response {
+    Response::new("200 OK", body).header("Content-Type", "application/json")
+}
+
+fn failed(e: &CteError) -> Response {
+    match e {
+        CteError::NotFound { .. } => not_found(),
+        CteError::InvalidArgument(_) | CteError::InvalidName { .. } => {
+            Response::new("400 Bad Request", format!("{}\n", e))
+        }
+        _ => Response::new("500 Internal Server Error", format!("{}\n", e)),
+    }
+}
+
+struct Notebook {
+    options: NotebookOptions,
+}
+
+impl Notebook {
+    fn authorized(&self, request: &Request) -> bool {
+        let Some(token) = &self.options.token else {
+            return true;
+        };
+        let header = request
+            .header("Authorization")
+            .and_then(|v| v.strip_prefix("token "))
+            .map(str::to_string);
+        header
+            .or_else(|| request.param("token"))
+            .is_some_and(|given| same_secret(given.trim(), token))
+    }
+
+    fn handle(&self, request: &Request) -> Response {
+        if request.method != "GET" && request.method != "HEAD" {
+            return Response::new("405 Method Not Allowed", "read-only\n")
+                .header("Allow", "GET, HEAD");
+        }
+        if !self.authorized(request) {
+            return Response::new("403 Forbidden", "missing or wrong token\n");
+        }
+        match request.path.as_str() {
+            "/api/tags" => {
+                let tags = Client::tag_query(".*", 0);
+                return json(json_array(tags.iter().filter(|t| !meta::is_reserved(t))));
+            }
+            "/api/targets" => return json(targets_json(&Client::list_targets())),
+            "/api/occupancy.svg" => {
+                return Response::new("200 OK", occupancy_svg(&Client::list_targets()))
+                    .header("Content-Type", "image/svg+xml")
+            }
+            _ => {}
+        }
+        let Some(rest) = request.path.strip_prefix("/api/tags/") else {
+            return not_found();
+        };
+        let (tag, blob) = match rest.split_once("/blobs/") {
+            Some((tag, blob)) => (tag, Some(blob)),
+            None => (rest, None),
+        };
+        let Ok(tag) = unescape(tag) else {
+            return not_found();
+        };
+        if tag.is_empty() || meta::is_reserved(&tag) || !Client::tag_exists(&tag) {
+            return not_found();
+        }
+        let tag = match Tag::try_new(&tag) {
+            Ok(tag) => tag,
+            Err(e) => return failed(&e),
+        };
+        let result = match blob {
+            None => self.listing(&tag, request),
+            Some(blob) => match unescape(blob) {
+                Ok(blob) if !blob.is_empty() && !meta::is_reserved(&blob) => {
+                    self.preview(&tag, &blob, request)
+                }
+                _ => return not_found(),
+            },
+        };
+        result.unwrap_or_else(|e| failed(&e))
+    }
+
+    fn listing(&self, tag: &Tag, request: &Request) -> Result<Response, CteError> {
+        let prefix = request.param("prefix").unwrap_or_default();
+        let delimiter = request.param("delimiter").unwrap_or_default();
+        let listing = tag.list(&prefix, &delimiter)?;
+        let mut body = format!(
+            "{{\"common_prefixes\":{},\"blobs\":{}",
+            json_array(&listing.common_prefixes),
+            json_array(&listing.blobs)
+        );
+        if request.param("summaries").is_some_and(|v| v == "1") {
+            let mut summaries = Vec::new();
+            for blob in &listing.blobs {
+                if let Some(summary) = tag.blob_summary(blob)? {
+                    summaries.push(format!("{}:{}", json_string(blob), summary_json(&summary)));
+                }
+            }
+            let _ = write!(body, ",\"summaries\":{{{}}}", summaries.join(","));
+        }
+        body.push('}');
+        Ok(json(body))
+    }
+
+    fn preview(&self, tag: &Tag, blob: &str, request: &Request) -> Result<Response, CteError> {
+        let bytes = match request.param("bytes") {
+            Some(v) => v
+                .parse::<u64>()
+                .map_err(|_| CteError::InvalidArgument(format!("bad byte count '{}'", v)))?,
+            None => self.options.preview_bytes,
+        }
+        .min(self.options.max_preview_bytes);
+        let stat = tag.stat_blob(blob)?.ok_or_else(|| CteError::NotFound {
+            blob: blob.to_string(),
+        })?;
+        let data = if bytes == 0 {
+            Vec::new()
+        } else {
+            let options = GetOptions {
+                size: Some(bytes.min(stat.size)),
+                ..Default::default()
+            };
+            tag.get(blob, &options)?
+        };
+        let truncated = (data.len() as u64) < stat.size;
+        let decoded = match preview(&data, truncated) {
+            Preview::Table(rows) => {
+                let rows: Vec<String> = rows.iter().map(json_array).collect();
+                format!("\"kind\":\"table\",\"rows\":[{}]", rows.join(","))
+            }
+            Preview::Text(text) => format!("\"kind\":\"text\",\"text\":{}", json_string(&text)),
+            Preview::Binary => format!("\"kind\":\"binary\",\"hex\":{}", json_string(&hex(&data))),
+        };
+        Ok(json(format!(
+            "{{\"tag\":{},\"blob\":{},\"size\":{},\"score\":{},\"preview_bytes\":{},\
+             \"truncated\":{},{}}}",
+            json_string(&tag.name),
+            json_string(blob),
+            stat.size,
+            stat.score,
+            data.len(),
+            truncated,
+            decoded
+        )))
+    }
+}
+
+/// A service from `Client::serve_notebook`. Stops accepting when dropped.
+pub struct NotebookServer {
+    server: http::Server,
+}
+
+impl NotebookServer {
+    /// The address the service listens on, with the port chosen if 0 was asked.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.server.local_addr()
+    }
+}
+
+impl Client {
+    /// Serve the notebook API at `addr` (see `notebook`) until the returned
+    /// server is dropped. Fails with `InvalidArgument` for an address that
+    /// isn't loopback unless `options.token` is set.
+    pub fn serve_notebook(
+        addr: impl ToSocketAddrs,
+        options: &NotebookOptions,
+    ) -> Result<NotebookServer, CteError> {
+        let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+        if options.token.is_none() && addrs.iter().any(|a| !a.ip().is_loopback()) {
+            return Err(CteError::InvalidArgument(
+                "a notebook service on a non-loopback address needs a token".into(),
+            ));
+        }
+        if options.token.as_deref() == Some("") {
+            return Err(CteError::InvalidArgument("empty notebook token".into()));
+        }
+        let notebook = Notebook {
+            options: options.clone(),
+        };
+        let server = http::serve(&addrs[..], move |request| notebook.handle(request))?;
+        Ok(NotebookServer { server })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notebook_rendering() {
+        assert_eq!(
+            preview(b"step,loss\n1,0.9\n2,0.7\n3,0.", true),
+            Preview::Table(vec![
+                vec!["step".into(), "loss".into()],
+                vec!["1".into(), "0.9".into()],
+                vec!["2".into(), "0.7".into()],
+            ])
+        );
+        assert!(matches!(preview(b"a\tb\nc\td\n", false), Preview::Table(_)));
+        assert_eq!(
+            preview("hello, w\u{e9}".as_bytes(), false),
+            Preview::Text("hello, w\u{e9}".into())
+        );
+        // A character cut by the end of the preview is dropped.
+        assert_eq!(
+            preview(&"caf\u{e9}".as_bytes()[..4], true),
+            Preview::Text("caf".into())
+        );
+        assert_eq!(preview(b"\x89PNG\r\n\x1a\n", true), Preview::Binary);
+        assert_eq!(hex(b"\x00\xff"), "00ff");
+        let summary = BlobSummary {
+            head: b"step,loss".to_vec(),
+            rows: Some(2),
+            columns: vec![crate::ColumnSummary {
+                name: "loss".into(),
+                min: 0.7,
+                max: 0.9,
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            summary_json(&summary),
+            "{\"head\":\"step,loss\",\"rows\":2,\"columns\":[{\"name\":\"loss\",\"min\":0.7,\"max\":0.9}],\"extra\":{}}"
+        );
+
+        let target = |name: &str, score, remaining_space| TargetInfo {
+            name: name.into(),
+            score,
+            remaining_space,
+            bytes_read: 0,
+            bytes_written: 10,
+        };
+        let targets = [
+            target("hdd<0>", 0.1, 1 << 30),
+            target("ram1", 1.0, 1 << 20),
+            target("ram0", 1.0, 1 << 20),
+        ];
+        let tiers = tiers(&targets);
+        assert_eq!(tiers[0].0, "1.000");
+        assert_eq!(tiers[0].1[0].name, "ram0");
+        let json = targets_json(&targets);
+        assert!(json.contains(
+            "{\"score\":1.000,\"targets\":[\"ram0\",\"ram1\"],\"remaining_space\":2097152,\
+             \"bytes_read\":0,\"bytes_written\":20}"
+        ));
+        let svg = occupancy_svg(&targets);
+        assert!(svg.contains("hdd&lt;0&gt; (0.100)") && svg.contains("1.0 GiB"));
+        assert!(svg.find("ram0").unwrap() < svg.find("hdd").unwrap());
+
+        assert!(same_secret("s3cret", "s3cret"));
+        assert!(!same_secret("s3cret", "s3creT") && !same_secret("s3", "s3cret"));
+    }
+}
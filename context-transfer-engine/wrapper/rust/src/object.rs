@@ -0,0 +1,245 @@
+//! Typed objects stored as blobs.
+//!
+//! `Tag::put_object` serializes a value with serde and writes it as a blob,
+//! recording the format and the Rust type name in the blob's attributes (see
+//! `attrs`); `Tag::get_object` reads it back in the recorded format. JSON is
+//! built in with the `objects` feature, and the `bincode` and `msgpack`
+//! features add those formats.
+//!
+//! A type that implements `CteBlob`, usually through `#[derive(CteBlob)]`
+//! (the `derive` feature), also records its schema, its fields' names and
+//! types, with `Tag::put_typed`. `Tag::get_typed` refuses a blob written with
+//! a different schema with `CteError::SchemaMismatch`, rather than decoding
+//! it into the wrong fields or failing somewhere inside the decoder.
+//!
+//! The data and its attributes are written one after the other, so a reader
+//! racing a put that changes a blob's format can see the old attributes.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::{CteError, GetOptions, PutOptions, Tag};
+
+/// Attribute holding the format an object was written in.
+pub const FORMAT_ATTR: &str = "cte.format";
+/// Attribute holding the Rust type name an object was written from.
+pub const TYPE_ATTR: &str = "cte.type";
+/// Attribute holding the `CteBlob::SCHEMA` of an object written with
+/// `put_typed`; empty for `put_object`.
+pub const SCHEMA_ATTR: &str = "cte.schema";
+
+/// A type whose objects record their schema (see `object`).
+pub trait CteBlob {
+    /// The type's name and fields, e.g. `Particle{id:u64,pos:[f32;3]}`.
+    const SCHEMA: &'static str;
+}
+
+/// Encoding of a typed object.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ObjectFormat {
+    #[default]
+    Json,
+    /// bincode 1.x with its default options. Needs the `bincode` feature.
+    Bincode,
+    /// MessagePack, with struct fields by name. Needs the `msgpack` feature.
+    MessagePack,
+}
+
+impl ObjectFormat {
+    /// The name recorded in `FORMAT_ATTR`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ObjectFormat::Json => "json",
+            ObjectFormat::Bincode => "bincode",
+            ObjectFormat::MessagePack => "msgpack",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            ObjectFormat::Json,
+            ObjectFormat::Bincode,
+            ObjectFormat::MessagePack,
+        ]
+        .into_iter()
+        .find(|f| f.name() == name)
+    }
+
+    fn encode<T: Serialize + ?Sized>(self, value: &T) -> Result<Vec<u8>, CteError> {
+        let failed = |e: String| {
+            CteError::InvalidArgument(format!(
+                "can't encode {} as {}: {}",
+                std::any::type_name::<T>(),
+                self.name(),
+                e
+            ))
+        };
+        match self {
+            ObjectFormat::Json => serde_json::to_vec(value).map_err(|e| failed(e.to_string())),
+            #[cfg(feature = "bincode")]
+            ObjectFormat::Bincode => bincode::serialize(value).map_err(|e| failed(e.to_string())),
+            #[cfg(feature = "msgpack")]
+            ObjectFormat::MessagePack => {
+                rmp_serde::to_vec_named(value).map_err(|e| failed(e.to_string()))
+            }
+            #[allow(unreachable_patterns)]
+            other => Err(missing_feature(other)),
+        }
+    }
+
+    fn decode<T: DeserializeOwned>(self, blob: &str, data: &[u8]) -> Result<T, CteError> {
+        let failed = |e: String| {
+            CteError::InvalidArgument(format!(
+                "'{}' doesn't decode from {} as {}: {}",
+                blob,
+                self.name(),
+                std::any::type_name::<T>(),
+                e
+            ))
+        };
+        match self {
+            ObjectFormat::Json => serde_json::from_slice(data).map_err(|e| failed(e.to_string())),
+            #[cfg(feature = "bincode")]
+            ObjectFormat::Bincode => bincode::deserialize(data).map_err(|e| failed(e.to_string())),
+            #[cfg(feature = "msgpack")]
+            ObjectFormat::MessagePack => {
+                rmp_serde::from_slice(data).map_err(|e| failed(e.to_string()))
+            }
+            #[allow(unreachable_patterns)]
+            other => Err(missing_feature(other)),
+        }
+    }
+}
+
+fn missing_feature(format: ObjectFormat) -> CteError {
+    let feature = match format {
+        ObjectFormat::Bincode => "bincode",
+        _ => "msgpack",
+    };
+    CteError::Unsupported(format!(
+        "{:?} objects need the `{}` feature",
+        format, feature
+    ))
+}
+
+impl Tag {
+    /// Write `value` as blob `name` in the default format, JSON (see
+    /// `object`). Returns the new generation.
+    pub fn put_object<T: Serialize + ?Sized>(
+        &self,
+        name: &str,
+        value: &T,
+    ) -> Result<u64, CteError> {
+        self.put_object_as(name, value, ObjectFormat::default())
+    }
+
+    /// `put_object` in `format`.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(tag = %self.name, blob = name, format = format.name()),
+            err
+        )
+    )]
+    pub fn put_object_as<T: Serialize + ?Sized>(
+        &self,
+        name: &str,
+        value: &T,
+        format: ObjectFormat,
+    ) -> Result<u64, CteError> {
+        self.put_encoded(name, value, format, "")
+    }
+
+    /// Read blob `name` as a `T`, in the format it was written in (JSON if it
+    /// doesn't say).
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(tag = %self.name, blob = name), err)
+    )]
+    pub fn get_object<T: DeserializeOwned>(&self, name: &str) -> Result<T, CteError> {
+        let format = self.object_attr(name, FORMAT_ATTR)?;
+        let format = match format.as_deref() {
+            None => ObjectFormat::default(),
+            Some(f) => ObjectFormat::from_name(f).ok_or_else(|| {
+                CteError::Unsupported(format!("'{}' is in unknown object format '{}'", name, f))
+            })?,
+        };
+        let data = self.get(name, &GetOptions::default())?;
+        format.decode(name, &data)
+    }
+
+    /// `put_object_as`, also recording `T::SCHEMA` for `get_typed` to check.
+    pub fn put_typed<T: Serialize + CteBlob>(
+        &self,
+        name: &str,
+        value: &T,
+        format: ObjectFormat,
+    ) -> Result<u64, CteError> {
+        self.put_encoded(name, value, format, T::SCHEMA)
+    }
+
+    /// `get_object`, failing with `CteError::SchemaMismatch` unless the blob
+    /// was written by `put_typed` with the same schema as `T`'s.
+    pub fn get_typed<T: DeserializeOwned + CteBlob>(&self, name: &str) -> Result<T, CteError> {
+        let schema = self.object_attr(name, SCHEMA_ATTR)?.unwrap_or_default();
+        if schema != T::SCHEMA {
+            return Err(CteError::SchemaMismatch {
+                blob: name.to_string(),
+                expected: T::SCHEMA.to_string(),
+                actual: schema,
+            });
+        }
+        self.get_object(name)
+    }
+
+    fn put_encoded<T: Serialize + ?Sized>(
+        &self,
+        name: &str,
+        value: &T,
+        format: ObjectFormat,
+        schema: &str,
+    ) -> Result<u64, CteError> {
+        let data = format.encode(value)?;
+        let generation = self.put(name, &data, &PutOptions::default())?;
+        self.set_blob_attrs(
+            name,
+            &[
+                (FORMAT_ATTR, format.name()),
+                (TYPE_ATTR, std::any::type_name::<T>()),
+                (SCHEMA_ATTR, schema),
+            ],
+        )?;
+        Ok(generation)
+    }
+
+    fn object_attr(&self, name: &str, key: &str) -> Result<Option<String>, CteError> {
+        Ok(self
+            .get_blob_attrs(name)?
+            .into_iter()
+            .find_map(|(k, v)| (k == key).then_some(v)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_object_format() {
+        for format in [
+            ObjectFormat::Json,
+            ObjectFormat::Bincode,
+            ObjectFormat::MessagePack,
+        ] {
+            assert_eq!(ObjectFormat::from_name(format.name()), Some(format));
+        }
+        assert_eq!(ObjectFormat::from_name("cbor"), None);
+        let data = ObjectFormat::Json.encode(&[1u32, 2, 3]).unwrap();
+        assert_eq!(data, b"[1,2,3]");
+        let back: Vec<u32> = ObjectFormat::Json.decode("b", &data).unwrap();
+        assert_eq!(back, [1, 2, 3]);
+        assert!(ObjectFormat::Json.decode::<String>("b", &data).is_err());
+    }
+}
@@ -0,0 +1,338 @@
+//! Per-tag change log.
+//!
+//! A tag opts in with `Tag::enable_change_log`, which creates the reserved
+//! `.cte/oplog` blob. From then on every put and reorganize made through the
+//! wrapper appends a record to it. A change's sequence number is the byte offset
+//! of its record in the log, so sequence numbers increase monotonically and
+//! `changes_since` can read just the tail instead of the whole log.
+//!
+//! Handles probe for the log on their first mutation and cache the answer, so a
+//! handle opened before the log was enabled keeps not logging until reopened.
+//!
+//! Each record carries the writing process's instance id, so a process tailing
+//! the log can tell its own changes from everyone else's, and the process's
+//! client label if it was initialized with one (see `ClientOptions`).
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::meta::{read_f32, read_str, read_u64, FieldReader, FieldWriter, RESERVED_PREFIX};
+use crate::{ffi, ffi_guard, CteError, Tag};
+
+const OPLOG_NAME: &str = ".cte/oplog";
+
+const FIELD_KIND: u8 = 1;
+const FIELD_BLOB: u8 = 2;
+const FIELD_OFFSET: u8 = 3;
+const FIELD_SIZE: u8 = 4;
+const FIELD_SCORE: u8 = 5;
+const FIELD_WRITER: u8 = 6;
+const FIELD_LABEL: u8 = 7;
+
+const KIND_PUT: u64 = 1;
+const KIND_REORGANIZE: u64 = 2;
+const KIND_DELETE: u64 = 3;
+
+/// Serializes offset lookup and append of log records within this process.
+static APPEND_LOCK: Mutex<()> = Mutex::new(());
+
+/// What happened to a blob.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeKind {
+    /// `size` bytes were written at `offset`.
+    Put { offset: u64, size: u64 },
+    /// The blob's placement score was changed.
+    Reorganize { score: f32 },
+    /// The blob was deleted.
+    Delete,
+}
+
+/// One logged mutation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub seq: u64,
+    pub blob: String,
+    pub kind: ChangeKind,
+    /// Instance id of the process that made the change (see `writer_id`).
+    pub writer: u64,
+    /// Client label of that process, if it had one.
+    pub label: Option<String>,
+}
+
+/// Result of `Tag::changes_since`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Changes {
+    pub changes: Vec<Change>,
+    /// Sequence number to pass to the next `changes_since` call.
+    pub next_seq: u64,
+}
+
+/// Blob names of a tag as of one change-log sequence number.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Listing {
+    pub seq: u64,
+    /// Sorted blob names.
+    pub blobs: Vec<String>,
+}
+
+/// Random id for this process, stamped on every change it logs.
+pub(crate) fn writer_id() -> u64 {
+    static ID: OnceLock<u64> = OnceLock::new();
+    *ID.get_or_init(|| {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        // splitmix64 over pid and start time
+        let mut z = nanos ^ ((std::process::id() as u64) << 32);
+        z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    })
+}
+
+/// Cached answer to "does this tag have a change log" (or another opt-in log).
+#[derive(Default)]
+pub(crate) struct LogState(AtomicU8);
+
+const STATE_UNKNOWN: u8 = 0;
+const STATE_OFF: u8 = 1;
+const STATE_ON: u8 = 2;
+
+impl LogState {
+    pub(crate) fn set_on(&self) {
+        self.0.store(STATE_ON, Ordering::Relaxed);
+    }
+
+    /// The cached answer, running `probe` to find it on first use.
+    pub(crate) fn is_on(&self, probe: impl FnOnce() -> bool) -> bool {
+        match self.0.load(Ordering::Relaxed) {
+            STATE_ON => true,
+            STATE_OFF => false,
+            _ => {
+                let on = probe();
+                let state = if on { STATE_ON } else { STATE_OFF };
+                let _ = self.0.compare_exchange(
+                    STATE_UNKNOWN,
+                    state,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                );
+                on
+            }
+        }
+    }
+}
+
+impl Tag {
+    /// Start recording changes to this tag. Idempotent.
+    ///
+    /// Panics if the runtime fails to create the log.
+    pub fn enable_change_log(&self) {
+        let _guard = APPEND_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        if ffi::tag_get_blob_size(&self.inner, OPLOG_NAME) == 0 {
+            // An empty record marks the log as present.
+            let marker = FieldWriter::default().finish();
+            if let Err(e) = ffi_guard::tag_put_blob(&self.inner, OPLOG_NAME, &marker, 0, 1.0) {
+                panic!("{}", e);
+            }
+        }
+        self.changelog.set_on();
+        crate::tagcache::forget(&self.name);
+    }
+
+    /// Current end of the change log; changes made after this call get a higher
+    /// sequence number. Returns 0 if the tag has no change log.
+    pub fn change_seq(&self) -> u64 {
+        ffi::tag_get_blob_size(&self.inner, OPLOG_NAME)
+    }
+
+    /// Changes recorded at or after `seq`, which must come from `change_seq`,
+    /// `Change::seq` or a previous `Changes::next_seq`.
+    pub fn changes_since(&self, seq: u64) -> Result<Changes, CteError> {
+        let end = self.change_seq();
+        if seq >= end {
+            return Ok(Changes {
+                changes: Vec::new(),
+                next_seq: end.max(seq),
+            });
+        }
+        let v = ffi_guard::tag_get_blob(&self.inner, OPLOG_NAME, end - seq, seq)?;
+        let changes = parse_log(v.as_slice(), seq).map_err(|reason| CteError::CorruptMetadata {
+            blob: OPLOG_NAME.to_string(),
+            reason,
+        })?;
+        Ok(Changes {
+            changes,
+            next_seq: end,
+        })
+    }
+
+    /// List the tag's blobs as they were at a single sequence number, even while
+    /// other clients are writing. Requires a change log.
+    ///
+    /// The runtime listing is taken between two reads of the log's end. Blobs the
+    /// log doesn't mention in that window were stable, so the listing is right
+    /// about them; blobs it does mention are resolved by their last logged change.
+    /// The result is the namespace as of the second read.
+    pub fn list_consistent(&self) -> Result<Listing, CteError> {
+        let start = self.change_seq();
+        if start == 0 {
+            return Err(CteError::Unsupported(format!(
+                "tag '{}' has no change log; call enable_change_log first",
+                self.name()
+            )));
+        }
+        let listed = self.blob_names();
+        let changes = self.changes_since(start)?;
+        let mut blobs: BTreeSet<String> = listed.into_iter().collect();
+        let mut last: HashMap<&str, &ChangeKind> = HashMap::new();
+        for change in &changes.changes {
+            last.insert(&change.blob, &change.kind);
+        }
+        for (blob, kind) in last {
+            match kind {
+                ChangeKind::Delete => {
+                    blobs.remove(blob);
+                }
+                _ => {
+                    blobs.insert(blob.to_string());
+                }
+            }
+        }
+        Ok(Listing {
+            seq: changes.next_seq,
+            blobs: blobs.into_iter().collect(),
+        })
+    }
+
+    /// Append a record for a mutation of `blob`, if this tag keeps a change log.
+    pub(crate) fn record_change(&self, blob: &str, kind: ChangeKind) {
+        if blob.starts_with(RESERVED_PREFIX) || !self.change_log_enabled() {
+            return;
+        }
+        let mut w = FieldWriter::default();
+        w.bytes(FIELD_BLOB, blob.as_bytes());
+        w.u64(FIELD_WRITER, writer_id());
+        if let Some(label) = crate::accounting::label() {
+            w.bytes(FIELD_LABEL, label.as_bytes());
+        }
+        match kind {
+            ChangeKind::Put { offset, size } => {
+                w.u64(FIELD_KIND, KIND_PUT);
+                w.u64(FIELD_OFFSET, offset);
+                w.u64(FIELD_SIZE, size);
+            }
+            ChangeKind::Reorganize { score } => {
+                w.u64(FIELD_KIND, KIND_REORGANIZE);
+                w.f32(FIELD_SCORE, score);
+            }
+            ChangeKind::Delete => w.u64(FIELD_KIND, KIND_DELETE),
+        }
+        let record = w.finish();
+        let _guard = APPEND_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let offset = ffi::tag_get_blob_size(&self.inner, OPLOG_NAME);
+        // The change has already been made; a record the runtime refuses is lost
+        // rather than failing it.
+        let _ = ffi_guard::tag_put_blob(&self.inner, OPLOG_NAME, &record, offset, 1.0);
+    }
+
+    fn change_log_enabled(&self) -> bool {
+        self.changelog.is_on(|| self.change_seq() > 0)
+    }
+}
+
+/// Decode the records in `buf`, which starts at log offset `base`.
+fn parse_log(buf: &[u8], base: u64) -> Result<Vec<Change>, String> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+    while pos < buf.len() {
+        let len = FieldReader::record_len(&buf[pos..]).ok_or("truncated record header")?;
+        let (mut kind, mut blob) = (0, String::new());
+        let (mut offset, mut size, mut score, mut writer) = (0, 0, 0.0, 0);
+        let mut label = None;
+        for (id, value) in FieldReader::new(&buf[pos..])? {
+            match id {
+                FIELD_KIND => kind = read_u64(value)?,
+                FIELD_BLOB => blob = read_str(value)?,
+                FIELD_OFFSET => offset = read_u64(value)?,
+                FIELD_SIZE => size = read_u64(value)?,
+                FIELD_SCORE => score = read_f32(value)?,
+                FIELD_WRITER => writer = read_u64(value)?,
+                FIELD_LABEL => label = Some(read_str(value)?),
+                _ => {}
+            }
+        }
+        let kind = match kind {
+            KIND_PUT => Some(ChangeKind::Put { offset, size }),
+            KIND_REORGANIZE => Some(ChangeKind::Reorganize { score }),
+            KIND_DELETE => Some(ChangeKind::Delete),
+            // Log marker, or a kind from a newer wrapper.
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            out.push(Change {
+                seq: base + pos as u64,
+                blob,
+                kind,
+                writer,
+                label,
+            });
+        }
+        pos += len;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log_sequence_numbers() {
+        let mut log = FieldWriter::default().finish();
+        let mut put = FieldWriter::default();
+        put.bytes(FIELD_BLOB, b"a");
+        put.u64(FIELD_KIND, KIND_PUT);
+        put.u64(FIELD_OFFSET, 8);
+        put.u64(FIELD_SIZE, 16);
+        let put = put.finish();
+        let mut reorg = FieldWriter::default();
+        reorg.bytes(FIELD_BLOB, b"b");
+        reorg.u64(FIELD_KIND, KIND_REORGANIZE);
+        reorg.f32(FIELD_SCORE, 0.25);
+        reorg.bytes(FIELD_LABEL, b"train-job-17");
+        log.extend_from_slice(&put);
+        log.extend_from_slice(&reorg.finish());
+
+        let changes = parse_log(&log, 0).unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                Change {
+                    seq: 4,
+                    blob: "a".into(),
+                    kind: ChangeKind::Put {
+                        offset: 8,
+                        size: 16
+                    },
+                    writer: 0,
+                    label: None,
+                },
+                Change {
+                    seq: 4 + put.len() as u64,
+                    blob: "b".into(),
+                    kind: ChangeKind::Reorganize { score: 0.25 },
+                    writer: 0,
+                    label: Some("train-job-17".into()),
+                },
+            ]
+        );
+
+        // Reading from the second record's sequence number yields only it.
+        let tail = parse_log(&log[4 + put.len()..], 4 + put.len() as u64).unwrap();
+        assert_eq!(tail, changes[1..]);
+    }
+}
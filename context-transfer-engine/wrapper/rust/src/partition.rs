@@ -0,0 +1,154 @@
+//! Pluggable partitioning of blobs across the runtime's metadata containers.
+//!
+//! By default the runtime picks the container that owns a blob by hashing its
+//! tag id and name. A registered `PartitionStrategy` replaces that choice for
+//! every blob operation the wrapper issues: the shim asks it (through the
+//! `blob_partition` hook) for a routing hash and sends the request to container
+//! `hash % container_count`. Shard splits (see `shard`) are applied on top.
+//!
+//! The strategy decides where blobs *are*, not just where new ones go, so every
+//! process of a deployment must register the same one before touching the data;
+//! clients with a different strategy (or none, including the C++ API) won't find
+//! blobs placed by it. Listings and queries broadcast to all containers and are
+//! unaffected.
+
+use std::sync::RwLock;
+
+use crate::checksum::xxh64;
+use crate::{ffi_guard, shard, CteTagId};
+
+/// Chooses the routing hash of a blob; `None` keeps the runtime's default.
+pub trait PartitionStrategy: Send + Sync {
+    fn partition(&self, tag_id: CteTagId, blob: &str) -> Option<u32>;
+}
+
+impl<F> PartitionStrategy for F
+where
+    F: Fn(CteTagId, &str) -> Option<u32> + Send + Sync,
+{
+    fn partition(&self, tag_id: CteTagId, blob: &str) -> Option<u32> {
+        self(tag_id, blob)
+    }
+}
+
+/// Hash partitioning with XXH64 over the tag id and the blob name, optionally
+/// ignoring a fixed-length prefix such as a timestamp, so blobs that differ only
+/// in it land together.
+#[derive(Debug, Clone, Default)]
+pub struct HashPartitioner {
+    skip_prefix: usize,
+}
+
+impl HashPartitioner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Leave the first `bytes` bytes of each name out of the hash.
+    pub fn skip_prefix(mut self, bytes: usize) -> Self {
+        self.skip_prefix = bytes;
+        self
+    }
+}
+
+impl PartitionStrategy for HashPartitioner {
+    fn partition(&self, tag_id: CteTagId, blob: &str) -> Option<u32> {
+        let key = blob.as_bytes().get(self.skip_prefix..).unwrap_or_default();
+        let seed = ((tag_id.major as u64) << 32) | tag_id.minor as u64;
+        Some(xxh64(seed, key) as u32)
+    }
+}
+
+/// Range partitioning: names below `boundaries[0]` go to partition 0, names from
+/// `boundaries[i - 1]` up to `boundaries[i]` to partition `i`, and so on, keeping
+/// lexically adjacent blobs in the same container.
+#[derive(Debug, Clone)]
+pub struct RangePartitioner {
+    boundaries: Vec<String>,
+}
+
+impl RangePartitioner {
+    pub fn new(mut boundaries: Vec<String>) -> Self {
+        boundaries.sort();
+        boundaries.dedup();
+        Self { boundaries }
+    }
+}
+
+impl PartitionStrategy for RangePartitioner {
+    fn partition(&self, _tag_id: CteTagId, blob: &str) -> Option<u32> {
+        Some(self.boundaries.partition_point(|b| b.as_str() <= blob) as u32)
+    }
+}
+
+static STRATEGY: RwLock<Option<Box<dyn PartitionStrategy>>> = RwLock::new(None);
+
+/// Register the process-wide partition strategy, replacing any previous one.
+pub fn set_partition_strategy(strategy: impl PartitionStrategy + 'static) {
+    *STRATEGY.write().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(strategy));
+}
+
+/// Remove the partition strategy; blob operations go back to runtime routing.
+pub fn clear_partition_strategy() {
+    *STRATEGY.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Routing hash the strategy picks for `name`, or `None` for the runtime's
+/// default. A panicking strategy counts as `None`, since this runs under the
+/// shim and must not unwind across the bridge.
+fn strategy_hash(tag_id: CteTagId, name: &str) -> Option<u32> {
+    let strategy = STRATEGY.read().unwrap_or_else(|e| e.into_inner());
+    let s = strategy.as_ref()?;
+    ffi_guard::hook("partition", None, || s.partition(tag_id, name))
+}
+
+/// Routing hash of an operation on `name`, given the runtime's own hash of it,
+/// and whether that's just the runtime's choice. Applies the strategy and any
+/// shard splits (see `shard`).
+pub(crate) fn resolve_route(tag_id: CteTagId, name: &str, default_hash: u32) -> (u32, bool) {
+    let chosen = strategy_hash(tag_id, name);
+    let base = chosen.unwrap_or(default_hash);
+    let routed = shard::apply_splits(tag_id, name, base);
+    (routed, chosen.is_none() && routed == base)
+}
+
+/// Shim hook: routing hash for an operation on `name`, or -1 for the runtime's
+/// default routing. `default_hash` is the hash the runtime would route by.
+/// Names that aren't UTF-8 are shown to the strategy with invalid sequences
+/// replaced.
+pub(crate) fn blob_partition(tag_id: &CteTagId, name: &[u8], default_hash: u32) -> i64 {
+    if let Some(hash) = shard::route_override() {
+        return hash as i64;
+    }
+    let name = String::from_utf8_lossy(name);
+    let (hash, default) = resolve_route(*tag_id, &name, default_hash);
+    shard::record(*tag_id, hash);
+    if default {
+        -1
+    } else {
+        hash as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partitioners() {
+        let id = CteTagId { major: 1, minor: 2 };
+        let ranges = RangePartitioner::new(vec!["m".into(), "f".into()]);
+        assert_eq!(ranges.partition(id, "apple"), Some(0));
+        assert_eq!(ranges.partition(id, "f"), Some(1));
+        assert_eq!(ranges.partition(id, "zebra"), Some(2));
+
+        let hash = HashPartitioner::new().skip_prefix(9);
+        assert_eq!(
+            hash.partition(id, "20240101/shard"),
+            hash.partition(id, "20240102/shard")
+        );
+        assert_eq!(hash.partition(id, "x"), hash.partition(id, "y"));
+
+        assert_eq!(blob_partition(&id, b"a", 7), -1);
+    }
+}
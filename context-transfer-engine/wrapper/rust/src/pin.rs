@@ -0,0 +1,374 @@
+//! Pinning blobs to a tier.
+//!
+//! Placement follows scores, so a policy or a warmup can move a blob down to a
+//! slow tier just before it is needed. `Tag::pin_blob` moves a blob to the
+//! tier of a given score and keeps it there: its puts are placed at that score
+//! whatever `PutOptions::score` or the `PlacementPolicy` say, group commits
+//! included, and `Tag::reorganize_blob` leaves it alone, so placement policies
+//! and `Client::warmup` pass it by. `Tag::unpin_blob` lifts the pin and leaves
+//! the blob where it is until something moves it. The pin is kept in the
+//! blob's metadata sidecar, so it holds for every client of the tag, and
+//! `BlobStat::pinned` reports it. Writes to a pinned blob still spill to a
+//! lower tier when the pinned one is full and a `SpillPolicy` allows it.
+//!
+//! A pin is held by the `PinLease` that `pin_blob` returns, and lifted when
+//! the lease is dropped or released. While it is held, a heartbeat thread
+//! renews it every third of `PIN_LEASE_TTL`; a pin whose lease wasn't renewed
+//! in time, because the process that took it died, no longer counts for
+//! anyone, so a crashed job can't keep a tier full. `PinLease::persist` turns
+//! a pin into one without a lease, kept until `unpin_blob`. Operators can see
+//! every pin with `Client::list_pins` and lift one with `Client::break_pin`;
+//! the process holding it then finds its lease gone and stops renewing it.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::meta::{meta_lock, read_u64, BlobMeta, META_PREFIX};
+use crate::oplog::writer_id;
+use crate::ttl::now_ms;
+use crate::{Client, CteError, CteTagId, Tag};
+
+/// How long a lease lasts without being renewed.
+pub const PIN_LEASE_TTL: Duration = Duration::from_secs(30);
+
+/// The lease behind a pin, as kept in the sidecar.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Lease {
+    /// `oplog::writer_id` of the holding process.
+    pub process: u64,
+    /// Which of that process's leases it is.
+    pub id: u64,
+    pub pid: u32,
+    pub expires_ms: u64,
+}
+
+impl Lease {
+    /// Sidecar encoding: process, id, pid, expiry.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut out = self.process.to_le_bytes().to_vec();
+        out.extend_from_slice(&self.id.to_le_bytes());
+        out.extend_from_slice(&self.pid.to_le_bytes());
+        out.extend_from_slice(&self.expires_ms.to_le_bytes());
+        out
+    }
+
+    pub(crate) fn decode(buf: &[u8]) -> Result<Self, String> {
+        if buf.len() != 28 {
+            return Err("bad pin lease field".into());
+        }
+        Ok(Self {
+            process: read_u64(&buf[..8])?,
+            id: read_u64(&buf[8..16])?,
+            pid: u32::from_le_bytes(buf[16..20].try_into().unwrap()),
+            expires_ms: read_u64(&buf[20..])?,
+        })
+    }
+
+    fn ours(&self, id: u64) -> bool {
+        self.process == writer_id() && self.id == id
+    }
+}
+
+impl BlobMeta {
+    /// The score the blob is pinned at, unless its lease has run out.
+    pub(crate) fn pin(&self) -> Option<f32> {
+        let held = self
+            .pin_lease
+            .as_ref()
+            .is_none_or(|l| l.expires_ms > now_ms());
+        self.pinned.filter(|_| held)
+    }
+}
+
+/// A pin held by this process (see `pin`). Dropping it lifts the pin.
+#[must_use = "dropping a PinLease lifts the pin"]
+#[derive(Debug)]
+pub struct PinLease {
+    id: u64,
+    tag: CteTagId,
+    blob: String,
+}
+
+/// A lease the heartbeat renews.
+struct Held {
+    id: u64,
+    tag: CteTagId,
+    blob: String,
+}
+
+static HELD: Mutex<Vec<Held>> = Mutex::new(Vec::new());
+static HEARTBEAT: AtomicBool = AtomicBool::new(false);
+static NEXT_LEASE: AtomicU64 = AtomicU64::new(1);
+
+fn held() -> MutexGuard<'static, Vec<Held>> {
+    HELD.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Renew the leases still held, until none is left.
+fn run_heartbeat() {
+    loop {
+        std::thread::sleep(PIN_LEASE_TTL / 3);
+        let leases: Vec<(u64, CteTagId, String)> = {
+            let held = held();
+            if held.is_empty() {
+                HEARTBEAT.store(false, Ordering::Relaxed);
+                return;
+            }
+            held.iter().map(|h| (h.id, h.tag, h.blob.clone())).collect()
+        };
+        for (id, tag, blob) in leases {
+            let tag = Tag::from_id(tag);
+            // A lease the runtime can't be asked about is tried again next
+            // time; one broken or replaced is let go.
+            if let Ok(false) = tag.update_lease(&blob, id, |lease| {
+                lease.expires_ms = now_ms() + PIN_LEASE_TTL.as_millis() as u64;
+            }) {
+                held().retain(|h| h.id != id);
+            }
+        }
+    }
+}
+
+impl PinLease {
+    pub fn blob(&self) -> &str {
+        &self.blob
+    }
+
+    /// True while the heartbeat renews the lease, that is, until the pin is
+    /// released, broken or replaced by another.
+    pub fn is_held(&self) -> bool {
+        held().iter().any(|h| h.id == self.id)
+    }
+
+    /// Lift the pin now. Returns whether this lease still held it.
+    pub fn release(self) -> Result<bool, CteError> {
+        self.lift()
+    }
+
+    /// Keep the pin after the lease is gone, until `Tag::unpin_blob` or
+    /// `Client::break_pin`. Returns whether this lease still held it.
+    pub fn persist(self) -> Result<bool, CteError> {
+        held().retain(|h| h.id != self.id);
+        let tag = Tag::from_id(self.tag);
+        let _guard = meta_lock();
+        let Some(mut meta) = tag.load_meta(&self.blob)? else {
+            return Ok(false);
+        };
+        if !meta.pin_lease.as_ref().is_some_and(|l| l.ours(self.id)) {
+            return Ok(false);
+        }
+        meta.pin_lease = None;
+        tag.store_meta(&self.blob, &meta)?;
+        Ok(true)
+    }
+
+    fn lift(&self) -> Result<bool, CteError> {
+        let held = {
+            let mut held = held();
+            let before = held.len();
+            held.retain(|h| h.id != self.id);
+            held.len() != before
+        };
+        if !held {
+            return Ok(false);
+        }
+        let tag = Tag::from_id(self.tag);
+        let _guard = meta_lock();
+        let Some(mut meta) = tag.load_meta(&self.blob)? else {
+            return Ok(false);
+        };
+        if !meta.pin_lease.as_ref().is_some_and(|l| l.ours(self.id)) {
+            return Ok(false);
+        }
+        meta.pinned = None;
+        meta.pin_lease = None;
+        tag.store_meta(&self.blob, &meta)?;
+        Ok(true)
+    }
+}
+
+impl Drop for PinLease {
+    fn drop(&mut self) {
+        let _ = self.lift();
+    }
+}
+
+/// A pin as `Client::list_pins` reports it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PinInfo {
+    pub tag: String,
+    pub blob: String,
+    pub score: f32,
+    /// Process ID of the lease holder; `None` for a pin without a lease.
+    pub pid: Option<u32>,
+    /// When the lease runs out unless renewed.
+    pub expires: Option<SystemTime>,
+    /// The lease ran out, so the pin no longer counts.
+    pub stale: bool,
+}
+
+impl Tag {
+    /// Move `name` to the tier of `score` (0.0 coldest to 1.0 hottest) and
+    /// keep it there while the returned lease is held (see `pin`), replacing
+    /// any earlier pin. `NotFound` if there is no such blob.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(tag = %self.name, blob = name, score = score), err)
+    )]
+    pub fn pin_blob(&self, name: &str, score: f32) -> Result<PinLease, CteError> {
+        if !(0.0..=1.0).contains(&score) {
+            return Err(CteError::InvalidArgument(format!(
+                "pin score {} is outside 0.0 to 1.0",
+                score
+            )));
+        }
+        let id = NEXT_LEASE.fetch_add(1, Ordering::Relaxed);
+        {
+            let _guard = meta_lock();
+            let meta = self.load_meta(name)?;
+            if self.get_blob_size(name) == 0 && meta.is_none() {
+                return Err(CteError::NotFound {
+                    blob: name.to_string(),
+                });
+            }
+            let mut meta = meta.unwrap_or_default();
+            meta.pinned = Some(score);
+            meta.pin_lease = Some(Lease {
+                process: writer_id(),
+                id,
+                pid: std::process::id(),
+                expires_ms: now_ms() + PIN_LEASE_TTL.as_millis() as u64,
+            });
+            self.store_meta(name, &meta)?;
+        }
+        let lease = PinLease {
+            id,
+            tag: self.get_tag_id(),
+            blob: name.to_string(),
+        };
+        {
+            let mut held = held();
+            held.push(Held {
+                id,
+                tag: lease.tag,
+                blob: name.to_string(),
+            });
+            if !HEARTBEAT.swap(true, Ordering::Relaxed) {
+                std::thread::spawn(run_heartbeat);
+            }
+        }
+        // Outside the lock, as the move runs event handlers. Should it fail,
+        // dropping the lease lifts the pin.
+        self.move_blob(name, score)?;
+        Ok(lease)
+    }
+
+    /// Remove `name`'s pin, leaving the blob where it is. Returns whether it
+    /// was pinned, a pin whose lease had run out included.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(tag = %self.name, blob = name), ret, err)
+    )]
+    pub fn unpin_blob(&self, name: &str) -> Result<bool, CteError> {
+        let _guard = meta_lock();
+        let Some(mut meta) = self.load_meta(name)? else {
+            return Ok(false);
+        };
+        meta.pin_lease = None;
+        if meta.pinned.take().is_none() {
+            return Ok(false);
+        }
+        self.store_meta(name, &meta)?;
+        Ok(true)
+    }
+
+    /// Apply `renew` to `name`'s lease if lease `id` of this process still
+    /// holds its pin; returns whether it did.
+    fn update_lease(
+        &self,
+        name: &str,
+        id: u64,
+        renew: impl FnOnce(&mut Lease),
+    ) -> Result<bool, CteError> {
+        let _guard = meta_lock();
+        let Some(mut meta) = self.load_meta(name)? else {
+            return Ok(false);
+        };
+        match meta.pin_lease.as_mut() {
+            Some(lease) if lease.ours(id) && meta.pinned.is_some() => renew(lease),
+            _ => return Ok(false),
+        }
+        self.store_meta(name, &meta)?;
+        Ok(true)
+    }
+
+    /// The pins in this tag, stale ones included.
+    fn pins(&self) -> Vec<PinInfo> {
+        let now = now_ms();
+        self.raw_blob_names()
+            .iter()
+            .filter_map(|n| n.strip_prefix(META_PREFIX))
+            .filter_map(|blob| {
+                let meta = self.load_meta(blob).ok().flatten()?;
+                let score = meta.pinned?;
+                let lease = meta.pin_lease.as_ref();
+                Some(PinInfo {
+                    tag: self.name.clone(),
+                    blob: blob.to_string(),
+                    score,
+                    pid: lease.map(|l| l.pid),
+                    expires: lease.map(|l| UNIX_EPOCH + Duration::from_millis(l.expires_ms)),
+                    stale: lease.is_some_and(|l| l.expires_ms <= now),
+                })
+            })
+            .collect()
+    }
+}
+
+impl Client {
+    /// Every pinned blob in every tag, by tag and blob name.
+    pub fn list_pins() -> Vec<PinInfo> {
+        let mut pins: Vec<PinInfo> = Self::tag_query(".*", 0)
+            .iter()
+            .flat_map(|tag| Tag::new(tag).pins())
+            .collect();
+        pins.sort_by(|a, b| (&a.tag, &a.blob).cmp(&(&b.tag, &b.blob)));
+        pins
+    }
+
+    /// Lift the pin on `blob` in `tag`, whoever holds it. Returns whether it
+    /// was pinned.
+    pub fn break_pin(tag: &str, blob: &str) -> Result<bool, CteError> {
+        Tag::try_new(tag)?.unpin_blob(blob)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pin_lease() {
+        let lease = Lease {
+            process: writer_id(),
+            id: 9,
+            pid: 100,
+            expires_ms: now_ms() + 60_000,
+        };
+        assert_eq!(Lease::decode(&lease.encode()).unwrap(), lease);
+        assert!(Lease::decode(&[0; 20]).is_err());
+        assert!(lease.ours(9) && !lease.ours(8));
+
+        let mut meta = BlobMeta {
+            pinned: Some(0.5),
+            pin_lease: Some(lease),
+            ..Default::default()
+        };
+        assert_eq!(meta.pin(), Some(0.5));
+        meta.pin_lease.as_mut().unwrap().expires_ms = now_ms() - 1;
+        assert_eq!(meta.pin(), None);
+        meta.pin_lease = None;
+        assert_eq!(meta.pin(), Some(0.5));
+    }
+}
@@ -0,0 +1,130 @@
+//! Application-driven placement policies.
+//!
+//! A registered `PlacementPolicy` is consulted by the shim (through the
+//! `placement_score` hook) whenever a blob is written without an explicit score,
+//! and by `Tag::apply_placement_policy` / `Tag::rebalance` to re-score blobs that
+//! already exist.
+
+use std::sync::RwLock;
+
+use crate::{ffi_guard, BlobDescriptor, BulkOptions, BulkReport, Tag};
+
+/// Score used when no policy is registered, matching `put_blob`'s historical default.
+const DEFAULT_SCORE: f32 = 1.0;
+
+/// Decides the placement score (0.0 = coldest tier, 1.0 = hottest) of a blob.
+///
+/// Returning a negative score defers placement to the runtime's DPE.
+pub trait PlacementPolicy: Send + Sync {
+    fn score(&self, blob: &BlobDescriptor) -> f32;
+
+    /// How `Client::capture_environment` records the policy. Name it and its
+    /// parameters, so captures under different policies differ.
+    fn describe(&self) -> String {
+        "custom".to_string()
+    }
+}
+
+impl<F> PlacementPolicy for F
+where
+    F: Fn(&BlobDescriptor) -> f32 + Send + Sync,
+{
+    fn score(&self, blob: &BlobDescriptor) -> f32 {
+        self(blob)
+    }
+}
+
+static POLICY: RwLock<Option<Box<dyn PlacementPolicy>>> = RwLock::new(None);
+
+/// Register the process-wide placement policy, replacing any previous one.
+pub fn set_placement_policy(policy: impl PlacementPolicy + 'static) {
+    *POLICY.write().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(policy));
+}
+
+/// Remove the placement policy; unscored puts go back to the default score.
+pub fn clear_placement_policy() {
+    *POLICY.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Shim hook: score for a put that didn't specify one.
+///
+/// Called from C++, so a panicking policy falls back to the default score rather
+/// than unwinding across the bridge.
+pub(crate) fn placement_score(desc: &BlobDescriptor) -> f32 {
+    let policy = POLICY.read().unwrap_or_else(|e| e.into_inner());
+    match policy.as_ref() {
+        Some(p) => ffi_guard::hook("placement_score", DEFAULT_SCORE, || p.score(desc)),
+        None => DEFAULT_SCORE,
+    }
+}
+
+/// `describe` of the registered policy, if any.
+pub(crate) fn describe_policy() -> Option<String> {
+    let policy = POLICY.read().unwrap_or_else(|e| e.into_inner());
+    policy.as_ref().map(|p| p.describe())
+}
+
+impl Tag {
+    /// Re-score every blob in this tag with the registered policy, reorganizing
+    /// those whose score changed. Returns the number of blobs reorganized.
+    pub fn apply_placement_policy(&self) -> usize {
+        self.rebalance(&BulkOptions::default()).blobs.len()
+    }
+
+    /// `apply_placement_policy` with a report of the blobs moved (or, with
+    /// `dry_run`, that would be moved) and of those the runtime failed to move.
+    pub fn rebalance(&self, options: &BulkOptions) -> BulkReport {
+        let mut report = BulkReport::new(options);
+        if POLICY.read().unwrap_or_else(|e| e.into_inner()).is_none() {
+            return report;
+        }
+        let tag_id = self.get_tag_id();
+        for name in self.blob_names() {
+            let desc = BlobDescriptor {
+                tag_id: crate::CteTagId {
+                    major: tag_id.major,
+                    minor: tag_id.minor,
+                },
+                size: self.get_blob_size(&name),
+                offset: 0,
+                name,
+            };
+            let score = placement_score(&desc);
+            if score >= 0.0 && score != self.get_blob_score(&desc.name) {
+                if !options.dry_run {
+                    if let Err(e) = self.try_reorganize_blob(&desc.name, score) {
+                        report.push_failed(self.name(), &desc.name, &e);
+                        continue;
+                    }
+                }
+                report.push(self.name(), &desc.name, desc.size);
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_registration() {
+        let desc = BlobDescriptor {
+            tag_id: crate::CteTagId { major: 1, minor: 2 },
+            name: "step_0007".to_string(),
+            size: 16,
+            offset: 0,
+        };
+        assert_eq!(placement_score(&desc), DEFAULT_SCORE);
+
+        set_placement_policy(|b: &BlobDescriptor| if b.name.ends_with("7") { 0.9 } else { 0.1 });
+        assert_eq!(placement_score(&desc), 0.9);
+
+        set_placement_policy(|_: &BlobDescriptor| -> f32 { panic!("bad policy") });
+        assert_eq!(placement_score(&desc), DEFAULT_SCORE);
+
+        clear_placement_policy();
+        assert_eq!(placement_score(&desc), DEFAULT_SCORE);
+    }
+}
@@ -0,0 +1,843 @@
+//! Pre-flight checks of a configuration against the host it is about to run on.
+//!
+//! `Client::preflight` needs no runtime: it reads the configuration the runtime
+//! would load and checks the environment for the things that most often stop a
+//! first start without a useful message. Those are file targets the runtime
+//! can't create, a `/dev/shm` too small for the segments, `shmmax` and `memlock`
+//! limits, hugepages, which copies of the IOWarp libraries get loaded, the
+//! hostfile, and whether the RPC port is free. Every problem comes with the fix
+//! an operator would apply.
+//!
+//! The runtime takes its configuration from `CHI_SERVER_CONF`, then
+//! `WRP_RUNTIME_CONF`, then `~/.chimaera/chimaera.yaml`, and ignores the path
+//! given to `init`; the checks use that same order unless a path is passed, and
+//! say so when the two disagree. Only the YAML subset the shipped configurations
+//! use is understood: block mappings and sequences, plain or quoted scalars.
+
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+
+use crate::Client;
+
+/// Runtime defaults (`ConfigManager::LoadDefault`) for settings a config omits.
+const DEFAULT_PORT: u16 = 9413;
+const DEFAULT_CLIENT_DATA_SEGMENT: u64 = 512 << 20;
+
+/// Libraries the wrapper links, as `lib<name>.so`.
+const LIBRARIES: [&str; 4] = [
+    "wrp_cte_core_client",
+    "chimaera_cxx",
+    "hermes_shm_host",
+    "zmq",
+];
+
+/// Where the loader looks after `LD_LIBRARY_PATH`: the wrapper's rpath, then
+/// the system directories.
+const LIBRARY_DIRS: [&str; 6] = [
+    "/usr/local/lib",
+    "/home/iowarp/miniconda3/lib",
+    "/lib64",
+    "/usr/lib64",
+    "/usr/lib/x86_64-linux-gnu",
+    "/usr/lib",
+];
+
+/// Outcome of one check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CheckStatus {
+    Ok,
+    /// Likely to cause trouble, or impossible to confirm.
+    Warn,
+    /// The runtime will not start or will not be usable as configured.
+    Fail,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CheckStatus::Ok => "ok",
+            CheckStatus::Warn => "warn",
+            CheckStatus::Fail => "FAIL",
+        })
+    }
+}
+
+/// One finding of `Client::preflight`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreflightCheck {
+    /// What was checked, e.g. `target /mnt/nvme/cte` or `port 9413`.
+    pub name: String,
+    pub status: CheckStatus,
+    /// What was found.
+    pub detail: String,
+    /// What to do about it; set for warnings and failures.
+    pub fix: Option<String>,
+}
+
+impl PreflightCheck {
+    fn ok(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: CheckStatus::Ok,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn warn(name: impl Into<String>, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            status: CheckStatus::Warn,
+            fix: Some(fix.into()),
+            ..Self::ok(name, detail)
+        }
+    }
+
+    fn fail(name: impl Into<String>, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Self {
+            status: CheckStatus::Fail,
+            ..Self::warn(name, detail, fix)
+        }
+    }
+}
+
+/// Findings of `Client::preflight`, in the order checked.
+#[derive(Debug, Clone, Default)]
+pub struct PreflightReport {
+    /// The configuration checked, if one was found.
+    pub config: Option<PathBuf>,
+    pub checks: Vec<PreflightCheck>,
+}
+
+impl PreflightReport {
+    /// True if nothing failed; warnings don't count.
+    pub fn ok(&self) -> bool {
+        self.worst() < CheckStatus::Fail
+    }
+
+    /// The most severe status found.
+    pub fn worst(&self) -> CheckStatus {
+        self.checks
+            .iter()
+            .map(|c| c.status)
+            .max()
+            .unwrap_or(CheckStatus::Ok)
+    }
+}
+
+/// A configuration flattened to `(path, value)` pairs, with paths such as
+/// `networking.port` or `compose[0].storage[1].path`.
+#[derive(Debug, Default)]
+struct Config {
+    values: Vec<(String, String)>,
+}
+
+/// A mapping key or sequence item open while flattening.
+struct Open {
+    indent: usize,
+    segment: String,
+    item: bool,
+    next_item: usize,
+}
+
+impl Config {
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut values = Vec::new();
+        let mut stack: Vec<Open> = Vec::new();
+        let path = |stack: &[Open], last: &str| {
+            let mut out = String::new();
+            for segment in stack.iter().map(|o| o.segment.as_str()).chain([last]) {
+                if !out.is_empty() && !segment.starts_with('[') && !segment.is_empty() {
+                    out.push('.');
+                }
+                out.push_str(segment);
+            }
+            out
+        };
+        for (n, raw) in text.lines().enumerate() {
+            let line = strip_comment(raw).trim_end();
+            let content = line.trim_start();
+            if content.is_empty() || content == "---" || content == "..." {
+                continue;
+            }
+            if line.starts_with('\t') {
+                return Err(format!("line {}: tabs can't indent YAML", n + 1));
+            }
+            let mut indent = line.len() - content.len();
+            let mut content = content;
+            while content == "-" || content.starts_with("- ") {
+                while stack
+                    .last()
+                    .is_some_and(|o| o.indent > indent || (o.indent == indent && o.item))
+                {
+                    stack.pop();
+                }
+                let index = match stack.last_mut() {
+                    Some(parent) => {
+                        parent.next_item += 1;
+                        parent.next_item - 1
+                    }
+                    None => return Err(format!("line {}: sequence at top level", n + 1)),
+                };
+                stack.push(Open {
+                    indent,
+                    segment: format!("[{}]", index),
+                    item: true,
+                    next_item: 0,
+                });
+                let rest = content[1..].trim_start();
+                indent += content.len() - rest.len();
+                content = rest;
+            }
+            if content.is_empty() {
+                continue;
+            }
+            // An item's own keys sit past its `- `, so one level with it is a sibling.
+            while stack.last().is_some_and(|o| o.indent >= indent) {
+                stack.pop();
+            }
+            match split_key(content) {
+                Some((key, "")) => stack.push(Open {
+                    indent,
+                    segment: key.to_string(),
+                    item: false,
+                    next_item: 0,
+                }),
+                Some((key, value)) => values.push((path(&stack, key), unquote(value))),
+                None if stack.last().is_some_and(|o| o.item) => {
+                    values.push((path(&stack, ""), unquote(content)))
+                }
+                None => return Err(format!("line {}: expected 'key: value'", n + 1)),
+            }
+        }
+        Ok(Self { values })
+    }
+
+    fn get(&self, path: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(p, _)| p == path)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The storage targets of every `wrp_cte_core` pool in `compose`.
+    fn targets(&self) -> Vec<Target> {
+        let mut targets = Vec::new();
+        for pool in 0.. {
+            let prefix = format!("compose[{}]", pool);
+            let Some(module) = self.get(&format!("{}.mod_name", prefix)) else {
+                break;
+            };
+            if module != "wrp_cte_core" {
+                continue;
+            }
+            for i in 0.. {
+                let item = format!("{}.storage[{}]", prefix, i);
+                let Some(path) = self.get(&format!("{}.path", item)) else {
+                    break;
+                };
+                let field = |name: &str| self.get(&format!("{}.{}", item, name));
+                targets.push(Target {
+                    path: path.to_string(),
+                    ram: field("bdev_type") == Some("ram") || path.starts_with("ram::"),
+                    capacity: field("capacity_limit").and_then(parse_size),
+                });
+            }
+        }
+        targets
+    }
+
+    fn has_cte_pool(&self) -> bool {
+        self.values.iter().any(|(p, v)| {
+            p.starts_with("compose[") && p.ends_with("].mod_name") && v == "wrp_cte_core"
+        })
+    }
+}
+
+/// A storage target from a configuration.
+struct Target {
+    path: String,
+    ram: bool,
+    capacity: Option<u64>,
+}
+
+/// `line` without a trailing `#` comment outside quotes.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut prev = ' ';
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '#') if prev.is_whitespace() => return &line[..i],
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if c == q => quote = None,
+            _ => {}
+        }
+        prev = c;
+    }
+    line
+}
+
+/// Split `key: value`, where the value may be empty.
+fn split_key(content: &str) -> Option<(&str, &str)> {
+    let (key, value) = match content.find(": ") {
+        Some(i) => (&content[..i], &content[i + 2..]),
+        None => (content.strip_suffix(':')?, ""),
+    };
+    let key = key.trim();
+    (!key.is_empty() && !key.contains(' ')).then_some((unquote_str(key), value.trim()))
+}
+
+fn unquote_str(s: &str) -> &str {
+    for q in ['"', '\''] {
+        if let Some(inner) = s.strip_prefix(q).and_then(|s| s.strip_suffix(q)) {
+            return inner;
+        }
+    }
+    s
+}
+
+fn unquote(s: &str) -> String {
+    unquote_str(s.trim()).to_string()
+}
+
+/// Parse a size such as `512MB`, `10GB`, `64k` or `4096` (binary multiples, as
+/// the runtime reads them).
+fn parse_size(s: &str) -> Option<u64> {
+    let upper = s.trim().to_ascii_uppercase();
+    let digits = upper.trim_end_matches("IB").trim_end_matches('B');
+    let (num, shift) = match digits.chars().last()? {
+        'K' => (&digits[..digits.len() - 1], 10),
+        'M' => (&digits[..digits.len() - 1], 20),
+        'G' => (&digits[..digits.len() - 1], 30),
+        'T' => (&digits[..digits.len() - 1], 40),
+        _ => (digits, 0),
+    };
+    num.trim().parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+/// `bytes` in the largest binary unit that keeps it whole enough to read.
+fn human(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Expand `${VAR}` references the way the runtime does; unset variables expand
+/// to nothing.
+fn expand(path: &str) -> String {
+    let mut out = String::new();
+    let mut rest = path;
+    while let Some(start) = rest.find("${") {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+        out.push_str(&std::env::var(&rest[start + 2..start + len]).unwrap_or_default());
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Field `key` of a `/proc` file of `key: value` or `key value` lines.
+fn proc_field(text: &str, key: &str) -> Option<String> {
+    text.lines()
+        .find_map(|l| l.strip_prefix(key))
+        .map(|v| v.trim_start_matches(':').trim().to_string())
+}
+
+/// Whether a file can be created in `dir`, found by creating one.
+fn dir_writable(dir: &Path) -> io::Result<()> {
+    let probe = dir.join(format!(".cte-preflight-{}", std::process::id()));
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)?;
+    fs::remove_file(&probe)
+}
+
+/// The configuration the runtime would load, and where that was decided from.
+pub(crate) fn locate(config_path: &str) -> (Option<PathBuf>, PreflightCheck) {
+    let env = ["CHI_SERVER_CONF", "WRP_RUNTIME_CONF"]
+        .into_iter()
+        .find_map(|var| std::env::var(var).ok().map(|v| (var, v)));
+    let home = std::env::var("HOME")
+        .ok()
+        .map(|h| PathBuf::from(h).join(".chimaera/chimaera.yaml"))
+        .filter(|p| p.exists());
+    let runtime = env
+        .as_ref()
+        .map(|(_, v)| PathBuf::from(v))
+        .or_else(|| home.clone());
+    let source = match (&env, &home) {
+        (Some((var, _)), _) => var.to_string(),
+        (None, Some(_)) => "~/.chimaera/chimaera.yaml".to_string(),
+        (None, None) => String::new(),
+    };
+    if config_path.is_empty() {
+        return match runtime {
+            Some(path) => {
+                let detail = format!("{} (from {})", path.display(), source);
+                (Some(path), PreflightCheck::ok("config", detail))
+            }
+            None => (
+                None,
+                PreflightCheck::warn(
+                    "config",
+                    "none found; the runtime starts with built-in defaults",
+                    "pass --config or set CHI_SERVER_CONF to the runtime configuration",
+                ),
+            ),
+        };
+    }
+    let path = PathBuf::from(config_path);
+    let check = match runtime {
+        Some(runtime) if same_file(&runtime, &path) => {
+            PreflightCheck::ok("config", format!("{} (from {})", path.display(), source))
+        }
+        Some(runtime) => PreflightCheck::warn(
+            "config",
+            format!(
+                "the runtime reads {} (from {}), not {}",
+                runtime.display(),
+                source,
+                path.display()
+            ),
+            format!("export CHI_SERVER_CONF={}", path.display()),
+        ),
+        None => PreflightCheck::warn(
+            "config",
+            format!(
+                "the runtime ignores {} without CHI_SERVER_CONF",
+                path.display()
+            ),
+            format!("export CHI_SERVER_CONF={}", path.display()),
+        ),
+    };
+    (Some(path), check)
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn check_target(target: &Target, mem_available: Option<u64>) -> PreflightCheck {
+    let capacity = target
+        .capacity
+        .map_or_else(|| "no capacity limit".to_string(), human);
+    if target.ram {
+        let name = format!("target {}", target.path);
+        return match (target.capacity, mem_available) {
+            (Some(c), Some(avail)) if c > avail => PreflightCheck::warn(
+                name,
+                format!("RAM target of {} but {} available", capacity, human(avail)),
+                "lower capacity_limit or free memory on this host",
+            ),
+            _ => PreflightCheck::ok(name, format!("RAM target, {}", capacity)),
+        };
+    }
+    let path = PathBuf::from(expand(&target.path));
+    let name = format!("target {}", path.display());
+    match fs::metadata(&path) {
+        Ok(meta) if meta.is_dir() => PreflightCheck::fail(
+            name,
+            "is a directory; file targets name the file the runtime creates",
+            format!(
+                "use a file path such as {}",
+                path.join("cte_target").display()
+            ),
+        ),
+        Ok(_) => match OpenOptions::new().read(true).write(true).open(&path) {
+            Ok(_) => PreflightCheck::ok(name, format!("existing file, {}", capacity)),
+            Err(e) => PreflightCheck::fail(
+                name,
+                format!("can't be opened for writing: {}", e),
+                format!("chown or chmod {} for the runtime's user", path.display()),
+            ),
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let dir = path
+                .parent()
+                .filter(|d| !d.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            if !dir.is_dir() {
+                return PreflightCheck::fail(
+                    name,
+                    format!("directory {} does not exist", dir.display()),
+                    format!("mkdir -p {}", dir.display()),
+                );
+            }
+            match dir_writable(dir) {
+                Ok(()) => PreflightCheck::ok(name, format!("will be created, {}", capacity)),
+                Err(e) => PreflightCheck::fail(
+                    name,
+                    format!("can't create files in {}: {}", dir.display(), e),
+                    format!("chown or chmod {} for the runtime's user", dir.display()),
+                ),
+            }
+        }
+        Err(e) => PreflightCheck::fail(
+            name,
+            format!("can't be examined: {}", e),
+            "check the path and its permissions",
+        ),
+    }
+}
+
+/// Checks of shared memory against the segments the runtime maps.
+fn check_shm(config: &Config, checks: &mut Vec<PreflightCheck>) {
+    let segment = |key: &str| config.get(&format!("memory.{}", key)).and_then(parse_size);
+    let client = segment("client_data_segment_size").unwrap_or(DEFAULT_CLIENT_DATA_SEGMENT);
+    let sizes = [
+        Some(client),
+        segment("main_segment_size"),
+        segment("runtime_data_segment_size"),
+    ];
+    let total: u64 = sizes.iter().flatten().sum();
+    let largest = sizes.iter().flatten().copied().max().unwrap_or(client);
+
+    let shm = Path::new("/dev/shm");
+    let size = fs::read_to_string("/proc/mounts").ok().and_then(|mounts| {
+        mounts
+            .lines()
+            .map(|l| l.split_whitespace().collect::<Vec<_>>())
+            .find(|f| f.get(1) == Some(&"/dev/shm"))
+            .and_then(|f| f.get(3)?.split(',').find_map(|o| o.strip_prefix("size=")))
+            .and_then(parse_size)
+    });
+    checks.push(match (dir_writable(shm), size) {
+        (Err(e), _) => PreflightCheck::fail(
+            "/dev/shm",
+            format!("not writable: {}", e),
+            "mount a tmpfs at /dev/shm writable by the runtime's user",
+        ),
+        (Ok(()), Some(size)) if size < total => PreflightCheck::fail(
+            "/dev/shm",
+            format!("{} but the segments need {}", human(size), human(total)),
+            format!(
+                "mount -o remount,size={}M /dev/shm (with Docker, --shm-size)",
+                total.div_ceil(1 << 20) * 2
+            ),
+        ),
+        (Ok(()), Some(size)) => PreflightCheck::ok(
+            "/dev/shm",
+            format!("{} for {} of segments", human(size), human(total)),
+        ),
+        (Ok(()), None) => PreflightCheck::ok(
+            "/dev/shm",
+            format!("writable; the segments need {}", human(total)),
+        ),
+    });
+
+    if let Some(shmmax) = fs::read_to_string("/proc/sys/kernel/shmmax")
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+    {
+        checks.push(if shmmax < largest {
+            PreflightCheck::warn(
+                "shmmax",
+                format!(
+                    "{} is below the largest segment, {}",
+                    human(shmmax),
+                    human(largest)
+                ),
+                format!("sysctl -w kernel.shmmax={}", largest),
+            )
+        } else {
+            PreflightCheck::ok("shmmax", human(shmmax))
+        });
+    }
+
+    if let Some(limit) = fs::read_to_string("/proc/self/limits")
+        .ok()
+        .and_then(|l| proc_field(&l, "Max locked memory"))
+    {
+        let soft = limit.split_whitespace().next().unwrap_or_default();
+        checks.push(match soft.parse::<u64>() {
+            Ok(bytes) if bytes < total => PreflightCheck::warn(
+                "memlock",
+                format!(
+                    "{} can't pin the {} of segments",
+                    human(bytes),
+                    human(total)
+                ),
+                "ulimit -l unlimited, or raise memlock in /etc/security/limits.conf",
+            ),
+            Ok(bytes) => PreflightCheck::ok("memlock", human(bytes)),
+            Err(_) => PreflightCheck::ok("memlock", soft),
+        });
+    }
+
+    if let Ok(meminfo) = fs::read_to_string("/proc/meminfo") {
+        let field = |key| proc_field(&meminfo, key).and_then(|v| v.parse::<u64>().ok());
+        let page = proc_field(&meminfo, "Hugepagesize")
+            .and_then(|v| v.split_whitespace().next()?.parse::<u64>().ok())
+            .map_or(2 << 20, |kb| kb << 10);
+        checks.push(match (field("HugePages_Total"), field("HugePages_Free")) {
+            (Some(0), _) | (None, _) => {
+                PreflightCheck::ok("hugepages", "none reserved; segments use regular pages")
+            }
+            (Some(total), Some(free)) => PreflightCheck::ok(
+                "hugepages",
+                format!("{} of {} free ({} pages)", free, total, human(page)),
+            ),
+            (Some(total), None) => PreflightCheck::ok("hugepages", format!("{} reserved", total)),
+        });
+    }
+}
+
+/// Directories the loader searches, in order.
+fn library_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = std::env::var("LD_LIBRARY_PATH")
+        .unwrap_or_default()
+        .split(':')
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .collect();
+    if let Ok(prefix) = std::env::var("CONDA_PREFIX") {
+        dirs.push(Path::new(&prefix).join("lib"));
+    }
+    dirs.extend(LIBRARY_DIRS.iter().map(PathBuf::from));
+    dirs
+}
+
+/// Which copy of each library was loaded into this process, and any other
+/// copies the loader could pick up instead.
+fn check_libraries(checks: &mut Vec<PreflightCheck>) {
+    let maps = fs::read_to_string("/proc/self/maps").unwrap_or_default();
+    let dirs = library_dirs();
+    for lib in LIBRARIES {
+        let file = format!("lib{}.so", lib);
+        let loaded = maps
+            .lines()
+            .filter_map(|l| l.split_whitespace().nth(5))
+            .find(|p| {
+                Path::new(p)
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n == file || n.starts_with(&format!("{}.", file)))
+            })
+            .map(PathBuf::from);
+        let mut found: Vec<PathBuf> = Vec::new();
+        for dir in &dirs {
+            if let Ok(real) = fs::canonicalize(dir.join(&file)) {
+                if !found.contains(&real) {
+                    found.push(real);
+                }
+            }
+        }
+        let name = format!("library {}", file);
+        let Some(used) = loaded.or_else(|| found.first().cloned()) else {
+            checks.push(PreflightCheck::fail(
+                name,
+                "not found",
+                "install IOWarp core, or add its lib directory to LD_LIBRARY_PATH",
+            ));
+            continue;
+        };
+        let others: Vec<String> = found
+            .iter()
+            .filter(|p| **p != used)
+            .map(|p| p.display().to_string())
+            .collect();
+        checks.push(if others.is_empty() {
+            PreflightCheck::ok(name, used.display().to_string())
+        } else {
+            PreflightCheck::warn(
+                name,
+                format!("{}; also found {}", used.display(), others.join(", ")),
+                "remove the stale copies or fix LD_LIBRARY_PATH so every process \
+                 and the runtime load the same build",
+            )
+        });
+    }
+}
+
+fn check_network(config: &Config, checks: &mut Vec<PreflightCheck>) {
+    if let Some(hostfile) = config.get("networking.hostfile").filter(|h| !h.is_empty()) {
+        let path = expand(hostfile);
+        checks.push(match fs::read_to_string(&path) {
+            Ok(hosts) => {
+                let n = hosts.lines().filter(|l| !l.trim().is_empty()).count();
+                if n == 0 {
+                    PreflightCheck::fail(
+                        "hostfile",
+                        format!("{} lists no hosts", path),
+                        "add one host per line, this one included",
+                    )
+                } else {
+                    PreflightCheck::ok("hostfile", format!("{}, {} hosts", path, n))
+                }
+            }
+            Err(e) => PreflightCheck::fail(
+                "hostfile",
+                format!("{}: {}", path, e),
+                "create the hostfile or fix networking.hostfile",
+            ),
+        });
+    }
+
+    let port = match config.get("networking.port").map(str::parse::<u16>) {
+        None => DEFAULT_PORT,
+        Some(Ok(port)) => port,
+        Some(Err(_)) => {
+            checks.push(PreflightCheck::fail(
+                "port",
+                format!(
+                    "networking.port {:?} isn't a port",
+                    config.get("networking.port")
+                ),
+                "set networking.port to a number between 1 and 65535",
+            ));
+            return;
+        }
+    };
+    let name = format!("port {}", port);
+    checks.push(match TcpListener::bind(("0.0.0.0", port)) {
+        Ok(_) => PreflightCheck::ok(name, "free"),
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse => PreflightCheck::warn(
+            name,
+            "in use; expected only if the runtime is already running here",
+            format!(
+                "find the holder with `ss -ltnp 'sport = :{}'`, stop it or change networking.port",
+                port
+            ),
+        ),
+        Err(e) => PreflightCheck::fail(
+            name,
+            format!("can't be bound: {}", e),
+            "choose another networking.port (ports below 1024 need privileges)",
+        ),
+    });
+}
+
+impl Client {
+    /// Check the configuration at `config_path` (or the one the runtime would
+    /// find, if empty) against this host; see `preflight`. Needs no `init`.
+    pub fn preflight(config_path: &str) -> PreflightReport {
+        let (path, located) = locate(config_path);
+        let mut report = PreflightReport {
+            config: path.clone(),
+            checks: vec![located],
+        };
+        let config = match &path {
+            None => Config::default(),
+            Some(path) => match fs::read_to_string(path).map_err(|e| e.to_string()) {
+                Ok(text) => match Config::parse(&text) {
+                    Ok(config) => config,
+                    Err(e) => {
+                        report.checks.push(PreflightCheck::fail(
+                            "config",
+                            format!("{}: {}", path.display(), e),
+                            "fix the YAML; indent with spaces",
+                        ));
+                        Config::default()
+                    }
+                },
+                Err(e) => {
+                    report.checks.push(PreflightCheck::fail(
+                        "config",
+                        format!("{}: {}", path.display(), e),
+                        "check the path and its permissions",
+                    ));
+                    Config::default()
+                }
+            },
+        };
+
+        if path.is_some() && !config.has_cte_pool() {
+            report.checks.push(PreflightCheck::warn(
+                "targets",
+                "no wrp_cte_core pool in compose, so no storage targets",
+                "add a wrp_cte_core entry with a storage list to compose",
+            ));
+        }
+        let mem_available = fs::read_to_string("/proc/meminfo")
+            .ok()
+            .and_then(|m| proc_field(&m, "MemAvailable"))
+            .and_then(|v| v.split_whitespace().next()?.parse::<u64>().ok())
+            .map(|kb| kb << 10);
+        for target in config.targets() {
+            report.checks.push(check_target(&target, mem_available));
+        }
+        check_shm(&config, &mut report.checks);
+        check_libraries(&mut report.checks);
+        check_network(&config, &mut report.checks);
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preflight_config() {
+        let dir = std::env::temp_dir().join(format!("cte_preflight_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let text = format!(
+            "---\n\
+             networking:\n  port: 9413   # RPC\n  hostfile: \"\"\n\
+             memory:\n  client_data_segment_size: 2GB\n\
+             compose:\n\
+             - mod_name: chimaera_bdev\n  capacity: 512MB\n\
+             - mod_name: wrp_cte_core\n  pool_name: cte_main\n  storage:\n\
+             \x20   - path: \"ram::tier1\"\n      bdev_type: ram\n      capacity_limit: 1GB\n\
+             \x20   - path: {dir}/target\n      bdev_type: file\n\
+             \x20   - path: {dir}/missing/target\n      capacity_limit: 10G\n\
+             \x20 dpe:\n    dpe_type: max_bw # or random\n",
+            dir = dir.display()
+        );
+        let config = Config::parse(&text).unwrap();
+        assert_eq!(config.get("networking.port"), Some("9413"));
+        assert_eq!(config.get("networking.hostfile"), Some(""));
+        assert_eq!(config.get("compose[1].storage[0].path"), Some("ram::tier1"));
+        assert_eq!(config.get("compose[1].dpe.dpe_type"), Some("max_bw"));
+        assert_eq!(config.get("compose[1].storage[2].dpe"), None);
+        assert!(config.has_cte_pool());
+        assert!(Config::parse("a:\n\tb: 1\n").is_err());
+
+        let targets = config.targets();
+        assert_eq!(targets.len(), 3);
+        assert!(targets[0].ram);
+        assert_eq!(targets[0].capacity, Some(1 << 30));
+        assert_eq!(targets[2].capacity, Some(10 << 30));
+        let status: Vec<_> = targets
+            .iter()
+            .map(|t| check_target(t, Some(64 << 30)).status)
+            .collect();
+        assert_eq!(
+            status,
+            [CheckStatus::Ok, CheckStatus::Ok, CheckStatus::Fail]
+        );
+        assert_eq!(
+            check_target(&targets[0], Some(1 << 20)).status,
+            CheckStatus::Warn
+        );
+
+        let path = dir.join("wrp.yaml");
+        fs::write(&path, &text).unwrap();
+        let report = Client::preflight(path.to_str().unwrap());
+        assert_eq!(report.config.as_deref(), Some(path.as_path()));
+        assert!(!report.ok());
+        assert!(report
+            .checks
+            .iter()
+            .all(|c| (c.status == CheckStatus::Ok) == c.fix.is_none()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,243 @@
+//! Giving memory back under host memory pressure.
+//!
+//! An application embedding CTE shares its memory with the wrapper's read
+//! cache, read-ahead and write-back buffers. `Client::watch_memory_pressure`
+//! starts a thread that checks the kernel's memory-pressure signals every
+//! `poll_interval` and, while they show pressure, shrinks them: the read cache
+//! is halved, least recently used first, ranges read ahead are dropped and
+//! write-back buffers are flushed (a refused write's error is kept for
+//! `Tag::flush`, as with any flush). Their settings don't change, so they fill
+//! up again once the pressure has passed.
+//!
+//! The signals are the pressure stall information (PSI) of the process's
+//! cgroup v2 (`memory.pressure`), or of the whole host
+//! (`/proc/pressure/memory`) outside one, and the cgroup's `memory.current`
+//! against its `memory.max`. There is pressure when the share of the last ten
+//! seconds in which some task stalled on memory (`some avg10`) reaches
+//! `psi_threshold` percent, or the cgroup's usage reaches `usage_ratio` of its
+//! limit. This is best effort: it needs Linux 4.20 or later with PSI on, and
+//! only reaches memory this process holds, not the runtime's or the shared
+//! data segment's.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::{readahead, readcache, writeback, Client, CteError};
+
+/// Settings for `Client::watch_memory_pressure`.
+#[derive(Debug, Clone)]
+pub struct MemoryPressureOptions {
+    /// `some avg10` percentage at which memory is given back.
+    pub psi_threshold: f64,
+    /// Share of the cgroup's `memory.max` in use at which memory is given
+    /// back; `None` to go by PSI alone.
+    pub usage_ratio: Option<f64>,
+    /// How often the signals are read.
+    pub poll_interval: Duration,
+}
+
+impl Default for MemoryPressureOptions {
+    fn default() -> Self {
+        Self {
+            psi_threshold: 10.0,
+            usage_ratio: Some(0.9),
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// The files the signals are read from.
+#[derive(Debug, Default, PartialEq)]
+struct Sources {
+    psi: Option<PathBuf>,
+    /// `memory.current` and `memory.max` of the cgroup.
+    usage: Option<(PathBuf, PathBuf)>,
+}
+
+/// The cgroup v2 path in `/proc/self/cgroup`.
+fn cgroup_path(proc_cgroup: &str) -> Option<&str> {
+    proc_cgroup.lines().find_map(|l| l.strip_prefix("0::"))
+}
+
+/// `some avg10` from a PSI file.
+fn parse_psi(text: &str) -> Option<f64> {
+    let some = text.lines().find(|l| l.starts_with("some "))?;
+    some.split_whitespace()
+        .find_map(|f| f.strip_prefix("avg10="))?
+        .parse()
+        .ok()
+}
+
+/// Share of `max` that `current` is; `None` without a limit.
+fn parse_usage(current: &str, max: &str) -> Option<f64> {
+    let current: f64 = current.trim().parse().ok()?;
+    let max: f64 = max.trim().parse().ok()?;
+    (max > 0.0).then(|| current / max)
+}
+
+impl Sources {
+    /// The signals readable under `root` (`/` but in tests).
+    fn find(root: &Path) -> Self {
+        let readable = |p: &Path| fs::read_to_string(p).is_ok();
+        let cgroup = fs::read_to_string(root.join("proc/self/cgroup"))
+            .ok()
+            .and_then(|c| {
+                cgroup_path(&c).map(|p| root.join("sys/fs/cgroup").join(p.trim_start_matches('/')))
+            });
+        let mut sources = Sources::default();
+        if let Some(dir) = cgroup {
+            let psi = dir.join("memory.pressure");
+            sources.psi = readable(&psi).then_some(psi);
+            let (current, max) = (dir.join("memory.current"), dir.join("memory.max"));
+            if readable(&current) && readable(&max) {
+                sources.usage = Some((current, max));
+            }
+        }
+        if sources.psi.is_none() {
+            let psi = root.join("proc/pressure/memory");
+            sources.psi = readable(&psi).then_some(psi);
+        }
+        sources
+    }
+
+    fn under_pressure(&self, options: &MemoryPressureOptions) -> bool {
+        let psi = self
+            .psi
+            .as_ref()
+            .and_then(|p| parse_psi(&fs::read_to_string(p).ok()?));
+        if psi.is_some_and(|p| p >= options.psi_threshold) {
+            return true;
+        }
+        let usage = self.usage.as_ref().and_then(|(current, max)| {
+            parse_usage(
+                &fs::read_to_string(current).ok()?,
+                &fs::read_to_string(max).ok()?,
+            )
+        });
+        matches!((usage, options.usage_ratio), (Some(u), Some(r)) if u >= r)
+    }
+}
+
+/// Shrink everything `pressure` describes; returns the bytes freed.
+fn shrink() -> u64 {
+    (readcache::shrink() + readahead::shrink() + writeback::flush_buffers()) as u64
+}
+
+#[derive(Default)]
+struct Counts {
+    shrinks: AtomicU64,
+    freed: AtomicU64,
+}
+
+/// A running watch from `Client::watch_memory_pressure`; stops when dropped.
+pub struct MemoryPressureWatch {
+    stop: Sender<()>,
+    counts: Arc<Counts>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl MemoryPressureWatch {
+    /// Times memory was given back so far.
+    pub fn shrinks(&self) -> u64 {
+        self.counts.shrinks.load(Ordering::Relaxed)
+    }
+
+    /// Bytes given back so far.
+    pub fn freed_bytes(&self) -> u64 {
+        self.counts.freed.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for MemoryPressureWatch {
+    fn drop(&mut self) {
+        let _ = self.stop.send(());
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Client {
+    /// Give memory back while the host is under memory pressure (see
+    /// `pressure`), until the returned watch is dropped. Fails with
+    /// `Unsupported` where no pressure signal can be read.
+    pub fn watch_memory_pressure(
+        options: MemoryPressureOptions,
+    ) -> Result<MemoryPressureWatch, CteError> {
+        let sources = Sources::find(Path::new("/"));
+        if sources == Sources::default() {
+            return Err(CteError::Unsupported(
+                "no memory pressure signal (PSI or cgroup v2) can be read".into(),
+            ));
+        }
+        let (stop, stopped) = mpsc::channel();
+        let counts = Arc::new(Counts::default());
+        let worker_counts = counts.clone();
+        let worker = std::thread::spawn(move || loop {
+            match stopped.recv_timeout(options.poll_interval) {
+                Err(RecvTimeoutError::Timeout) => {
+                    if sources.under_pressure(&options) {
+                        worker_counts.shrinks.fetch_add(1, Ordering::Relaxed);
+                        worker_counts.freed.fetch_add(shrink(), Ordering::Relaxed);
+                    }
+                }
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => return,
+            }
+        });
+        Ok(MemoryPressureWatch {
+            stop,
+            counts,
+            worker: Some(worker),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pressure_signals() {
+        let psi = "some avg10=12.50 avg60=3.00 avg300=0.80 total=123456\n\
+                   full avg10=1.00 avg60=0.20 avg300=0.05 total=2345\n";
+        assert_eq!(parse_psi(psi), Some(12.5));
+        assert_eq!(parse_psi("full avg10=1.00\n"), None);
+        assert_eq!(parse_usage("900\n", "1000\n"), Some(0.9));
+        assert_eq!(parse_usage("900\n", "max\n"), None);
+        assert_eq!(
+            cgroup_path("0::/user.slice/app.scope\n"),
+            Some("/user.slice/app.scope")
+        );
+        assert_eq!(cgroup_path("12:memory:/app\n"), None);
+
+        let root = std::env::temp_dir().join(format!("cte_pressure_{}", std::process::id()));
+        let cgroup = root.join("sys/fs/cgroup/app");
+        fs::create_dir_all(&cgroup).unwrap();
+        fs::create_dir_all(root.join("proc/self")).unwrap();
+        fs::write(root.join("proc/self/cgroup"), "0::/app\n").unwrap();
+        fs::write(cgroup.join("memory.pressure"), psi).unwrap();
+        fs::write(cgroup.join("memory.current"), "500\n").unwrap();
+        fs::write(cgroup.join("memory.max"), "1000\n").unwrap();
+        let sources = Sources::find(&root);
+        assert_eq!(sources.psi, Some(cgroup.join("memory.pressure")));
+        let options = MemoryPressureOptions::default();
+        assert!(sources.under_pressure(&options));
+        let options = MemoryPressureOptions {
+            psi_threshold: 20.0,
+            usage_ratio: Some(0.4),
+            ..Default::default()
+        };
+        assert!(sources.under_pressure(&options));
+        let options = MemoryPressureOptions {
+            usage_ratio: None,
+            ..options
+        };
+        assert!(!sources.under_pressure(&options));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,168 @@
+//! Trace context propagation into the runtime.
+//!
+//! A registered `TraceContextProvider` is asked for the caller's current trace
+//! context (typically the active OpenTelemetry span) whenever the shim submits
+//! a put, through the `trace_key` hook. For sampled contexts the put's task is
+//! flagged for tracing and carries the span ID as its trace key, which the
+//! runtime keeps with the blob (and in its metadata snapshots), so a stored blob
+//! can be followed back to the span that wrote it. The runtime's task context
+//! has room for 64 bits, so the trace ID itself stays client-side, and get tasks
+//! have no context to carry anything.
+//!
+//! The wrapper doesn't depend on OpenTelemetry; the provider adapts whatever
+//! the application uses:
+//!
+//! ```ignore
+//! use opentelemetry::trace::TraceContextExt;
+//!
+//! wrp_cte_rs::set_trace_context_provider(|| {
+//!     let cx = opentelemetry::Context::current();
+//!     let span = cx.span().span_context().clone();
+//!     span.is_valid().then(|| wrp_cte_rs::TraceContext {
+//!         trace_id: u128::from_be_bytes(span.trace_id().to_bytes()),
+//!         span_id: u64::from_be_bytes(span.span_id().to_bytes()),
+//!         sampled: span.is_sampled(),
+//!     })
+//! });
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+
+use crate::ffi_guard;
+
+/// A W3C trace context: the trace an operation belongs to and its parent span.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceContext {
+    pub trace_id: u128,
+    pub span_id: u64,
+    pub sampled: bool,
+}
+
+impl TraceContext {
+    /// Parse a W3C `traceparent` header value.
+    pub fn from_traceparent(value: &str) -> Option<Self> {
+        let mut parts = value.trim().split('-');
+        let (version, trace, span, flags) =
+            (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        let hex = |s: &str, len: usize| s.len() == len && s.bytes().all(|b| b.is_ascii_hexdigit());
+        if !hex(version, 2)
+            || version == "ff"
+            || !hex(trace, 32)
+            || !hex(span, 16)
+            || !hex(flags, 2)
+        {
+            return None;
+        }
+        // Later versions may append fields; version 00 has exactly four.
+        if version == "00" && parts.next().is_some() {
+            return None;
+        }
+        let trace_id = u128::from_str_radix(trace, 16).ok()?;
+        let span_id = u64::from_str_radix(span, 16).ok()?;
+        let flags = u8::from_str_radix(flags, 16).ok()?;
+        (trace_id != 0 && span_id != 0).then_some(Self {
+            trace_id,
+            span_id,
+            sampled: flags & 1 != 0,
+        })
+    }
+
+    /// This context as a `traceparent` header value.
+    pub fn traceparent(&self) -> String {
+        format!(
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id,
+            self.span_id,
+            u8::from(self.sampled)
+        )
+    }
+}
+
+/// Supplies the trace context of the calling thread's current operation.
+pub trait TraceContextProvider: Send + Sync {
+    fn current(&self) -> Option<TraceContext>;
+}
+
+impl<F> TraceContextProvider for F
+where
+    F: Fn() -> Option<TraceContext> + Send + Sync,
+{
+    fn current(&self) -> Option<TraceContext> {
+        self()
+    }
+}
+
+static PROVIDER: RwLock<Option<Box<dyn TraceContextProvider>>> = RwLock::new(None);
+/// Mirrors `PROVIDER.is_some()` so untraced puts skip the lock.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Register the process-wide trace context provider, replacing any previous one.
+pub fn set_trace_context_provider(provider: impl TraceContextProvider + 'static) {
+    *PROVIDER.write().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(provider));
+    ENABLED.store(true, Ordering::Release);
+}
+
+/// Remove the trace context provider; puts go back to carrying no context.
+pub fn clear_trace_context_provider() {
+    ENABLED.store(false, Ordering::Release);
+    *PROVIDER.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Shim hook: trace key for a put being submitted, or 0 for none.
+///
+/// Called from C++, so a panicking provider means no context rather than
+/// unwinding across the bridge.
+pub(crate) fn trace_key() -> u64 {
+    if !ENABLED.load(Ordering::Acquire) {
+        return 0;
+    }
+    let provider = PROVIDER.read().unwrap_or_else(|e| e.into_inner());
+    let current = provider
+        .as_ref()
+        .and_then(|p| ffi_guard::hook("trace_key", None, || p.current()));
+    match current {
+        Some(ctx) if ctx.sampled => ctx.span_id,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traceparent() {
+        let header = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let ctx = TraceContext::from_traceparent(header).unwrap();
+        assert_eq!(ctx.trace_id, 0x4bf92f3577b34da6a3ce929d0e0e4736);
+        assert_eq!(ctx.span_id, 0x00f067aa0ba902b7);
+        assert!(ctx.sampled);
+        assert_eq!(ctx.traceparent(), header);
+
+        let unsampled = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00";
+        assert!(!TraceContext::from_traceparent(unsampled).unwrap().sampled);
+        for bad in [
+            "",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01",
+        ] {
+            assert_eq!(TraceContext::from_traceparent(bad), None, "{}", bad);
+        }
+        // Unknown later versions may carry more fields.
+        let future = "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra";
+        assert!(TraceContext::from_traceparent(future).is_some());
+
+        assert_eq!(trace_key(), 0);
+        set_trace_context_provider(move || TraceContext::from_traceparent(header));
+        assert_eq!(trace_key(), 0x00f067aa0ba902b7);
+        set_trace_context_provider(move || TraceContext::from_traceparent(unsampled));
+        assert_eq!(trace_key(), 0);
+        set_trace_context_provider(|| -> Option<TraceContext> { panic!("provider bug") });
+        assert_eq!(trace_key(), 0);
+        clear_trace_context_provider();
+    }
+}
@@ -0,0 +1,335 @@
+//! Structured blob queries with predicates on size, score and attributes.
+//!
+//! `Client::query` runs the tag/blob regex query and resolves every match's size
+//! and score in a single shim call. Size and score bounds implied by the
+//! predicate's top-level conjunction are pushed down into that call, so blobs
+//! outside them never cross the bridge. The full predicate (including `Or`,
+//! `Not` and attribute tests, which need the wrapper's metadata sidecars) is then
+//! evaluated in the wrapper.
+
+use std::str;
+
+use crate::ffi::BlobQueryRow;
+use crate::Client;
+use crate::{ffi, handshake, names, Attrs, Capability, CteError, Tag};
+
+/// Comparison operator for predicates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cmp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl Cmp {
+    fn test<T: PartialOrd>(self, lhs: T, rhs: T) -> bool {
+        match self {
+            Cmp::Lt => lhs < rhs,
+            Cmp::Le => lhs <= rhs,
+            Cmp::Gt => lhs > rhs,
+            Cmp::Ge => lhs >= rhs,
+            Cmp::Eq => lhs == rhs,
+            Cmp::Ne => lhs != rhs,
+        }
+    }
+}
+
+/// A filter over blobs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    Size(Cmp, u64),
+    Score(Cmp, f32),
+    /// Compare attribute `key` against a value; blobs without the key never match.
+    Attr(String, Cmp, String),
+    /// The blob has attribute `key`.
+    HasAttr(String),
+    And(Vec<Predicate>),
+    Or(Vec<Predicate>),
+    Not(Box<Predicate>),
+}
+
+impl Predicate {
+    pub fn size(cmp: Cmp, bytes: u64) -> Self {
+        Predicate::Size(cmp, bytes)
+    }
+
+    pub fn score(cmp: Cmp, score: f32) -> Self {
+        Predicate::Score(cmp, score)
+    }
+
+    pub fn attr_eq(key: &str, value: &str) -> Self {
+        Predicate::Attr(key.to_string(), Cmp::Eq, value.to_string())
+    }
+
+    pub fn and(self, other: Predicate) -> Self {
+        match self {
+            Predicate::And(mut v) => {
+                v.push(other);
+                Predicate::And(v)
+            }
+            p => Predicate::And(vec![p, other]),
+        }
+    }
+
+    pub fn or(self, other: Predicate) -> Self {
+        match self {
+            Predicate::Or(mut v) => {
+                v.push(other);
+                Predicate::Or(v)
+            }
+            p => Predicate::Or(vec![p, other]),
+        }
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self) -> Self {
+        Predicate::Not(Box::new(self))
+    }
+
+    /// True if evaluating this predicate needs blob attributes.
+    fn uses_attrs(&self) -> bool {
+        match self {
+            Predicate::Size(..) | Predicate::Score(..) => false,
+            Predicate::Attr(..) | Predicate::HasAttr(_) => true,
+            Predicate::And(v) | Predicate::Or(v) => v.iter().any(Predicate::uses_attrs),
+            Predicate::Not(p) => p.uses_attrs(),
+        }
+    }
+
+    fn eval(&self, size: u64, score: f32, attrs: &Attrs) -> bool {
+        let attr = |key: &str| attrs.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+        match self {
+            Predicate::Size(cmp, v) => cmp.test(size, *v),
+            Predicate::Score(cmp, v) => cmp.test(score, *v),
+            Predicate::Attr(key, cmp, v) => {
+                attr(key).is_some_and(|a| cmp.test(a.as_str(), v.as_str()))
+            }
+            Predicate::HasAttr(key) => attr(key).is_some(),
+            Predicate::And(v) => v.iter().all(|p| p.eval(size, score, attrs)),
+            Predicate::Or(v) => v.iter().any(|p| p.eval(size, score, attrs)),
+            Predicate::Not(p) => !p.eval(size, score, attrs),
+        }
+    }
+
+    /// Narrow `bounds` by the size/score terms this predicate requires. Only
+    /// conjunctions are followed; anything else can't narrow the superset.
+    fn push_down(&self, b: &mut Bounds) {
+        match self {
+            Predicate::Size(cmp, v) => match cmp {
+                Cmp::Gt => b.min_size = b.min_size.max(v.saturating_add(1)),
+                Cmp::Ge => b.min_size = b.min_size.max(*v),
+                Cmp::Lt => b.max_size = b.max_size.min(v.saturating_sub(1)),
+                Cmp::Le => b.max_size = b.max_size.min(*v),
+                Cmp::Eq => {
+                    b.min_size = b.min_size.max(*v);
+                    b.max_size = b.max_size.min(*v);
+                }
+                Cmp::Ne => {}
+            },
+            // Float bounds are inclusive in the shim; strictness is rechecked in eval.
+            Predicate::Score(cmp, v) => match cmp {
+                Cmp::Gt | Cmp::Ge => b.min_score = b.min_score.max(*v),
+                Cmp::Lt | Cmp::Le => b.max_score = b.max_score.min(*v),
+                Cmp::Eq => {
+                    b.min_score = b.min_score.max(*v);
+                    b.max_score = b.max_score.min(*v);
+                }
+                Cmp::Ne => {}
+            },
+            Predicate::And(v) => v.iter().for_each(|p| p.push_down(b)),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Bounds {
+    min_size: u64,
+    max_size: u64,
+    min_score: f32,
+    max_score: f32,
+}
+
+impl Default for Bounds {
+    fn default() -> Self {
+        Self {
+            min_size: 0,
+            max_size: u64::MAX,
+            min_score: f32::NEG_INFINITY,
+            max_score: f32::INFINITY,
+        }
+    }
+}
+
+/// A structured query for `Client::query`.
+#[derive(Debug, Clone)]
+pub struct QueryBuilder {
+    tag_re: String,
+    blob_re: String,
+    filter: Option<Predicate>,
+    limit: Option<usize>,
+}
+
+impl Default for QueryBuilder {
+    fn default() -> Self {
+        Self {
+            tag_re: ".*".to_string(),
+            blob_re: ".*".to_string(),
+            filter: None,
+            limit: None,
+        }
+    }
+}
+
+impl QueryBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Regex tag names must fully match (default `.*`).
+    pub fn tag(mut self, regex: &str) -> Self {
+        self.tag_re = regex.to_string();
+        self
+    }
+
+    /// Regex blob names must fully match (default `.*`).
+    pub fn blob(mut self, regex: &str) -> Self {
+        self.blob_re = regex.to_string();
+        self
+    }
+
+    /// Add a predicate; multiple calls are ANDed together.
+    pub fn filter(mut self, predicate: Predicate) -> Self {
+        self.filter = Some(match self.filter.take() {
+            Some(p) => p.and(predicate),
+            None => predicate,
+        });
+        self
+    }
+
+    /// Return at most `n` results.
+    pub fn limit(mut self, n: usize) -> Self {
+        self.limit = Some(n);
+        self
+    }
+}
+
+/// One blob matched by `Client::query`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryResult {
+    pub tag: String,
+    pub blob: String,
+    pub size: u64,
+    pub score: f32,
+    /// The blob's attributes, if the query's predicate referenced any.
+    pub attrs: Attrs,
+}
+
+impl Client {
+    /// Run a structured query.
+    pub fn query(query: QueryBuilder) -> Result<Vec<QueryResult>, CteError> {
+        handshake::require(Capability::Query)?;
+        let mut bounds = Bounds::default();
+        let needs_attrs = query.filter.as_ref().is_some_and(Predicate::uses_attrs);
+        if let Some(p) = &query.filter {
+            p.push_down(&mut bounds);
+        }
+        if bounds.min_size > bounds.max_size || bounds.min_score > bounds.max_score {
+            return Ok(Vec::new());
+        }
+        let rows = ffi::client_blob_query_stat(
+            &query.tag_re,
+            &query.blob_re,
+            0,
+            bounds.min_size,
+            bounds.max_size,
+            bounds.min_score,
+            bounds.max_score,
+        );
+        let limit = query.limit.unwrap_or(usize::MAX);
+        let mut out = Vec::new();
+        let mut current: Option<Tag> = None;
+        let policy = names::name_policy();
+        for BlobQueryRow {
+            tag,
+            blob,
+            size,
+            score,
+        } in rows
+        {
+            if out.len() >= limit {
+                break;
+            }
+            // Names that aren't UTF-8 have no wrapper metadata (see `rawname`).
+            let foreign = str::from_utf8(&tag).is_err() || str::from_utf8(&blob).is_err();
+            let (Some(tag), Some(blob)) =
+                (names::decode(&tag, policy)?, names::decode(&blob, policy)?)
+            else {
+                continue;
+            };
+            let attrs = if needs_attrs && !foreign {
+                if current.as_ref().map(|t| t.name()) != Some(tag.as_str()) {
+                    current = Some(Tag::new(&tag));
+                }
+                current.as_ref().unwrap().get_blob_attrs(&blob)?
+            } else {
+                Vec::new()
+            };
+            if query
+                .filter
+                .as_ref()
+                .is_none_or(|p| p.eval(size, score, &attrs))
+            {
+                out.push(QueryResult {
+                    tag,
+                    blob,
+                    size,
+                    score,
+                    attrs,
+                });
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_down_conjunction() {
+        let p = Predicate::size(Cmp::Gt, 1 << 20)
+            .and(Predicate::score(Cmp::Lt, 0.3))
+            .and(Predicate::attr_eq("run_id", "X"));
+        let mut b = Bounds::default();
+        p.push_down(&mut b);
+        assert_eq!(b.min_size, (1 << 20) + 1);
+        assert_eq!(b.max_size, u64::MAX);
+        assert_eq!(b.max_score, 0.3);
+
+        // A disjunction can't narrow the superset.
+        let mut b = Bounds::default();
+        Predicate::size(Cmp::Gt, 10)
+            .or(Predicate::score(Cmp::Lt, 0.1))
+            .push_down(&mut b);
+        assert_eq!(b, Bounds::default());
+    }
+
+    #[test]
+    fn test_eval() {
+        let attrs = vec![("run_id".to_string(), "X".to_string())];
+        let p = Predicate::size(Cmp::Gt, 100)
+            .and(Predicate::score(Cmp::Lt, 0.3))
+            .and(Predicate::attr_eq("run_id", "X"));
+        assert!(p.eval(200, 0.2, &attrs));
+        assert!(!p.eval(200, 0.3, &attrs));
+        assert!(!p.eval(200, 0.2, &Vec::new()));
+        assert!(Predicate::HasAttr("run_id".into())
+            .not()
+            .eval(0, 0.0, &Vec::new()));
+    }
+}
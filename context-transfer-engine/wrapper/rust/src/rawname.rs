@@ -0,0 +1,209 @@
+//! Blob and tag names that aren't UTF-8.
+//!
+//! The runtime names tags and blobs with arbitrary bytes, but the wrapper's API
+//! takes `&str`, so a name imported from a POSIX path that isn't UTF-8 couldn't
+//! be used. The `_bytes_name` methods take names as bytes. A name that is valid
+//! UTF-8 is passed to the ordinary method and gets everything the wrapper
+//! layers on top of the runtime. Any other name goes to the runtime as is: its
+//! data is written and read in `RequestLimits::max_chunk` pieces at the score
+//! given, and the wrapper keeps no metadata for it, so it has no generation,
+//! attributes, checksum, compression, encryption, TTL or trash, writes to it
+//! aren't in the access or change log, and no events are sent for it.
+//! `get_contained_blobs` returns such names as `NamePolicy` says (see `names`);
+//! `get_contained_blobs_bytes` returns them exactly. A tag opened by such a
+//! name has the same replacements in `Tag::name`, events and logs.
+//!
+//! Names travel through the bridge with their length, so they may hold any
+//! bytes, NULs included. Creating a tag or writing a blob whose name is longer
+//! than `ClientOptions::max_name_len` (`DEFAULT_MAX_NAME_LEN` unless set) fails
+//! with `CteError::InvalidArgument` before reaching the runtime; the limit in
+//! force is `RuntimeInfo::max_name_len`. The wrapper's own sidecar names don't
+//! count against it.
+
+use std::str;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{accounting, ffi, ffi_guard, limits, meta, shard, Client, CteError, Tag};
+
+/// Longest tag or blob name, in bytes, unless `ClientOptions::max_name_len`
+/// says otherwise.
+const DEFAULT_MAX_NAME_LEN: usize = 4096;
+
+static MAX_NAME_LEN: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_NAME_LEN);
+
+pub(crate) fn set_max_name_len(len: usize) {
+    MAX_NAME_LEN.store(len, Ordering::Relaxed);
+}
+
+pub(crate) fn max_name_len() -> usize {
+    MAX_NAME_LEN.load(Ordering::Relaxed)
+}
+
+/// `InvalidArgument` if `name` is over the name length limit.
+pub(crate) fn check_name(name: &[u8]) -> Result<(), CteError> {
+    let max = max_name_len();
+    if name.len() <= max || name.starts_with(meta::RESERVED_PREFIX.as_bytes()) {
+        return Ok(());
+    }
+    Err(CteError::InvalidArgument(format!(
+        "name of {} bytes is longer than the limit of {} bytes",
+        name.len(),
+        max
+    )))
+}
+
+impl Tag {
+    /// `try_new` for a name that may not be UTF-8 (see `rawname`).
+    pub fn try_new_bytes_name(name: &[u8]) -> Result<Self, CteError> {
+        if let Ok(name) = str::from_utf8(name) {
+            return Self::try_new(name);
+        }
+        let tag = Self {
+            inner: ffi_guard::tag_new_bytes(name)?,
+            changelog: Default::default(),
+            accesslog: Default::default(),
+            versioning: Default::default(),
+            name: String::from_utf8_lossy(name).into_owned(),
+        };
+        shard::load_splits(&tag);
+        Ok(tag)
+    }
+
+    /// `put_blob_with_options` for a blob name that may not be UTF-8,
+    /// returning the runtime's error instead of panicking.
+    pub fn put_blob_bytes_name(
+        &self,
+        name: &[u8],
+        data: &[u8],
+        offset: u64,
+        score: f32,
+    ) -> Result<(), CteError> {
+        if let Ok(name) = str::from_utf8(name) {
+            return self
+                .try_write_blob(name, data, offset, Some(score))
+                .map(|_| ());
+        }
+        let max_chunk = limits::request_limits().max_chunk;
+        for (at, len) in limits::chunks(offset, data.len() as u64, max_chunk) {
+            let from = (at - offset) as usize;
+            let chunk = &data[from..from + len as usize];
+            ffi_guard::tag_put_blob_bytes(&self.inner, name, chunk, at, score)?;
+        }
+        accounting::record(accounting::Op::Put, data.len() as u64);
+        Ok(())
+    }
+
+    /// `get_blob_with_offset` for a blob name that may not be UTF-8, returning
+    /// the runtime's error instead of panicking.
+    pub fn get_blob_bytes_name(
+        &self,
+        name: &[u8],
+        size: u64,
+        offset: u64,
+    ) -> Result<Vec<u8>, CteError> {
+        if let Ok(name) = str::from_utf8(name) {
+            return self.read_blob(name, size, offset);
+        }
+        let limits = limits::request_limits();
+        let mut buf = limits::read_buffer(&String::from_utf8_lossy(name), size, &limits)?;
+        self.read_raw_into(name, &mut buf, offset, limits.max_chunk)?;
+        Ok(buf)
+    }
+
+    /// `read_blob_into` for a blob name that may not be UTF-8.
+    pub(crate) fn read_blob_into_bytes_name(
+        &self,
+        name: &[u8],
+        buf: &mut [u8],
+        offset: u64,
+    ) -> Result<(), CteError> {
+        if let Ok(name) = str::from_utf8(name) {
+            return self.read_blob_into(name, buf, offset);
+        }
+        let limits = limits::request_limits();
+        limits::check_read(&String::from_utf8_lossy(name), buf.len() as u64, &limits)?;
+        self.read_raw_into(name, buf, offset, limits.max_chunk)
+    }
+
+    /// Fill `buf` from `offset` of a blob named by bytes, in `max_chunk` pieces.
+    fn read_raw_into(
+        &self,
+        name: &[u8],
+        buf: &mut [u8],
+        offset: u64,
+        max_chunk: u64,
+    ) -> Result<(), CteError> {
+        let size = buf.len() as u64;
+        let mut at = 0;
+        for (chunk_offset, len) in limits::chunks(offset, size, max_chunk) {
+            let v = ffi_guard::tag_get_blob_bytes(&self.inner, name, len, chunk_offset)?;
+            let n = v.len().min(buf.len() - at);
+            buf[at..at + n].copy_from_slice(&v.as_slice()[..n]);
+            at += n;
+        }
+        accounting::record(accounting::Op::Get, size);
+        Ok(())
+    }
+
+    /// `hard_del_blob` for a blob name that may not be UTF-8.
+    pub(crate) fn hard_del_blob_bytes_name(&self, name: &[u8]) -> bool {
+        match str::from_utf8(name) {
+            Ok(name) => self.hard_del_blob(name),
+            Err(_) => ffi::tag_del_blob_bytes(&self.inner, name),
+        }
+    }
+
+    /// `get_blob_size` for a blob name that may not be UTF-8.
+    pub fn get_blob_size_bytes_name(&self, name: &[u8]) -> u64 {
+        match str::from_utf8(name) {
+            Ok(name) => self.get_blob_size(name),
+            Err(_) => ffi::tag_get_blob_size_bytes(&self.inner, name),
+        }
+    }
+
+    /// `del_blob` for a blob name that may not be UTF-8.
+    pub fn del_blob_bytes_name(&self, name: &[u8]) -> bool {
+        match str::from_utf8(name) {
+            Ok(name) => self.del_blob(name),
+            Err(_) => ffi::tag_del_blob_bytes(&self.inner, name),
+        }
+    }
+
+    /// `get_contained_blobs` with names exactly as stored.
+    pub fn get_contained_blobs_bytes(&self) -> Vec<Vec<u8>> {
+        let v = ffi::tag_get_contained_blobs(&self.inner);
+        v.iter()
+            .map(|s| s.as_bytes())
+            .filter(|s| !s.starts_with(meta::RESERVED_PREFIX.as_bytes()))
+            .map(<[u8]>::to_vec)
+            .collect()
+    }
+}
+
+impl Client {
+    /// `del_tag` for a tag name that may not be UTF-8.
+    pub fn del_tag_bytes_name(name: &[u8]) -> bool {
+        match str::from_utf8(name) {
+            Ok(name) => Client::del_tag(name),
+            Err(_) => ffi::client_del_tag_bytes(name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_limit() {
+        assert!(check_name(&[b'a'; DEFAULT_MAX_NAME_LEN]).is_ok());
+        assert!(check_name(b"nul\0inside").is_ok());
+        let long = [b'a'; DEFAULT_MAX_NAME_LEN + 1];
+        assert!(matches!(
+            check_name(&long),
+            Err(CteError::InvalidArgument(_))
+        ));
+        let sidecar = [meta::META_PREFIX.as_bytes(), &long].concat();
+        assert!(check_name(&sidecar).is_ok());
+    }
+}
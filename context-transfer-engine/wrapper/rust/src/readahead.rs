@@ -0,0 +1,373 @@
+//! Read-ahead for streaming reads.
+//!
+//! A reader scanning a blob in consecutive ranges waits a round trip on each
+//! one. With a read-ahead policy set on the tag (`Tag::set_readahead`), the
+//! wrapper reads the ranges that follow on a background thread while the
+//! caller works on the current one, and answers the next read from memory when
+//! it comes as expected: the same size, starting where the last one ended.
+//!
+//! - `ReadAhead::None`, the default: every read goes to the runtime. Right for
+//!   random access such as index lookups, where ranges read ahead are wasted.
+//! - `ReadAhead::Sequential(n)`: keep `n` ranges ahead of every read.
+//! - `ReadAhead::Adaptive`: start with one range once two reads in a row are
+//!   consecutive, double that each time a range read ahead is used, up to
+//!   `ADAPTIVE_MAX`, and stop at the first read that jumps elsewhere.
+//!
+//! Read-ahead applies to `Tag::get` of blobs stored as written (reads of
+//! compressed or encrypted blobs cover the whole blob anyway) without a
+//! generation precondition, `get_blob_with_offset` and `get_blob_vectored`. At
+//! most `MAX_AHEAD` bytes are read ahead per blob. The policy belongs to the
+//! tag in this process, whichever handle set it. Ranges read ahead are dropped
+//! by writes and deletes made through this process (see `events`) and once
+//! `MAX_AGE` old; a write by another client in between isn't seen, as with the
+//! read cache (see `readcache`).
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::{CteError, CteTagId, Event, Tag};
+
+/// Most ranges `ReadAhead::Adaptive` keeps ahead.
+pub const ADAPTIVE_MAX: u32 = 16;
+/// Most bytes read ahead of one blob; fewer, larger ranges are kept ahead of
+/// large reads.
+const MAX_AHEAD: u64 = 64 << 20;
+/// Ranges read ahead longer ago than this are read again.
+const MAX_AGE: Duration = Duration::from_secs(5);
+/// Blobs followed at once; the least recently read is forgotten past this.
+const MAX_STREAMS: usize = 256;
+
+/// How far a tag's reads are read ahead (see `readahead`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReadAhead {
+    #[default]
+    None,
+    /// Keep this many ranges ahead of every read.
+    Sequential(u32),
+    /// Ramp up while reads are consecutive and stop when they aren't.
+    Adaptive,
+}
+
+type TagKey = (u32, u32);
+type BlobKey = (TagKey, String);
+
+/// A range read ahead: `None` while the read is in flight.
+type Ahead = Option<(Vec<u8>, Instant)>;
+
+/// The reads of one blob.
+struct Stream {
+    /// Tells a background read whether the stream it read for is still the
+    /// one here.
+    id: u64,
+    /// Where the next read is expected, and its size.
+    next: u64,
+    size: u64,
+    /// Consecutive reads so far.
+    run: u32,
+    /// `Adaptive`'s current number of ranges ahead.
+    depth: u32,
+    ahead: HashMap<u64, Ahead>,
+    used: u64,
+}
+
+/// What `State::read` decided.
+#[derive(Debug, PartialEq)]
+struct Plan {
+    /// The data, if it was read ahead.
+    data: Option<Vec<u8>>,
+    stream: u64,
+    /// Offsets to read ahead now.
+    fetch: Vec<u64>,
+}
+
+#[derive(Default)]
+struct State {
+    policies: HashMap<TagKey, ReadAhead>,
+    streams: HashMap<BlobKey, Stream>,
+    clock: u64,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+static NEXT_STREAM: AtomicU64 = AtomicU64::new(1);
+
+fn state() -> MutexGuard<'static, Option<State>> {
+    STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn tag_key(id: CteTagId) -> TagKey {
+    (id.major, id.minor)
+}
+
+impl State {
+    /// Note a read of `size` bytes at `offset` of `key` under `policy`.
+    fn read(
+        &mut self,
+        key: &BlobKey,
+        policy: ReadAhead,
+        offset: u64,
+        size: u64,
+        now: Instant,
+    ) -> Plan {
+        self.clock += 1;
+        if !self.streams.contains_key(key) && self.streams.len() >= MAX_STREAMS {
+            let oldest = self
+                .streams
+                .iter()
+                .min_by_key(|(_, s)| s.used)
+                .map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                self.streams.remove(&oldest);
+            }
+        }
+        let stream = self.streams.entry(key.clone()).or_insert_with(|| Stream {
+            id: NEXT_STREAM.fetch_add(1, Ordering::Relaxed),
+            next: 0,
+            size: 0,
+            run: 0,
+            depth: 0,
+            ahead: HashMap::new(),
+            used: 0,
+        });
+        stream.used = self.clock;
+        if stream.run == 0 || offset != stream.next || size != stream.size {
+            stream.id = NEXT_STREAM.fetch_add(1, Ordering::Relaxed);
+            stream.run = 0;
+            stream.depth = 0;
+            stream.ahead.clear();
+        }
+        stream.run += 1;
+        stream.next = offset.saturating_add(size);
+        stream.size = size;
+        let data = match stream.ahead.remove(&offset) {
+            Some(Some((data, at))) if now.duration_since(at) < MAX_AGE => Some(data),
+            _ => None,
+        };
+        stream.ahead.retain(|&o, _| o > offset);
+        let depth = match policy {
+            ReadAhead::None => 0,
+            ReadAhead::Sequential(n) => n,
+            ReadAhead::Adaptive => {
+                if data.is_some() {
+                    stream.depth = (stream.depth * 2).min(ADAPTIVE_MAX);
+                } else if stream.run >= 2 {
+                    stream.depth = stream.depth.max(1);
+                }
+                stream.depth
+            }
+        };
+        let depth = match size {
+            0 => 0,
+            size => depth.min((MAX_AHEAD / size) as u32),
+        };
+        let mut fetch = Vec::new();
+        for i in 1..=u64::from(depth) {
+            let Some(at) = size.checked_mul(i).and_then(|d| offset.checked_add(d)) else {
+                break;
+            };
+            if let Entry::Vacant(slot) = stream.ahead.entry(at) {
+                slot.insert(None);
+                fetch.push(at);
+            }
+        }
+        Plan {
+            data,
+            stream: stream.id,
+            fetch,
+        }
+    }
+
+    /// Keep `data` read ahead at `offset`, or forget the range if `None`,
+    /// unless `stream` has been restarted or dropped since.
+    fn fill(&mut self, key: &BlobKey, stream: u64, offset: u64, data: Option<Vec<u8>>) -> bool {
+        let Some(s) = self.streams.get_mut(key).filter(|s| s.id == stream) else {
+            return false;
+        };
+        match data {
+            Some(data) => {
+                if let Some(slot @ None) = s.ahead.get_mut(&offset) {
+                    *slot = Some((data, Instant::now()));
+                }
+            }
+            None => {
+                s.ahead.remove(&offset);
+            }
+        }
+        true
+    }
+}
+
+/// Drop what `event` makes stale. Called for every local event.
+pub(crate) fn observe(event: &Event) {
+    if !ENABLED.load(Ordering::Acquire) {
+        return;
+    }
+    let mut state = state();
+    let Some(state) = state.as_mut() else {
+        return;
+    };
+    match event {
+        // Streams are keyed by tag ID and events carry names, so drop the
+        // blob in every tag.
+        Event::BlobPut { blob, .. } | Event::BlobDeleted { blob, .. } => {
+            state.streams.retain(|k, _| k.1 != *blob)
+        }
+        Event::TagCreated { .. } | Event::TagDeleted { .. } => state.streams.clear(),
+        Event::BlobReorganized { .. } | Event::BlobExpiring { .. } => {}
+    }
+}
+
+/// Drop every range read ahead; returns the bytes freed.
+pub(crate) fn shrink() -> usize {
+    let mut state = state();
+    let Some(state) = state.as_mut() else {
+        return 0;
+    };
+    let freed = state
+        .streams
+        .values()
+        .flat_map(|s| s.ahead.values())
+        .map(|a| a.as_ref().map_or(0, |(data, _)| data.len()))
+        .sum();
+    state.streams.clear();
+    freed
+}
+
+/// Read `offsets`, each `size` bytes, of `key` on a thread of its own.
+fn spawn_reads(id: CteTagId, key: BlobKey, stream: u64, size: u64, offsets: Vec<u64>) {
+    std::thread::spawn(move || {
+        let tag = Tag::from_id(id);
+        let blob_size = tag.get_blob_size(&key.1);
+        for (i, &offset) in offsets.iter().enumerate() {
+            // Only whole ranges are kept: the reader's own read of a short
+            // tail fails or succeeds as the runtime decides.
+            let data = (offset.saturating_add(size) <= blob_size)
+                .then(|| tag.read_blob(&key.1, size, offset).ok())
+                .flatten();
+            let failed = data.is_none();
+            let mut state = state();
+            let Some(state) = state.as_mut() else {
+                return;
+            };
+            if !state.fill(&key, stream, offset, data) {
+                return;
+            }
+            if failed {
+                for &rest in &offsets[i + 1..] {
+                    state.fill(&key, stream, rest, None);
+                }
+                return;
+            }
+        }
+    });
+}
+
+impl Tag {
+    /// Read ahead of this tag's reads as `policy` says (see `readahead`).
+    /// Setting `ReadAhead::None` also drops what was read ahead.
+    pub fn set_readahead(&self, policy: ReadAhead) {
+        let key = tag_key(self.get_tag_id());
+        let mut state = state();
+        let state = state.get_or_insert_with(State::default);
+        if policy == ReadAhead::None {
+            state.policies.remove(&key);
+            state.streams.retain(|k, _| k.0 != key);
+        } else {
+            state.policies.insert(key, policy);
+        }
+        ENABLED.store(!state.policies.is_empty(), Ordering::Release);
+    }
+
+    /// The tag's read-ahead policy.
+    pub fn readahead(&self) -> ReadAhead {
+        if !ENABLED.load(Ordering::Acquire) {
+            return ReadAhead::None;
+        }
+        let key = tag_key(self.get_tag_id());
+        state()
+            .as_ref()
+            .and_then(|s| s.policies.get(&key).copied())
+            .unwrap_or_default()
+    }
+
+    /// `read_blob`, answered from what was read ahead and reading ahead of it
+    /// as the tag's policy says.
+    pub(crate) fn read_blob_ahead(
+        &self,
+        name: &str,
+        size: u64,
+        offset: u64,
+    ) -> Result<Vec<u8>, CteError> {
+        if !ENABLED.load(Ordering::Acquire) || crate::meta::is_reserved(name) {
+            return self.read_blob(name, size, offset);
+        }
+        let id = self.get_tag_id();
+        let key = (tag_key(id), name.to_string());
+        let plan = {
+            let mut state = state();
+            let Some(state) = state.as_mut() else {
+                return self.read_blob(name, size, offset);
+            };
+            let Some(&policy) = state.policies.get(&key.0) else {
+                return self.read_blob(name, size, offset);
+            };
+            state.read(&key, policy, offset, size, Instant::now())
+        };
+        if !plan.fetch.is_empty() {
+            spawn_reads(id, key, plan.stream, size, plan.fetch);
+        }
+        match plan.data {
+            Some(data) => Ok(data),
+            None => self.read_blob(name, size, offset),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readahead_plan() {
+        let mut state = State::default();
+        let key = ((1, 2), "scan".to_string());
+        let now = Instant::now();
+
+        let plan = state.read(&key, ReadAhead::Sequential(2), 0, 10, now);
+        assert_eq!((plan.data, plan.fetch), (None, vec![10, 20]));
+        let stream = plan.stream;
+        assert!(state.fill(&key, stream, 10, Some(vec![1; 10])));
+        let plan = state.read(&key, ReadAhead::Sequential(2), 10, 10, now);
+        assert_eq!((plan.data, plan.fetch), (Some(vec![1; 10]), vec![30]));
+        // A jump restarts the stream, and reads still in flight are dropped.
+        let plan = state.read(&key, ReadAhead::Sequential(2), 100, 10, now);
+        assert_eq!(plan.fetch, vec![110, 120]);
+        assert!(!state.fill(&key, stream, 20, Some(vec![2; 10])));
+        assert!(state.fill(&key, plan.stream, 110, Some(vec![3; 10])));
+        let later = now + 2 * MAX_AGE;
+        assert_eq!(
+            state
+                .read(&key, ReadAhead::Sequential(0), 110, 10, later)
+                .data,
+            None
+        );
+
+        // Adaptive waits for a consecutive read, then doubles on each hit.
+        let key = ((1, 2), "index".to_string());
+        let plan = state.read(&key, ReadAhead::Adaptive, 0, 4, now);
+        assert!(plan.fetch.is_empty());
+        let plan = state.read(&key, ReadAhead::Adaptive, 4, 4, now);
+        assert_eq!(plan.fetch, vec![8]);
+        state.fill(&key, plan.stream, 8, Some(vec![0; 4]));
+        let plan = state.read(&key, ReadAhead::Adaptive, 8, 4, now);
+        assert_eq!((plan.data.is_some(), plan.fetch), (true, vec![12, 16]));
+        let plan = state.read(&key, ReadAhead::Adaptive, 0, 4, now);
+        assert!(plan.fetch.is_empty());
+        // Large reads keep fewer ranges ahead.
+        let plan = state.read(&key, ReadAhead::Sequential(4), 0, MAX_AHEAD / 2, now);
+        assert_eq!(plan.fetch.len(), 2);
+    }
+}
@@ -0,0 +1,353 @@
+//! Client-side caching of blob reads.
+//!
+//! With `Client::enable_read_cache`, the data returned by `Tag::get`,
+//! `get_blob` and `get_blob_with_offset` is kept in this process, keyed by
+//! tag, blob and range, and asking for the same range again is answered from
+//! memory. The least recently used ranges are dropped once the cache holds
+//! `ReadCacheOptions::capacity` bytes. Entries of a blob are dropped by the
+//! events of writes and deletes made through this process (see `events`),
+//! whether or not anyone subscribes to them, and a read that raced with such a
+//! write isn't cached. Writes by other clients are only seen through their
+//! change logs when `poll_remote` is set, and otherwise once the entry is `ttl`
+//! old, so `ttl` bounds how stale a read can be. Reads with a generation
+//! precondition or checksum verification always go to the runtime.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::{Client, CteTagId, Event, EventFilter, EventKind, Subscription, Tag};
+
+/// Settings for `Client::enable_read_cache`.
+#[derive(Debug, Clone)]
+pub struct ReadCacheOptions {
+    /// Bytes of data cached at once.
+    pub capacity: usize,
+    /// Larger reads aren't cached.
+    pub max_entry: usize,
+    /// How long a cached read is trusted.
+    pub ttl: Duration,
+    /// Also drop entries for blobs written by other clients, found by polling
+    /// change logs every interval (see `EventFilter::poll_remote`).
+    pub poll_remote: Option<Duration>,
+}
+
+impl Default for ReadCacheOptions {
+    fn default() -> Self {
+        Self {
+            capacity: 64 << 20,
+            max_entry: 1 << 20,
+            ttl: Duration::from_secs(30),
+            poll_remote: None,
+        }
+    }
+}
+
+/// A cached range: offset, size (`None` for "to the end") and whether the
+/// data is as stored (the legacy gets) or as written (`Tag::get`).
+pub(crate) type Range = (u64, Option<u64>, bool);
+
+type BlobKey = ((u32, u32), String);
+
+struct Entry {
+    data: Vec<u8>,
+    expires: Instant,
+    /// Position in `Cache::lru`.
+    used: u64,
+}
+
+struct Cache {
+    capacity: usize,
+    max_entry: usize,
+    ttl: Duration,
+    blobs: HashMap<BlobKey, HashMap<Range, Entry>>,
+    /// Entries by last use, oldest first.
+    lru: BTreeMap<u64, (BlobKey, Range)>,
+    next_use: u64,
+    bytes: usize,
+    /// IDs of the tags with cached reads, by name, for invalidating by event.
+    ids: HashMap<String, (u32, u32)>,
+    _remote: Option<Subscription>,
+}
+
+impl Cache {
+    fn new(options: &ReadCacheOptions, remote: Option<Subscription>) -> Self {
+        Self {
+            capacity: options.capacity,
+            max_entry: options.max_entry.min(options.capacity),
+            ttl: options.ttl,
+            blobs: HashMap::new(),
+            lru: BTreeMap::new(),
+            next_use: 0,
+            bytes: 0,
+            ids: HashMap::new(),
+            _remote: remote,
+        }
+    }
+
+    fn get(&mut self, key: &BlobKey, range: Range, now: Instant) -> Option<Vec<u8>> {
+        let entry = self.blobs.get_mut(key)?.get_mut(&range)?;
+        if entry.expires <= now {
+            self.remove(key, range);
+            return None;
+        }
+        self.lru.remove(&entry.used);
+        entry.used = self.next_use;
+        self.lru.insert(self.next_use, (key.clone(), range));
+        self.next_use += 1;
+        Some(entry.data.clone())
+    }
+
+    fn insert(&mut self, key: BlobKey, tag: &str, range: Range, data: &[u8], now: Instant) {
+        if data.len() > self.max_entry {
+            return;
+        }
+        self.remove(&key, range);
+        while self.bytes + data.len() > self.capacity {
+            let Some((_, (old_key, old_range))) = self.lru.pop_first() else {
+                break;
+            };
+            self.remove(&old_key, old_range);
+        }
+        self.ids.insert(tag.to_string(), key.0);
+        self.lru.insert(self.next_use, (key.clone(), range));
+        self.blobs.entry(key).or_default().insert(
+            range,
+            Entry {
+                data: data.to_vec(),
+                expires: now + self.ttl,
+                used: self.next_use,
+            },
+        );
+        self.next_use += 1;
+        self.bytes += data.len();
+    }
+
+    fn remove(&mut self, key: &BlobKey, range: Range) {
+        let Some(ranges) = self.blobs.get_mut(key) else {
+            return;
+        };
+        if let Some(entry) = ranges.remove(&range) {
+            self.lru.remove(&entry.used);
+            self.bytes -= entry.data.len();
+        }
+        if ranges.is_empty() {
+            self.blobs.remove(key);
+        }
+    }
+
+    /// Drop the least recently used entries until at most `bytes` are cached.
+    fn shrink_to(&mut self, bytes: usize) {
+        while self.bytes > bytes {
+            let Some((_, (key, range))) = self.lru.pop_first() else {
+                break;
+            };
+            self.remove(&key, range);
+        }
+    }
+
+    fn forget(&mut self, keep: impl Fn(&BlobKey) -> bool) {
+        let (lru, bytes) = (&mut self.lru, &mut self.bytes);
+        self.blobs.retain(|key, ranges| {
+            if keep(key) {
+                return true;
+            }
+            for entry in ranges.values() {
+                lru.remove(&entry.used);
+                *bytes -= entry.data.len();
+            }
+            false
+        });
+    }
+
+    /// The cached tag an event's tag name refers to, if any.
+    fn resolve(&self, tag: &str) -> Option<(u32, u32)> {
+        if let Some(&id) = self.ids.get(tag) {
+            return Some(id);
+        }
+        // Handles opened by ID are named `#major.minor`.
+        let (major, minor) = tag.strip_prefix('#')?.split_once('.')?;
+        Some((major.parse().ok()?, minor.parse().ok()?))
+    }
+
+    fn observe(&mut self, event: &Event) {
+        let Some(id) = self.resolve(event.tag()) else {
+            return;
+        };
+        match event {
+            Event::BlobPut { blob, .. } | Event::BlobDeleted { blob, .. } => {
+                let key = (id, blob.clone());
+                self.forget(|k| *k != key);
+            }
+            Event::TagCreated { .. } | Event::TagDeleted { .. } => self.forget(|k| k.0 != id),
+            Event::BlobReorganized { .. } | Event::BlobExpiring { .. } => {}
+        }
+    }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static CACHE: Mutex<Option<Cache>> = Mutex::new(None);
+/// Bumped by every write event, so a read that overlapped one isn't cached.
+static EPOCH: AtomicU64 = AtomicU64::new(0);
+
+fn cache() -> std::sync::MutexGuard<'static, Option<Cache>> {
+    CACHE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn key(id: CteTagId, blob: &str) -> BlobKey {
+    ((id.major, id.minor), blob.to_string())
+}
+
+/// Drop the entries `event` makes stale. Called for every local event.
+pub(crate) fn observe(event: &Event) {
+    if !ENABLED.load(Ordering::Acquire)
+        || matches!(
+            event.kind(),
+            EventKind::BlobReorganized | EventKind::BlobExpiring
+        )
+    {
+        return;
+    }
+    let mut cache = cache();
+    EPOCH.fetch_add(1, Ordering::AcqRel);
+    if let Some(cache) = cache.as_mut() {
+        cache.observe(event);
+    }
+}
+
+/// Halve the data cached, least recently used first; returns the bytes freed.
+pub(crate) fn shrink() -> usize {
+    let mut cache = cache();
+    let Some(cache) = cache.as_mut() else {
+        return 0;
+    };
+    let before = cache.bytes;
+    cache.shrink_to(before / 2);
+    before - cache.bytes
+}
+
+/// Token to take before a read whose data may be cached.
+pub(crate) fn epoch() -> u64 {
+    EPOCH.load(Ordering::Acquire)
+}
+
+impl Tag {
+    /// The cached data of `range` of `name`, if any.
+    pub(crate) fn cached_read(&self, name: &str, range: Range) -> Option<Vec<u8>> {
+        if !ENABLED.load(Ordering::Acquire) {
+            return None;
+        }
+        let key = key(self.get_tag_id(), name);
+        cache()
+            .as_mut()
+            .and_then(|c| c.get(&key, range, Instant::now()))
+    }
+
+    /// Cache `data` as `range` of `name`, read by a read started at `epoch`.
+    pub(crate) fn cache_read(&self, name: &str, range: Range, data: &[u8], epoch: u64) {
+        if !ENABLED.load(Ordering::Acquire) || crate::meta::is_reserved(name) {
+            return;
+        }
+        let key = key(self.get_tag_id(), name);
+        let mut cache = cache();
+        if EPOCH.load(Ordering::Acquire) != epoch {
+            return;
+        }
+        if let Some(cache) = cache.as_mut() {
+            cache.insert(key, self.name(), range, data, Instant::now());
+        }
+    }
+}
+
+impl Client {
+    /// Cache reads as `options` describes (see `readcache`), replacing any
+    /// earlier cache.
+    pub fn enable_read_cache(options: ReadCacheOptions) {
+        let remote = options.poll_remote.map(|interval| {
+            let filter = EventFilter::new()
+                .kinds(&[
+                    EventKind::BlobPut,
+                    EventKind::BlobDeleted,
+                    EventKind::TagCreated,
+                    EventKind::TagDeleted,
+                ])
+                .poll_remote(interval);
+            Client::subscribe_with(filter, |event| {
+                if let Some(cache) = cache().as_mut() {
+                    cache.observe(event);
+                }
+                EPOCH.fetch_add(1, Ordering::AcqRel);
+            })
+        });
+        *cache() = Some(Cache::new(&options, remote));
+        ENABLED.store(true, Ordering::Release);
+    }
+
+    /// Stop caching reads and forget those cached.
+    pub fn disable_read_cache() {
+        ENABLED.store(false, Ordering::Release);
+        let old = cache().take();
+        // Dropping the remote subscription joins its poller, which may be
+        // waiting on the cache lock.
+        drop(old);
+    }
+
+    /// Bytes of data the read cache holds.
+    pub fn read_cache_size() -> usize {
+        cache().as_ref().map_or(0, |c| c.bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_cache() {
+        let options = ReadCacheOptions {
+            capacity: 8,
+            max_entry: 4,
+            ttl: Duration::from_secs(10),
+            poll_remote: None,
+        };
+        let mut cache = Cache::new(&options, None);
+        let now = Instant::now();
+        let a = ((7, 1), "a".to_string());
+        let b = ((7, 1), "b".to_string());
+        let whole = (0, None, false);
+        cache.insert(a.clone(), "config", whole, b"aaaa", now);
+        cache.insert(b.clone(), "config", whole, b"bbb", now);
+        assert_eq!(cache.get(&a, whole, now).unwrap(), b"aaaa");
+        assert_eq!(cache.get(&a, (0, Some(2), false), now), None);
+        assert_eq!(cache.get(&a, whole, now + Duration::from_secs(11)), None);
+        assert_eq!(cache.bytes, 3);
+        // Too big to cache at all.
+        cache.insert(a.clone(), "config", whole, b"aaaaa", now);
+        assert_eq!(cache.bytes, 3);
+
+        // Full: the least recently used goes first.
+        cache.insert(a.clone(), "config", whole, b"aaaa", now);
+        assert!(cache.get(&b, whole, now).is_some());
+        cache.insert(a.clone(), "config", (4, None, true), b"cc", now);
+        assert_eq!(cache.bytes, 5);
+        assert_eq!(cache.get(&a, whole, now), None);
+
+        cache.shrink_to(3);
+        assert_eq!(cache.bytes, 2);
+        assert!(cache.get(&a, (4, None, true), now).is_some());
+        cache.insert(b.clone(), "config", whole, b"bbb", now);
+
+        cache.observe(&Event::BlobPut {
+            tag: "#7.1".into(),
+            blob: "b".into(),
+            offset: 0,
+            size: 1,
+        });
+        assert_eq!(cache.get(&b, whole, now), None);
+        cache.observe(&Event::TagDeleted {
+            tag: "config".into(),
+        });
+        assert_eq!((cache.bytes, cache.lru.len()), (0, 0));
+    }
+}
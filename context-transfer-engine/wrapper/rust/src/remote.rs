@@ -0,0 +1,93 @@
+//! Targets on other nodes of the deployment.
+//!
+//! `Client::register_remote_target` adds memory of another node's runtime to
+//! the CTE pool as a target of its own, so blobs placed there are written to
+//! and read from that node over the runtime's network. Registering one per
+//! node of a job's allocation pools the nodes' memory into a burst buffer
+//! every node can place on. The node is looked up in the runtime's hostfile,
+//! by the name or address it is listed under, or by one that resolves to the
+//! same address; its id is its line in the file. All nodes of a deployment
+//! listen on the port of its configuration, so `port` is only checked against
+//! that one. The target is listed as `<host>:<port>` and scored like any other.
+
+use std::collections::HashSet;
+use std::net::{IpAddr, ToSocketAddrs};
+
+use crate::{ffi, ffi_guard, Client, CteError};
+
+/// Addresses `host` resolves to; none if it doesn't.
+fn addresses(host: &str) -> HashSet<IpAddr> {
+    (host, 0)
+        .to_socket_addrs()
+        .map(|addrs| addrs.map(|a| a.ip()).collect())
+        .unwrap_or_default()
+}
+
+/// Offset of `host` in `hosts`: the entry spelled the same, else the first
+/// that resolves to an address `host` does.
+fn node_index(host: &str, hosts: &[String]) -> Option<usize> {
+    if let Some(i) = hosts.iter().position(|h| h == host) {
+        return Some(i);
+    }
+    let wanted = addresses(host);
+    if wanted.is_empty() {
+        return None;
+    }
+    hosts
+        .iter()
+        .position(|h| !addresses(h).is_disjoint(&wanted))
+}
+
+impl Client {
+    /// Register `capacity` bytes of memory on the runtime at `host`:`port` as
+    /// a target (see `remote`) and return its name. `port` 0 means the
+    /// deployment's port. Fails with `InvalidArgument` if `host` isn't in the
+    /// hostfile or `port` isn't the deployment's, and with
+    /// `CteError::Runtime` if the node refuses the target.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(host = host, port = port, size = capacity), err)
+    )]
+    pub fn register_remote_target(
+        host: &str,
+        port: u16,
+        capacity: u64,
+    ) -> Result<String, CteError> {
+        if capacity == 0 {
+            return Err(CteError::InvalidArgument(
+                "a remote target needs a capacity".into(),
+            ));
+        }
+        let node_port = ffi::client_node_port();
+        if port != 0 && u32::from(port) != node_port {
+            return Err(CteError::InvalidArgument(format!(
+                "the deployment's nodes listen on port {}, not {}",
+                node_port, port
+            )));
+        }
+        let hosts = ffi_guard::client_hostfile()?;
+        let node = node_index(host, &hosts).ok_or_else(|| {
+            CteError::InvalidArgument(format!("'{}' is not a node of the deployment", host))
+        })?;
+        let name = format!("{}:{}", host, node_port);
+        ffi_guard::client_register_remote_target(&name, node as u32, capacity)?;
+        Ok(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_index() {
+        let hosts: Vec<String> = ["10.0.0.1", "10.0.0.2", "127.0.0.1"]
+            .iter()
+            .map(|h| h.to_string())
+            .collect();
+        assert_eq!(node_index("10.0.0.2", &hosts), Some(1));
+        assert_eq!(node_index("localhost", &hosts), Some(2));
+        assert_eq!(node_index("10.0.0.9", &hosts), None);
+        assert_eq!(node_index("no-such-host.invalid", &hosts), None);
+    }
+}
@@ -0,0 +1,674 @@
+//! REST/JSON API over HTTP (feature `http`).
+//!
+//! `Client::serve_rest`, or `clio serve --rest`, answers plain HTTP requests
+//! for dashboards and `curl`, alongside the gRPC service of `grpc`:
+//!
+//! - `GET /v1/tags?regex=R&max=N`: the names of the tags matching `R` (all if
+//!   not given), as a JSON array.
+//! - `GET /v1/tags/<tag>?prefix=P&delimiter=D`: one level of the tag's blobs,
+//!   as `Tag::list` returns it. `DELETE` deletes the tag and its blobs.
+//! - `GET /v1/tags/<tag>/blobs/<blob>`: the blob's bytes, or the `Range:
+//!   bytes=...` of them asked for (one range; `206` with `Content-Range`).
+//!   `PUT` writes the body at `?offset=N` (0 if not given) with `?score=S`,
+//!   creating the tag and blob as needed; `DELETE` deletes the blob.
+//! - `GET /v1/tags/<tag>/stat/<blob>`: the blob's size, score, generation and
+//!   times, as a JSON object.
+//! - `GET /v1/query?tag=R&blob=R&max=N`: the tags matching `tag`, or with
+//!   `blob` the `{"tag","blob"}` pairs matching both.
+//! - `GET /v1/targets`: every target with its score, free space and traffic.
+//! - `GET /v1/events?tag=R&blob=R&kinds=K,...&poll_ms=N`: a WebSocket carrying
+//!   one JSON text message per event of `Client::subscribe` whose tag, and
+//!   blob for blob events, match the regexes, such as
+//!   `{"kind":"blob_put","tag":"t","blob":"b","offset":0,"size":8}`. `kinds`
+//!   keeps only the named kinds (`blob_put`, `blob_deleted`,
+//!   `blob_reorganized`, `blob_expiring`, `tag_created`, `tag_deleted`), and
+//!   `poll_ms` also reports other clients' changes, polled that often (see
+//!   `events`); without it only this process's are seen. The socket is pinged
+//!   when quiet.
+//!
+//! Path segments are percent-encoded, and `/` is allowed in the blob part.
+//! Wrapper sidecars are never listed and can't be named. Errors are
+//! `{"error": message}` with the status of `CteError`: 400, 404, 409 for a
+//! failed precondition, 413 for a read over `RequestLimits` or a body over
+//! `max_body_bytes` and 500 otherwise.
+//! Authorization, and the loopback rule without a token, are as for the
+//! notebook service (see `notebook`), except that a token may also come as
+//! `Authorization: Bearer ...`.
+//!
+//! The service is an axum router on a Tokio runtime of its own, with the
+//! client's blocking calls made on the runtime's blocking threads.
+
+use std::collections::HashMap;
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::body::Bytes;
+use axum::extract::rejection::WebSocketUpgradeRejection;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{DefaultBodyLimit, Path, Query, Request, State};
+use axum::http::{header, HeaderMap, HeaderValue, Method, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use regex::Regex;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::ffi_c::json_string;
+use crate::http::same_secret;
+use crate::{meta, Client, CteError, Event, EventFilter, EventKind, GetOptions, PutOptions, Tag};
+
+/// How long an event socket may stay quiet before it is pinged.
+const KEEPALIVE: Duration = Duration::from_secs(15);
+
+/// Events an event socket holds for a slow client before the subscription
+/// waits for it.
+const EVENT_QUEUE: usize = 1024;
+
+/// Settings for `Client::serve_rest`.
+#[derive(Debug, Clone)]
+pub struct RestOptions {
+    /// Secret every request must present; `None` to accept any local request.
+    pub token: Option<String>,
+    /// Refuse `PUT` and `DELETE`, for a dashboard.
+    pub read_only: bool,
+    /// Largest `PUT` body, in bytes.
+    pub max_body_bytes: usize,
+}
+
+impl Default for RestOptions {
+    fn default() -> Self {
+        Self {
+            token: None,
+            read_only: false,
+            max_body_bytes: 64 << 20,
+        }
+    }
+}
+
+type Params = HashMap<String, String>;
+
+fn json(body: String) -> Response {
+    ([(header::CONTENT_TYPE, "application/json")], body).into_response()
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    let body = format!("{{\"error\":{}}}", json_string(message));
+    (status, [(header::CONTENT_TYPE, "application/json")], body).into_response()
+}
+
+fn failed(e: &CteError) -> Response {
+    let status = match e {
+        CteError::NotFound { .. } => StatusCode::NOT_FOUND,
+        CteError::InvalidArgument(_) | CteError::InvalidName { .. } => StatusCode::BAD_REQUEST,
+        CteError::GenerationMismatch { .. } | CteError::Fenced { .. } => StatusCode::CONFLICT,
+        CteError::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    error(status, &e.to_string())
+}
+
+/// A header value of digits and punctuation.
+fn value(s: String) -> HeaderValue {
+    HeaderValue::try_from(s).expect("an ASCII header value")
+}
+
+/// Run `call` on a blocking thread, as the client's calls block; a panic in
+/// it is a 500.
+async fn blocking(call: impl FnOnce() -> Result<Response, CteError> + Send + 'static) -> Response {
+    match tokio::task::spawn_blocking(call).await {
+        Ok(Ok(response)) => response,
+        Ok(Err(e)) => failed(&e),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()),
+    }
+}
+
+fn json_array<'a>(items: impl IntoIterator<Item = &'a String>) -> String {
+    let items: Vec<String> = items.into_iter().map(|s| json_string(s)).collect();
+    format!("[{}]", items.join(","))
+}
+
+fn unix_ms(t: Option<SystemTime>) -> String {
+    t.and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or("null".into(), |d| d.as_millis().to_string())
+}
+
+/// The one range of `Range: bytes=...` within a blob of `size` bytes, as
+/// `(offset, length)`; `None` if it isn't satisfiable, and the whole blob for
+/// a header this doesn't parse (as a server may ignore it).
+fn byte_range(header: Option<&str>, size: u64) -> Option<(u64, u64)> {
+    let whole = Some((0, size));
+    let Some(spec) = header.and_then(|h| h.trim().strip_prefix("bytes=")) else {
+        return whole;
+    };
+    let Some((first, last)) = spec.split_once('-').filter(|_| !spec.contains(',')) else {
+        return whole;
+    };
+    let (first, last) = (first.trim(), last.trim());
+    if first.is_empty() {
+        // The last `last` bytes.
+        let Ok(n) = last.parse::<u64>() else {
+            return whole;
+        };
+        let n = n.min(size);
+        return (n > 0).then_some((size - n, n));
+    }
+    let Ok(first) = first.parse::<u64>() else {
+        return whole;
+    };
+    let last = match last {
+        "" => size.saturating_sub(1),
+        last => match last.parse::<u64>() {
+            Ok(last) if last >= first => last.min(size.saturating_sub(1)),
+            _ => return whole,
+        },
+    };
+    if first >= size {
+        return None;
+    }
+    Some((first, last - first + 1))
+}
+
+/// A number from query parameter `name`, `default` if it isn't given.
+fn number<T: FromStr>(params: &Params, name: &str, default: T) -> Result<T, CteError> {
+    match params.get(name) {
+        Some(v) => v
+            .parse()
+            .map_err(|_| CteError::InvalidArgument(format!("bad {} '{}'", name, v))),
+        None => Ok(default),
+    }
+}
+
+/// Refuses an empty or reserved name.
+fn checked(kind: &str, name: &str) -> Result<(), CteError> {
+    if name.is_empty() || meta::is_reserved(name) {
+        return Err(CteError::InvalidArgument(format!(
+            "bad {} '{}'",
+            kind, name
+        )));
+    }
+    Ok(())
+}
+
+/// The tag `name`, which must exist; its name has been `checked`.
+fn existing(name: &str) -> Result<Tag, CteError> {
+    if !Client::tag_exists(name) {
+        return Err(CteError::NotFound {
+            blob: name.to_string(),
+        });
+    }
+    Tag::try_new(name)
+}
+
+/// What an event socket sends: `filter`, narrowed by the regexes, which must
+/// match the whole name.
+struct Watch {
+    filter: EventFilter,
+    tag: Option<Regex>,
+    blob: Option<Regex>,
+}
+
+impl Watch {
+    fn parse(params: &Params) -> Result<Self, CteError> {
+        let regex = |name: &str| {
+            params
+                .get(name)
+                .map(|r| {
+                    Regex::new(&format!("^(?:{})$", r)).map_err(|e| {
+                        CteError::InvalidArgument(format!("bad {} regex '{}': {}", name, r, e))
+                    })
+                })
+                .transpose()
+        };
+        let mut filter = EventFilter::new();
+        if let Some(kinds) = params.get("kinds") {
+            let kinds = kinds
+                .split(',')
+                .map(|name| {
+                    EventKind::ALL
+                        .into_iter()
+                        .find(|kind| kind.name() == name.trim())
+                        .ok_or_else(|| {
+                            CteError::InvalidArgument(format!("unknown event kind '{}'", name))
+                        })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            filter = filter.kinds(&kinds);
+        }
+        let poll_ms = number(params, "poll_ms", 0u64)?;
+        if poll_ms > 0 {
+            filter = filter.poll_remote(Duration::from_millis(poll_ms));
+        }
+        Ok(Self {
+            filter,
+            tag: regex("tag")?,
+            blob: regex("blob")?,
+        })
+    }
+
+    fn matches(&self, event: &Event) -> bool {
+        self.tag.as_ref().is_none_or(|re| re.is_match(event.tag()))
+            && match (&self.blob, event.blob()) {
+                (Some(re), Some(blob)) => re.is_match(blob),
+                _ => true,
+            }
+    }
+
+    /// Pass the matching events of a subscription to `sender` until it is
+    /// closed. Blocks.
+    fn forward(self, sender: mpsc::Sender<String>) {
+        let events = Client::subscribe(self.filter.clone());
+        while !sender.is_closed() {
+            let Some(event) = events.recv_timeout(Duration::from_secs(1)) else {
+                continue;
+            };
+            if self.matches(&event) && sender.blocking_send(event.json()).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Send events to `socket` until the client goes away.
+    async fn run(self, mut socket: WebSocket) {
+        let (sender, mut events) = mpsc::channel(EVENT_QUEUE);
+        tokio::task::spawn_blocking(move || self.forward(sender));
+        loop {
+            let sent = tokio::select! {
+                event = events.recv() => match event {
+                    Some(text) => socket.send(Message::Text(text)).await,
+                    None => break,
+                },
+                incoming = socket.recv() => match incoming {
+                    Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                },
+                _ = tokio::time::sleep(KEEPALIVE) => socket.send(Message::Ping(Vec::new())).await,
+            };
+            if sent.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Rest {
+    options: Arc<RestOptions>,
+}
+
+impl Rest {
+    fn authorized(&self, request: &Request) -> bool {
+        let Some(token) = &self.options.token else {
+            return true;
+        };
+        let header = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| {
+                v.strip_prefix("token ")
+                    .or_else(|| v.strip_prefix("Bearer "))
+                    .or_else(|| v.strip_prefix("bearer "))
+                    .map(str::to_string)
+            });
+        header
+            .or_else(|| {
+                let Query(mut params) = Query::<Params>::try_from_uri(request.uri()).ok()?;
+                params.remove("token")
+            })
+            .is_some_and(|given| same_secret(given.trim(), token))
+    }
+}
+
+/// Authorization and the read-only rule, ahead of every route.
+async fn guard(State(rest): State<Rest>, request: Request, next: Next) -> Response {
+    if !rest.authorized(&request) {
+        return error(StatusCode::FORBIDDEN, "missing or wrong token");
+    }
+    if rest.options.read_only && matches!(*request.method(), Method::PUT | Method::DELETE) {
+        let mut response = error(StatusCode::METHOD_NOT_ALLOWED, "this service is read-only");
+        response
+            .headers_mut()
+            .insert(header::ALLOW, HeaderValue::from_static("GET, HEAD"));
+        return response;
+    }
+    next.run(request).await
+}
+
+async fn tags(Query(params): Query<Params>) -> Response {
+    blocking(move || {
+        let regex = params.get("regex").map_or(".*", String::as_str);
+        let tags = Client::tag_query(regex, number(&params, "max", 0)?);
+        Ok(json(json_array(&tags)))
+    })
+    .await
+}
+
+async fn query(Query(params): Query<Params>) -> Response {
+    blocking(move || {
+        let tag_re = params.get("tag").map_or(".*", String::as_str);
+        let max = number(&params, "max", 0)?;
+        let Some(blob_re) = params.get("blob") else {
+            return Ok(json(json_array(&Client::tag_query(tag_re, max))));
+        };
+        let pairs: Vec<String> = Client::blob_query(tag_re, blob_re, max)
+            .iter()
+            .map(|(tag, blob)| {
+                format!(
+                    "{{\"tag\":{},\"blob\":{}}}",
+                    json_string(tag),
+                    json_string(blob)
+                )
+            })
+            .collect();
+        Ok(json(format!("[{}]", pairs.join(","))))
+    })
+    .await
+}
+
+async fn targets() -> Response {
+    blocking(|| Ok(json(targets_json()))).await
+}
+
+async fn events(
+    Query(params): Query<Params>,
+    upgrade: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+) -> Response {
+    let upgrade = match upgrade {
+        Ok(upgrade) => upgrade,
+        Err(e) => {
+            let mut response = error(StatusCode::UPGRADE_REQUIRED, &e.body_text());
+            let websocket = HeaderValue::from_static("websocket");
+            response.headers_mut().insert(header::UPGRADE, websocket);
+            return response;
+        }
+    };
+    match Watch::parse(&params) {
+        Ok(watch) => upgrade.on_upgrade(move |socket| watch.run(socket)),
+        Err(e) => failed(&e),
+    }
+}
+
+async fn list_tag(Path(tag): Path<String>, Query(params): Query<Params>) -> Response {
+    blocking(move || {
+        checked("tag", &tag)?;
+        let tag = existing(&tag)?;
+        let prefix = params.get("prefix").map_or("", String::as_str);
+        let delimiter = params.get("delimiter").map_or("", String::as_str);
+        let listing = tag.list(prefix, delimiter)?;
+        Ok(json(format!(
+            "{{\"common_prefixes\":{},\"blobs\":{}}}",
+            json_array(&listing.common_prefixes),
+            json_array(&listing.blobs)
+        )))
+    })
+    .await
+}
+
+async fn delete_tag(Path(tag): Path<String>) -> Response {
+    blocking(move || {
+        checked("tag", &tag)?;
+        existing(&tag)?;
+        let deleted = Client::del_tag(&tag);
+        Ok(json(format!("{{\"deleted\":{}}}", deleted)))
+    })
+    .await
+}
+
+async fn get_blob(
+    method: Method,
+    Path((tag, blob)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Response {
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    blocking(move || {
+        checked("tag", &tag)?;
+        checked("blob", &blob)?;
+        let tag = existing(&tag)?;
+        let stat = tag
+            .stat_blob(&blob)?
+            .ok_or_else(|| CteError::NotFound { blob: blob.clone() })?;
+        let Some((offset, length)) = byte_range(range.as_deref(), stat.size) else {
+            let mut response = error(StatusCode::RANGE_NOT_SATISFIABLE, "range outside the blob");
+            let unsatisfied = value(format!("bytes */{}", stat.size));
+            response
+                .headers_mut()
+                .insert(header::CONTENT_RANGE, unsatisfied);
+            return Ok(response);
+        };
+        let data = if length == 0 || method == Method::HEAD {
+            Vec::new()
+        } else {
+            let options = GetOptions {
+                offset,
+                size: Some(length),
+                ..Default::default()
+            };
+            tag.get(&blob, &options)?
+        };
+        let partial = range.is_some() && length < stat.size;
+        let status = if partial {
+            StatusCode::PARTIAL_CONTENT
+        } else {
+            StatusCode::OK
+        };
+        let mut response = (status, data).into_response();
+        let headers = response.headers_mut();
+        let octets = HeaderValue::from_static("application/octet-stream");
+        headers.insert(header::CONTENT_TYPE, octets);
+        headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+        headers.insert(header::ETAG, value(format!("\"{}\"", stat.generation)));
+        if partial {
+            let end = offset + length - 1;
+            let content_range = value(format!("bytes {}-{}/{}", offset, end, stat.size));
+            headers.insert(header::CONTENT_RANGE, content_range);
+        }
+        Ok(response)
+    })
+    .await
+}
+
+async fn put_blob(
+    Path((tag, blob)): Path<(String, String)>,
+    Query(params): Query<Params>,
+    body: Bytes,
+) -> Response {
+    blocking(move || {
+        checked("tag", &tag)?;
+        checked("blob", &blob)?;
+        let score = match params.get("score") {
+            Some(_) => Some(number(&params, "score", 0.0f32)?),
+            None => None,
+        };
+        let options = PutOptions {
+            offset: number(&params, "offset", 0)?,
+            score,
+            ..Default::default()
+        };
+        Tag::try_new(&tag)?.put(&blob, &body, &options)?;
+        Ok(StatusCode::NO_CONTENT.into_response())
+    })
+    .await
+}
+
+async fn delete_blob(Path((tag, blob)): Path<(String, String)>) -> Response {
+    blocking(move || {
+        checked("tag", &tag)?;
+        checked("blob", &blob)?;
+        let tag = existing(&tag)?;
+        if !tag.del_blob(&blob) {
+            return Err(CteError::NotFound { blob });
+        }
+        Ok(StatusCode::NO_CONTENT.into_response())
+    })
+    .await
+}
+
+async fn stat_blob(Path((tag, blob)): Path<(String, String)>) -> Response {
+    blocking(move || {
+        checked("tag", &tag)?;
+        checked("blob", &blob)?;
+        let tag = existing(&tag)?;
+        let stat = tag.stat_blob(&blob)?.ok_or(CteError::NotFound { blob })?;
+        Ok(json(format!(
+            "{{\"size\":{},\"stored_size\":{},\"score\":{},\"generation\":{},\
+             \"written_ms\":{},\"expires_ms\":{}}}",
+            stat.size,
+            stat.stored_size,
+            stat.score,
+            stat.generation,
+            unix_ms(stat.written),
+            unix_ms(stat.expires)
+        )))
+    })
+    .await
+}
+
+async fn not_found(request: Request) -> Response {
+    failed(&CteError::NotFound {
+        blob: request.uri().path().to_string(),
+    })
+}
+
+fn targets_json() -> String {
+    let targets: Vec<String> = Client::list_targets()
+        .iter()
+        .map(|t| {
+            format!(
+                "{{\"target\":{},\"score\":{},\"remaining_bytes\":{},\"bytes_read\":{},\
+                 \"bytes_written\":{}}}",
+                json_string(&t.name),
+                t.score,
+                t.remaining_space,
+                t.bytes_read,
+                t.bytes_written
+            )
+        })
+        .collect();
+    format!("[{}]", targets.join(","))
+}
+
+/// The routes of `rest`.
+fn router(options: &RestOptions) -> Router {
+    let rest = Rest {
+        options: Arc::new(options.clone()),
+    };
+    Router::new()
+        .route("/v1/tags", get(tags))
+        .route("/v1/query", get(query))
+        .route("/v1/targets", get(targets))
+        .route("/v1/events", get(events))
+        .route("/v1/tags/:tag", get(list_tag).delete(delete_tag))
+        .route(
+            "/v1/tags/:tag/blobs/*blob",
+            get(get_blob).put(put_blob).delete(delete_blob),
+        )
+        .route("/v1/tags/:tag/stat/*blob", get(stat_blob))
+        .fallback(not_found)
+        .layer(middleware::from_fn_with_state(rest, guard))
+        .layer(DefaultBodyLimit::max(options.max_body_bytes))
+}
+
+/// A service from `Client::serve_rest`. Stops when dropped, letting requests
+/// in progress finish; event sockets are closed.
+pub struct RestServer {
+    addr: SocketAddr,
+    stop: Option<oneshot::Sender<()>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl RestServer {
+    /// The address the service listens on, with the port chosen if 0 was asked.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for RestServer {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Client {
+    /// Serve the REST API at `addr` (see `rest`) until the returned server is
+    /// dropped. Fails with `InvalidArgument` for an address that isn't
+    /// loopback unless `options.token` is set.
+    pub fn serve_rest(
+        addr: impl ToSocketAddrs,
+        options: &RestOptions,
+    ) -> Result<RestServer, CteError> {
+        let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+        if options.token.is_none() && addrs.iter().any(|a| !a.ip().is_loopback()) {
+            return Err(CteError::InvalidArgument(
+                "a REST service on a non-loopback address needs a token".into(),
+            ));
+        }
+        if options.token.as_deref() == Some("") {
+            return Err(CteError::InvalidArgument("empty REST token".into()));
+        }
+        let listener = TcpListener::bind(&addrs[..])?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+        let router = router(options);
+        let (stop, stopped) = oneshot::channel::<()>();
+        let worker = std::thread::spawn(move || {
+            runtime.block_on(async move {
+                let Ok(listener) = tokio::net::TcpListener::from_std(listener) else {
+                    return;
+                };
+                let _ = axum::serve(listener, router)
+                    .with_graceful_shutdown(async {
+                        let _ = stopped.await;
+                    })
+                    .await;
+            });
+            // Event sockets outlive the graceful shutdown, and each holds a
+            // blocking thread for up to a second after its socket goes.
+            runtime.shutdown_timeout(Duration::from_secs(2));
+        });
+        Ok(RestServer {
+            addr,
+            stop: Some(stop),
+            worker: Some(worker),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_range() {
+        assert_eq!(byte_range(None, 10), Some((0, 10)));
+        assert_eq!(byte_range(Some("bytes=2-4"), 10), Some((2, 3)));
+        assert_eq!(byte_range(Some("bytes=8-"), 10), Some((8, 2)));
+        assert_eq!(byte_range(Some("bytes=5-99"), 10), Some((5, 5)));
+        assert_eq!(byte_range(Some("bytes=-3"), 10), Some((7, 3)));
+        assert_eq!(byte_range(Some("bytes=-30"), 10), Some((0, 10)));
+        assert_eq!(byte_range(Some("bytes=10-"), 10), None);
+        assert_eq!(byte_range(Some("bytes=-0"), 10), None);
+        assert_eq!(byte_range(Some("bytes=0-1,4-5"), 10), Some((0, 10)));
+        assert_eq!(byte_range(Some("lines=1-2"), 10), Some((0, 10)));
+        assert_eq!(unix_ms(None), "null");
+        assert_eq!(
+            unix_ms(Some(UNIX_EPOCH + std::time::Duration::from_millis(5))),
+            "5"
+        );
+    }
+}
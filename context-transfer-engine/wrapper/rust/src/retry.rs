@@ -0,0 +1,288 @@
+//! Retries of transient failures with exponential backoff.
+//!
+//! A `RetryPolicy` reruns an operation that failed with one of its `retryable`
+//! error classes, sleeping `initial_backoff`, then `multiplier` times longer
+//! after each failure up to `max_backoff`. Each attempt runs under its own
+//! `attempt_timeout` deadline (see `timeout`), so a runtime that hangs while it
+//! restarts shows up as a `Timeout` and is retried once it answers again.
+//!
+//! A policy set with `set_retry_policy` (or `ClientOptions::retry` on init) is
+//! applied automatically to `Tag::get`, `Tag::stat_blob`, `Tag::stat_blobs` and
+//! unconditional `Tag::put`s; `Client::with_retry` applies one to any closure,
+//! and operations inside it aren't retried again on their own. Conditional puts
+//! are never retried automatically: an attempt that timed out may still have
+//! landed, and its retry would then fail the precondition it met.
+//!
+//! Retries stop early once an enclosing `Client::with_op_options` deadline has
+//! passed or would pass during the backoff.
+
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::RwLock;
+use std::time::Duration;
+
+use crate::timeout::op_timeout;
+use crate::{Client, CteError, OpOptions};
+
+/// Broad kinds of `CteError`, for choosing what to retry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorClass {
+    /// The runtime didn't answer in time (`Timeout`).
+    Timeout,
+    /// Another writer got there first (`GenerationMismatch`, `Fenced`,
+    /// `LeaseExpired`).
+    Conflict,
+    /// `NotFound`.
+    NotFound,
+    /// Stored data or metadata failed to check out (`ChecksumMismatch`,
+    /// `CorruptMetadata`), as a read racing a write can see.
+    Corrupt,
+    /// The request itself was refused (`InvalidArgument`, `InvalidName`,
+    /// `Unsupported`, `Encryption`, `TooLarge`, `SchemaMismatch`, `Device`);
+    /// retrying won't help.
+    Rejected,
+    /// A local filesystem operation failed (`Io`).
+    Io,
+    /// The caller stopped the operation (`Cancelled`).
+    Cancelled,
+    /// The shim or runtime threw, or a panic was caught (`Runtime`).
+    Runtime,
+}
+
+impl CteError {
+    /// The class of this error, as `RetryPolicy::retryable` lists them.
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            CteError::Timeout(_) => ErrorClass::Timeout,
+            CteError::Fenced { .. }
+            | CteError::GenerationMismatch { .. }
+            | CteError::LeaseExpired { .. } => ErrorClass::Conflict,
+            CteError::NotFound { .. } => ErrorClass::NotFound,
+            CteError::ChecksumMismatch { .. } | CteError::CorruptMetadata { .. } => {
+                ErrorClass::Corrupt
+            }
+            CteError::Io(_) => ErrorClass::Io,
+            CteError::Cancelled => ErrorClass::Cancelled,
+            CteError::Runtime { .. } => ErrorClass::Runtime,
+            CteError::Encryption(_)
+            | CteError::Unsupported(_)
+            | CteError::InvalidArgument(_)
+            | CteError::InvalidName { .. }
+            | CteError::TooLarge { .. }
+            | CteError::SchemaMismatch { .. }
+            | CteError::Device(_) => ErrorClass::Rejected,
+        }
+    }
+}
+
+/// When and how often to retry a failed operation.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Attempts in total, the first included; 1 disables retries.
+    pub max_attempts: u32,
+    /// Wait before the first retry.
+    pub initial_backoff: Duration,
+    /// Longest wait between attempts.
+    pub max_backoff: Duration,
+    /// Growth of the wait after each failed attempt.
+    pub multiplier: f64,
+    /// Wait a random time between half of and the whole backoff, so clients
+    /// that failed together don't retry together.
+    pub jitter: bool,
+    /// Deadline of each attempt; `None` waits on the runtime indefinitely.
+    pub attempt_timeout: Option<Duration>,
+    /// Error classes worth another attempt.
+    pub retryable: Vec<ErrorClass>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            multiplier: 2.0,
+            jitter: true,
+            attempt_timeout: Some(Duration::from_secs(30)),
+            retryable: vec![ErrorClass::Timeout],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The wait after failed attempt `attempt` (1-based), before jitter.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = self
+            .multiplier
+            .max(1.0)
+            .powi(attempt.saturating_sub(1) as i32);
+        let secs = self.initial_backoff.as_secs_f64() * factor;
+        Duration::try_from_secs_f64(secs).map_or(self.max_backoff, |d| d.min(self.max_backoff))
+    }
+
+    fn run<T>(&self, mut f: impl FnMut() -> Result<T, CteError>) -> Result<T, CteError> {
+        let _scope = RetryScope::enter();
+        let mut attempt = 1;
+        loop {
+            let result = match self.attempt_timeout {
+                Some(timeout) => {
+                    Client::with_op_options(&OpOptions { timeout }, &mut f).and_then(|r| r)
+                }
+                None => f(),
+            };
+            let e = match result {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+            if attempt >= self.max_attempts || !self.retryable.contains(&e.class()) {
+                return Err(e);
+            }
+            let mut wait = self.backoff(attempt);
+            if self.jitter {
+                wait = wait.mul_f64(0.5 + 0.5 * random_unit());
+            }
+            // Don't retry into, or sleep through, an enclosing deadline.
+            let left = op_timeout();
+            if left < 0.0 || (left > 0.0 && wait.as_secs_f32() >= left) {
+                return Err(e);
+            }
+            std::thread::sleep(wait);
+            attempt += 1;
+        }
+    }
+}
+
+/// A number in `[0, 1)`, different on every call.
+fn random_unit() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+static POLICY: RwLock<Option<RetryPolicy>> = RwLock::new(None);
+
+thread_local! {
+    /// Set while a policy is running an operation on this thread.
+    static RETRYING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Marks the thread as retrying until dropped.
+struct RetryScope {
+    outer: bool,
+}
+
+impl RetryScope {
+    fn enter() -> Self {
+        Self {
+            outer: RETRYING.replace(true),
+        }
+    }
+}
+
+impl Drop for RetryScope {
+    fn drop(&mut self) {
+        RETRYING.set(self.outer);
+    }
+}
+
+/// Set the process-wide retry policy, replacing any previous one.
+pub fn set_retry_policy(policy: RetryPolicy) {
+    *POLICY.write().unwrap_or_else(|e| e.into_inner()) = Some(policy);
+}
+
+/// Remove the process-wide retry policy; operations fail on the first error again.
+pub fn clear_retry_policy() {
+    *POLICY.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Run `f` under the process-wide policy, if one is set and no policy is
+/// already running it.
+pub(crate) fn retrying<T>(mut f: impl FnMut() -> Result<T, CteError>) -> Result<T, CteError> {
+    if RETRYING.get() {
+        return f();
+    }
+    let policy = POLICY.read().unwrap_or_else(|e| e.into_inner()).clone();
+    match policy {
+        Some(policy) => policy.run(f),
+        None => f(),
+    }
+}
+
+impl Client {
+    /// Run `f`, retrying it under `policy` (see `retry`). Operations inside `f`
+    /// aren't retried again by the process-wide policy.
+    pub fn with_retry<T>(
+        policy: &RetryPolicy,
+        f: impl FnMut() -> Result<T, CteError>,
+    ) -> Result<T, CteError> {
+        policy.run(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicy {
+            max_attempts: 4,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(3),
+            jitter: false,
+            attempt_timeout: None,
+            ..Default::default()
+        };
+        let waits: Vec<_> = (1..=4).map(|n| policy.backoff(n)).collect();
+        assert_eq!(
+            waits,
+            [1, 2, 3, 3].map(Duration::from_millis),
+            "doubles up to max_backoff"
+        );
+
+        let timeout = || CteError::Timeout(Duration::from_secs(1));
+        let mut calls = 0;
+        let result = Client::with_retry(&policy, || {
+            calls += 1;
+            // Nested operations run once; the outer policy does the retrying.
+            assert!(RETRYING.get());
+            if calls < 3 {
+                Err(timeout())
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+        assert!(!RETRYING.get());
+
+        let mut calls = 0;
+        let result: Result<(), _> = Client::with_retry(&policy, || {
+            calls += 1;
+            Err(timeout())
+        });
+        assert!(matches!(result, Err(CteError::Timeout(_))));
+        assert_eq!(calls, 4);
+
+        let mut calls = 0;
+        let result: Result<(), _> = Client::with_retry(&policy, || {
+            calls += 1;
+            Err(CteError::NotFound { blob: "b".into() })
+        });
+        assert_eq!(result.unwrap_err().class(), ErrorClass::NotFound);
+        assert_eq!(calls, 1, "not retryable");
+
+        // Each attempt gets its own deadline.
+        let policy = RetryPolicy {
+            attempt_timeout: Some(Duration::from_secs(10)),
+            ..policy
+        };
+        Client::with_retry(&policy, || {
+            assert!(op_timeout() > 9.0);
+            Ok(())
+        })
+        .unwrap();
+        assert!((0..100)
+            .map(|_| random_unit())
+            .all(|u| (0.0..1.0).contains(&u)));
+    }
+}
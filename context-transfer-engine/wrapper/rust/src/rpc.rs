@@ -0,0 +1,216 @@
+//! What the tonic services (`grpc`, `flight`) share: running one on a Tokio
+//! runtime of its own, its token check and the status of a `CteError`.
+
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::thread::JoinHandle;
+
+use tokio::sync::oneshot;
+use tonic::service::Routes;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Request, Response, Status};
+
+use crate::{meta, Client, CteError, Tag};
+
+pub(crate) fn status(e: &CteError) -> Status {
+    let message = e.to_string();
+    match e {
+        CteError::NotFound { .. } => Status::not_found(message),
+        CteError::InvalidArgument(_) | CteError::InvalidName { .. } => {
+            Status::invalid_argument(message)
+        }
+        CteError::TooLarge { .. } => Status::resource_exhausted(message),
+        CteError::Unsupported(_) => Status::unimplemented(message),
+        CteError::Timeout(_) => Status::deadline_exceeded(message),
+        CteError::Cancelled => Status::cancelled(message),
+        CteError::Fenced { .. }
+        | CteError::GenerationMismatch { .. }
+        | CteError::LeaseExpired { .. } => Status::aborted(message),
+        CteError::ChecksumMismatch { .. } | CteError::CorruptMetadata { .. } => {
+            Status::data_loss(message)
+        }
+        _ => Status::internal(message),
+    }
+}
+
+/// Run `call` on a blocking thread, as the client's calls block; a panic in
+/// it is an `INTERNAL` error.
+pub(crate) async fn blocking<T: Send + 'static>(
+    call: impl FnOnce() -> Result<T, CteError> + Send + 'static,
+) -> Result<Response<T>, Status> {
+    match tokio::task::spawn_blocking(call).await {
+        Ok(Ok(response)) => Ok(Response::new(response)),
+        Ok(Err(e)) => Err(status(&e)),
+        Err(e) => Err(Status::internal(e.to_string())),
+    }
+}
+
+/// Refuses an empty or reserved name.
+pub(crate) fn checked(kind: &str, name: &str) -> Result<(), CteError> {
+    if name.is_empty() || meta::is_reserved(name) {
+        return Err(CteError::InvalidArgument(format!(
+            "bad {} name '{}'",
+            kind, name
+        )));
+    }
+    Ok(())
+}
+
+/// The tag `name`, which must exist.
+pub(crate) fn existing(name: &str) -> Result<Tag, CteError> {
+    checked("tag", name)?;
+    if !Client::tag_exists(name) {
+        return Err(CteError::NotFound {
+            blob: name.to_string(),
+        });
+    }
+    Tag::try_new(name)
+}
+
+/// Equal without stopping at the first difference.
+fn same_secret(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+/// An interceptor letting through the calls that carry `token` as
+/// `authorization: bearer <token>`, or every call if it is `None`.
+pub(crate) fn authorize(
+    token: Option<String>,
+) -> impl FnMut(Request<()>) -> Result<Request<()>, Status> + Clone + Send + Sync + 'static {
+    move |request: Request<()>| {
+        let Some(token) = &token else {
+            return Ok(request);
+        };
+        let given = request
+            .metadata()
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("bearer ").or(v.strip_prefix("Bearer ")));
+        match given {
+            Some(given) if same_secret(given.trim(), token) => Ok(request),
+            _ => Err(Status::unauthenticated("missing or wrong token")),
+        }
+    }
+}
+
+/// `addr`, refused if it isn't loopback and there is no `token`. `what`
+/// names the service in the error.
+pub(crate) fn listen_addrs(
+    addr: impl ToSocketAddrs,
+    token: Option<&str>,
+    what: &str,
+) -> Result<Vec<SocketAddr>, CteError> {
+    let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+    if token.is_none() && addrs.iter().any(|a| !a.ip().is_loopback()) {
+        return Err(CteError::InvalidArgument(format!(
+            "a {} service on a non-loopback address needs a token",
+            what
+        )));
+    }
+    if token == Some("") {
+        return Err(CteError::InvalidArgument(format!("empty {} token", what)));
+    }
+    Ok(addrs)
+}
+
+/// A running service, from `serve`. Stops when dropped, letting calls in
+/// progress finish.
+pub(crate) struct Server {
+    addr: SocketAddr,
+    stop: Option<oneshot::Sender<()>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Server {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Answer `routes` at `addrs` on a thread and runtime of their own.
+pub(crate) fn serve(addrs: &[SocketAddr], routes: Routes) -> Result<Server, CteError> {
+    let listener = TcpListener::bind(addrs)?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    let (stop, stopped) = oneshot::channel();
+    let worker = std::thread::spawn(move || {
+        runtime.block_on(async move {
+            let Ok(listener) = tokio::net::TcpListener::from_std(listener) else {
+                return;
+            };
+            let Ok(incoming) = TcpIncoming::from_listener(listener, true, None) else {
+                return;
+            };
+            let _ = tonic::transport::Server::builder()
+                .add_routes(routes)
+                .serve_with_incoming_shutdown(incoming, async {
+                    let _ = stopped.await;
+                })
+                .await;
+        })
+    });
+    Ok(Server {
+        addr,
+        stop: Some(stop),
+        worker: Some(worker),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_codes() {
+        let code = |e: CteError| status(&e).code();
+        assert_eq!(
+            code(CteError::NotFound { blob: "b".into() }),
+            tonic::Code::NotFound
+        );
+        assert_eq!(
+            code(CteError::InvalidArgument("x".into())),
+            tonic::Code::InvalidArgument
+        );
+        assert_eq!(
+            code(CteError::TooLarge {
+                blob: "b".into(),
+                size: 2,
+                limit: Some(1)
+            }),
+            tonic::Code::ResourceExhausted
+        );
+        assert_eq!(code(CteError::Cancelled), tonic::Code::Cancelled);
+        assert_eq!(
+            code(CteError::Unsupported("x".into())),
+            tonic::Code::Unimplemented
+        );
+        assert!(same_secret("abc", "abc") && !same_secret("abc", "abd"));
+        assert!(listen_addrs("0.0.0.0:0", None, "gRPC").is_err());
+        assert!(listen_addrs("127.0.0.1:0", Some(""), "gRPC").is_err());
+        assert!(listen_addrs("0.0.0.0:0", Some("t"), "gRPC").is_ok());
+    }
+
+    #[test]
+    fn test_checked_names() {
+        assert!(checked("blob", "").is_err());
+        assert!(checked("blob", "run/1").is_ok());
+        assert!(checked("tag", &format!("{}x", meta::RESERVED_PREFIX)).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+//! Object-store targets.
+//!
+//! `Client::register_s3_target` adds an S3 bucket, or a prefix of one, as the
+//! coldest tier: the runtime opens it as an object-store block device
+//! (`chimaera::bdev::BdevType::kObject`, in runtimes built with
+//! `-DWRP_CORE_ENABLE_S3=ON`) that stores each page of the target as one
+//! object under the prefix. Its device model is an object store's, so it
+//! scores below the node's disks and is only filled once they are. The
+//! target is listed as `s3://<bucket>/<prefix>`. Credentials are taken as
+//! given or from the usual `AWS_*` environment variables, passed to the
+//! runtime with the registration, and never printed.
+
+use std::fmt;
+
+use crate::{ffi_guard, Client, CteError};
+
+/// Credentials and endpoint for an object store.
+#[derive(Clone, PartialEq, Eq)]
+pub struct S3Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// For temporary credentials.
+    pub session_token: Option<String>,
+    pub region: String,
+    /// Endpoint of an S3-compatible store; `None` for AWS.
+    pub endpoint: Option<String>,
+}
+
+impl fmt::Debug for S3Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("S3Credentials")
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &"<redacted>")
+            .field(
+                "session_token",
+                &self.session_token.as_ref().map(|_| "<redacted>"),
+            )
+            .field("region", &self.region)
+            .field("endpoint", &self.endpoint)
+            .finish()
+    }
+}
+
+impl S3Credentials {
+    /// From `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`, `AWS_SESSION_TOKEN`,
+    /// `AWS_REGION` (or `AWS_DEFAULT_REGION`, else `us-east-1`) and
+    /// `AWS_ENDPOINT_URL`; `InvalidArgument` if the key pair is missing.
+    pub fn from_env() -> Result<Self, CteError> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let missing = |name: &str| CteError::InvalidArgument(format!("{} is not set", name));
+        Ok(Self {
+            access_key_id: var("AWS_ACCESS_KEY_ID").ok_or_else(|| missing("AWS_ACCESS_KEY_ID"))?,
+            secret_access_key: var("AWS_SECRET_ACCESS_KEY")
+                .ok_or_else(|| missing("AWS_SECRET_ACCESS_KEY"))?,
+            session_token: var("AWS_SESSION_TOKEN"),
+            region: var("AWS_REGION")
+                .or_else(|| var("AWS_DEFAULT_REGION"))
+                .unwrap_or_else(|| "us-east-1".to_string()),
+            endpoint: var("AWS_ENDPOINT_URL"),
+        })
+    }
+}
+
+/// `InvalidArgument` unless `bucket` follows S3's bucket naming rules.
+fn check_bucket(bucket: &str) -> Result<(), CteError> {
+    let bytes = bucket.as_bytes();
+    let edge = |b: Option<&u8>| b.is_some_and(|b| b.is_ascii_lowercase() || b.is_ascii_digit());
+    let ok = (3..=63).contains(&bytes.len())
+        && bytes
+            .iter()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || *b == b'.' || *b == b'-')
+        && edge(bytes.first())
+        && edge(bytes.last())
+        && !bucket.contains("..");
+    if ok {
+        Ok(())
+    } else {
+        Err(CteError::InvalidArgument(format!(
+            "'{}' is not a valid bucket name",
+            bucket
+        )))
+    }
+}
+
+impl Client {
+    /// Register `prefix` of `bucket` as a storage target of about
+    /// `capacity_hint` bytes (see `s3`) and return its name. The runtime
+    /// checks the bucket and credentials with a request before the target is
+    /// added. Fails with `InvalidArgument` for a bad bucket name, a missing
+    /// key pair or no capacity, with `Unsupported` if the runtime was built
+    /// without the object-store device, and with `CteError::Runtime` if the
+    /// store is unreachable or refuses the credentials.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(bucket = bucket, prefix = prefix, size = capacity_hint), err)
+    )]
+    pub fn register_s3_target(
+        bucket: &str,
+        prefix: &str,
+        credentials: &S3Credentials,
+        capacity_hint: u64,
+    ) -> Result<String, CteError> {
+        check_bucket(bucket)?;
+        if credentials.access_key_id.is_empty() || credentials.secret_access_key.is_empty() {
+            return Err(CteError::InvalidArgument(
+                "S3 credentials need an access key pair".into(),
+            ));
+        }
+        if capacity_hint == 0 {
+            return Err(CteError::InvalidArgument(
+                "an object-store target needs a capacity hint".into(),
+            ));
+        }
+        let prefix = prefix.trim_matches('/');
+        let name = format!("s3://{}/{}", bucket, prefix);
+        if !ffi_guard::client_register_s3_target(&name, credentials, bucket, prefix, capacity_hint)?
+        {
+            return Err(CteError::Unsupported(format!(
+                "object-store target {}: the runtime was built without WRP_CORE_ENABLE_S3",
+                name
+            )));
+        }
+        Ok(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_s3_target_arguments() {
+        for ok in ["cold-tier", "run.42.archive", "abc"] {
+            assert!(check_bucket(ok).is_ok(), "{}", ok);
+        }
+        for bad in ["ab", "Cold", "-cold", "cold-", "a..b", "under_score"] {
+            assert!(check_bucket(bad).is_err(), "{}", bad);
+        }
+        let credentials = S3Credentials {
+            access_key_id: "AKIDEXAMPLE".into(),
+            secret_access_key: "wJalrXUtnFEMI".into(),
+            session_token: Some("token".into()),
+            region: "us-east-1".into(),
+            endpoint: None,
+        };
+        let shown = format!("{:?}", credentials);
+        assert!(shown.contains("AKIDEXAMPLE"));
+        assert!(!shown.contains("wJalrXUtnFEMI") && !shown.contains("\"token\""));
+        // Bad arguments are turned away before the runtime is asked.
+        let no_secret = S3Credentials {
+            secret_access_key: String::new(),
+            ..credentials.clone()
+        };
+        for refused in [
+            Client::register_s3_target("Cold", "cte", &credentials, 1 << 40),
+            Client::register_s3_target("cold-tier", "cte", &no_secret, 1 << 40),
+            Client::register_s3_target("cold-tier", "cte", &credentials, 0),
+        ] {
+            assert!(matches!(refused, Err(CteError::InvalidArgument(_))));
+        }
+    }
+}
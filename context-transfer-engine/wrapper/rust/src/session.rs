@@ -0,0 +1,196 @@
+//! Ephemeral tags bound to the client session.
+//!
+//! `Tag::ephemeral` creates an unnamed tag for intermediate data, such as shuffle
+//! output passed between pipeline stages. It belongs to this process's session and
+//! is deleted, blobs and all, by `Client::finalize`. If the process dies first,
+//! the tag is reaped by the next `init`, `Client::gc` or `Client::reap_sessions`
+//! on any client, once the session's heartbeat has gone stale.
+//!
+//! While a session owns ephemeral tags it refreshes a heartbeat record in the
+//! reserved `.cte/sessions` tag every `HEARTBEAT_INTERVAL`. Ephemeral tags are
+//! named `.cte/tmp/<session>/<n>`, so they are hidden from `Client::tag_query` and
+//! produce no events, and deleting them bypasses the trash.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::meta::{read_u64, tag_exists};
+use crate::oplog::writer_id;
+use crate::ttl::now_ms;
+use crate::{events, ffi, ffi_guard, BulkOptions, BulkReport, Client, Tag};
+
+const SESSIONS_TAG: &str = ".cte/sessions";
+const EPHEMERAL_PREFIX: &str = ".cte/tmp/";
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+/// A session whose heartbeat is older than this is presumed dead.
+const SESSION_TIMEOUT_MS: u64 = 30_000;
+
+static NEXT_EPHEMERAL: AtomicU64 = AtomicU64::new(0);
+static HEARTBEAT: Mutex<Option<(Sender<()>, JoinHandle<()>)>> = Mutex::new(None);
+
+fn session_name(writer: u64) -> String {
+    format!("{:016x}", writer)
+}
+
+/// Session id owning the ephemeral tag `name`, if it is one.
+fn owner(name: &str) -> Option<u64> {
+    let (session, _) = name.strip_prefix(EPHEMERAL_PREFIX)?.split_once('/')?;
+    u64::from_str_radix(session, 16).ok()
+}
+
+fn ephemeral_tags(writer: Option<u64>) -> Vec<String> {
+    let prefix = match writer {
+        Some(w) => format!("{}{}/", EPHEMERAL_PREFIX, session_name(w)),
+        None => EPHEMERAL_PREFIX.to_string(),
+    };
+    ffi::client_tag_query(&format!("{}.*", events::exact(&prefix)), 0)
+        .iter()
+        .map(|s| s.to_string_lossy().into_owned())
+        .collect()
+}
+
+fn beat() {
+    let now = now_ms().to_le_bytes();
+    // A missed heartbeat is made up for by the next one.
+    let Ok(tag) = Tag::try_new(SESSIONS_TAG) else {
+        return;
+    };
+    let _ = ffi_guard::tag_put_blob(&tag.inner, &session_name(writer_id()), &now, 0, 1.0);
+}
+
+/// Last heartbeat of every session that has published one.
+fn heartbeats() -> Vec<(u64, u64)> {
+    if !tag_exists(SESSIONS_TAG) {
+        return Vec::new();
+    }
+    let tag = Tag::new(SESSIONS_TAG);
+    let mut out = Vec::new();
+    for name in tag.raw_blob_names() {
+        let Ok(writer) = u64::from_str_radix(&name, 16) else {
+            continue;
+        };
+        if tag.get_blob_size(&name) != 8 {
+            continue;
+        }
+        let Ok(buf) = ffi_guard::tag_get_blob(&tag.inner, &name, 8, 0) else {
+            continue;
+        };
+        if let Ok(ms) = read_u64(buf.as_slice()) {
+            out.push((writer, ms));
+        }
+    }
+    out
+}
+
+fn start_heartbeat() {
+    let mut heartbeat = HEARTBEAT.lock().unwrap_or_else(|e| e.into_inner());
+    if heartbeat.is_some() {
+        return;
+    }
+    beat();
+    let (stop, stopped) = mpsc::channel::<()>();
+    let worker = std::thread::spawn(move || loop {
+        match stopped.recv_timeout(HEARTBEAT_INTERVAL) {
+            Err(RecvTimeoutError::Timeout) => beat(),
+            _ => return,
+        }
+    });
+    *heartbeat = Some((stop, worker));
+}
+
+/// Delete an ephemeral tag outright: no trash, and no events since it is reserved.
+fn drop_tag(name: &str, options: &BulkOptions, report: &mut BulkReport) {
+    let tag = Tag::new(name);
+    let blobs = tag.raw_blob_names();
+    if !options.dry_run && !ffi::client_del_tag(name) {
+        return;
+    }
+    for blob in blobs {
+        let size = tag.get_blob_size(&blob);
+        report.push(name, &blob, size);
+    }
+}
+
+impl Tag {
+    /// Create an unnamed tag owned by this client session. It is deleted by
+    /// `Client::finalize`, or reaped after a crash (see `session`).
+    pub fn ephemeral() -> Tag {
+        start_heartbeat();
+        let n = NEXT_EPHEMERAL.fetch_add(1, Ordering::Relaxed);
+        Tag::new(&format!(
+            "{}{}/{}",
+            EPHEMERAL_PREFIX,
+            session_name(writer_id()),
+            n
+        ))
+    }
+
+    /// True if this tag was created by `Tag::ephemeral`, in any session.
+    pub fn is_ephemeral(&self) -> bool {
+        owner(&self.name).is_some()
+    }
+}
+
+impl Client {
+    /// End this process's session: delete its ephemeral tags and stop its
+    /// heartbeat. Later `Tag::ephemeral` calls start a new one.
+    pub fn finalize() -> BulkReport {
+        let options = BulkOptions::default();
+        let mut report = BulkReport::new(&options);
+        if let Some((stop, worker)) = HEARTBEAT.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            drop(stop);
+            let _ = worker.join();
+        }
+        for name in ephemeral_tags(Some(writer_id())) {
+            drop_tag(&name, &options, &mut report);
+        }
+        let sessions = Tag::new(SESSIONS_TAG);
+        ffi::tag_del_blob(&sessions.inner, &session_name(writer_id()));
+        report
+    }
+
+    /// Delete the ephemeral tags of sessions whose heartbeat has gone stale, i.e.
+    /// processes that exited without `Client::finalize`.
+    pub fn reap_sessions(options: &BulkOptions) -> BulkReport {
+        let mut report = BulkReport::new(options);
+        let tags = ephemeral_tags(None);
+        if tags.is_empty() {
+            return report;
+        }
+        let now = now_ms();
+        let beats = heartbeats();
+        let alive = |writer: u64| {
+            writer == writer_id()
+                || beats
+                    .iter()
+                    .any(|&(w, ms)| w == writer && now.saturating_sub(ms) < SESSION_TIMEOUT_MS)
+        };
+        for name in tags {
+            if owner(&name).is_some_and(|w| !alive(w)) {
+                drop_tag(&name, options, &mut report);
+            }
+        }
+        if !options.dry_run {
+            let sessions = Tag::new(SESSIONS_TAG);
+            for &(writer, _) in beats.iter().filter(|&&(w, _)| !alive(w)) {
+                ffi::tag_del_blob(&sessions.inner, &session_name(writer));
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_owner() {
+        assert_eq!(owner(".cte/tmp/00000000000000ff/3"), Some(0xff));
+        assert_eq!(owner(".cte/tmp/00000000000000ff"), None);
+        assert_eq!(owner(".cte/trash/x/1"), None);
+    }
+}
@@ -0,0 +1,82 @@
+//! Protecting freshly written blobs from demotion.
+//!
+//! A checkpoint is written once and read back soon after to verify it, but a
+//! score recalculation in between (a `PlacementPolicy` applied with
+//! `Tag::apply_placement_policy`, `Client::start_auto_tier`, any
+//! `Tag::reorganize_blob`) can see a blob with no reads yet and move it to disk
+//! just before that read. With `set_settle_time`, a blob written less than the
+//! settle time ago can't be demoted: `Tag::reorganize_blob` and the auto-tier
+//! task leave it where it is when asked to lower its score, and may move it
+//! once it has settled. Raising a score, and `Tag::pin_blob`, work as always.
+//!
+//! Write times are kept in the blob's metadata sidecar, so a blob written by
+//! one client is protected from demotion by any other, according to the settle
+//! time of the client that would demote it. Placement the runtime does on its
+//! own, such as evicting a full tier, isn't affected.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::meta::BlobMeta;
+use crate::ttl::now_ms;
+use crate::Tag;
+
+/// Settle time in milliseconds; 0 turns protection off.
+static SETTLE_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Protect blobs from demotion for `time` after each write (see `settle`);
+/// `Duration::ZERO`, the default, turns protection off.
+pub fn set_settle_time(time: Duration) {
+    SETTLE_MS.store(time.as_millis() as u64, Ordering::Relaxed);
+}
+
+/// The current settle time.
+pub fn settle_time() -> Duration {
+    Duration::from_millis(SETTLE_MS.load(Ordering::Relaxed))
+}
+
+impl BlobMeta {
+    /// True if the blob was written within the settle time.
+    pub(crate) fn is_settling(&self) -> bool {
+        self.settling_at(now_ms(), SETTLE_MS.load(Ordering::Relaxed))
+    }
+
+    fn settling_at(&self, now_ms: u64, settle_ms: u64) -> bool {
+        settle_ms != 0 && self.written_ms != 0 && now_ms < self.written_ms.saturating_add(settle_ms)
+    }
+
+    /// When the blob was last written, if that was recorded.
+    pub(crate) fn written_at(&self) -> Option<SystemTime> {
+        (self.written_ms != 0).then(|| UNIX_EPOCH + Duration::from_millis(self.written_ms))
+    }
+}
+
+impl Tag {
+    /// True if moving `name`, with metadata `meta`, to `score` would demote it
+    /// before it has settled.
+    pub(crate) fn demotes_settling(&self, name: &str, meta: &BlobMeta, score: f32) -> bool {
+        meta.is_settling() && score < self.get_blob_score(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_settling() {
+        let mut meta = BlobMeta::default();
+        assert!(!meta.settling_at(1_000, 500));
+        assert_eq!(meta.written_at(), None);
+        meta.written_ms = 1_000;
+        assert!(meta.settling_at(1_000, 500));
+        assert!(meta.settling_at(1_499, 500));
+        assert!(!meta.settling_at(1_500, 500));
+        assert!(!meta.settling_at(1_000, 0));
+        assert_eq!(
+            meta.written_at(),
+            Some(UNIX_EPOCH + Duration::from_millis(1_000))
+        );
+        assert_eq!(settle_time(), Duration::ZERO);
+    }
+}
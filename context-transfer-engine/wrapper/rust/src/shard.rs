@@ -0,0 +1,486 @@
+//! Hot-shard detection and online splitting.
+//!
+//! Each blob operation is routed to one of the runtime's metadata containers (a
+//! "shard") by a hash of the tag and blob name, or by the registered
+//! `PartitionStrategy`. A few very busy names, or a range strategy over
+//! time-prefixed names, can load one container far more than the rest. While
+//! `Client::monitor_shards` is on, the routing hook counts operations per tag and
+//! shard, and `Client::hot_shards` reports the shards that stand out.
+//!
+//! `Tag::split_shard` spreads one shard of a tag over several containers while
+//! the tag stays in use. Names in the shard are reassigned by a salted hash and
+//! their blobs (sidecars included) are moved one at a time; operations in this
+//! process follow each blob as soon as it has moved. The finished split is
+//! recorded in the tag's reserved `.cte/splits` blob and applied by every
+//! process that opens the tag afterwards. A process that already had the tag
+//! open keeps its old routes, so split while other processes are idle, or
+//! restart them after. `Client::start_auto_split` splits hot shards as they are
+//! detected.
+
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Mutex, RwLock};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::checksum::xxh64;
+use crate::io::COPY_CHUNK;
+use crate::meta::{meta_lock, read_u64, FieldReader, FieldWriter};
+use crate::partition::resolve_route;
+use crate::{ffi, ffi_guard, txn, Client, CteError, CteTagId, Tag};
+
+const SPLITS_NAME: &str = ".cte/splits";
+
+const FIELD_SPLIT: u8 = 1;
+
+const FIELD_SHARD: u8 = 1;
+const FIELD_WAYS: u8 = 2;
+const FIELD_CONTAINERS: u8 = 3;
+
+type TagKey = (u32, u32);
+
+fn tag_key(id: CteTagId) -> TagKey {
+    (id.major, id.minor)
+}
+
+/// One shard of a tag repartitioned over `ways` containers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Split {
+    shard: u32,
+    ways: u32,
+    /// Container count the split was made for.
+    containers: u32,
+    /// Names moved so far while the split is in progress; `None` once done.
+    moving: Option<HashSet<String>>,
+}
+
+impl Split {
+    /// Container of `name` under this split, given its route `hash` before it.
+    fn route(&self, name: &str, hash: u32) -> Option<u32> {
+        if hash % self.containers != self.shard
+            || self.moving.as_ref().is_some_and(|m| !m.contains(name))
+        {
+            return None;
+        }
+        Some(self.target(name))
+    }
+
+    /// Container `name` is moved to: the shard itself or one of `ways - 1`
+    /// others spread evenly over the ring.
+    fn target(&self, name: &str) -> u32 {
+        let stride = (self.containers / self.ways).max(1);
+        let way = xxh64(self.shard as u64, name.as_bytes()) % self.ways as u64;
+        (self.shard + way as u32 * stride) % self.containers
+    }
+}
+
+fn encode_splits(splits: &[Split]) -> Vec<u8> {
+    let mut w = FieldWriter::default();
+    for s in splits.iter().filter(|s| s.moving.is_none()) {
+        let mut split = FieldWriter::default();
+        split.u64(FIELD_SHARD, s.shard as u64);
+        split.u64(FIELD_WAYS, s.ways as u64);
+        split.u64(FIELD_CONTAINERS, s.containers as u64);
+        w.bytes(FIELD_SPLIT, &split.finish());
+    }
+    w.finish()
+}
+
+fn decode_splits(buf: &[u8]) -> Result<Vec<Split>, String> {
+    let mut splits = Vec::new();
+    for (id, value) in FieldReader::new(buf)? {
+        if id != FIELD_SPLIT {
+            continue;
+        }
+        let mut split = Split {
+            shard: 0,
+            ways: 1,
+            containers: 1,
+            moving: None,
+        };
+        for (id, value) in FieldReader::new(value)? {
+            let n = read_u64(value)? as u32;
+            match id {
+                FIELD_SHARD => split.shard = n,
+                FIELD_WAYS => split.ways = n.max(1),
+                FIELD_CONTAINERS => split.containers = n.max(1),
+                _ => {}
+            }
+        }
+        splits.push(split);
+    }
+    Ok(splits)
+}
+
+/// Splits of every tag opened in this process, in the order they were made.
+static SPLITS: RwLock<BTreeMap<TagKey, Vec<Split>>> = RwLock::new(BTreeMap::new());
+
+thread_local! {
+    /// Route forced for this thread's blob operations while a blob is moved.
+    static ROUTE_OVERRIDE: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+pub(crate) fn route_override() -> Option<u32> {
+    ROUTE_OVERRIDE.with(Cell::get)
+}
+
+fn with_route<T>(hash: u32, f: impl FnOnce() -> T) -> T {
+    ROUTE_OVERRIDE.with(|r| r.set(Some(hash)));
+    let out = f();
+    ROUTE_OVERRIDE.with(|r| r.set(None));
+    out
+}
+
+/// Route of `name` after the splits of its tag, given its route `hash` before.
+pub(crate) fn apply_splits(tag_id: CteTagId, name: &str, hash: u32) -> u32 {
+    if name == SPLITS_NAME {
+        return hash;
+    }
+    let splits = SPLITS.read().unwrap_or_else(|e| e.into_inner());
+    let Some(tag) = splits.get(&tag_key(tag_id)) else {
+        return hash;
+    };
+    tag.iter()
+        .fold(hash, |h, split| split.route(name, h).unwrap_or(h))
+}
+
+/// Load the recorded splits of `tag`, once per process.
+pub(crate) fn load_splits(tag: &Tag) {
+    let key = tag_key(tag.get_tag_id());
+    if SPLITS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .contains_key(&key)
+    {
+        return;
+    }
+    let size = ffi::tag_get_blob_size(&tag.inner, SPLITS_NAME);
+    // A corrupt record routes like an unsplit tag rather than failing every open.
+    let loaded = if size == 0 {
+        Vec::new()
+    } else {
+        ffi_guard::tag_get_blob(&tag.inner, SPLITS_NAME, size, 0)
+            .ok()
+            .and_then(|buf| decode_splits(buf.as_slice()).ok())
+            .unwrap_or_default()
+    };
+    SPLITS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(key)
+        .or_insert(loaded);
+}
+
+/// Operation counts per tag and shard, while monitoring.
+struct Monitor {
+    containers: u32,
+    counts: HashMap<(TagKey, u32), u64>,
+}
+
+static MONITORING: AtomicBool = AtomicBool::new(false);
+static MONITOR: Mutex<Option<Monitor>> = Mutex::new(None);
+
+/// Count an operation routed by `hash` against its tag's shard.
+pub(crate) fn record(tag_id: CteTagId, hash: u32) {
+    if !MONITORING.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut monitor = MONITOR.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(m) = monitor.as_mut() {
+        let shard = hash % m.containers;
+        *m.counts.entry((tag_key(tag_id), shard)).or_default() += 1;
+    }
+}
+
+fn container_count() -> Result<u32, CteError> {
+    match ffi::client_container_count() {
+        0 => Err(CteError::Unsupported(
+            "the runtime doesn't report its metadata container count".into(),
+        )),
+        n => Ok(n),
+    }
+}
+
+/// Counted operations on one shard of a tag.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShardLoad {
+    pub tag_id: CteTagId,
+    pub shard: u32,
+    pub ops: u64,
+    /// Load relative to an even spread of the tag's operations over all
+    /// containers: 1.0 is even, `containers` means every operation hit this shard.
+    pub ratio: f64,
+}
+
+/// When `Client::start_auto_split` splits a shard.
+#[derive(Debug, Clone)]
+pub struct AutoSplitOptions {
+    /// How often the counts are checked.
+    pub interval: Duration,
+    /// Minimum `ShardLoad::ratio` of a shard to split.
+    pub min_ratio: f64,
+    /// Operations a tag needs before any of its shards is split.
+    pub min_ops: u64,
+    /// Containers a hot shard is spread over.
+    pub ways: u32,
+}
+
+impl Default for AutoSplitOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            min_ratio: 2.0,
+            min_ops: 10_000,
+            ways: 4,
+        }
+    }
+}
+
+/// Background hot-shard splitter started by `Client::start_auto_split`; stops
+/// when dropped.
+pub struct AutoSplitTask {
+    stop: Option<Sender<()>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Drop for AutoSplitTask {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Client {
+    /// Start counting blob operations per tag and shard, discarding any earlier
+    /// counts.
+    pub fn monitor_shards() -> Result<(), CteError> {
+        let containers = container_count()?;
+        *MONITOR.lock().unwrap_or_else(|e| e.into_inner()) = Some(Monitor {
+            containers,
+            counts: HashMap::new(),
+        });
+        MONITORING.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Stop counting and discard the counts.
+    pub fn stop_shard_monitor() {
+        MONITORING.store(false, Ordering::Relaxed);
+        *MONITOR.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    /// Counted load of every tag and shard seen since monitoring started,
+    /// busiest first.
+    pub fn shard_loads() -> Vec<ShardLoad> {
+        let monitor = MONITOR.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(m) = monitor.as_ref() else {
+            return Vec::new();
+        };
+        let mut per_tag: HashMap<TagKey, u64> = HashMap::new();
+        for ((tag, _), ops) in &m.counts {
+            *per_tag.entry(*tag).or_default() += ops;
+        }
+        let mut loads: Vec<ShardLoad> = m
+            .counts
+            .iter()
+            .map(|(&((major, minor), shard), &ops)| ShardLoad {
+                tag_id: CteTagId { major, minor },
+                shard,
+                ops,
+                ratio: ops as f64 * m.containers as f64 / per_tag[&(major, minor)] as f64,
+            })
+            .collect();
+        loads.sort_by_key(|l| std::cmp::Reverse(l.ops));
+        loads
+    }
+
+    /// Shards with a load ratio of at least `min_ratio`, in tags with at least
+    /// `min_ops` counted operations.
+    pub fn hot_shards(min_ratio: f64, min_ops: u64) -> Vec<ShardLoad> {
+        let loads = Self::shard_loads();
+        let mut per_tag: HashMap<TagKey, u64> = HashMap::new();
+        for l in &loads {
+            *per_tag.entry(tag_key(l.tag_id)).or_default() += l.ops;
+        }
+        loads
+            .into_iter()
+            .filter(|l| l.ratio >= min_ratio && per_tag[&tag_key(l.tag_id)] >= min_ops)
+            .collect()
+    }
+
+    /// Monitor shards and split those that `options` considers hot, on a
+    /// background thread until the returned task is dropped.
+    pub fn start_auto_split(options: AutoSplitOptions) -> Result<AutoSplitTask, CteError> {
+        Self::monitor_shards()?;
+        let (stop, stopped) = mpsc::channel::<()>();
+        let worker = std::thread::spawn(move || loop {
+            match stopped.recv_timeout(options.interval) {
+                Err(RecvTimeoutError::Timeout) => {
+                    for hot in Self::hot_shards(options.min_ratio, options.min_ops) {
+                        let tag = Tag::from_id(hot.tag_id);
+                        if tag.split_shard(hot.shard, options.ways).is_ok() {
+                            forget_counts(hot.tag_id);
+                        }
+                    }
+                }
+                _ => return,
+            }
+        });
+        Ok(AutoSplitTask {
+            stop: Some(stop),
+            worker: Some(worker),
+        })
+    }
+}
+
+/// Drop the counts of a tag whose routes just changed.
+fn forget_counts(tag_id: CteTagId) {
+    let mut monitor = MONITOR.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(m) = monitor.as_mut() {
+        m.counts.retain(|(tag, _), _| *tag != tag_key(tag_id));
+    }
+}
+
+impl Tag {
+    /// Spread shard `shard` of this tag over `ways` containers, moving its blobs
+    /// while the tag stays in use. Returns the number of blobs moved.
+    pub fn split_shard(&self, shard: u32, ways: u32) -> Result<usize, CteError> {
+        let containers = container_count()?;
+        if ways < 2 || shard >= containers {
+            return Err(CteError::InvalidArgument(format!(
+                "can't split shard {} of {} containers {} ways",
+                shard, containers, ways
+            )));
+        }
+        let key = tag_key(self.get_tag_id());
+        {
+            let mut splits = SPLITS.write().unwrap_or_else(|e| e.into_inner());
+            let tag = splits.entry(key).or_default();
+            if tag.iter().any(|s| s.moving.is_some()) {
+                return Err(CteError::InvalidArgument(format!(
+                    "a split of tag '{}' is already in progress",
+                    self.name()
+                )));
+            }
+            tag.push(Split {
+                shard,
+                ways,
+                containers,
+                moving: Some(HashSet::new()),
+            });
+        }
+        let mut moved = 0;
+        loop {
+            let pending = self.unsplit_names(key);
+            if pending.is_empty() {
+                // Blobs created since the last pass are still on the old route.
+                let _commit = txn::write_guard();
+                let _guard = meta_lock();
+                if !self.unsplit_names(key).is_empty() {
+                    continue;
+                }
+                let recorded = {
+                    let mut splits = SPLITS.write().unwrap_or_else(|e| e.into_inner());
+                    let tag = splits.get_mut(&key).expect("split registered above");
+                    tag.last_mut().expect("split registered above").moving = None;
+                    encode_splits(tag)
+                };
+                ffi_guard::tag_put_blob(&self.inner, SPLITS_NAME, &recorded, 0, 1.0)?;
+                return Ok(moved);
+            }
+            for (name, from) in pending {
+                let _commit = txn::write_guard();
+                let _guard = meta_lock();
+                moved += self.move_to_split(key, &name, from)? as usize;
+            }
+        }
+    }
+
+    /// Names in the shard being split that haven't moved yet, with their current
+    /// routes.
+    fn unsplit_names(&self, key: TagKey) -> Vec<(String, u32)> {
+        let id = self.get_tag_id();
+        let names = self.raw_blob_names();
+        // Copied out: routing below takes the lock again.
+        let split = {
+            let splits = SPLITS.read().unwrap_or_else(|e| e.into_inner());
+            splits[&key].last().expect("split in progress").clone()
+        };
+        let moving = split.moving.as_ref().expect("split in progress");
+        names
+            .into_iter()
+            .filter(|n| n != SPLITS_NAME && !moving.contains(n))
+            .filter_map(|n| {
+                let (from, _) = resolve_route(id, &n, ffi::tag_blob_hash(&self.inner, &n));
+                (from % split.containers == split.shard).then_some((n, from))
+            })
+            .collect()
+    }
+
+    /// Move `name` from route `from` to its container under the split in
+    /// progress. Caller holds the commit and meta locks. Returns whether data
+    /// was moved.
+    fn move_to_split(&self, key: TagKey, name: &str, from: u32) -> Result<bool, CteError> {
+        let to = {
+            let splits = SPLITS.read().unwrap_or_else(|e| e.into_inner());
+            splits[&key].last().expect("split in progress").target(name)
+        };
+        let size = with_route(from, || ffi::tag_get_blob_size(&self.inner, name));
+        let copy = to != from && size > 0;
+        if copy {
+            let score = with_route(from, || ffi::tag_get_blob_score(&self.inner, name));
+            let mut offset = 0;
+            while offset < size {
+                let len = COPY_CHUNK.min(size - offset);
+                let chunk = with_route(from, || {
+                    ffi_guard::tag_get_blob(&self.inner, name, len, offset)
+                })?;
+                with_route(to, || {
+                    ffi_guard::tag_put_blob(&self.inner, name, chunk.as_slice(), offset, score)
+                })?;
+                offset += len;
+            }
+        }
+        {
+            let mut splits = SPLITS.write().unwrap_or_else(|e| e.into_inner());
+            let split = splits.get_mut(&key).and_then(|t| t.last_mut());
+            if let Some(moving) = split.and_then(|s| s.moving.as_mut()) {
+                moving.insert(name.to_string());
+            }
+        }
+        if copy {
+            with_route(from, || ffi::tag_del_blob(&self.inner, name));
+        }
+        Ok(copy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_routes() {
+        let mut split = Split {
+            shard: 3,
+            ways: 4,
+            containers: 16,
+            moving: Some(HashSet::new()),
+        };
+        // Only moved names follow an in-progress split.
+        assert_eq!(split.route("a", 19), None);
+        split.moving = None;
+        assert_eq!(split.route("a", 18), None);
+        let targets: HashSet<u32> = (0..200)
+            .filter_map(|i| split.route(&format!("blob-{}", i), 19))
+            .collect();
+        assert_eq!(targets, HashSet::from([3, 7, 11, 15]));
+
+        let recorded = decode_splits(&encode_splits(&[split.clone()])).unwrap();
+        assert_eq!(recorded, [split]);
+    }
+}
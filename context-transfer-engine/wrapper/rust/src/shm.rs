@@ -0,0 +1,161 @@
+//! Shared-memory publish of small blobs to processes on the same node.
+//!
+//! `Tag::publish_shm` copies a blob's current contents into a file under
+//! `/dev/shm` (a memory-backed filesystem, so nothing touches disk), and any local
+//! process can map it read-only with `Tag::open_shm`, skipping the runtime
+//! entirely. It is meant for control and metadata records exchanged between
+//! co-scheduled processes, so blobs are limited to `SHM_MAX_SIZE`.
+//!
+//! A published region is a snapshot: later writes to the blob are not reflected
+//! until it is published again. Republishing replaces the file atomically, so
+//! existing mappings keep seeing the old snapshot and new ones see the new one.
+//! Regions outlive the process and are removed by `Tag::unpublish_shm`.
+//! Encrypted tags can't be published, since the region holds plaintext.
+
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::ops::Deref;
+use std::path::PathBuf;
+
+use memmap2::Mmap;
+
+use crate::meta::read_u64;
+use crate::{CteError, GetOptions, Tag};
+
+/// Largest blob `Tag::publish_shm` accepts.
+pub const SHM_MAX_SIZE: u64 = 1024 * 1024;
+
+const SHM_DIR: &str = "/dev/shm";
+/// Generation and data length, both `u64` little-endian.
+const HEADER_LEN: usize = 16;
+
+fn hex(s: &str) -> String {
+    s.bytes().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Region file for `blob` of `tag`. Names are hex-encoded so any tag or blob
+/// name maps to a distinct, valid file name.
+fn region_path(tag: &str, blob: &str) -> Result<PathBuf, CteError> {
+    let file = format!("cte.{}.{}", hex(tag), hex(blob));
+    if file.len() > 250 {
+        return Err(CteError::InvalidArgument(format!(
+            "tag and blob names of '{}' are too long for a shared-memory region",
+            blob
+        )));
+    }
+    Ok(PathBuf::from(SHM_DIR).join(file))
+}
+
+/// A read-only mapping of a published blob.
+pub struct ShmBlob {
+    map: Mmap,
+    generation: u64,
+    len: usize,
+}
+
+impl ShmBlob {
+    /// Generation of the blob when it was published.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+impl Deref for ShmBlob {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.map[HEADER_LEN..HEADER_LEN + self.len]
+    }
+}
+
+impl Tag {
+    /// Publish the current contents of `name` to a shared-memory region (see
+    /// `shm`).
+    pub fn publish_shm(&self, name: &str) -> Result<(), CteError> {
+        if self.is_encrypted()? {
+            return Err(CteError::Unsupported(format!(
+                "'{}' is in an encrypted tag; it can't be published in plaintext",
+                name
+            )));
+        }
+        let stat = self.stat_blob(name)?.ok_or_else(|| CteError::NotFound {
+            blob: name.to_string(),
+        })?;
+        if stat.size > SHM_MAX_SIZE {
+            return Err(CteError::InvalidArgument(format!(
+                "'{}' is {} bytes; shared-memory publish is limited to {}",
+                name, stat.size, SHM_MAX_SIZE
+            )));
+        }
+        let options = GetOptions {
+            if_generation_match: Some(stat.generation),
+            ..Default::default()
+        };
+        let data = self.get(name, &options)?;
+        let path = region_path(self.name(), name)?;
+        let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+        let mut file = File::create(&tmp)?;
+        file.write_all(&stat.generation.to_le_bytes())?;
+        file.write_all(&(data.len() as u64).to_le_bytes())?;
+        file.write_all(&data)?;
+        drop(file);
+        fs::rename(&tmp, &path).inspect_err(|_| {
+            let _ = fs::remove_file(&tmp);
+        })?;
+        Ok(())
+    }
+
+    /// Map the published region of `name` read-only.
+    pub fn open_shm(&self, name: &str) -> Result<ShmBlob, CteError> {
+        let file = match File::open(region_path(self.name(), name)?) {
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Err(CteError::NotFound {
+                    blob: name.to_string(),
+                })
+            }
+            other => other?,
+        };
+        // Safety: regions are only ever replaced by rename, never written in
+        // place, so the mapped file doesn't change underneath us.
+        let map = unsafe { Mmap::map(&file)? };
+        let corrupt = |reason: &str| CteError::CorruptMetadata {
+            blob: name.to_string(),
+            reason: format!("shared-memory region: {}", reason),
+        };
+        if map.len() < HEADER_LEN {
+            return Err(corrupt("truncated header"));
+        }
+        let generation = read_u64(&map[..8]).map_err(|e| corrupt(&e))?;
+        let len = read_u64(&map[8..HEADER_LEN]).map_err(|e| corrupt(&e))? as usize;
+        if map.len() - HEADER_LEN < len {
+            return Err(corrupt("truncated data"));
+        }
+        Ok(ShmBlob {
+            map,
+            generation,
+            len,
+        })
+    }
+
+    /// Remove the published region of `name`. Existing mappings stay valid.
+    /// Returns false if nothing was published.
+    pub fn unpublish_shm(&self, name: &str) -> Result<bool, CteError> {
+        match fs::remove_file(region_path(self.name(), name)?) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_region_path() {
+        let path = region_path("jobs", "ctl/0").unwrap();
+        assert_eq!(path, PathBuf::from("/dev/shm/cte.6a6f6273.63746c2f30"));
+        assert!(region_path("t", &"x".repeat(200)).is_err());
+    }
+}
@@ -0,0 +1,460 @@
+//! Capacity planning by simulation.
+//!
+//! `simulate` replays a `Workload` against a modeled set of targets, with no
+//! runtime and no I/O, and projects how full each target gets and how long
+//! operations wait for one. A workload is either recorded, from a tag's access
+//! log (`Workload::from_entries` of `AccessReport::entries`), or synthetic
+//! (`Workload::synthetic`). By changing the capacities, bandwidths and
+//! options, a site can see how large a burst buffer has to be before buying
+//! one.
+//!
+//! The model is deliberately simple. A write goes to the fastest target whose
+//! score isn't above the blob's, or the slowest target if all are; if that one
+//! is full it spills to the next slower target with room (with
+//! `SimOptions::spill`) or fails. A rewrite frees the old copy first. Each
+//! target moves one operation at a time at its bandwidth, so operations queue
+//! behind each other, and an operation's stall is the time it waits for its
+//! target. Blobs read before the workload wrote them are taken to be on the
+//! slowest target already. Latency, metadata and the network are not modeled.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::{AccessEntry, AccessKind};
+
+/// A modeled storage target.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimTarget {
+    pub name: String,
+    pub capacity: u64,
+    /// Bytes per second.
+    pub read_bandwidth: f64,
+    /// Bytes per second.
+    pub write_bandwidth: f64,
+    /// Placement score, 0.0 coldest to 1.0 hottest.
+    pub score: f32,
+}
+
+/// One operation of a workload.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimOp {
+    /// When the operation is issued, in milliseconds from any fixed start.
+    pub time_ms: u64,
+    pub kind: AccessKind,
+    pub blob: String,
+    /// Bytes read or written; ignored for deletes.
+    pub size: u64,
+    /// Placement score of a write; `None` for `SimOptions::write_score`.
+    pub score: Option<f32>,
+}
+
+/// Operations to replay, in the order they are issued.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Workload {
+    pub ops: Vec<SimOp>,
+}
+
+/// A synthetic workload for `Workload::synthetic`.
+#[derive(Debug, Clone)]
+pub struct SyntheticMix {
+    pub ops: usize,
+    /// Distinct blobs the operations pick from, uniformly.
+    pub blobs: usize,
+    pub blob_size: u64,
+    /// Share of operations that are reads; the rest are writes.
+    pub read_fraction: f64,
+    /// Time between operations.
+    pub interval: Duration,
+    /// The same seed gives the same workload.
+    pub seed: u64,
+}
+
+impl Default for SyntheticMix {
+    fn default() -> Self {
+        Self {
+            ops: 10_000,
+            blobs: 1000,
+            blob_size: 1 << 20,
+            read_fraction: 0.5,
+            interval: Duration::from_millis(1),
+            seed: 1,
+        }
+    }
+}
+
+/// SplitMix64, enough for a reproducible mix.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl Workload {
+    /// The accesses of an access log (see `Client::access_report`), in order.
+    pub fn from_entries(entries: &[AccessEntry]) -> Self {
+        let start = entries.iter().map(|e| e.time_ms).min().unwrap_or(0);
+        Self {
+            ops: entries
+                .iter()
+                .map(|e| SimOp {
+                    time_ms: e.time_ms - start,
+                    kind: e.kind,
+                    blob: e.blob.clone(),
+                    size: e.size,
+                    score: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// A random mix of reads and writes of whole blobs.
+    pub fn synthetic(mix: &SyntheticMix) -> Self {
+        let mut rng = Rng(mix.seed);
+        let step = mix.interval.as_millis() as u64;
+        let ops = (0..mix.ops)
+            .map(|i| {
+                let blob = format!("blob{}", rng.next() % mix.blobs.max(1) as u64);
+                let kind = if rng.unit() < mix.read_fraction {
+                    AccessKind::Read
+                } else {
+                    AccessKind::Write
+                };
+                SimOp {
+                    time_ms: i as u64 * step,
+                    kind,
+                    blob,
+                    size: mix.blob_size,
+                    score: None,
+                }
+            })
+            .collect();
+        Self { ops }
+    }
+}
+
+/// How `simulate` places data.
+#[derive(Debug, Clone)]
+pub struct SimOptions {
+    /// Score of writes that don't give one.
+    pub write_score: f32,
+    /// Spill writes to slower targets when theirs is full, as a
+    /// `SpillPolicy` does; without it they fail.
+    pub spill: bool,
+    /// Move a blob read from a target slower than the fastest to the fastest
+    /// one with room, as `Client::warmup` would.
+    pub promote_on_read: bool,
+}
+
+impl Default for SimOptions {
+    fn default() -> Self {
+        Self {
+            write_score: 1.0,
+            spill: true,
+            promote_on_read: false,
+        }
+    }
+}
+
+/// What `simulate` projects for one target.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TargetProjection {
+    pub name: String,
+    pub capacity: u64,
+    /// Most bytes held at any time.
+    pub peak_used: u64,
+    /// Bytes held at the end.
+    pub final_used: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    /// Time spent moving data.
+    pub busy: Duration,
+    /// Time operations waited for the target.
+    pub stall: Duration,
+}
+
+/// The outcome of `simulate`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SimReport {
+    /// In the order the targets were given.
+    pub targets: Vec<TargetProjection>,
+    pub reads: u64,
+    pub writes: u64,
+    pub deletes: u64,
+    /// Writes that spilled below the target they were placed on.
+    pub spilled_writes: u64,
+    /// Writes no target had room for.
+    pub failed_writes: u64,
+    pub promotions: u64,
+    /// Stall summed over all operations.
+    pub total_stall: Duration,
+    /// Longest any one operation stalled.
+    pub max_stall: Duration,
+    /// From the first operation's issue to the end of the last transfer.
+    pub makespan: Duration,
+}
+
+struct State<'a> {
+    targets: &'a [SimTarget],
+    /// Target indices, fastest first.
+    by_speed: Vec<usize>,
+    used: Vec<u64>,
+    /// When each target is next free, in seconds.
+    free_at: Vec<f64>,
+    /// Where each blob is, and its size.
+    blobs: HashMap<String, (usize, u64)>,
+    report: SimReport,
+    end: f64,
+}
+
+impl State<'_> {
+    /// Move `size` bytes on `target` for an operation issued at `now`.
+    fn transfer(&mut self, target: usize, now: f64, size: u64, write: bool) {
+        let t = &self.targets[target];
+        let bandwidth = if write {
+            t.write_bandwidth
+        } else {
+            t.read_bandwidth
+        };
+        let start = self.free_at[target].max(now);
+        let busy = if bandwidth > 0.0 {
+            size as f64 / bandwidth
+        } else {
+            0.0
+        };
+        self.free_at[target] = start + busy;
+        self.end = self.end.max(start + busy);
+        let stall = Duration::from_secs_f64(start - now);
+        let p = &mut self.report.targets[target];
+        p.busy += Duration::from_secs_f64(busy);
+        p.stall += stall;
+        if write {
+            p.bytes_written += size;
+        } else {
+            p.bytes_read += size;
+        }
+        self.report.total_stall += stall;
+        self.report.max_stall = self.report.max_stall.max(stall);
+    }
+
+    fn hold(&mut self, target: usize, size: u64) {
+        self.used[target] += size;
+        let p = &mut self.report.targets[target];
+        p.peak_used = p.peak_used.max(self.used[target]);
+    }
+
+    fn release(&mut self, blob: &str) {
+        if let Some((target, size)) = self.blobs.remove(blob) {
+            self.used[target] -= size;
+        }
+    }
+
+    fn room(&self, target: usize, size: u64) -> bool {
+        self.targets[target].capacity - self.used[target] >= size
+    }
+
+    /// Where a write at `score` goes: its place in `by_speed`, then the first
+    /// with room from there, if any.
+    fn place(&self, score: f32, size: u64, spill: bool) -> (usize, Option<usize>) {
+        let first = self
+            .by_speed
+            .iter()
+            .position(|&i| self.targets[i].score <= score)
+            .unwrap_or(self.by_speed.len() - 1);
+        let candidates = if spill {
+            &self.by_speed[first..]
+        } else {
+            &self.by_speed[first..=first]
+        };
+        let chosen = candidates.iter().position(|&i| self.room(i, size));
+        (first, chosen.map(|c| first + c))
+    }
+}
+
+/// Replay `workload` against `targets` (see `simulate`). With no targets
+/// nothing can be placed, so every write fails.
+pub fn simulate(workload: &Workload, targets: &[SimTarget], options: &SimOptions) -> SimReport {
+    let mut by_speed: Vec<usize> = (0..targets.len()).collect();
+    by_speed.sort_by(|&a, &b| targets[b].score.total_cmp(&targets[a].score));
+    let mut state = State {
+        targets,
+        by_speed,
+        used: vec![0; targets.len()],
+        free_at: vec![0.0; targets.len()],
+        blobs: HashMap::new(),
+        report: SimReport {
+            targets: targets
+                .iter()
+                .map(|t| TargetProjection {
+                    name: t.name.clone(),
+                    capacity: t.capacity,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        },
+        end: 0.0,
+    };
+    let start = workload
+        .ops
+        .first()
+        .map_or(0.0, |op| op.time_ms as f64 / 1000.0);
+    for op in &workload.ops {
+        let now = op.time_ms as f64 / 1000.0;
+        state.end = state.end.max(now);
+        match op.kind {
+            AccessKind::Write => {
+                state.report.writes += 1;
+                if targets.is_empty() {
+                    state.report.failed_writes += 1;
+                    continue;
+                }
+                state.release(&op.blob);
+                let score = op.score.unwrap_or(options.write_score);
+                match state.place(score, op.size, options.spill) {
+                    (first, Some(chosen)) => {
+                        let target = state.by_speed[chosen];
+                        if chosen != first {
+                            state.report.spilled_writes += 1;
+                        }
+                        state.hold(target, op.size);
+                        state.blobs.insert(op.blob.clone(), (target, op.size));
+                        state.transfer(target, now, op.size, true);
+                    }
+                    (_, None) => state.report.failed_writes += 1,
+                }
+            }
+            AccessKind::Read => {
+                state.report.reads += 1;
+                let Some(&slowest) = state.by_speed.last() else {
+                    continue;
+                };
+                let (target, size) = match state.blobs.get(&op.blob) {
+                    Some(&found) => found,
+                    None => {
+                        // Written before the workload began.
+                        let size = op.size.min(targets[slowest].capacity - state.used[slowest]);
+                        state.hold(slowest, size);
+                        state.blobs.insert(op.blob.clone(), (slowest, size));
+                        (slowest, size)
+                    }
+                };
+                state.transfer(target, now, op.size, false);
+                let fastest = state.by_speed[0];
+                if options.promote_on_read && target != fastest {
+                    let faster = state
+                        .by_speed
+                        .iter()
+                        .copied()
+                        .take_while(|&i| i != target)
+                        .find(|&i| state.room(i, size));
+                    if let Some(faster) = faster {
+                        state.release(&op.blob);
+                        state.hold(faster, size);
+                        state.blobs.insert(op.blob.clone(), (faster, size));
+                        state.transfer(faster, now, size, true);
+                        state.report.promotions += 1;
+                    }
+                }
+            }
+            AccessKind::Delete => {
+                state.report.deletes += 1;
+                state.release(&op.blob);
+            }
+        }
+    }
+    for (p, used) in state.report.targets.iter_mut().zip(&state.used) {
+        p.final_used = *used;
+    }
+    state.report.makespan = Duration::from_secs_f64((state.end - start).max(0.0));
+    state.report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn op(time_ms: u64, kind: AccessKind, blob: &str, size: u64) -> SimOp {
+        SimOp {
+            time_ms,
+            kind,
+            blob: blob.into(),
+            size,
+            score: None,
+        }
+    }
+
+    #[test]
+    fn test_simulate() {
+        let targets = [
+            SimTarget {
+                name: "hdd".into(),
+                capacity: 1000,
+                read_bandwidth: 100.0,
+                write_bandwidth: 100.0,
+                score: 0.1,
+            },
+            SimTarget {
+                name: "nvme".into(),
+                capacity: 100,
+                read_bandwidth: 1000.0,
+                write_bandwidth: 1000.0,
+                score: 1.0,
+            },
+        ];
+        let workload = Workload {
+            ops: vec![
+                op(0, AccessKind::Write, "a", 100),
+                // The NVMe is full, so this spills to the disk.
+                op(0, AccessKind::Write, "b", 100),
+                // Waits 50 ms for the write of "a" to finish.
+                op(50, AccessKind::Read, "a", 100),
+                op(2000, AccessKind::Delete, "a", 0),
+                op(2000, AccessKind::Read, "old", 50),
+            ],
+        };
+        let report = simulate(&workload, &targets, &SimOptions::default());
+        assert_eq!((report.writes, report.reads, report.deletes), (2, 2, 1));
+        assert_eq!((report.spilled_writes, report.failed_writes), (1, 0));
+        let (hdd, nvme) = (&report.targets[0], &report.targets[1]);
+        assert_eq!((nvme.peak_used, nvme.final_used), (100, 0));
+        assert_eq!((hdd.peak_used, hdd.final_used), (150, 150));
+        assert_eq!(nvme.stall, Duration::from_millis(50));
+        assert_eq!(report.max_stall, Duration::from_millis(50));
+        assert_eq!(report.makespan, Duration::from_millis(2500));
+
+        let strict = SimOptions {
+            spill: false,
+            ..Default::default()
+        };
+        assert_eq!(simulate(&workload, &targets, &strict).failed_writes, 1);
+        let promoting = SimOptions {
+            promote_on_read: true,
+            ..Default::default()
+        };
+        let report = simulate(&workload, &targets, &promoting);
+        // "old" moves up once "a" is gone.
+        assert_eq!(report.promotions, 1);
+        assert_eq!(report.targets[1].final_used, 50);
+
+        let mix = SyntheticMix {
+            ops: 100,
+            blobs: 10,
+            ..Default::default()
+        };
+        let synthetic = Workload::synthetic(&mix);
+        assert_eq!(synthetic, Workload::synthetic(&mix));
+        assert_eq!(synthetic.ops.len(), 100);
+        assert!(simulate(&synthetic, &[], &SimOptions::default()).failed_writes > 0);
+    }
+}
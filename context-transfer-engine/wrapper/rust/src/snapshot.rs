@@ -0,0 +1,229 @@
+//! Point-in-time snapshots of a tag.
+//!
+//! The runtime has no copy-on-write of its own, so snapshots are built on blob
+//! versioning (see `versions`): `Tag::snapshot` turns versioning on and records
+//! a manifest of the tag's blobs, with their sizes and generations, in the
+//! reserved `.cte/snapshots/<label>` blob. No data is copied up front; a blob's
+//! snapshotted contents move into its version history the first time it is
+//! overwritten or deleted afterwards. `Client::restore_snapshot` puts every blob
+//! back to the version recorded for it and deletes blobs created since.
+//!
+//! A snapshot stays restorable only while the versions it needs are kept, so
+//! `Tag::prune_blob_versions` can break it; so can writes made below the
+//! wrapper, which leave no version behind. Restore checks before changing
+//! anything and reports a missing version as `NotFound`.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::meta::{meta_lock, read_str, read_u64, FieldReader, FieldWriter};
+use crate::ttl::now_ms;
+use crate::{ffi, ffi_guard, txn, Client, CteError, Tag};
+
+const SNAPSHOT_PREFIX: &str = ".cte/snapshots/";
+
+const FIELD_CREATED: u8 = 1;
+const FIELD_BLOB: u8 = 2;
+
+const FIELD_BLOB_NAME: u8 = 1;
+const FIELD_BLOB_GENERATION: u8 = 2;
+const FIELD_BLOB_SIZE: u8 = 3;
+
+/// A snapshot of a tag, as listed by `Tag::list_snapshots`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotInfo {
+    pub label: String,
+    pub created: SystemTime,
+    /// Number of blobs the tag held.
+    pub blobs: usize,
+    /// Their total size in bytes.
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Manifest {
+    created_ms: u64,
+    /// Generation and size of each blob, by name.
+    blobs: BTreeMap<String, (u64, u64)>,
+}
+
+impl Manifest {
+    fn encode(&self) -> Vec<u8> {
+        let mut w = FieldWriter::default();
+        w.u64(FIELD_CREATED, self.created_ms);
+        for (name, (generation, size)) in &self.blobs {
+            let mut entry = FieldWriter::default();
+            entry.bytes(FIELD_BLOB_NAME, name.as_bytes());
+            entry.u64(FIELD_BLOB_GENERATION, *generation);
+            entry.u64(FIELD_BLOB_SIZE, *size);
+            w.bytes(FIELD_BLOB, &entry.finish());
+        }
+        w.finish()
+    }
+
+    fn decode(buf: &[u8]) -> Result<Self, String> {
+        let mut manifest = Manifest {
+            created_ms: 0,
+            blobs: BTreeMap::new(),
+        };
+        for (id, value) in FieldReader::new(buf)? {
+            match id {
+                FIELD_CREATED => manifest.created_ms = read_u64(value)?,
+                FIELD_BLOB => {
+                    let (mut name, mut generation, mut size) = (None, 0, 0);
+                    for (id, value) in FieldReader::new(value)? {
+                        match id {
+                            FIELD_BLOB_NAME => name = Some(read_str(value)?),
+                            FIELD_BLOB_GENERATION => generation = read_u64(value)?,
+                            FIELD_BLOB_SIZE => size = read_u64(value)?,
+                            _ => {}
+                        }
+                    }
+                    let name = name.ok_or("snapshot entry without a name")?;
+                    manifest.blobs.insert(name, (generation, size));
+                }
+                _ => {}
+            }
+        }
+        Ok(manifest)
+    }
+
+    fn info(&self, label: &str) -> SnapshotInfo {
+        SnapshotInfo {
+            label: label.to_string(),
+            created: UNIX_EPOCH + Duration::from_millis(self.created_ms),
+            blobs: self.blobs.len(),
+            bytes: self.blobs.values().map(|(_, size)| size).sum(),
+        }
+    }
+}
+
+fn snapshot_name(label: &str) -> String {
+    format!("{}{}", SNAPSHOT_PREFIX, label)
+}
+
+impl Tag {
+    /// Record the current state of this tag as snapshot `label`, which must not
+    /// already exist. Enables versioning on the tag.
+    pub fn snapshot(&self, label: &str) -> Result<SnapshotInfo, CteError> {
+        if label.is_empty() {
+            return Err(CteError::InvalidArgument("empty snapshot label".into()));
+        }
+        let name = snapshot_name(label);
+        if self.get_blob_size(&name) > 0 {
+            return Err(CteError::InvalidArgument(format!(
+                "tag '{}' already has a snapshot '{}'",
+                self.name(),
+                label
+            )));
+        }
+        // Versioning first, so no write can slip between the manifest and it.
+        self.enable_versioning();
+        let manifest = {
+            let _commit = txn::read_guard();
+            let _guard = meta_lock();
+            let mut blobs = BTreeMap::new();
+            for blob in self.blob_names() {
+                let meta = self.load_meta(&blob)?.unwrap_or_default();
+                let size = self.get_blob_size(&blob);
+                if !meta.is_expired() && (size > 0 || meta.generation > 0) {
+                    blobs.insert(blob, (meta.generation, size));
+                }
+            }
+            let manifest = Manifest {
+                created_ms: now_ms(),
+                blobs,
+            };
+            ffi_guard::tag_put_blob(&self.inner, &name, &manifest.encode(), 0, 1.0)?;
+            manifest
+        };
+        Ok(manifest.info(label))
+    }
+
+    /// Snapshots of this tag, oldest first.
+    pub fn list_snapshots(&self) -> Result<Vec<SnapshotInfo>, CteError> {
+        let mut out = Vec::new();
+        for name in self.raw_blob_names() {
+            if let Some(label) = name.strip_prefix(SNAPSHOT_PREFIX) {
+                out.push(self.load_snapshot(label)?.info(label));
+            }
+        }
+        out.sort_by_key(|s| s.created);
+        Ok(out)
+    }
+
+    /// Delete snapshot `label`. The versions it referenced are kept until pruned.
+    pub fn delete_snapshot(&self, label: &str) -> bool {
+        ffi::tag_del_blob(&self.inner, &snapshot_name(label))
+    }
+
+    fn load_snapshot(&self, label: &str) -> Result<Manifest, CteError> {
+        let name = snapshot_name(label);
+        let size = self.get_blob_size(&name);
+        if size == 0 {
+            return Err(CteError::NotFound {
+                blob: format!("snapshot '{}'", label),
+            });
+        }
+        let buf = ffi_guard::tag_get_blob(&self.inner, &name, size, 0)?;
+        Manifest::decode(buf.as_slice())
+            .map_err(|reason| CteError::CorruptMetadata { blob: name, reason })
+    }
+}
+
+impl Client {
+    /// Return `tag` to its state at snapshot `label`: blobs changed or deleted
+    /// since are restored to their snapshotted version and blobs created since
+    /// are deleted, each as an ordinary write or delete. Returns how many blobs
+    /// were changed. Fails without changing anything if a needed version is gone.
+    pub fn restore_snapshot(tag: &str, label: &str) -> Result<usize, CteError> {
+        let tag = Tag::try_new(tag)?;
+        let manifest = tag.load_snapshot(label)?;
+        let _commit = txn::write_guard();
+        let mut restores = Vec::new();
+        for (blob, &(generation, _)) in &manifest.blobs {
+            let meta = tag.load_meta(blob)?.unwrap_or_default();
+            let exists = !meta.is_expired() && (tag.get_blob_size(blob) > 0 || meta.generation > 0);
+            if exists && meta.generation == generation {
+                continue;
+            }
+            if tag.archived_version_size(blob, generation) == 0 {
+                return Err(CteError::NotFound {
+                    blob: format!("{}@{}", blob, generation),
+                });
+            }
+            restores.push((blob, generation));
+        }
+        let created: Vec<String> = tag
+            .blob_names()
+            .into_iter()
+            .filter(|b| !manifest.blobs.contains_key(b))
+            .collect();
+        for (blob, generation) in &restores {
+            tag.rollback_blob(blob, *generation)?;
+        }
+        for blob in &created {
+            tag.del_blob(blob);
+        }
+        Ok(restores.len() + created.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_roundtrip() {
+        let manifest = Manifest {
+            created_ms: 1_700_000_000_000,
+            blobs: BTreeMap::from([
+                ("ckpt/shard-0".to_string(), (3, 4096)),
+                ("ckpt/manifest".to_string(), (1, 12)),
+            ]),
+        };
+        let decoded = Manifest::decode(&manifest.encode()).unwrap();
+        assert_eq!(decoded, manifest);
+        assert_eq!(decoded.info("init").bytes, 4108);
+    }
+}
@@ -0,0 +1,217 @@
+//! Spilling writes to slower tiers when the preferred one is full.
+//!
+//! The runtime places each put on one target with room for all of it, so a
+//! long write fails partway once the tier it was scored for fills up. With a
+//! `SpillPolicy` set, a put request of a write that the runtime fails is
+//! retried on the fastest lower tier (by `TargetInfo::score`) that still has
+//! room, down to `SpillPolicy::min_score`: as much as that room holds goes
+//! there, the remainder to the next tier down, and the rest of the write
+//! follows the last of them. The blob's metadata records where it spilled
+//! (`BlobStat::spill`), also when the write fails after spilling some of it,
+//! and later writes at or past that
+//! offset, such as the next chunks of a `Client::stage_in` or appends, go
+//! straight to the spilled tier. Rewriting the blob from offset 0 starts it
+//! afresh on the tier it asks for.
+//!
+//! Spilling only helps when space is what ran out: if the retry fails too, the
+//! write fails with the first error.
+
+use std::sync::RwLock;
+
+use crate::meta::{read_f32, read_u64, BlobMeta};
+use crate::{ffi_guard, limits, placement, BlobDescriptor, Client, CteError, Tag, TargetInfo};
+
+/// When writes spill (see `spill`).
+#[derive(Debug, Clone)]
+pub struct SpillPolicy {
+    /// Lowest tier score a write may spill onto.
+    pub min_score: f32,
+}
+
+impl Default for SpillPolicy {
+    fn default() -> Self {
+        Self { min_score: 0.0 }
+    }
+}
+
+/// Where a blob left the tier it was written for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spill {
+    /// Offset of the first byte written to a lower tier.
+    pub offset: u64,
+    /// Score the blob has been written at from there on.
+    pub score: f32,
+}
+
+impl Spill {
+    /// Sidecar encoding: offset, score.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut out = self.offset.to_le_bytes().to_vec();
+        out.extend_from_slice(&self.score.to_le_bytes());
+        out
+    }
+
+    pub(crate) fn decode(buf: &[u8]) -> Result<Self, String> {
+        if buf.len() != 12 {
+            return Err("bad spill field".into());
+        }
+        Ok(Self {
+            offset: read_u64(&buf[..8])?,
+            score: read_f32(&buf[8..])?,
+        })
+    }
+}
+
+static POLICY: RwLock<Option<SpillPolicy>> = RwLock::new(None);
+
+/// Set the process-wide spill policy, replacing any previous one.
+pub fn set_spill_policy(policy: SpillPolicy) {
+    *POLICY.write().unwrap_or_else(|e| e.into_inner()) = Some(policy);
+}
+
+/// Remove the spill policy; writes fail when their tier is full again.
+pub fn clear_spill_policy() {
+    *POLICY.write().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+pub(crate) fn policy() -> Option<SpillPolicy> {
+    POLICY.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// The fastest target below `score`, and not below `min_score`, with room.
+fn next_tier(targets: &[TargetInfo], score: f32, min_score: f32) -> Option<&TargetInfo> {
+    targets
+        .iter()
+        .filter(|t| t.score < score && t.score >= min_score && t.remaining_space > 0)
+        .max_by(|a, b| a.score.total_cmp(&b.score))
+}
+
+impl Tag {
+    /// Put `data` at `offset` in requests of at most `RequestLimits::max_chunk`,
+    /// spilling to lower tiers as the policy allows and recording it in `meta`.
+    pub(crate) fn put_spilling(
+        &self,
+        name: &str,
+        data: &[u8],
+        offset: u64,
+        score: Option<f32>,
+        meta: &mut BlobMeta,
+    ) -> Result<(), CteError> {
+        if offset == 0 {
+            meta.spill = None;
+        }
+        let mut score = match meta.spill {
+            Some(spill) if offset >= spill.offset => Some(spill.score),
+            _ => score,
+        };
+        let policy = policy();
+        let max_chunk = limits::request_limits().max_chunk;
+        let slice = |at: u64, len: u64| {
+            let from = (at - offset) as usize;
+            &data[from..from + len as usize]
+        };
+        let spilled = meta.spill;
+        for (at, len) in limits::chunks(offset, data.len() as u64, max_chunk) {
+            let e = match self.put_chunk(name, slice(at, len), at, score) {
+                Ok(()) => continue,
+                Err(e) => e,
+            };
+            let policy = match (&policy, &e) {
+                (Some(policy), CteError::Runtime { .. }) => policy,
+                _ => return Err(e),
+            };
+            let mut current =
+                score.unwrap_or_else(|| self.unscored(name, data.len() as u64, offset));
+            let (mut piece, end) = (at, at + len);
+            while piece < end {
+                let targets = Client::list_targets();
+                let Some(tier) = next_tier(&targets, current, policy.min_score) else {
+                    // Record what did spill, so reads and later writes find it.
+                    if meta.spill != spilled {
+                        self.store_meta(name, meta)?;
+                    }
+                    return Err(e);
+                };
+                // Whether or not this tier takes the piece, the next try is below it.
+                current = tier.score;
+                let n = tier.remaining_space.min(end - piece);
+                if self
+                    .put_chunk(name, slice(piece, n), piece, Some(tier.score))
+                    .is_err()
+                {
+                    continue;
+                }
+                meta.spill = Some(Spill {
+                    offset: meta.spill.map_or(piece, |s| s.offset),
+                    score: tier.score,
+                });
+                score = Some(tier.score);
+                piece += n;
+            }
+        }
+        Ok(())
+    }
+
+    fn put_chunk(
+        &self,
+        name: &str,
+        data: &[u8],
+        offset: u64,
+        score: Option<f32>,
+    ) -> Result<(), CteError> {
+        match score {
+            Some(score) => ffi_guard::tag_put_blob(&self.inner, name, data, offset, score),
+            None => ffi_guard::tag_put_blob_placed(&self.inner, name, data, offset),
+        }
+    }
+
+    /// The score an unscored put of `name` was placed at; above every tier if
+    /// the runtime chose.
+    fn unscored(&self, name: &str, size: u64, offset: u64) -> f32 {
+        let score = placement::placement_score(&BlobDescriptor {
+            tag_id: self.get_tag_id(),
+            name: name.to_string(),
+            size,
+            offset,
+        });
+        if score < 0.0 {
+            f32::INFINITY
+        } else {
+            score
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spill_tiers() {
+        let target = |name: &str, score, remaining_space| TargetInfo {
+            name: name.into(),
+            score,
+            remaining_space,
+            bytes_read: 0,
+            bytes_written: 0,
+        };
+        let targets = [
+            target("ram", 1.0, 0),
+            target("nvme", 0.6, 4096),
+            target("full_ssd", 0.4, 0),
+            target("hdd", 0.1, 1 << 30),
+        ];
+        let name = |t: Option<&TargetInfo>| t.map(|t| t.name.clone());
+        assert_eq!(name(next_tier(&targets, 1.0, 0.0)).as_deref(), Some("nvme"));
+        assert_eq!(name(next_tier(&targets, 0.6, 0.0)).as_deref(), Some("hdd"));
+        assert_eq!(name(next_tier(&targets, 0.6, 0.2)), None);
+        assert_eq!(name(next_tier(&targets, 0.1, 0.0)), None);
+
+        let spill = Spill {
+            offset: 1 << 33,
+            score: 0.25,
+        };
+        assert_eq!(Spill::decode(&spill.encode()), Ok(spill));
+        assert!(Spill::decode(&[0; 4]).is_err());
+    }
+}
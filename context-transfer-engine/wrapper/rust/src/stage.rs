@@ -0,0 +1,433 @@
+//! Directory tree stage-in / stage-out.
+//!
+//! Files are stored one blob per file, named by their path relative to the staged
+//! root with `/` separators. Each worker thread opens its own `Tag` handle and
+//! moves files in `chunk_size` pieces using blob offsets. On Unix, a path that
+//! isn't UTF-8 keeps its bytes as the blob name (see `rawname`), and stages back
+//! out to the same path.
+//!
+//! A cancelled transfer (see `StageOptions::cancel`) keeps the files it
+//! finished and removes the ones it was partway through: their blobs on
+//! stage-in, their files on stage-out.
+
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::cancel::{self, CancellationToken};
+use crate::{Client, CteError, Tag};
+
+/// Callback invoked as staging progresses.
+pub type ProgressFn = Arc<dyn Fn(&StageProgress) + Send + Sync>;
+
+/// Options for `Client::stage_in` / `Client::stage_out_with_options`.
+#[derive(Clone)]
+pub struct StageOptions {
+    /// Number of files transferred concurrently.
+    pub parallelism: usize,
+    /// Bytes moved per put/get call.
+    pub chunk_size: usize,
+    /// Placement score for staged-in blobs.
+    pub score: f32,
+    /// Called after every chunk and every completed file, from the worker threads.
+    pub progress: Option<ProgressFn>,
+    /// Stops the transfer between chunks with `CteError::Cancelled`.
+    pub cancel: Option<CancellationToken>,
+}
+
+impl Default for StageOptions {
+    fn default() -> Self {
+        Self {
+            parallelism: std::thread::available_parallelism().map_or(4, |n| n.get()),
+            chunk_size: 4 * 1024 * 1024,
+            score: 1.0,
+            progress: None,
+            cancel: None,
+        }
+    }
+}
+
+impl StageOptions {
+    /// Set the progress callback.
+    pub fn with_progress(mut self, f: impl Fn(&StageProgress) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Arc::new(f));
+        self
+    }
+
+    /// Set the cancellation token.
+    pub fn with_cancel(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+}
+
+/// Snapshot of a running stage-in or stage-out.
+#[derive(Debug, Clone)]
+pub struct StageProgress {
+    pub files_done: usize,
+    pub files_total: usize,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+    /// Blob name of the file that triggered this update.
+    pub current: String,
+}
+
+/// Totals for a completed transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StageReport {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// One file <-> blob transfer.
+struct Entry {
+    blob: Vec<u8>,
+    path: PathBuf,
+    size: u64,
+}
+
+impl Entry {
+    /// The blob name as progress reports and errors show it.
+    fn label(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.blob)
+    }
+}
+
+/// Bytes of a path component, as a blob name piece. Outside Unix, only UTF-8.
+#[cfg(unix)]
+fn component_bytes(c: &OsStr) -> Option<&[u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Some(c.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn component_bytes(c: &OsStr) -> Option<&[u8]> {
+    c.to_str().map(str::as_bytes)
+}
+
+/// The relative path a blob name stages out to.
+#[cfg(unix)]
+fn blob_path(blob: &[u8]) -> Option<&Path> {
+    use std::os::unix::ffi::OsStrExt;
+    Some(Path::new(OsStr::from_bytes(blob)))
+}
+
+#[cfg(not(unix))]
+fn blob_path(blob: &[u8]) -> Option<&Path> {
+    std::str::from_utf8(blob).ok().map(Path::new)
+}
+
+struct Progress<'a> {
+    options: &'a StageOptions,
+    files_done: AtomicUsize,
+    files_total: usize,
+    bytes_done: AtomicU64,
+    bytes_total: u64,
+}
+
+impl Progress<'_> {
+    fn report(&self, current: &str, bytes: u64, file_done: bool) {
+        let bytes_done = self.bytes_done.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        let files_done = if file_done {
+            self.files_done.fetch_add(1, Ordering::Relaxed) + 1
+        } else {
+            self.files_done.load(Ordering::Relaxed)
+        };
+        if let Some(cb) = &self.options.progress {
+            cb(&StageProgress {
+                files_done,
+                files_total: self.files_total,
+                bytes_done,
+                bytes_total: self.bytes_total,
+                current: current.to_string(),
+            });
+        }
+    }
+}
+
+impl Client {
+    /// Copy every regular file under `dir_path` into `tag_name`, one blob per file.
+    ///
+    /// Blob names are the file paths relative to `dir_path`, `/`-separated.
+    /// Symlinked directories are not followed.
+    pub fn stage_in(
+        dir_path: impl AsRef<Path>,
+        tag_name: &str,
+        options: &StageOptions,
+    ) -> Result<StageReport, CteError> {
+        let root = dir_path.as_ref();
+        let mut entries = Vec::new();
+        walk(root, root, &mut entries)?;
+        entries.sort_by(|a, b| a.blob.cmp(&b.blob));
+        run_parallel(tag_name, &entries, options, |tag, entry, progress| {
+            let mut file = File::open(&entry.path)?;
+            let mut buf = vec![0u8; options.chunk_size.max(1)];
+            let mut offset = 0u64;
+            loop {
+                if cancel::cancelled(&options.cancel) {
+                    if offset > 0 {
+                        tag.hard_del_blob_bytes_name(&entry.blob);
+                    }
+                    return Err(CteError::Cancelled);
+                }
+                let n = read_full(&mut file, &mut buf)?;
+                if n == 0 && offset > 0 {
+                    break;
+                }
+                tag.put_blob_bytes_name(&entry.blob, &buf[..n], offset, options.score)?;
+                offset += n as u64;
+                progress.report(&entry.label(), n as u64, false);
+                if n < buf.len() {
+                    break;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    /// Write every blob of `tag_name` to a file under `dir_path`.
+    pub fn stage_out(tag_name: &str, dir_path: impl AsRef<Path>) -> Result<StageReport, CteError> {
+        Self::stage_out_with_options(tag_name, dir_path, &StageOptions::default())
+    }
+
+    /// `stage_out` with explicit parallelism, chunk size and progress callback.
+    ///
+    /// Blob names that would escape `dir_path` (absolute paths or `..`
+    /// components) are rejected before anything is written, and a tag that
+    /// does not exist is `NotFound` rather than an empty tree.
+    pub fn stage_out_with_options(
+        tag_name: &str,
+        dir_path: impl AsRef<Path>,
+        options: &StageOptions,
+    ) -> Result<StageReport, CteError> {
+        if !Client::tag_exists(tag_name) {
+            return Err(CteError::NotFound {
+                blob: tag_name.to_string(),
+            });
+        }
+        let root = dir_path.as_ref();
+        let tag = Tag::try_new(tag_name)?;
+        let mut entries = Vec::new();
+        for blob in tag.get_contained_blobs_bytes() {
+            let rel = match blob_path(&blob) {
+                Some(rel)
+                    if !blob.is_empty()
+                        && rel.components().all(|c| matches!(c, Component::Normal(_))) =>
+                {
+                    rel
+                }
+                _ => {
+                    return Err(CteError::InvalidArgument(format!(
+                        "blob name '{}' is not a relative path",
+                        String::from_utf8_lossy(&blob)
+                    )))
+                }
+            };
+            entries.push(Entry {
+                size: tag.get_blob_size_bytes_name(&blob),
+                path: root.join(rel),
+                blob,
+            });
+        }
+        drop(tag);
+        run_parallel(tag_name, &entries, options, |tag, entry, progress| {
+            if let Some(parent) = entry.path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut file = File::create(&entry.path)?;
+            let chunk = options.chunk_size.max(1) as u64;
+            let mut offset = 0u64;
+            while offset < entry.size {
+                if cancel::cancelled(&options.cancel) {
+                    drop(file);
+                    fs::remove_file(&entry.path)?;
+                    return Err(CteError::Cancelled);
+                }
+                let len = chunk.min(entry.size - offset);
+                let data = tag.get_blob_bytes_name(&entry.blob, len, offset)?;
+                file.write_all(&data)?;
+                offset += len;
+                progress.report(&entry.label(), len, false);
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Run `transfer` over `entries` on `options.parallelism` threads, stopping at the
+/// first error or once `options.cancel` is cancelled.
+fn run_parallel<F>(
+    tag_name: &str,
+    entries: &[Entry],
+    options: &StageOptions,
+    transfer: F,
+) -> Result<StageReport, CteError>
+where
+    F: Fn(&Tag, &Entry, &Progress) -> Result<(), CteError> + Sync,
+{
+    let progress = Progress {
+        options,
+        files_done: AtomicUsize::new(0),
+        files_total: entries.len(),
+        bytes_done: AtomicU64::new(0),
+        bytes_total: entries.iter().map(|e| e.size).sum(),
+    };
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let first_err: Mutex<Option<CteError>> = Mutex::new(None);
+    let workers = options.parallelism.clamp(1, entries.len().max(1));
+
+    std::thread::scope(|s| {
+        for _ in 0..workers {
+            s.spawn(|| {
+                let tag = match Tag::try_new(tag_name) {
+                    Ok(tag) => tag,
+                    Err(e) => {
+                        failed.store(true, Ordering::Relaxed);
+                        first_err.lock().unwrap().get_or_insert(e);
+                        return;
+                    }
+                };
+                while !failed.load(Ordering::Relaxed) && !cancel::cancelled(&options.cancel) {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(entry) = entries.get(i) else { break };
+                    match transfer(&tag, entry, &progress) {
+                        Ok(()) => progress.report(&entry.label(), 0, true),
+                        Err(e) => {
+                            failed.store(true, Ordering::Relaxed);
+                            first_err.lock().unwrap().get_or_insert(e);
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    if let Some(e) = first_err.into_inner().unwrap() {
+        return Err(e);
+    }
+    // Cancelled between files, before every one was reached.
+    if progress.files_done.load(Ordering::Relaxed) < entries.len() {
+        return Err(CteError::Cancelled);
+    }
+    Ok(StageReport {
+        files: entries.len(),
+        bytes: progress.bytes_total,
+    })
+}
+
+/// `(blob name, path)` of every regular file under `root`, named as `stage_in`
+/// names them. A file whose name would land in the wrapper's reserved
+/// namespace (`.cte/...`) is an error rather than a bookkeeping overwrite, and
+/// so is a path that isn't UTF-8.
+pub(crate) fn walk_files(root: &Path) -> Result<Vec<(String, PathBuf)>, CteError> {
+    let mut entries = Vec::new();
+    walk(root, root, &mut entries)?;
+    entries
+        .into_iter()
+        .map(|e| match String::from_utf8(e.blob) {
+            Ok(blob) => Ok((blob, e.path)),
+            Err(_) => Err(CteError::InvalidArgument(format!(
+                "path {} is not valid UTF-8",
+                e.path.display()
+            ))),
+        })
+        .collect()
+}
+
+fn walk(root: &Path, dir: &Path, out: &mut Vec<Entry>) -> Result<(), CteError> {
+    for dent in fs::read_dir(dir)? {
+        let dent = dent?;
+        let path = dent.path();
+        if dent.file_type()?.is_dir() {
+            walk(root, &path, out)?;
+            continue;
+        }
+        let md = fs::metadata(&path)?;
+        if !md.is_file() {
+            continue;
+        }
+        let rel = path.strip_prefix(root).expect("walked path is under root");
+        let mut parts = Vec::new();
+        for c in rel.components() {
+            match component_bytes(c.as_os_str()) {
+                Some(s) => parts.push(s),
+                None => {
+                    return Err(CteError::InvalidArgument(format!(
+                        "path {} is not valid UTF-8",
+                        path.display()
+                    )))
+                }
+            }
+        }
+        let blob = parts.join(&b'/');
+        if blob.starts_with(crate::meta::RESERVED_PREFIX.as_bytes()) {
+            return Err(CteError::InvalidArgument(format!(
+                "path {} maps to reserved blob name '{}'",
+                path.display(),
+                String::from_utf8_lossy(&blob)
+            )));
+        }
+        out.push(Entry {
+            blob,
+            path,
+            size: md.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Fill `buf` as far as possible, returning fewer bytes only at end of file.
+fn read_full(file: &mut File, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_walk_relative_names() {
+        let root = std::env::temp_dir().join(format!("cte_stage_walk_{}", std::process::id()));
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("top.txt"), b"1").unwrap();
+        fs::write(root.join("a/b/deep.bin"), b"22").unwrap();
+
+        let mut entries = Vec::new();
+        walk(&root, &root, &mut entries).unwrap();
+        let mut names: Vec<_> = entries
+            .iter()
+            .map(|e| (e.blob.as_slice(), e.size))
+            .collect();
+        names.sort();
+        assert_eq!(names, vec![(&b"a/b/deep.bin"[..], 2), (&b"top.txt"[..], 1)]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_walk_refuses_reserved_names() {
+        let root = std::env::temp_dir().join(format!("cte_stage_reserved_{}", std::process::id()));
+        fs::create_dir_all(root.join(".cte/meta")).unwrap();
+        fs::write(root.join(".cte/meta/x"), b"forged").unwrap();
+
+        let mut entries = Vec::new();
+        let err = walk(&root, &root, &mut entries).unwrap_err();
+        assert!(matches!(err, CteError::InvalidArgument(ref m) if m.contains(".cte/meta/x")));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+//! Process-wide initialization state.
+//!
+//! The C++ side keeps unsynchronized "already initialized" flags, so two
+//! threads calling `init` at once race on them. `init` therefore runs at most
+//! one initialization at a time: the first call that succeeds fixes the state
+//! for the process, calls made meanwhile wait for it, and later calls return
+//! that state without initializing again. A failed attempt leaves the process
+//! uninitialized, so it can be retried.
+
+use std::sync::{Mutex, OnceLock};
+
+use crate::{ffi, CteError};
+
+/// How this process is connected to CTE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RuntimeState {
+    /// Neither `init` nor anything else has initialized Chimaera.
+    Uninitialized,
+    /// A client of a runtime running in another process.
+    ClientAttached,
+    /// A client of a runtime this process started and serves.
+    EmbeddedRuntime,
+}
+
+impl RuntimeState {
+    fn from_mode(mode: u8) -> Self {
+        match mode {
+            0 => RuntimeState::Uninitialized,
+            1 => RuntimeState::ClientAttached,
+            _ => RuntimeState::EmbeddedRuntime,
+        }
+    }
+
+    /// What the C++ side reports, for code that initialized it without `init`.
+    pub(crate) fn current() -> Self {
+        Self::from_mode(ffi::chimaera_mode())
+    }
+}
+
+/// One initialization per process, serialized.
+pub(crate) struct InitOnce {
+    state: OnceLock<RuntimeState>,
+    running: Mutex<()>,
+}
+
+impl InitOnce {
+    pub(crate) const fn new() -> Self {
+        Self {
+            state: OnceLock::new(),
+            running: Mutex::new(()),
+        }
+    }
+
+    /// The state a successful `run` reached, if any.
+    pub(crate) fn get(&self) -> Option<RuntimeState> {
+        self.state.get().copied()
+    }
+
+    /// Run `init` unless an earlier call succeeded, waiting for one in flight.
+    pub(crate) fn run(
+        &self,
+        init: impl FnOnce() -> Result<RuntimeState, CteError>,
+    ) -> Result<RuntimeState, CteError> {
+        if let Some(state) = self.get() {
+            return Ok(state);
+        }
+        let _running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(state) = self.get() {
+            return Ok(state);
+        }
+        let state = init()?;
+        Ok(*self.state.get_or_init(|| state))
+    }
+}
+
+pub(crate) static INIT: InitOnce = InitOnce::new();
+
+/// How this process is connected to CTE: set by the first successful `init`,
+/// or as the C++ side reports it before then, so libraries sharing the process
+/// can tell whether someone already started a runtime.
+pub fn runtime_state() -> RuntimeState {
+    INIT.get().unwrap_or_else(RuntimeState::current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_init_once() {
+        let once = InitOnce::new();
+        let failed = once.run(|| Err(CteError::Unsupported("no runtime".into())));
+        assert!(failed.is_err());
+        assert_eq!(once.get(), None, "a failed init can be retried");
+
+        let calls = AtomicUsize::new(0);
+        let states: Vec<_> = std::thread::scope(|s| {
+            let threads: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        once.run(|| {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                            std::thread::sleep(Duration::from_millis(20));
+                            Ok(RuntimeState::EmbeddedRuntime)
+                        })
+                    })
+                })
+                .collect();
+            threads.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(states
+            .iter()
+            .all(|s| matches!(s, Ok(RuntimeState::EmbeddedRuntime))));
+        let again = once.run(|| Ok(RuntimeState::ClientAttached)).unwrap();
+        assert_eq!(again, RuntimeState::EmbeddedRuntime);
+        assert_eq!(RuntimeState::from_mode(1), RuntimeState::ClientAttached);
+    }
+}
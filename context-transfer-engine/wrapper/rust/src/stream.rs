@@ -0,0 +1,209 @@
+//! `std::io` access to blobs.
+//!
+//! `Tag::reader` gives a `BlobReader`, which reads a blob through `Read` and
+//! `Seek` with ranged gets, so code written against files can read a blob
+//! without fetching all of it. `Tag::writer` gives a `BlobWriter`, which
+//! gathers contiguous writes and puts them at their offset once
+//! `WRITE_BUFFER` bytes are waiting, before a write elsewhere, on `flush` and
+//! on drop (where a failure is lost, as with `std::io::BufWriter`). Both hold
+//! their own handle on the tag, so they can outlive the `Tag` they came from.
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::{CteError, GetOptions, PutOptions, Tag};
+
+/// Bytes a `BlobWriter` gathers before putting them.
+pub const WRITE_BUFFER: usize = 4 << 20;
+
+fn seek_to(base: u64, delta: i64) -> io::Result<u64> {
+    base.checked_add_signed(delta).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "seek before the start of the blob",
+        )
+    })
+}
+
+/// Reads a blob as a file (see `stream`).
+pub struct BlobReader {
+    tag: Tag,
+    name: String,
+    pos: u64,
+    /// The blob's size when the reader was made or last refreshed.
+    len: u64,
+}
+
+impl BlobReader {
+    /// The blob's size, as of `Tag::reader` or the last `refresh`.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Take the blob's size again, after it was written to.
+    pub fn refresh(&mut self) -> Result<(), CteError> {
+        self.len = self
+            .tag
+            .stat_blob(&self.name)?
+            .ok_or_else(|| CteError::NotFound {
+                blob: self.name.clone(),
+            })?
+            .size;
+        Ok(())
+    }
+
+    /// Read from `offset` into `buf`, without moving the position. Returns the
+    /// bytes read, fewer than `buf` holds only at the end of the blob.
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, CteError> {
+        let len = (buf.len() as u64).min(self.len.saturating_sub(offset));
+        if len == 0 {
+            return Ok(0);
+        }
+        let options = GetOptions {
+            offset,
+            size: Some(len),
+            ..Default::default()
+        };
+        let data = self.tag.get(&self.name, &options)?;
+        buf[..data.len()].copy_from_slice(&data);
+        Ok(data.len())
+    }
+}
+
+impl Read for BlobReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.read_at(self.pos, buf).map_err(io::Error::other)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for BlobReader {
+    fn seek(&mut self, to: SeekFrom) -> io::Result<u64> {
+        self.pos = match to {
+            SeekFrom::Start(pos) => pos,
+            SeekFrom::End(delta) => seek_to(self.len, delta)?,
+            SeekFrom::Current(delta) => seek_to(self.pos, delta)?,
+        };
+        Ok(self.pos)
+    }
+}
+
+/// Writes a blob as a file (see `stream`).
+pub struct BlobWriter {
+    tag: Tag,
+    name: String,
+    pos: u64,
+    buf: Vec<u8>,
+    /// Where `buf` goes.
+    at: u64,
+    /// The blob's size, counting the bytes in `buf`.
+    len: u64,
+}
+
+impl BlobWriter {
+    /// The blob's size, counting writes not put yet.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether there are writes not put yet.
+    pub fn is_dirty(&self) -> bool {
+        !self.buf.is_empty()
+    }
+
+    /// Write `data` at `offset`, without moving the position.
+    pub fn write_at(&mut self, offset: u64, data: &[u8]) -> Result<(), CteError> {
+        if !self.buf.is_empty() && self.at + self.buf.len() as u64 != offset {
+            self.send()?;
+        }
+        if self.buf.is_empty() {
+            self.at = offset;
+        }
+        self.buf.extend_from_slice(data);
+        self.len = self.len.max(offset + data.len() as u64);
+        if self.buf.len() >= WRITE_BUFFER {
+            self.send()?;
+        }
+        Ok(())
+    }
+
+    /// Put the writes gathered so far.
+    pub fn send(&mut self) -> Result<(), CteError> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let options = PutOptions {
+            offset: self.at,
+            ..Default::default()
+        };
+        self.tag.put(&self.name, &self.buf, &options)?;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+impl Write for BlobWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.write_at(self.pos, data).map_err(io::Error::other)?;
+        self.pos += data.len() as u64;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send().map_err(io::Error::other)
+    }
+}
+
+impl Seek for BlobWriter {
+    fn seek(&mut self, to: SeekFrom) -> io::Result<u64> {
+        self.pos = match to {
+            SeekFrom::Start(pos) => pos,
+            SeekFrom::End(delta) => seek_to(self.len, delta)?,
+            SeekFrom::Current(delta) => seek_to(self.pos, delta)?,
+        };
+        Ok(self.pos)
+    }
+}
+
+impl Drop for BlobWriter {
+    fn drop(&mut self) {
+        let _ = self.send();
+    }
+}
+
+impl Tag {
+    /// Read blob `name` as a file (see `stream`). Fails with `NotFound` if it
+    /// doesn't exist.
+    pub fn reader(&self, name: &str) -> Result<BlobReader, CteError> {
+        let mut reader = BlobReader {
+            tag: Tag::from_id(self.get_tag_id()),
+            name: name.to_string(),
+            pos: 0,
+            len: 0,
+        };
+        reader.refresh()?;
+        Ok(reader)
+    }
+
+    /// Write blob `name` as a file (see `stream`), from offset 0. Bytes not
+    /// written over keep their data; the blob is created by the first put.
+    pub fn writer(&self, name: &str) -> Result<BlobWriter, CteError> {
+        let len = self.stat_blob(name)?.map_or(0, |s| s.size);
+        Ok(BlobWriter {
+            tag: Tag::from_id(self.get_tag_id()),
+            name: name.to_string(),
+            pos: 0,
+            buf: Vec::new(),
+            at: 0,
+            len,
+        })
+    }
+}
@@ -0,0 +1,334 @@
+//! Compact per-blob summaries for browsing.
+//!
+//! A UI listing a tag wants more than names and sizes: the first bytes of each
+//! blob, how many rows a table has, the range of its numeric columns. Fetching
+//! every blob to find out is too slow, so registered `Summarizer`s look at the
+//! data once, when `Tag::put` writes a whole blob, and their findings are kept
+//! as a `BlobSummary` in the blob's metadata sidecar, where `Tag::blob_summary`
+//! and `Tag::summaries` read them back. `HeadSummarizer` and
+//! `TableSummarizer` cover the common cases. A write that doesn't replace the
+//! whole blob (an offset put, an append, a raw write) drops its summary, since
+//! it no longer describes the data; `Tag::summarize_blob` makes a new one from
+//! the stored data. Summarizers see the data before it is compressed or
+//! encrypted, so a summary of an encrypted blob shows what its data holds to
+//! anyone who can read the tag; don't register ones that would keep secrets.
+
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use crate::meta::{meta_lock, read_pair, read_str, read_u64, FieldReader, FieldWriter};
+use crate::{ffi_guard, CteError, GetOptions, Tag};
+
+const FIELD_HEAD: u8 = 1;
+const FIELD_ROWS: u8 = 2;
+const FIELD_COLUMN: u8 = 3;
+const FIELD_EXTRA: u8 = 4;
+
+const FIELD_COLUMN_NAME: u8 = 1;
+const FIELD_COLUMN_MIN: u8 = 2;
+const FIELD_COLUMN_MAX: u8 = 3;
+
+/// What the summarizers found in a blob.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BlobSummary {
+    /// The first bytes of the data.
+    pub head: Vec<u8>,
+    /// Rows, for tabular data.
+    pub rows: Option<u64>,
+    /// Numeric columns, for tabular data.
+    pub columns: Vec<ColumnSummary>,
+    /// Anything else a summarizer wants shown.
+    pub extra: BTreeMap<String, String>,
+}
+
+/// Range of a numeric column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnSummary {
+    pub name: String,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl BlobSummary {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut w = FieldWriter::default();
+        if !self.head.is_empty() {
+            w.bytes(FIELD_HEAD, &self.head);
+        }
+        if let Some(rows) = self.rows {
+            w.u64(FIELD_ROWS, rows);
+        }
+        for c in &self.columns {
+            let mut column = FieldWriter::default();
+            column.bytes(FIELD_COLUMN_NAME, c.name.as_bytes());
+            column.u64(FIELD_COLUMN_MIN, c.min.to_bits());
+            column.u64(FIELD_COLUMN_MAX, c.max.to_bits());
+            w.bytes(FIELD_COLUMN, &column.finish());
+        }
+        for (k, v) in &self.extra {
+            w.pair(FIELD_EXTRA, k, v);
+        }
+        w.finish()
+    }
+
+    pub(crate) fn decode(buf: &[u8]) -> Result<Self, String> {
+        let mut summary = Self::default();
+        for (id, value) in FieldReader::new(buf)? {
+            match id {
+                FIELD_HEAD => summary.head = value.to_vec(),
+                FIELD_ROWS => summary.rows = Some(read_u64(value)?),
+                FIELD_COLUMN => {
+                    let mut column = ColumnSummary {
+                        name: String::new(),
+                        min: 0.0,
+                        max: 0.0,
+                    };
+                    for (id, value) in FieldReader::new(value)? {
+                        match id {
+                            FIELD_COLUMN_NAME => column.name = read_str(value)?,
+                            FIELD_COLUMN_MIN => column.min = f64::from_bits(read_u64(value)?),
+                            FIELD_COLUMN_MAX => column.max = f64::from_bits(read_u64(value)?),
+                            _ => {}
+                        }
+                    }
+                    summary.columns.push(column);
+                }
+                FIELD_EXTRA => {
+                    let (k, v) = read_pair(value)?;
+                    summary.extra.insert(k, v);
+                }
+                _ => {}
+            }
+        }
+        Ok(summary)
+    }
+}
+
+/// Fills in part of a blob's summary from its data.
+pub trait Summarizer: Send + Sync {
+    fn summarize(&self, blob: &str, data: &[u8], summary: &mut BlobSummary);
+}
+
+impl<F> Summarizer for F
+where
+    F: Fn(&str, &[u8], &mut BlobSummary) + Send + Sync,
+{
+    fn summarize(&self, blob: &str, data: &[u8], summary: &mut BlobSummary) {
+        self(blob, data, summary)
+    }
+}
+
+/// Keeps the first `bytes` bytes of every blob.
+#[derive(Debug, Clone)]
+pub struct HeadSummarizer {
+    pub bytes: usize,
+}
+
+impl Default for HeadSummarizer {
+    fn default() -> Self {
+        Self { bytes: 256 }
+    }
+}
+
+impl Summarizer for HeadSummarizer {
+    fn summarize(&self, _blob: &str, data: &[u8], summary: &mut BlobSummary) {
+        summary.head = data[..data.len().min(self.bytes)].to_vec();
+    }
+}
+
+/// Counts the rows of CSV and TSV blobs and ranges their numeric columns.
+/// A blob is taken as a table if it is UTF-8 and its first line splits, on
+/// tabs or else commas, into at least two fields; that line is the header.
+/// A column is numeric if every non-empty field of it parses as a number.
+/// At most `max_bytes` are looked at, and a blob longer than that gets no
+/// table summary.
+#[derive(Debug, Clone)]
+pub struct TableSummarizer {
+    pub max_bytes: usize,
+}
+
+impl Default for TableSummarizer {
+    fn default() -> Self {
+        Self {
+            max_bytes: 64 << 20,
+        }
+    }
+}
+
+impl Summarizer for TableSummarizer {
+    fn summarize(&self, _blob: &str, data: &[u8], summary: &mut BlobSummary) {
+        if data.len() > self.max_bytes {
+            return;
+        }
+        let Ok(text) = std::str::from_utf8(data) else {
+            return;
+        };
+        let mut lines = text.lines().filter(|l| !l.is_empty());
+        let Some(header) = lines.next() else {
+            return;
+        };
+        let delimiter = if header.contains('\t') { '\t' } else { ',' };
+        let names: Vec<&str> = header.split(delimiter).map(str::trim).collect();
+        if names.len() < 2 {
+            return;
+        }
+        // `None` once a column has a field that isn't a number.
+        let mut ranges: Vec<Option<(f64, f64)>> =
+            vec![Some((f64::INFINITY, f64::NEG_INFINITY)); names.len()];
+        let mut rows = 0;
+        for line in lines {
+            rows += 1;
+            for (range, field) in ranges.iter_mut().zip(line.split(delimiter)) {
+                let field = field.trim();
+                if field.is_empty() {
+                    continue;
+                }
+                *range = match (*range, field.parse::<f64>()) {
+                    (Some((min, max)), Ok(v)) if v.is_finite() => Some((min.min(v), max.max(v))),
+                    _ => None,
+                };
+            }
+        }
+        summary.rows = Some(rows);
+        summary.columns = names
+            .iter()
+            .zip(ranges)
+            .filter_map(|(name, range)| {
+                let (min, max) = range.filter(|(min, max)| min <= max)?;
+                Some(ColumnSummary {
+                    name: name.to_string(),
+                    min,
+                    max,
+                })
+            })
+            .collect();
+    }
+}
+
+static SUMMARIZERS: RwLock<Vec<Box<dyn Summarizer>>> = RwLock::new(Vec::new());
+
+/// Register a summarizer. Summarizers run in the order they were added, each
+/// on the summary the ones before it filled in.
+pub fn add_summarizer(summarizer: impl Summarizer + 'static) {
+    SUMMARIZERS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(Box::new(summarizer));
+}
+
+/// Remove every summarizer; puts stop recording summaries.
+pub fn clear_summarizers() {
+    SUMMARIZERS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .clear();
+}
+
+/// The registered summarizers' summary of `data`, or `None` if there are none
+/// or they found nothing. A summarizer that panics is skipped.
+pub(crate) fn summarize(blob: &str, data: &[u8]) -> Option<BlobSummary> {
+    let summarizers = SUMMARIZERS.read().unwrap_or_else(|e| e.into_inner());
+    if summarizers.is_empty() {
+        return None;
+    }
+    let mut summary = BlobSummary::default();
+    for s in summarizers.iter() {
+        ffi_guard::hook("summarizer", (), || s.summarize(blob, data, &mut summary));
+    }
+    (!summary.is_empty()).then_some(summary)
+}
+
+impl Tag {
+    /// The summary recorded for `name` (see `summary`), if it has one.
+    pub fn blob_summary(&self, name: &str) -> Result<Option<BlobSummary>, CteError> {
+        Ok(self.load_meta(name)?.and_then(|m| m.summary))
+    }
+
+    /// Summaries of the blobs whose names start with `prefix`, by name; blobs
+    /// without one are left out.
+    pub fn summaries(&self, prefix: &str) -> Result<BTreeMap<String, BlobSummary>, CteError> {
+        let mut summaries = BTreeMap::new();
+        for name in self.blob_names() {
+            if !name.starts_with(prefix) {
+                continue;
+            }
+            if let Some(summary) = self.blob_summary(&name)? {
+                summaries.insert(name, summary);
+            }
+        }
+        Ok(summaries)
+    }
+
+    /// Record `summary` for `name`, as computed elsewhere; `NotFound` if there
+    /// is no such blob.
+    pub fn set_blob_summary(&self, name: &str, summary: &BlobSummary) -> Result<(), CteError> {
+        let _guard = meta_lock();
+        self.store_summary(name, Some(summary.clone()))
+    }
+
+    /// Summarize `name` again from its stored data with the registered
+    /// summarizers, replacing its summary; returns the new one.
+    pub fn summarize_blob(&self, name: &str) -> Result<Option<BlobSummary>, CteError> {
+        let data = self.get(name, &GetOptions::default())?;
+        let summary = summarize(name, &data);
+        let _guard = meta_lock();
+        self.store_summary(name, summary.clone())?;
+        Ok(summary)
+    }
+
+    /// Replace the summary of `name`; the caller holds the meta lock.
+    fn store_summary(&self, name: &str, summary: Option<BlobSummary>) -> Result<(), CteError> {
+        let meta = self.load_meta(name)?;
+        if self.get_blob_size(name) == 0 && meta.is_none() {
+            return Err(CteError::NotFound {
+                blob: name.to_string(),
+            });
+        }
+        let mut meta = meta.unwrap_or_default();
+        meta.summary = summary;
+        self.store_meta(name, &meta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarizers() {
+        let csv = b"step,energy,label\n1,-3.5,a\n2,7,b\n3,,c\n";
+        let mut summary = BlobSummary::default();
+        HeadSummarizer { bytes: 4 }.summarize("t.csv", csv, &mut summary);
+        TableSummarizer::default().summarize("t.csv", csv, &mut summary);
+        assert_eq!(summary.head, b"step");
+        assert_eq!(summary.rows, Some(3));
+        let ranges: Vec<(&str, f64, f64)> = summary
+            .columns
+            .iter()
+            .map(|c| (c.name.as_str(), c.min, c.max))
+            .collect();
+        assert_eq!(ranges, [("step", 1.0, 3.0), ("energy", -3.5, 7.0)]);
+        summary.extra.insert("schema".into(), "v2".into());
+        assert_eq!(BlobSummary::decode(&summary.encode()).unwrap(), summary);
+
+        let mut binary = BlobSummary::default();
+        TableSummarizer::default().summarize("b", &[0xff, 0, 1], &mut binary);
+        assert!(binary.is_empty());
+
+        assert_eq!(summarize("t.csv", csv), None);
+        add_summarizer(|_: &str, data: &[u8], s: &mut BlobSummary| {
+            s.extra.insert("len".into(), data.len().to_string());
+        });
+        add_summarizer(|_: &str, _: &[u8], _: &mut BlobSummary| panic!("bad summarizer"));
+        assert_eq!(
+            summarize("t.csv", csv).unwrap().extra["len"],
+            csv.len().to_string()
+        );
+        clear_summarizers();
+        assert_eq!(summarize("t.csv", csv), None);
+    }
+}
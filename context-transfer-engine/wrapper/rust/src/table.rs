@@ -0,0 +1,358 @@
+//! A tag's Arrow and Parquet blobs as a DataFusion table (feature
+//! `datafusion`).
+//!
+//! `CteTableProvider::try_new` makes the blobs of a tag whose names match a
+//! regex one table, to register with `SessionContext::register_table` and
+//! query with SQL where the data already is, without staging it to files. A
+//! blob starting with Parquet's magic is read with `Tag::parquet_reader` (see
+//! `columnar`), any other as an Arrow IPC stream with
+//! `Tag::get_record_batches` (see `batches`). Every blob must have the table's
+//! schema: the first matching blob's unless `CteTableOptions::schema` gives
+//! one. The blobs are listed again at each scan, so the table sees blobs
+//! written since it was made.
+//!
+//! `CteTableOptions::attr_columns` adds a `Utf8` column per attribute key,
+//! holding each blob's value of it (null where a blob has none), as a
+//! partition column of a directory-partitioned dataset would. Filters on those
+//! columns alone (`=`, `<>`, `IN`, `IS [NOT] NULL`, and `AND`, `OR` and `NOT`
+//! of them) are pushed down: only the blobs they admit are read, and DataFusion
+//! doesn't check them again. Other filters are left to DataFusion. The blobs a
+//! scan admits are read when it is planned, one partition per blob.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, RecordBatch, RecordBatchReader, StringArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use async_trait::async_trait;
+use datafusion::catalog::{Session, TableProvider};
+use datafusion::common::ScalarValue;
+use datafusion::datasource::memory::MemorySourceConfig;
+use datafusion::error::DataFusionError;
+use datafusion::logical_expr::expr::InList;
+use datafusion::logical_expr::{
+    BinaryExpr, Expr, Operator, TableProviderFilterPushDown, TableType,
+};
+use datafusion::physical_plan::ExecutionPlan;
+
+use crate::events::exact;
+use crate::{Client, CteError, GetOptions, Tag};
+
+/// First bytes of a Parquet file.
+const PARQUET_MAGIC: &[u8] = b"PAR1";
+
+/// Settings for `CteTableProvider::try_new`.
+#[derive(Debug, Clone)]
+pub struct CteTableOptions {
+    /// Regex the names of the table's blobs match.
+    pub pattern: String,
+    /// Attribute keys to add as `Utf8` columns, after the data's.
+    pub attr_columns: Vec<String>,
+    /// Schema of the blobs' data; `None` to take the first blob's.
+    pub schema: Option<SchemaRef>,
+}
+
+impl Default for CteTableOptions {
+    fn default() -> Self {
+        Self {
+            pattern: ".*".into(),
+            attr_columns: Vec::new(),
+            schema: None,
+        }
+    }
+}
+
+/// A blob's values of the attribute columns, by column name.
+type AttrRow<'a> = HashMap<&'a str, Option<&'a str>>;
+
+/// The value `expr` has for a blob: `Some(None)` for null, `None` if it isn't
+/// a string literal or an attribute column.
+fn value<'a>(expr: &'a Expr, row: &AttrRow<'a>) -> Option<Option<&'a str>> {
+    match expr {
+        Expr::Column(column) => row.get(column.name.as_str()).copied(),
+        Expr::Literal(
+            ScalarValue::Utf8(s) | ScalarValue::LargeUtf8(s) | ScalarValue::Utf8View(s),
+        ) => Some(s.as_deref()),
+        _ => None,
+    }
+}
+
+/// Whether a blob with the attribute values `row` passes `expr`, in SQL's
+/// three-valued logic (`Some(None)` for unknown); `None` if `expr` can't be
+/// decided from attributes.
+fn admits(expr: &Expr, row: &AttrRow) -> Option<Option<bool>> {
+    match expr {
+        Expr::BinaryExpr(BinaryExpr { left, op, right }) => match op {
+            Operator::And => Some(match (admits(left, row)?, admits(right, row)?) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            }),
+            Operator::Or => Some(match (admits(left, row)?, admits(right, row)?) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            }),
+            Operator::Eq | Operator::NotEq => {
+                let (left, right) = (value(left, row)?, value(right, row)?);
+                Some(
+                    left.zip(right)
+                        .map(|(l, r)| (l == r) == (*op == Operator::Eq)),
+                )
+            }
+            _ => None,
+        },
+        Expr::Not(inner) => Some(admits(inner, row)?.map(|b| !b)),
+        Expr::IsNull(inner) => Some(Some(value(inner, row)?.is_none())),
+        Expr::IsNotNull(inner) => Some(Some(value(inner, row)?.is_some())),
+        Expr::InList(InList {
+            expr,
+            list,
+            negated,
+        }) => {
+            let item = value(expr, row)?;
+            let items = list
+                .iter()
+                .map(|e| value(e, row))
+                .collect::<Option<Vec<_>>>()?;
+            Some(match item {
+                None => None,
+                Some(v) if items.contains(&Some(v)) => Some(!negated),
+                Some(_) if items.contains(&None) => None,
+                Some(_) => Some(*negated),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// A tag's Arrow and Parquet blobs as a DataFusion table (see `table`).
+#[derive(Debug)]
+pub struct CteTableProvider {
+    tag: String,
+    options: CteTableOptions,
+    /// The data's schema followed by the attribute columns.
+    schema: SchemaRef,
+}
+
+impl CteTableProvider {
+    /// The table of the blobs of `tag` that `options` selects. Fails with
+    /// `InvalidArgument` if no blob matches and `options.schema` is `None`, or
+    /// an attribute column has the name of a data column.
+    pub fn try_new(tag: &str, options: CteTableOptions) -> Result<Self, CteError> {
+        let handle = Tag::try_new(tag)?;
+        let data = match &options.schema {
+            Some(schema) => schema.clone(),
+            None => {
+                let (_, first) = Client::blob_query(&exact(tag), &options.pattern, 1)
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| {
+                        CteError::InvalidArgument(format!(
+                            "no blob of tag '{}' matches '{}' to take a schema from",
+                            tag, options.pattern
+                        ))
+                    })?;
+                blob_schema(&handle, &first)?
+            }
+        };
+        let mut fields: Vec<Field> = data.fields().iter().map(|f| f.as_ref().clone()).collect();
+        for key in &options.attr_columns {
+            if fields.iter().any(|f| f.name() == key) {
+                return Err(CteError::InvalidArgument(format!(
+                    "attribute column '{}' has the name of another column",
+                    key
+                )));
+            }
+            fields.push(Field::new(key, DataType::Utf8, true));
+        }
+        Ok(Self {
+            tag: tag.to_string(),
+            options,
+            schema: Arc::new(Schema::new(fields)),
+        })
+    }
+
+    /// Whether filter `expr` is decided by the attribute columns alone.
+    fn pushable(&self, expr: &Expr) -> bool {
+        let row: AttrRow = self
+            .options
+            .attr_columns
+            .iter()
+            .map(|k| (k.as_str(), None))
+            .collect();
+        admits(expr, &row).is_some()
+    }
+
+    /// The batches of the blobs `filters` admit, one partition per blob, until
+    /// `limit` rows are read.
+    fn read(
+        &self,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> Result<Vec<Vec<RecordBatch>>, CteError> {
+        let filters: Vec<&Expr> = filters.iter().filter(|f| self.pushable(f)).collect();
+        let tag = Tag::try_new(&self.tag)?;
+        let mut partitions = Vec::new();
+        let mut rows = 0;
+        for (_, blob) in Client::blob_query(&exact(&self.tag), &self.options.pattern, 0) {
+            if limit.is_some_and(|l| rows >= l) {
+                break;
+            }
+            let attrs = tag.load_meta(&blob)?.unwrap_or_default().attrs;
+            let values: Vec<Option<&str>> = self
+                .options
+                .attr_columns
+                .iter()
+                .map(|k| attrs.get(k).map(String::as_str))
+                .collect();
+            let row: AttrRow = self
+                .options
+                .attr_columns
+                .iter()
+                .map(String::as_str)
+                .zip(values.iter().copied())
+                .collect();
+            if !filters.iter().all(|f| admits(f, &row) == Some(Some(true))) {
+                continue;
+            }
+            let mut partition = Vec::new();
+            for batch in blob_batches(&tag, &blob)? {
+                let mut columns = batch.columns().to_vec();
+                for value in &values {
+                    let column: ArrayRef =
+                        Arc::new(StringArray::from(vec![*value; batch.num_rows()]));
+                    columns.push(column);
+                }
+                rows += batch.num_rows();
+                partition.push(RecordBatch::try_new(self.schema.clone(), columns).map_err(
+                    |e| {
+                        CteError::InvalidArgument(format!(
+                            "blob '{}' doesn't have the table's schema: {}",
+                            blob, e
+                        ))
+                    },
+                )?);
+            }
+            partitions.push(partition);
+        }
+        if partitions.is_empty() {
+            partitions.push(Vec::new());
+        }
+        Ok(partitions)
+    }
+}
+
+fn is_parquet(tag: &Tag, blob: &str) -> Result<bool, CteError> {
+    let options = GetOptions {
+        size: Some(PARQUET_MAGIC.len() as u64),
+        ..Default::default()
+    };
+    Ok(tag.get(blob, &options)? == PARQUET_MAGIC)
+}
+
+fn blob_schema(tag: &Tag, blob: &str) -> Result<SchemaRef, CteError> {
+    if is_parquet(tag, blob)? {
+        return Ok(tag.parquet_reader(blob)?.schema());
+    }
+    tag.get_record_batches(blob)?
+        .first()
+        .map(RecordBatch::schema)
+        .ok_or_else(|| CteError::InvalidArgument(format!("blob '{}' has no record batches", blob)))
+}
+
+fn blob_batches(tag: &Tag, blob: &str) -> Result<Vec<RecordBatch>, CteError> {
+    if !is_parquet(tag, blob)? {
+        return tag.get_record_batches(blob);
+    }
+    tag.parquet_reader(blob)?
+        .collect::<Result<_, _>>()
+        .map_err(|e| CteError::InvalidArgument(format!("Parquet file '{}': {}", blob, e)))
+}
+
+#[async_trait]
+impl TableProvider for CteTableProvider {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Base
+    }
+
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> datafusion::error::Result<Vec<TableProviderFilterPushDown>> {
+        Ok(filters
+            .iter()
+            .map(|f| {
+                if self.pushable(f) {
+                    TableProviderFilterPushDown::Exact
+                } else {
+                    TableProviderFilterPushDown::Unsupported
+                }
+            })
+            .collect())
+    }
+
+    async fn scan(
+        &self,
+        _state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> datafusion::error::Result<Arc<dyn ExecutionPlan>> {
+        let partitions = self
+            .read(filters, limit)
+            .map_err(|e| DataFusionError::External(Box::new(e)))?;
+        let exec =
+            MemorySourceConfig::try_new_exec(&partitions, self.schema(), projection.cloned())?;
+        Ok(exec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::logical_expr::{col, lit};
+
+    #[test]
+    fn test_attr_filters() {
+        let row: AttrRow = [("run", Some("7")), ("site", None)].into_iter().collect();
+        let admitted = |e: Expr| admits(&e, &row);
+        assert_eq!(admitted(col("run").eq(lit("7"))), Some(Some(true)));
+        assert_eq!(admitted(col("run").not_eq(lit("7"))), Some(Some(false)));
+        assert_eq!(admitted(col("site").eq(lit("a"))), Some(None));
+        assert_eq!(admitted(col("site").is_null()), Some(Some(true)));
+        assert_eq!(
+            admitted(col("run").in_list(vec![lit("6"), lit("7")], false)),
+            Some(Some(true))
+        );
+        assert_eq!(
+            admitted(col("run").in_list(vec![lit("6")], true)),
+            Some(Some(true))
+        );
+        // Unknown OR true is true; unknown AND true is unknown.
+        assert_eq!(
+            admitted(col("site").eq(lit("a")).or(col("run").eq(lit("7")))),
+            Some(Some(true))
+        );
+        assert_eq!(
+            admitted(col("site").eq(lit("a")).and(col("run").eq(lit("7")))),
+            Some(None)
+        );
+        // Data columns and other literals aren't decided here.
+        assert_eq!(admitted(col("energy").eq(lit("7"))), None);
+        assert_eq!(admitted(col("run").eq(lit(7))), None);
+        assert_eq!(
+            admitted(col("run").eq(lit("7")).and(col("energy").is_null())),
+            None
+        );
+    }
+}
@@ -0,0 +1,144 @@
+//! Cached tag handles.
+//!
+//! `Tag::new` asks the runtime to get or create the tag every time, which
+//! services that open a tag per request pay on each one. `Tag::open_cached`
+//! shares one handle per name instead: the first open of a name goes to the
+//! runtime and later ones are a hash lookup, until the name is evicted (the
+//! least recently opened of `TAG_CACHE_LEN` names goes first) or invalidated.
+//! Deleting a tag through this process drops its handle, and so do
+//! `enable_access_log`, `enable_change_log` and `enable_versioning` on any
+//! handle of it, since handles remember those settings; an open racing with
+//! either doesn't cache what it opened. A tag deleted by another client stays
+//! cached, and its handle then acts as one kept across the delete would.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::{CteError, Event, Tag};
+
+/// Tag names cached at once.
+const TAG_CACHE_LEN: usize = 1024;
+
+struct Cache<T> {
+    /// Handle and last use of each cached name.
+    tags: HashMap<String, (Arc<T>, u64)>,
+    /// Ticks at every open, for last use.
+    clock: u64,
+    /// Bumped by every invalidation, so an open that overlapped one doesn't
+    /// cache.
+    epoch: u64,
+}
+
+impl<T> Cache<T> {
+    fn new() -> Self {
+        Self {
+            tags: HashMap::new(),
+            clock: 0,
+            epoch: 0,
+        }
+    }
+
+    fn get(&mut self, name: &str) -> Option<Arc<T>> {
+        self.clock += 1;
+        let (tag, used) = self.tags.get_mut(name)?;
+        *used = self.clock;
+        Some(Arc::clone(tag))
+    }
+
+    /// Cache `tag`, opened at `epoch`, unless invalidated since; returns the
+    /// handle to use, which is an earlier one if another open won the race.
+    fn insert(&mut self, name: &str, tag: Arc<T>, epoch: u64) -> Arc<T> {
+        if epoch != self.epoch {
+            return tag;
+        }
+        if let Some(cached) = self.get(name) {
+            return cached;
+        }
+        if self.tags.len() >= TAG_CACHE_LEN {
+            let oldest = self
+                .tags
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(name, _)| name.clone());
+            if let Some(oldest) = oldest {
+                self.tags.remove(&oldest);
+            }
+        }
+        self.tags
+            .insert(name.to_string(), (Arc::clone(&tag), self.clock));
+        tag
+    }
+
+    fn forget(&mut self, name: &str) {
+        self.epoch += 1;
+        self.tags.remove(name);
+    }
+}
+
+static CACHE: Mutex<Option<Cache<Tag>>> = Mutex::new(None);
+
+fn with_cache<R>(f: impl FnOnce(&mut Cache<Tag>) -> R) -> R {
+    let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    f(cache.get_or_insert_with(Cache::new))
+}
+
+/// Drop the cached handle of `name`, if any.
+pub(crate) fn forget(name: &str) {
+    with_cache(|cache| cache.forget(name));
+}
+
+/// Drop the handles `event` makes stale. Called for every local event.
+pub(crate) fn observe(event: &Event) {
+    if let Event::TagDeleted { tag } = event {
+        forget(tag);
+    }
+}
+
+impl Tag {
+    /// A shared handle on the tag `name`, created if missing, from the handle
+    /// cache (see `tagcache`); fails like `try_new`.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(tag = name), err)
+    )]
+    pub fn open_cached(name: &str) -> Result<Arc<Tag>, CteError> {
+        let epoch = match with_cache(|cache| cache.get(name).ok_or(cache.epoch)) {
+            Ok(tag) => return Ok(tag),
+            Err(epoch) => epoch,
+        };
+        let tag = Arc::new(Tag::try_new(name)?);
+        Ok(with_cache(|cache| cache.insert(name, tag, epoch)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tag_cache() {
+        let mut cache = Cache::<u32>::new();
+        // Stand-ins for handles, numbered by open.
+        let open = Arc::new;
+        let a = cache.insert("a", open(1), 0);
+        assert!(Arc::ptr_eq(&cache.get("a").unwrap(), &a));
+        // Losing a race returns the handle already cached.
+        let again = cache.insert("a", open(2), 0);
+        assert!(Arc::ptr_eq(&again, &a));
+
+        // An open that overlapped an invalidation isn't cached.
+        cache.forget("a");
+        assert!(cache.get("a").is_none());
+        cache.insert("a", open(3), 0);
+        assert!(cache.get("a").is_none());
+
+        for i in 0..TAG_CACHE_LEN as u32 {
+            cache.insert(&format!("t{}", i), open(i), cache.epoch);
+        }
+        cache.get("t0");
+        cache.insert("new", open(0), cache.epoch);
+        assert_eq!(cache.tags.len(), TAG_CACHE_LEN);
+        assert!(cache.get("t0").is_some(), "recently used names stay");
+        assert!(cache.get("t1").is_none(), "the least recently used goes");
+    }
+}
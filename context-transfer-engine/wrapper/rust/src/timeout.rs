@@ -0,0 +1,162 @@
+//! Deadlines for blocking operations.
+//!
+//! `Client::with_op_options` runs a closure under a deadline. Every runtime task
+//! the shim waits on for that closure, on the calling thread, is waited on only
+//! until the deadline (through the `op_timeout` hook). Past it nothing more is
+//! submitted, calls return at once with empty results, and the closure's value
+//! is replaced by `CteError::Timeout`, so a service can fail over instead of
+//! hanging on a stuck runtime. Blob reads and writes inside the closure fail
+//! with `Timeout` themselves, so an abandoned get is never mistaken for zeros
+//! and an abandoned put never bumps the blob's generation or op log. Scopes nest, and an inner scope can only shorten
+//! the deadline.
+//!
+//! A timed-out task is abandoned, not cancelled: it can still complete in the
+//! runtime (a put may land after all), and its task and shared-memory buffer are
+//! left to the runtime rather than freed under it. Listing a tag's blobs and
+//! tag or blob queries go through runtime calls that take no timeout, and
+//! threads the closure spawns don't inherit its deadline.
+
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use crate::{diag, Client, CteError};
+
+/// Settings for `Client::with_op_options`.
+#[derive(Debug, Clone)]
+pub struct OpOptions {
+    /// Time the whole closure may spend waiting on the runtime.
+    pub timeout: Duration,
+}
+
+impl Default for OpOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+thread_local! {
+    /// Deadline of the innermost `with_op_options` scope on this thread.
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+    /// `OpOptions::timeout` of that scope.
+    static TIMEOUT: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+    /// Whether the shim gave up on a task since the scope began.
+    static TIMED_OUT: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Puts back the enclosing scope's state, also when the closure panics.
+struct Restore {
+    deadline: Option<Instant>,
+    timeout: Duration,
+    timed_out: bool,
+}
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        DEADLINE.set(self.deadline);
+        TIMEOUT.set(self.timeout);
+        TIMED_OUT.set(self.timed_out);
+    }
+}
+
+/// Shim hook: seconds left until the deadline, 0 for none, or -1 once it has
+/// passed.
+pub(crate) fn op_timeout() -> f32 {
+    let Some(deadline) = DEADLINE.get() else {
+        return 0.0;
+    };
+    match deadline.checked_duration_since(Instant::now()) {
+        Some(left) if !left.is_zero() => left.as_secs_f32().max(f32::MIN_POSITIVE),
+        _ => -1.0,
+    }
+}
+
+/// Shim hook: a wait ran out and its task was abandoned.
+pub(crate) fn op_timed_out() {
+    TIMED_OUT.set(true);
+}
+
+/// Whether a wait in the current scope ran out, after which results are empty
+/// rather than real.
+pub(crate) fn timed_out() -> bool {
+    TIMED_OUT.get()
+}
+
+/// `CteError::Timeout` if a wait in the current scope ran out, for readers
+/// that would otherwise decode an empty result as real data.
+pub(crate) fn check() -> Result<(), CteError> {
+    match timed_out() {
+        true => Err(CteError::Timeout(TIMEOUT.get())),
+        false => Ok(()),
+    }
+}
+
+impl Client {
+    /// Run `f` with every runtime wait on this thread bounded by
+    /// `options.timeout` in total (see `timeout`). Returns `CteError::Timeout`
+    /// if any wait ran out, whatever `f` returned.
+    pub fn with_op_options<T>(options: &OpOptions, f: impl FnOnce() -> T) -> Result<T, CteError> {
+        let deadline = Instant::now().checked_add(options.timeout);
+        let _restore = Restore {
+            deadline: DEADLINE.get(),
+            timeout: TIMEOUT.replace(options.timeout),
+            timed_out: TIMED_OUT.replace(false),
+        };
+        let deadline = match (DEADLINE.get(), deadline) {
+            (Some(outer), Some(inner)) => Some(outer.min(inner)),
+            (outer, inner) => inner.or(outer),
+        };
+        DEADLINE.set(deadline);
+        let value = f();
+        if TIMED_OUT.get() {
+            let e = CteError::Timeout(options.timeout);
+            diag::note_error(&e);
+            return Err(e);
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deadline_scopes() {
+        assert_eq!(op_timeout(), 0.0);
+        let long = OpOptions {
+            timeout: Duration::from_secs(60),
+        };
+        let short = OpOptions {
+            timeout: Duration::from_millis(1),
+        };
+        let result = Client::with_op_options(&long, || {
+            let limit = op_timeout();
+            assert!(limit > 59.0 && limit <= 60.0);
+            // An inner scope shortens the deadline and reports its own expiry.
+            let inner = Client::with_op_options(&short, || {
+                std::thread::sleep(Duration::from_millis(5));
+                assert_eq!(op_timeout(), -1.0);
+                op_timed_out();
+                assert!(timed_out());
+                assert!(matches!(check(), Err(CteError::Timeout(t)) if t == short.timeout));
+                7
+            });
+            assert!(matches!(inner, Err(CteError::Timeout(t)) if t == short.timeout));
+            // Nothing ran out in the outer scope itself.
+            assert!(op_timeout() > 59.0);
+            // A longer inner scope can't extend it.
+            let longer = OpOptions {
+                timeout: Duration::from_secs(600),
+            };
+            Client::with_op_options(&longer, || assert!(op_timeout() <= 60.0)).unwrap();
+            1
+        });
+        assert_eq!(result.unwrap(), 1);
+        assert_eq!(op_timeout(), 0.0);
+        // Outside any scope a timeout has no one to report to.
+        op_timed_out();
+        assert_eq!(Client::with_op_options(&long, || 2).unwrap(), 2);
+    }
+}
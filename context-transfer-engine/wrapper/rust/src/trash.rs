@@ -0,0 +1,280 @@
+//! Soft delete: a recycle bin for blobs and tags.
+//!
+//! While the trash is enabled, `Tag::del_blob` and `Client::del_tag` move blobs
+//! into a per-tag trash tag (`.cte/trash/<tag>`) instead of deleting them. Each
+//! entry is named `<deleted-at-ms>.<seq>/<blob>`, so a blob deleted several times
+//! keeps one entry per deletion, even within a millisecond. Entries older than
+//! the retention window are purged once per `del_tag` or bulk delete of the same
+//! tag, and by `purge_expired` and `Client::gc`.
+//!
+//! Moving a blob copies its data, so soft deletes cost a read and a write of the
+//! whole blob.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::alert::{self, Alert};
+use crate::{events, ChangeKind, Client, CteError, Event, Tag};
+
+const TRASH_PREFIX: &str = ".cte/trash/";
+
+static RETENTION: RwLock<Option<Duration>> = RwLock::new(None);
+
+/// Tells apart entries of the same blob deleted in the same millisecond.
+static SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// A soft-deleted blob.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrashEntry {
+    pub tag: String,
+    pub blob: String,
+    pub deleted_at: SystemTime,
+    pub size: u64,
+    /// Name of the entry inside the trash tag.
+    entry: String,
+    seq: u64,
+}
+
+pub(crate) fn enabled() -> bool {
+    retention().is_some()
+}
+
+pub(crate) fn retention() -> Option<Duration> {
+    *RETENTION.read().unwrap_or_else(|e| e.into_inner())
+}
+
+pub(crate) fn is_trash_tag(name: &str) -> bool {
+    name.starts_with(TRASH_PREFIX)
+}
+
+fn trash_tag(tag: &str) -> Result<Tag, CteError> {
+    Tag::try_new(&format!("{}{}", TRASH_PREFIX, tag))
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+fn entry_name(blob: &str) -> String {
+    format!(
+        "{}.{}/{}",
+        now_ms(),
+        SEQ.fetch_add(1, Ordering::Relaxed),
+        blob
+    )
+}
+
+/// Split a trash entry name into deletion time, sequence number and original
+/// blob name. Entries named before the sequence number was added have seq 0.
+fn parse_entry(entry: &str) -> Option<(u64, u64, &str)> {
+    let (stamp, blob) = entry.split_once('/')?;
+    let (ms, seq) = stamp.split_once('.').unwrap_or((stamp, "0"));
+    Some((ms.parse().ok()?, seq.parse().ok()?, blob))
+}
+
+/// Move every blob of `tag` into its trash, ahead of deleting the tag itself.
+pub(crate) fn trash_tag_blobs(tag: &str) -> Result<(), CteError> {
+    let src = Tag::try_new(tag)?;
+    for blob in src.blob_names() {
+        src.soft_del_blob(&blob)?;
+    }
+    purge_expired_of(tag);
+    Ok(())
+}
+
+/// Purge `tag`'s trash entries past the retention window, if the trash is on.
+pub(crate) fn purge_expired_of(tag: &str) {
+    if is_trash_tag(tag) {
+        return;
+    }
+    if let Some(retention) = retention() {
+        purge_tag(tag, Some(retention), None, false);
+    }
+}
+
+impl Tag {
+    /// Move `name` into this tag's trash.
+    pub(crate) fn soft_del_blob(&self, name: &str) -> Result<(), CteError> {
+        if self.get_blob_size(name) == 0 && self.load_meta(name)?.is_none() {
+            return Err(CteError::NotFound {
+                blob: name.to_string(),
+            });
+        }
+        let trash = trash_tag(&self.name)?;
+        self.copy_blob_raw(name, &trash, &entry_name(name))?;
+        self.hard_del_blob(name);
+        Ok(())
+    }
+}
+
+/// Trash entries of `tag` older than `older_than` (all if `None`), optionally
+/// only those for `blob`, as `(entry, size)`. Deleted unless `dry_run`, raising
+/// an `Alert::Purged` for each entry purged for its age.
+pub(crate) fn purge_tag(
+    tag: &str,
+    older_than: Option<Duration>,
+    blob: Option<&str>,
+    dry_run: bool,
+) -> Vec<(String, u64)> {
+    let cutoff = older_than.map(|d| now_ms().saturating_sub(d.as_millis() as u64));
+    // A trash the runtime can't open has nothing to purge.
+    let Ok(trash) = trash_tag(tag) else {
+        return Vec::new();
+    };
+    let mut purged = Vec::new();
+    for entry in trash.blob_names() {
+        let Some((ms, _, name)) = parse_entry(&entry) else {
+            continue;
+        };
+        if blob.is_some_and(|b| b != name) || cutoff.is_some_and(|c| ms >= c) {
+            continue;
+        }
+        let size = trash.get_blob_size(&entry);
+        if dry_run {
+            purged.push((entry, size));
+        } else if trash.hard_del_blob(&entry) {
+            if older_than.is_some() {
+                alert::raise(Alert::Purged {
+                    tag: tag.to_string(),
+                    blob: name.to_string(),
+                });
+            }
+            purged.push((entry, size));
+        }
+    }
+    purged
+}
+
+/// Original names of tags that have a trash.
+pub(crate) fn trashed_tags() -> Vec<String> {
+    crate::ffi::client_tag_query(&format!("{}.*", events::exact(TRASH_PREFIX)), 0)
+        .iter()
+        .map(|s| s.to_string_lossy().into_owned())
+        .filter_map(|s| s.strip_prefix(TRASH_PREFIX).map(str::to_string))
+        .collect()
+}
+
+impl Client {
+    /// Turn on soft delete, keeping deleted blobs for `retention`.
+    pub fn enable_trash(retention: Duration) {
+        *RETENTION.write().unwrap_or_else(|e| e.into_inner()) = Some(retention);
+    }
+
+    /// Turn soft delete off. Existing trash entries are kept until purged.
+    pub fn disable_trash() {
+        *RETENTION.write().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    /// Soft-deleted blobs of `tag`, oldest first.
+    pub fn list_trash(tag: &str) -> Vec<TrashEntry> {
+        let Ok(trash) = trash_tag(tag) else {
+            return Vec::new();
+        };
+        let mut out: Vec<TrashEntry> = trash
+            .blob_names()
+            .into_iter()
+            .filter_map(|entry| {
+                let (ms, seq, blob) = parse_entry(&entry)?;
+                Some(TrashEntry {
+                    tag: tag.to_string(),
+                    blob: blob.to_string(),
+                    deleted_at: UNIX_EPOCH + Duration::from_millis(ms),
+                    size: trash.get_blob_size(&entry),
+                    entry: entry.clone(),
+                    seq,
+                })
+            })
+            .collect();
+        out.sort_by_key(|e| (e.deleted_at, e.seq));
+        out
+    }
+
+    /// Restore the most recently deleted copy of `blob` into `tag`, overwriting
+    /// any blob that has since been written under the same name.
+    pub fn restore(tag: &str, blob: &str) -> Result<(), CteError> {
+        let entry = Self::list_trash(tag)
+            .into_iter()
+            .rev()
+            .find(|e| e.blob == blob)
+            .ok_or_else(|| CteError::NotFound {
+                blob: blob.to_string(),
+            })?;
+        restore_entry(&entry)
+    }
+
+    /// Restore every blob in `tag`'s trash (newest copy of each). Returns the
+    /// number of blobs restored.
+    pub fn restore_tag(tag: &str) -> Result<usize, CteError> {
+        let mut newest = std::collections::BTreeMap::new();
+        for entry in Self::list_trash(tag) {
+            newest.insert(entry.blob.clone(), entry);
+        }
+        for entry in newest.values() {
+            restore_entry(entry)?;
+        }
+        Ok(newest.len())
+    }
+
+    /// Permanently delete trash entries for `tag`, or only those of `blob`.
+    pub fn purge(tag: &str, blob: Option<&str>) -> usize {
+        purge_tag(tag, None, blob, false).len()
+    }
+
+    /// Permanently delete trash entries past the retention window in every tag.
+    pub fn purge_expired() -> usize {
+        let Some(retention) = retention() else {
+            return 0;
+        };
+        trashed_tags()
+            .iter()
+            .map(|t| purge_tag(t, Some(retention), None, false).len())
+            .sum()
+    }
+}
+
+fn restore_entry(entry: &TrashEntry) -> Result<(), CteError> {
+    let trash = trash_tag(&entry.tag)?;
+    let dst = Tag::try_new(&entry.tag)?;
+    let size = trash.copy_blob_raw(&entry.entry, &dst, &entry.blob)?;
+    trash.hard_del_blob(&entry.entry);
+    dst.record_change(&entry.blob, ChangeKind::Put { offset: 0, size });
+    events::emit(Event::BlobPut {
+        tag: entry.tag.clone(),
+        blob: entry.blob.clone(),
+        offset: 0,
+        size,
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_entry() {
+        assert_eq!(
+            parse_entry("1700000000000.3/run42/out.bin"),
+            Some((1700000000000, 3, "run42/out.bin"))
+        );
+        assert_eq!(
+            parse_entry("1700000000000/run42/out.bin"),
+            Some((1700000000000, 0, "run42/out.bin"))
+        );
+        assert_eq!(parse_entry("not-a-time/x"), None);
+        assert_eq!(parse_entry("1700000000000.x/x"), None);
+        assert_eq!(parse_entry("noslash"), None);
+    }
+
+    #[test]
+    fn test_entry_names_are_unique() {
+        let (a, b) = (entry_name("x"), entry_name("x"));
+        assert_ne!(a, b);
+        let (ms_a, seq_a, _) = parse_entry(&a).unwrap();
+        let (ms_b, seq_b, _) = parse_entry(&b).unwrap();
+        assert!((ms_a, seq_a) < (ms_b, seq_b));
+    }
+}
@@ -0,0 +1,272 @@
+//! Blob time-to-live and expiration.
+//!
+//! A blob given a TTL (`PutOptions::ttl` or `Tag::set_blob_ttl`) records its
+//! expiry time in its metadata sidecar. Once that passes the blob is treated as
+//! gone: `Tag::get` and `Tag::stat_blob` delete it on access and report it
+//! missing, and `Client::expire` (run by `Client::gc`, or periodically by the
+//! task `Client::start_expiry` returns) deletes expired blobs everywhere.
+//! Deletion goes through `Tag::del_blob`, so expired blobs land in the trash
+//! when it is enabled.
+//!
+//! `get_blob` and the other legacy reads don't load metadata and still return
+//! an expired blob's data until it is collected. Expiry uses the client's clock.
+//!
+//! With `set_expiry_warning`, the same scans give owners notice first: a blob
+//! due to expire within the lead time gets one `Event::BlobExpiring`, and a
+//! POST to the webhook if one is set, so it can be exported or its TTL
+//! extended in time. Scans run only as often as `Client::start_expiry`'s
+//! interval, so keep that well under the lead time. A blob whose TTL is
+//! changed is warned about again for its new expiry.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Mutex, RwLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::alert::{self, Alert};
+use crate::events::{self, Event};
+use crate::ffi_c::json_string;
+use crate::meta::{meta_lock, BlobMeta};
+use crate::webhook::Endpoint;
+use crate::{BulkOptions, BulkReport, Client, CteError, Tag};
+
+/// Warning lead time in milliseconds; 0 turns warnings off.
+static WARN_MS: AtomicU64 = AtomicU64::new(0);
+static WEBHOOK: RwLock<Option<Endpoint>> = RwLock::new(None);
+/// Expiry time each `(tag, blob)` was last warned about.
+static WARNED: Mutex<Option<HashMap<(String, String), u64>>> = Mutex::new(None);
+
+/// Warn about blobs `lead` before they expire (see `ttl`), also POSTing each
+/// warning as JSON to `webhook` (an `http://` URL) if given.
+pub fn set_expiry_warning(lead: Duration, webhook: Option<&str>) -> Result<(), CteError> {
+    let endpoint = webhook.map(Endpoint::parse).transpose()?;
+    *WEBHOOK.write().unwrap_or_else(|e| e.into_inner()) = endpoint;
+    WARN_MS.store((lead.as_millis() as u64).max(1), Ordering::Relaxed);
+    Ok(())
+}
+
+/// Stop warning about expiring blobs.
+pub fn clear_expiry_warning() {
+    WARN_MS.store(0, Ordering::Relaxed);
+    *WEBHOOK.write().unwrap_or_else(|e| e.into_inner()) = None;
+    *WARNED.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// The webhook body for a warning.
+fn warning_json(tag: &str, blob: &str, expires_ms: u64) -> String {
+    format!(
+        "{{\"event\":\"blob_expiring\",\"tag\":{},\"blob\":{},\"expires_ms\":{}}}",
+        json_string(tag),
+        json_string(blob),
+        expires_ms
+    )
+}
+
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Expiry time `ttl` from now, in milliseconds since the Unix epoch.
+pub(crate) fn expiry_after(ttl: Duration) -> u64 {
+    now_ms().saturating_add(ttl.as_millis() as u64).max(1)
+}
+
+impl BlobMeta {
+    /// True if the blob has a TTL that has passed.
+    pub(crate) fn is_expired(&self) -> bool {
+        self.expires_ms != 0 && self.expires_ms <= now_ms()
+    }
+
+    /// The blob's expiry time, if it has a TTL.
+    pub(crate) fn expires_at(&self) -> Option<SystemTime> {
+        (self.expires_ms != 0).then(|| UNIX_EPOCH + Duration::from_millis(self.expires_ms))
+    }
+
+    /// True if the blob expires within `lead_ms` of `now_ms`, but hasn't yet.
+    fn expires_within(&self, now_ms: u64, lead_ms: u64) -> bool {
+        self.expires_ms > now_ms && self.expires_ms <= now_ms.saturating_add(lead_ms)
+    }
+}
+
+impl Tag {
+    /// Expire `name` `ttl` from now, replacing any earlier TTL.
+    pub fn set_blob_ttl(&self, name: &str, ttl: Duration) -> Result<(), CteError> {
+        self.update_expiry(name, expiry_after(ttl))
+    }
+
+    /// Remove `name`'s TTL so it no longer expires.
+    pub fn clear_blob_ttl(&self, name: &str) -> Result<(), CteError> {
+        self.update_expiry(name, 0)
+    }
+
+    fn update_expiry(&self, name: &str, expires_ms: u64) -> Result<(), CteError> {
+        let _guard = meta_lock();
+        let meta = self.load_meta(name)?;
+        let exists = self.get_blob_size(name) > 0 || meta.is_some();
+        let mut meta = meta.unwrap_or_default();
+        if !exists || meta.is_expired() {
+            return Err(CteError::NotFound {
+                blob: name.to_string(),
+            });
+        }
+        meta.expires_ms = expires_ms;
+        self.store_meta(name, &meta)
+    }
+
+    /// Delete `name` if `meta` says it has expired. Returns whether it had.
+    pub(crate) fn expire_if_due(&self, name: &str, meta: Option<&BlobMeta>) -> bool {
+        if !meta.is_some_and(BlobMeta::is_expired) {
+            return false;
+        }
+        if self.del_blob(name) {
+            self.raise_expired(name);
+        }
+        true
+    }
+
+    /// Warn that `name` is about to expire, once per expiry time, if warnings
+    /// are on and it is due within the lead time.
+    fn warn_if_expiring(&self, name: &str, meta: &BlobMeta) {
+        let lead = WARN_MS.load(Ordering::Relaxed);
+        if lead == 0 || !meta.expires_within(now_ms(), lead) {
+            return;
+        }
+        {
+            let mut warned = WARNED.lock().unwrap_or_else(|e| e.into_inner());
+            let warned = warned.get_or_insert_with(HashMap::new);
+            // Forget blobs that have expired since; they won't be due again.
+            let now = now_ms();
+            warned.retain(|_, expires_ms| *expires_ms > now);
+            let key = (self.name().to_string(), name.to_string());
+            if warned.insert(key, meta.expires_ms) == Some(meta.expires_ms) {
+                return;
+            }
+        }
+        let expires_at = UNIX_EPOCH + Duration::from_millis(meta.expires_ms);
+        events::emit(Event::BlobExpiring {
+            tag: self.name().to_string(),
+            blob: name.to_string(),
+            expires_at,
+        });
+        alert::raise(Alert::BlobExpiring {
+            tag: self.name().to_string(),
+            blob: name.to_string(),
+            expires_at,
+        });
+        let endpoint = WEBHOOK.read().unwrap_or_else(|e| e.into_inner()).clone();
+        if let Some(endpoint) = endpoint {
+            let body = warning_json(self.name(), name, meta.expires_ms);
+            // Off the scan's thread; an undelivered warning is dropped.
+            std::thread::spawn(move || endpoint.post(&[], &body));
+        }
+    }
+
+    fn raise_expired(&self, name: &str) {
+        alert::raise(Alert::Expired {
+            tag: self.name().to_string(),
+            blob: name.to_string(),
+        });
+    }
+
+    /// Delete this tag's expired blobs.
+    pub fn expire_blobs(&self, options: &BulkOptions) -> BulkReport {
+        let mut report = BulkReport::new(options);
+        for name in self.blob_names() {
+            let Ok(Some(meta)) = self.load_meta(&name) else {
+                continue;
+            };
+            if !meta.is_expired() {
+                self.warn_if_expiring(&name, &meta);
+                continue;
+            }
+            let size = self.get_blob_size(&name);
+            if options.dry_run {
+                report.push(self.name(), &name, size);
+            } else if self.del_blob(&name) {
+                self.raise_expired(&name);
+                report.push(self.name(), &name, size);
+            }
+        }
+        report
+    }
+}
+
+/// Background expiry started by `Client::start_expiry`; stops when dropped.
+pub struct ExpiryTask {
+    stop: Option<Sender<()>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Drop for ExpiryTask {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Client {
+    /// Delete expired blobs in every tag.
+    pub fn expire(options: &BulkOptions) -> BulkReport {
+        let mut report = BulkReport::new(options);
+        for tag_name in Self::tag_query(".*", 0) {
+            report.merge(Tag::new(&tag_name).expire_blobs(options));
+        }
+        report
+    }
+
+    /// Run `Client::expire` every `interval` on a background thread until the
+    /// returned task is dropped.
+    pub fn start_expiry(interval: Duration) -> ExpiryTask {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let worker = std::thread::spawn(move || loop {
+            match stopped.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => {
+                    Client::expire(&BulkOptions::default());
+                }
+                _ => return,
+            }
+        });
+        ExpiryTask {
+            stop: Some(stop),
+            worker: Some(worker),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expiry() {
+        let mut meta = BlobMeta::default();
+        assert!(!meta.is_expired() && meta.expires_at().is_none());
+        meta.expires_ms = expiry_after(Duration::from_secs(3600));
+        assert!(!meta.is_expired());
+        meta.expires_ms = now_ms() - 1;
+        assert!(meta.is_expired());
+    }
+
+    #[test]
+    fn test_expiry_warning() {
+        let meta = BlobMeta {
+            expires_ms: 10_000,
+            ..Default::default()
+        };
+        assert!(meta.expires_within(9_000, 1_000));
+        assert!(!meta.expires_within(8_999, 1_000));
+        assert!(!meta.expires_within(10_000, 1_000));
+        assert!(!BlobMeta::default().expires_within(9_000, 1_000));
+        assert_eq!(
+            warning_json("t", "a\"b", 10_000),
+            r#"{"event":"blob_expiring","tag":"t","blob":"a\"b","expires_ms":10000}"#
+        );
+        assert!(set_expiry_warning(Duration::from_secs(60), Some("ftp://x")).is_err());
+    }
+}
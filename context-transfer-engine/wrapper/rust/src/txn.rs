@@ -0,0 +1,330 @@
+//! Multi-blob transactions.
+//!
+//! `Tag::transaction` collects puts and deletes and applies them together on
+//! `Txn::commit`, so a checkpoint's data blobs and its manifest appear at once.
+//! Puts are staged as they are made, in reserved `.cte/txn/<id>/<n>` blobs of the
+//! same tag with the put's compression, encryption, checksum and TTL already
+//! applied; commit checks every generation precondition, writes a journal
+//! (`.cte/txn/<id>`) listing the operations, moves the staged blobs into place,
+//! marks every blob it covers with the transaction's ID, moves the staged blobs
+//! into place, performs the deletes and removes the journal. Nothing is applied
+//! if a precondition fails, and dropping an uncommitted `Txn` discards its
+//! staging.
+//!
+//! Once the journal is written the transaction is committed, even if applying
+//! it fails partway or the process crashes. `Tag::get`, `Tag::stat_blob` and
+//! `Tag::stat_blobs` that find a blob still marked finish the transaction from
+//! its journal before answering, so they never return a partial commit; a
+//! failure doing so is their error. Commits also exclude those readers in this
+//! process for their duration. The legacy `get_blob` calls don't look at the
+//! marks, and readers in other processes can still see a blob briefly missing
+//! while a commit moves it into place. `Tag::recover_transactions` (run for
+//! every tag by `Client::gc`) finishes every pending journal of a tag; staging
+//! of transactions that were never committed stays until its tag is deleted.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::meta::{meta_lock, read_str, FieldReader, FieldWriter, META_PREFIX};
+use crate::oplog::writer_id;
+use crate::{ffi, ffi_guard, CteError, PutOptions, Tag};
+
+const TXN_PREFIX: &str = ".cte/txn/";
+
+const FIELD_PUT: u8 = 1;
+const FIELD_DELETE: u8 = 2;
+
+static COMMIT_LOCK: RwLock<()> = RwLock::new(());
+static NEXT_TXN: AtomicU64 = AtomicU64::new(0);
+
+#[cfg(test)]
+thread_local! {
+    /// Index of the operation `apply` fails at on this thread, set by tests.
+    static FAIL_AT: std::cell::Cell<Option<usize>> = const { std::cell::Cell::new(None) };
+}
+
+/// Make `apply` on this thread fail before operation `op`, or not at all.
+#[cfg(test)]
+pub(crate) fn fail_apply_at(op: Option<usize>) {
+    FAIL_AT.set(op);
+}
+
+/// Held by readers that must not observe a commit in progress.
+pub(crate) fn read_guard() -> RwLockReadGuard<'static, ()> {
+    COMMIT_LOCK.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Held by commits, and by anything else applied to several blobs at once.
+pub(crate) fn write_guard() -> RwLockWriteGuard<'static, ()> {
+    COMMIT_LOCK.write().unwrap_or_else(|e| e.into_inner())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TxnOp {
+    Put {
+        name: String,
+        if_generation_match: Option<u64>,
+    },
+    Delete {
+        name: String,
+    },
+}
+
+impl TxnOp {
+    fn name(&self) -> &str {
+        match self {
+            TxnOp::Put { name, .. } | TxnOp::Delete { name } => name,
+        }
+    }
+}
+
+fn encode_journal(ops: &[TxnOp]) -> Vec<u8> {
+    let mut w = FieldWriter::default();
+    for op in ops {
+        match op {
+            TxnOp::Put { name, .. } => w.bytes(FIELD_PUT, name.as_bytes()),
+            TxnOp::Delete { name } => w.bytes(FIELD_DELETE, name.as_bytes()),
+        }
+    }
+    w.finish()
+}
+
+fn decode_journal(buf: &[u8]) -> Result<Vec<TxnOp>, String> {
+    let mut ops = Vec::new();
+    for (id, value) in FieldReader::new(buf)? {
+        let name = read_str(value)?;
+        match id {
+            FIELD_PUT => ops.push(TxnOp::Put {
+                name,
+                if_generation_match: None,
+            }),
+            FIELD_DELETE => ops.push(TxnOp::Delete { name }),
+            _ => {}
+        }
+    }
+    Ok(ops)
+}
+
+fn staging_name(id: &str, index: usize) -> String {
+    format!("{}{}/{}", TXN_PREFIX, id, index)
+}
+
+/// Puts and deletes against one tag, applied together by `commit`.
+pub struct Txn<'a> {
+    tag: &'a Tag,
+    id: String,
+    ops: Vec<TxnOp>,
+}
+
+impl Txn<'_> {
+    /// Stage a write of `data` to `name`. `options.offset` must be 0; a
+    /// generation precondition is checked at commit.
+    pub fn put(&mut self, name: &str, data: &[u8], options: &PutOptions) -> Result<(), CteError> {
+        if options.offset != 0 {
+            return Err(CteError::InvalidArgument(
+                "transactional puts must write the whole blob at offset 0".into(),
+            ));
+        }
+        let staged = PutOptions {
+            if_generation_match: None,
+            ..options.clone()
+        };
+        self.tag
+            .put(&staging_name(&self.id, self.ops.len()), data, &staged)?;
+        self.ops.push(TxnOp::Put {
+            name: name.to_string(),
+            if_generation_match: options.if_generation_match,
+        });
+        Ok(())
+    }
+
+    /// Stage a deletion of `name`.
+    pub fn delete(&mut self, name: &str) {
+        self.ops.push(TxnOp::Delete {
+            name: name.to_string(),
+        });
+    }
+
+    /// Apply every staged operation, in order. On a failed precondition nothing
+    /// has been applied; on a failure applying them, some may have been, and the
+    /// next reader of a blob the rest cover, or `Tag::recover_transactions`,
+    /// rolls them forward.
+    pub fn commit(mut self) -> Result<(), CteError> {
+        let _commit = write_guard();
+        for op in &self.ops {
+            if let TxnOp::Put {
+                name,
+                if_generation_match: Some(expected),
+            } = op
+            {
+                let actual = self.tag.load_meta(name)?.map_or(0, |m| m.generation);
+                if actual != *expected {
+                    return Err(CteError::GenerationMismatch {
+                        blob: name.to_string(),
+                        expected: *expected,
+                        actual,
+                    });
+                }
+            }
+        }
+        // From here on the journal owns the staging, even if applying fails.
+        let ops = std::mem::take(&mut self.ops);
+        let journal = format!("{}{}", TXN_PREFIX, self.id);
+        ffi_guard::tag_put_blob(&self.tag.inner, &journal, &encode_journal(&ops), 0, 1.0)?;
+        self.tag.mark_pending(&self.id, &ops)?;
+        self.tag.apply(&self.id, &ops)?;
+        ffi::tag_del_blob(&self.tag.inner, &journal);
+        Ok(())
+    }
+}
+
+impl Drop for Txn<'_> {
+    fn drop(&mut self) {
+        for (i, op) in self.ops.iter().enumerate() {
+            if matches!(op, TxnOp::Put { .. }) {
+                self.tag.discard_staged(&staging_name(&self.id, i));
+            }
+        }
+    }
+}
+
+impl Tag {
+    /// Start a transaction on this tag.
+    pub fn transaction(&self) -> Txn<'_> {
+        let n = NEXT_TXN.fetch_add(1, Ordering::Relaxed);
+        Txn {
+            tag: self,
+            id: format!("{:016x}-{}", writer_id(), n),
+            ops: Vec::new(),
+        }
+    }
+
+    /// Finish commits of this tag that failed partway or were interrupted by a
+    /// crash, returning how many were rolled forward.
+    pub fn recover_transactions(&self) -> Result<usize, CteError> {
+        let journals: Vec<String> = self
+            .raw_blob_names()
+            .into_iter()
+            .filter(|n| {
+                n.strip_prefix(TXN_PREFIX)
+                    .is_some_and(|id| !id.contains('/'))
+            })
+            .collect();
+        if journals.is_empty() {
+            return Ok(0);
+        }
+        let _commit = write_guard();
+        let mut recovered = 0;
+        for journal in journals {
+            let id = &journal[TXN_PREFIX.len()..];
+            if self.finish(id)? {
+                recovered += 1;
+            }
+        }
+        Ok(recovered)
+    }
+
+    /// Finish transaction `id` for a reader that found `name` marked by it,
+    /// with no commit guard held. Clears the mark if the transaction is already
+    /// done.
+    pub(crate) fn roll_forward(&self, id: &str, name: &str) -> Result<(), CteError> {
+        let _commit = write_guard();
+        if self.finish(id)? {
+            return Ok(());
+        }
+        let _guard = meta_lock();
+        if let Some(mut meta) = self.load_meta(name)? {
+            if meta.pending_txn.as_deref() == Some(id) {
+                meta.pending_txn = None;
+                self.store_meta(name, &meta)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply and remove the journal of transaction `id`; false if there is none.
+    fn finish(&self, id: &str) -> Result<bool, CteError> {
+        let journal = format!("{}{}", TXN_PREFIX, id);
+        let size = self.get_blob_size(&journal);
+        if size == 0 {
+            return Ok(false);
+        }
+        let buf = ffi_guard::tag_get_blob(&self.inner, &journal, size, 0)?;
+        let ops = decode_journal(buf.as_slice()).map_err(|reason| CteError::CorruptMetadata {
+            blob: journal.clone(),
+            reason,
+        })?;
+        self.apply(id, &ops)?;
+        ffi::tag_del_blob(&self.inner, &journal);
+        Ok(true)
+    }
+
+    /// Mark every blob `ops` cover as pending on transaction `id`, so readers
+    /// finish it before answering. Moving a staged blob into place, or deleting
+    /// one, drops its mark.
+    fn mark_pending(&self, id: &str, ops: &[TxnOp]) -> Result<(), CteError> {
+        let _guard = meta_lock();
+        for op in ops {
+            let name = op.name();
+            let mut meta = self.load_meta(name)?.unwrap_or_default();
+            meta.pending_txn = Some(id.to_string());
+            self.store_meta(name, &meta)?;
+        }
+        Ok(())
+    }
+
+    /// Apply journaled `ops` of transaction `id`. Idempotent: a put whose staging
+    /// is gone was already moved into place.
+    fn apply(&self, id: &str, ops: &[TxnOp]) -> Result<(), CteError> {
+        for (i, op) in ops.iter().enumerate() {
+            #[cfg(test)]
+            if FAIL_AT.get() == Some(i) {
+                return Err(CteError::Io(std::io::Error::other("injected failure")));
+            }
+            let name = op.name();
+            match op {
+                TxnOp::Put { .. } => {
+                    let staged = staging_name(id, i);
+                    if self.get_blob_size(&staged) == 0 {
+                        continue;
+                    }
+                    self.install_copy(&staged, name)?;
+                    self.discard_staged(&staged);
+                }
+                TxnOp::Delete { .. } => {
+                    self.del_blob(name);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn discard_staged(&self, staged: &str) {
+        let _guard = meta_lock();
+        ffi::tag_del_blob(&self.inner, staged);
+        ffi::tag_del_blob(&self.inner, &format!("{}{}", META_PREFIX, staged));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_roundtrip() {
+        let ops = vec![
+            TxnOp::Put {
+                name: "ckpt/shard-0".into(),
+                if_generation_match: None,
+            },
+            TxnOp::Delete {
+                name: "ckpt/old".into(),
+            },
+            TxnOp::Put {
+                name: "ckpt/manifest".into(),
+                if_generation_match: None,
+            },
+        ];
+        assert_eq!(decode_journal(&encode_journal(&ops)).unwrap(), ops);
+    }
+}
@@ -0,0 +1,106 @@
+//! Scatter-gather puts and gets.
+//!
+//! A slab of a strided array (every row of a 2-D tile, say) is many small
+//! buffers that belong one after another in a blob. Written one `put_blob` at a
+//! time, each pays a round trip to the runtime and the blob is half-written in
+//! between; `Tag::put_blob_vectored` writes them as one transfer instead, and
+//! `Tag::get_blob_vectored` reads a range back in one and spreads it over the
+//! caller's buffers. The buffers are gathered into (and scattered from) one
+//! staging buffer, since the runtime takes a single contiguous one.
+
+use std::borrow::Cow;
+use std::io::{IoSlice, IoSliceMut};
+
+use crate::{writeback, CteError, Tag};
+
+/// The buffers as one, borrowed if there is only one.
+fn gather<'a>(iovecs: &'a [IoSlice<'_>]) -> Cow<'a, [u8]> {
+    match iovecs {
+        [one] => Cow::Borrowed(one),
+        _ => Cow::Owned(iovecs.iter().flat_map(|v| v.iter().copied()).collect()),
+    }
+}
+
+/// Copy `data` into the buffers in order.
+fn scatter(data: &[u8], iovecs: &mut [IoSliceMut<'_>]) {
+    let mut at = 0;
+    for v in iovecs {
+        let n = v.len().min(data.len() - at);
+        v[..n].copy_from_slice(&data[at..at + n]);
+        at += n;
+    }
+}
+
+impl Tag {
+    /// Write the buffers in `iovecs` one after another into a blob from
+    /// `offset`, in one transfer (see `vectored`).
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(tag = %self.name, blob = name, buffers = iovecs.len(), offset = offset),
+            err
+        )
+    )]
+    pub fn put_blob_vectored(
+        &self,
+        name: &str,
+        iovecs: &[IoSlice<'_>],
+        offset: u64,
+    ) -> Result<(), CteError> {
+        writeback::flush_blob(self, name);
+        self.try_write_blob(name, &gather(iovecs), offset, None)?;
+        Ok(())
+    }
+
+    /// Fill the buffers in `iovecs`, in order, from a blob's data at `offset`,
+    /// read in one transfer (see `vectored`). Fails as `get_blob` panics, for a
+    /// missing blob or a range over `RequestLimits::max_read`.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(tag = %self.name, blob = name, buffers = iovecs.len(), offset = offset),
+            err
+        )
+    )]
+    pub fn get_blob_vectored(
+        &self,
+        name: &str,
+        iovecs: &mut [IoSliceMut<'_>],
+        offset: u64,
+    ) -> Result<(), CteError> {
+        writeback::flush_blob(self, name);
+        let size = iovecs.iter().map(|v| v.len() as u64).sum();
+        let data = self.read_blob_cached(name, size, offset)?;
+        scatter(&data, iovecs);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gather_scatter() {
+        let (a, b, c) = (*b"row0", *b"", *b"row1!");
+        let iovecs = [IoSlice::new(&a), IoSlice::new(&b), IoSlice::new(&c)];
+        let data = gather(&iovecs);
+        assert_eq!(&data[..], b"row0row1!");
+        assert!(matches!(gather(&iovecs[..1]), Cow::Borrowed(_)));
+        assert!(gather(&[]).is_empty());
+
+        let (mut x, mut y, mut z) = ([0u8; 3], [0u8; 0], [0u8; 6]);
+        let mut out = [
+            IoSliceMut::new(&mut x),
+            IoSliceMut::new(&mut y),
+            IoSliceMut::new(&mut z),
+        ];
+        scatter(&data, &mut out);
+        assert_eq!(&x, b"row");
+        assert_eq!(&z, b"0row1!");
+    }
+}
@@ -0,0 +1,188 @@
+//! Per-blob version history for opted-in tags.
+//!
+//! After `Tag::enable_versioning`, every write made through the wrapper first
+//! copies the version it replaces (data, score and metadata) to the reserved
+//! `.cte/versions/<blob>/<generation>` blob, so each put leaves the previous
+//! generation readable with `Tag::get_blob_version` and restorable with
+//! `Tag::rollback_blob`. Versions are numbered by generation; the live blob is
+//! the newest. Deleting a blob archives its last version too, and a blob written
+//! again after a delete carries on numbering from its history, so a deleted
+//! checkpoint can be rolled back and version numbers are never reused.
+//! `Tag::prune_blob_versions` bounds the history.
+//!
+//! The copy costs a read and a write of the old version, including for small
+//! appends to a large blob.
+
+use crate::meta::is_reserved;
+use crate::{ffi, ffi_guard, CteError, GetOptions, Tag};
+
+const VERSIONING_MARKER: &str = ".cte/versioned";
+const VERSIONS_PREFIX: &str = ".cte/versions/";
+
+fn version_name(blob: &str, version: u64) -> String {
+    // Zero-padded so listings sort by version.
+    format!("{}{}/{:020}", VERSIONS_PREFIX, blob, version)
+}
+
+/// One version of a blob, as listed by `Tag::list_blob_versions`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlobVersion {
+    /// The blob's generation when this version was current.
+    pub version: u64,
+    pub size: u64,
+    /// This is the live blob rather than an archived copy.
+    pub current: bool,
+}
+
+impl Tag {
+    /// Keep the previous version of a blob on every write from now on. Has no
+    /// effect on writes made before it was called.
+    ///
+    /// Panics if the runtime fails to record the setting.
+    pub fn enable_versioning(&self) {
+        if ffi::tag_get_blob_size(&self.inner, VERSIONING_MARKER) == 0 {
+            if let Err(e) = ffi_guard::tag_put_blob(&self.inner, VERSIONING_MARKER, &[1], 0, 1.0) {
+                panic!("{}", e);
+            }
+        }
+        self.versioning.set_on();
+        crate::tagcache::forget(&self.name);
+    }
+
+    pub(crate) fn versioning_enabled(&self) -> bool {
+        self.versioning
+            .is_on(|| ffi::tag_get_blob_size(&self.inner, VERSIONING_MARKER) > 0)
+    }
+
+    /// Archive the current contents of `name`, at `generation`, ahead of a write.
+    /// Caller holds the meta lock.
+    pub(crate) fn archive_version(&self, name: &str, generation: u64) {
+        if is_reserved(name) || !self.versioning_enabled() || self.get_blob_size(name) == 0 {
+            return;
+        }
+        // A read error leaves the write unversioned rather than failing it.
+        let _ = self.copy_blob_raw(name, self, &version_name(name, generation));
+    }
+
+    /// Archive the current contents of `name` ahead of deleting it. Caller holds
+    /// the meta lock.
+    pub(crate) fn archive_deleted(&self, name: &str) {
+        if is_reserved(name) || !self.versioning_enabled() {
+            return;
+        }
+        let generation = self
+            .load_meta(name)
+            .ok()
+            .flatten()
+            .map_or(0, |m| m.generation);
+        self.archive_version(name, generation);
+    }
+
+    /// Generation to continue from when `name` is written with no generation of
+    /// its own (new or deleted): its newest archived version, or 0.
+    pub(crate) fn generation_floor(&self, name: &str) -> u64 {
+        if !self.versioning_enabled() {
+            return 0;
+        }
+        let prefix = format!("{}{}/", VERSIONS_PREFIX, name);
+        self.raw_blob_names()
+            .iter()
+            .filter_map(|n| n.strip_prefix(&prefix)?.parse::<u64>().ok())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Size of the archived copy of version `version` of `name`; 0 if it has none.
+    pub(crate) fn archived_version_size(&self, name: &str, version: u64) -> u64 {
+        self.get_blob_size(&version_name(name, version))
+    }
+
+    /// Every version of `name`, oldest first, including the live blob if it exists.
+    pub fn list_blob_versions(&self, name: &str) -> Result<Vec<BlobVersion>, CteError> {
+        let prefix = format!("{}{}/", VERSIONS_PREFIX, name);
+        let mut versions = Vec::new();
+        for archived in self.raw_blob_names() {
+            let Some(Ok(version)) = archived.strip_prefix(&prefix).map(str::parse::<u64>) else {
+                continue;
+            };
+            if let Some(stat) = self.stat_blob(&archived)? {
+                versions.push(BlobVersion {
+                    version,
+                    size: stat.size,
+                    current: false,
+                });
+            }
+        }
+        if let Some(stat) = self.stat_blob(name)? {
+            versions.push(BlobVersion {
+                version: stat.generation,
+                size: stat.size,
+                current: true,
+            });
+        }
+        versions.sort_by_key(|v| v.version);
+        Ok(versions)
+    }
+
+    /// Read version `version` of `name`.
+    pub fn get_blob_version(&self, name: &str, version: u64) -> Result<Vec<u8>, CteError> {
+        let archived = version_name(name, version);
+        if self.get_blob_size(&archived) > 0 {
+            return self.get(&archived, &GetOptions::default());
+        }
+        let current = GetOptions {
+            if_generation_match: Some(version),
+            ..Default::default()
+        };
+        match self.get(name, &current) {
+            Err(CteError::GenerationMismatch { .. }) => Err(CteError::NotFound {
+                blob: format!("{}@{}", name, version),
+            }),
+            other => other,
+        }
+    }
+
+    /// Make version `version` of `name` live again, as a new write (the version
+    /// being replaced is archived like any other). Returns the new generation.
+    pub fn rollback_blob(&self, name: &str, version: u64) -> Result<u64, CteError> {
+        let archived = version_name(name, version);
+        if self.get_blob_size(&archived) == 0 {
+            return Err(CteError::NotFound {
+                blob: format!("{}@{}", name, version),
+            });
+        }
+        self.install_copy(&archived, name)
+    }
+
+    /// Delete all but the newest `keep` archived versions of `name`, returning how
+    /// many were removed.
+    pub fn prune_blob_versions(&self, name: &str, keep: usize) -> usize {
+        let prefix = format!("{}{}/", VERSIONS_PREFIX, name);
+        let mut archived: Vec<String> = self
+            .raw_blob_names()
+            .into_iter()
+            .filter(|n| {
+                n.strip_prefix(&prefix)
+                    .is_some_and(|v| v.parse::<u64>().is_ok())
+            })
+            .collect();
+        archived.sort();
+        let excess = archived.len().saturating_sub(keep);
+        for old in &archived[..excess] {
+            self.hard_del_blob(old);
+        }
+        excess
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_names_sort() {
+        let mut names = [version_name("ckpt", 10), version_name("ckpt", 9)];
+        names.sort();
+        assert_eq!(names[0], ".cte/versions/ckpt/00000000000000000009");
+    }
+}
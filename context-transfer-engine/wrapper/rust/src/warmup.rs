@@ -0,0 +1,281 @@
+//! Priming hot tiers from a job's manifest.
+//!
+//! A manifest lists the working set of a job, one entry per line: a tag name
+//! alone for every blob in the tag, or a tag name, a tab and a blob name, which
+//! may be a glob (`*`, `?`, `[...]`). Blank lines and lines starting with `#`
+//! are skipped. `Client::warmup` reorganizes every listed blob that sits below
+//! `WarmupOptions::score` up to it before returning, so the job can start
+//! against resident data.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::cancel::{self, CancellationToken};
+use crate::index::glob_match;
+use crate::{Client, CteError, Tag};
+
+/// Scores within this of the target count as reached; they are stored as `f32`.
+const SCORE_SLACK: f32 = 1e-4;
+
+/// One manifest line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    tag: String,
+    /// Blob name or glob; `None` for the whole tag.
+    blobs: Option<String>,
+}
+
+/// The working set of a job, for `Client::warmup`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WarmupManifest {
+    entries: Vec<Entry>,
+}
+
+impl WarmupManifest {
+    /// Parse a manifest (see `warmup` for the format).
+    pub fn parse(text: &str) -> Result<Self, CteError> {
+        let mut entries = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split('\t');
+            let tag = fields.next().unwrap_or_default();
+            let blobs = fields.next().filter(|b| !b.is_empty());
+            if tag.is_empty() || fields.next().is_some() {
+                return Err(CteError::InvalidArgument(format!(
+                    "manifest line {}: expected '<tag>' or '<tag>\\t<blob>'",
+                    n + 1
+                )));
+            }
+            entries.push(Entry {
+                tag: tag.to_string(),
+                blobs: blobs.map(str::to_string),
+            });
+        }
+        Ok(Self { entries })
+    }
+
+    /// Read and parse the manifest at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CteError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Add every blob of `tag`.
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.entries.push(Entry {
+            tag: tag.to_string(),
+            blobs: None,
+        });
+        self
+    }
+
+    /// Add the blobs of `tag` matching `blobs`, a name or a glob.
+    pub fn blobs(mut self, tag: &str, blobs: &str) -> Self {
+        self.entries.push(Entry {
+            tag: tag.to_string(),
+            blobs: Some(blobs.to_string()),
+        });
+        self
+    }
+}
+
+/// Options for `Client::warmup`.
+#[derive(Debug, Clone)]
+pub struct WarmupOptions {
+    /// Placement score the working set is promoted to.
+    pub score: f32,
+    /// Number of blobs reorganized concurrently.
+    pub parallelism: usize,
+    /// Stops the warm-up between blobs with `CteError::Cancelled`; blobs
+    /// already promoted stay where they are.
+    pub cancel: Option<CancellationToken>,
+}
+
+impl Default for WarmupOptions {
+    fn default() -> Self {
+        Self {
+            score: 1.0,
+            parallelism: std::thread::available_parallelism().map_or(4, |n| n.get()),
+            cancel: None,
+        }
+    }
+}
+
+/// What a warm-up found and did.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WarmupReport {
+    /// Blobs in the working set.
+    pub blobs: usize,
+    /// Their total size.
+    pub bytes: u64,
+    /// Blobs that were reorganized up to the target score.
+    pub promoted: usize,
+    /// Blobs left below the target score, as `tag\tblob`: the runtime skips
+    /// moves smaller than its `score_difference_threshold` and can run out of
+    /// room on the hot tiers.
+    pub cold: Vec<String>,
+    /// Manifest entries that matched nothing, as `tag` or `tag\tblob`.
+    pub missing: Vec<String>,
+    /// Time from the start of the call until the working set was in place.
+    pub elapsed: Duration,
+}
+
+impl WarmupReport {
+    /// True if every blob of the working set is at the target score.
+    pub fn resident(&self) -> bool {
+        self.cold.is_empty()
+    }
+}
+
+/// Expand `manifest` into sorted, distinct `(tag, blob)` pairs.
+fn resolve(
+    manifest: &WarmupManifest,
+    report: &mut WarmupReport,
+) -> Result<Vec<(String, String)>, CteError> {
+    let mut set = BTreeSet::new();
+    for entry in &manifest.entries {
+        if !Client::tag_exists(&entry.tag) {
+            report.missing.push(entry.tag.clone());
+            continue;
+        }
+        let tag = Tag::try_new(&entry.tag)?;
+        let mut found = false;
+        match &entry.blobs {
+            Some(name) if !name.contains(['*', '?', '[']) => {
+                found = tag.get_blob_size(name) > 0;
+                if found {
+                    set.insert((entry.tag.clone(), name.clone()));
+                }
+            }
+            pattern => {
+                for blob in tag.blob_names() {
+                    if pattern.as_ref().is_none_or(|p| glob_match(p, &blob)) {
+                        found = true;
+                        set.insert((entry.tag.clone(), blob));
+                    }
+                }
+            }
+        }
+        // A whole tag may be empty; a named blob or glob should match.
+        if let (false, Some(blobs)) = (found, &entry.blobs) {
+            report.missing.push(format!("{}\t{}", entry.tag, blobs));
+        }
+    }
+    Ok(set.into_iter().collect())
+}
+
+impl Client {
+    /// Promote the working set in `manifest` to `options.score` (see `warmup`).
+    /// Returns once every blob has been moved or found to be there already;
+    /// missing entries and blobs the runtime left cold are reported, not fatal,
+    /// while a move the runtime fails stops the warm-up with its error.
+    pub fn warmup(
+        manifest: &WarmupManifest,
+        options: &WarmupOptions,
+    ) -> Result<WarmupReport, CteError> {
+        if !(0.0..=1.0).contains(&options.score) {
+            return Err(CteError::InvalidArgument(format!(
+                "warm-up score {} is outside 0.0..=1.0",
+                options.score
+            )));
+        }
+        let start = Instant::now();
+        let mut report = WarmupReport::default();
+        let blobs = resolve(manifest, &mut report)?;
+        report.blobs = blobs.len();
+
+        let target = options.score - SCORE_SLACK;
+        let bytes = AtomicU64::new(0);
+        let promoted = AtomicUsize::new(0);
+        let cold = Mutex::new(Vec::new());
+        let next = AtomicUsize::new(0);
+        let stopped = AtomicBool::new(false);
+        let first_err: Mutex<Option<CteError>> = Mutex::new(None);
+        std::thread::scope(|s| {
+            for _ in 0..options.parallelism.clamp(1, blobs.len().max(1)) {
+                s.spawn(|| {
+                    let mut current: Option<Tag> = None;
+                    let fail = |e| {
+                        stopped.store(true, Ordering::Relaxed);
+                        let mut first = first_err.lock().unwrap_or_else(|e| e.into_inner());
+                        first.get_or_insert(e);
+                    };
+                    while let Some((tag_name, blob)) =
+                        blobs.get(next.fetch_add(1, Ordering::Relaxed))
+                    {
+                        if stopped.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        if cancel::cancelled(&options.cancel) {
+                            stopped.store(true, Ordering::Relaxed);
+                            break;
+                        }
+                        if current.as_ref().map(Tag::name) != Some(tag_name.as_str()) {
+                            match Tag::try_new(tag_name) {
+                                Ok(tag) => current = Some(tag),
+                                Err(e) => {
+                                    fail(e);
+                                    break;
+                                }
+                            }
+                        }
+                        let tag = current.as_ref().unwrap();
+                        bytes.fetch_add(tag.get_blob_size(blob), Ordering::Relaxed);
+                        if tag.get_blob_score(blob) >= target {
+                            continue;
+                        }
+                        if let Err(e) = tag.try_reorganize_blob(blob, options.score) {
+                            fail(e);
+                            break;
+                        }
+                        if tag.get_blob_score(blob) >= target {
+                            promoted.fetch_add(1, Ordering::Relaxed);
+                        } else {
+                            let mut cold = cold.lock().unwrap_or_else(|e| e.into_inner());
+                            cold.push(format!("{}\t{}", tag_name, blob));
+                        }
+                    }
+                });
+            }
+        });
+        if let Some(e) = first_err.into_inner().unwrap_or_else(|e| e.into_inner()) {
+            return Err(e);
+        }
+        if stopped.into_inner() {
+            return Err(CteError::Cancelled);
+        }
+        report.bytes = bytes.into_inner();
+        report.promoted = promoted.into_inner();
+        report.cold = cold.into_inner().unwrap_or_else(|e| e.into_inner());
+        report.cold.sort();
+        report.elapsed = start.elapsed();
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest() {
+        let text = "# job 42\nclimate\n\nclimate\trun1/*.nc\r\nmasks\tland.bin\n";
+        let manifest = WarmupManifest::parse(text).unwrap();
+        let expected = WarmupManifest::default()
+            .tag("climate")
+            .blobs("climate", "run1/*.nc")
+            .blobs("masks", "land.bin");
+        assert_eq!(manifest, expected);
+        assert!(WarmupManifest::parse("a\tb\tc\n").is_err());
+        assert!(WarmupManifest::parse("\tblob\n").is_err());
+        assert_eq!(
+            WarmupManifest::parse("t\t\n").unwrap(),
+            WarmupManifest::default().tag("t")
+        );
+    }
+}
@@ -0,0 +1,109 @@
+//! Minimal HTTP/1.1 client for posting notifications to webhooks.
+//!
+//! Only plain `http://` URLs: a webhook behind TLS is reached through a local
+//! proxy or relay, as elsewhere in the wrapper (see `http`).
+
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::CteError;
+
+/// Connect, write and read timeout for one delivery.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A parsed `http://host[:port][/path]` URL.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Endpoint {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl Endpoint {
+    pub fn parse(url: &str) -> Result<Self, CteError> {
+        let bad = || CteError::InvalidArgument(format!("'{}' is not an http:// URL", url));
+        let rest = url.strip_prefix("http://").ok_or_else(bad)?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        // The last `:` starts the port, unless it is inside an IPv6 `[...]`.
+        let (host, port) = match authority.rfind(':') {
+            Some(i) if !authority[i..].contains(']') => (
+                &authority[..i],
+                authority[i + 1..].parse().map_err(|_| bad())?,
+            ),
+            _ => (authority, 80),
+        };
+        if host.is_empty() || path.contains(char::is_whitespace) {
+            return Err(bad());
+        }
+        Ok(Endpoint {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    /// POST `body` as JSON with any extra `headers`. Returns the response's
+    /// status code.
+    pub fn post(&self, headers: &[(&str, String)], body: &str) -> io::Result<u16> {
+        let host = self.host.trim_start_matches('[').trim_end_matches(']');
+        let addr = (host, self.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address"))?;
+        let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut head = format!(
+            "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\nConnection: close\r\n",
+            self.path,
+            self.host,
+            self.port,
+            body.len()
+        );
+        for (name, value) in headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        write!(stream, "{}\r\n{}", head, body)?;
+        let mut status = [0u8; 12];
+        stream.read_exact(&mut status)?;
+        let code = match &status[..9] {
+            b"HTTP/1.1 " | b"HTTP/1.0 " => std::str::from_utf8(&status[9..])
+                .ok()
+                .and_then(|c| c.parse().ok()),
+            _ => None,
+        };
+        code.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("bad response '{}'", String::from_utf8_lossy(&status)),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint() {
+        let e = Endpoint::parse("http://hooks.local:8080/cte/expiring").unwrap();
+        assert_eq!(
+            (e.host.as_str(), e.port, e.path.as_str()),
+            ("hooks.local", 8080, "/cte/expiring")
+        );
+        let e = Endpoint::parse("http://10.0.0.5").unwrap();
+        assert_eq!((e.port, e.path.as_str()), (80, "/"));
+        let e = Endpoint::parse("http://[::1]:9000/x").unwrap();
+        assert_eq!((e.host.as_str(), e.port), ("[::1]", 9000));
+        assert_eq!(Endpoint::parse("http://[::1]").unwrap().port, 80);
+        assert!(Endpoint::parse("https://hooks.local/").is_err());
+        assert!(Endpoint::parse("http://:80/").is_err());
+        assert!(Endpoint::parse("http://host:port/").is_err());
+    }
+}
@@ -0,0 +1,419 @@
+//! Write-back buffering of small writes.
+//!
+//! A sensor that writes a few bytes at a time with `Tag::put_blob` or
+//! `Tag::put_blob_with_options` pays a runtime round trip, plus the sidecar's,
+//! for each. With `Tag::enable_write_back`, those writes are kept in this
+//! process instead and return at once. Writes to the same blob are coalesced:
+//! overlapping and adjacent ranges become one, later bytes winning, so a
+//! stream of appends goes out as a single write. Buffered writes are sent when
+//! the tag has `WriteBackOptions::max_bytes` buffered (by the write that reaches
+//! it), on `Tag::flush`, `Tag::disable_write_back` or `Client::flush_all`, and,
+//! in `WriteBackMode::Timed`, once the oldest is `max_delay` old.
+//!
+//! Durability depends on the mode:
+//!
+//! - `WriteBackMode::Timed`: a write reaches the runtime within about
+//!   `max_delay` of being made. A process that dies sooner loses it.
+//! - `WriteBackMode::Manual`: a write reaches the runtime only when the buffer
+//!   fills or the application flushes, so everything since the last flush can
+//!   be lost; flush wherever the data has to be safe.
+//!
+//! In both modes a write that returned is not yet visible to other processes.
+//! In this process, `Tag::get`, `get_blob`, `get_blob_with_offset`, `put`,
+//! `stat_blob`, `reorganize_blob` and `del_blob` first send the blob's
+//! buffered writes, so they see (and order after) them; sizes, listings and
+//! queries see buffered writes only once they are flushed. Buffering is per
+//! tag and shared by every handle on it in the process. A buffered write that
+//! the runtime refuses when it is sent is dropped, and the error is returned by
+//! the next `Tag::flush`.
+
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{Client, CteError, Tag};
+
+/// When a write-back buffer is flushed (see `writeback`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteBackMode {
+    /// On size, on request, and once the oldest write is `max_delay` old.
+    Timed,
+    /// On size and on request only.
+    Manual,
+}
+
+/// Settings for `Tag::enable_write_back`.
+#[derive(Debug, Clone)]
+pub struct WriteBackOptions {
+    pub mode: WriteBackMode,
+    /// Buffered bytes at which the tag is flushed.
+    pub max_bytes: usize,
+    /// Age of the oldest write at which a `Timed` buffer is flushed.
+    pub max_delay: Duration,
+}
+
+impl Default for WriteBackOptions {
+    fn default() -> Self {
+        Self {
+            mode: WriteBackMode::Timed,
+            max_bytes: 1 << 20,
+            max_delay: Duration::from_millis(100),
+        }
+    }
+}
+
+/// How often the flusher thread looks for `Timed` buffers that are due.
+const CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Buffered data of one blob: disjoint extents by offset, and the score of the
+/// last write.
+#[derive(Debug, Default)]
+struct Extents {
+    ranges: BTreeMap<u64, Vec<u8>>,
+    score: Option<f32>,
+}
+
+/// Merge `data` at `offset` into `ranges`, joining every range it overlaps or
+/// touches. Returns the change in buffered bytes.
+fn coalesce(ranges: &mut BTreeMap<u64, Vec<u8>>, offset: u64, data: &[u8]) -> isize {
+    if data.is_empty() {
+        return 0;
+    }
+    let end = offset + data.len() as u64;
+    // Ranges starting at or before `end` whose end reaches `offset`.
+    let touching: Vec<u64> = ranges
+        .range((Bound::Unbounded, Bound::Included(end)))
+        .rev()
+        .take_while(|(start, bytes)| **start + bytes.len() as u64 >= offset)
+        .map(|(start, _)| *start)
+        .collect();
+    let mut removed = 0;
+    let mut start = offset;
+    let mut merged_end = end;
+    let mut parts = Vec::with_capacity(touching.len());
+    for s in touching {
+        let bytes = ranges.remove(&s).unwrap();
+        removed += bytes.len();
+        start = start.min(s);
+        merged_end = merged_end.max(s + bytes.len() as u64);
+        parts.push((s, bytes));
+    }
+    let mut merged = vec![0; (merged_end - start) as usize];
+    for (s, bytes) in parts {
+        let at = (s - start) as usize;
+        merged[at..at + bytes.len()].copy_from_slice(&bytes);
+    }
+    let at = (offset - start) as usize;
+    merged[at..at + data.len()].copy_from_slice(data);
+    let added = merged.len();
+    ranges.insert(start, merged);
+    added as isize - removed as isize
+}
+
+struct Buffer {
+    /// A handle of our own, for the flusher thread.
+    tag: Arc<Tag>,
+    options: WriteBackOptions,
+    blobs: BTreeMap<String, Extents>,
+    bytes: usize,
+    /// When the oldest buffered write was made.
+    oldest: Option<Instant>,
+    /// First error of a flush not made by `Tag::flush`, not yet reported.
+    error: Option<CteError>,
+    /// Held while a flush writes, so flushes of the tag go out in order.
+    flushing: Arc<Mutex<()>>,
+}
+
+type TagKey = (u32, u32);
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static BUFFERS: Mutex<Option<HashMap<TagKey, Buffer>>> = Mutex::new(None);
+/// Whether the flusher thread is running.
+static FLUSHER: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// Set while this thread sends buffered writes, whose own operations must
+    /// not flush again.
+    static FLUSHING: Cell<bool> = const { Cell::new(false) };
+}
+
+fn key(tag: &Tag) -> TagKey {
+    let id = tag.get_tag_id();
+    (id.major, id.minor)
+}
+
+fn buffers() -> std::sync::MutexGuard<'static, Option<HashMap<TagKey, Buffer>>> {
+    BUFFERS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Buffer a legacy write if the tag has write-back on; false if it has not.
+pub(crate) fn buffer(tag: &Tag, name: &str, data: &[u8], offset: u64, score: Option<f32>) -> bool {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return false;
+    }
+    let key = key(tag);
+    let full = {
+        let mut guard = buffers();
+        let Some(buffer) = guard.as_mut().and_then(|b| b.get_mut(&key)) else {
+            return false;
+        };
+        let extents = buffer.blobs.entry(name.to_string()).or_default();
+        let change = coalesce(&mut extents.ranges, offset, data);
+        extents.score = score;
+        buffer.bytes = buffer.bytes.saturating_add_signed(change);
+        buffer.oldest.get_or_insert_with(Instant::now);
+        buffer.bytes >= buffer.options.max_bytes
+    };
+    if full {
+        if let Err(e) = flush_tag(key, None) {
+            keep_error(key, e);
+        }
+    }
+    true
+}
+
+fn keep_error(key: TagKey, e: CteError) {
+    if let Some(buffer) = buffers().as_mut().and_then(|b| b.get_mut(&key)) {
+        buffer.error.get_or_insert(e);
+    }
+}
+
+/// Send the buffered writes of `key`, or only those of blob `only`. Returns
+/// the bytes sent; the first write the runtime refused is the error, after
+/// the rest have been sent.
+fn flush_tag(key: TagKey, only: Option<&str>) -> Result<usize, CteError> {
+    let (tag, flushing) = match buffers().as_ref().and_then(|b| b.get(&key)) {
+        Some(buffer) => (Arc::clone(&buffer.tag), Arc::clone(&buffer.flushing)),
+        None => return Ok(0),
+    };
+    let _flushing = flushing.lock().unwrap_or_else(|e| e.into_inner());
+    FLUSHING.set(true);
+    let result = send(key, only, &tag);
+    FLUSHING.set(false);
+    result
+}
+
+fn send(key: TagKey, only: Option<&str>, tag: &Tag) -> Result<usize, CteError> {
+    let blobs = {
+        let mut guard = buffers();
+        let Some(buffer) = guard.as_mut().and_then(|b| b.get_mut(&key)) else {
+            return Ok(0);
+        };
+        let blobs = match only {
+            Some(name) => match buffer.blobs.remove(name) {
+                Some(extents) => BTreeMap::from([(name.to_string(), extents)]),
+                None => return Ok(0),
+            },
+            None => std::mem::take(&mut buffer.blobs),
+        };
+        let taken: usize = blobs
+            .values()
+            .flat_map(|e| e.ranges.values())
+            .map(Vec::len)
+            .sum();
+        buffer.bytes -= taken;
+        if buffer.blobs.is_empty() {
+            buffer.oldest = None;
+        }
+        blobs
+    };
+    let (mut sent, mut error) = (0, None);
+    for (name, extents) in blobs {
+        for (offset, data) in extents.ranges {
+            match tag.try_write_blob(&name, &data, offset, extents.score) {
+                Ok(_) => sent += data.len(),
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+    }
+    error.map_or(Ok(sent), Err)
+}
+
+/// Send the buffered writes of blob `name`, before an operation on it.
+pub(crate) fn flush_blob(tag: &Tag, name: &str) {
+    if !ENABLED.load(Ordering::Relaxed) || FLUSHING.get() {
+        return;
+    }
+    let key = key(tag);
+    if let Err(e) = flush_tag(key, Some(name)) {
+        keep_error(key, e);
+    }
+}
+
+/// Send every tag's buffered writes, keeping errors for `Tag::flush`;
+/// returns the bytes sent.
+pub(crate) fn flush_buffers() -> usize {
+    let keys: Vec<TagKey> = buffers()
+        .as_ref()
+        .map(|m| m.keys().copied().collect())
+        .unwrap_or_default();
+    let mut sent = 0;
+    for key in keys {
+        match flush_tag(key, None) {
+            Ok(n) => sent += n,
+            Err(e) => keep_error(key, e),
+        }
+    }
+    sent
+}
+
+/// Flush the `Timed` buffers that are due, until none is left.
+fn run_flusher() {
+    loop {
+        std::thread::sleep(CHECK_INTERVAL);
+        let due: Vec<TagKey> = {
+            let guard = buffers();
+            let Some(map) = guard.as_ref().filter(|m| !m.is_empty()) else {
+                FLUSHER.store(false, Ordering::Relaxed);
+                return;
+            };
+            map.iter()
+                .filter(|(_, b)| {
+                    b.options.mode == WriteBackMode::Timed
+                        && b.oldest.is_some_and(|t| t.elapsed() >= b.options.max_delay)
+                })
+                .map(|(k, _)| *k)
+                .collect()
+        };
+        for key in due {
+            if let Err(e) = flush_tag(key, None) {
+                keep_error(key, e);
+            }
+        }
+    }
+}
+
+impl Tag {
+    /// Buffer this tag's legacy puts in this process (see `writeback`), or
+    /// change the settings of a buffer already on. Fails with
+    /// `InvalidArgument` for a zero `max_bytes`.
+    pub fn enable_write_back(&self, options: WriteBackOptions) -> Result<(), CteError> {
+        if options.max_bytes == 0 {
+            return Err(CteError::InvalidArgument(
+                "a write-back buffer needs a size".into(),
+            ));
+        }
+        let key = key(self);
+        {
+            let mut guard = buffers();
+            let map = guard.get_or_insert_with(HashMap::new);
+            match map.get_mut(&key) {
+                Some(buffer) => buffer.options = options,
+                None => {
+                    let id = self.get_tag_id();
+                    map.insert(
+                        key,
+                        Buffer {
+                            tag: Arc::new(Tag::reopen(id, self.name.clone())),
+                            options,
+                            blobs: BTreeMap::new(),
+                            bytes: 0,
+                            oldest: None,
+                            error: None,
+                            flushing: Arc::new(Mutex::new(())),
+                        },
+                    );
+                }
+            }
+            ENABLED.store(true, Ordering::Relaxed);
+        }
+        if !FLUSHER.swap(true, Ordering::Relaxed) {
+            std::thread::spawn(run_flusher);
+        }
+        Ok(())
+    }
+
+    /// Flush this tag's buffer and stop buffering its writes.
+    pub fn disable_write_back(&self) -> Result<(), CteError> {
+        let result = self.flush();
+        let mut guard = buffers();
+        if let Some(map) = guard.as_mut() {
+            map.remove(&key(self));
+            if map.is_empty() {
+                ENABLED.store(false, Ordering::Relaxed);
+            }
+        }
+        result.map(drop)
+    }
+
+    /// Whether this tag's legacy puts are buffered.
+    pub fn write_back_enabled(&self) -> bool {
+        ENABLED.load(Ordering::Relaxed)
+            && buffers()
+                .as_ref()
+                .is_some_and(|m| m.contains_key(&key(self)))
+    }
+
+    /// Bytes buffered for this tag and not yet sent.
+    pub fn write_back_pending(&self) -> usize {
+        buffers()
+            .as_ref()
+            .and_then(|m| m.get(&key(self)))
+            .map_or(0, |b| b.bytes)
+    }
+
+    /// Send every buffered write of this tag now. Returns the bytes sent, or
+    /// the first error of this or an earlier flush.
+    #[cfg_attr(
+        feature = "trace",
+        tracing::instrument(level = "debug", skip_all, fields(tag = %self.name), ret, err)
+    )]
+    pub fn flush(&self) -> Result<usize, CteError> {
+        let key = key(self);
+        let earlier = buffers()
+            .as_mut()
+            .and_then(|m| m.get_mut(&key))
+            .and_then(|b| b.error.take());
+        let sent = flush_tag(key, None)?;
+        earlier.map_or(Ok(sent), Err)
+    }
+}
+
+impl Client {
+    /// `Tag::flush` every tag with write-back on; returns the bytes sent, or
+    /// the first error after flushing the rest.
+    pub fn flush_all() -> Result<usize, CteError> {
+        let tags: Vec<Arc<Tag>> = buffers()
+            .as_ref()
+            .map(|m| m.values().map(|b| Arc::clone(&b.tag)).collect())
+            .unwrap_or_default();
+        let (mut sent, mut error) = (0, None);
+        for tag in tags {
+            match tag.flush() {
+                Ok(n) => sent += n,
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        error.map_or(Ok(sent), Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coalesce() {
+        let mut ranges = BTreeMap::new();
+        assert_eq!(coalesce(&mut ranges, 0, b"abc"), 3);
+        // Adjacent: one range.
+        assert_eq!(coalesce(&mut ranges, 3, b"def"), 3);
+        assert_eq!(ranges, BTreeMap::from([(0, b"abcdef".to_vec())]));
+        // Apart: two.
+        assert_eq!(coalesce(&mut ranges, 10, b"xy"), 2);
+        // Overlapping both: later bytes win and the gap is filled.
+        assert_eq!(coalesce(&mut ranges, 5, b"ZZZZZ"), 4);
+        assert_eq!(ranges, BTreeMap::from([(0, b"abcdeZZZZZxy".to_vec())]));
+        // Inside: nothing grows.
+        assert_eq!(coalesce(&mut ranges, 1, b"B"), 0);
+        assert_eq!(ranges[&0], b"aBcdeZZZZZxy");
+        assert_eq!(coalesce(&mut ranges, 20, b""), 0);
+    }
+}
@@ -0,0 +1,615 @@
+//! Zarr v3 stores over tags (feature `zarr`).
+//!
+//! `ZarrStore` is the store of the Zarr v3 spec over one tag: a key is the name
+//! of a blob (`zarr.json`, `temp/c/0/3`), so a Zarr hierarchy written through
+//! it lives in the tag's blobs and tiers like any other data. `get`, `set` and
+//! `erase` are blob gets, puts and deletes, and `list_prefix` and `list_dir` are
+//! `Tag::list` (see `listing`). `get_range` is a ranged get, so a reader after
+//! a shard's index or part of a chunk doesn't transfer the rest.
+//!
+//! `ZarrStore::create_array` and `open_array` give a `ZarrArray`: fixed-size
+//! numeric elements on a regular chunk grid, encoded with the `bytes` codec
+//! (little-endian) under the default chunk key encoding, which zarr-python and
+//! zarrs read and write as is. Arrays with other codecs or grids are opened
+//! only as keys. Chunks are read and written whole by grid coordinates; a chunk
+//! never written reads as the fill value. `ZarrArray::set_region_score` moves
+//! the chunks under a region of the array to a score (see
+//! `Tag::reorganize_blob`), so a hot slab can sit on fast tiers while the rest
+//! of the array stays on slow ones.
+
+use serde_json::Value;
+
+use crate::{BlobListing, CteError, GetOptions, PutOptions, Tag};
+
+/// Name of the metadata document of every array and group.
+pub const ZARR_JSON: &str = "zarr.json";
+
+/// Element types a `ZarrArray` can hold, by their Zarr names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZarrDataType {
+    Bool,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    UInt8,
+    UInt16,
+    UInt32,
+    UInt64,
+    Float32,
+    Float64,
+}
+
+const DATA_TYPES: [ZarrDataType; 11] = [
+    ZarrDataType::Bool,
+    ZarrDataType::Int8,
+    ZarrDataType::Int16,
+    ZarrDataType::Int32,
+    ZarrDataType::Int64,
+    ZarrDataType::UInt8,
+    ZarrDataType::UInt16,
+    ZarrDataType::UInt32,
+    ZarrDataType::UInt64,
+    ZarrDataType::Float32,
+    ZarrDataType::Float64,
+];
+
+impl ZarrDataType {
+    /// The `data_type` of the array metadata.
+    pub fn name(self) -> &'static str {
+        match self {
+            ZarrDataType::Bool => "bool",
+            ZarrDataType::Int8 => "int8",
+            ZarrDataType::Int16 => "int16",
+            ZarrDataType::Int32 => "int32",
+            ZarrDataType::Int64 => "int64",
+            ZarrDataType::UInt8 => "uint8",
+            ZarrDataType::UInt16 => "uint16",
+            ZarrDataType::UInt32 => "uint32",
+            ZarrDataType::UInt64 => "uint64",
+            ZarrDataType::Float32 => "float32",
+            ZarrDataType::Float64 => "float64",
+        }
+    }
+
+    /// Bytes per element.
+    pub fn size(self) -> usize {
+        match self {
+            ZarrDataType::Bool | ZarrDataType::Int8 | ZarrDataType::UInt8 => 1,
+            ZarrDataType::Int16 | ZarrDataType::UInt16 => 2,
+            ZarrDataType::Int32 | ZarrDataType::UInt32 | ZarrDataType::Float32 => 4,
+            ZarrDataType::Int64 | ZarrDataType::UInt64 | ZarrDataType::Float64 => 8,
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        DATA_TYPES.into_iter().find(|t| t.name() == name)
+    }
+
+    /// `value` as one little-endian element.
+    fn encode(self, value: f64) -> Vec<u8> {
+        match self {
+            ZarrDataType::Bool => vec![u8::from(value != 0.0)],
+            ZarrDataType::Int8 => (value as i8).to_le_bytes().to_vec(),
+            ZarrDataType::Int16 => (value as i16).to_le_bytes().to_vec(),
+            ZarrDataType::Int32 => (value as i32).to_le_bytes().to_vec(),
+            ZarrDataType::Int64 => (value as i64).to_le_bytes().to_vec(),
+            ZarrDataType::UInt8 => (value as u8).to_le_bytes().to_vec(),
+            ZarrDataType::UInt16 => (value as u16).to_le_bytes().to_vec(),
+            ZarrDataType::UInt32 => (value as u32).to_le_bytes().to_vec(),
+            ZarrDataType::UInt64 => (value as u64).to_le_bytes().to_vec(),
+            ZarrDataType::Float32 => (value as f32).to_le_bytes().to_vec(),
+            ZarrDataType::Float64 => value.to_le_bytes().to_vec(),
+        }
+    }
+
+    /// `value` as the metadata's `fill_value`.
+    fn fill_json(self, value: f64) -> String {
+        match self {
+            ZarrDataType::Bool => (value != 0.0).to_string(),
+            ZarrDataType::Float32 | ZarrDataType::Float64 => {
+                if value.is_nan() {
+                    "\"NaN\"".into()
+                } else if value.is_infinite() {
+                    let sign = if value < 0.0 { "-" } else { "" };
+                    format!("\"{}Infinity\"", sign)
+                } else {
+                    value.to_string()
+                }
+            }
+            ZarrDataType::UInt8
+            | ZarrDataType::UInt16
+            | ZarrDataType::UInt32
+            | ZarrDataType::UInt64 => (value as u64).to_string(),
+            _ => (value as i64).to_string(),
+        }
+    }
+}
+
+/// What `zarr.json` says about an array the wrapper can read and write.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZarrArrayMeta {
+    /// Elements along each dimension.
+    pub shape: Vec<u64>,
+    /// Elements of a chunk along each dimension.
+    pub chunk_shape: Vec<u64>,
+    pub data_type: ZarrDataType,
+    /// Value of the elements of chunks never written.
+    pub fill_value: f64,
+    /// Separator of the chunk keys' coordinates, `/` or `.`.
+    pub separator: char,
+}
+
+impl ZarrArrayMeta {
+    /// An array of `shape` in `chunk_shape` chunks, filled with zeros.
+    pub fn new(shape: &[u64], chunk_shape: &[u64], data_type: ZarrDataType) -> Self {
+        Self {
+            shape: shape.to_vec(),
+            chunk_shape: chunk_shape.to_vec(),
+            data_type,
+            fill_value: 0.0,
+            separator: '/',
+        }
+    }
+
+    fn validate(&self) -> Result<(), CteError> {
+        if self.shape.len() != self.chunk_shape.len() {
+            return Err(CteError::InvalidArgument(format!(
+                "{}-d chunks for a {}-d array",
+                self.chunk_shape.len(),
+                self.shape.len()
+            )));
+        }
+        if self.chunk_shape.contains(&0) {
+            return Err(CteError::InvalidArgument("chunks must not be empty".into()));
+        }
+        if !matches!(self.separator, '/' | '.') {
+            return Err(CteError::InvalidArgument(format!(
+                "chunk key separator '{}' isn't '/' or '.'",
+                self.separator
+            )));
+        }
+        Ok(())
+    }
+
+    fn to_json(&self) -> String {
+        let list = |v: &[u64]| {
+            let items: Vec<String> = v.iter().map(u64::to_string).collect();
+            format!("[{}]", items.join(","))
+        };
+        format!(
+            "{{\"zarr_format\":3,\"node_type\":\"array\",\"shape\":{},\"data_type\":\"{}\",\
+             \"chunk_grid\":{{\"name\":\"regular\",\"configuration\":{{\"chunk_shape\":{}}}}},\
+             \"chunk_key_encoding\":{{\"name\":\"default\",\"configuration\":{{\"separator\":\"{}\"}}}},\
+             \"fill_value\":{},\"codecs\":[{{\"name\":\"bytes\",\"configuration\":{{\"endian\":\"little\"}}}}],\
+             \"attributes\":{{}}}}",
+            list(&self.shape),
+            self.data_type.name(),
+            list(&self.chunk_shape),
+            self.separator,
+            self.data_type.fill_json(self.fill_value),
+        )
+    }
+
+    /// Parse an array's `zarr.json`, failing with `Unsupported` for arrays
+    /// `ZarrArray` doesn't handle.
+    fn parse(path: &str, json: &[u8]) -> Result<Self, CteError> {
+        let malformed =
+            |what: &str| CteError::InvalidArgument(format!("zarr.json of '{}': {}", path, what));
+        let unsupported =
+            |what: String| CteError::Unsupported(format!("Zarr array '{}' has {}", path, what));
+        let doc: Value = serde_json::from_slice(json).map_err(|e| malformed(&e.to_string()))?;
+        if doc.get("zarr_format").and_then(Value::as_u64) != Some(3) {
+            return Err(unsupported("no zarr_format 3".into()));
+        }
+        if doc.get("node_type").and_then(Value::as_str) != Some("array") {
+            return Err(malformed("not an array"));
+        }
+        let dims = |v: Option<&Value>| -> Option<Vec<u64>> {
+            v?.as_array()?.iter().map(Value::as_u64).collect()
+        };
+        let shape = dims(doc.get("shape")).ok_or_else(|| malformed("bad shape"))?;
+        let name = doc.get("data_type").and_then(Value::as_str).unwrap_or("");
+        let data_type = ZarrDataType::parse(name)
+            .ok_or_else(|| unsupported(format!("data type '{}'", name)))?;
+
+        let grid = doc.get("chunk_grid");
+        if grid.and_then(|g| g.get("name")).and_then(Value::as_str) != Some("regular") {
+            return Err(unsupported("an irregular chunk grid".into()));
+        }
+        let chunk_shape = dims(
+            grid.and_then(|g| g.get("configuration"))
+                .and_then(|c| c.get("chunk_shape")),
+        )
+        .ok_or_else(|| malformed("bad chunk_shape"))?;
+
+        let encoding = doc.get("chunk_key_encoding");
+        if encoding.and_then(|e| e.get("name")).and_then(Value::as_str) != Some("default") {
+            return Err(unsupported(
+                "a chunk key encoding other than default".into(),
+            ));
+        }
+        let separator = match encoding
+            .and_then(|e| e.get("configuration"))
+            .and_then(|c| c.get("separator"))
+            .and_then(Value::as_str)
+        {
+            None | Some("/") => '/',
+            Some(".") => '.',
+            Some(other) => return Err(malformed(&format!("separator '{}'", other))),
+        };
+
+        let codecs = doc.get("codecs").and_then(Value::as_array);
+        let plain = codecs.is_some_and(|codecs| {
+            codecs.len() == 1
+                && codecs[0].get("name").and_then(Value::as_str) == Some("bytes")
+                && matches!(
+                    codecs[0]
+                        .get("configuration")
+                        .and_then(|c| c.get("endian"))
+                        .and_then(Value::as_str),
+                    None | Some("little")
+                )
+        });
+        if !plain {
+            return Err(unsupported("codecs other than little-endian bytes".into()));
+        }
+
+        let fill_value = match doc.get("fill_value") {
+            Some(Value::Bool(b)) => f64::from(u8::from(*b)),
+            Some(Value::String(s)) => match s.as_str() {
+                "NaN" => f64::NAN,
+                "Infinity" => f64::INFINITY,
+                "-Infinity" => f64::NEG_INFINITY,
+                _ => return Err(unsupported(format!("fill value '{}'", s))),
+            },
+            Some(v) => v.as_f64().ok_or_else(|| malformed("bad fill_value"))?,
+            None => return Err(malformed("no fill_value")),
+        };
+        let meta = Self {
+            shape,
+            chunk_shape,
+            data_type,
+            fill_value,
+            separator,
+        };
+        meta.validate().map_err(|e| malformed(&e.to_string()))?;
+        Ok(meta)
+    }
+}
+
+/// A Zarr v3 store over a tag (see `zarr`).
+pub struct ZarrStore {
+    tag: Tag,
+}
+
+/// `path` as a key prefix: empty for the root, else with a trailing `/`.
+fn node_prefix(path: &str) -> String {
+    let path = path.trim_matches('/');
+    if path.is_empty() {
+        String::new()
+    } else {
+        format!("{}/", path)
+    }
+}
+
+impl ZarrStore {
+    /// The store over tag `tag`, created if it doesn't exist.
+    pub fn open(tag: &str) -> Result<Self, CteError> {
+        Ok(Self {
+            tag: Tag::try_new(tag)?,
+        })
+    }
+
+    pub fn tag(&self) -> &Tag {
+        &self.tag
+    }
+
+    /// The value of `key`, or `None` if it isn't set.
+    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>, CteError> {
+        self.get_range(key, 0, None)
+    }
+
+    /// `size` bytes of the value of `key` from `offset` (all that follows if
+    /// `None`), or `None` if it isn't set.
+    pub fn get_range(
+        &self,
+        key: &str,
+        offset: u64,
+        size: Option<u64>,
+    ) -> Result<Option<Vec<u8>>, CteError> {
+        let options = GetOptions {
+            offset,
+            size,
+            ..Default::default()
+        };
+        match self.tag.get(key, &options) {
+            Ok(data) => Ok(Some(data)),
+            Err(CteError::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Set `key` to `value`, replacing any value it had.
+    pub fn set(&self, key: &str, value: &[u8]) -> Result<(), CteError> {
+        self.set_with_score(key, value, None)
+    }
+
+    fn set_with_score(&self, key: &str, value: &[u8], score: Option<f32>) -> Result<(), CteError> {
+        // A put only overwrites its range, so a longer old value goes first.
+        if self
+            .tag
+            .stat_blob(key)?
+            .is_some_and(|s| s.size > value.len() as u64)
+        {
+            self.tag.del_blob(key);
+        }
+        let options = PutOptions {
+            score,
+            ..Default::default()
+        };
+        self.tag.put(key, value, &options)?;
+        Ok(())
+    }
+
+    pub fn exists(&self, key: &str) -> Result<bool, CteError> {
+        Ok(self.tag.stat_blob(key)?.is_some())
+    }
+
+    /// Remove `key`; a key that isn't set is left alone.
+    pub fn erase(&self, key: &str) -> Result<(), CteError> {
+        if self.exists(key)? {
+            self.tag.del_blob(key);
+        }
+        Ok(())
+    }
+
+    /// Remove every key starting with `prefix`.
+    pub fn erase_prefix(&self, prefix: &str) -> Result<(), CteError> {
+        for key in self.list_prefix(prefix)? {
+            self.tag.del_blob(&key);
+        }
+        Ok(())
+    }
+
+    /// Every key, sorted.
+    pub fn list(&self) -> Result<Vec<String>, CteError> {
+        self.list_prefix("")
+    }
+
+    /// The keys starting with `prefix`, sorted.
+    pub fn list_prefix(&self, prefix: &str) -> Result<Vec<String>, CteError> {
+        Ok(self.tag.list(prefix, "")?.blobs)
+    }
+
+    /// The keys directly under `prefix` (empty or ending with `/`) and the
+    /// prefixes of the ones further down.
+    pub fn list_dir(&self, prefix: &str) -> Result<BlobListing, CteError> {
+        self.tag.list(prefix, "/")
+    }
+
+    /// Write the metadata of a group at `path` (`""` for the root).
+    pub fn create_group(&self, path: &str) -> Result<(), CteError> {
+        let key = format!("{}{}", node_prefix(path), ZARR_JSON);
+        self.set(
+            &key,
+            b"{\"zarr_format\":3,\"node_type\":\"group\",\"attributes\":{}}",
+        )
+    }
+
+    /// Write the metadata of an array at `path` and return it. Chunks of an
+    /// array there before are left as they are.
+    pub fn create_array(&self, path: &str, meta: ZarrArrayMeta) -> Result<ZarrArray<'_>, CteError> {
+        meta.validate()?;
+        let prefix = node_prefix(path);
+        self.set(
+            &format!("{}{}", prefix, ZARR_JSON),
+            meta.to_json().as_bytes(),
+        )?;
+        Ok(ZarrArray {
+            store: self,
+            prefix,
+            meta,
+        })
+    }
+
+    /// The array at `path`. Fails with `NotFound` if there is none, and with
+    /// `Unsupported` if it isn't encoded the way `ZarrArray` handles.
+    pub fn open_array(&self, path: &str) -> Result<ZarrArray<'_>, CteError> {
+        let prefix = node_prefix(path);
+        let key = format!("{}{}", prefix, ZARR_JSON);
+        let json = self.get(&key)?.ok_or(CteError::NotFound { blob: key })?;
+        Ok(ZarrArray {
+            store: self,
+            meta: ZarrArrayMeta::parse(path, &json)?,
+            prefix,
+        })
+    }
+}
+
+/// An array in a `ZarrStore` (see `zarr`).
+pub struct ZarrArray<'a> {
+    store: &'a ZarrStore,
+    /// Key prefix of the array's node.
+    prefix: String,
+    meta: ZarrArrayMeta,
+}
+
+impl ZarrArray<'_> {
+    pub fn meta(&self) -> &ZarrArrayMeta {
+        &self.meta
+    }
+
+    /// Chunks along each dimension.
+    pub fn grid_shape(&self) -> Vec<u64> {
+        self.meta
+            .shape
+            .iter()
+            .zip(&self.meta.chunk_shape)
+            .map(|(n, c)| n.div_ceil(*c))
+            .collect()
+    }
+
+    /// Bytes of a chunk's data.
+    pub fn chunk_len(&self) -> usize {
+        self.meta.chunk_shape.iter().product::<u64>() as usize * self.meta.data_type.size()
+    }
+
+    /// The key of the chunk at grid coordinates `coords`.
+    pub fn chunk_key(&self, coords: &[u64]) -> Result<String, CteError> {
+        let grid = self.grid_shape();
+        if coords.len() != grid.len() || coords.iter().zip(&grid).any(|(c, n)| c >= n) {
+            return Err(CteError::InvalidArgument(format!(
+                "chunk {:?} is outside the {:?} chunk grid",
+                coords, grid
+            )));
+        }
+        let mut key = format!("{}c", self.prefix);
+        for c in coords {
+            key.push(self.meta.separator);
+            key.push_str(&c.to_string());
+        }
+        Ok(key)
+    }
+
+    /// Write the chunk at `coords`: `chunk_len` bytes of little-endian
+    /// elements in C order.
+    pub fn write_chunk(&self, coords: &[u64], data: &[u8]) -> Result<(), CteError> {
+        self.write_chunk_with_score(coords, data, None)
+    }
+
+    /// `write_chunk`, placing the chunk at `score`.
+    pub fn write_chunk_with_score(
+        &self,
+        coords: &[u64],
+        data: &[u8],
+        score: Option<f32>,
+    ) -> Result<(), CteError> {
+        let key = self.chunk_key(coords)?;
+        if data.len() != self.chunk_len() {
+            return Err(CteError::InvalidArgument(format!(
+                "chunk of {} bytes, not {}",
+                data.len(),
+                self.chunk_len()
+            )));
+        }
+        self.store.set_with_score(&key, data, score)
+    }
+
+    /// The chunk at `coords`, or a chunk of the fill value if it was never
+    /// written.
+    pub fn read_chunk(&self, coords: &[u64]) -> Result<Vec<u8>, CteError> {
+        let key = self.chunk_key(coords)?;
+        match self.store.get(&key)? {
+            Some(data) => Ok(data),
+            None => Ok(self
+                .meta
+                .data_type
+                .encode(self.meta.fill_value)
+                .repeat(self.meta.chunk_shape.iter().product::<u64>() as usize)),
+        }
+    }
+
+    /// Grid coordinates of the chunks holding elements in `start..end`, in C
+    /// order.
+    fn chunks_under(&self, start: &[u64], end: &[u64]) -> Result<Vec<Vec<u64>>, CteError> {
+        let ndim = self.meta.shape.len();
+        if start.len() != ndim || end.len() != ndim {
+            return Err(CteError::InvalidArgument(format!(
+                "region of {} and {} dimensions in a {}-d array",
+                start.len(),
+                end.len(),
+                ndim
+            )));
+        }
+        let ranges: Vec<(u64, u64)> = (0..ndim)
+            .map(|d| {
+                let c = self.meta.chunk_shape[d];
+                let end = end[d].min(self.meta.shape[d]);
+                (start[d] / c, end.div_ceil(c))
+            })
+            .collect();
+        let mut out = Vec::new();
+        if ranges.iter().any(|(from, to)| from >= to) {
+            return Ok(out);
+        }
+        let mut coords: Vec<u64> = ranges.iter().map(|r| r.0).collect();
+        loop {
+            out.push(coords.clone());
+            let mut d = ndim;
+            loop {
+                if d == 0 {
+                    return Ok(out);
+                }
+                d -= 1;
+                coords[d] += 1;
+                if coords[d] < ranges[d].1 {
+                    break;
+                }
+                coords[d] = ranges[d].0;
+            }
+        }
+    }
+
+    /// Move the chunk at `coords` to `score`, if it was written. Returns
+    /// whether it was.
+    pub fn set_chunk_score(&self, coords: &[u64], score: f32) -> Result<bool, CteError> {
+        let key = self.chunk_key(coords)?;
+        if !self.store.exists(&key)? {
+            return Ok(false);
+        }
+        self.store.tag.try_reorganize_blob(&key, score)?;
+        Ok(true)
+    }
+
+    /// Move every written chunk holding elements in `start..end` (element
+    /// coordinates, end exclusive) to `score`. Returns the chunks moved.
+    pub fn set_region_score(
+        &self,
+        start: &[u64],
+        end: &[u64],
+        score: f32,
+    ) -> Result<usize, CteError> {
+        let mut moved = 0;
+        for coords in self.chunks_under(start, end)? {
+            if self.set_chunk_score(&coords, score)? {
+                moved += 1;
+            }
+        }
+        Ok(moved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_array_metadata() {
+        let mut meta = ZarrArrayMeta::new(&[100, 50], &[32, 25], ZarrDataType::Float32);
+        meta.fill_value = f64::NAN;
+        let json = meta.to_json();
+        assert!(json.contains("\"chunk_shape\":[32,25]"));
+        assert!(json.contains("\"fill_value\":\"NaN\""));
+        let parsed = ZarrArrayMeta::parse("temp", json.as_bytes()).unwrap();
+        assert_eq!(parsed.shape, meta.shape);
+        assert_eq!(parsed.data_type, ZarrDataType::Float32);
+        assert!(parsed.fill_value.is_nan());
+
+        let gzip = json.replace(
+            "[{\"name\":\"bytes\",\"configuration\":{\"endian\":\"little\"}}]",
+            "[{\"name\":\"bytes\"},{\"name\":\"gzip\",\"configuration\":{\"level\":1}}]",
+        );
+        assert!(matches!(
+            ZarrArrayMeta::parse("temp", gzip.as_bytes()),
+            Err(CteError::Unsupported(_))
+        ));
+        assert_eq!(ZarrDataType::Int16.fill_json(-3.0), "-3");
+        assert_eq!(ZarrDataType::UInt16.encode(258.0), [2, 1]);
+        assert!(ZarrArrayMeta::new(&[4], &[2, 2], ZarrDataType::Bool)
+            .validate()
+            .is_err());
+        assert_eq!(node_prefix("/a/b/"), "a/b/");
+        assert_eq!(node_prefix(""), "");
+    }
+}